@@ -0,0 +1,223 @@
+# Unicode table generator
+#
+# Generates src/re/unicode.rs from the Unicode Character Database.
+#
+# Usage: python src/etc/unicode.py <ucd directory>
+#
+# The UCD files can be found at http://www.unicode.org/Public/UCD/latest/ucd/
+# The following files are used:
+#
+#   * CaseFolding.txt
+#   * DerivedGeneralCategory.txt (in extracted/)
+#   * DerivedCoreProperties.txt
+#   * PropList.txt
+#   * PropertyValueAliases.txt
+#   * Scripts.txt
+import os
+import sys
+from datetime import datetime
+
+OUTPUT_FILE = 'src/re/unicode.rs'
+
+HEADER = """// This is an auto-generated file
+// Generated by src/etc/unicode.py
+//
+// Last Modified: %s
+"""
+
+def ucd_path(ucd, name):
+  for path in [os.path.join(ucd, name), 
+               os.path.join(ucd, 'extracted', name)]:
+    if os.path.exists(path):
+      return path
+  raise Exception("Couldn't find %s in %s" % (name, ucd))
+
+def ucd_lines(ucd, name):
+  for line in open(ucd_path(ucd, name)):
+    line = line.split('#')[0].strip()
+    if line:
+      yield [field.strip() for field in line.split(';')]
+
+def escape_char(c):
+  if c <= 0xFFFF:
+    return "'\\u%04X'" % c
+  return "'\\U%08X'" % c
+
+def parse_range(field):
+  if '..' in field:
+    start, end = field.split('..')
+    return (int(start, 16), int(end, 16))
+  return (int(field, 16), int(field, 16))
+
+def load_properties(ucd, name):
+  """Maps each property value in a UCD file to the set of 
+  characters that have it."""
+  props = {}
+  for fields in ucd_lines(ucd, name):
+    (start, end) = parse_range(fields[0])
+    props.setdefault(fields[1], set()).update(range(start, end + 1))
+  return props
+
+def to_ranges(chars):
+  """Collapses a set of characters into sorted, non-overlapping 
+  ranges. Surrogates aren't valid chars, so they are left out."""
+  ranges = []
+  for c in sorted(chars):
+    if 0xD800 <= c <= 0xDFFF:
+      continue
+    if ranges and ranges[-1][1] + 1 == c:
+      ranges[-1] = (ranges[-1][0], c)
+    else:
+      ranges.append((c, c))
+  return ranges
+
+def negate(chars):
+  return set(range(0, 0x110000)) - chars
+
+def load_case_orbits(ucd):
+  """Groups characters that are equal under simple case folding
+  (status C and S), and links each one to the next in its group."""
+  classes = {}
+  for fields in ucd_lines(ucd, 'CaseFolding.txt'):
+    if fields[1] not in ('C', 'S'):
+      continue
+    c, folded = int(fields[0], 16), int(fields[2], 16)
+    classes.setdefault(folded, set([folded])).add(c)
+
+  orbits = []
+  for members in classes.values():
+    members = sorted(members)
+    for (i, c) in enumerate(members):
+      orbits.append((c, members[(i + 1) % len(members)]))
+  return sorted(orbits)
+
+def emit_char_pairs(name, pairs, doc):
+  out = "\n/// %s\n" % doc
+  out += "pub static %s: &'static [(char, char)] = &[\n" % name
+  out += ",\n".join("  (%s, %s)" % (escape_char(a), escape_char(b)) 
+                    for (a, b) in pairs)
+  out += "\n];\n"
+  return out
+
+def normalize(name):
+  """Loose matching of property names (UAX #44, LM3): case, spaces, 
+  underscores and hyphens are ignored."""
+  return name.replace(' ', '').replace('_', '').replace('-', '').lower()
+
+def load_aliases(ucd, prop):
+  """Maps every alias of a property's values to the name of the value
+  used in the generated tables (the long name for scripts, the short 
+  name for general categories)."""
+  aliases = {}
+  for fields in ucd_lines(ucd, 'PropertyValueAliases.txt'):
+    if fields[0] != prop:
+      continue
+    key = normalize(fields[1] if prop == 'gc' else fields[2])
+    for alias in fields[1:]:
+      aliases[normalize(alias)] = key
+  return aliases
+
+def general_categories(ucd):
+  categories = load_properties(ucd, 'DerivedGeneralCategory.txt')
+  tables = {}
+  for (cat, chars) in categories.items():
+    tables[normalize(cat)] = chars
+    group = normalize(cat[0])
+    tables[group] = tables.get(group, set()) | chars
+  tables['lc'] = categories['Lu'] | categories['Ll'] | categories['Lt']
+  return tables
+
+def scripts(ucd):
+  tables = {}
+  for (script, chars) in load_properties(ucd, 'Scripts.txt').items():
+    tables[normalize(script)] = chars
+  return tables
+
+def binary_properties(ucd):
+  tables = {'any': set(range(0, 0x110000)), 'ascii': set(range(0, 0x80))}
+  for name in ('PropList.txt', 'DerivedCoreProperties.txt'):
+    for (prop, chars) in load_properties(ucd, name).items():
+      tables[normalize(prop)] = chars
+  return tables
+
+def emit_tables(name, tables, doc):
+  out = "\n/// %s\n" % doc
+  out += "pub static %s: &'static [(&'static str, &'static [(char, char)])] = &[\n" % name
+  entries = []
+  for key in sorted(tables.keys()):
+    ranges = ",\n".join("    (%s, %s)" % (escape_char(a), escape_char(b)) 
+                        for (a, b) in to_ranges(tables[key]))
+    if ranges:
+      entries.append("  (\"%s\", &[\n%s\n  ])" % (key, ranges))
+    else:
+      entries.append("  (\"%s\", &[])" % key)
+  out += ",\n".join(entries)
+  out += "\n];\n"
+  return out
+
+def emit_aliases(name, aliases, doc):
+  out = "\n/// %s\n" % doc
+  out += "pub static %s: &'static [(&'static str, &'static str)] = &[\n" % name
+  out += ",\n".join("  (\"%s\", \"%s\")" % (alias, aliases[alias]) 
+                    for alias in sorted(aliases.keys()))
+  out += "\n];\n"
+  return out
+
+def emit_ranges(name, chars, doc):
+  return emit_char_pairs(name, to_ranges(chars), doc)
+
+def perl_classes(ucd):
+  """Builds the Unicode versions of `\d`, `\s` and `\w`, following
+  UTS #18 (Annex C)."""
+  categories = load_properties(ucd, 'DerivedGeneralCategory.txt')
+  core = load_properties(ucd, 'DerivedCoreProperties.txt')
+  props = load_properties(ucd, 'PropList.txt')
+
+  digit = categories['Nd']
+  space = props['White_Space']
+  word = set(core['Alphabetic']) | digit | categories['Pc'] | \
+         props['Join_Control']
+  for cat in ('Mn', 'Mc', 'Me'):
+    word |= categories[cat]
+
+  return [('PerlDigit', digit, "`\\d`"), 
+          ('PerlSpace', space, "`\\s`"), 
+          ('PerlWord', word, "`\\w`")]
+
+if __name__ == "__main__":
+  if len(sys.argv) != 2:
+    print("Usage: python src/etc/unicode.py <ucd directory>")
+    sys.exit(1)
+
+  ucd = sys.argv[1]
+  date = datetime.today().strftime("%B %d %Y %I:%M%p")
+
+  buf = HEADER % date
+
+  buf += emit_char_pairs('CaseFoldOrbit', load_case_orbits(ucd),
+    "Simple case folding orbits. Each character maps to the next character\n"
+    "/// that is equal to it under simple case folding, and the last maps back\n"
+    "/// to the first.")
+
+  for (name, chars, escape) in perl_classes(ucd):
+    buf += emit_ranges(name, chars, "Unicode %s" % escape)
+    buf += emit_ranges('Negated' + name, negate(chars), 
+      "Unicode %s" % escape.upper())
+
+  buf += emit_tables('GeneralCategories', general_categories(ucd),
+    "General categories, and the one letter groups of categories (and `LC`),\n"
+    "/// by normalized short name")
+  buf += emit_aliases('GeneralCategoryAliases', load_aliases(ucd, 'gc'),
+    "Normalized names of general categories, mapped to their short name")
+  buf += emit_tables('Scripts', scripts(ucd), 
+    "Scripts, by normalized long name")
+  buf += emit_aliases('ScriptAliases', load_aliases(ucd, 'sc'),
+    "Normalized names of scripts, mapped to their long name")
+  buf += emit_tables('BinaryProperties', binary_properties(ucd), 
+    "Binary properties, by normalized name")
+
+  out = open(OUTPUT_FILE, 'w')
+  out.write(buf)
+  out.close()
+
+  print("Successfully generated unicode file: %s" % OUTPUT_FILE)
@@ -1,19 +1,32 @@
 use parse::{Expr, CharClass, CharClassStatic};
 use std::char::{from_u32, MAX};
-use std::cmp::{Less, Greater};
+use std::cmp::{Less, Equal, Greater, max, min};
+use std::ascii::StrAsciiExt;
+use unicode::{CaseFoldOrbit, PerlDigit, PerlSpace, PerlWord, NegatedPerlDigit,
+  NegatedPerlSpace, NegatedPerlWord, GeneralCategories, GeneralCategoryAliases,
+  Scripts, ScriptAliases, BinaryProperties};
 
 pub type Range = (char, char);
 
 /// Static Character Classes
-pub static NumericClass: Expr = CharClassStatic([
+pub static DigitClass: Expr = CharClassStatic(PerlDigit);
+pub static WordClass: Expr = CharClassStatic(PerlWord);
+pub static SpaceClass: Expr = CharClassStatic(PerlSpace);
+pub static NegatedDigitClass: Expr = CharClassStatic(NegatedPerlDigit);
+pub static NegatedWordClass: Expr = CharClassStatic(NegatedPerlWord);
+pub static NegatedSpaceClass: Expr = CharClassStatic(NegatedPerlSpace);
+
+/// ASCII-only versions of `\d`, `\w` and `\s`
+pub static AsciiDigitClass: Expr = CharClassStatic([
   ('0', '9')
 ]);
-pub static AlphaClass: Expr = CharClassStatic([
-  ('a', 'z'), 
+pub static AsciiWordClass: Expr = CharClassStatic([
+  ('0', '9'), 
   ('A', 'Z'), 
-  ('_', '_')
+  ('_', '_'), 
+  ('a', 'z')
 ]);
-pub static WhitespaceClass: Expr = CharClassStatic([
+pub static AsciiSpaceClass: Expr = CharClassStatic([
   (' ', ' '), 
   ('\t', '\t'), 
   ('\u000b', '\u000b'), 
@@ -21,21 +34,61 @@ pub static WhitespaceClass: Expr = CharClassStatic([
   ('\n', '\n'), 
   ('\r', '\r')
 ]);
-pub static NegatedNumericClass: Expr = CharClassStatic([
+pub static NegatedAsciiDigitClass: Expr = CharClassStatic([
   ('\u0000', '\u002F'), ('\u003A', MAX)
 ]);
-pub static NegatedAlphaClass: Expr = CharClassStatic([
-  ('\u0000', '\u0040'), 
+pub static NegatedAsciiWordClass: Expr = CharClassStatic([
+  ('\u0000', '\u002F'), 
+  ('\u003A', '\u0040'), 
   ('\u005B', '\u005E'), 
   ('\u0060', '\u0060'), 
   ('\u007B', MAX)
 ]);
-pub static NegatedWhitespaceClass: Expr = CharClassStatic([
+pub static NegatedAsciiSpaceClass: Expr = CharClassStatic([
   ('\u0000', '\u0008'), 
   ('\u000e', '\u001f'), 
   ('\u0021', MAX)
 ]);
 
+/// Classes for `.`, depending on what ends a line
+pub static AnyClass: Expr = CharClassStatic([
+  ('\u0000', MAX)
+]);
+pub static NotNewlineClass: Expr = CharClassStatic([
+  ('\u0000', '\u0009'), 
+  ('\u000b', MAX)
+]);
+pub static NotCRLFClass: Expr = CharClassStatic([
+  ('\u0000', '\u0009'), 
+  ('\u000b', '\u000c'), 
+  ('\u000e', MAX)
+]);
+pub static NotLineTerminatorClass: Expr = CharClassStatic([
+  ('\u0000', '\u0009'), 
+  ('\u000e', '\u0084'), 
+  ('\u0086', '\u2027'), 
+  ('\u202a', MAX)
+]);
+
+/// POSIX bracket classes (`[:alpha:]`), sorted by name. These 
+/// only contain ASCII characters, as in the C locale.
+static PosixClasses: &'static [(&'static str, &'static [Range])] = &[
+  ("alnum", &[('0', '9'), ('A', 'Z'), ('a', 'z')]),
+  ("alpha", &[('A', 'Z'), ('a', 'z')]),
+  ("ascii", &[('\u0000', '\u007f')]),
+  ("blank", &[('\t', '\t'), (' ', ' ')]),
+  ("cntrl", &[('\u0000', '\u001f'), ('\u007f', '\u007f')]),
+  ("digit", &[('0', '9')]),
+  ("graph", &[('!', '~')]),
+  ("lower", &[('a', 'z')]),
+  ("print", &[(' ', '~')]),
+  ("punct", &[('!', '/'), (':', '@'), ('[', '`'), ('{', '~')]),
+  ("space", &[('\t', '\r'), (' ', ' ')]),
+  ("upper", &[('A', 'Z')]),
+  ("word", &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')]),
+  ("xdigit", &[('0', '9'), ('A', 'F'), ('a', 'f')])
+];
+
 /// Try to get the prev character in sequence from 
 /// the given one.
 fn prev_char(c: char) -> Option<char> {
@@ -96,10 +149,8 @@ pub fn new_charclass(ranges: ~[Range]) -> Expr {
           if (start <= end) {
             new_ranges.push((start, end))
           }
-        } else if (start < e && end > e) {
+        } else if (end > e) {
           new_ranges.push((s, end))
-        } else if (start < e && end < e) {
-          new_ranges.push((s, e))
         } else {
           new_ranges.push((s, e))
         }
@@ -115,6 +166,187 @@ pub fn new_charclass(ranges: ~[Range]) -> Expr {
   CharClass(new_ranges)
 }
 
+/// Checks if a character is in a table of sorted, non-overlapping
+/// ranges.
+pub fn in_table(c: char, table: &'static [Range]) -> bool {
+  table.bsearch(|&(start, end)| {
+    if (end < c) {
+      Less
+    } else if (start > c) {
+      Greater
+    } else {
+      Equal
+    }
+  }).is_some()
+}
+
+/// Normalizes the name of a Unicode property, or property value, 
+/// so that case, spaces, underscores and hyphens are ignored 
+/// (`Script=Old_Italic` is the same as `script=olditalic`).
+fn normalize_property(name: &str) -> ~str {
+  let mut key = ~"";
+
+  for c in name.chars() {
+    match c {
+      ' ' | '_' | '-' => (),
+      _ => key.push_char(c)
+    }
+  }
+
+  key.to_ascii_lower()
+}
+
+/// Finds a table by its normalized name.
+fn find_table(name: &str, tables: &'static [(&'static str, &'static [Range])]) 
+              -> Option<&'static [Range]> {
+  match tables.bsearch(|&(k, _)| k.cmp(&name)) {
+    Some(i) => {
+      let (_, table) = tables[i];
+      Some(table)
+    }
+    None => None
+  }
+}
+
+/// Finds a table through an alias of its name (ex. `Lu` or 
+/// `Uppercase_Letter`).
+fn find_aliased_table(name: &str, aliases: &'static [(&'static str, &'static str)], 
+                      tables: &'static [(&'static str, &'static [Range])]) 
+                      -> Option<&'static [Range]> {
+  match aliases.bsearch(|&(k, _)| k.cmp(&name)) {
+    Some(i) => {
+      let (_, key) = aliases[i];
+      find_table(key, tables)
+    }
+    None => None
+  }
+}
+
+/// Returns the ranges of characters with a Unicode property, as 
+/// used by `\p{...}`. Supports:
+///
+/// * General categories (`L`, `Lu`, `Uppercase_Letter`, `gc=Lu`)
+/// * Scripts (`Greek`, `Script=Han`, `sc=Hani`)
+/// * Binary properties (`Alphabetic`, `White_Space`, `Any`, `ASCII`)
+///
+/// A name without a property is looked up in that order.
+pub fn property_table(name: &str) -> Option<&'static [Range]> {
+  let (prop, value) = match name.find('=') {
+    Some(i) => {
+      (normalize_property(name.slice_to(i)), 
+       normalize_property(name.slice_from(i + 1)))
+    }
+    None => (~"", normalize_property(name))
+  };
+
+  let general_category = find_aliased_table(value, GeneralCategoryAliases, 
+                                            GeneralCategories);
+  let script = find_aliased_table(value, ScriptAliases, Scripts);
+
+  match prop.as_slice() {
+    "" => {
+      general_category
+        .or(script)
+        .or(find_table(value, BinaryProperties))
+    }
+    "gc" | "generalcategory" => general_category,
+    "sc" | "script" => script,
+    _ => None
+  }
+}
+
+/// Returns the ranges of a POSIX bracket class, by name (ex. `alpha` 
+/// for `[:alpha:]`).
+pub fn posix_class(name: &str) -> Option<&'static [Range]> {
+  find_table(name, PosixClasses)
+}
+
+/// Checks if a character is a word character. This is the 
+/// same set of characters `\w` matches, and is used for word 
+/// boundaries.
+pub fn is_word_char(c: char, ascii: bool) -> bool {
+  if (ascii) {
+    match c {
+      'a'..'z' | 'A'..'Z' | '0'..'9' | '_' => true,
+      _ => false
+    }
+  } else {
+    in_table(c, PerlWord)
+  }
+}
+
+/// Returns the next character in the case folding orbit of `c`,
+/// or None if `c` is only equal to itself under simple case 
+/// folding. Following the orbit eventually leads back to `c`.
+pub fn simple_fold(c: char) -> Option<char> {
+  match CaseFoldOrbit.bsearch(|&(k, _)| k.cmp(&c)) {
+    Some(i) => {
+      let (_, next) = CaseFoldOrbit[i];
+      Some(next)
+    }
+    None => None
+  }
+}
+
+/// Checks if two characters are equal under simple case folding.
+pub fn fold_eq(a: char, b: char) -> bool {
+  if (a == b) {
+    return true
+  }
+
+  let mut f = simple_fold(a);
+
+  while (f.is_some() && f != Some(a)) {
+    if (f == Some(b)) {
+      return true
+    }
+    f = simple_fold(f.unwrap());
+  }
+
+  false
+}
+
+/// Adds every character that is equal to one in the set of ranges
+/// under simple case folding. The result isn't ordered, so it should 
+/// be passed to `new_charclass` or `new_negated_charclass`.
+pub fn fold_ranges(ranges: ~[Range]) -> ~[Range] {
+  let mut folded = ranges.clone();
+
+  for &(start, end) in ranges.iter() {
+    // Find the first character in the orbit table 
+    // that is in the range
+    let mut lo = 0;
+    let mut hi = CaseFoldOrbit.len();
+
+    while (lo < hi) {
+      let mid = (lo + hi) / 2;
+      let (k, _) = CaseFoldOrbit[mid];
+      if (k < start) {
+        lo = mid + 1;
+      } else {
+        hi = mid;
+      }
+    }
+
+    // Orbits can have more than two members, like k, K and 
+    // the Kelvin sign, so follow each one all the way around
+    for &(k, next) in CaseFoldOrbit.slice_from(lo).iter() {
+      if (k > end) {
+        break;
+      }
+
+      let mut f = next;
+
+      while (f != k) {
+        folded.push((f, f));
+        f = simple_fold(f).unwrap();
+      }
+    }
+  }
+
+  folded
+}
+
 /// Construct a CharClass with a set of ranges, and negate them.
 pub fn new_negated_charclass(ranges: ~[Range]) -> Expr {
   let mut ranges = ranges;
@@ -150,6 +382,55 @@ pub fn new_negated_charclass(ranges: ~[Range]) -> Expr {
   CharClass(new_ranges)
 }
 
+/// Construct a CharClass with the characters that are in both 
+/// sets of ranges (ex. Given [a-z] and [d-x0-9], [d-x]).
+pub fn new_intersected_charclass(lft: ~[Range], rgt: ~[Range]) -> Expr {
+  let lft = class_ranges(new_charclass(lft));
+  let rgt = class_ranges(new_charclass(rgt));
+
+  let mut new_ranges = ~[];
+  let mut i = 0;
+  let mut j = 0;
+
+  // Both sides are ordered and don't overlap, so we can 
+  // walk through them together
+  while (i < lft.len() && j < rgt.len()) {
+    let (s1, e1) = lft[i];
+    let (s2, e2) = rgt[j];
+
+    let start = max(s1, s2);
+    let end = min(e1, e2);
+
+    if (start <= end) {
+      new_ranges.push((start, end));
+    }
+
+    if (e1 < e2) {
+      i += 1;
+    } else {
+      j += 1;
+    }
+  }
+
+  CharClass(new_ranges)
+}
+
+/// Construct a CharClass with the characters in the first set of 
+/// ranges that aren't in the second (ex. Given [a-z] and [aeiou], 
+/// [b-df-hj-np-tv-z]).
+pub fn new_subtracted_charclass(lft: ~[Range], rgt: ~[Range]) -> Expr {
+  new_intersected_charclass(lft, class_ranges(new_negated_charclass(rgt)))
+}
+
+/// Gets the ranges out of a character class.
+pub fn class_ranges(cc: Expr) -> ~[Range] {
+  match cc {
+    CharClass(ranges) => ranges,
+    CharClassStatic(ranges) => ranges.to_owned(),
+    _ => unreachable!()
+  }
+}
+
 #[cfg(test)]
 mod char_class_tests {
   use std::char::MAX;
@@ -218,6 +499,82 @@ mod char_class_tests {
     assert_eq!(unravel_cc(cc), ~[('A', 'E')]);
   }
 
+  #[test]
+  fn char_class_touching_ranges() {
+    let cc = new_charclass(~[('a', 'c'), ('c', 'f')]);
+    assert_eq!(unravel_cc(cc), ~[('a', 'f')]);
+  }
+
+  #[test]
+  fn char_class_fold() {
+    let cc = new_charclass(fold_ranges(~[('a', 'c'), ('k', 'k')]));
+    assert_eq!(unravel_cc(cc), ~[('A', 'C'), ('K', 'K'), ('a', 'c'), 
+               ('k', 'k'), ('\u212A', '\u212A')]);
+  }
+
+  #[test]
+  fn char_class_fold_orbit() {
+    let cc = new_charclass(fold_ranges(~[('s', 's')]));
+    assert_eq!(unravel_cc(cc), ~[('S', 'S'), ('s', 's'), ('\u017F', '\u017F')]);
+    let cc = new_charclass(fold_ranges(~[('\u03c3', '\u03c3')]));
+    assert_eq!(unravel_cc(cc), ~[('\u03a3', '\u03a3'), ('\u03c2', '\u03c2'),
+               ('\u03c3', '\u03c3')]);
+  }
+
+  #[test]
+  fn char_class_fold_eq() {
+    assert!(fold_eq('k', '\u212A'));
+    assert!(fold_eq('\u03c2', '\u03a3'));
+    assert!(!fold_eq('a', 'b'));
+  }
+
+  #[test]
+  fn char_class_property() {
+    assert!(property_table("Lu").is_some());
+    assert!(property_table("Uppercase Letter").is_some());
+    assert!(property_table("sc=Grek").is_some());
+    assert!(property_table("Script=Old_Italic").is_some());
+    assert!(property_table("white-space").is_some());
+    assert!(property_table("gc=Greek").is_none());
+    assert!(property_table("Klingon").is_none());
+  }
+
+  #[test]
+  fn char_class_intersect() {
+    let cc = new_intersected_charclass(~[('a', 'z')], ~[('0', '9'), ('x', 'x'), 
+                                       ('d', 'g'), ('y', '~')]);
+    assert_eq!(unravel_cc(cc), ~[('d', 'g'), ('x', 'x'), ('y', 'z')]);
+  }
+
+  #[test]
+  fn char_class_intersect_empty() {
+    let cc = new_intersected_charclass(~[('a', 'c')], ~[('d', 'f')]);
+    assert_eq!(unravel_cc(cc), ~[]);
+  }
+
+  #[test]
+  fn char_class_subtract() {
+    let cc = new_subtracted_charclass(~[('a', 'k')], ~[('e', 'e'), ('i', 'i')]);
+    assert_eq!(unravel_cc(cc), ~[('a', 'd'), ('f', 'h'), ('j', 'k')]);
+  }
+
+  #[test]
+  fn char_class_posix() {
+    assert_eq!(posix_class("xdigit").unwrap().to_owned(), 
+               ~[('0', '9'), ('A', 'F'), ('a', 'f')]);
+    assert!(posix_class("alpha").is_some());
+    assert!(posix_class("Alpha").is_none());
+  }
+
+  #[test]
+  fn char_class_word_char() {
+    assert!(is_word_char('\u00e9', false));
+    assert!(is_word_char('\u0663', false));
+    assert!(!is_word_char('\u00e9', true));
+    assert!(is_word_char('7', true));
+    assert!(!is_word_char('-', false));
+  }
+
   fn char_class_negate_sequential() {
     let cc = new_charclass(~[('a', 'a'), ('b', 'b'), ('c', 'c')]);
     assert_eq!(unravel_cc(cc), ~[('\u0000', '`'), ('d', MAX)]);
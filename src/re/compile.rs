@@ -1,9 +1,12 @@
 use parse::Expr;
-use parse::{Greedy, NonGreedy};
+use parse::{Greedy, NonGreedy, Possessive};
+use parse::{LookAhead, LookBehind};
 use parse::{Empty, Literal, CharClass, CharClassStatic, Alternation,
-            Concatenation, Repetition, Capture, AssertWordBoundary,
-            AssertNonWordBoundary, AssertStart, AssertEnd};
-use charclass::Range;
+            Concatenation, Repetition, Capture, Backref, Lookaround, Atomic,
+            AssertWordBoundary, AssertNonWordBoundary, AssertStart, AssertEnd,
+            AssertLineStart, AssertLineEnd};
+use charclass::{Range, simple_fold};
+use flags::{IGNORECASE, ASCII};
 
 #[deriving(Clone)]
 pub enum Instruction {
@@ -13,11 +16,26 @@ pub enum Instruction {
   InstJump(uint),
   InstCaptureStart(uint, Option<~str>),
   InstCaptureEnd(uint),
+  // The flag is set if the comparison ignores case
+  InstBackref(uint, bool),
   InstSplit(uint, uint),
   InstAssertStart,
   InstAssertEnd,
-  InstWordBoundary,
-  InstNonWordBoundary,
+  // Line assertions carry the flags that decide 
+  // what ends a line
+  InstAssertLineStart(uint),
+  InstAssertLineEnd(uint),
+  // The flag is set if only ASCII characters are 
+  // word characters
+  InstWordBoundary(bool),
+  InstNonWordBoundary(bool),
+  // Lookarounds run their own program at the current 
+  // position. The flag is set if the assertion is negated.
+  InstLookahead(~[Instruction], bool),
+  InstLookbehind(~[Instruction], uint, bool),
+  // Atomic groups run their own program at the current 
+  // position, and continue from the end of its first match
+  InstAtomic(~[Instruction]),
   InstNoop
 }
 
@@ -30,11 +48,18 @@ impl ToStr for Instruction {
       InstJump(i)               => format!("InstJump {:u}", i),
       InstCaptureStart(id, _)   => format!("InstCaptureStart {:u}", id),
       InstCaptureEnd(id)        => format!("InstCaptureEnd {:u}", id),
+      InstBackref(id, i)        => format!("InstBackref {:u} {:b}", id, i),
       InstSplit(l, r)           => format!("InstSplit {:u} | {:u}", l, r),
-      InstAssertStart           => ~"InstLineStart",
-      InstAssertEnd             => ~"InstLineEnd",
-      InstWordBoundary          => ~"InstWordBoundary",
-      InstNonWordBoundary       => ~"InstNonWordBoundary",
+      InstAssertStart           => ~"InstAssertStart",
+      InstAssertEnd             => ~"InstAssertEnd",
+      InstAssertLineStart(_)    => ~"InstAssertLineStart",
+      InstAssertLineEnd(_)      => ~"InstAssertLineEnd",
+      InstWordBoundary(_)       => ~"InstWordBoundary",
+      InstNonWordBoundary(_)    => ~"InstNonWordBoundary",
+      InstLookahead(ref p, n)   => format!("InstLookahead {:b} ({:u})", n, p.len()),
+      InstLookbehind(ref p, w, n) => format!("InstLookbehind {:b} {:u} ({:u})", n, w, 
+                                             p.len()),
+      InstAtomic(ref p)         => format!("InstAtomic ({:u})", p.len()),
       InstNoop                  => ~"InstNoop"
     }
   }
@@ -82,6 +107,29 @@ fn generate_repeat_split(left: uint, right: uint, nongreedy: bool) -> Instructio
   }
 }
 
+/// Compiles a literal character. When ignoring case, this matches 
+/// every character that is equal to it under simple case folding.
+#[inline]
+fn compile_literal(c: char, flags: uint, stack: &mut ~[Instruction]) {
+  if (flags & IGNORECASE != 0) {
+    let mut orbit = ~[];
+    let mut f = simple_fold(c);
+
+    while (f.is_some() && f != Some(c)) {
+      orbit.push((f.unwrap(), f.unwrap()));
+      f = simple_fold(f.unwrap());
+    }
+
+    if (orbit.len() > 0) {
+      orbit.push((c, c));
+      compile_charclass(orbit, stack);
+      return
+    }
+  }
+
+  stack.push(InstLiteral(c));
+}
+
 /// Calls _compile_recursive, then pushes a `InstMatch` onto the 
 /// end of the Instruction stack
 ///
@@ -100,6 +148,51 @@ pub fn compile_recursive(re: &Expr) -> ~[Instruction] {
   stack
 }
 
+/// Counts the number of capturing groups in a compiled program.
+///
+/// Repetitions duplicate capture instructions, so this looks for the 
+/// highest group number rather than counting instructions.
+///
+/// # Arguments
+///
+/// * prog - The compiled program
+pub fn count_captures(prog: &[Instruction]) -> uint {
+  let mut ncaps = 0;
+
+  for inst in prog.iter() {
+    let n = match *inst {
+      InstCaptureStart(id, _) => id + 1,
+      InstLookahead(ref sub, _) |
+      InstLookbehind(ref sub, _, _) |
+      InstAtomic(ref sub) => count_captures(*sub),
+      _ => 0
+    };
+    if (n > ncaps) {
+      ncaps = n;
+    }
+  }
+
+  ncaps
+}
+
+/// Checks if a compiled program contains backreferences or atomic 
+/// groups, which the `PikeVM` can't execute.
+///
+/// # Arguments
+///
+/// * prog - The compiled program
+pub fn needs_backtrack(prog: &[Instruction]) -> bool {
+  prog.iter().any(|inst| {
+    match *inst {
+      InstBackref(..) | 
+      InstAtomic(..) => true,
+      InstLookahead(ref sub, _) |
+      InstLookbehind(ref sub, _, _) => needs_backtrack(*sub),
+      _ => false
+    }
+  })
+}
+
 /// Compiles a Regexp into a list of Instructions recursively
 ///
 /// Returns the number of captures instructions compiled.
@@ -120,8 +213,8 @@ fn _compile_recursive(expr: &Expr, stack: &mut ~[Instruction]) -> uint {
   )
 
   match *expr {
-    Literal(c) => {
-      stack.push(InstLiteral(c));
+    Literal(c, flags) => {
+      compile_literal(c, flags, stack);
     }
     Alternation(ref lft, ref rgt) => {
       // Compile to:
@@ -171,10 +264,33 @@ fn _compile_recursive(expr: &Expr, stack: &mut ~[Instruction]) -> uint {
       ncap += _compile_recursive(*expr, stack);
       stack.push(InstCaptureEnd(id));
     }
+    Backref(id, flags) => {
+      stack.push(InstBackref(id, flags & IGNORECASE != 0));
+    }
+    Lookaround(ref expr, kind, negated) => {
+      // The body is compiled to a separate program, 
+      // with its own InstMatch
+      let sub = compile_recursive(*expr);
+
+      match kind {
+        LookAhead => stack.push(InstLookahead(sub, negated)),
+        LookBehind(width) => stack.push(InstLookbehind(sub, width, negated))
+      }
+    }
+    Atomic(ref expr) => {
+      stack.push(InstAtomic(compile_recursive(*expr)));
+    }
+    Repetition(ref expr, start, end, Possessive) => {
+      // The same as an atomic group around the greedy 
+      // repetition
+      let rep = Repetition(expr.clone(), start, end, Greedy);
+
+      stack.push(InstAtomic(compile_recursive(&rep)));
+    }
     Repetition(ref expr, start, end, quantifier) => {
       let nongreedy = match quantifier {
-        Greedy    => false,
-        NonGreedy => true
+        NonGreedy => true,
+        _         => false
       };
 
       for _ in range(0, start) {
@@ -205,11 +321,11 @@ fn _compile_recursive(expr: &Expr, stack: &mut ~[Instruction]) -> uint {
         _ => ()
       }
     }
-    AssertWordBoundary => {
-      stack.push(InstWordBoundary);
+    AssertWordBoundary(flags) => {
+      stack.push(InstWordBoundary(flags & ASCII != 0));
     }
-    AssertNonWordBoundary => {
-      stack.push(InstNonWordBoundary);
+    AssertNonWordBoundary(flags) => {
+      stack.push(InstNonWordBoundary(flags & ASCII != 0));
     }
     AssertStart => {
       stack.push(InstAssertStart);
@@ -217,6 +333,12 @@ fn _compile_recursive(expr: &Expr, stack: &mut ~[Instruction]) -> uint {
     AssertEnd => {
       stack.push(InstAssertEnd);
     }
+    AssertLineStart(flags) => {
+      stack.push(InstAssertLineStart(flags));
+    }
+    AssertLineEnd(flags) => {
+      stack.push(InstAssertLineEnd(flags));
+    }
     Empty => ()
   }
 
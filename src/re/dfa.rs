@@ -0,0 +1,990 @@
+use std::vec;
+use std::mem;
+use std::cmp::max;
+use std::char::MAX;
+use std::cell::RefCell;
+use std::hashmap::HashMap;
+use compile::Instruction;
+use compile::{InstLiteral, InstRange, InstMatch, InstJump, InstSplit,
+  InstCaptureStart, InstCaptureEnd, InstAssertStart, InstAssertEnd, InstNoop};
+use exec::{ExecStrategy, Thread, Threads, PikeVM};
+use error::DFAError::*;
+
+/// The number of bytes of transitions the `LazyDFA` keeps
+/// for a program before it gives up
+static CACHE_SIZE: uint = 1 << 21;
+
+/// The fewest states the cache holds, no matter how many
+/// character classes there are
+static MIN_STATES: uint = 16;
+
+/// Checks if a program can be run by a DFA.
+///
+/// A DFA only knows the current character, so it can't keep
+/// captures, or check assertions that look around the position.
+/// The absolute start and end assertions are the exception,
+/// since they only depend on where the input starts and stops.
+///
+/// # Arguments
+///
+/// * prog - The compiled program
+pub fn dfa_supported(prog: &[Instruction]) -> bool {
+  prog.iter().all(|inst| {
+    match *inst {
+      InstLiteral(..) | InstRange(..) | InstMatch | InstJump(..) |
+      InstSplit(..) | InstCaptureStart(..) | InstCaptureEnd(..) |
+      InstAssertStart | InstAssertEnd | InstNoop => true,
+      _ => false
+    }
+  })
+}
+
+/// Splits up the characters into classes, so that every
+/// instruction in the program treats all the characters in a
+/// class the same way.
+///
+/// Returns the first character of each class, in order.
+/// The first class always starts at `\0`.
+pub fn char_classes(prog: &[Instruction]) -> ~[u32] {
+  let mut bounds = ~[0u32];
+
+  for inst in prog.iter() {
+    match *inst {
+      InstLiteral(c) => {
+        bounds.push(c as u32);
+        bounds.push(c as u32 + 1);
+      }
+      InstRange(s, e) => {
+        bounds.push(s as u32);
+        bounds.push(e as u32 + 1);
+      }
+      _ => ()
+    }
+  }
+
+  bounds.sort();
+  bounds.dedup();
+  bounds.retain(|&b| b <= MAX as u32);
+  bounds
+}
+
+/// Finds the character class a character is in.
+///
+/// # Arguments
+///
+/// * classes - The first character of each class (see `char_classes`)
+/// * c - The character
+#[inline]
+pub fn class_of(classes: &[u32], c: char) -> uint {
+  let c = c as u32;
+  let mut lo = 0;
+  let mut hi = classes.len();
+
+  while (hi - lo > 1) {
+    let mid = (lo + hi) / 2;
+    if (classes[mid] <= c) {
+      lo = mid;
+    } else {
+      hi = mid;
+    }
+  }
+
+  lo
+}
+
+/// Follows the instructions that don't consume any input from
+/// `pc`, and adds the ones threads stop at to `insts`, in
+/// priority order.
+///
+/// Threads wait at an `InstAssertEnd` until it's known if the
+/// input ends.
+///
+/// # Arguments
+///
+/// * prog - The compiled program
+/// * pc - The instruction to start from
+/// * insts - The instructions threads are at
+/// * seen - The instructions already followed in this step
+/// * at_start - Whether this is the start of the input
+/// * at_end - Whether this is the end of the input
+fn add_closure(prog: &[Instruction], mut pc: uint, insts: &mut ~[uint],
+               seen: &mut ~[bool], at_start: bool, at_end: bool) {
+  loop {
+    if (seen[pc]) {
+      return
+    }
+    seen[pc] = true;
+
+    match prog[pc] {
+      InstJump(addr) => {
+        pc = addr;
+      }
+      InstSplit(laddr, raddr) => {
+        add_closure(prog, laddr, insts, seen, at_start, at_end);
+        pc = raddr;
+      }
+      InstCaptureStart(..) | InstCaptureEnd(..) | InstNoop => {
+        pc += 1;
+      }
+      InstAssertStart => {
+        if (!at_start) {
+          return
+        }
+        pc += 1;
+      }
+      InstAssertEnd if at_end => {
+        pc += 1;
+      }
+      _ => break
+    }
+  }
+
+  insts.push(pc);
+}
+
+/// Finds the instructions threads are at at the start of the input.
+///
+/// # Arguments
+///
+/// * prog - The compiled program
+/// * at_start - Whether the input starts at the beginning of the string
+pub fn start_insts(prog: &[Instruction], at_start: bool) -> ~[uint] {
+  let mut insts = ~[];
+  let mut seen = vec::from_elem(prog.len(), false);
+
+  add_closure(prog, 0, &mut insts, &mut seen, at_start, false);
+  insts
+}
+
+/// Moves the threads in `insts` over a character class, or over
+/// the end of the input if `class` is None, the same way the
+/// `PikeVM` would. Threads after one that matched have a lower
+/// priority, so they are dropped.
+///
+/// # Arguments
+///
+/// * prog - The compiled program
+/// * classes - The first character of each class (see `char_classes`)
+/// * insts - The instructions threads are at
+/// * class - The character class to move over
+/// * seed - Whether to start a new thread after the character
+/// * at_start - Whether nothing has been consumed since the start
+///              of the string, which matters at the end of the input
+pub fn step(prog: &[Instruction], classes: &[u32], insts: &[uint],
+            class: Option<uint>, seed: bool, at_start: bool) -> ~[uint] {
+  let mut next = ~[];
+  let mut seen = vec::from_elem(prog.len(), false);
+
+  for &pc in insts.iter() {
+    match (&prog[pc], class) {
+      (&InstMatch, _) => break,
+      (&InstLiteral(m), Some(k)) if classes[k] == m as u32 => {
+        add_closure(prog, pc + 1, &mut next, &mut seen, false, false);
+      }
+      (&InstRange(s, e), Some(k)) if classes[k] >= s as u32 &&
+                                     classes[k] <= e as u32 => {
+        add_closure(prog, pc + 1, &mut next, &mut seen, false, false);
+      }
+      // `$^` can match an empty input, so the start has to be
+      // known here too
+      (&InstAssertEnd, None) => {
+        add_closure(prog, pc + 1, &mut next, &mut seen, at_start, true);
+      }
+      _ => ()
+    }
+  }
+
+  if (seed && class.is_some()) {
+    add_closure(prog, 0, &mut next, &mut seen, false, false);
+  }
+
+  next
+}
+
+/// Checks if any of the threads in `insts` have matched.
+pub fn has_match(prog: &[Instruction], insts: &[uint]) -> bool {
+  insts.iter().any(|&pc| {
+    match prog[pc] {
+      InstMatch => true,
+      _ => false
+    }
+  })
+}
+
+/// Fills in the captures of a match a DFA found ending at `end`.
+///
+/// The `PikeVM` only has to run up to the end of the match. If the
+/// match was anchored and there are no groups, the DFA already
+/// knows everything, so it isn't needed at all.
+fn capture_pass(inst: &[Instruction], ncaps: uint, threads: &RefCell<Threads>,
+                input: &str, start: uint, end: uint,
+                anchored: bool) -> Option<Thread> {
+  if (anchored && ncaps == 0) {
+    return Some(Thread { pc: 0, start: start, end: end, captures: ~[] })
+  }
+
+  PikeVM::new(inst, ncaps, threads).execute(input, start, end, anchored)
+}
+
+/// A state of the `LazyDFA`: the instructions the `PikeVM`'s
+/// threads would be at, in priority order.
+struct DFAState {
+  insts: ~[uint],
+  // Set while no match has been found in an unanchored search,
+  // so a new thread is started at every position
+  seeding: bool,
+  // Set for a start state at the beginning of the string
+  at_start: bool,
+  matched: bool,
+  // The state for each character class, and the end of the
+  // input last. Filled in as they're needed.
+  next: ~[Option<uint>]
+}
+
+/// The states the `LazyDFA` has built so far. A compiled
+/// `Regexp` holds onto this, so states are shared between calls.
+pub struct DFACache {
+  priv classes: ~[u32],
+  priv states: ~[DFAState],
+  priv index: HashMap<(~[uint], bool, bool), uint>,
+  priv limit: uint
+}
+
+impl DFACache {
+  /// Creates an empty cache for a program. The number of states
+  /// is limited so the transitions take up about `CACHE_SIZE` bytes.
+  pub fn new(prog: &[Instruction]) -> DFACache {
+    let classes = char_classes(prog);
+    let size = (classes.len() + 1) * mem::size_of::<Option<uint>>();
+
+    DFACache::with_limit(classes, max(CACHE_SIZE / size, MIN_STATES))
+  }
+  /// Creates an empty cache that holds at most `limit` states.
+  fn with_limit(classes: ~[u32], limit: uint) -> DFACache {
+    DFACache {
+      classes: classes,
+      states: ~[],
+      index: HashMap::new(),
+      limit: limit
+    }
+  }
+}
+
+impl DFACache {
+  /// Finds a state, or adds it if it's new. Returns None if
+  /// the cache is full.
+  fn add_state(&mut self, prog: &[Instruction], insts: ~[uint],
+               seeding: bool, at_start: bool) -> Option<uint> {
+    let key = (insts, seeding, at_start);
+
+    match self.index.find(&key) {
+      Some(&s) => return Some(s),
+      None => ()
+    }
+
+    if (self.states.len() >= self.limit) {
+      return None
+    }
+
+    let (insts, seeding, at_start) = key;
+    let s = self.states.len();
+
+    self.index.insert((insts.clone(), seeding, at_start), s);
+    self.states.push(DFAState {
+      matched: has_match(prog, insts),
+      insts: insts,
+      seeding: seeding,
+      at_start: at_start,
+      next: vec::from_elem(self.classes.len() + 1, None)
+    });
+
+    Some(s)
+  }
+  /// Finds the state a search starts in.
+  fn start_state(&mut self, prog: &[Instruction], at_start: bool,
+                 anchored: bool) -> Option<uint> {
+    self.add_state(prog, start_insts(prog, at_start), !anchored, at_start)
+  }
+  /// Follows the transition from a state over a character class,
+  /// or over the end of the input if `class` is None. The
+  /// transition is built the first time it's followed.
+  fn transition(&mut self, prog: &[Instruction], s: uint,
+                class: Option<uint>) -> Option<uint> {
+    let k = match class {
+      Some(k) => k,
+      None => self.classes.len()
+    };
+
+    match self.states[s].next[k] {
+      Some(n) => return Some(n),
+      None => ()
+    }
+
+    let seeding = self.states[s].seeding && !self.states[s].matched;
+    let insts = step(prog, self.classes, self.states[s].insts, class, seeding,
+                     self.states[s].at_start);
+
+    match self.add_state(prog, insts, seeding, false) {
+      Some(n) => {
+        self.states[s].next[k] = Some(n);
+        Some(n)
+      }
+      None => None
+    }
+  }
+  /// Throws away all the states.
+  fn clear(&mut self) {
+    self.states.clear();
+    self.index.clear();
+  }
+}
+
+/// The outcome of running the `LazyDFA` over some input.
+enum DFAResult {
+  // The end of the match
+  DFAMatch(uint),
+  DFANoMatch,
+  // The state cache filled up
+  DFAGaveUp
+}
+
+/// Lazy DFA implementation
+///
+/// Builds DFA states from the program as the input needs them,
+/// each standing for the set of threads the `PikeVM` would have.
+/// This finds where a match ends with one lookup per character,
+/// but knows nothing about captures, so the `PikeVM` fills those
+/// in afterwards, without having to look past the end of the match.
+///
+/// Only runs programs `dfa_supported` accepts. If the state cache
+/// fills up, it's cleared and the `PikeVM` is used instead.
+pub struct LazyDFA<'a> {
+  priv inst:    &'a [Instruction],
+  priv ncaps:   uint,
+  priv cache:   &'a RefCell<DFACache>,
+  priv threads: &'a RefCell<Threads>
+}
+
+impl<'a> LazyDFA<'a> {
+  pub fn new(inst: &'a [Instruction], ncaps: uint,
+             cache: &'a RefCell<DFACache>,
+             threads: &'a RefCell<Threads>) -> LazyDFA<'a> {
+    LazyDFA {
+      inst: inst,
+      ncaps: ncaps,
+      cache: cache,
+      threads: threads
+    }
+  }
+}
+
+impl<'a> LazyDFA<'a> {
+  /// Runs the DFA over the input, and finds the end of the
+  /// leftmost match.
+  ///
+  /// # Arguments
+  ///
+  /// * input - The input string
+  /// * start - The position to start from
+  /// * anchored - Whether the match has to start at `start`
+  /// * shortest - Whether to stop as soon as anything matches
+  fn find(&self, input: &str, start: uint, anchored: bool,
+          shortest: bool) -> DFAResult {
+    let mut borrow = self.cache.borrow_mut();
+    let cache = borrow.get();
+    let len = input.len();
+    let mut sp = start;
+    let mut found = None;
+
+    macro_rules! next_state(
+      ($s: expr, $class: expr) => (
+        match cache.transition(self.inst, $s, $class) {
+          Some(n) => n,
+          None => {
+            cache.clear();
+            return DFAGaveUp
+          }
+        }
+      );
+    )
+
+    let mut s = match cache.start_state(self.inst, start == 0, anchored) {
+      Some(s) => s,
+      None => {
+        cache.clear();
+        return DFAGaveUp
+      }
+    };
+
+    loop {
+      if (cache.states[s].matched) {
+        found = Some(sp);
+        if (shortest) {
+          break;
+        }
+      }
+
+      // No threads left, and none will be started
+      if (cache.states[s].insts.len() == 0) {
+        break;
+      }
+
+      if (sp >= len) {
+        s = next_state!(s, None);
+        if (cache.states[s].matched) {
+          found = Some(sp);
+        }
+        break;
+      }
+
+      let c = input.char_at(sp);
+
+      s = next_state!(s, Some(class_of(cache.classes, c)));
+      sp += c.len_utf8_bytes();
+    }
+
+    match found {
+      Some(end) => DFAMatch(end),
+      None => DFANoMatch
+    }
+  }
+  /// Runs the program, letting the DFA find where the match
+  /// ends before the `PikeVM` fills in the captures.
+  fn execute(&self, input: &str, start: uint, anchored: bool) -> Option<Thread> {
+    match self.find(input, start, anchored, false) {
+      DFAMatch(end) => {
+        capture_pass(self.inst, self.ncaps, self.threads, input, start,
+                     end, anchored)
+      }
+      DFANoMatch => None,
+      DFAGaveUp => {
+        let pike = PikeVM::new(self.inst, self.ncaps, self.threads);
+        pike.execute(input, start, input.len(), anchored)
+      }
+    }
+  }
+  /// Checks if there is a match at or after `start`, without
+  /// finding where it is.
+  pub fn is_match(&self, input: &str, start: uint) -> bool {
+    match self.find(input, start, false, true) {
+      DFAMatch(_) => true,
+      DFANoMatch => false,
+      DFAGaveUp => {
+        let pike = PikeVM::new(self.inst, self.ncaps, self.threads);
+        pike.search(input, start).is_some()
+      }
+    }
+  }
+}
+
+impl<'a> ExecStrategy for LazyDFA<'a> {
+  fn run(&self, input: &str, start: uint) -> Option<Thread> {
+    self.execute(input, start, true)
+  }
+  fn search(&self, input: &str, start: uint) -> Option<Thread> {
+    self.execute(input, start, false)
+  }
+}
+
+/// Finds which of the start states a search begins in.
+#[inline]
+fn start_index(at_start: bool, anchored: bool) -> uint {
+  (if (at_start) { 2 } else { 0 }) + (if (anchored) { 1 } else { 0 })
+}
+
+/// Finds a state built by the subset construction, or adds it if
+/// it's new.
+///
+/// # Arguments
+///
+/// * index - The states built so far, by their threads
+/// * states - The threads of each state
+/// * insts - The instructions threads are at
+/// * seeding - Whether new threads are still being started
+/// * at_start - Whether it's a start state at the beginning of
+///              the string
+/// * max_states - The most states there can be
+fn add_subset(index: &mut HashMap<(~[uint], bool, bool), uint>,
+              states: &mut ~[(~[uint], bool, bool)], insts: ~[uint], 
+              seeding: bool, at_start: bool, 
+              max_states: uint) -> Result<uint, DFACode> {
+  let key = (insts, seeding, at_start);
+
+  match index.find(&key) {
+    Some(&s) => return Ok(s),
+    None => ()
+  }
+
+  if (states.len() >= max_states) {
+    return Err(DFATooManyStates)
+  }
+
+  let s = states.len();
+
+  index.insert(key.clone(), s);
+  states.push(key);
+
+  Ok(s)
+}
+
+/// Merges states that can't be told apart, with Hopcroft's
+/// algorithm. States start out split by whether they match,
+/// and blocks are split until every state in a block moves to
+/// the same block on every symbol.
+///
+/// Returns the block each state ends up in, and the number
+/// of blocks.
+///
+/// # Arguments
+///
+/// * trans - The next state for each state and symbol
+/// * accept - Whether each state matches
+fn minimize(trans: &[~[uint]], accept: &[bool]) -> (~[uint], uint) {
+  let n = trans.len();
+  let nsyms = trans[0].len();
+
+  // The states that move into each state, for each symbol
+  let mut inv: ~[~[~[uint]]] = vec::from_fn(nsyms, |_| vec::from_fn(n, |_| ~[]));
+
+  for q in range(0, n) {
+    for c in range(0, nsyms) {
+      inv[c][trans[q][c]].push(q);
+    }
+  }
+
+  let mut blocks: ~[~[uint]] = ~[];
+  let mut block_of = vec::from_elem(n, 0u);
+
+  for &matched in [true, false].iter() {
+    let block: ~[uint] = range(0, n).filter(|&q| accept[q] == matched).collect();
+
+    if (block.len() > 0) {
+      for &q in block.iter() {
+        block_of[q] = blocks.len();
+      }
+      blocks.push(block);
+    }
+  }
+
+  let mut work: ~[uint] = range(0, blocks.len()).collect();
+  let mut in_work = vec::from_elem(blocks.len(), true);
+  let mut marked = vec::from_elem(n, false);
+  let mut count = vec::from_elem(n, 0u);
+
+  loop {
+    let a = match work.pop_opt() {
+      Some(a) => a,
+      None => break
+    };
+    in_work[a] = false;
+
+    let splitter = blocks[a].clone();
+
+    for c in range(0, nsyms) {
+      let mut touched = ~[];
+
+      for &q in splitter.iter() {
+        for &p in inv[c][q].iter() {
+          if (!marked[p]) {
+            marked[p] = true;
+            if (count[block_of[p]] == 0) {
+              touched.push(block_of[p]);
+            }
+            count[block_of[p]] += 1;
+          }
+        }
+      }
+
+      for &b in touched.iter() {
+        if (count[b] < blocks[b].len()) {
+          let mut inside = ~[];
+          let mut outside = ~[];
+
+          for &q in blocks[b].iter() {
+            if (marked[q]) {
+              inside.push(q);
+            } else {
+              outside.push(q);
+            }
+          }
+
+          let nb = blocks.len();
+
+          for &q in outside.iter() {
+            block_of[q] = nb;
+          }
+
+          let smaller = if (inside.len() <= outside.len()) { b } else { nb };
+
+          blocks[b] = inside;
+          blocks.push(outside);
+          in_work.push(false);
+
+          // If the old block still has to be split by, both halves
+          // do. Otherwise the smaller half is enough.
+          if (in_work[b]) {
+            work.push(nb);
+            in_work[nb] = true;
+          } else {
+            work.push(smaller);
+            in_work[smaller] = true;
+          }
+        }
+        count[b] = 0;
+      }
+
+      for &q in splitter.iter() {
+        for &p in inv[c][q].iter() {
+          marked[p] = false;
+        }
+      }
+    }
+  }
+
+  (block_of, blocks.len())
+}
+
+/// A complete, minimized DFA, built ahead of time from a program.
+///
+/// Like the `LazyDFA`, each state stands for the threads the
+/// `PikeVM` would have, but all of them are built up front, so
+/// running it never has to fall back.
+pub struct DFA {
+  priv classes: ~[u32],
+  // The next state for each state and symbol, one row per state.
+  // The last symbol is the end of the input.
+  priv trans: ~[uint],
+  priv accept: ~[bool],
+  // States that can never lead to a match
+  priv dead: ~[bool],
+  priv starts: ~[uint]
+}
+
+impl DFA {
+  /// Builds the DFA for a program with the subset construction,
+  /// then minimizes it.
+  ///
+  /// # Arguments
+  ///
+  /// * prog - The compiled program
+  /// * max_states - The most states the DFA can have before
+  ///                minimizing. Building stops with an error
+  ///                past this.
+  pub fn new(prog: &[Instruction], max_states: uint) -> Result<DFA, DFACode> {
+    if (!dfa_supported(prog)) {
+      return Err(DFAUnsupported)
+    }
+
+    let classes = char_classes(prog);
+    let nsyms = classes.len() + 1;
+    let mut index = HashMap::new();
+    let mut states = ~[];
+    let mut starts = ~[];
+
+    for &at_start in [false, true].iter() {
+      for &anchored in [false, true].iter() {
+        let insts = start_insts(prog, at_start);
+        match add_subset(&mut index, &mut states, insts, !anchored, at_start,
+                         max_states) {
+          Ok(s) => starts.push(s),
+          Err(e) => return Err(e)
+        }
+      }
+    }
+
+    let mut trans: ~[~[uint]] = ~[];
+    let mut accept = ~[];
+    let mut s = 0;
+
+    // New states are added to the end as they're found
+    while (s < states.len()) {
+      let (insts, seeding, at_start) = states[s].clone();
+      let matched = has_match(prog, insts);
+      let seeding = seeding && !matched;
+      let mut row = vec::with_capacity(nsyms);
+
+      for k in range(0, nsyms) {
+        let class = if (k < classes.len()) { Some(k) } else { None };
+        let next = step(prog, classes, insts, class, seeding, at_start);
+
+        match add_subset(&mut index, &mut states, next, seeding, false, 
+                         max_states) {
+          Ok(n) => row.push(n),
+          Err(e) => return Err(e)
+        }
+      }
+
+      trans.push(row);
+      accept.push(matched);
+      s += 1;
+    }
+
+    let (block_of, nblocks) = minimize(trans, accept);
+    let mut min_trans = vec::from_elem(nblocks * nsyms, 0u);
+    let mut min_accept = vec::from_elem(nblocks, false);
+
+    for q in range(0, trans.len()) {
+      let b = block_of[q];
+      min_accept[b] = accept[q];
+      for k in range(0, nsyms) {
+        min_trans[b * nsyms + k] = block_of[trans[q][k]];
+      }
+    }
+
+    // Anything that can't reach a matching state is dead, so
+    // running can stop as soon as it gets there
+    let mut dead = vec::from_fn(nblocks, |b| !min_accept[b]);
+    let mut changed = true;
+
+    while (changed) {
+      changed = false;
+      for b in range(0, nblocks) {
+        if (dead[b] && range(0, nsyms).any(|k| !dead[min_trans[b * nsyms + k]])) {
+          dead[b] = false;
+          changed = true;
+        }
+      }
+    }
+
+    Ok(DFA {
+      classes: classes,
+      trans: min_trans,
+      accept: min_accept,
+      dead: dead,
+      starts: starts.iter().map(|&s| block_of[s]).collect()
+    })
+  }
+}
+
+impl DFA {
+  /// Returns the number of states.
+  pub fn len(&self) -> uint {
+    self.accept.len()
+  }
+  /// Runs the DFA over the input, and finds the end of the
+  /// leftmost match.
+  ///
+  /// # Arguments
+  ///
+  /// * input - The input string
+  /// * start - The position to start from
+  /// * anchored - Whether the match has to start at `start`
+  /// * shortest - Whether to stop as soon as anything matches
+  fn find(&self, input: &str, start: uint, anchored: bool,
+          shortest: bool) -> Option<uint> {
+    let nsyms = self.classes.len() + 1;
+    let len = input.len();
+    let mut s = self.starts[start_index(start == 0, anchored)];
+    let mut sp = start;
+    let mut found = None;
+
+    loop {
+      if (self.accept[s]) {
+        found = Some(sp);
+        if (shortest) {
+          break;
+        }
+      }
+
+      if (self.dead[s]) {
+        break;
+      }
+
+      if (sp >= len) {
+        s = self.trans[s * nsyms + nsyms - 1];
+        if (self.accept[s]) {
+          found = Some(sp);
+        }
+        break;
+      }
+
+      let c = input.char_at(sp);
+
+      s = self.trans[s * nsyms + class_of(self.classes, c)];
+      sp += c.len_utf8_bytes();
+    }
+
+    found
+  }
+  /// Checks if there is a match anywhere in the input.
+  pub fn is_match(&self, input: &str) -> bool {
+    self.find(input, 0, false, true).is_some()
+  }
+}
+
+/// Full DFA implementation
+///
+/// Runs a `DFA` built ahead of time to find where a match ends,
+/// then lets the `PikeVM` fill in the captures.
+pub struct FullDFA<'a> {
+  priv dfa:     &'a DFA,
+  priv inst:    &'a [Instruction],
+  priv ncaps:   uint,
+  priv threads: &'a RefCell<Threads>
+}
+
+impl<'a> FullDFA<'a> {
+  pub fn new(dfa: &'a DFA, inst: &'a [Instruction], ncaps: uint,
+             threads: &'a RefCell<Threads>) -> FullDFA<'a> {
+    FullDFA {
+      dfa: dfa,
+      inst: inst,
+      ncaps: ncaps,
+      threads: threads
+    }
+  }
+}
+
+impl<'a> FullDFA<'a> {
+  fn execute(&self, input: &str, start: uint, anchored: bool) -> Option<Thread> {
+    match self.dfa.find(input, start, anchored, false) {
+      Some(end) => {
+        capture_pass(self.inst, self.ncaps, self.threads, input, start,
+                     end, anchored)
+      }
+      None => None
+    }
+  }
+}
+
+impl<'a> ExecStrategy for FullDFA<'a> {
+  fn run(&self, input: &str, start: uint) -> Option<Thread> {
+    self.execute(input, start, true)
+  }
+  fn search(&self, input: &str, start: uint) -> Option<Thread> {
+    self.execute(input, start, false)
+  }
+}
+
+#[cfg(test)]
+mod dfa_tests {
+  use std::cell::RefCell;
+  use dfa::*;
+  use error::DFAError::*;
+  use exec::{ExecStrategy, PikeVM, Threads};
+  use compile::{compile_recursive, count_captures};
+  use parse::parse;
+
+  macro_rules! test_dfa(
+    ($re: expr, $input: expr, $limit: expr) => (
+      {
+        let prog = compile_recursive(&parse($re, 0).unwrap());
+        let ncaps = count_captures(prog);
+        let cache = RefCell::new(DFACache::with_limit(char_classes(prog), $limit));
+        let threads = RefCell::new(Threads::new(prog.len()));
+        let other = RefCell::new(Threads::new(prog.len()));
+
+        assert!(dfa_supported(prog));
+
+        let dfa = LazyDFA::new(prog, ncaps, &cache, &threads);
+        let pike = PikeVM::new(prog, ncaps, &other);
+
+        for _ in range(0, 2) {
+          let expect = pike.search($input, 0).map(|t| (t.start, t.end));
+          assert_eq!(dfa.search($input, 0).map(|t| (t.start, t.end)), expect);
+          assert_eq!(dfa.is_match($input, 0), expect.is_some());
+          let expect = pike.run($input, 0).map(|t| (t.start, t.end));
+          assert_eq!(dfa.run($input, 0).map(|t| (t.start, t.end)), expect);
+        }
+      }
+    );
+  )
+
+  #[test]
+  fn char_classes_test() {
+    let prog = compile_recursive(&parse("a[b-d]", 0).unwrap());
+    let classes = char_classes(prog);
+    assert_eq!(classes, ~[0, 'a' as u32, 'b' as u32, 'e' as u32]);
+    assert_eq!(class_of(classes, 'c'), 2);
+    assert_eq!(class_of(classes, 'z'), 3);
+  }
+
+  #[test]
+  fn dfa_supported_test() {
+    let prog = compile_recursive(&parse("^a(b|c)*$", 0).unwrap());
+    assert!(dfa_supported(prog));
+    let prog = compile_recursive(&parse("a\\b", 0).unwrap());
+    assert!(!dfa_supported(prog));
+  }
+
+  #[test]
+  fn dfa_matches_pike_test() {
+    test_dfa!("a+b", "xxaaab", 1000);
+    test_dfa!("a|ab", "ab", 1000);
+    test_dfa!("ab|a", "ab", 1000);
+    test_dfa!("a*?b", "caab", 1000);
+    test_dfa!("x*", "yyy", 1000);
+    test_dfa!("^ab", "cab", 1000);
+    test_dfa!("b$", "abb", 1000);
+    test_dfa!("(a$)|a", "ab", 1000);
+    test_dfa!("é+", "aéé", 1000);
+  }
+
+  macro_rules! test_full_dfa(
+    ($re: expr, $input: expr) => (
+      {
+        let prog = compile_recursive(&parse($re, 0).unwrap());
+        let ncaps = count_captures(prog);
+        let dfa = DFA::new(prog, 10000).unwrap();
+        let threads = RefCell::new(Threads::new(prog.len()));
+        let other = RefCell::new(Threads::new(prog.len()));
+
+        let full = FullDFA::new(&dfa, prog, ncaps, &threads);
+        let pike = PikeVM::new(prog, ncaps, &other);
+
+        let expect = pike.search($input, 0).map(|t| (t.start, t.end));
+        assert_eq!(full.search($input, 0).map(|t| (t.start, t.end)), expect);
+        assert_eq!(dfa.is_match($input), expect.is_some());
+        let expect = pike.run($input, 0).map(|t| (t.start, t.end));
+        assert_eq!(full.run($input, 0).map(|t| (t.start, t.end)), expect);
+      }
+    );
+  )
+
+  fn dfa_len(re: &str) -> uint {
+    let prog = compile_recursive(&parse(re, 0).unwrap());
+    DFA::new(prog, 10000).unwrap().len()
+  }
+
+  #[test]
+  fn full_dfa_matches_pike_test() {
+    test_full_dfa!("a+b", "xxaaab");
+    test_full_dfa!("a|ab", "ab");
+    test_full_dfa!("ab|a", "ab");
+    test_full_dfa!("^\\d{4}-\\d{2}$", "2014-01");
+    test_full_dfa!("^\\d{4}-\\d{2}$", "2014-011");
+    test_full_dfa!("(a$)|a", "ab");
+    test_full_dfa!("x*", "yyy");
+  }
+
+  #[test]
+  fn dfa_empty_input_test() {
+    // The end of an empty input is also its start
+    for &re in ["$^", "^$", "a*$^", "$^|b"].iter() {
+      test_dfa!(re, "", 1000);
+      test_dfa!(re, "a", 1000);
+      test_full_dfa!(re, "");
+      test_full_dfa!(re, "a");
+    }
+  }
+
+  #[test]
+  fn full_dfa_minimize_test() {
+    assert_eq!(dfa_len("(a|b)*c"), dfa_len("[ab]*c"));
+    assert_eq!(dfa_len("a|b"), dfa_len("[ab]"));
+  }
+
+  #[test]
+  fn full_dfa_limit_test() {
+    let prog = compile_recursive(&parse("(a|b)*a(a|b)(a|b)(a|b)(a|b)", 0).unwrap());
+    assert_eq!(DFA::new(prog, 10).err(), Some(DFATooManyStates));
+    let prog = compile_recursive(&parse("(a)\\1", 0).unwrap());
+    assert_eq!(DFA::new(prog, 10).err(), Some(DFAUnsupported));
+  }
+
+  #[test]
+  fn dfa_cache_full_test() {
+    test_dfa!("(a|b)*abb", "ababbab", 2);
+    test_dfa!("[a-z]+\\d", "hello world4", 3);
+  }
+}
@@ -1,8 +1,10 @@
 // define a bunch of possible errors 
 // that we know can be generated
 
+use error::ParseCodes::ParseCode;
+
 // parsing codes 
-pub mod ParseError {
+pub mod ParseCodes {
   static PARSE_ERR: &'static str = "Parse Error: ";
 
   pub enum ParseCode {
@@ -29,9 +31,28 @@ pub mod ParseError {
     ParseUnexpectedCharacter,
 
     ParseIncompleteEscapeSeq,
+    ParseInvalidHexEscape,
+    ParseInvalidOctalEscape,
+    ParseInvalidControlEscape,
+    ParseInvalidCodePoint,
+    ParseUnknownEscape,
+
+    // group errors
+    ParseExpectedOpeningAngle,
+    ParseExpectedClosingAngle,
+    ParseInvalidGroupName,
+    ParseDuplicateGroupName,
+    ParseUnknownGroupName,
+    ParseInvalidBackreference,
+    ParseOpenGroupBackreference,
+    ParseVariableWidthLookbehind,
+    ParseUnknownFlag,
+    ParseUnknownProperty,
 
     // char class errors
     ParseEmptyCharClassRange,
+    ParseUnknownPosixClass,
+    ParseBadCharacterRange,
 
     // library errors
     ParseInternalError,
@@ -59,7 +80,24 @@ pub mod ParseError {
         ParseUnexpectedOperand      => PARSE_ERR + "Unexpected operand was on the stack",
         ParseUnexpectedCharacter    => PARSE_ERR + "Unexpected character in input",
         ParseIncompleteEscapeSeq    => PARSE_ERR + "Expected a character to escape",
+        ParseInvalidHexEscape       => PARSE_ERR + "Bad hexadecimal escape",
+        ParseInvalidOctalEscape     => PARSE_ERR + "Octal escape is greater than \\377",
+        ParseInvalidControlEscape   => PARSE_ERR + "Bad control character escape",
+        ParseInvalidCodePoint       => PARSE_ERR + "Escape is not a valid code point",
+        ParseUnknownEscape          => PARSE_ERR + "Unknown escape sequence",
+        ParseExpectedOpeningAngle   => PARSE_ERR + "Expected '<'",
+        ParseExpectedClosingAngle   => PARSE_ERR + "Expected '>'",
+        ParseInvalidGroupName       => PARSE_ERR + "Bad character in group name",
+        ParseDuplicateGroupName     => PARSE_ERR + "Redefinition of group name",
+        ParseUnknownGroupName       => PARSE_ERR + "Unknown group name",
+        ParseInvalidBackreference   => PARSE_ERR + "Invalid group reference",
+        ParseOpenGroupBackreference => PARSE_ERR + "Cannot refer to an open group",
+        ParseVariableWidthLookbehind => PARSE_ERR + "Look-behind requires fixed-width pattern",
+        ParseUnknownFlag            => PARSE_ERR + "Unknown flag",
+        ParseUnknownProperty        => PARSE_ERR + "Unknown Unicode property",
         ParseEmptyCharClassRange    => PARSE_ERR + "Empty character class",
+        ParseUnknownPosixClass      => PARSE_ERR + "Unknown POSIX class name",
+        ParseBadCharacterRange      => PARSE_ERR + "Bad character range",
         ParseInternalError |
         ParseNotRepetition |
         ParseUnknownError           => PARSE_ERR + "Unknown error (probably a bug)",
@@ -68,3 +106,151 @@ pub mod ParseError {
     }
   }
 }
+
+/// A parse error, with where it happened in the pattern.
+pub struct ParseError {
+  code: ParseCode,
+  // Byte and character offsets of the start of the span
+  offset: uint,
+  position: uint,
+  // Byte range of the part of the pattern that 
+  // caused the error
+  span: (uint, uint),
+  priv pattern: ~str
+}
+
+impl ParseError {
+  /// Creates an error for the span `start..end` (byte offsets) 
+  /// of a pattern.
+  pub fn new(code: ParseCode, pattern: &str, start: uint, 
+             end: uint) -> ParseError {
+    ParseError {
+      code: code,
+      offset: start,
+      position: pattern.slice_to(start).char_len(),
+      span: (start, end),
+      pattern: pattern.to_owned()
+    }
+  }
+
+  /// Formats the error with the line of the pattern it happened 
+  /// on, and a caret underline beneath the span. For example:
+  ///
+  /// ~~~
+  /// Parse Error: Bad hexadecimal escape at position 2
+  /// ab\x4z
+  ///   ^^^
+  /// ~~~
+  pub fn diagnostic(&self) -> ~str {
+    let (start, end) = self.span;
+
+    // Only show the line with the start of the span, 
+    // in case the pattern is in verbose mode
+    let line_start = match self.pattern.slice_to(start).rfind('\n') {
+      Some(i) => i + 1,
+      None => 0
+    };
+    let line_end = match self.pattern.slice_from(start).find('\n') {
+      Some(i) => start + i,
+      None => self.pattern.len()
+    };
+
+    let line = self.pattern.slice(line_start, line_end);
+    let mut underline = ~"";
+
+    // Keep tabs, so that the caret lines up
+    for c in self.pattern.slice(line_start, start).chars() {
+      underline.push_char(if (c == '\t') { '\t' } else { ' ' });
+    }
+
+    let width = if (end > line_end) { line_end } else { end };
+    let ncarets = self.pattern.slice(start, width).char_len();
+
+    for _ in range(0, if (ncarets == 0) { 1 } else { ncarets }) {
+      underline.push_char('^');
+    }
+
+    format!("{:s}\n{:s}\n{:s}", self.to_str(), line, underline)
+  }
+}
+
+impl ToStr for ParseError {
+  fn to_str(&self) -> ~str {
+    format!("{:s} at position {:u}", self.code.to_str(), self.position)
+  }
+}
+
+// replacement template codes
+pub mod TemplateError {
+  static TEMPLATE_ERR: &'static str = "Template Error: ";
+
+  #[deriving(Eq)]
+  pub enum TemplateCode {
+    TemplateIncompleteEscapeSeq,
+    TemplateExpectedOpeningAngle,
+    TemplateExpectedClosingAngle,
+    TemplateEmptyGroupName,
+    TemplateInvalidGroupReference,
+    TemplateUnknownGroupName,
+    TemplateBadEscape
+  }
+
+  impl ToStr for TemplateCode {
+    fn to_str(&self) -> ~str {
+      match *self {
+        TemplateIncompleteEscapeSeq   => TEMPLATE_ERR + "Expected a character to escape",
+        TemplateExpectedOpeningAngle  => TEMPLATE_ERR + "Expected '<'",
+        TemplateExpectedClosingAngle  => TEMPLATE_ERR + "Expected '>'",
+        TemplateEmptyGroupName        => TEMPLATE_ERR + "Missing group name",
+        TemplateInvalidGroupReference => TEMPLATE_ERR + "Invalid group reference",
+        TemplateUnknownGroupName      => TEMPLATE_ERR + "Unknown group name",
+        TemplateBadEscape             => TEMPLATE_ERR + "Bad escape"
+      }
+    }
+  }
+}
+
+// DFA construction codes
+pub mod DFAError {
+  static DFA_ERR: &'static str = "DFA Error: ";
+
+  #[deriving(Eq)]
+  pub enum DFACode {
+    DFAUnsupported,
+    DFATooManyStates
+  }
+
+  impl ToStr for DFACode {
+    fn to_str(&self) -> ~str {
+      match *self {
+        DFAUnsupported   => DFA_ERR + "Pattern can't be run by a DFA",
+        DFATooManyStates => DFA_ERR + "Too many states"
+      }
+    }
+  }
+}
+
+// building codes, for `RegexpBuilder`
+pub mod BuildError {
+  use error::ParseError;
+  use error::DFAError::DFACode;
+
+  static BUILD_ERR: &'static str = "Build Error: ";
+
+  pub enum BuildCode {
+    BuildParseError(ParseError),
+    BuildDFAError(DFACode),
+    // The engine asked for can't run the pattern
+    BuildUnsupportedStrategy
+  }
+
+  impl ToStr for BuildCode {
+    fn to_str(&self) -> ~str {
+      match *self {
+        BuildParseError(ref e)   => e.to_str(),
+        BuildDFAError(ref e)     => e.to_str(),
+        BuildUnsupportedStrategy => BUILD_ERR + "Engine can't run the pattern"
+      }
+    }
+  }
+}
@@ -1,63 +1,347 @@
 use std::vec;
 use std::util::swap;
-use compile::Instruction;
-use compile::{InstLiteral, InstRange, InstMatch, InstJump, 
-  InstCaptureStart, InstCaptureEnd, InstSplit, 
-  InstAssertStart, InstAssertEnd, InstWordBoundary,
-  InstNonWordBoundary, InstNoop};
-use result::{Match, CapturingGroup};
-
-/// This should be able to take compiled 
+use std::cell::RefCell;
+use std::hashmap::HashSet;
+use compile::{Instruction, needs_backtrack};
+use compile::{InstLiteral, InstRange, InstMatch, InstJump,
+  InstCaptureStart, InstCaptureEnd, InstBackref, InstSplit,
+  InstAssertStart, InstAssertEnd, InstAssertLineStart, 
+  InstAssertLineEnd, InstWordBoundary,
+  InstNonWordBoundary, InstLookahead, InstLookbehind, InstAtomic, InstNoop};
+use result::CapturingGroup;
+use charclass::{fold_eq, is_word_char};
+use flags::{CRLF, NEWLINE_ANY};
+
+/// This should be able to take compiled
 /// instructions and execute them (see compile.rs)
+///
+/// Positions passed in and returned are byte offsets
+/// into the input string.
 pub trait ExecStrategy {
-  fn run(&self, input: &str, start_index: uint) -> Option<Thread>;
+  /// Tries to match the program starting exactly at `start`.
+  fn run(&self, input: &str, start: uint) -> Option<Thread>;
+
+  /// Finds the leftmost match at or after `start`.
+  ///
+  /// By default, this tries an anchored match at every character
+  /// boundary. Strategies that can do better should override it.
+  fn search(&self, input: &str, start: uint) -> Option<Thread> {
+    let mut sp = start;
+
+    loop {
+      match self.run(input, sp) {
+        Some(t) => return Some(t),
+        None => ()
+      }
+      if (sp >= input.len()) {
+        return None
+      }
+      sp += input.char_at(sp).len_utf8_bytes();
+    }
+  }
 }
 
 #[deriving(Clone)]
-struct Thread {
+pub struct Thread {
   pc: uint,
+  start: uint,
   end: uint,
   captures: ~[Option<CapturingGroup>]
 }
 
 impl Thread {
-  fn new(pc: uint, end: uint) -> Thread {
-    Thread { 
-      pc: pc, 
-      end: end,
-      captures: ~[]
+  fn new(pc: uint, start: uint, ncaps: uint) -> Thread {
+    Thread {
+      pc: pc,
+      start: start,
+      end: start,
+      captures: vec::from_elem(ncaps, None)
     }
   }
 }
 
 impl ToStr for Thread {
   fn to_str(&self) -> ~str {
-    format!("<Thread pc: {:u}, end: {:u}>", self.pc, self.end)
+    format!("<Thread pc: {:u}, start: {:u}, end: {:u}>", self.pc,
+            self.start, self.end)
+  }
+}
+
+/// A list of threads for a single step of the `PikeVM`.
+///
+/// `marks` records the generation an instruction was last
+/// added in, so that each instruction only has one thread per
+/// step (the one with the highest priority). Clearing the list
+/// just bumps the generation.
+struct ThreadList {
+  threads: ~[Thread],
+  marks: ~[uint],
+  gen: uint
+}
+
+impl ThreadList {
+  fn new(size: uint) -> ThreadList {
+    ThreadList {
+      threads: vec::with_capacity(size),
+      marks: vec::from_elem(size, 0u),
+      gen: 1
+    }
+  }
+  /// Marks the instruction as seen for this step. Returns
+  /// false if it was already seen.
+  #[inline]
+  fn mark(&mut self, pc: uint) -> bool {
+    if (self.marks[pc] == self.gen) {
+      false
+    } else {
+      self.marks[pc] = self.gen;
+      true
+    }
+  }
+  #[inline]
+  fn clear(&mut self) {
+    self.threads.clear();
+    self.gen += 1;
+  }
+}
+
+/// The thread lists the `PikeVM` steps through. These are
+/// kept outside of the VM so that a compiled `Regexp` can hold
+/// onto them, and reuse the allocations between calls.
+pub struct Threads {
+  priv clist: ThreadList,
+  priv nlist: ThreadList,
+  // Thread lists for running lookarounds, which are put
+  // back after each one
+  priv spare: ~[Threads]
+}
+
+impl Threads {
+  pub fn new(size: uint) -> Threads {
+    Threads {
+      clist: ThreadList::new(size),
+      nlist: ThreadList::new(size),
+      spare: ~[]
+    }
+  }
+  /// The size of program the thread lists can be used for.
+  fn size(&self) -> uint {
+    self.clist.marks.len()
   }
 }
 
 /// Pike VM implementation
 ///
-/// Supports everything except
-/// Assertions and Backreferences
+/// Supports everything except Backreferences and atomic groups
 pub struct PikeVM<'a> {
-  priv inst:  &'a [Instruction],
-  priv ncaps: uint
+  priv inst:    &'a [Instruction],
+  priv ncaps:   uint,
+  priv threads: &'a RefCell<Threads>
 }
 
 impl<'a> PikeVM<'a> {
-  pub fn new(inst: &'a [Instruction], ncaps: uint) -> PikeVM<'a> {
+  pub fn new(inst: &'a [Instruction], ncaps: uint,
+             threads: &'a RefCell<Threads>) -> PikeVM<'a> {
     PikeVM {
       inst: inst,
-      ncaps: ncaps 
+      ncaps: ncaps,
+      threads: threads
+    }
+  }
+}
+
+/// Checks if there is a word boundary at a given
+/// position in the input.
+///
+/// # Arguments
+///
+/// * input - The input string
+/// * sp - The position in the input
+/// * ascii - Whether only ASCII characters are word characters
+#[inline]
+fn is_word_boundary(input: &str, sp: uint, ascii: bool) -> bool {
+  let before = sp > 0 && is_word_char(input.char_at_reverse(sp), ascii);
+  let after = sp < input.len() && is_word_char(input.char_at(sp), ascii);
+
+  before != after
+}
+
+/// Runs the program of a lookaround assertion at a position in
+/// the input, and returns the thread of the sub-match if there
+/// is one. 
+///
+/// Lookbehinds always match a fixed number of characters, so they 
+/// are run from `width` characters back, and have to end at `sp`.
+///
+/// # Arguments
+///
+/// * prog - The program of the assertion
+/// * ncaps - The number of capturing groups in the whole pattern
+/// * input - The input string
+/// * sp - The position of the assertion
+/// * width - The width of a lookbehind, or None for a lookahead
+/// * captures - The captures of the thread checking the assertion,
+///              which backreferences inside of it can refer to
+/// * spare - Thread lists that can be reused for the assertion
+fn run_lookaround(prog: &[Instruction], ncaps: uint, input: &str, sp: uint,
+                  width: Option<uint>, captures: &[Option<CapturingGroup>],
+                  spare: &mut ~[Threads]) -> Option<Thread> {
+  let start = match width {
+    Some(w) => {
+      let mut start = sp;
+      for _ in range(0, w) {
+        if (start == 0) {
+          return None
+        }
+        start -= input.char_at_reverse(start).len_utf8_bytes();
+      }
+      start
     }
+    None => sp
+  };
+
+  let found = if (needs_backtrack(prog)) {
+    let mut t = Thread::new(0, start, ncaps);
+    t.captures = captures.to_owned();
+    Backtrack::new(prog, ncaps).execute(input, t)
+  } else {
+    // The `PikeVM` can't do backreferences, so the captures
+    // don't change what matches
+    let threads = match spare.pop_opt() {
+      Some(threads) if threads.size() >= prog.len() => threads,
+      _ => Threads::new(prog.len())
+    };
+    let threads = RefCell::new(threads);
+    let found = PikeVM::new(prog, ncaps, &threads).run(input, start);
+
+    spare.push(threads.unwrap());
+    found
+  };
+
+  match found {
+    Some(t) if width.is_none() || t.end == sp => Some(t),
+    _ => None
+  }
+}
+
+/// Checks a lookaround assertion for a thread. If the assertion 
+/// is positive, the captures from inside of it are copied into 
+/// the thread.
+///
+/// Returns true if the assertion passed.
+fn check_lookaround(t: &mut Thread, prog: &[Instruction], ncaps: uint,
+                    input: &str, sp: uint, width: Option<uint>, 
+                    negated: bool, spare: &mut ~[Threads]) -> bool {
+  match run_lookaround(prog, ncaps, input, sp, width, t.captures, spare) {
+    Some(sub) => {
+      if (negated) {
+        return false
+      }
+      for (i, cap) in sub.captures.move_iter().enumerate() {
+        if (cap.is_some()) {
+          t.captures[i] = cap;
+        }
+      }
+      true
+    }
+    None => negated
+  }
+}
+
+/// Checks if a character ends a line, given the flags a line 
+/// assertion was compiled with.
+#[inline]
+fn is_line_terminator(c: char, flags: uint) -> bool {
+  match c {
+    '\n' => true,
+    '\r' => flags & (CRLF | NEWLINE_ANY) != 0,
+    '\x0b' | '\x0c' | '\x85' | '\u2028' | '\u2029' => {
+      flags & NEWLINE_ANY != 0
+    }
+    _ => false
+  }
+}
+
+/// Checks if a position in the input is at the start of a line.
+///
+/// # Arguments
+///
+/// * input - The input string
+/// * sp - The position in the input
+/// * flags - The flags the assertion was compiled with
+#[inline]
+fn is_line_start(input: &str, sp: uint, flags: uint) -> bool {
+  if (sp == 0) {
+    return true
+  }
+
+  let prev = input.char_at_reverse(sp);
+
+  if (!is_line_terminator(prev, flags)) {
+    return false
+  }
+
+  // Not between a `\r` and a `\n`
+  prev != '\r' || sp == input.len() || input.char_at(sp) != '\n'
+}
+
+/// Checks if a position in the input is at the end of a line.
+///
+/// # Arguments
+///
+/// * input - The input string
+/// * sp - The position in the input
+/// * flags - The flags the assertion was compiled with
+#[inline]
+fn is_line_end(input: &str, sp: uint, flags: uint) -> bool {
+  if (sp == input.len()) {
+    return true
+  }
+
+  let next = input.char_at(sp);
+
+  if (!is_line_terminator(next, flags)) {
+    return false
+  }
+
+  // Not between a `\r` and a `\n`
+  next != '\n' || sp == 0 || !is_line_terminator('\r', flags) ||
+    input.char_at_reverse(sp) != '\r'
+}
+
+/// Checks if an assertion holds at a position in the input.
+/// Anything that isn't a simple assertion always holds.
+///
+/// # Arguments
+///
+/// * inst - The instruction to check
+/// * input - The input string
+/// * sp - The position in the input
+pub fn assertion_holds(inst: &Instruction, input: &str, sp: uint) -> bool {
+  match *inst {
+    InstAssertStart => sp == 0,
+    InstAssertEnd => sp == input.len(),
+    InstAssertLineStart(flags) => is_line_start(input, sp, flags),
+    InstAssertLineEnd(flags) => is_line_end(input, sp, flags),
+    InstWordBoundary(ascii) => is_word_boundary(input, sp, ascii),
+    InstNonWordBoundary(ascii) => !is_word_boundary(input, sp, ascii),
+    _ => true
   }
 }
 
 impl<'a> PikeVM<'a> {
-  #[inline]
-  fn addThread(&self, mut t: Thread, tlist: &mut ~[Thread]) {
+  /// Follows all the instructions that don't consume any input
+  /// from `t`, adding the resulting threads to `tlist`.
+  ///
+  /// Assertions are checked here, since they only depend on
+  /// the position in the input (`sp`).
+  fn addThread(&self, mut t: Thread, tlist: &mut ThreadList,
+               spare: &mut ~[Threads], input: &str, sp: uint) {
     loop {
+      if (!tlist.mark(t.pc)) {
+        return
+      }
+
       match self.inst[t.pc] {
         InstJump(addr) => {
           t.pc = addr;
@@ -68,29 +352,72 @@ impl<'a> PikeVM<'a> {
 
           t.pc = raddr;
 
-          self.addThread(split, tlist);
+          self.addThread(split, tlist, spare, input, sp);
         }
-        InstCaptureStart(num, ref id) => {
+        InstCaptureStart(num, _) => {
           t.pc = t.pc + 1;
-          
-          // Fill in spaces with None, if there is no
-          // knowledge of a capture instruction
-          while (t.captures.len() < num + 1) {
-            t.captures.push(None);
-          }
-
-          t.captures[num] = Some(CapturingGroup::new(t.end, t.end, num));
+          t.captures[num] = Some(CapturingGroup::new(sp, sp, num));
         }
         InstCaptureEnd(num) => {
           t.pc = t.pc + 1;
 
           match t.captures[num] {
             Some(ref mut cap) => {
-              cap.end = t.end;
+              cap.end = sp;
             }
             None => unreachable!()
           }
         }
+        InstAssertStart => {
+          if (sp != 0) {
+            return
+          }
+          t.pc = t.pc + 1;
+        }
+        InstAssertEnd => {
+          if (sp != input.len()) {
+            return
+          }
+          t.pc = t.pc + 1;
+        }
+        InstAssertLineStart(flags) => {
+          if (!is_line_start(input, sp, flags)) {
+            return
+          }
+          t.pc = t.pc + 1;
+        }
+        InstAssertLineEnd(flags) => {
+          if (!is_line_end(input, sp, flags)) {
+            return
+          }
+          t.pc = t.pc + 1;
+        }
+        InstWordBoundary(ascii) => {
+          if (!is_word_boundary(input, sp, ascii)) {
+            return
+          }
+          t.pc = t.pc + 1;
+        }
+        InstNonWordBoundary(ascii) => {
+          if (is_word_boundary(input, sp, ascii)) {
+            return
+          }
+          t.pc = t.pc + 1;
+        }
+        InstLookahead(ref sub, negated) => {
+          if (!check_lookaround(&mut t, *sub, self.ncaps, input, sp,
+                                None, negated, spare)) {
+            return
+          }
+          t.pc = t.pc + 1;
+        }
+        InstLookbehind(ref sub, width, negated) => {
+          if (!check_lookaround(&mut t, *sub, self.ncaps, input, sp,
+                                Some(width), negated, spare)) {
+            return
+          }
+          t.pc = t.pc + 1;
+        }
         InstNoop => {
           t.pc = t.pc + 1;
         }
@@ -98,153 +425,610 @@ impl<'a> PikeVM<'a> {
       }
     }
 
-    tlist.push(t);
+    tlist.threads.push(t);
   }
-}
 
-impl<'a> ExecStrategy for PikeVM<'a> {
-  fn run(&self, input: &str, start_index: uint) -> Option<Thread> {
-    // \x03 is an end of string indicator. it resolves issues
-    // the program reaches the end of the string, and still
-    // needs to perform instructions
-    // This needs to be accounted for when computing things like
-    // the end of the input string
-    let input = input.to_owned().append("\x03");
+  /// Runs the program over the input in a single left to right pass.
+  ///
+  /// If the search is unanchored, a new thread is started at every
+  /// position until a match is found, as if the program was prefixed
+  /// with `.*?`. Seeded threads have the lowest priority, so the
+  /// leftmost match still wins.
+  ///
+  /// Threads stop consuming input at `end`, though assertions still
+  /// look at the whole input. This lets the `LazyDFA` limit the
+  /// work once it knows where the match ends.
+  pub fn execute(&self, input: &str, start: uint, end: uint,
+                 anchored: bool) -> Option<Thread> {
 
     // `sp` is a reference to a byte position in the input string.
     // Anytime this is incremented, we have to be aware of the number of
     // bytes the character is.
-    let mut sp = 0;
+    let mut sp = start;
     let mut found = None;
 
-    let mut clist: ~[Thread] = vec::with_capacity(self.inst.len());
-    let mut nlist: ~[Thread] = vec::with_capacity(self.inst.len());
-    
-    // To start from an index other than than the first character,
-    // need to compute the number of bytes from the beginning to
-    // wherever we want to start
-    for i in range(0, input.char_len()) {
-      let c = input.char_at(sp);
-
-      // Wait until the start_index is hit
-      if (i == start_index) {
+    // Reuse the thread lists from the last run. Anything left
+    // over is stale.
+    let mut borrow = self.threads.borrow_mut();
+    let threads = borrow.get();
+
+    let clist = &mut threads.clist;
+    let nlist = &mut threads.nlist;
+    let spare = &mut threads.spare;
+
+    clist.clear();
+    nlist.clear();
+
+    // The main loop.
+    //
+    // For each character in the input, loop through threads
+    // that represent different traversal paths through the
+    // list of instructions. The only time new threads are
+    // created, are when `InstSplit` instructions occur, or
+    // when a new thread is seeded.
+    loop {
+      if (found.is_none() && (!anchored || sp == start)) {
+        self.addThread(Thread::new(0, sp, self.ncaps), clist, spare, input, sp);
+      }
+
+      if (clist.threads.len() == 0 && (anchored || found.is_some())) {
         break;
       }
 
-      // Some chars are different byte lengths, so
-      // we can't just inc by 1
-      sp += c.len_utf8_bytes();
+      let (c, next) = if (sp < end) {
+        let c = input.char_at(sp);
+        (Some(c), sp + c.len_utf8_bytes())
+      } else {
+        (None, sp)
+      };
+
+      for i in range(0, clist.threads.len()) {
+        let pc = clist.threads[i].pc;
+
+        let matches = match self.inst[pc] {
+          InstLiteral(m) => c == Some(m),
+          InstRange(s, e) => {
+            match c {
+              Some(c) => c >= s && c <= e,
+              None => false
+            }
+          }
+          InstMatch => {
+            // Threads after this one have a lower priority,
+            // so they can be cut off.
+            found = Some(clist.threads[i].clone());
+            break;
+          }
+          _ => false
+        };
+
+        if (matches) {
+          let mut t = clist.threads[i].clone();
+
+          t.pc = pc + 1;
+          t.end = next;
+
+          self.addThread(t, nlist, spare, input, next);
+        }
+      }
+
+      if (sp >= end) {
+        break;
+      }
+
+      sp = next;
+
+      swap(clist, nlist);
+      nlist.clear();
     }
 
-    self.addThread(Thread::new(0, sp), &mut clist);
+    found
+  }
+}
 
-    // The main loop.
-    //
-    // For each character in the input, loop through threads (starting with
-    // one dummy thread) that represent different traversal
-    // paths through the list of instructions. The only
-    // time new threads are created, are when `InstSplit` instructions occur.
-    for i in range(start_index, input.char_len()) {
-      let c = input.char_at(sp);
+impl<'a> ExecStrategy for PikeVM<'a> {
+  fn run(&self, input: &str, start: uint) -> Option<Thread> {
+    self.execute(input, start, input.len(), true)
+  }
+  fn search(&self, input: &str, start: uint) -> Option<Thread> {
+    self.execute(input, start, input.len(), false)
+  }
+}
 
-      sp += c.len_utf8_bytes();
+/// Checks if `input` starts with the text of a group, and returns
+/// the number of bytes matched.
+///
+/// When ignoring case, characters are compared with simple case 
+/// folding, so the number of bytes can differ from the group.
+fn match_backref(group: &str, input: &str, icase: bool) -> Option<uint> {
+  if (!icase) {
+    return if (input.starts_with(group)) { 
+      Some(group.len()) 
+    } else { 
+      None 
+    }
+  }
 
-      //println(format!("-- Execution ({:c}|{:u}) --", c, sp));
+  let mut sp = 0;
 
-      while (clist.len() > 0) {
-        let mut t = clist.shift();;
+  for c in group.chars() {
+    if (sp >= input.len()) {
+      return None
+    }
+    let d = input.char_at(sp);
+    if (!fold_eq(c, d)) {
+      return None
+    }
+    sp += d.len_utf8_bytes();
+  }
 
-        match self.inst[t.pc] {
-          InstLiteral(m) => {
-            if (c == m && i != input.char_len()) {
-              t.pc = t.pc + 1;
-              t.end = sp;
+  Some(sp)
+}
+
+/// A unit of work for the `Backtrack` engine.
+enum Job {
+  // Try to match from an instruction at a position in the input
+  JobExplore(uint, uint),
+  // Undo a change to a capture when backtracking past it
+  JobRestore(uint, Option<CapturingGroup>)
+}
+
+/// Backtracking implementation
+///
+/// Supports everything, including Backreferences and atomic
+/// groups, but can take exponential time on some patterns. 
+/// Used when the `PikeVM` can't handle a pattern.
+pub struct Backtrack<'a> {
+  priv inst:  &'a [Instruction],
+  priv ncaps: uint,
+  // Set if what matches depends on the captures
+  priv backrefs: bool
+}
+
+impl<'a> Backtrack<'a> {
+  pub fn new(inst: &'a [Instruction], ncaps: uint) -> Backtrack<'a> {
+    Backtrack {
+      inst: inst,
+      ncaps: ncaps,
+      backrefs: has_backrefs(inst)
+    }
+  }
+}
+
+/// Checks if a program has backreferences anywhere, including 
+/// inside lookarounds and atomic groups.
+fn has_backrefs(prog: &[Instruction]) -> bool {
+  prog.iter().any(|inst| {
+    match *inst {
+      InstBackref(..) => true,
+      InstLookahead(ref sub, _) |
+      InstLookbehind(ref sub, _, _) |
+      InstAtomic(ref sub) => has_backrefs(*sub),
+      _ => false
+    }
+  })
+}
+
+/// Checks a lookaround assertion for the backtracking engines. 
+/// Any captures it changes are saved on the job stack, so they 
+/// are undone on backtracking.
+fn backtrack_lookaround(t: &mut Thread, jobs: &mut ~[Job], prog: &[Instruction],
+                        ncaps: uint, input: &str, sp: uint, width: Option<uint>,
+                        negated: bool, spare: &mut ~[Threads]) -> bool {
+  let saved = t.captures.clone();
+
+  if (!check_lookaround(t, prog, ncaps, input, sp, width, negated, spare)) {
+    return false
+  }
+
+  for (i, cap) in saved.move_iter().enumerate() {
+    jobs.push(JobRestore(i, cap));
+  }
+
+  true
+}
+
+impl<'a> ExecStrategy for Backtrack<'a> {
+  fn run(&self, input: &str, start: uint) -> Option<Thread> {
+    self.execute(input, Thread::new(0, start, self.ncaps))
+  }
+}
+
+impl<'a> Backtrack<'a> {
+  /// Runs the program from the position of a thread, starting 
+  /// with the captures it already has.
+  fn execute(&self, input: &str, mut t: Thread) -> Option<Thread> {
+    let len = input.len();
+    let start = t.start;
+
+    // Alternatives that still need to be tried, most
+    // preferred on top
+    let mut jobs = ~[JobExplore(0, start)];
+
+    // Each split is only explored once at a given position. This
+    // keeps loops that can match the empty string, like `(a*)*`,
+    // from running forever. With backreferences, the same split
+    // can still match with different captures, so they're part
+    // of what's been visited.
+    let mut visited = HashSet::new();
 
-              self.addThread(t, &mut nlist);
+    // Thread lists for lookarounds, kept for the whole run
+    let mut spare = ~[];
+
+    loop {
+      let (mut pc, mut sp) = match jobs.pop_opt() {
+        Some(JobExplore(pc, sp)) => (pc, sp),
+        Some(JobRestore(num, cap)) => {
+          t.captures[num] = cap;
+          continue;
+        }
+        None => return None
+      };
+
+      // Follow this path until it either matches, or fails
+      loop {
+        match self.inst[pc] {
+          InstLiteral(m) => {
+            if (sp < len && input.char_at(sp) == m) {
+              sp += m.len_utf8_bytes();
+              pc += 1;
+            } else {
+              break;
             }
           }
-          InstRange(start, end) => {
-            if (c >= start && c <= end && i != input.char_len()) {
-              t.pc = t.pc + 1;
-              t.end = sp;
-
-              self.addThread(t, &mut nlist);
+          InstRange(s, e) => {
+            if (sp >= len) {
+              break;
+            }
+            let c = input.char_at(sp);
+            if (c >= s && c <= e) {
+              sp += c.len_utf8_bytes();
+              pc += 1;
+            } else {
+              break;
+            }
+          }
+          InstMatch => {
+            t.end = sp;
+            return Some(t)
+          }
+          InstJump(addr) => {
+            pc = addr;
+          }
+          InstSplit(laddr, raddr) => {
+            let caps: ~[Option<(uint, uint)>] = if (self.backrefs) {
+              t.captures.iter().map(|cap| {
+                cap.as_ref().map(|cap| (cap.start, cap.end))
+              }).collect()
+            } else {
+              ~[]
+            };
+            if (!visited.insert((pc, sp, caps))) {
+              break;
+            }
+            jobs.push(JobExplore(raddr, sp));
+            pc = laddr;
+          }
+          InstCaptureStart(num, _) => {
+            jobs.push(JobRestore(num, t.captures[num].clone()));
+            t.captures[num] = Some(CapturingGroup::new(sp, sp, num));
+            pc += 1;
+          }
+          InstCaptureEnd(num) => {
+            jobs.push(JobRestore(num, t.captures[num].clone()));
+            match t.captures[num] {
+              Some(ref mut cap) => {
+                cap.end = sp;
+              }
+              None => unreachable!()
+            }
+            pc += 1;
+          }
+          InstBackref(num, icase) => {
+            // A reference to a group that didn't 
+            // participate never matches
+            let (s, e) = match t.captures[num] {
+              Some(ref cap) => (cap.start, cap.end),
+              None => break
+            };
+            match match_backref(input.slice(s, e), input.slice_from(sp), icase) {
+              Some(n) => {
+                sp += n;
+                pc += 1;
+              }
+              None => break
             }
           }
           InstAssertStart => {
-            if (i == 0) {
-              t.pc = t.pc + 1;
-
-              self.addThread(t, &mut clist);
+            if (sp != 0) {
+              break;
             }
+            pc += 1;
           }
           InstAssertEnd => {
-            // Account for the extra character added onto each
-            // input string
-            if (i == input.char_len() - 1) {
-              t.pc = t.pc + 1;
-
-              self.addThread(t, &mut clist);
+            if (sp != len) {
+              break;
+            }
+            pc += 1;
+          }
+          InstAssertLineStart(flags) => {
+            if (!is_line_start(input, sp, flags)) {
+              break;
             }
+            pc += 1;
           }
-          InstWordBoundary => {
-            if (i == 0 ||
-                i == input.char_len()) {
-              continue;
+          InstAssertLineEnd(flags) => {
+            if (!is_line_end(input, sp, flags)) {
+              break;
             }
-            if (i == start_index &&
-                !input.char_at_reverse(t.end).is_alphanumeric()) {
-              continue;
+            pc += 1;
+          }
+          InstWordBoundary(ascii) => {
+            if (!is_word_boundary(input, sp, ascii)) {
+              break;
             }
-            if (!c.is_alphanumeric()) {
-              continue;
+            pc += 1;
+          }
+          InstNonWordBoundary(ascii) => {
+            if (is_word_boundary(input, sp, ascii)) {
+              break;
             }
-            t.pc = t.pc + 1;
-            
-            self.addThread(t, &mut clist);
+            pc += 1;
           }
-          InstNonWordBoundary => {
-            if (i == start_index &&
-                i != 0 &&
-                input.char_at_reverse(t.end).is_alphanumeric()) {
-              continue;
+          InstLookahead(ref sub, negated) => {
+            if (!backtrack_lookaround(&mut t, &mut jobs, *sub, self.ncaps,
+                                      input, sp, None, negated, &mut spare)) {
+              break;
             }
-            if (i != input.char_len() &&
-                i != 0 &&
-                i != start_index &&
-                c.is_alphanumeric()) {
-              continue;
+            pc += 1;
+          }
+          InstLookbehind(ref sub, width, negated) => {
+            if (!backtrack_lookaround(&mut t, &mut jobs, *sub, self.ncaps,
+                                      input, sp, Some(width), negated, &mut spare)) {
+              break;
             }
-            t.pc = t.pc + 1;
+            pc += 1;
+          }
+          InstAtomic(ref sub) => {
+            // Only the first way the group can match is tried, 
+            // so it's never backtracked into
+            let sub = Backtrack::new(*sub, self.ncaps);
+            let mut from = Thread::new(0, sp, self.ncaps);
+
+            from.captures = t.captures.clone();
 
-            self.addThread(t, &mut clist);
+            match sub.execute(input, from) {
+              Some(found) => {
+                for (i, cap) in t.captures.iter().enumerate() {
+                  jobs.push(JobRestore(i, cap.clone()));
+                }
+                t.captures = found.captures;
+                sp = found.end;
+                pc += 1;
+              }
+              None => break
+            }
           }
-          InstMatch => {
-            found = Some(t.clone());
-            break;
+          InstNoop => {
+            pc += 1;
           }
-          _ => unreachable!()
         }
       }
+    }
+  }
+}
 
-      swap(&mut clist, &mut nlist);
-      nlist.clear();
+/// The most bits the `BoundedBacktrack` will use to keep track 
+/// of where it's been.
+static MAX_VISITED_BITS: uint = 256 * 1024;
+
+/// Bounded backtracking implementation
+///
+/// Backtracks like `Backtrack`, keeping captures in place instead 
+/// of copying them for each thread, but never tries an instruction
+/// at the same position twice. A bitset with a bit for every 
+/// instruction and position keeps track, so the running time is 
+/// linear. Since the bitset grows with the input, this is only 
+/// used on short inputs.
+///
+/// Supports the same patterns as the `PikeVM`.
+pub struct BoundedBacktrack<'a> {
+  priv inst:  &'a [Instruction],
+  priv ncaps: uint
+}
+
+impl<'a> BoundedBacktrack<'a> {
+  pub fn new(inst: &'a [Instruction], ncaps: uint) -> BoundedBacktrack<'a> {
+    BoundedBacktrack {
+      inst: inst,
+      ncaps: ncaps
     }
+  }
+  /// Checks if the bitset for a program and an input of `len` 
+  /// bytes is small enough.
+  pub fn fits(prog: &[Instruction], len: uint) -> bool {
+    prog.len() * (len + 1) <= MAX_VISITED_BITS
+  }
+}
 
-    // Adjust for captures that were
-    // seen while parsing to get the proper
-    // groups length in the `Match`.
-    match found {
-      Some(ref mut ma) => {
-        if (ma.captures.len() < self.ncaps) {
-          for _ in range(ma.captures.len(), self.ncaps) {
-            ma.captures.push(None);
+impl<'a> BoundedBacktrack<'a> {
+  /// Tries each position in turn, from `start`. Positions that 
+  /// failed from an earlier start are still marked as visited, 
+  /// since they'll fail again.
+  fn execute(&self, input: &str, start: uint, anchored: bool) -> Option<Thread> {
+    let width = input.len() - start + 1;
+    let mut visited = vec::from_elem((self.inst.len() * width + 31) / 32, 0u32);
+    let mut t = Thread::new(0, start, self.ncaps);
+    let mut spare = ~[];
+    let mut sp = start;
+
+    loop {
+      t.start = sp;
+
+      match self.explore(input, &mut t, &mut visited, start, width, &mut spare) {
+        Some(end) => {
+          t.end = end;
+          return Some(t)
+        }
+        None => ()
+      }
+
+      if (anchored || sp >= input.len()) {
+        return None
+      }
+      sp += input.char_at(sp).len_utf8_bytes();
+    }
+  }
+  /// Follows the program from `t.start`, most preferred path first. 
+  /// Returns the end of the match, if there is one. Otherwise, 
+  /// all of the captures are back the way they were.
+  ///
+  /// # Arguments
+  ///
+  /// * input - The input string
+  /// * t - The thread to fill in the captures of
+  /// * visited - The instructions and positions already tried
+  /// * base - The position the bitset starts at
+  /// * width - The number of positions in the bitset
+  /// * spare - Thread lists for lookarounds
+  fn explore(&self, input: &str, t: &mut Thread, visited: &mut ~[u32],
+             base: uint, width: uint, spare: &mut ~[Threads]) -> Option<uint> {
+    let len = input.len();
+    let mut jobs = ~[JobExplore(0, t.start)];
+
+    loop {
+      let (mut pc, mut sp) = match jobs.pop_opt() {
+        Some(JobExplore(pc, sp)) => (pc, sp),
+        Some(JobRestore(num, cap)) => {
+          t.captures[num] = cap;
+          continue;
+        }
+        None => return None
+      };
+
+      // Follow this path until it either matches, fails, or
+      // gets somewhere that's been tried before
+      loop {
+        let bit = pc * width + sp - base;
+
+        if (visited[bit / 32] & (1 << (bit % 32)) != 0) {
+          break;
+        }
+        visited[bit / 32] |= 1 << (bit % 32);
+
+        match self.inst[pc] {
+          InstLiteral(m) => {
+            if (sp < len && input.char_at(sp) == m) {
+              sp += m.len_utf8_bytes();
+              pc += 1;
+            } else {
+              break;
+            }
+          }
+          InstRange(s, e) => {
+            if (sp >= len) {
+              break;
+            }
+            let c = input.char_at(sp);
+            if (c >= s && c <= e) {
+              sp += c.len_utf8_bytes();
+              pc += 1;
+            } else {
+              break;
+            }
+          }
+          InstMatch => {
+            return Some(sp)
+          }
+          InstJump(addr) => {
+            pc = addr;
+          }
+          InstSplit(laddr, raddr) => {
+            jobs.push(JobExplore(raddr, sp));
+            pc = laddr;
+          }
+          InstCaptureStart(num, _) => {
+            jobs.push(JobRestore(num, t.captures[num].clone()));
+            t.captures[num] = Some(CapturingGroup::new(sp, sp, num));
+            pc += 1;
+          }
+          InstCaptureEnd(num) => {
+            jobs.push(JobRestore(num, t.captures[num].clone()));
+            match t.captures[num] {
+              Some(ref mut cap) => {
+                cap.end = sp;
+              }
+              None => unreachable!()
+            }
+            pc += 1;
+          }
+          InstAssertStart => {
+            if (sp != 0) {
+              break;
+            }
+            pc += 1;
+          }
+          InstAssertEnd => {
+            if (sp != len) {
+              break;
+            }
+            pc += 1;
           }
+          InstAssertLineStart(flags) => {
+            if (!is_line_start(input, sp, flags)) {
+              break;
+            }
+            pc += 1;
+          }
+          InstAssertLineEnd(flags) => {
+            if (!is_line_end(input, sp, flags)) {
+              break;
+            }
+            pc += 1;
+          }
+          InstWordBoundary(ascii) => {
+            if (!is_word_boundary(input, sp, ascii)) {
+              break;
+            }
+            pc += 1;
+          }
+          InstNonWordBoundary(ascii) => {
+            if (is_word_boundary(input, sp, ascii)) {
+              break;
+            }
+            pc += 1;
+          }
+          InstLookahead(ref sub, negated) => {
+            if (!backtrack_lookaround(t, &mut jobs, *sub, self.ncaps,
+                                      input, sp, None, negated, spare)) {
+              break;
+            }
+            pc += 1;
+          }
+          InstLookbehind(ref sub, w, negated) => {
+            if (!backtrack_lookaround(t, &mut jobs, *sub, self.ncaps,
+                                      input, sp, Some(w), negated, spare)) {
+              break;
+            }
+            pc += 1;
+          }
+          InstNoop => {
+            pc += 1;
+          }
+          // Whether these match depends on more than the 
+          // position, so they need the `Backtrack`
+          InstBackref(..) | InstAtomic(..) => unreachable!()
         }
       }
-      _ => { }
     }
+  }
+}
 
-    found
+impl<'a> ExecStrategy for BoundedBacktrack<'a> {
+  fn run(&self, input: &str, start: uint) -> Option<Thread> {
+    self.execute(input, start, true)
+  }
+  fn search(&self, input: &str, start: uint) -> Option<Thread> {
+    self.execute(input, start, false)
   }
 }
@@ -0,0 +1,36 @@
+// Flags that change how a pattern is parsed and 
+// matched. These can be combined with `|` and passed 
+// to `Regexp::with_flags`.
+
+/// Case-insensitive matching, using Unicode simple
+/// case folding (`(?i)`)
+pub static IGNORECASE: uint = 1 << 0;
+
+/// `^` and `$` match at the beginning and end of 
+/// lines (`(?m)`)
+pub static MULTILINE: uint = 1 << 1;
+
+/// `.` matches any character, including newlines (`(?s)`)
+pub static DOTALL: uint = 1 << 2;
+
+/// Whitespace and `#` comments in the pattern are 
+/// ignored (`(?x)`)
+pub static VERBOSE: uint = 1 << 3;
+
+/// `\r\n` and `\r` also end lines, so `.` doesn't 
+/// match `\r`. In multiline mode, `^` and `$` never 
+/// match between a `\r` and a `\n`.
+pub static CRLF: uint = 1 << 4;
+
+/// Any Unicode line terminator (`\n`, `\v`, `\f`, `\r`,
+/// `\x85`, `\u2028`, `\u2029` and `\r\n`) ends a line,
+/// for both `.` and multiline mode.
+pub static NEWLINE_ANY: uint = 1 << 5;
+
+/// `\d`, `\w`, `\s` and word boundaries only consider
+/// ASCII characters (`(?a)`)
+pub static ASCII: uint = 1 << 6;
+
+/// Escapes of unknown ASCII letters (like `\q`) are 
+/// errors, instead of matching the letter
+pub static STRICT: uint = 1 << 7;
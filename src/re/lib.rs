@@ -9,16 +9,22 @@
 
 extern mod extra;
 
-pub use regexp::UncompiledRegexp;
+pub use regexp::{Regexp, UncompiledRegexp};
+pub use exec::{ExecStrategy, Thread};
 
 mod test;
 mod exec;
-mod error;
+mod dfa;
+mod onepass;
 mod parse;
 mod state;
 mod compile;
 mod charclass;
+mod template;
+mod unicode;
 
+pub mod error;
+pub mod flags;
 pub mod result;
 pub mod regexp;
 
@@ -0,0 +1,448 @@
+use std::vec;
+use compile::Instruction;
+use compile::{InstLiteral, InstRange, InstMatch, InstJump, InstSplit,
+  InstCaptureStart, InstCaptureEnd, InstAssertStart, InstAssertEnd,
+  InstAssertLineStart, InstAssertLineEnd, InstWordBoundary,
+  InstNonWordBoundary, InstNoop};
+use exec::{ExecStrategy, Thread, assertion_holds};
+use result::CapturingGroup;
+
+/// Programs with more nodes than this aren't one-pass, so that
+/// checking a long pattern doesn't slow down compiling it.
+pub static MAX_NODES: uint = 1000;
+
+/// A way out of a node of a one-pass program: the characters that
+/// can be consumed (none for a match), along with the captures and
+/// assertions on the way there. All the ranges of a character class
+/// share one edge.
+struct Edge {
+  ranges: ~[(char, char)],
+  path: ~[uint],
+  // The node to go to after consuming a character
+  next: uint
+}
+
+impl Edge {
+  /// Checks if the edge consumes a character, with a binary
+  /// search over its sorted ranges.
+  fn consumes(&self, c: char) -> bool {
+    let mut lo = 0;
+    let mut hi = self.ranges.len();
+
+    while (lo < hi) {
+      let mid = (lo + hi) / 2;
+      let (s, e) = self.ranges[mid];
+      if (c < s) {
+        hi = mid;
+      } else if (c > e) {
+        lo = mid + 1;
+      } else {
+        return true
+      }
+    }
+
+    false
+  }
+}
+
+/// Follows jumps from `pc`, so that instructions that lead to the
+/// same place share a node.
+fn skip_jumps(prog: &[Instruction], mut pc: uint) -> uint {
+  for _ in range(0, prog.len()) {
+    match prog[pc] {
+      InstJump(addr) => pc = addr,
+      InstNoop => pc += 1,
+      _ => break
+    }
+  }
+  pc
+}
+
+/// Follows the instructions that don't consume any input from `pc`,
+/// and adds an `Edge` for each instruction it ends up at, in
+/// priority order.
+///
+/// Returns false if the program isn't one-pass from here, which is
+/// when an instruction can be reached in two ways, or there's an
+/// instruction that depends on more than the position.
+///
+/// # Arguments
+///
+/// * prog - The compiled program
+/// * pc - The instruction to start from
+/// * path - The captures and assertions passed so far
+/// * seen - The instructions already reached, marked with `mark`
+/// * mark - The mark for this node, so `seen` doesn't have to be
+///          cleared between nodes
+/// * edges - The edges found so far
+fn follow(prog: &[Instruction], pc: uint, path: &mut ~[uint],
+          seen: &mut ~[uint], mark: uint, edges: &mut ~[Edge]) -> bool {
+  if (seen[pc] == mark) {
+    return false
+  }
+  seen[pc] = mark;
+
+  match prog[pc] {
+    InstJump(addr) => follow(prog, addr, path, seen, mark, edges),
+    InstSplit(laddr, raddr) => {
+      follow(prog, laddr, path, seen, mark, edges) &&
+        follow(prog, raddr, path, seen, mark, edges)
+    }
+    InstCaptureStart(..) | InstCaptureEnd(..) | InstAssertStart |
+    InstAssertEnd | InstAssertLineStart(..) | InstAssertLineEnd(..) |
+    InstWordBoundary(..) | InstNonWordBoundary(..) => {
+      path.push(pc);
+      let ok = follow(prog, pc + 1, path, seen, mark, edges);
+      path.pop();
+      ok
+    }
+    InstNoop => follow(prog, pc + 1, path, seen, mark, edges),
+    InstLiteral(..) | InstRange(..) => {
+      let range = consumed(&prog[pc]).unwrap();
+      let next = skip_jumps(prog, pc + 1);
+      let n = edges.len();
+
+      // Ranges of the same class come one after another, and 
+      // go to the same place
+      if (n > 0 && edges[n - 1].ranges.len() > 0 && edges[n - 1].next == next &&
+          edges[n - 1].path == *path) {
+        edges[n - 1].ranges.push(range);
+      } else {
+        edges.push(Edge { ranges: ~[range], path: path.clone(), next: next });
+      }
+      true
+    }
+    InstMatch => {
+      edges.push(Edge { ranges: ~[], path: path.clone(), next: pc });
+      true
+    }
+    _ => false
+  }
+}
+
+/// Finds the characters an instruction consumes.
+fn consumed(inst: &Instruction) -> Option<(char, char)> {
+  match *inst {
+    InstLiteral(c) => Some((c, c)),
+    InstRange(s, e) => Some((s, e)),
+    _ => None
+  }
+}
+
+/// A program that has been checked to be one-pass: at every
+/// position, the next character decides which way to go, so only
+/// one thread is ever needed.
+pub struct OnePassProg {
+  // The edges out of each instruction a thread can be at after
+  // consuming a character, and out of the start. Only the
+  // nodes that can be reached are filled in.
+  priv nodes: ~[Option<~[Edge]>],
+  // Set if every match has to start at the beginning of the input
+  priv anchored: bool
+}
+
+impl OnePassProg {
+  /// Checks if a program is one-pass. Returns None if it isn't.
+  ///
+  /// It is when, from the start and after every character, the
+  /// instructions reachable without consuming input can each only
+  /// be reached one way, and consume different characters.
+  ///
+  /// # Arguments
+  ///
+  /// * prog - The compiled program
+  pub fn new(prog: &[Instruction]) -> Option<OnePassProg> {
+    let mut nodes = vec::from_fn(prog.len(), |_| None);
+    let mut nnodes = 0;
+    let mut seen = vec::from_elem(prog.len(), 0u);
+    let mut work = ~[0u];
+
+    loop {
+      let pc = match work.pop_opt() {
+        Some(pc) => pc,
+        None => break
+      };
+
+      if (nodes[pc].is_some()) {
+        continue;
+      }
+
+      nnodes += 1;
+      if (nnodes > MAX_NODES) {
+        return None
+      }
+
+      let mut edges = ~[];
+
+      if (!follow(prog, pc, &mut ~[], &mut seen, nnodes, &mut edges)) {
+        return None
+      }
+
+      // Once sorted, a range overlaps an earlier one if it starts
+      // before the furthest end so far. That's only a problem if
+      // they lead different ways.
+      let mut ranges = ~[];
+
+      for (i, edge) in edges.mut_iter().enumerate() {
+        edge.ranges.sort();
+        for &(s, e) in edge.ranges.iter() {
+          ranges.push((s, e, i));
+        }
+      }
+
+      ranges.sort();
+
+      let mut furthest: Option<(char, uint)> = None;
+
+      for &(s, e, i) in ranges.iter() {
+        match furthest {
+          Some((end, j)) if s <= end && i != j => return None,
+          Some((end, _)) if e <= end => (),
+          _ => furthest = Some((e, i))
+        }
+      }
+
+      for edge in edges.iter() {
+        if (edge.ranges.len() > 0) {
+          work.push(edge.next);
+        }
+      }
+
+      nodes[pc] = Some(edges);
+    }
+
+    let anchored = match nodes[0] {
+      Some(ref edges) => edges.iter().all(|edge| {
+        edge.path.iter().any(|&pc| {
+          match prog[pc] {
+            InstAssertStart => true,
+            _ => false
+          }
+        })
+      }),
+      None => false
+    };
+
+    Some(OnePassProg {
+      nodes: nodes,
+      anchored: anchored
+    })
+  }
+}
+
+impl OnePassProg {
+  /// Checks if matches can only start at the beginning of the input.
+  pub fn anchored(&self) -> bool {
+    self.anchored
+  }
+}
+
+/// One-pass NFA implementation
+///
+/// Runs a program `OnePassProg` has checked with a single thread,
+/// filling in the captures in place. When a match is found but a
+/// longer one is still possible, the changes made after it are
+/// logged, so they can be undone if the longer match fails.
+///
+/// Only anchored runs are done in one pass. Searching tries
+/// each position in turn.
+pub struct OnePass<'a> {
+  priv prog:  &'a OnePassProg,
+  priv inst:  &'a [Instruction],
+  priv ncaps: uint
+}
+
+impl<'a> OnePass<'a> {
+  pub fn new(prog: &'a OnePassProg, inst: &'a [Instruction],
+             ncaps: uint) -> OnePass<'a> {
+    OnePass {
+      prog: prog,
+      inst: inst,
+      ncaps: ncaps
+    }
+  }
+}
+
+impl<'a> OnePass<'a> {
+  /// Sets the captures on the path of an edge, logging the
+  /// old values.
+  fn apply(&self, path: &[uint], captures: &mut ~[Option<CapturingGroup>],
+           sp: uint, log: &mut ~[(uint, Option<CapturingGroup>)]) {
+    for &pc in path.iter() {
+      match self.inst[pc] {
+        InstCaptureStart(num, _) => {
+          log.push((num, captures[num].clone()));
+          captures[num] = Some(CapturingGroup::new(sp, sp, num));
+        }
+        InstCaptureEnd(num) => {
+          log.push((num, captures[num].clone()));
+          match captures[num] {
+            Some(ref mut cap) => {
+              cap.end = sp;
+            }
+            None => unreachable!()
+          }
+        }
+        _ => ()
+      }
+    }
+  }
+}
+
+impl<'a> ExecStrategy for OnePass<'a> {
+  fn run(&self, input: &str, start: uint) -> Option<Thread> {
+    let len = input.len();
+    let mut t = Thread {
+      pc: 0,
+      start: start,
+      end: start,
+      captures: vec::from_elem(self.ncaps, None)
+    };
+    let mut log = ~[];
+    // The position of the last match, and the path to it
+    let mut found: Option<(uint, &[uint])> = None;
+    let mut sp = start;
+
+    loop {
+      let edges = match self.prog.nodes[t.pc] {
+        Some(ref edges) => edges.as_slice(),
+        None => unreachable!()
+      };
+      let c = if (sp < len) { Some(input.char_at(sp)) } else { None };
+      let mut matched = None;
+      let mut next = None;
+
+      for (i, edge) in edges.iter().enumerate() {
+        if (!edge.path.iter().all(|&pc| assertion_holds(&self.inst[pc], input, sp))) {
+          continue;
+        }
+
+        match c {
+          _ if edge.ranges.len() == 0 => matched = Some(i),
+          Some(c) if edge.consumes(c) => next = Some(i),
+          _ => ()
+        }
+      }
+
+      match (matched, next) {
+        // A better match might come after this one
+        (Some(m), Some(n)) if n < m => {
+          found = Some((sp, edges[m].path.as_slice()));
+          log.clear();
+        }
+        // Nothing can beat this one
+        (Some(m), _) => {
+          self.apply(edges[m].path, &mut t.captures, sp, &mut log);
+          t.end = sp;
+          return Some(t)
+        }
+        _ => ()
+      }
+
+      match next {
+        Some(n) => {
+          self.apply(edges[n].path, &mut t.captures, sp, &mut log);
+          t.pc = edges[n].next;
+          sp += c.unwrap().len_utf8_bytes();
+        }
+        None => break
+      }
+
+      if (found.is_none()) {
+        log.clear();
+      }
+    }
+
+    match found {
+      Some((end, path)) => {
+        // Go back to the way things were at the last match
+        loop {
+          match log.pop_opt() {
+            Some((num, cap)) => t.captures[num] = cap,
+            None => break
+          }
+        }
+        self.apply(path, &mut t.captures, end, &mut log);
+        t.end = end;
+        Some(t)
+      }
+      None => None
+    }
+  }
+}
+
+#[cfg(test)]
+mod onepass_tests {
+  use std::cell::RefCell;
+  use onepass::*;
+  use exec::{ExecStrategy, PikeVM, Threads, Thread};
+  use compile::{compile_recursive, count_captures};
+  use parse::parse;
+
+  macro_rules! test_onepass(
+    ($re: expr, $input: expr) => (
+      {
+        let inst = compile_recursive(&parse($re, 0).unwrap());
+        let ncaps = count_captures(inst);
+        let prog = OnePassProg::new(inst).unwrap();
+        let threads = RefCell::new(Threads::new(inst.len()));
+
+        let onepass = OnePass::new(&prog, inst, ncaps);
+        let pike = PikeVM::new(inst, ncaps, &threads);
+
+        let found = |t: Thread| {
+          let caps: ~[Option<(uint, uint)>] = t.captures.iter().map(|c| {
+            c.as_ref().map(|c| (c.start, c.end))
+          }).collect();
+          (t.start, t.end, caps)
+        };
+        assert_eq!(onepass.run($input, 0).map(|t| found(t)),
+                   pike.run($input, 0).map(|t| found(t)));
+      }
+    );
+  )
+
+  fn is_onepass(re: &str) -> bool {
+    OnePassProg::new(compile_recursive(&parse(re, 0).unwrap())).is_some()
+  }
+
+  #[test]
+  fn onepass_analysis_test() {
+    assert!(is_onepass("^(\\d{4})-(\\d{2})$"));
+    assert!(is_onepass("(\\w+)=(\\d*)"));
+    assert!(is_onepass("[a-z]+|[0-9]+"));
+    assert!(is_onepass("\\w+"));
+    assert!(is_onepass("([a-c]|[b-d])x"));
+    assert!(!is_onepass("([a-c]|[b-d]y)x"));
+    // Too many nodes to check
+    assert!(is_onepass("a{500}"));
+    assert!(!is_onepass("a{2000}"));
+    assert!(is_onepass("(a|b)c"));
+    assert!(!is_onepass("(a|ab)c"));
+    assert!(!is_onepass("a*a"));
+    assert!(!is_onepass("(a)\\1"));
+  }
+
+  #[test]
+  fn onepass_anchored_test() {
+    let inst = compile_recursive(&parse("^ab", 0).unwrap());
+    assert!(OnePassProg::new(inst).unwrap().anchored());
+    let inst = compile_recursive(&parse("^ab|c", 0).unwrap());
+    assert!(!OnePassProg::new(inst).unwrap().anchored());
+  }
+
+  #[test]
+  fn onepass_matches_pike_test() {
+    test_onepass!("^(\\d{4})-(\\d{2})$", "2014-01");
+    test_onepass!("^(\\d{4})-(\\d{2})$", "2014-011");
+    test_onepass!("(\\w+)=(\\d*)", "key=123;");
+    test_onepass!("(\\w+)=(\\d*)", "k\u00e9y=\u0663");
+    test_onepass!("(?a)(\\w+)=(\\d*)", "key=");
+    test_onepass!("([a-c]|[b-d])x", "cx");
+    test_onepass!("(?:a|(?:)|b)", "b");
+    test_onepass!("(a+)(b)?", "aaac");
+    test_onepass!("(a+)(b)?", "aaab");
+    test_onepass!("(a*?)b", "aab");
+    test_onepass!("(x)(y)?z?", "xyw");
+  }
+}
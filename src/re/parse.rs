@@ -1,30 +1,55 @@
 use state::State;
-use std::char::MAX;
-use error::ParseError::*;
-use charclass::{Range, new_charclass, new_negated_charclass, AlphaClass, 
-  NumericClass, WhitespaceClass, NegatedAlphaClass, NegatedNumericClass,
-  NegatedWhitespaceClass};
-
-#[deriving(ToStr)]
+use std::char;
+use std::util::replace;
+use error::ParseCodes::*;
+use error::ParseError;
+use flags::{IGNORECASE, MULTILINE, DOTALL, VERBOSE, CRLF, NEWLINE_ANY, ASCII, 
+  STRICT};
+use charclass::{Range, new_charclass, new_negated_charclass, fold_ranges, 
+  new_intersected_charclass, new_subtracted_charclass, class_ranges, 
+  property_table, posix_class, DigitClass, 
+  WordClass, SpaceClass, NegatedDigitClass, NegatedWordClass, NegatedSpaceClass, 
+  AsciiDigitClass, AsciiWordClass, AsciiSpaceClass, NegatedAsciiDigitClass, 
+  NegatedAsciiWordClass, NegatedAsciiSpaceClass, AnyClass, NotNewlineClass, 
+  NotCRLFClass, NotLineTerminatorClass};
+
+#[deriving(Clone, ToStr)]
 pub enum QuantifierPrefix {
   Greedy,
-  NonGreedy
+  NonGreedy,
+  // Never gives back what it matched (`a*+`)
+  Possessive
+}
+
+/// The direction of a lookaround assertion. Lookbehinds
+/// carry their width, in characters.
+#[deriving(Clone, ToStr)]
+pub enum LookKind {
+  LookAhead,
+  LookBehind(uint)
 }
 
-#[deriving(ToStr)]
+/// Expressions whose meaning depends on the flags (see flags.rs)
+/// carry the flags that were in effect where they were parsed.
+#[deriving(Clone, ToStr)]
 pub enum Expr {
   Empty,
-  Literal(char),
+  Literal(char, uint),
   CharClass(~[Range]),
   CharClassStatic(&'static [Range]),
   Alternation(~Expr, ~Expr),
   Concatenation(~Expr, ~Expr),
   Repetition(~Expr, uint, Option<uint>, QuantifierPrefix),
   Capture(~Expr, uint, Option<~str>),
-  AssertWordBoundary,
-  AssertNonWordBoundary,
+  Backref(uint, uint),
+  Lookaround(~Expr, LookKind, bool),
+  Atomic(~Expr),
+  AssertWordBoundary(uint),
+  AssertNonWordBoundary(uint),
   AssertStart,
-  AssertEnd
+  AssertEnd,
+  AssertLineStart(uint),
+  AssertLineEnd(uint)
 }
 
 macro_rules! check_ok(
@@ -40,13 +65,48 @@ macro_rules! check_ok(
 /// The public parse function. Builds the initial `State` from the 
 /// input string, and calls an underlying parse function.
 ///
+/// Errors span from the start of the token that was being parsed
+/// to where the parser stopped.
+///
 /// # Arguments
 ///
 /// * t - The regular expression string
-pub fn parse(t: &str) -> Result<Expr, ParseCode> {
-  let mut p = State::new(t);
+/// * flags - The flags to parse with (see flags.rs)
+pub fn parse(t: &str, flags: uint) -> Result<Expr, ParseError> {
+  match parse_groups(t, flags) {
+    Ok((expr, _, _)) => Ok(expr),
+    Err(e) => Err(e)
+  }
+}
 
-  _parse_recursive(&mut p)
+/// Like `parse`, but also returns how many capturing groups the
+/// pattern has, and the names of the named ones. This counts groups
+/// that don't make it into the compiled program, like the one in 
+/// `(a){0}`.
+///
+/// # Arguments
+///
+/// * t - The regular expression string
+/// * flags - The flags to parse with (see flags.rs)
+pub fn parse_groups(t: &str, flags: uint) -> Result<(Expr, uint, ~[(~str, uint)]), 
+                                                      ParseError> {
+  let mut p = State::with_flags(t, flags);
+
+  match _parse_recursive(&mut p) {
+    Ok(expr) => Ok((expr, p.ncaptures, replace(&mut p.names, ~[]))),
+    Err(code) => {
+      let end = p.cursor();
+
+      // A missing `)` is reported where it was expected
+      let start = match code {
+        ParseExpectedClosingParen => end,
+        _ if p.token_start() < end => p.token_start(),
+        _ => end
+      };
+
+      Err(ParseError::new(code, t, start, end))
+    }
+  }
 }
 
 /// Parses an escaped value at a given state.
@@ -57,50 +117,384 @@ pub fn parse(t: &str) -> Result<Expr, ParseCode> {
 #[inline]
 fn parse_escape(p: &mut State) -> Result<Expr, ParseCode> {
   let current = p.current();
+  let ascii = p.flags & ASCII != 0;
 
-  // Replace these with static vectors
   let cc = match current {
-    Some('d') => NumericClass, 
-    Some('D') => NegatedNumericClass, 
-    Some('w') => AlphaClass, 
-    Some('W') => NegatedAlphaClass, 
-    Some('s') => WhitespaceClass, 
-    Some('S') => NegatedWhitespaceClass, 
+    Some('d') if ascii => AsciiDigitClass, 
+    Some('D') if ascii => NegatedAsciiDigitClass, 
+    Some('w') if ascii => AsciiWordClass, 
+    Some('W') if ascii => NegatedAsciiWordClass, 
+    Some('s') if ascii => AsciiSpaceClass, 
+    Some('S') if ascii => NegatedAsciiSpaceClass, 
+    Some('d') => DigitClass, 
+    Some('D') => NegatedDigitClass, 
+    Some('w') => WordClass, 
+    Some('W') => NegatedWordClass, 
+    Some('s') => SpaceClass, 
+    Some('S') => NegatedSpaceClass, 
     Some('b') => {
       p.next();
       
-      return Ok(AssertNonWordBoundary)
+      return Ok(AssertWordBoundary(p.flags))
     }
     Some('B') => {
       p.next();
 
-      return Ok(AssertWordBoundary)
+      return Ok(AssertNonWordBoundary(p.flags))
+    }
+    // The start and end of the input, even 
+    // in multiline mode
+    Some('A') => {
+      p.next();
+
+      return Ok(AssertStart)
+    }
+    Some('Z') | Some('z') => {
+      p.next();
+
+      return Ok(AssertEnd)
+    }
+    Some('p') | Some('P') => return parse_property(p),
+    Some(d) if d >= '1' && d <= '9' => return parse_backref(p),
+    Some(_) => {
+      return match parse_escape_char(p) {
+        Ok(c) => Ok(Literal(c, p.flags)),
+        Err(e) => Err(e)
+      }
     }
-    Some(_) => return parse_escape_char(p),
     None => return Err(ParseIncompleteEscapeSeq)
   };
 
   p.next();
 
-  println(cc.to_str());
-
   Ok(cc)
 }
 
-/// Parses an escaped character at a given state.
+/// Parses a Unicode property escape (`\pL`, `\p{Greek}`, `\P{Lu}`) 
+/// at a given state. The state should be at the `p` or `P`.
 ///
 /// # Arguments
 ///
 /// * p - The current state of parsing
-#[inline]
-fn parse_escape_char(p: &mut State) -> Result<Expr, ParseCode> {
+fn parse_property(p: &mut State) -> Result<Expr, ParseCode> {
+  let mut negate = p.current() == Some('P');
+  let mut name = ~"";
+
+  p.next();
+
   match p.current() {
+    Some('{') => {
+      p.next();
+
+      loop {
+        match p.current() {
+          Some('}') => {
+            p.next();
+            break;
+          }
+          Some(c) => {
+            p.next();
+            name.push_char(c);
+          }
+          None => return Err(ParseExpectedClosingBrace)
+        }
+      }
+    }
+    // A one letter name, like `\pL`
     Some(c) => {
       p.next();
+      name.push_char(c);
+    }
+    None => return Err(ParseIncompleteEscapeSeq)
+  }
+
+  // `\p{^L}` is the same as `\P{L}`
+  if (name.starts_with("^")) {
+    negate = !negate;
+    name = name.slice_from(1).to_owned();
+  }
+
+  let table = match property_table(name) {
+    Some(table) => table,
+    None => return Err(ParseUnknownProperty)
+  };
+
+  if (!negate && p.flags & IGNORECASE == 0) {
+    if (table.len() == 0) {
+      return Err(ParseEmptyCharClassRange)
+    }
+    return Ok(CharClassStatic(table))
+  }
+
+  let mut ranges = table.to_owned();
+
+  if (p.flags & IGNORECASE != 0) {
+    ranges = fold_ranges(ranges);
+  }
+
+  let cc = if (negate) {
+    new_negated_charclass(ranges)
+  } else {
+    new_charclass(ranges)
+  };
+
+  match cc {
+    CharClass(ref r) if r.len() == 0 => Err(ParseEmptyCharClassRange),
+    _ => Ok(cc)
+  }
+}
+
+/// Parses an escaped character at a given state, after the `\`.
+///
+/// Supports:
+///
+/// * `\a`, `\e`, `\f`, `\n`, `\r`, `\t`, `\v` = Control characters
+/// * `\xHH`, `\x{H...}`, `\uHHHH`, `\UHHHHHHHH` = Hex code points
+/// * `\0`, `\0o`, `\0oo` = Octal code points (up to `\377`)
+/// * `\cX` = Control-X
+///
+/// Any other character is escaped to itself, except for unknown 
+/// ASCII letters in strict mode.
+///
+/// # Arguments
+///
+/// * p - The current state of parsing
+fn parse_escape_char(p: &mut State) -> Result<char, ParseCode> {
+  let c = match p.current() {
+    Some(c) => c,
+    None => return Err(ParseIncompleteEscapeSeq)
+  };
+
+  if (c >= '0' && c <= '7') {
+    return parse_octal(p)
+  }
+
+  p.next();
+
+  match c {
+    'a' => Ok('\x07'),
+    'e' => Ok('\x1b'),
+    'f' => Ok('\x0c'),
+    'n' => Ok('\n'),
+    'r' => Ok('\r'),
+    't' => Ok('\t'),
+    'v' => Ok('\x0b'),
+    'x' => {
+      match p.current() {
+        Some('{') => {
+          p.next();
+
+          let (code, len) = parse_hex_digits(p, 8);
+
+          match p.current() {
+            Some('}') if len > 0 => p.next(),
+            _ => return Err(ParseInvalidHexEscape)
+          }
+
+          code_point(code)
+        }
+        _ => parse_hex(p, 2)
+      }
+    }
+    'u' => parse_hex(p, 4),
+    'U' => parse_hex(p, 8),
+    'c' => {
+      // Control characters are the letter with bit 6 
+      // flipped, so `\cA` and `\ca` are both `\x01`
+      match p.current() {
+        Some(x) if x.is_ascii() && !x.is_control() => {
+          p.next();
+          code_point((x.to_ascii().to_upper().to_char() as u32) ^ 0x40)
+        }
+        Some(_) => Err(ParseInvalidControlEscape),
+        None => Err(ParseIncompleteEscapeSeq)
+      }
+    }
+    'a'..'z' | 'A'..'Z' if p.flags & STRICT != 0 => Err(ParseUnknownEscape),
+    _ => Ok(c)
+  }
+}
+
+/// Parses an escaped character inside of a character class, where 
+/// `\b` is a backspace.
+///
+/// # Arguments
+///
+/// * p - The current state of parsing
+fn parse_class_escape(p: &mut State) -> Result<char, ParseCode> {
+  match p.current() {
+    Some('b') => {
+      p.next();
+      Ok('\x08')
+    }
+    _ => parse_escape_char(p)
+  }
+}
+
+/// Parses up to `max` hex digits at a given state. Returns their 
+/// value, and how many digits there were.
+///
+/// # Arguments
+///
+/// * p - The current state of parsing
+/// * max - The maximum number of digits to parse
+fn parse_hex_digits(p: &mut State, max: uint) -> (u32, uint) {
+  let mut code = 0u32;
+  let mut len = 0;
+
+  while (len < max) {
+    match p.current() {
+      Some(d) if d.is_digit_radix(16) => {
+        code = code * 16 + char::to_digit(d, 16).unwrap() as u32;
+        len += 1;
+        p.next();
+      }
+      _ => break
+    }
+  }
+
+  (code, len)
+}
+
+/// Parses exactly `n` hex digits at a given state, and returns the 
+/// character they encode.
+///
+/// # Arguments
+///
+/// * p - The current state of parsing
+/// * n - The number of digits
+fn parse_hex(p: &mut State, n: uint) -> Result<char, ParseCode> {
+  match parse_hex_digits(p, n) {
+    (code, len) if len == n => code_point(code),
+    _ => Err(ParseInvalidHexEscape)
+  }
+}
+
+/// Parses up to three octal digits at a given state, and returns 
+/// the character they encode.
+///
+/// # Arguments
+///
+/// * p - The current state of parsing
+fn parse_octal(p: &mut State) -> Result<char, ParseCode> {
+  let mut code = 0u32;
+  let mut len = 0;
+
+  while (len < 3) {
+    match p.current() {
+      Some(d) if is_octal(Some(d)) => {
+        code = code * 8 + char::to_digit(d, 8).unwrap() as u32;
+        len += 1;
+        p.next();
+      }
+      _ => break
+    }
+  }
+
+  // Like Python, octal escapes are limited to a byte
+  if (code > 255) {
+    return Err(ParseInvalidOctalEscape)
+  }
+
+  code_point(code)
+}
+
+/// Checks if a character is an octal digit.
+#[inline]
+fn is_octal(c: Option<char>) -> bool {
+  match c {
+    Some(d) => d >= '0' && d <= '7',
+    None => false
+  }
+}
+
+/// Converts a code point from an escape to a character.
+fn code_point(code: u32) -> Result<char, ParseCode> {
+  match char::from_u32(code) {
+    Some(c) => Ok(c),
+    None => Err(ParseInvalidCodePoint)
+  }
+}
+
+/// Parses a numbered backreference (`\1` - `\99`) at a given
+/// state. Like Python, three octal digits (`\101`) are an octal
+/// escape instead.
+///
+/// # Arguments
+///
+/// * p - The current state of parsing
+fn parse_backref(p: &mut State) -> Result<Expr, ParseCode> {
+  if (is_octal(p.current()) && is_octal(p.peek()) && is_octal(p.peekn(2))) {
+    return match parse_octal(p) {
+      Ok(c) => Ok(Literal(c, p.flags)),
+      Err(e) => Err(e)
+    }
+  }
+
+  let mut n = 0;
+  let mut len = 0;
 
-      Ok(Literal(c))
+  while (len < 2) {
+    match p.current() {
+      Some(d) if d.is_digit() => {
+        n = n * 10 + char::to_digit(d, 10).unwrap();
+        len += 1;
+        p.next();
+      }
+      _ => break
     }
-    None => Err(ParseIncompleteEscapeSeq) 
+  }
+
+  // Groups are numbered from 1 in the pattern, but 
+  // from 0 internally
+  check_backref(p, n - 1)
+}
+
+/// Parses a named backreference (`(?P=name)`) at a given state.
+/// The state should be right after the `=`.
+///
+/// # Arguments
+///
+/// * p - The current state of parsing
+fn parse_named_backref(p: &mut State) -> Result<Expr, ParseCode> {
+  let mut name = ~"";
+
+  loop {
+    match p.current() {
+      Some(')') => {
+        p.next();
+        break;
+      }
+      Some(c) => {
+        p.next();
+        name.push_char(c);
+      }
+      None => return Err(ParseExpectedClosingParen)
+    }
+  }
+
+  let index = match p.names.iter().find(|& &(ref n, _)| *n == name) {
+    Some(&(_, index)) => index,
+    None => return Err(ParseUnknownGroupName)
+  };
+
+  check_backref(p, index)
+}
+
+/// Checks that a backreference refers to a group that has 
+/// already been closed.
+///
+/// # Arguments
+///
+/// * p - The current state of parsing
+/// * index - The group being referred to
+fn check_backref(p: &mut State, index: uint) -> Result<Expr, ParseCode> {
+  if (index >= p.ncaptures) {
+    Err(ParseInvalidBackreference)
+  } else if (p.open_groups.contains(&index)) {
+    Err(ParseOpenGroupBackreference)
+  } else {
+    Ok(Backref(index, p.flags))
   }
 }
 
@@ -114,6 +508,10 @@ fn parse_group(p: &mut State) -> Result<Expr, ParseCode> {
   let mut capturing = true;
   let mut name: Option<~str> = None;
 
+  // Flags changed inside of the group don't apply 
+  // outside of it
+  let flags = p.flags;
+
   // Check for an extension denoted by a ?
   // 
   // Currently supporting:
@@ -121,13 +519,37 @@ fn parse_group(p: &mut State) -> Result<Expr, ParseCode> {
   // * `?:` = No Capture
   // * `?#` = Comment
   // * `?P<name> = Named Capturing Group
+  // * `?P=name` = Named Backreference
+  // * `?=`, `?!` = Positive and negative lookahead
+  // * `?<=`, `?<!` = Positive and negative lookbehind
+  // * `?>` = Atomic group
+  // * `?aimsx-aimsx` = Set flags for the rest of the group
+  // * `?aimsx-aimsx:` = Set flags for the group
+  let mut look: Option<(bool, bool)> = None;
+  let mut atomic = false;
+
   match p.current() {
     Some('?') => {
       match p.peek() {
+        Some('=') | Some('!') => {
+          look = Some((false, p.peek() == Some('!')));
+          p.consume(2);
+          capturing = false;
+        }
+        Some('<') if p.peekn(2) == Some('=') || p.peekn(2) == Some('!') => {
+          look = Some((true, p.peekn(2) == Some('!')));
+          p.consume(3);
+          capturing = false;
+        }
         Some(':') => {
           p.consume(2);
           capturing = false;
         }
+        Some('>') => {
+          p.consume(2);
+          capturing = false;
+          atomic = true;
+        }
         // A Comment. Everything in the parenthases is 
         // ignored
         Some('#') => {
@@ -136,6 +558,7 @@ fn parse_group(p: &mut State) -> Result<Expr, ParseCode> {
           loop {
             match p.current() {
               Some(')') => {
+                p.next();
                 return Ok(Empty)
               }
               Some('\\') if p.len() == 0 => {
@@ -150,8 +573,35 @@ fn parse_group(p: &mut State) -> Result<Expr, ParseCode> {
         }
         Some('P') => {
           p.consume(2);
+
+          match p.current() {
+            Some('=') => {
+              p.next();
+              return parse_named_backref(p)
+            }
+            _ => ()
+          }
+
+          match parse_group_name(p) {
+            Ok(n) => name = Some(n),
+            Err(e) => return Err(e)
+          }
+        }
+        _ => {
+          p.next();
+
+          match parse_flags(p) {
+            Ok((f, true)) => {
+              p.flags = f;
+              capturing = false;
+            }
+            Ok((f, false)) => {
+              p.flags = f;
+              return Ok(Empty)
+            }
+            Err(e) => return Err(e)
+          }
         }
-        _ => () 
       }
     }
     _ => ()
@@ -163,6 +613,7 @@ fn parse_group(p: &mut State) -> Result<Expr, ParseCode> {
   
   if (capturing) {
     p.ncaptures += 1;
+    p.open_groups.push(ncap);
   }
 
   let expr = match _parse_recursive(p) {
@@ -173,31 +624,257 @@ fn parse_group(p: &mut State) -> Result<Expr, ParseCode> {
   p.next();
 
   p.nparens -= 1;
+  p.flags = flags;
 
   if (capturing) {
-    Ok(Capture(~expr, ncap, name)) 
-  } else {
-    Ok(expr)
+    p.open_groups.pop();
+    return Ok(Capture(~expr, ncap, name))
+  }
+
+  if (atomic) {
+    return Ok(Atomic(~expr))
+  }
+
+  match look {
+    Some((false, negated)) => {
+      Ok(Lookaround(~expr, LookAhead, negated))
+    }
+    Some((true, negated)) => {
+      match fixed_width(&expr) {
+        Some(width) => Ok(Lookaround(~expr, LookBehind(width), negated)),
+        None => Err(ParseVariableWidthLookbehind)
+      }
+    }
+    None => Ok(expr)
   }
 }
 
-/// Parses a character class at a given state.
+/// Computes the number of characters an expression always matches,
+/// or None if it can match strings of different lengths.
+///
+/// # Arguments
+///
+/// * expr - The expression to measure
+fn fixed_width(expr: &Expr) -> Option<uint> {
+  match *expr {
+    Empty | 
+    AssertWordBoundary(_) | 
+    AssertNonWordBoundary(_) | 
+    AssertStart | 
+    AssertEnd |
+    AssertLineStart(_) |
+    AssertLineEnd(_) |
+    Lookaround(..) => Some(0),
+    Literal(..) | 
+    CharClass(_) | 
+    CharClassStatic(_) => Some(1),
+    Alternation(ref lft, ref rgt) => {
+      match (fixed_width(*lft), fixed_width(*rgt)) {
+        (Some(l), Some(r)) if l == r => Some(l),
+        _ => None
+      }
+    }
+    Concatenation(ref lft, ref rgt) => {
+      match (fixed_width(*lft), fixed_width(*rgt)) {
+        (Some(l), Some(r)) => Some(l + r),
+        _ => None
+      }
+    }
+    Repetition(ref expr, start, Some(end), _) if start == end => {
+      fixed_width(*expr).map(|w| w * start)
+    }
+    Capture(ref expr, _, _) |
+    Atomic(ref expr) => fixed_width(*expr),
+    Repetition(..) |
+    Backref(..) => None
+  }
+}
+
+/// Parses inline flags at a given state. The state should be right
+/// after the `?`.
+///
+/// Returns the flags, and whether they are only for the group 
+/// (`(?i:...)`), or for the rest of the enclosing group (`(?i)`).
+///
+/// # Arguments
+///
+/// * p - The current state of parsing
+fn parse_flags(p: &mut State) -> Result<(uint, bool), ParseCode> {
+  let mut flags = p.flags;
+  let mut negate = false;
+
+  loop {
+    match p.current() {
+      Some(')') => {
+        p.next();
+        return Ok((flags, false))
+      }
+      Some(':') => {
+        p.next();
+        return Ok((flags, true))
+      }
+      Some('-') if !negate => {
+        p.next();
+        negate = true;
+      }
+      Some(c) => {
+        let flag = match c {
+          'i' => IGNORECASE,
+          'm' => MULTILINE,
+          's' => DOTALL,
+          'x' => VERBOSE,
+          'a' => ASCII,
+          _   => return Err(ParseUnknownFlag)
+        };
+
+        p.next();
+
+        if (negate) {
+          flags &= !flag;
+        } else {
+          flags |= flag;
+        }
+      }
+      None => return Err(ParseExpectedClosingParen)
+    }
+  }
+}
+
+/// Parses the `<name>` of a named capturing group, and
+/// checks that it is a valid, unique identifier.
 ///
-/// # Issues
+/// # Arguments
+///
+/// * p - The current state of parsing
+fn parse_group_name(p: &mut State) -> Result<~str, ParseCode> {
+  match p.current() {
+    Some('<') => p.next(),
+    _ => return Err(ParseExpectedOpeningAngle)
+  }
+
+  let mut name = ~"";
+
+  loop {
+    match p.current() {
+      Some('>') => {
+        p.next();
+        break;
+      }
+      Some(c) => {
+        p.next();
+        name.push_char(c);
+      }
+      None => return Err(ParseExpectedClosingAngle)
+    }
+  }
+
+  if (!is_identifier(name)) {
+    return Err(ParseInvalidGroupName)
+  }
+
+  if (p.names.iter().any(|&(ref n, _)| *n == name)) {
+    return Err(ParseDuplicateGroupName)
+  }
+
+  p.names.push((name.clone(), p.ncaptures));
+
+  Ok(name)
+}
+
+/// Checks if a string is a valid group name. Like Python, 
+/// this is an identifier that doesn't begin with a digit.
+fn is_identifier(s: &str) -> bool {
+  if (s.len() == 0) {
+    return false
+  }
+  if (s.char_at(0).is_digit()) {
+    return false
+  }
+  s.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Parses a POSIX bracket class (`[:alpha:]`, `[:^alpha:]`) inside 
+/// of a character class, and returns its ranges. The state should be 
+/// at the opening `[`. Returns None, without consuming anything, if 
+/// there isn't a POSIX class at the state.
+///
+/// # Arguments
+///
+/// * p - The current state of parsing
+fn parse_posix_class(p: &mut State) -> Option<Result<~[Range], ParseCode>> {
+  if (p.peek() != Some(':')) {
+    return None
+  }
+
+  let mut name = ~"";
+  let mut len = 2;
+
+  loop {
+    match p.peekn(len) {
+      Some(':') if p.peekn(len + 1) == Some(']') => break,
+      Some(c) if c.is_alphabetic() || (c == '^' && len == 2) => {
+        name.push_char(c);
+        len += 1;
+      }
+      _ => return None
+    }
+  }
+
+  p.consume(len + 2);
+
+  let negate = name.starts_with("^");
+
+  if (negate) {
+    name = name.slice_from(1).to_owned();
+  }
+
+  match posix_class(name) {
+    Some(table) if negate => {
+      Some(Ok(class_ranges(new_negated_charclass(table.to_owned()))))
+    }
+    Some(table) => Some(Ok(table.to_owned())),
+    None => Some(Err(ParseUnknownPosixClass))
+  }
+}
+
+/// Parses a character class at a given state.
 ///
-/// * Can't have nested char classes. Need a way to combine char classes.
+/// Classes can be nested (`[a[bc]]`), and combined with `&&` 
+/// (intersection) and `--` (subtraction). These are applied left 
+/// to right, so `[a-z--[aeiou]&&[a-m]]` is the consonants in `a-m`.
 ///
 /// # Arguments
 ///
 /// * p - The current state of parsing
 #[inline]
 fn parse_charclass(p: &mut State) -> Result<Expr, ParseCode> {
-  // we need to keep track of any [, ( in
-  // the input, because we can just ignore 
-  // them
-  let mut nbracket: uint = 0;
+  let ranges = match parse_class_ranges(p) {
+    Ok(ranges) => ranges,
+    Err(e) => return Err(e)
+  };
+
+  // Check to see if the created char class is 
+  // empty
+  if (ranges.len() == 0) {
+    return Err(ParseEmptyCharClassRange)
+  }
+
+  Ok(CharClass(ranges))
+}
+
+/// Parses the inside of a character class (after the `[`), and 
+/// returns its ordered ranges.
+///
+/// # Arguments
+///
+/// * p - The current state of parsing
+fn parse_class_ranges(p: &mut State) -> Result<~[Range], ParseCode> {
   let mut ranges = ~[];
 
+  // The left side of a pending `&&` or `--`, and 
+  // the operator
+  let mut lhs: Option<(~[Range], char)> = None;
+
   // check to see if the first char following
   // '[' is a '^', if so, it is a negated char 
   // class 
@@ -222,71 +899,198 @@ fn parse_charclass(p: &mut State) -> Result<Expr, ParseCode> {
   loop {
     match p.current() {
       Some('[') => {
-        p.next();
-        nbracket += 1;
+        match parse_posix_class(p) {
+          Some(Ok(r)) => ranges.push_all_move(r),
+          Some(Err(e)) => return Err(e),
+          // A nested class
+          None => {
+            p.next();
+            match parse_class_ranges(p) {
+              Ok(r) => ranges.push_all_move(r),
+              Err(e) => return Err(e)
+            }
+          }
+        }
       },
       Some(']') => {
         p.next();
-        if (nbracket > 0) {
-          nbracket -= 1;
-        } else {
-          let cc = if (negate) {
-            new_negated_charclass(ranges)
-          } else {
-            new_charclass(ranges)
-          };
-          // Check to see if the created char class is 
-          // empty
-          if (match cc {
-            CharClass(ref r) => r.len() == 0,
-            _ => unreachable!() 
-          }) {
-            return Err(ParseEmptyCharClassRange)
-          }
-          return Ok(cc)
+
+        let mut ranges = apply_class_op(lhs, ranges, p.flags & IGNORECASE != 0);
+
+        // Case folding has to happen before negating, so 
+        // that `[^a]` doesn't match `A`
+        if (p.flags & IGNORECASE != 0) {
+          ranges = fold_ranges(ranges);
         }
+
+        let cc = if (negate) {
+          new_negated_charclass(ranges)
+        } else {
+          new_charclass(ranges)
+        };
+
+        return Ok(class_ranges(cc))
+      }
+      Some(op) if class_op(p) == Some(op) => {
+        p.consume(2);
+
+        let operand = apply_class_op(replace(&mut lhs, None), 
+                                     replace(&mut ranges, ~[]),
+                                     p.flags & IGNORECASE != 0);
+        lhs = Some((operand, op));
       }
       Some('\\') => {
         p.next();
         match p.current() {
-          Some(c) => {
-            ranges.push((c, c));
+          // Shorthand classes can be used inside of 
+          // brackets
+          Some('d') | Some('D') | Some('w') | Some('W') | 
+          Some('s') | Some('S') | Some('p') | Some('P') => {
+            match parse_escape(p) {
+              Ok(cc) => ranges.push_all_move(class_ranges(cc)),
+              Err(e) => return Err(e)
+            }
+
+            // A class can't start a range...something like [\d-z]
+            match (p.current(), p.peek()) {
+              (Some('-'), Some(e)) if e != ']' && class_op(p).is_none() => {
+                return Err(ParseBadCharacterRange)
+              }
+              _ => ()
+            }
+          }
+          Some(_) => {
+            let c = match parse_class_escape(p) {
+              Ok(c) => c,
+              Err(e) => return Err(e)
+            };
+
+            match parse_class_range(p, c) {
+              Ok(r) => ranges.push(r),
+              Err(e) => return Err(e)
+            }
           }
           None => return Err(ParseIncompleteEscapeSeq)
         }
-        p.next();
       }
       Some(c) => {
         p.next();
-        
-        // check to see if its this is part of a 
-        // range
-        match p.current() {
-          Some('-') => {
-            match p.peek() {
-              // Not a range...something like [a-]
-              Some(']') => {
-                ranges.push((c, c));
-              }
-              // A range...something like [a-b]
-              Some(e) => {
-                ranges.push((c, e));
-                p.consume(2);
-              }
-              // End of string
-              None => break
+
+        match parse_class_range(p, c) {
+          Ok(r) => ranges.push(r),
+          Err(e) => return Err(e)
+        }
+      }
+      None => break
+    }
+  }
+
+  Err(ParseExpectedClosingBracket)
+}
+
+/// Parses the end of a range in a character class (the `-z` in 
+/// `[a-z]`) if there is one, given the start of the range.
+///
+/// # Arguments
+///
+/// * p - The current state of parsing
+/// * c - The character before the state
+fn parse_class_range(p: &mut State, c: char) -> Result<Range, ParseCode> {
+  match p.current() {
+    Some('-') => {
+      match p.peek() {
+        // Not a range...something like [a-] or [a--b]
+        Some(']') | None => Ok((c, c)),
+        Some('-') if class_op(p).is_some() => Ok((c, c)),
+        // A range with an escaped end...something like [a-\x7f]
+        Some('\\') => {
+          p.consume(2);
+
+          // A class can't end a range...something like [a-\d]
+          match p.current() {
+            Some('d') | Some('D') | Some('w') | Some('W') | 
+            Some('s') | Some('S') | Some('p') | Some('P') => {
+              return Err(ParseBadCharacterRange)
             }
+            _ => ()
           }
-          // A single character...something like [a]
-          Some(_) | None => ranges.push((c, c))
+
+          match parse_class_escape(p) {
+            Ok(e) => checked_class_range(c, e),
+            Err(e) => Err(e)
+          }
+        }
+        // A range...something like [a-b]
+        Some(e) => {
+          p.consume(2);
+          checked_class_range(c, e)
         }
       }
-      None => break
     }
+    // A single character...something like [a]
+    _ => Ok((c, c))
+  }
+}
 
+/// Makes a range in a character class, failing if it's backwards
+/// (something like [z-a]).
+///
+/// # Arguments
+///
+/// * start - The first character in the range
+/// * end - The last character in the range
+fn checked_class_range(start: char, end: char) -> Result<Range, ParseCode> {
+  if (end < start) {
+    Err(ParseBadCharacterRange)
+  } else {
+    Ok((start, end))
   }
+}
 
-  Err(ParseExpectedClosingBracket)
+/// Checks if the state is at a `&&` or `--` in a character class,
+/// and returns the operator if so. It's only an operator when 
+/// something follows it, so `[+--]` is the range from `+` to `-`.
+///
+/// # Arguments
+///
+/// * p - The current state of parsing
+fn class_op(p: &State) -> Option<char> {
+  match (p.peekn(0), p.peekn(1), p.peekn(2)) {
+    (Some(op), Some(op2), Some(next)) if (op == '&' || op == '-') && 
+                                         op2 == op && next != ']' => {
+      Some(op)
+    }
+    _ => None
+  }
+}
+
+/// Applies a pending `&&` or `--` in a character class, given the 
+/// ranges on its right side.
+///
+/// # Arguments
+///
+/// * lhs - The left side of the operation, and the operator
+/// * rhs - The right side of the operation
+/// * fold - Whether to case fold both sides first
+fn apply_class_op(lhs: Option<(~[Range], char)>, rhs: ~[Range], 
+                  fold: bool) -> ~[Range] {
+  let (l, op) = match lhs {
+    Some(lhs) => lhs,
+    None => return rhs
+  };
+
+  // Folding after the operation would be too late: `(?i)[k--K]` 
+  // has to be empty
+  let (l, r) = if (fold) {
+    (fold_ranges(l), fold_ranges(rhs))
+  } else {
+    (l, rhs)
+  };
+
+  match op {
+    '&' => class_ranges(new_intersected_charclass(l, r)),
+    _ => class_ranges(new_subtracted_charclass(l, r))
+  }
 }
 
 /// Determines if there is a repetition operator at a given state and 
@@ -306,7 +1110,7 @@ fn parse_repetition(p: &mut State) -> Option<(uint, Option<uint>)> {
   // these help parse numbers with more than
   // 1 digit
   let mut buf = ~"";
-  let mut len = 0;
+  let mut len = skip_verbose_ahead(p, 0);
 
   loop {
     match p.peekn(len) {
@@ -318,10 +1122,12 @@ fn parse_repetition(p: &mut State) -> Option<(uint, Option<uint>)> {
     }
   }
 
-  if (len == 0) {
+  if (buf.len() == 0) {
     return None
   }
 
+  len = skip_verbose_ahead(p, len);
+
   // this is guaranteed to be a digit because 
   // we only append it to the buffer if the char
   // is a digit
@@ -334,7 +1140,7 @@ fn parse_repetition(p: &mut State) -> Option<(uint, Option<uint>)> {
   // if there is a '}', there is an exact repetition
   match p.peekn(len) {
     Some(',') => { 
-      len += 1;
+      len = skip_verbose_ahead(p, len + 1);
     }
     Some('}') => {
       p.consume(len + 1);
@@ -366,6 +1172,8 @@ fn parse_repetition(p: &mut State) -> Option<(uint, Option<uint>)> {
 
   let end = from_str::<uint>(buf).unwrap();
 
+  len = skip_verbose_ahead(p, len);
+
   match p.peekn(len) {
     Some('}') => {
       p.consume(len + 1);
@@ -382,10 +1190,24 @@ fn parse_repetition(p: &mut State) -> Option<(uint, Option<uint>)> {
 /// * p - The current state of parsing
 fn _parse_recursive(p: &mut State) -> Result<Expr, ParseCode> {
   let mut stack = ~[];
+  let outer_token = p.token_start();
+  // Set if the last token was a group that only sets flags, 
+  // which leaves nothing to repeat
+  let mut after_flags = false;
 
   loop {
+    if (p.flags & VERBOSE != 0) {
+      skip_verbose(p);
+    }
+
+    p.mark_token();
+
+    let flags_before = after_flags;
+    after_flags = false;
+
     match p.current() {
       Some('(') => {
+        after_flags = at_flag_group(p);
         p.next();
         do_concat(&mut stack);
         let expr = check_ok!(parse_group(p));
@@ -417,14 +1239,26 @@ fn _parse_recursive(p: &mut State) -> Result<Expr, ParseCode> {
       }
 
       Some(c) if c == '*' || c == '?' || c == '+' => {
+        if (flags_before) {
+          return Err(ParseEmptyRepetition)
+        }
+
         p.next();
 
+        if (p.flags & VERBOSE != 0) {
+          skip_verbose(p);
+        }
+
         // Look for a quantifier
         let quantifier = match p.current() {
           Some('?') => {
             p.next();
             NonGreedy
           }
+          Some('+') => {
+            p.next();
+            Possessive
+          }
           _ => Greedy
         };
         
@@ -433,8 +1267,11 @@ fn _parse_recursive(p: &mut State) -> Result<Expr, ParseCode> {
           Some(Repetition(..)) |
           Some(AssertStart) | 
           Some(AssertEnd) | 
-          Some(AssertWordBoundary) |
-          Some(AssertNonWordBoundary) => {
+          Some(AssertLineStart(_)) | 
+          Some(AssertLineEnd(_)) | 
+          Some(AssertWordBoundary(_)) |
+          Some(AssertNonWordBoundary(_)) |
+          Some(Lookaround(..)) => {
             return Err(ParseEmptyRepetition)
           }
           Some(expr) => {
@@ -451,6 +1288,9 @@ fn _parse_recursive(p: &mut State) -> Result<Expr, ParseCode> {
       Some('{') => {
         p.next();
         match parse_repetition(p) {
+          Some(_) if flags_before => {
+            return Err(ParseEmptyRepetition)
+          }
           Some(rep) => {
             let (start, end) = rep;
 
@@ -461,12 +1301,20 @@ fn _parse_recursive(p: &mut State) -> Result<Expr, ParseCode> {
               _ => ()
             }
 
+            if (p.flags & VERBOSE != 0) {
+              skip_verbose(p);
+            }
+
             // Look for a quantifier
             let quantifier = match p.current() {
               Some('?') => {
                 p.next();
                 NonGreedy
               },
+              Some('+') => {
+                p.next();
+                Possessive
+              },
               _ => Greedy
             };
 
@@ -485,16 +1333,37 @@ fn _parse_recursive(p: &mut State) -> Result<Expr, ParseCode> {
 
       Some('.') => {
         p.next();
-        stack.push(CharClass(~[('\0', MAX)]));
+
+        // Unless DOTALL is set, `.` matches anything
+        // but the end of a line
+        let cc = if (p.flags & DOTALL != 0) {
+          AnyClass
+        } else if (p.flags & NEWLINE_ANY != 0) {
+          NotLineTerminatorClass
+        } else if (p.flags & CRLF != 0) {
+          NotCRLFClass
+        } else {
+          NotNewlineClass
+        };
+
+        stack.push(cc);
       }
 
       Some('^') => {
         p.next();
-        stack.push(AssertStart);
+        if (p.flags & MULTILINE != 0) {
+          stack.push(AssertLineStart(p.flags));
+        } else {
+          stack.push(AssertStart);
+        }
       }
       Some('$') => {
         p.next();
-        stack.push(AssertEnd);
+        if (p.flags & MULTILINE != 0) {
+          stack.push(AssertLineEnd(p.flags));
+        } else {
+          stack.push(AssertEnd);
+        }
       }
 
       Some('[') => {
@@ -507,7 +1376,7 @@ fn _parse_recursive(p: &mut State) -> Result<Expr, ParseCode> {
       }
       Some(c) => {
         p.next();
-        stack.push(Literal(c));
+        stack.push(Literal(c, p.flags));
       }
       None => break // end of string
     }
@@ -520,6 +1389,10 @@ fn _parse_recursive(p: &mut State) -> Result<Expr, ParseCode> {
   if (p.hasUnmatchedParens() && p.isEnd()) {
     Err(ParseExpectedClosingParen)
   } else {
+    // Errors found after this returns are about the caller's 
+    // token (like the group this is in), not the last one here
+    p.restore_token(outer_token);
+
     match stack.pop_opt() {
       Some(expr)  => Ok(expr),
       None        => Ok(Empty)
@@ -527,6 +1400,92 @@ fn _parse_recursive(p: &mut State) -> Result<Expr, ParseCode> {
   }
 }
 
+/// Checks if the state is at a group that only sets flags, like 
+/// `(?i)`. These don't match anything, so they can't be repeated.
+///
+/// # Arguments
+///
+/// * p - The current state of parsing
+fn at_flag_group(p: &State) -> bool {
+  if (p.peekn(1) != Some('?')) {
+    return false
+  }
+
+  let mut len = 2;
+
+  loop {
+    match p.peekn(len) {
+      Some(')') => return len > 2,
+      Some('i') | Some('m') | Some('s') | Some('x') | 
+      Some('a') | Some('-') => len += 1,
+      _ => return false
+    }
+  }
+}
+
+/// Skips whitespace and `#` comments (up to the end of the line) 
+/// at a given state. Only used in verbose mode.
+///
+/// # Arguments
+///
+/// * p - The current state of parsing
+fn skip_verbose(p: &mut State) {
+  loop {
+    match p.current() {
+      Some(c) if c.is_whitespace() => p.next(),
+      Some('#') => {
+        loop {
+          match p.current() {
+            Some('\n') => {
+              p.next();
+              break;
+            }
+            Some(_) => p.next(),
+            None => break
+          }
+        }
+      }
+      _ => break
+    }
+  }
+}
+
+/// Like `skip_verbose`, but looks `len` characters ahead of the 
+/// state without moving it, and returns how far ahead the next 
+/// character that isn't skipped is. Does nothing unless in 
+/// verbose mode.
+///
+/// # Arguments
+///
+/// * p - The current state of parsing
+/// * len - How far ahead to start
+fn skip_verbose_ahead(p: &State, mut len: uint) -> uint {
+  if (p.flags & VERBOSE == 0) {
+    return len
+  }
+
+  loop {
+    match p.peekn(len) {
+      Some(c) if c.is_whitespace() => len += 1,
+      Some('#') => {
+        loop {
+          match p.peekn(len) {
+            Some('\n') => {
+              len += 1;
+              break;
+            }
+            Some(_) => len += 1,
+            None => break
+          }
+        }
+      }
+      _ => break
+    }
+  }
+
+  len
+}
+
 /// Concatenates all itemes on the stack if there are more 
 /// than two.
 ///
@@ -557,12 +1516,19 @@ fn print_stack(stack: &mut ~[Expr]) {
 #[cfg(test)]
 mod parse_tests {
   use super::*;
-  use error::ParseError::*;
+  use std::char::MAX;
+  use flags::{IGNORECASE, VERBOSE, STRICT};
+  use error::ParseCodes::*;
+  use error::ParseError;
 
   macro_rules! test_parse(
     ($input: expr, $expect: pat) => (
       {
-        let ok = match parse($input) {
+        let res = match parse($input, 0) {
+          Ok(expr) => Ok(expr),
+          Err(e) => Err(e.code)
+        };
+        let ok = match res {
           $expect => true,
           _ => false
         };
@@ -621,4 +1587,407 @@ mod parse_tests {
   fn parse_backward_slash_err() {
     test_parse!("\\", Err(ParseIncompleteEscapeSeq));
   }
+
+  #[test]
+  fn parse_named_group_ok() {
+    test_parse!("(?P<first_1>a)(?P<second>b)", Ok(_));
+  }
+
+  #[test]
+  fn parse_named_group_no_name_err() {
+    test_parse!("(?Pa)", Err(ParseExpectedOpeningAngle));
+  }
+
+  #[test]
+  fn parse_named_group_unclosed_err() {
+    test_parse!("(?P<name", Err(ParseExpectedClosingAngle));
+  }
+
+  #[test]
+  fn parse_named_group_bad_name_err() {
+    test_parse!("(?P<1abc>a)", Err(ParseInvalidGroupName));
+  }
+
+  #[test]
+  fn parse_named_group_duplicate_err() {
+    test_parse!("(?P<a>x)(?P<a>y)", Err(ParseDuplicateGroupName));
+  }
+
+  #[test]
+  fn parse_backref_ok() {
+    test_parse!("(a)(b)\\2\\1", Ok(_));
+  }
+
+  #[test]
+  fn parse_named_backref_ok() {
+    test_parse!("(?P<a>x)(?P=a)", Ok(_));
+  }
+
+  #[test]
+  fn parse_backref_undefined_err() {
+    test_parse!("(a)\\2", Err(ParseInvalidBackreference));
+  }
+
+  #[test]
+  fn parse_backref_open_group_err() {
+    test_parse!("(a\\1)", Err(ParseOpenGroupBackreference));
+  }
+
+  #[test]
+  fn parse_lookahead_ok() {
+    test_parse!("a(?=b)(?!c)", Ok(_));
+  }
+
+  #[test]
+  fn parse_lookbehind_ok() {
+    test_parse!("(?<=ab|cd)(?<!x{2}[yz])e", Ok(_));
+  }
+
+  #[test]
+  fn parse_variable_lookbehind_err() {
+    test_parse!("(?<=a+)b", Err(ParseVariableWidthLookbehind));
+  }
+
+  #[test]
+  fn parse_uneven_lookbehind_err() {
+    test_parse!("(?<!a|bc)d", Err(ParseVariableWidthLookbehind));
+  }
+
+  #[test]
+  fn parse_repeated_lookaround_err() {
+    test_parse!("(?=a)*", Err(ParseEmptyRepetition));
+  }
+
+  #[test]
+  fn parse_inline_flags_ok() {
+    test_parse!("(?i)a(?m-s:b)(?-i)c", Ok(_));
+  }
+
+  #[test]
+  fn parse_repeated_flag_group_err() {
+    test_parse!("ab(?i)*", Err(ParseEmptyRepetition));
+    test_parse!("(?i)+", Err(ParseEmptyRepetition));
+    test_parse!("a(?m){2}", Err(ParseEmptyRepetition));
+    test_parse!("(?i)a*", Ok(_));
+    test_parse!("(?i:a)*", Ok(_));
+  }
+
+  #[test]
+  fn parse_unknown_flag_err() {
+    test_parse!("(?iq)a", Err(ParseUnknownFlag));
+  }
+
+  #[test]
+  fn parse_unclosed_flags_err() {
+    test_parse!("(?i", Err(ParseExpectedClosingParen));
+  }
+
+  #[test]
+  fn parse_scoped_flags_test() {
+    let ok = match parse("(?i:a)b", 0) {
+      Ok(Concatenation(~Literal('a', f), ~Literal('b', g))) => {
+        f & IGNORECASE != 0 && g & IGNORECASE == 0
+      }
+      _ => false
+    };
+    assert!(ok);
+  }
+
+  #[test]
+  fn parse_comment_ok() {
+    test_parse!("a(?#comment)b(c)", Ok(_));
+  }
+
+  #[test]
+  fn parse_verbose_test() {
+    let ok = match parse(" a # comment\n\\  ", VERBOSE) {
+      Ok(Concatenation(~Literal('a', _), ~Literal(' ', _))) => true,
+      _ => false
+    };
+    assert!(ok);
+  }
+
+  #[test]
+  fn parse_verbose_quantifier_test() {
+    let ok = match parse("a* ?", VERBOSE) {
+      Ok(Repetition(~Literal('a', _), 0, None, NonGreedy)) => true,
+      _ => false
+    };
+    assert!(ok);
+    let ok = match parse("a{ 2, # at least\n 3 } +", VERBOSE) {
+      Ok(Repetition(~Literal('a', _), 2, Some(3), Possessive)) => true,
+      _ => false
+    };
+    assert!(ok);
+  }
+
+  #[test]
+  fn parse_verbose_class_test() {
+    let ok = match parse("(?x)[ #]", 0) {
+      Ok(CharClass(ref r)) => r.len() == 2,
+      _ => false
+    };
+    assert!(ok);
+  }
+
+  #[test]
+  fn parse_property_ok() {
+    test_parse!("\\pL\\p{Lu}\\P{Greek}\\p{Script=Han}\\p{^White_Space}", Ok(_));
+  }
+
+  #[test]
+  fn parse_unknown_property_err() {
+    test_parse!("\\p{Klingon}", Err(ParseUnknownProperty));
+  }
+
+  #[test]
+  fn parse_unclosed_property_err() {
+    test_parse!("\\p{Lu", Err(ParseExpectedClosingBrace));
+  }
+
+  #[test]
+  fn parse_posix_class_test() {
+    let ok = match parse("[[:digit:][:upper:]_]", 0) {
+      Ok(CharClass(r)) => r == ~[('0', '9'), ('A', 'Z'), ('_', '_')],
+      _ => false
+    };
+    assert!(ok);
+  }
+
+  #[test]
+  fn parse_negated_posix_class_test() {
+    let ok = match parse("[[:^alpha:]]", 0) {
+      Ok(CharClass(r)) => r.len() == 3 && r[1] == ('[', '`'),
+      _ => false
+    };
+    assert!(ok);
+  }
+
+  #[test]
+  fn parse_unknown_posix_class_err() {
+    test_parse!("[[:alfa:]]", Err(ParseUnknownPosixClass));
+  }
+
+  #[test]
+  fn parse_nested_class_test() {
+    let ok = match parse("[a[b-c][^\x00-y]]", 0) {
+      Ok(CharClass(r)) => r == ~[('a', 'c'), ('z', MAX)],
+      _ => false
+    };
+    assert!(ok);
+  }
+
+  #[test]
+  fn parse_class_intersection_test() {
+    let ok = match parse("[a-z&&[^aeiou]&&a-f]", 0) {
+      Ok(CharClass(r)) => r == ~[('b', 'd'), ('f', 'f')],
+      _ => false
+    };
+    assert!(ok);
+  }
+
+  #[test]
+  fn parse_class_subtraction_test() {
+    let ok = match parse("[a-f--[bd]--e]", 0) {
+      Ok(CharClass(r)) => r == ~[('a', 'a'), ('c', 'c'), ('f', 'f')],
+      _ => false
+    };
+    assert!(ok);
+  }
+
+  #[test]
+  fn parse_class_dash_range_test() {
+    let ok = match parse("[+--]", 0) {
+      Ok(CharClass(r)) => r == ~[('+', '-')],
+      _ => false
+    };
+    assert!(ok);
+    test_parse!("(?i)[k--K]", Err(ParseEmptyCharClassRange));
+  }
+
+  #[test]
+  fn parse_class_bad_range_err() {
+    test_parse!("[a-\\d]", Err(ParseBadCharacterRange));
+    test_parse!("[a-\\w]", Err(ParseBadCharacterRange));
+    test_parse!("[a-\\p{Lu}]", Err(ParseBadCharacterRange));
+    test_parse!("[\\d-z]", Err(ParseBadCharacterRange));
+    test_parse!("[\\x39-\\x30]", Err(ParseBadCharacterRange));
+    test_parse!("[z-a]", Err(ParseBadCharacterRange));
+    test_parse!("[\\d-]", Ok(_));
+    test_parse!("[a-\\x7f]", Ok(_));
+  }
+
+  #[test]
+  fn parse_empty_class_operation_err() {
+    test_parse!("[a-c&&x-z]", Err(ParseEmptyCharClassRange));
+  }
+
+  #[test]
+  fn parse_unclosed_nested_class_err() {
+    test_parse!("[a[bc]", Err(ParseExpectedClosingBracket));
+  }
+
+  #[test]
+  fn parse_word_boundary_test() {
+    test_parse!("\\b", Ok(AssertWordBoundary(_)));
+    test_parse!("\\B", Ok(AssertNonWordBoundary(_)));
+  }
+
+  #[test]
+  fn parse_escapes_ok() {
+    test_parse!("\\a\\e\\f\\n\\r\\t\\v\\x41\\u00e9\\U0001F600\\0\\012", Ok(_));
+  }
+
+  #[test]
+  fn parse_braced_hex_test() {
+    let ok = match parse("\\x{1F600}", 0) {
+      Ok(Literal('\U0001F600', _)) => true,
+      _ => false
+    };
+    assert!(ok);
+  }
+
+  #[test]
+  fn parse_octal_test() {
+    let ok = match parse("\\101", 0) {
+      Ok(Literal('A', _)) => true,
+      _ => false
+    };
+    assert!(ok);
+  }
+
+  #[test]
+  fn parse_control_test() {
+    let ok = match parse("\\cj", 0) {
+      Ok(Literal('\n', _)) => true,
+      _ => false
+    };
+    assert!(ok);
+  }
+
+  #[test]
+  fn parse_class_escapes_test() {
+    let ok = match parse("[\\x30-\\x39\\t\\b]", 0) {
+      Ok(CharClass(r)) => r == ~[('\x08', '\x08'), ('\t', '\t'), ('0', '9')],
+      _ => false
+    };
+    assert!(ok);
+  }
+
+  #[test]
+  fn parse_short_hex_err() {
+    test_parse!("\\x4", Err(ParseInvalidHexEscape));
+  }
+
+  #[test]
+  fn parse_unclosed_hex_err() {
+    test_parse!("\\x{41", Err(ParseInvalidHexEscape));
+  }
+
+  #[test]
+  fn parse_invalid_code_point_err() {
+    test_parse!("\\x{110000}", Err(ParseInvalidCodePoint));
+  }
+
+  #[test]
+  fn parse_surrogate_err() {
+    test_parse!("[\\ud800]", Err(ParseInvalidCodePoint));
+  }
+
+  #[test]
+  fn parse_octal_range_err() {
+    test_parse!("\\777", Err(ParseInvalidOctalEscape));
+  }
+
+  #[test]
+  fn parse_unknown_escape_ok() {
+    test_parse!("\\q\\%", Ok(_));
+  }
+
+  #[test]
+  fn parse_strict_unknown_escape_err() {
+    let ok = match parse("\\q", STRICT) {
+      Err(ParseError { code: ParseUnknownEscape, .. }) => true,
+      _ => false
+    };
+    assert!(ok);
+  }
+
+  #[test]
+  fn parse_error_span_test() {
+    let err = parse("ab\\x4z", 0).unwrap_err();
+    assert_eq!(err.span, (2, 5));
+    assert_eq!(err.position, 2);
+  }
+
+  #[test]
+  fn parse_error_nested_span_test() {
+    let err = parse("(a(b\\x4z))", 0).unwrap_err();
+    assert_eq!(err.span, (4, 7));
+    let err = parse("x(?<=a+)b", 0).unwrap_err();
+    assert!(match err.code { ParseVariableWidthLookbehind => true, _ => false });
+    assert_eq!(err.span, (1, 7));
+  }
+
+  #[test]
+  fn parse_error_position_test() {
+    let err = parse("\u00e9\u00e9(?Q)", 0).unwrap_err();
+    assert_eq!(err.offset, 4);
+    assert_eq!(err.position, 2);
+  }
+
+  #[test]
+  fn parse_error_unclosed_group_test() {
+    let err = parse("(a|b", 0).unwrap_err();
+    assert_eq!(err.span, (4, 4));
+    assert_eq!(err.diagnostic(), 
+               ~"Parse Error: Expected ')' at position 4\n(a|b\n    ^");
+  }
+
+  #[test]
+  fn parse_error_diagnostic_test() {
+    let err = parse("ab\\x4z", 0).unwrap_err();
+    assert_eq!(err.diagnostic(), 
+               ~"Parse Error: Bad hexadecimal escape at position 2\nab\\x4z\n  ^^^");
+  }
+
+  #[test]
+  fn parse_error_diagnostic_line_test() {
+    let err = parse("a\n\t[b\nc", VERBOSE).unwrap_err();
+    assert_eq!(err.diagnostic(), 
+               ~"Parse Error: Expected ']' at position 3\n\t[b\n\t^^");
+  }
+
+  #[test]
+  fn parse_possessive_ok() {
+    test_parse!("a*+b++c?+d{1,2}+", Ok(_));
+  }
+
+  #[test]
+  fn parse_possessive_test() {
+    let ok = match parse("a++", 0) {
+      Ok(Repetition(~Literal('a', _), 1, None, Possessive)) => true,
+      _ => false
+    };
+    assert!(ok);
+  }
+
+  #[test]
+  fn parse_atomic_group_test() {
+    let ok = match parse("(?>ab|a)", 0) {
+      Ok(Atomic(~Alternation(..))) => true,
+      _ => false
+    };
+    assert!(ok);
+  }
+
+  #[test]
+  fn parse_repeated_possessive_err() {
+    test_parse!("a*++", Err(ParseEmptyRepetition));
+  }
+
+  #[test]
+  fn parse_named_backref_unknown_err() {
+    test_parse!("(?P<a>x)(?P=b)", Err(ParseUnknownGroupName));
+  }
 }
@@ -1,88 +1,1103 @@
-use exec::{ExecStrategy, PikeVM};
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::hashmap::HashMap;
+use exec::{ExecStrategy, Thread, PikeVM, Backtrack, BoundedBacktrack, Threads};
+use dfa::{LazyDFA, DFACache, DFA, FullDFA, dfa_supported};
+use onepass::{OnePass, OnePassProg};
 use compile::Instruction;
-use result::Match;
-use parse::parse;
-use compile::compile_recursive;
-use error::ParseError::*;
+use result::{Match, Found, FoundStr, FoundGroups};
+use parse::parse_groups;
+use compile::{compile_recursive, needs_backtrack};
+use error::ParseError;
+use error::ParseCodes::ParseCode;
+use error::TemplateError::TemplateCode;
+use error::DFAError::DFACode;
+use error::BuildError::*;
+use template::Template;
 
-/// Uncompiled regular expression. 
-pub struct UncompiledRegexp {
-  prog: ~[Instruction]
+/// The engines a `Regexp` can be made to use. See 
+/// `RegexpBuilder::strategy`.
+#[deriving(Clone, Eq)]
+pub enum MatchStrategy {
+  // Picks an engine based on the pattern and the input
+  StrategyAuto,
+  StrategyPikeVM,
+  StrategyBacktrack,
+  // Falls back to the PikeVM when the input is too long for
+  // the visited set to stay small
+  StrategyBoundedBacktrack,
+  StrategyLazyDFA,
+  // Builds a full DFA, with at most this many states
+  StrategyDFA(uint),
+  // Needs a one-pass pattern with at most `onepass::MAX_NODES`
+  // nodes. Only anchored matches, and searches for patterns 
+  // starting with `^` from position 0, run on it. Other searches would restart 
+  // it at every position, so they use the PikeVM.
+  StrategyOnePass
+}
+
+/// Compiled regular expression. 
+///
+/// Holds onto the compiled program, along with the 
+/// state the VM needs while executing, so repeated calls 
+/// don't need to pay for setting things up again.
+///
+/// The `OnePass` engine is only built for one-pass patterns 
+/// that need at most `onepass::MAX_NODES` (1000) nodes, and only
+/// runs anchored matches or searches of `^` patterns from the
+/// start of the input. Everything else falls back to the other
+/// engines.
+pub struct Regexp {
+  priv prog: ~[Instruction],
+  priv ncaps: uint,
+  priv names: Rc<HashMap<~str, uint>>,
+  priv backtrack: bool,
+  priv threads: RefCell<Threads>,
+  // Only there if the program can run on a DFA
+  priv dfa: Option<RefCell<DFACache>>,
+  // Only there if it's been built with `compile_dfa`
+  priv full_dfa: Option<DFA>,
+  // Only there if the program is one-pass
+  priv onepass: Option<OnePassProg>,
+  priv strategy: MatchStrategy
 }
 
 /// Constructors
-impl UncompiledRegexp {
-  pub fn new(s: &str) -> Result<UncompiledRegexp, ParseCode> {
-    match parse(s) {
-      Ok(ref expr) => {
+impl Regexp {
+  pub fn new(s: &str) -> Result<Regexp, ParseError> {
+    Regexp::with_flags(s, 0)
+  }
+  /// Compiles a pattern with flags (see flags.rs), which
+  /// can be combined with `|`.
+  pub fn with_flags(s: &str, flags: uint) -> Result<Regexp, ParseError> {
+    match parse_groups(s, flags) {
+      Ok((ref expr, ncaps, ref group_names)) => {
         let prog = compile_recursive(expr);
-        Ok(UncompiledRegexp { prog: prog })
+        let mut names = HashMap::new();
+        for &(ref name, index) in group_names.iter() {
+          names.insert(name.clone(), index);
+        }
+        let threads = Threads::new(prog.len());
+        let backtrack = needs_backtrack(prog);
+        let dfa = if (!backtrack && dfa_supported(prog)) {
+          Some(RefCell::new(DFACache::new(prog)))
+        } else {
+          None
+        };
+        let onepass = if (!backtrack) {
+          OnePassProg::new(prog)
+        } else {
+          None
+        };
+
+        Ok(Regexp { 
+          prog: prog, 
+          ncaps: ncaps,
+          names: Rc::new(names),
+          backtrack: backtrack,
+          threads: RefCell::new(threads),
+          dfa: dfa,
+          full_dfa: None,
+          onepass: onepass,
+          strategy: StrategyAuto
+        })
       }
       Err(e) => Err(e)
     }
   }
 }
 
-/// TODO:
-/// The API needs some work.
-/// Allow for other implementations to be used?
-impl UncompiledRegexp { 
+/// Uncompiled regular expression. 
+///
+/// This is what the library used to provide, and is kept so that
+/// existing code still builds. It's a thin wrapper over `Regexp`.
+#[deprecated = "use `Regexp` instead"]
+pub struct UncompiledRegexp {
+  priv re: Regexp
+}
+
+impl UncompiledRegexp {
+  pub fn new(s: &str) -> Result<UncompiledRegexp, ParseCode> {
+    match Regexp::new(s) {
+      Ok(re) => Ok(UncompiledRegexp { re: re }),
+      Err(e) => Err(e.code)
+    }
+  }
   /// Checks if the beginning of the input string 
   /// contains a match, and returns it.
   pub fn exec(&self, input: &str) -> Option<Match> {
-    let strat = PikeVM::new(self.prog, 0);
-    match strat.run(input, 0) {
-      Some(t) => {
-        Some(Match::new(0, t.end, input, t.captures))
+    self.re.exec(input)
+  }
+  /// Finds the first occurrence of the pattern in the 
+  /// input string and returns it.
+  pub fn search(&self, input: &str) -> Option<Match> {
+    self.re.search(input)
+  }
+}
+
+/// Options for compiling a `Regexp`. See `Regexp::build`.
+pub struct RegexpBuilder<'a> {
+  priv pattern: &'a str,
+  priv flags: uint,
+  priv strategy: MatchStrategy
+}
+
+impl<'a> RegexpBuilder<'a> {
+  /// Sets the flags (see flags.rs), which can be combined with `|`.
+  pub fn flags(mut self, flags: uint) -> RegexpBuilder<'a> {
+    self.flags = flags;
+    self
+  }
+  /// Sets the engine to use. By default, it's picked for each
+  /// call (`StrategyAuto`).
+  pub fn strategy(mut self, strategy: MatchStrategy) -> RegexpBuilder<'a> {
+    self.strategy = strategy;
+    self
+  }
+  /// Compiles the pattern. Fails if the engine that was asked for
+  /// can't run it.
+  pub fn build(self) -> Result<Regexp, BuildCode> {
+    let mut re = match Regexp::with_flags(self.pattern, self.flags) {
+      Ok(re) => re,
+      Err(e) => return Err(BuildParseError(e))
+    };
+
+    let supported = match self.strategy {
+      StrategyAuto | StrategyBacktrack => true,
+      StrategyPikeVM | StrategyBoundedBacktrack => !re.backtrack,
+      StrategyLazyDFA => re.dfa.is_some(),
+      StrategyOnePass => re.onepass.is_some(),
+      StrategyDFA(max_states) => {
+        match re.compile_dfa(max_states) {
+          Ok(()) => true,
+          Err(e) => return Err(BuildDFAError(e))
+        }
       }
+    };
+
+    if (!supported) {
+      return Err(BuildUnsupportedStrategy)
+    }
+
+    re.strategy = self.strategy;
+    Ok(re)
+  }
+}
+
+impl Regexp { 
+  /// Starts building a `Regexp`, for setting options that 
+  /// `new` and `with_flags` don't have.
+  pub fn build<'a>(s: &'a str) -> RegexpBuilder<'a> {
+    RegexpBuilder {
+      pattern: s,
+      flags: 0,
+      strategy: StrategyAuto
+    }
+  }
+  /// Returns the engine the `Regexp` was built to use.
+  pub fn strategy(&self) -> MatchStrategy {
+    self.strategy
+  }
+
+  /// Returns the number of capturing groups in the pattern.
+  pub fn ncaptures(&self) -> uint {
+    self.ncaps
+  }
+  /// Returns the group number of a named group.
+  pub fn group_index(&self, name: &str) -> Option<uint> {
+    match self.names.borrow().find_equiv(&name) {
+      Some(&index) => Some(index),
       None => None
     }
   }
+  /// Returns the names of the named groups in the pattern, along
+  /// with their group numbers, in the order they appear.
+  pub fn group_names(&self) -> ~[(~str, uint)] {
+    let mut names: ~[(~str, uint)] = self.names.borrow().iter().map(|(name, &index)| {
+      (name.clone(), index)
+    }).collect();
+
+    names.sort_by(|&(_, a), &(_, b)| a.cmp(&b));
+    names
+  }
+  /// Checks if the beginning of the input string 
+  /// contains a match, and returns it.
+  pub fn exec(&self, input: &str) -> Option<Match> {
+    self.execute(input, 0, true)
+  }
+  /// Builds a complete DFA for the pattern up front, which is
+  /// then used for all matching. This is worth it for patterns
+  /// that are used a lot.
+  ///
+  /// Fails if the pattern has features a DFA can't handle (see 
+  /// `dfa_supported`), or if the DFA would need more than 
+  /// `max_states` states.
+  pub fn compile_dfa(&mut self, max_states: uint) -> Result<(), DFACode> {
+    match DFA::new(self.prog, max_states) {
+      Ok(dfa) => {
+        self.full_dfa = Some(dfa);
+        Ok(())
+      }
+      Err(e) => Err(e)
+    }
+  }
+  /// Checks if the pattern occurs anywhere in the input string.
+  ///
+  /// This can be a lot faster than `search`, since it doesn't 
+  /// need to find where the match is.
+  pub fn is_match(&self, input: &str) -> bool {
+    match self.strategy {
+      StrategyAuto | StrategyLazyDFA | StrategyDFA(_) => (),
+      _ => return self.search(input).is_some()
+    }
+
+    match (&self.full_dfa, &self.dfa) {
+      (&Some(ref dfa), _) => dfa.is_match(input),
+      (&None, &Some(ref cache)) => {
+        LazyDFA::new(self.prog, self.ncaps, cache, &self.threads).is_match(input, 0)
+      }
+      (&None, &None) => self.search(input).is_some()
+    }
+  }
   /// Finds the first occurrence of the pattern in the 
   /// input string and returns it.
   pub fn search(&self, input: &str) -> Option<Match> {
-    let len = input.len();
-    let strat = PikeVM::new(self.prog, 0); 
+    self.search_from(input, 0)
+  }
+  /// Finds the first occurrence of the pattern at or after 
+  /// the byte offset `start`.
+  fn search_from(&self, input: &str, start: uint) -> Option<Match> {
+    self.execute(input, start, false)
+  }
+  /// Runs the program with the engine the `Regexp` was built with.
+  fn execute(&self, input: &str, start: uint, anchored: bool) -> Option<Match> {
+    let found = match self.strategy {
+      StrategyAuto => self.execute_auto(input, start, anchored),
+      StrategyPikeVM => {
+        let strat = PikeVM::new(self.prog, self.ncaps, &self.threads); 
+        run_strategy(&strat, input, start, anchored)
+      }
+      StrategyBacktrack => {
+        let strat = Backtrack::new(self.prog, self.ncaps);
+        run_strategy(&strat, input, start, anchored)
+      }
+      StrategyBoundedBacktrack => {
+        if (BoundedBacktrack::fits(self.prog, input.len() - start)) {
+          let strat = BoundedBacktrack::new(self.prog, self.ncaps);
+          run_strategy(&strat, input, start, anchored)
+        } else {
+          let strat = PikeVM::new(self.prog, self.ncaps, &self.threads); 
+          run_strategy(&strat, input, start, anchored)
+        }
+      }
+      StrategyLazyDFA => {
+        let strat = LazyDFA::new(self.prog, self.ncaps, self.dfa.get_ref(),
+                                 &self.threads);
+        run_strategy(&strat, input, start, anchored)
+      }
+      StrategyDFA(_) => {
+        let strat = FullDFA::new(self.full_dfa.get_ref(), self.prog, self.ncaps,
+                                 &self.threads);
+        run_strategy(&strat, input, start, anchored)
+      }
+      StrategyOnePass => {
+        let prog = self.onepass.get_ref();
+
+        if (anchored || (prog.anchored() && start == 0)) {
+          let strat = OnePass::new(prog, self.prog, self.ncaps);
+          run_strategy(&strat, input, start, true)
+        } else {
+          let strat = PikeVM::new(self.prog, self.ncaps, &self.threads); 
+          run_strategy(&strat, input, start, anchored)
+        }
+      }
+    };
+
+    match found {
+      Some(t) => {
+        Some(Match::new(t.start, t.end, input, t.captures,
+                        self.names.clone()))
+      }
+      None => None
+    }
+  }
+  /// Runs the program with an engine that can handle it. A DFA
+  /// built with `compile_dfa` is preferred, then the `OnePass` 
+  /// engine for anchored matches, never for unanchored searches. Short inputs go to the
+  /// `BoundedBacktrack`, and longer ones to the `LazyDFA`, then 
+  /// the `PikeVM`. None of these can do backreferences or atomic
+  /// groups.
+  fn execute_auto(&self, input: &str, start: uint,
+                  anchored: bool) -> Option<Thread> {
+    let onepass = match self.onepass {
+      Some(ref prog) if (anchored || (prog.anchored() && start == 0)) => {
+        Some(prog)
+      }
+      _ => None
+    };
 
-    for start in range(0, len + 1) {
-      match strat.run(input, start) {
-        Some(t) => {
-          return Some(Match::new(start, t.end, input, t.captures))
+    if (self.backtrack) {
+      let strat = Backtrack::new(self.prog, self.ncaps);
+      run_strategy(&strat, input, start, anchored)
+    } else if (self.full_dfa.is_none() && onepass.is_some()) {
+      let strat = OnePass::new(onepass.unwrap(), self.prog, self.ncaps);
+      run_strategy(&strat, input, start, true)
+    } else if (self.full_dfa.is_none() && 
+               BoundedBacktrack::fits(self.prog, input.len() - start)) {
+      let strat = BoundedBacktrack::new(self.prog, self.ncaps);
+      run_strategy(&strat, input, start, anchored)
+    } else {
+      match (&self.full_dfa, &self.dfa) {
+        (&Some(ref dfa), _) => {
+          let strat = FullDFA::new(dfa, self.prog, self.ncaps, &self.threads);
+          run_strategy(&strat, input, start, anchored)
+        }
+        (&None, &Some(ref cache)) => {
+          let strat = LazyDFA::new(self.prog, self.ncaps, cache, &self.threads);
+          run_strategy(&strat, input, start, anchored)
         }
-        None => ()
+        (&None, &None) => {
+          let strat = PikeVM::new(self.prog, self.ncaps, &self.threads); 
+          run_strategy(&strat, input, start, anchored)
+        }
+      }
+    }
+  }
+  /// Returns an iterator over all the non-overlapping matches
+  /// in the input string.
+  pub fn find_iter<'a>(&'a self, input: &'a str) -> FindIter<'a> {
+    FindIter {
+      re: self,
+      input: input,
+      pos: 0,
+      done: false
+    }
+  }
+  /// Finds all the non-overlapping matches in the input string.
+  ///
+  /// Like Python, this returns the matched strings if there are no 
+  /// groups in the pattern, the group if there is one, and all the 
+  /// groups otherwise.
+  pub fn findall(&self, input: &str) -> ~[Found] {
+    self.find_iter(input).map(|ma| {
+      match self.ncaps {
+        0 => FoundStr(ma.matched()),
+        1 => FoundStr(ma.group(0)),
+        _ => FoundGroups(ma.groups())
       }
+    }).collect()
+  }
+  /// Replaces the leftmost non-overlapping matches in the input 
+  /// string with the replacement template. If `count` is 0, all 
+  /// matches are replaced.
+  ///
+  /// The template can refer to groups with `\1` or `\g<1>`, and
+  /// the whole match with `\g<0>`.
+  pub fn sub(&self, repl: &str, input: &str, 
+             count: uint) -> Result<~str, TemplateCode> {
+    match self.subn(repl, input, count) {
+      Ok((s, _)) => Ok(s),
+      Err(e) => Err(e)
     }
+  }
+  /// Same as `sub`, but also returns the number of 
+  /// replacements made.
+  pub fn subn(&self, repl: &str, input: &str, 
+              count: uint) -> Result<(~str, uint), TemplateCode> {
+    match Template::new(repl, self.ncaps, self.names.borrow()) {
+      Ok(template) => {
+        Ok(self.subn_with(input, count, |ma| template.expand(ma)))
+      }
+      Err(e) => Err(e)
+    }
+  }
+  /// Same as `sub`, but the replacement for each match is
+  /// returned by `f`.
+  pub fn sub_with(&self, input: &str, count: uint, 
+                  f: |&Match| -> ~str) -> ~str {
+    let (s, _) = self.subn_with(input, count, f);
+    s
+  }
+  /// Same as `subn`, but the replacement for each match is
+  /// returned by `f`.
+  pub fn subn_with(&self, input: &str, count: uint, 
+                   f: |&Match| -> ~str) -> (~str, uint) {
+    let mut out = ~"";
+    let mut last = 0;
+    let mut n = 0;
+
+    for ma in self.find_iter(input) {
+      if (count != 0 && n >= count) {
+        break;
+      }
 
-    None
+      out.push_str(input.slice(last, ma.start));
+      out.push_str(f(&ma));
+
+      last = ma.end;
+      n += 1;
+    }
+
+    out.push_str(input.slice_from(last));
+
+    (out, n)
+  }
+  /// Splits the input string by occurrences of the pattern. If 
+  /// `maxsplit` is not 0, at most `maxsplit` splits are made, and the
+  /// rest of the string is the last element.
+  ///
+  /// Like Python, if there are capturing groups in the pattern, the 
+  /// groups of each separator are included in the result. Groups 
+  /// that didn't participate in the match are empty.
+  pub fn split(&self, input: &str, maxsplit: uint) -> ~[~str] {
+    let mut pieces = ~[];
+    let mut last = 0;
+    let mut n = 0;
+
+    for ma in self.find_iter(input) {
+      if (maxsplit != 0 && n >= maxsplit) {
+        break;
+      }
+
+      pieces.push(input.slice(last, ma.start).to_owned());
+      pieces.push_all_move(ma.groups());
+
+      last = ma.end;
+      n += 1;
+    }
+
+    pieces.push(input.slice_from(last).to_owned());
+
+    pieces
+  }
+}
+
+/// Runs an `ExecStrategy`, either anchored at `start` or searching 
+/// from it.
+fn run_strategy<S: ExecStrategy>(strat: &S, input: &str, start: uint,
+                                 anchored: bool) -> Option<Thread> {
+  if (anchored) {
+    strat.run(input, start)
+  } else {
+    strat.search(input, start)
+  }
+}
+
+/// Iterator over successive non-overlapping matches.
+/// See `Regexp::find_iter`.
+pub struct FindIter<'a> {
+  priv re: &'a Regexp,
+  priv input: &'a str,
+  priv pos: uint,
+  priv done: bool
+}
+
+impl<'a> Iterator<Match> for FindIter<'a> {
+  fn next(&mut self) -> Option<Match> {
+    if (self.done) {
+      return None
+    }
+
+    match self.re.search_from(self.input, self.pos) {
+      Some(ma) => {
+        // An empty match would be found again at the same 
+        // position, so skip ahead a character.
+        if (ma.end == ma.start) {
+          if (ma.end < self.input.len()) {
+            self.pos = ma.end + self.input.char_at(ma.end).len_utf8_bytes();
+          } else {
+            self.done = true;
+          }
+        } else {
+          self.pos = ma.end;
+        }
+        Some(ma)
+      }
+      None => {
+        self.done = true;
+        None
+      }
+    }
   }
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use flags::{IGNORECASE, MULTILINE, DOTALL, CRLF, NEWLINE_ANY, ASCII, STRICT};
+  use result::{FoundStr, FoundGroups};
 
   #[test]
   fn parse_alternation_ok_test() {
-    assert!(UncompiledRegexp::new("a|b").is_ok());
+    assert!(Regexp::new("a|b").is_ok());
   }
 
   #[test]
   fn parse_concatenation_ok_test() {
-    assert!(UncompiledRegexp::new("a(bc)d").is_ok());
+    assert!(Regexp::new("a(bc)d").is_ok());
   }
 
   #[test]
   fn parse_char_class_ok_test() {
-    assert!(UncompiledRegexp::new("[a-zABC!@#]]]").is_ok());
+    assert!(Regexp::new("[a-zABC!@#]]]").is_ok());
   }
 
   #[test]
   fn parse_capture_ok_test() {
-    assert!(UncompiledRegexp::new("(hel(ABC)ok)").is_ok());
+    assert!(Regexp::new("(hel(ABC)ok)").is_ok());
   }
 
   #[test]
   fn parse_capture_fail_test() {
-    assert!(UncompiledRegexp::new("(hel(ABC)ok").is_err());
+    assert!(Regexp::new("(hel(ABC)ok").is_err());
+  }
+
+  #[test]
+  fn uncompiled_regexp_test() {
+    let re = UncompiledRegexp::new("a(b)").unwrap();
+    assert_eq!(re.search("xab").unwrap().group(0), ~"b");
+    assert!(re.exec("xab").is_none());
+    assert!(UncompiledRegexp::new("a(b").is_err());
+  }
+
+  #[test]
+  fn ncaptures_test() {
+    let re = Regexp::new("(a)(?:b)((c)|d)*").unwrap();
+    assert_eq!(re.ncaptures(), 3);
+  }
+
+  #[test]
+  fn search_leftmost_test() {
+    let re = Regexp::new("b+|ab").unwrap();
+    assert_eq!(re.search("xxabbb").unwrap().matched(), ~"ab");
+  }
+
+  #[test]
+  fn search_multibyte_test() {
+    let re = Regexp::new("c(d)").unwrap();
+    let ma = re.search("ああcd").unwrap();
+    assert_eq!(ma.matched(), ~"cd");
+    assert_eq!(ma.group(0), ~"d");
+  }
+
+  #[test]
+  fn word_boundary_test() {
+    assert_eq!(Regexp::new("\\bx").unwrap().search("ax x").unwrap().start, 3);
+    assert_eq!(Regexp::new("\\Bx").unwrap().search("x ax").unwrap().start, 3);
+  }
+
+  #[test]
+  fn exec_anchored_test() {
+    let re = Regexp::new("ab").unwrap();
+    assert!(re.exec("xab").is_none());
+    assert!(re.exec("abx").is_some());
+  }
+
+  #[test]
+  fn find_iter_test() {
+    let re = Regexp::new("a+").unwrap();
+    let found: ~[~str] = re.find_iter("baaacaa").map(|ma| ma.matched()).collect();
+    assert_eq!(found, ~[~"aaa", ~"aa"]);
+  }
+
+  #[test]
+  fn find_iter_empty_test() {
+    let re = Regexp::new("x*").unwrap();
+    let found: ~[(uint, uint)] = re.find_iter("axb").map(|ma| (ma.start(), ma.end())).collect();
+    assert_eq!(found, ~[(0, 0), (1, 2), (2, 2), (3, 3)]);
+  }
+
+  #[test]
+  fn findall_test() {
+    let re = Regexp::new("[0-9]+").unwrap();
+    assert_eq!(re.findall("a1b22c333"), ~[FoundStr(~"1"), FoundStr(~"22"), 
+               FoundStr(~"333")]);
+
+    let re = Regexp::new("(\\w)=([0-9])").unwrap();
+    assert_eq!(re.findall("a=1, b=2"), ~[FoundGroups(~[~"a", ~"1"]),
+               FoundGroups(~[~"b", ~"2"])]);
+  }
+
+  #[test]
+  fn sub_test() {
+    let re = Regexp::new("(\\w+)@(\\w+)").unwrap();
+    assert_eq!(re.sub("\\2 at \\g<1>", "me@home, you@work", 0), 
+               Ok(~"home at me, work at you"));
+    assert_eq!(re.sub("<\\g<0>>", "me@home, you@work", 1), 
+               Ok(~"<me@home>, you@work"));
+  }
+
+  #[test]
+  fn sub_empty_match_test() {
+    let re = Regexp::new("x*").unwrap();
+    assert_eq!(re.sub("-", "abxd", 0), Ok(~"-a-b--d-"));
+  }
+
+  #[test]
+  fn subn_test() {
+    let re = Regexp::new("a").unwrap();
+    assert_eq!(re.subn("b", "banana", 0), Ok((~"bbnbnb", 3)));
+    assert_eq!(re.subn("b", "banana", 2), Ok((~"bbnbna", 2)));
+  }
+
+  #[test]
+  fn sub_bad_template_test() {
+    let re = Regexp::new("(a)").unwrap();
+    assert!(re.sub("\\2", "a", 0).is_err());
+  }
+
+  #[test]
+  fn sub_with_test() {
+    let re = Regexp::new("[0-9]+").unwrap();
+    let s = re.sub_with("1 + 22", 0, |ma| ma.matched().len().to_str());
+    assert_eq!(s, ~"1 + 2");
+  }
+
+  #[test]
+  fn split_test() {
+    let re = Regexp::new(", *").unwrap();
+    assert_eq!(re.split("a, b,c", 0), ~[~"a", ~"b", ~"c"]);
+    assert_eq!(re.split("a, b,c", 1), ~[~"a", ~"b,c"]);
+  }
+
+  #[test]
+  fn split_groups_test() {
+    let re = Regexp::new("(-)|(\\+)").unwrap();
+    assert_eq!(re.split("1-2+3", 0), ~[~"1", ~"-", ~"", ~"2", ~"", ~"+", ~"3"]);
+  }
+
+  #[test]
+  fn split_empty_match_test() {
+    let re = Regexp::new("x*").unwrap();
+    assert_eq!(re.split("axb", 0), ~[~"", ~"a", ~"", ~"b", ~""]);
+  }
+
+  #[test]
+  fn named_groups_test() {
+    let re = Regexp::new("(?P<key>\\w+)=(\\w+)|(?P<flag>-\\w)").unwrap();
+    assert_eq!(re.group_names(), ~[(~"key", 0), (~"flag", 2)]);
+    assert_eq!(re.group_index("flag"), Some(2));
+    assert_eq!(re.group_index("value"), None);
+
+    let ma = re.search("x=y").unwrap();
+    assert_eq!(ma.group_by_name("key"), Some(~"x"));
+    assert_eq!(ma.group_by_name("flag"), Some(~""));
+    assert_eq!(ma.group_by_name("value"), None);
+
+    let dict = ma.groupdict();
+    assert_eq!(dict.len(), 2);
+    assert_eq!(dict.find_equiv(&("key")), Some(&~"x"));
+  }
+
+  #[test]
+  fn sub_named_group_test() {
+    let re = Regexp::new("(?P<first>\\w+) (?P<last>\\w+)").unwrap();
+    assert_eq!(re.sub("\\g<last>, \\g<first>", "John Smith", 0), 
+               Ok(~"Smith, John"));
+  }
+
+  #[test]
+  fn backref_test() {
+    let re = Regexp::new("(\\w+) \\1").unwrap();
+    let ma = re.search("say hello hello there").unwrap();
+    assert_eq!(ma.matched(), ~"hello hello");
+    assert!(re.search("hello world").is_none());
+  }
+
+  #[test]
+  fn ignorecase_class_operation_test() {
+    let re = Regexp::new("(?i)[a-z&&[K]]").unwrap();
+    assert!(re.search("K").is_some());
+    assert!(re.search("k").is_some());
+    let re = Regexp::new("(?i)[a-z--[k]]").unwrap();
+    assert!(re.search("K").is_none());
+    assert!(re.search("\u212A").is_none());
+    assert!(re.search("j").is_some());
+  }
+
+  #[test]
+  fn zero_repeat_group_test() {
+    // The group isn't compiled, but can still be referred to
+    let re = Regexp::new("(a){0}\\1").unwrap();
+    assert!(re.search("aa").is_none());
+    let re = Regexp::new("(b)(a){0}").unwrap();
+    assert_eq!(re.search("ba").unwrap().matched(), ~"b");
+  }
+
+  #[test]
+  fn zero_repeat_group_api_test() {
+    let re = Regexp::new("(b)(a){0}").unwrap();
+    assert_eq!(re.ncaptures(), 2);
+    assert_eq!(re.search("ba").unwrap().groups(), ~[~"b", ~""]);
+    assert_eq!(re.findall("bb"), ~[FoundGroups(~[~"b", ~""]), 
+                                   FoundGroups(~[~"b", ~""])]);
+    assert_eq!(re.split("abc", 0), ~[~"a", ~"b", ~"", ~"c"]);
+    assert_eq!(re.sub("[\\2]", "abc", 0), Ok(~"a[]c"));
+
+    let re = Regexp::new("(?P<x>a){0}b").unwrap();
+    assert_eq!(re.group_index("x"), Some(0));
+    assert_eq!(re.sub("[\\g<x>]", "b", 0), Ok(~"[]"));
+  }
+
+  #[test]
+  fn backref_split_captures_test() {
+    // The `ab` branch reaches the same split as the `a` branch,
+    // but with a different group
+    let re = Regexp::new("^(a|ab)b*c\\1$").unwrap();
+    assert_eq!(re.search("abcab").unwrap().group(0), ~"ab");
+  }
+
+  #[test]
+  fn named_backref_test() {
+    let re = Regexp::new("(?P<q>['\"]).*?(?P=q)").unwrap();
+    assert_eq!(re.search("x = 'it\"s'").unwrap().matched(), ~"'it\"s'");
+  }
+
+  #[test]
+  fn backref_find_iter_test() {
+    let re = Regexp::new("(.)\\1").unwrap();
+    let found: ~[~str] = re.find_iter("aabccdee").map(|ma| ma.matched()).collect();
+    assert_eq!(found, ~[~"aa", ~"cc", ~"ee"]);
+  }
+
+  #[test]
+  fn lookaround_test() {
+    let re = Regexp::new("(?<=\\$)[0-9]+(?![0-9]|\\.)").unwrap();
+    let found: ~[~str] = re.find_iter("$10 20 $30.5 $40").map(|ma| ma.matched()).collect();
+    assert_eq!(found, ~[~"10", ~"40"]);
+  }
+
+  #[test]
+  fn lookaround_backref_test() {
+    let re = Regexp::new("(\\w)(?=\\1)").unwrap();
+    let ma = re.search("aab").unwrap();
+    assert_eq!((ma.start, ma.end), (0, 1));
+    assert!(Regexp::new("(\\w)(?!\\1)").unwrap().search("aab").unwrap().start == 1);
+  }
+
+  #[test]
+  fn lookbehind_multibyte_test() {
+    let re = Regexp::new("(?<=あ)b").unwrap();
+    assert_eq!(re.search("abあb").unwrap().start(), 5);
+  }
+
+  #[test]
+  fn ignorecase_test() {
+    let re = Regexp::with_flags("hello", IGNORECASE).unwrap();
+    assert_eq!(re.search("say HeLLo").unwrap().matched(), ~"HeLLo");
+  }
+
+  #[test]
+  fn ignorecase_charclass_test() {
+    let re = Regexp::with_flags("^[a-z]+$", IGNORECASE).unwrap();
+    assert!(re.search("AbC").is_some());
+    // Kelvin sign folds to 'k'
+    assert!(re.search("\u212A").is_some());
+
+    let re = Regexp::with_flags("[^a]", IGNORECASE).unwrap();
+    assert!(re.search("A").is_none());
+  }
+
+  #[test]
+  fn ignorecase_charclass_orbit_test() {
+    assert!(Regexp::new("(?i)[a-z]").unwrap().search("\u212A").is_some());
+    assert!(Regexp::new("(?i)[k]").unwrap().search("\u212A").is_some());
+    assert!(Regexp::new("(?i)[s]").unwrap().search("\u017F").is_some());
+    assert!(Regexp::new("(?i)[\u03c3]").unwrap().search("\u03c2").is_some());
+  }
+
+  #[test]
+  fn ignorecase_literal_orbit_test() {
+    // 'k', 'K' and the Kelvin sign are all equal
+    let re = Regexp::with_flags("k", IGNORECASE).unwrap();
+    assert!(re.search("\u212A").is_some());
+    // Sigma has three forms
+    let re = Regexp::with_flags("\u03c3", IGNORECASE).unwrap();
+    assert!(re.search("\u03a3").is_some());
+    assert!(re.search("\u03c2").is_some());
+  }
+
+  #[test]
+  fn ignorecase_backref_test() {
+    let re = Regexp::with_flags("(a+)b\\1", IGNORECASE).unwrap();
+    assert_eq!(re.search("aAbaA").unwrap().matched(), ~"aAbaA");
+  }
+
+  #[test]
+  fn multiline_test() {
+    let re = Regexp::with_flags("^\\w+$", MULTILINE).unwrap();
+    let found: ~[~str] = re.find_iter("one\ntwo\n\nthree").map(|ma| ma.matched()).collect();
+    assert_eq!(found, ~[~"one", ~"two", ~"three"]);
+  }
+
+  #[test]
+  fn multiline_crlf_test() {
+    let re = Regexp::with_flags("^\\w*$", MULTILINE | CRLF).unwrap();
+    let found: ~[~str] = re.find_iter("one\r\ntwo\rthree").map(|ma| ma.matched()).collect();
+    assert_eq!(found, ~[~"one", ~"two", ~"three"]);
+
+    // Without CRLF, the `\r` is part of the line
+    let re = Regexp::with_flags("^\\w+$", MULTILINE).unwrap();
+    assert!(re.search("one\r\n").is_none());
+  }
+
+  #[test]
+  fn absolute_anchors_test() {
+    let re = Regexp::with_flags("\\Aa|b\\z|c\\Z", MULTILINE).unwrap();
+    assert!(re.search("x\na").is_none());
+    assert!(re.search("b\nx").is_none());
+    assert!(re.search("c\n").is_none());
+    assert!(re.search("a\nx").is_some());
+    assert!(re.search("x\nc").is_some());
+  }
+
+  #[test]
+  fn dot_newline_test() {
+    let re = Regexp::new("a.b").unwrap();
+    assert!(re.search("a\nb").is_none());
+    assert!(re.search("a\rb").is_some());
+
+    let re = Regexp::with_flags("a.b", DOTALL).unwrap();
+    assert!(re.search("a\nb").is_some());
+
+    let re = Regexp::with_flags("a.b", CRLF).unwrap();
+    assert!(re.search("a\rb").is_none());
+  }
+
+  #[test]
+  fn newline_any_test() {
+    let re = Regexp::with_flags("a.b", NEWLINE_ANY).unwrap();
+    assert!(re.search("a\u2028b").is_none());
+    assert!(re.search("a\x85b").is_none());
+    assert!(re.search("a\tb").is_some());
+
+    let re = Regexp::with_flags("^\\w+$", MULTILINE | NEWLINE_ANY).unwrap();
+    let found: ~[~str] = re.find_iter("one\u2029two\r\nthree").map(|ma| ma.matched()).collect();
+    assert_eq!(found, ~[~"one", ~"two", ~"three"]);
+  }
+
+  #[test]
+  fn unicode_classes_test() {
+    let re = Regexp::new("\\w+\\s\\d+").unwrap();
+    let ma = re.search("\u00fcber\u00a0\u0663\u0664").unwrap();
+    assert_eq!(ma.matched(), ~"\u00fcber\u00a0\u0663\u0664");
+
+    let re = Regexp::with_flags("\\w+\\s\\d+", ASCII).unwrap();
+    assert!(re.search("\u00fcber\u00a0\u0663\u0664").is_none());
+  }
+
+  #[test]
+  fn ascii_word_boundary_test() {
+    let re = Regexp::new("\\bx").unwrap();
+    assert!(re.search("\u00e9x").is_none());
+
+    let re = Regexp::with_flags("\\bx", ASCII).unwrap();
+    assert!(re.search("\u00e9x").is_some());
+  }
+
+  #[test]
+  fn unicode_property_test() {
+    let re = Regexp::new("\\p{Greek}+").unwrap();
+    assert_eq!(re.search("abc \u03b1\u03b2\u03b3").unwrap().matched(), 
+               ~"\u03b1\u03b2\u03b3");
+
+    let re = Regexp::new("\\pLu\\p{Lu}\\P{L}").unwrap();
+    assert!(re.search("\u00e9uX1").is_some());
+    assert!(re.search("\u00e9uXy").is_none());
+
+    let re = Regexp::new("\\p{sc=Han}").unwrap();
+    assert!(re.search("\u6f22").is_some());
+  }
+
+  #[test]
+  fn posix_class_test() {
+    let re = Regexp::new("[[:alpha:]_][[:alnum:]_]*").unwrap();
+    assert_eq!(re.search("1 foo_2").unwrap().matched(), ~"foo_2");
+
+    let re = Regexp::new("[[:^space:][:blank:]]+").unwrap();
+    assert_eq!(re.search("a b\nc").unwrap().matched(), ~"a b");
+  }
+
+  #[test]
+  fn class_set_operations_test() {
+    let re = Regexp::new("[\\w&&[^\\d]]+").unwrap();
+    assert_eq!(re.search("12ab_3").unwrap().matched(), ~"ab_");
+
+    let re = Regexp::new("[a-z--[aeiou]]+").unwrap();
+    assert_eq!(re.search("quick").unwrap().matched(), ~"q");
+    assert_eq!(re.search("strength").unwrap().matched(), ~"str");
+  }
+
+  #[test]
+  fn escapes_test() {
+    let re = Regexp::new("\\x{263A}\\cI[\\x00-\\x08\\e]").unwrap();
+    assert!(re.search("\u263a\t\x1b").is_some());
+    assert!(re.search("\u263a\t\x1c").is_none());
+  }
+
+  #[test]
+  fn strict_escapes_test() {
+    assert!(Regexp::new("\\y").is_ok());
+    assert!(Regexp::with_flags("\\y", STRICT).is_err());
+    assert!(Regexp::with_flags("\\.\\n", STRICT).is_ok());
+  }
+
+  #[test]
+  fn parse_error_test() {
+    match Regexp::new("(a|b") {
+      Err(e) => assert_eq!(e.to_str(), ~"Parse Error: Expected ')' at position 4"),
+      Ok(_) => fail!()
+    }
+  }
+
+  #[test]
+  fn atomic_group_test() {
+    let re = Regexp::new("a(?>bc|b)c").unwrap();
+    assert!(re.search("abcc").is_some());
+    assert!(re.search("abc").is_none());
+
+    let re = Regexp::new("(?>(a+))b\\1").unwrap();
+    let ma = re.search("xaaaba").unwrap();
+    assert_eq!(ma.matched(), ~"aba");
+    assert_eq!(ma.group(0), ~"a");
+  }
+
+  #[test]
+  fn possessive_test() {
+    assert!(Regexp::new("a*+a").unwrap().search("aaa").is_none());
+    assert!(Regexp::new("a++b").unwrap().search("aaab").is_some());
+    assert!(Regexp::new("a?+a").unwrap().search("a").is_none());
+    assert!(Regexp::new("a{1,2}+a").unwrap().search("aaa").is_some());
+    assert!(Regexp::new("a{1,2}+a").unwrap().search("aa").is_none());
+
+    let re = Regexp::new("\"[^\"]*+\"").unwrap();
+    assert_eq!(re.search("say \"hi\" now").unwrap().matched(), ~"\"hi\"");
+  }
+
+  #[test]
+  fn is_match_test() {
+    let re = Regexp::new("[a-z]+@[a-z]+\\.com").unwrap();
+    assert!(re.is_match("mail bob@example.com now"));
+    assert!(!re.is_match("mail bob@example.org now"));
+    assert!(Regexp::new("(\\w)\\1").unwrap().is_match("abba"));
+    assert!(!Regexp::new("^b").unwrap().is_match("ab"));
+  }
+
+  #[test]
+  fn dfa_capture_pass_test() {
+    let re = Regexp::new("(a$)|(a)").unwrap();
+    let ma = re.search("ab").unwrap();
+    assert_eq!(ma.group(1), ~"a");
+    assert_eq!(ma.group(0), ~"");
+
+    let re = Regexp::new("(\\d+)-(\\d+)$").unwrap();
+    let ma = re.search("12-34 56-78").unwrap();
+    assert_eq!(ma.groups(), ~[~"56", ~"78"]);
+  }
+
+  #[test]
+  fn compile_dfa_test() {
+    let mut re = Regexp::new("^(\\d{4})-(\\d{2})-(\\d{2})$").unwrap();
+    assert!(re.compile_dfa(1000).is_ok());
+    assert!(re.is_match("2014-01-31"));
+    assert!(!re.is_match("2014-01-31x"));
+    assert_eq!(re.exec("2014-01-31").unwrap().groups(), ~[~"2014", ~"01", ~"31"]);
+
+    let mut re = Regexp::new("(\\w+) \\1").unwrap();
+    assert!(re.compile_dfa(1000).is_err());
+    let mut re = Regexp::new("(a|b)*a(a|b)(a|b)(a|b)").unwrap();
+    assert!(re.compile_dfa(4).is_err());
+    assert!(re.is_match("xabbb"));
+  }
+
+  #[test]
+  fn bounded_backtrack_test() {
+    let re = Regexp::new("(\\w+)@(\\w+)\\.(com|org)").unwrap();
+    let ma = re.search("mail: bob@example.org").unwrap();
+    assert_eq!(ma.groups(), ~[~"bob", ~"example", ~"org"]);
+
+    // Too long for the bitset, so another engine gets the same match
+    let long = "x".repeat(100000) + "mail: bob@example.org";
+    let ma = re.search(long.as_slice()).unwrap();
+    assert_eq!(ma.groups(), ~[~"bob", ~"example", ~"org"]);
+
+    let re = Regexp::new("(a*)*b|(a+)c").unwrap();
+    let ma = re.search("aaac").unwrap();
+    assert_eq!(ma.group(1), ~"aaa");
+    assert!(Regexp::new("(?<=a)b(?=c)").unwrap().search("abc").is_some());
+  }
+
+  #[test]
+  fn onepass_test() {
+    let re = Regexp::new("(\\w+)=(\\d*);?").unwrap();
+    let ma = re.exec("size=42;rest").unwrap();
+    assert_eq!(ma.groups(), ~[~"size", ~"42"]);
+    assert!(re.exec("=42").is_none());
+
+    let re = Regexp::new("^(\\d+)(?:\\.(\\d+))?$").unwrap();
+    assert_eq!(re.search("3.14").unwrap().groups(), ~[~"3", ~"14"]);
+    assert_eq!(re.search("3").unwrap().groups(), ~[~"3", ~""]);
+    assert!(re.search("3.").is_none());
+  }
+
+  #[test]
+  fn strategy_test() {
+    let strategies = [StrategyAuto, StrategyPikeVM, StrategyBacktrack,
+                      StrategyBoundedBacktrack, StrategyLazyDFA, 
+                      StrategyDFA(1000), StrategyOnePass];
+
+    for &strategy in strategies.iter() {
+      let re = Regexp::build("(\\d+)-(\\d+)").strategy(strategy).build().unwrap();
+      assert_eq!(re.strategy(), strategy);
+      assert_eq!(re.search("ab 12-34").unwrap().groups(), ~[~"12", ~"34"]);
+      assert_eq!(re.exec("12-34x").unwrap().matched(), ~"12-34");
+      assert!(re.is_match("1-2"));
+      assert!(!re.is_match("1-"));
+    }
+  }
+
+  #[test]
+  fn strategy_onepass_search_test() {
+    // Unanchored searches, and searches past the start, go to
+    // the PikeVM
+    for &strategy in [StrategyAuto, StrategyOnePass].iter() {
+      let re = Regexp::build("^a").strategy(strategy).build().unwrap();
+      assert_eq!(re.findall("aaa"), ~[FoundStr(~"a")]);
+
+      let re = Regexp::build("a(b)").strategy(strategy).build().unwrap();
+      assert_eq!(re.findall("ab xab"), ~[FoundStr(~"b"), FoundStr(~"b")]);
+    }
+  }
+
+  #[test]
+  fn strategy_bounded_backtrack_long_test() {
+    let re = Regexp::build("(\\d+)-(\\d+)").strategy(StrategyBoundedBacktrack)
+                                          .build().unwrap();
+    // Too long for the bitset, so it runs on the PikeVM
+    let long = "x".repeat(1000000) + "12-34";
+    assert_eq!(re.search(long.as_slice()).unwrap().groups(), ~[~"12", ~"34"]);
+  }
+
+  #[test]
+  fn strategy_unsupported_test() {
+    let re = Regexp::build("(a)\\1").strategy(StrategyPikeVM).build();
+    assert_eq!(re.err().unwrap().to_str(), ~"Build Error: Engine can't run the pattern");
+    assert!(Regexp::build("(a)\\1").strategy(StrategyBacktrack).build().is_ok());
+    assert!(Regexp::build("a*a").strategy(StrategyOnePass).build().is_err());
+    assert!(Regexp::build("a\\b").strategy(StrategyLazyDFA).build().is_err());
+    assert!(Regexp::build("(a|b)*a(a|b)(a|b)").strategy(StrategyDFA(4)).build().is_err());
+    assert!(Regexp::build("(a").build().is_err());
+
+    let re = Regexp::build("A").flags(IGNORECASE).strategy(StrategyDFA(100)).build().unwrap();
+    assert!(re.is_match("a"));
+  }
+
+  #[test]
+  fn reuse_test() {
+    let re = Regexp::new("a(b+)c").unwrap();
+    for _ in range(0, 3) {
+      assert_eq!(re.search("xxabbbc").unwrap().group(0), ~"bbb");
+      assert!(re.search("xxac").is_none());
+    }
   }
 }
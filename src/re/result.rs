@@ -1,21 +1,28 @@
 // a match result
 
+use std::rc::Rc;
+use std::hashmap::HashMap;
+
 #[deriving(Clone)]
 pub struct Match {
   start: uint,
   end: uint,
   input: ~str,
-  groups: ~[Option<CapturingGroup>]
+  groups: ~[Option<CapturingGroup>],
+  // shared with the `Regexp` that produced the match
+  names: Rc<HashMap<~str, uint>>
 }
 
 impl Match {
   pub fn new(start: uint, end: uint, input: &str, 
-         groups: ~[Option<CapturingGroup>]) -> Match {
+         groups: ~[Option<CapturingGroup>],
+         names: Rc<HashMap<~str, uint>>) -> Match {
     Match {
       start: start,
       end: end,
       input: input.to_owned(),
-      groups: groups
+      groups: groups,
+      names: names
     }
   }
 }
@@ -42,6 +49,44 @@ impl Match {
   }
 }
 
+impl Match {
+  /// Byte offset of the start of the match.
+  pub fn start(&self) -> uint {
+    self.start
+  }
+  /// Byte offset of the end of the match.
+  pub fn end(&self) -> uint {
+    self.end
+  }
+  /// The number of capturing groups in the match.
+  pub fn ngroups(&self) -> uint {
+    self.groups.len()
+  }
+  /// Returns every group as a string. Groups that 
+  /// didn't participate in the match are empty.
+  pub fn groups(&self) -> ~[~str] {
+    range(0, self.groups.len()).map(|i| self.group(i)).collect()
+  }
+  /// Returns the group with the given name, or None if there is
+  /// no group with that name in the pattern.
+  pub fn group_by_name(&self, name: &str) -> Option<~str> {
+    match self.names.borrow().find_equiv(&name) {
+      Some(&index) => Some(self.group(index)),
+      None => None
+    }
+  }
+  /// Returns all the named groups, keyed by name.
+  pub fn groupdict(&self) -> HashMap<~str, ~str> {
+    let mut dict = HashMap::new();
+
+    for (name, &index) in self.names.borrow().iter() {
+      dict.insert(name.clone(), self.group(index));
+    }
+
+    dict
+  }
+}
+
 impl ToStr for Match {
   fn to_str(&self) -> ~str {
     format!("<Match str: {:s} groups: {:u}>", self.matched(),
@@ -66,3 +111,14 @@ impl CapturingGroup {
   }
 }
 
+
+/// An item found by `findall`. 
+///
+/// Like Python, this is the matched string when the pattern has 
+/// no groups, the group when there is one, and all the groups 
+/// when there are more.
+#[deriving(Clone, Eq, ToStr)]
+pub enum Found {
+  FoundStr(~str),
+  FoundGroups(~[~str])
+}
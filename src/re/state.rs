@@ -2,19 +2,31 @@ pub struct State<'a> {
   priv input: &'a str,
   priv cursor: uint,
   priv len: uint,
+  priv token: uint,
   ncaptures: uint,
-  nparens: uint
+  nparens: uint,
+  names: ~[(~str, uint)],
+  open_groups: ~[uint],
+  flags: uint
 }
 
 impl<'a> State<'a> {
   #[inline]
   pub fn new(input: &'a str) -> State<'a> {
+    State::with_flags(input, 0)
+  }
+  #[inline]
+  pub fn with_flags(input: &'a str, flags: uint) -> State<'a> {
     State {
       input: input,
       cursor: 0,
       len: input.char_len(),
+      token: 0,
       ncaptures: 0,
-      nparens: 0
+      nparens: 0,
+      names: ~[],
+      open_groups: ~[],
+      flags: flags
     }
   }
 }
@@ -60,6 +72,23 @@ impl<'a> State<'a> {
       None
     }
   }
+  /// The byte offset of the cursor.
+  pub fn cursor(&self) -> uint {
+    self.cursor
+  }
+  /// Marks the cursor as the start of the token being
+  /// parsed, so errors can point at it.
+  pub fn mark_token(&mut self) {
+    self.token = self.cursor;
+  }
+  /// The byte offset of the start of the last marked token.
+  pub fn token_start(&self) -> uint {
+    self.token
+  }
+  /// Goes back to an earlier token start, from `token_start`.
+  pub fn restore_token(&mut self, token: uint) {
+    self.token = token;
+  }
   pub fn isEnd(&self) -> bool {
     self.len == 0
   }
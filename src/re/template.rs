@@ -0,0 +1,276 @@
+use std::char;
+use std::hashmap::HashMap;
+use state::State;
+use result::Match;
+use error::TemplateError::*;
+
+/// A piece of a replacement template.
+#[deriving(ToStr)]
+enum Piece {
+  PieceLiteral(~str),
+  // Following Python, 0 refers to the whole match, and 1 to
+  // the first capturing group
+  PieceGroup(uint)
+}
+
+/// A parsed replacement template, used by `sub` and `subn`.
+pub struct Template {
+  priv pieces: ~[Piece]
+}
+
+impl Template {
+  /// Parses a Python style replacement template.
+  ///
+  /// Supports:
+  ///
+  /// * `\1` - `\99` = Numbered group
+  /// * `\g<1>` = Numbered group (`\g<0>` is the whole match)
+  /// * `\g<name>` = Named group
+  /// * `\n`, `\t`, ... = Escaped characters
+  ///
+  /// # Arguments
+  ///
+  /// * s - The template string
+  /// * ncaps - The number of capturing groups in the pattern
+  /// * names - The named groups in the pattern
+  pub fn new(s: &str, ncaps: uint, 
+             names: &HashMap<~str, uint>) -> Result<Template, TemplateCode> {
+    let mut p = State::new(s);
+    let mut pieces = ~[];
+    let mut buf = ~"";
+
+    // Pushes a group reference, flushing any literal text
+    // before it
+    macro_rules! push_group(
+      ($n: expr) => (
+        {
+          if (buf.len() > 0) {
+            pieces.push(PieceLiteral(buf.clone()));
+            buf.clear();
+          }
+          pieces.push(PieceGroup($n));
+        }
+      );
+    )
+
+    loop {
+      match p.current() {
+        Some('\\') => {
+          p.next();
+          match p.current() {
+            Some('g') => {
+              p.next();
+              match parse_group_ref(&mut p, ncaps, names) {
+                Ok(n) => push_group!(n),
+                Err(e) => return Err(e)
+              }
+            }
+            // An octal escape
+            Some('0') => {
+              let mut n = 0;
+              let mut len = 0;
+
+              while (len < 3) {
+                match p.current() {
+                  Some(d) if d >= '0' && d <= '7' => {
+                    n = n * 8 + char::to_digit(d, 8).unwrap();
+                    len += 1;
+                    p.next();
+                  }
+                  _ => break
+                }
+              }
+
+              buf.push_char(char::from_u32(n as u32).unwrap());
+            }
+            Some(d) if d.is_digit() => {
+              let mut n = char::to_digit(d, 10).unwrap();
+
+              p.next();
+
+              match p.current() {
+                Some(d) if d.is_digit() => {
+                  n = n * 10 + char::to_digit(d, 10).unwrap();
+                  p.next();
+                }
+                _ => ()
+              }
+
+              if (n > ncaps) {
+                return Err(TemplateInvalidGroupReference)
+              }
+
+              push_group!(n);
+            }
+            Some(c) => {
+              p.next();
+
+              match c {
+                'n'  => buf.push_char('\n'),
+                't'  => buf.push_char('\t'),
+                'r'  => buf.push_char('\r'),
+                'f'  => buf.push_char('\x0c'),
+                'v'  => buf.push_char('\x0b'),
+                'a'  => buf.push_char('\x07'),
+                'b'  => buf.push_char('\x08'),
+                '\\' => buf.push_char('\\'),
+                // Like Python, letters are saved for escapes 
+                // that might be added later
+                'a'..'z' | 'A'..'Z' => return Err(TemplateBadEscape),
+                // Anything else is left alone
+                _    => {
+                  buf.push_char('\\');
+                  buf.push_char(c);
+                }
+              }
+            }
+            None => return Err(TemplateIncompleteEscapeSeq)
+          }
+        }
+        Some(c) => {
+          p.next();
+          buf.push_char(c);
+        }
+        None => break
+      }
+    }
+
+    if (buf.len() > 0) {
+      pieces.push(PieceLiteral(buf));
+    }
+
+    Ok(Template { pieces: pieces })
+  }
+}
+
+impl Template {
+  /// Fills in the template with the groups from a match.
+  pub fn expand(&self, ma: &Match) -> ~str {
+    let mut out = ~"";
+
+    for piece in self.pieces.iter() {
+      match *piece {
+        PieceLiteral(ref s) => out.push_str(*s),
+        PieceGroup(0) => out.push_str(ma.matched()),
+        PieceGroup(n) => out.push_str(ma.group(n - 1))
+      }
+    }
+
+    out
+  }
+}
+
+/// Parses the `<...>` following a `\g` in a template, and
+/// returns the group it refers to.
+///
+/// # Arguments
+///
+/// * p - The current state of parsing
+/// * ncaps - The number of capturing groups in the pattern
+/// * names - The named groups in the pattern
+fn parse_group_ref(p: &mut State, ncaps: uint, 
+                   names: &HashMap<~str, uint>) -> Result<uint, TemplateCode> {
+  match p.current() {
+    Some('<') => p.next(),
+    _ => return Err(TemplateExpectedOpeningAngle)
+  }
+
+  let mut name = ~"";
+
+  loop {
+    match p.current() {
+      Some('>') => {
+        p.next();
+        break;
+      }
+      Some(c) => {
+        p.next();
+        name.push_char(c);
+      }
+      None => return Err(TemplateExpectedClosingAngle)
+    }
+  }
+
+  if (name.len() == 0) {
+    return Err(TemplateEmptyGroupName)
+  }
+
+  match from_str::<uint>(name) {
+    Some(n) if n <= ncaps => Ok(n),
+    Some(_) => Err(TemplateInvalidGroupReference),
+    None => {
+      match names.find(&name) {
+        Some(&index) => Ok(index + 1),
+        None => Err(TemplateUnknownGroupName)
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod template_tests {
+  use super::*;
+  use std::hashmap::HashMap;
+  use error::TemplateError::*;
+
+  macro_rules! test_template(
+    ($input: expr, $ncaps: expr, $expect: pat) => (
+      {
+        let mut names = HashMap::new();
+        names.insert(~"name", 0u);
+
+        let ok = match Template::new($input, $ncaps, &names) {
+          $expect => true,
+          _ => false
+        };
+        assert!(ok);
+      }
+    );
+  )
+
+  #[test]
+  fn template_literal_ok() {
+    test_template!("abc\\n\\&", 0, Ok(_));
+  }
+
+  #[test]
+  fn template_group_ok() {
+    test_template!("\\1-\\g<2>-\\g<0>", 2, Ok(_));
+  }
+
+  #[test]
+  fn template_bad_escape_err() {
+    test_template!("a\\qb", 0, Err(TemplateBadEscape));
+    test_template!("\\b\\&", 0, Ok(_));
+  }
+
+  #[test]
+  fn template_invalid_group_err() {
+    test_template!("\\3", 2, Err(TemplateInvalidGroupReference));
+  }
+
+  #[test]
+  fn template_unclosed_name_err() {
+    test_template!("\\g<1", 1, Err(TemplateExpectedClosingAngle));
+  }
+
+  #[test]
+  fn template_named_group_ok() {
+    test_template!("\\g<name>", 1, Ok(_));
+  }
+
+  #[test]
+  fn template_unknown_name_err() {
+    test_template!("\\g<other>", 1, Err(TemplateUnknownGroupName));
+  }
+
+  #[test]
+  fn template_empty_name_err() {
+    test_template!("\\g<>", 1, Err(TemplateEmptyGroupName));
+  }
+
+  #[test]
+  fn template_trailing_backslash_err() {
+    test_template!("a\\", 0, Err(TemplateIncompleteEscapeSeq));
+  }
+}
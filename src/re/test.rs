@@ -2,13 +2,13 @@
 // This is an auto-generated test file
 // Generated by src/test/test_generator.py
 //
-// Last Modified: January 05 2014 02:40AM
+// Last Modified: October 18 2026 06:04AM
 
 macro_rules! run_tests(
   ($re: expr, $input: expr, $matched: expr, $ident: expr, 
    $expect: pat, $groups: expr) => (
     {
-      let re = match UncompiledRegexp::new($re) {
+      let re = match Regexp::new($re) {
         Ok(regex) => regex,
         Err(e) => fail!(e)
       };
@@ -48,7 +48,7 @@ macro_rules! run_tests(
 
 #[cfg(test)]
 mod python_tests {
-  use regexp::UncompiledRegexp;
+  use regexp::Regexp;
 
   // Tests start here
   
@@ -764,4 +764,309 @@ mod python_tests {
     run_tests!("a{5,}", "aaaa", ~"", "140", None, &'static [])
   }
 
+  #[test]
+  fn test_case_ident_141() {
+    run_tests!("a(?=b)", "acab", ~"a", "141", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_142() {
+    run_tests!("a(?!b)", "abac", ~"a", "142", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_143() {
+    run_tests!("a(?!b)", "ab", ~"", "143", None, &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_144() {
+    run_tests!("(?<=a)b", "cbab", ~"b", "144", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_145() {
+    run_tests!("(?<!a)b", "abcb", ~"b", "145", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_146() {
+    run_tests!("(?<!a)b", "ab", ~"", "146", None, &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_147() {
+    run_tests!("(?<=(a)|(b))c", "bc", ~"c", "147", Some(_), &'static ["", "b"])
+  }
+
+  #[test]
+  fn test_case_ident_148() {
+    run_tests!("(\\w)(?=(\\w))", "x y", ~"", "148", None, &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_149() {
+    run_tests!("(?=(a+))a*b\\1", "baaabac", ~"aba", "149", Some(_), &'static ["a"])
+  }
+
+  #[test]
+  fn test_case_ident_150() {
+    run_tests!("(?i)abc", "xABCy", ~"ABC", "150", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_151() {
+    run_tests!("a(?i:b)c", "aBc", ~"aBc", "151", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_152() {
+    run_tests!("a(?i:b)c", "aBC", ~"", "152", None, &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_153() {
+    run_tests!("(?i)a(?-i:b)c", "AbC", ~"AbC", "153", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_154() {
+    run_tests!("(?i)a(?-i:b)c", "ABC", ~"", "154", None, &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_155() {
+    run_tests!("(?i)[^a]", "A", ~"", "155", None, &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_156() {
+    run_tests!("(?i)(a)\\1", "aA", ~"aA", "156", Some(_), &'static ["a"])
+  }
+
+  #[test]
+  fn test_case_ident_157() {
+    run_tests!("(?m)^b", "a\nb", ~"b", "157", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_158() {
+    run_tests!("(?m)a$", "a\nb", ~"a", "158", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_159() {
+    run_tests!("^b", "a\nb", ~"", "159", None, &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_160() {
+    run_tests!("(?m)^$", "a\n\nb", ~"", "160", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_161() {
+    run_tests!("(?m)\\Ab", "a\nb", ~"", "161", None, &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_162() {
+    run_tests!("(?m)a\\Z", "a\nb", ~"", "162", None, &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_163() {
+    run_tests!("\\Aab\\Z", "ab", ~"ab", "163", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_164() {
+    run_tests!("a.c", "a\nc", ~"", "164", None, &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_165() {
+    run_tests!("(?s)a.c", "a\nc", ~"a\nc", "165", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_166() {
+    run_tests!("a(?s:.)c.", "a\nc\n", ~"", "166", None, &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_167() {
+    run_tests!(".+", "ab\ncd", ~"ab", "167", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_168() {
+    run_tests!("a(?#comment)b", "ab", ~"ab", "168", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_169() {
+    run_tests!("(?x) a b # c\n c", "abc", ~"abc", "169", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_170() {
+    run_tests!("(?x)a\\ b", "a b", ~"a b", "170", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_171() {
+    run_tests!("(?x)a b", "a b", ~"", "171", None, &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_172() {
+    run_tests!("(?x)[ #]+", "x #", ~" #", "172", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_173() {
+    run_tests!("(?x)a +", "aaa", ~"aaa", "173", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_174() {
+    run_tests!("\\w+", "héllo", ~"héllo", "174", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_175() {
+    run_tests!("\\d+", "x٣٤", ~"٣٤", "175", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_176() {
+    run_tests!("\\bé\\b", "-é-", ~"é", "176", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_177() {
+    run_tests!("\\W", "é!", ~"!", "177", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_178() {
+    run_tests!("(?a)\\w+", "héllo", ~"h", "178", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_179() {
+    run_tests!("(?a)\\bl", "él", ~"l", "179", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_180() {
+    run_tests!("\\Bl", "él", ~"l", "180", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_181() {
+    run_tests!("[\\d]+", "ab12", ~"12", "181", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_182() {
+    run_tests!("[^\\W\\d]+", "12ab3", ~"ab", "182", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_183() {
+    run_tests!("[\\s,]+", "a , b", ~" , ", "183", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_184() {
+    run_tests!("[a\\-z]+", "b-az", ~"-az", "184", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_185() {
+    run_tests!("\\n", "a\nb", ~"\n", "185", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_186() {
+    run_tests!("\\x41B\\U00000043", "xABC", ~"ABC", "186", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_187() {
+    run_tests!("[\\x30-\\x39]+", "ab123", ~"123", "187", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_188() {
+    run_tests!("\\101", "A", ~"A", "188", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_189() {
+    run_tests!("[\\t\\n]+", "a\t\nb", ~"\t\n", "189", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_190() {
+    run_tests!("\\u00e9", "café", ~"é", "190", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_191() {
+    run_tests!("a(?>bc|b)c", "abcc", ~"abcc", "191", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_192() {
+    run_tests!("a(?>bc|b)c", "abc", ~"", "192", None, &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_193() {
+    run_tests!("(?>(a+))b\\1", "xaaaba", ~"aba", "193", Some(_), &'static ["a"])
+  }
+
+  #[test]
+  fn test_case_ident_194() {
+    run_tests!("a*+a", "aaa", ~"", "194", None, &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_195() {
+    run_tests!("a++b", "aaab", ~"aaab", "195", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_196() {
+    run_tests!("a{1,2}+a", "aaa", ~"aaa", "196", Some(_), &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_197() {
+    run_tests!("a{1,2}+a", "aa", ~"", "197", None, &'static [])
+  }
+
+  #[test]
+  fn test_case_ident_198() {
+    run_tests!("(abc)\\1", "abcabc", ~"abcabc", "198", Some(_), &'static ["abc"])
+  }
+
+  #[test]
+  fn test_case_ident_199() {
+    run_tests!("([a-c]*)\\1", "abcabc", ~"abcabc", "199", Some(_), &'static ["abc"])
+  }
+
+  #[test]
+  fn test_case_ident_200() {
+    run_tests!("(a+).\\1$", "aaaaa", ~"aaaaa", "200", Some(_), &'static ["aa"])
+  }
+
+  #[test]
+  fn test_case_ident_201() {
+    run_tests!("^(a+).\\1$", "aaaa", ~"", "201", None, &'static [])
+  }
+
 }
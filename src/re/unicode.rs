@@ -0,0 +1,17087 @@
+// This is an auto-generated file
+// Generated by src/etc/unicode.py
+//
+// Last Modified: October 18 2026 05:24AM
+
+/// Simple case folding orbits. Each character maps to the next character
+/// that is equal to it under simple case folding, and the last maps back
+/// to the first.
+pub static CaseFoldOrbit: &'static [(char, char)] = &[
+  ('\u0041', '\u0061'),
+  ('\u0042', '\u0062'),
+  ('\u0043', '\u0063'),
+  ('\u0044', '\u0064'),
+  ('\u0045', '\u0065'),
+  ('\u0046', '\u0066'),
+  ('\u0047', '\u0067'),
+  ('\u0048', '\u0068'),
+  ('\u0049', '\u0069'),
+  ('\u004A', '\u006A'),
+  ('\u004B', '\u006B'),
+  ('\u004C', '\u006C'),
+  ('\u004D', '\u006D'),
+  ('\u004E', '\u006E'),
+  ('\u004F', '\u006F'),
+  ('\u0050', '\u0070'),
+  ('\u0051', '\u0071'),
+  ('\u0052', '\u0072'),
+  ('\u0053', '\u0073'),
+  ('\u0054', '\u0074'),
+  ('\u0055', '\u0075'),
+  ('\u0056', '\u0076'),
+  ('\u0057', '\u0077'),
+  ('\u0058', '\u0078'),
+  ('\u0059', '\u0079'),
+  ('\u005A', '\u007A'),
+  ('\u0061', '\u0041'),
+  ('\u0062', '\u0042'),
+  ('\u0063', '\u0043'),
+  ('\u0064', '\u0044'),
+  ('\u0065', '\u0045'),
+  ('\u0066', '\u0046'),
+  ('\u0067', '\u0047'),
+  ('\u0068', '\u0048'),
+  ('\u0069', '\u0049'),
+  ('\u006A', '\u004A'),
+  ('\u006B', '\u212A'),
+  ('\u006C', '\u004C'),
+  ('\u006D', '\u004D'),
+  ('\u006E', '\u004E'),
+  ('\u006F', '\u004F'),
+  ('\u0070', '\u0050'),
+  ('\u0071', '\u0051'),
+  ('\u0072', '\u0052'),
+  ('\u0073', '\u017F'),
+  ('\u0074', '\u0054'),
+  ('\u0075', '\u0055'),
+  ('\u0076', '\u0056'),
+  ('\u0077', '\u0057'),
+  ('\u0078', '\u0058'),
+  ('\u0079', '\u0059'),
+  ('\u007A', '\u005A'),
+  ('\u00B5', '\u039C'),
+  ('\u00C0', '\u00E0'),
+  ('\u00C1', '\u00E1'),
+  ('\u00C2', '\u00E2'),
+  ('\u00C3', '\u00E3'),
+  ('\u00C4', '\u00E4'),
+  ('\u00C5', '\u00E5'),
+  ('\u00C6', '\u00E6'),
+  ('\u00C7', '\u00E7'),
+  ('\u00C8', '\u00E8'),
+  ('\u00C9', '\u00E9'),
+  ('\u00CA', '\u00EA'),
+  ('\u00CB', '\u00EB'),
+  ('\u00CC', '\u00EC'),
+  ('\u00CD', '\u00ED'),
+  ('\u00CE', '\u00EE'),
+  ('\u00CF', '\u00EF'),
+  ('\u00D0', '\u00F0'),
+  ('\u00D1', '\u00F1'),
+  ('\u00D2', '\u00F2'),
+  ('\u00D3', '\u00F3'),
+  ('\u00D4', '\u00F4'),
+  ('\u00D5', '\u00F5'),
+  ('\u00D6', '\u00F6'),
+  ('\u00D8', '\u00F8'),
+  ('\u00D9', '\u00F9'),
+  ('\u00DA', '\u00FA'),
+  ('\u00DB', '\u00FB'),
+  ('\u00DC', '\u00FC'),
+  ('\u00DD', '\u00FD'),
+  ('\u00DE', '\u00FE'),
+  ('\u00DF', '\u1E9E'),
+  ('\u00E0', '\u00C0'),
+  ('\u00E1', '\u00C1'),
+  ('\u00E2', '\u00C2'),
+  ('\u00E3', '\u00C3'),
+  ('\u00E4', '\u00C4'),
+  ('\u00E5', '\u212B'),
+  ('\u00E6', '\u00C6'),
+  ('\u00E7', '\u00C7'),
+  ('\u00E8', '\u00C8'),
+  ('\u00E9', '\u00C9'),
+  ('\u00EA', '\u00CA'),
+  ('\u00EB', '\u00CB'),
+  ('\u00EC', '\u00CC'),
+  ('\u00ED', '\u00CD'),
+  ('\u00EE', '\u00CE'),
+  ('\u00EF', '\u00CF'),
+  ('\u00F0', '\u00D0'),
+  ('\u00F1', '\u00D1'),
+  ('\u00F2', '\u00D2'),
+  ('\u00F3', '\u00D3'),
+  ('\u00F4', '\u00D4'),
+  ('\u00F5', '\u00D5'),
+  ('\u00F6', '\u00D6'),
+  ('\u00F8', '\u00D8'),
+  ('\u00F9', '\u00D9'),
+  ('\u00FA', '\u00DA'),
+  ('\u00FB', '\u00DB'),
+  ('\u00FC', '\u00DC'),
+  ('\u00FD', '\u00DD'),
+  ('\u00FE', '\u00DE'),
+  ('\u00FF', '\u0178'),
+  ('\u0100', '\u0101'),
+  ('\u0101', '\u0100'),
+  ('\u0102', '\u0103'),
+  ('\u0103', '\u0102'),
+  ('\u0104', '\u0105'),
+  ('\u0105', '\u0104'),
+  ('\u0106', '\u0107'),
+  ('\u0107', '\u0106'),
+  ('\u0108', '\u0109'),
+  ('\u0109', '\u0108'),
+  ('\u010A', '\u010B'),
+  ('\u010B', '\u010A'),
+  ('\u010C', '\u010D'),
+  ('\u010D', '\u010C'),
+  ('\u010E', '\u010F'),
+  ('\u010F', '\u010E'),
+  ('\u0110', '\u0111'),
+  ('\u0111', '\u0110'),
+  ('\u0112', '\u0113'),
+  ('\u0113', '\u0112'),
+  ('\u0114', '\u0115'),
+  ('\u0115', '\u0114'),
+  ('\u0116', '\u0117'),
+  ('\u0117', '\u0116'),
+  ('\u0118', '\u0119'),
+  ('\u0119', '\u0118'),
+  ('\u011A', '\u011B'),
+  ('\u011B', '\u011A'),
+  ('\u011C', '\u011D'),
+  ('\u011D', '\u011C'),
+  ('\u011E', '\u011F'),
+  ('\u011F', '\u011E'),
+  ('\u0120', '\u0121'),
+  ('\u0121', '\u0120'),
+  ('\u0122', '\u0123'),
+  ('\u0123', '\u0122'),
+  ('\u0124', '\u0125'),
+  ('\u0125', '\u0124'),
+  ('\u0126', '\u0127'),
+  ('\u0127', '\u0126'),
+  ('\u0128', '\u0129'),
+  ('\u0129', '\u0128'),
+  ('\u012A', '\u012B'),
+  ('\u012B', '\u012A'),
+  ('\u012C', '\u012D'),
+  ('\u012D', '\u012C'),
+  ('\u012E', '\u012F'),
+  ('\u012F', '\u012E'),
+  ('\u0132', '\u0133'),
+  ('\u0133', '\u0132'),
+  ('\u0134', '\u0135'),
+  ('\u0135', '\u0134'),
+  ('\u0136', '\u0137'),
+  ('\u0137', '\u0136'),
+  ('\u0139', '\u013A'),
+  ('\u013A', '\u0139'),
+  ('\u013B', '\u013C'),
+  ('\u013C', '\u013B'),
+  ('\u013D', '\u013E'),
+  ('\u013E', '\u013D'),
+  ('\u013F', '\u0140'),
+  ('\u0140', '\u013F'),
+  ('\u0141', '\u0142'),
+  ('\u0142', '\u0141'),
+  ('\u0143', '\u0144'),
+  ('\u0144', '\u0143'),
+  ('\u0145', '\u0146'),
+  ('\u0146', '\u0145'),
+  ('\u0147', '\u0148'),
+  ('\u0148', '\u0147'),
+  ('\u014A', '\u014B'),
+  ('\u014B', '\u014A'),
+  ('\u014C', '\u014D'),
+  ('\u014D', '\u014C'),
+  ('\u014E', '\u014F'),
+  ('\u014F', '\u014E'),
+  ('\u0150', '\u0151'),
+  ('\u0151', '\u0150'),
+  ('\u0152', '\u0153'),
+  ('\u0153', '\u0152'),
+  ('\u0154', '\u0155'),
+  ('\u0155', '\u0154'),
+  ('\u0156', '\u0157'),
+  ('\u0157', '\u0156'),
+  ('\u0158', '\u0159'),
+  ('\u0159', '\u0158'),
+  ('\u015A', '\u015B'),
+  ('\u015B', '\u015A'),
+  ('\u015C', '\u015D'),
+  ('\u015D', '\u015C'),
+  ('\u015E', '\u015F'),
+  ('\u015F', '\u015E'),
+  ('\u0160', '\u0161'),
+  ('\u0161', '\u0160'),
+  ('\u0162', '\u0163'),
+  ('\u0163', '\u0162'),
+  ('\u0164', '\u0165'),
+  ('\u0165', '\u0164'),
+  ('\u0166', '\u0167'),
+  ('\u0167', '\u0166'),
+  ('\u0168', '\u0169'),
+  ('\u0169', '\u0168'),
+  ('\u016A', '\u016B'),
+  ('\u016B', '\u016A'),
+  ('\u016C', '\u016D'),
+  ('\u016D', '\u016C'),
+  ('\u016E', '\u016F'),
+  ('\u016F', '\u016E'),
+  ('\u0170', '\u0171'),
+  ('\u0171', '\u0170'),
+  ('\u0172', '\u0173'),
+  ('\u0173', '\u0172'),
+  ('\u0174', '\u0175'),
+  ('\u0175', '\u0174'),
+  ('\u0176', '\u0177'),
+  ('\u0177', '\u0176'),
+  ('\u0178', '\u00FF'),
+  ('\u0179', '\u017A'),
+  ('\u017A', '\u0179'),
+  ('\u017B', '\u017C'),
+  ('\u017C', '\u017B'),
+  ('\u017D', '\u017E'),
+  ('\u017E', '\u017D'),
+  ('\u017F', '\u0053'),
+  ('\u0180', '\u0243'),
+  ('\u0181', '\u0253'),
+  ('\u0182', '\u0183'),
+  ('\u0183', '\u0182'),
+  ('\u0184', '\u0185'),
+  ('\u0185', '\u0184'),
+  ('\u0186', '\u0254'),
+  ('\u0187', '\u0188'),
+  ('\u0188', '\u0187'),
+  ('\u0189', '\u0256'),
+  ('\u018A', '\u0257'),
+  ('\u018B', '\u018C'),
+  ('\u018C', '\u018B'),
+  ('\u018E', '\u01DD'),
+  ('\u018F', '\u0259'),
+  ('\u0190', '\u025B'),
+  ('\u0191', '\u0192'),
+  ('\u0192', '\u0191'),
+  ('\u0193', '\u0260'),
+  ('\u0194', '\u0263'),
+  ('\u0195', '\u01F6'),
+  ('\u0196', '\u0269'),
+  ('\u0197', '\u0268'),
+  ('\u0198', '\u0199'),
+  ('\u0199', '\u0198'),
+  ('\u019A', '\u023D'),
+  ('\u019C', '\u026F'),
+  ('\u019D', '\u0272'),
+  ('\u019E', '\u0220'),
+  ('\u019F', '\u0275'),
+  ('\u01A0', '\u01A1'),
+  ('\u01A1', '\u01A0'),
+  ('\u01A2', '\u01A3'),
+  ('\u01A3', '\u01A2'),
+  ('\u01A4', '\u01A5'),
+  ('\u01A5', '\u01A4'),
+  ('\u01A6', '\u0280'),
+  ('\u01A7', '\u01A8'),
+  ('\u01A8', '\u01A7'),
+  ('\u01A9', '\u0283'),
+  ('\u01AC', '\u01AD'),
+  ('\u01AD', '\u01AC'),
+  ('\u01AE', '\u0288'),
+  ('\u01AF', '\u01B0'),
+  ('\u01B0', '\u01AF'),
+  ('\u01B1', '\u028A'),
+  ('\u01B2', '\u028B'),
+  ('\u01B3', '\u01B4'),
+  ('\u01B4', '\u01B3'),
+  ('\u01B5', '\u01B6'),
+  ('\u01B6', '\u01B5'),
+  ('\u01B7', '\u0292'),
+  ('\u01B8', '\u01B9'),
+  ('\u01B9', '\u01B8'),
+  ('\u01BC', '\u01BD'),
+  ('\u01BD', '\u01BC'),
+  ('\u01BF', '\u01F7'),
+  ('\u01C4', '\u01C5'),
+  ('\u01C5', '\u01C6'),
+  ('\u01C6', '\u01C4'),
+  ('\u01C7', '\u01C8'),
+  ('\u01C8', '\u01C9'),
+  ('\u01C9', '\u01C7'),
+  ('\u01CA', '\u01CB'),
+  ('\u01CB', '\u01CC'),
+  ('\u01CC', '\u01CA'),
+  ('\u01CD', '\u01CE'),
+  ('\u01CE', '\u01CD'),
+  ('\u01CF', '\u01D0'),
+  ('\u01D0', '\u01CF'),
+  ('\u01D1', '\u01D2'),
+  ('\u01D2', '\u01D1'),
+  ('\u01D3', '\u01D4'),
+  ('\u01D4', '\u01D3'),
+  ('\u01D5', '\u01D6'),
+  ('\u01D6', '\u01D5'),
+  ('\u01D7', '\u01D8'),
+  ('\u01D8', '\u01D7'),
+  ('\u01D9', '\u01DA'),
+  ('\u01DA', '\u01D9'),
+  ('\u01DB', '\u01DC'),
+  ('\u01DC', '\u01DB'),
+  ('\u01DD', '\u018E'),
+  ('\u01DE', '\u01DF'),
+  ('\u01DF', '\u01DE'),
+  ('\u01E0', '\u01E1'),
+  ('\u01E1', '\u01E0'),
+  ('\u01E2', '\u01E3'),
+  ('\u01E3', '\u01E2'),
+  ('\u01E4', '\u01E5'),
+  ('\u01E5', '\u01E4'),
+  ('\u01E6', '\u01E7'),
+  ('\u01E7', '\u01E6'),
+  ('\u01E8', '\u01E9'),
+  ('\u01E9', '\u01E8'),
+  ('\u01EA', '\u01EB'),
+  ('\u01EB', '\u01EA'),
+  ('\u01EC', '\u01ED'),
+  ('\u01ED', '\u01EC'),
+  ('\u01EE', '\u01EF'),
+  ('\u01EF', '\u01EE'),
+  ('\u01F1', '\u01F2'),
+  ('\u01F2', '\u01F3'),
+  ('\u01F3', '\u01F1'),
+  ('\u01F4', '\u01F5'),
+  ('\u01F5', '\u01F4'),
+  ('\u01F6', '\u0195'),
+  ('\u01F7', '\u01BF'),
+  ('\u01F8', '\u01F9'),
+  ('\u01F9', '\u01F8'),
+  ('\u01FA', '\u01FB'),
+  ('\u01FB', '\u01FA'),
+  ('\u01FC', '\u01FD'),
+  ('\u01FD', '\u01FC'),
+  ('\u01FE', '\u01FF'),
+  ('\u01FF', '\u01FE'),
+  ('\u0200', '\u0201'),
+  ('\u0201', '\u0200'),
+  ('\u0202', '\u0203'),
+  ('\u0203', '\u0202'),
+  ('\u0204', '\u0205'),
+  ('\u0205', '\u0204'),
+  ('\u0206', '\u0207'),
+  ('\u0207', '\u0206'),
+  ('\u0208', '\u0209'),
+  ('\u0209', '\u0208'),
+  ('\u020A', '\u020B'),
+  ('\u020B', '\u020A'),
+  ('\u020C', '\u020D'),
+  ('\u020D', '\u020C'),
+  ('\u020E', '\u020F'),
+  ('\u020F', '\u020E'),
+  ('\u0210', '\u0211'),
+  ('\u0211', '\u0210'),
+  ('\u0212', '\u0213'),
+  ('\u0213', '\u0212'),
+  ('\u0214', '\u0215'),
+  ('\u0215', '\u0214'),
+  ('\u0216', '\u0217'),
+  ('\u0217', '\u0216'),
+  ('\u0218', '\u0219'),
+  ('\u0219', '\u0218'),
+  ('\u021A', '\u021B'),
+  ('\u021B', '\u021A'),
+  ('\u021C', '\u021D'),
+  ('\u021D', '\u021C'),
+  ('\u021E', '\u021F'),
+  ('\u021F', '\u021E'),
+  ('\u0220', '\u019E'),
+  ('\u0222', '\u0223'),
+  ('\u0223', '\u0222'),
+  ('\u0224', '\u0225'),
+  ('\u0225', '\u0224'),
+  ('\u0226', '\u0227'),
+  ('\u0227', '\u0226'),
+  ('\u0228', '\u0229'),
+  ('\u0229', '\u0228'),
+  ('\u022A', '\u022B'),
+  ('\u022B', '\u022A'),
+  ('\u022C', '\u022D'),
+  ('\u022D', '\u022C'),
+  ('\u022E', '\u022F'),
+  ('\u022F', '\u022E'),
+  ('\u0230', '\u0231'),
+  ('\u0231', '\u0230'),
+  ('\u0232', '\u0233'),
+  ('\u0233', '\u0232'),
+  ('\u023A', '\u2C65'),
+  ('\u023B', '\u023C'),
+  ('\u023C', '\u023B'),
+  ('\u023D', '\u019A'),
+  ('\u023E', '\u2C66'),
+  ('\u023F', '\u2C7E'),
+  ('\u0240', '\u2C7F'),
+  ('\u0241', '\u0242'),
+  ('\u0242', '\u0241'),
+  ('\u0243', '\u0180'),
+  ('\u0244', '\u0289'),
+  ('\u0245', '\u028C'),
+  ('\u0246', '\u0247'),
+  ('\u0247', '\u0246'),
+  ('\u0248', '\u0249'),
+  ('\u0249', '\u0248'),
+  ('\u024A', '\u024B'),
+  ('\u024B', '\u024A'),
+  ('\u024C', '\u024D'),
+  ('\u024D', '\u024C'),
+  ('\u024E', '\u024F'),
+  ('\u024F', '\u024E'),
+  ('\u0250', '\u2C6F'),
+  ('\u0251', '\u2C6D'),
+  ('\u0252', '\u2C70'),
+  ('\u0253', '\u0181'),
+  ('\u0254', '\u0186'),
+  ('\u0256', '\u0189'),
+  ('\u0257', '\u018A'),
+  ('\u0259', '\u018F'),
+  ('\u025B', '\u0190'),
+  ('\u025C', '\uA7AB'),
+  ('\u0260', '\u0193'),
+  ('\u0261', '\uA7AC'),
+  ('\u0263', '\u0194'),
+  ('\u0265', '\uA78D'),
+  ('\u0266', '\uA7AA'),
+  ('\u0268', '\u0197'),
+  ('\u0269', '\u0196'),
+  ('\u026A', '\uA7AE'),
+  ('\u026B', '\u2C62'),
+  ('\u026C', '\uA7AD'),
+  ('\u026F', '\u019C'),
+  ('\u0271', '\u2C6E'),
+  ('\u0272', '\u019D'),
+  ('\u0275', '\u019F'),
+  ('\u027D', '\u2C64'),
+  ('\u0280', '\u01A6'),
+  ('\u0282', '\uA7C5'),
+  ('\u0283', '\u01A9'),
+  ('\u0287', '\uA7B1'),
+  ('\u0288', '\u01AE'),
+  ('\u0289', '\u0244'),
+  ('\u028A', '\u01B1'),
+  ('\u028B', '\u01B2'),
+  ('\u028C', '\u0245'),
+  ('\u0292', '\u01B7'),
+  ('\u029D', '\uA7B2'),
+  ('\u029E', '\uA7B0'),
+  ('\u0345', '\u0399'),
+  ('\u0370', '\u0371'),
+  ('\u0371', '\u0370'),
+  ('\u0372', '\u0373'),
+  ('\u0373', '\u0372'),
+  ('\u0376', '\u0377'),
+  ('\u0377', '\u0376'),
+  ('\u037B', '\u03FD'),
+  ('\u037C', '\u03FE'),
+  ('\u037D', '\u03FF'),
+  ('\u037F', '\u03F3'),
+  ('\u0386', '\u03AC'),
+  ('\u0388', '\u03AD'),
+  ('\u0389', '\u03AE'),
+  ('\u038A', '\u03AF'),
+  ('\u038C', '\u03CC'),
+  ('\u038E', '\u03CD'),
+  ('\u038F', '\u03CE'),
+  ('\u0391', '\u03B1'),
+  ('\u0392', '\u03B2'),
+  ('\u0393', '\u03B3'),
+  ('\u0394', '\u03B4'),
+  ('\u0395', '\u03B5'),
+  ('\u0396', '\u03B6'),
+  ('\u0397', '\u03B7'),
+  ('\u0398', '\u03B8'),
+  ('\u0399', '\u03B9'),
+  ('\u039A', '\u03BA'),
+  ('\u039B', '\u03BB'),
+  ('\u039C', '\u03BC'),
+  ('\u039D', '\u03BD'),
+  ('\u039E', '\u03BE'),
+  ('\u039F', '\u03BF'),
+  ('\u03A0', '\u03C0'),
+  ('\u03A1', '\u03C1'),
+  ('\u03A3', '\u03C2'),
+  ('\u03A4', '\u03C4'),
+  ('\u03A5', '\u03C5'),
+  ('\u03A6', '\u03C6'),
+  ('\u03A7', '\u03C7'),
+  ('\u03A8', '\u03C8'),
+  ('\u03A9', '\u03C9'),
+  ('\u03AA', '\u03CA'),
+  ('\u03AB', '\u03CB'),
+  ('\u03AC', '\u0386'),
+  ('\u03AD', '\u0388'),
+  ('\u03AE', '\u0389'),
+  ('\u03AF', '\u038A'),
+  ('\u03B1', '\u0391'),
+  ('\u03B2', '\u03D0'),
+  ('\u03B3', '\u0393'),
+  ('\u03B4', '\u0394'),
+  ('\u03B5', '\u03F5'),
+  ('\u03B6', '\u0396'),
+  ('\u03B7', '\u0397'),
+  ('\u03B8', '\u03D1'),
+  ('\u03B9', '\u1FBE'),
+  ('\u03BA', '\u03F0'),
+  ('\u03BB', '\u039B'),
+  ('\u03BC', '\u00B5'),
+  ('\u03BD', '\u039D'),
+  ('\u03BE', '\u039E'),
+  ('\u03BF', '\u039F'),
+  ('\u03C0', '\u03D6'),
+  ('\u03C1', '\u03F1'),
+  ('\u03C2', '\u03C3'),
+  ('\u03C3', '\u03A3'),
+  ('\u03C4', '\u03A4'),
+  ('\u03C5', '\u03A5'),
+  ('\u03C6', '\u03D5'),
+  ('\u03C7', '\u03A7'),
+  ('\u03C8', '\u03A8'),
+  ('\u03C9', '\u2126'),
+  ('\u03CA', '\u03AA'),
+  ('\u03CB', '\u03AB'),
+  ('\u03CC', '\u038C'),
+  ('\u03CD', '\u038E'),
+  ('\u03CE', '\u038F'),
+  ('\u03CF', '\u03D7'),
+  ('\u03D0', '\u0392'),
+  ('\u03D1', '\u03F4'),
+  ('\u03D5', '\u03A6'),
+  ('\u03D6', '\u03A0'),
+  ('\u03D7', '\u03CF'),
+  ('\u03D8', '\u03D9'),
+  ('\u03D9', '\u03D8'),
+  ('\u03DA', '\u03DB'),
+  ('\u03DB', '\u03DA'),
+  ('\u03DC', '\u03DD'),
+  ('\u03DD', '\u03DC'),
+  ('\u03DE', '\u03DF'),
+  ('\u03DF', '\u03DE'),
+  ('\u03E0', '\u03E1'),
+  ('\u03E1', '\u03E0'),
+  ('\u03E2', '\u03E3'),
+  ('\u03E3', '\u03E2'),
+  ('\u03E4', '\u03E5'),
+  ('\u03E5', '\u03E4'),
+  ('\u03E6', '\u03E7'),
+  ('\u03E7', '\u03E6'),
+  ('\u03E8', '\u03E9'),
+  ('\u03E9', '\u03E8'),
+  ('\u03EA', '\u03EB'),
+  ('\u03EB', '\u03EA'),
+  ('\u03EC', '\u03ED'),
+  ('\u03ED', '\u03EC'),
+  ('\u03EE', '\u03EF'),
+  ('\u03EF', '\u03EE'),
+  ('\u03F0', '\u039A'),
+  ('\u03F1', '\u03A1'),
+  ('\u03F2', '\u03F9'),
+  ('\u03F3', '\u037F'),
+  ('\u03F4', '\u0398'),
+  ('\u03F5', '\u0395'),
+  ('\u03F7', '\u03F8'),
+  ('\u03F8', '\u03F7'),
+  ('\u03F9', '\u03F2'),
+  ('\u03FA', '\u03FB'),
+  ('\u03FB', '\u03FA'),
+  ('\u03FD', '\u037B'),
+  ('\u03FE', '\u037C'),
+  ('\u03FF', '\u037D'),
+  ('\u0400', '\u0450'),
+  ('\u0401', '\u0451'),
+  ('\u0402', '\u0452'),
+  ('\u0403', '\u0453'),
+  ('\u0404', '\u0454'),
+  ('\u0405', '\u0455'),
+  ('\u0406', '\u0456'),
+  ('\u0407', '\u0457'),
+  ('\u0408', '\u0458'),
+  ('\u0409', '\u0459'),
+  ('\u040A', '\u045A'),
+  ('\u040B', '\u045B'),
+  ('\u040C', '\u045C'),
+  ('\u040D', '\u045D'),
+  ('\u040E', '\u045E'),
+  ('\u040F', '\u045F'),
+  ('\u0410', '\u0430'),
+  ('\u0411', '\u0431'),
+  ('\u0412', '\u0432'),
+  ('\u0413', '\u0433'),
+  ('\u0414', '\u0434'),
+  ('\u0415', '\u0435'),
+  ('\u0416', '\u0436'),
+  ('\u0417', '\u0437'),
+  ('\u0418', '\u0438'),
+  ('\u0419', '\u0439'),
+  ('\u041A', '\u043A'),
+  ('\u041B', '\u043B'),
+  ('\u041C', '\u043C'),
+  ('\u041D', '\u043D'),
+  ('\u041E', '\u043E'),
+  ('\u041F', '\u043F'),
+  ('\u0420', '\u0440'),
+  ('\u0421', '\u0441'),
+  ('\u0422', '\u0442'),
+  ('\u0423', '\u0443'),
+  ('\u0424', '\u0444'),
+  ('\u0425', '\u0445'),
+  ('\u0426', '\u0446'),
+  ('\u0427', '\u0447'),
+  ('\u0428', '\u0448'),
+  ('\u0429', '\u0449'),
+  ('\u042A', '\u044A'),
+  ('\u042B', '\u044B'),
+  ('\u042C', '\u044C'),
+  ('\u042D', '\u044D'),
+  ('\u042E', '\u044E'),
+  ('\u042F', '\u044F'),
+  ('\u0430', '\u0410'),
+  ('\u0431', '\u0411'),
+  ('\u0432', '\u1C80'),
+  ('\u0433', '\u0413'),
+  ('\u0434', '\u1C81'),
+  ('\u0435', '\u0415'),
+  ('\u0436', '\u0416'),
+  ('\u0437', '\u0417'),
+  ('\u0438', '\u0418'),
+  ('\u0439', '\u0419'),
+  ('\u043A', '\u041A'),
+  ('\u043B', '\u041B'),
+  ('\u043C', '\u041C'),
+  ('\u043D', '\u041D'),
+  ('\u043E', '\u1C82'),
+  ('\u043F', '\u041F'),
+  ('\u0440', '\u0420'),
+  ('\u0441', '\u1C83'),
+  ('\u0442', '\u1C84'),
+  ('\u0443', '\u0423'),
+  ('\u0444', '\u0424'),
+  ('\u0445', '\u0425'),
+  ('\u0446', '\u0426'),
+  ('\u0447', '\u0427'),
+  ('\u0448', '\u0428'),
+  ('\u0449', '\u0429'),
+  ('\u044A', '\u1C86'),
+  ('\u044B', '\u042B'),
+  ('\u044C', '\u042C'),
+  ('\u044D', '\u042D'),
+  ('\u044E', '\u042E'),
+  ('\u044F', '\u042F'),
+  ('\u0450', '\u0400'),
+  ('\u0451', '\u0401'),
+  ('\u0452', '\u0402'),
+  ('\u0453', '\u0403'),
+  ('\u0454', '\u0404'),
+  ('\u0455', '\u0405'),
+  ('\u0456', '\u0406'),
+  ('\u0457', '\u0407'),
+  ('\u0458', '\u0408'),
+  ('\u0459', '\u0409'),
+  ('\u045A', '\u040A'),
+  ('\u045B', '\u040B'),
+  ('\u045C', '\u040C'),
+  ('\u045D', '\u040D'),
+  ('\u045E', '\u040E'),
+  ('\u045F', '\u040F'),
+  ('\u0460', '\u0461'),
+  ('\u0461', '\u0460'),
+  ('\u0462', '\u0463'),
+  ('\u0463', '\u1C87'),
+  ('\u0464', '\u0465'),
+  ('\u0465', '\u0464'),
+  ('\u0466', '\u0467'),
+  ('\u0467', '\u0466'),
+  ('\u0468', '\u0469'),
+  ('\u0469', '\u0468'),
+  ('\u046A', '\u046B'),
+  ('\u046B', '\u046A'),
+  ('\u046C', '\u046D'),
+  ('\u046D', '\u046C'),
+  ('\u046E', '\u046F'),
+  ('\u046F', '\u046E'),
+  ('\u0470', '\u0471'),
+  ('\u0471', '\u0470'),
+  ('\u0472', '\u0473'),
+  ('\u0473', '\u0472'),
+  ('\u0474', '\u0475'),
+  ('\u0475', '\u0474'),
+  ('\u0476', '\u0477'),
+  ('\u0477', '\u0476'),
+  ('\u0478', '\u0479'),
+  ('\u0479', '\u0478'),
+  ('\u047A', '\u047B'),
+  ('\u047B', '\u047A'),
+  ('\u047C', '\u047D'),
+  ('\u047D', '\u047C'),
+  ('\u047E', '\u047F'),
+  ('\u047F', '\u047E'),
+  ('\u0480', '\u0481'),
+  ('\u0481', '\u0480'),
+  ('\u048A', '\u048B'),
+  ('\u048B', '\u048A'),
+  ('\u048C', '\u048D'),
+  ('\u048D', '\u048C'),
+  ('\u048E', '\u048F'),
+  ('\u048F', '\u048E'),
+  ('\u0490', '\u0491'),
+  ('\u0491', '\u0490'),
+  ('\u0492', '\u0493'),
+  ('\u0493', '\u0492'),
+  ('\u0494', '\u0495'),
+  ('\u0495', '\u0494'),
+  ('\u0496', '\u0497'),
+  ('\u0497', '\u0496'),
+  ('\u0498', '\u0499'),
+  ('\u0499', '\u0498'),
+  ('\u049A', '\u049B'),
+  ('\u049B', '\u049A'),
+  ('\u049C', '\u049D'),
+  ('\u049D', '\u049C'),
+  ('\u049E', '\u049F'),
+  ('\u049F', '\u049E'),
+  ('\u04A0', '\u04A1'),
+  ('\u04A1', '\u04A0'),
+  ('\u04A2', '\u04A3'),
+  ('\u04A3', '\u04A2'),
+  ('\u04A4', '\u04A5'),
+  ('\u04A5', '\u04A4'),
+  ('\u04A6', '\u04A7'),
+  ('\u04A7', '\u04A6'),
+  ('\u04A8', '\u04A9'),
+  ('\u04A9', '\u04A8'),
+  ('\u04AA', '\u04AB'),
+  ('\u04AB', '\u04AA'),
+  ('\u04AC', '\u04AD'),
+  ('\u04AD', '\u04AC'),
+  ('\u04AE', '\u04AF'),
+  ('\u04AF', '\u04AE'),
+  ('\u04B0', '\u04B1'),
+  ('\u04B1', '\u04B0'),
+  ('\u04B2', '\u04B3'),
+  ('\u04B3', '\u04B2'),
+  ('\u04B4', '\u04B5'),
+  ('\u04B5', '\u04B4'),
+  ('\u04B6', '\u04B7'),
+  ('\u04B7', '\u04B6'),
+  ('\u04B8', '\u04B9'),
+  ('\u04B9', '\u04B8'),
+  ('\u04BA', '\u04BB'),
+  ('\u04BB', '\u04BA'),
+  ('\u04BC', '\u04BD'),
+  ('\u04BD', '\u04BC'),
+  ('\u04BE', '\u04BF'),
+  ('\u04BF', '\u04BE'),
+  ('\u04C0', '\u04CF'),
+  ('\u04C1', '\u04C2'),
+  ('\u04C2', '\u04C1'),
+  ('\u04C3', '\u04C4'),
+  ('\u04C4', '\u04C3'),
+  ('\u04C5', '\u04C6'),
+  ('\u04C6', '\u04C5'),
+  ('\u04C7', '\u04C8'),
+  ('\u04C8', '\u04C7'),
+  ('\u04C9', '\u04CA'),
+  ('\u04CA', '\u04C9'),
+  ('\u04CB', '\u04CC'),
+  ('\u04CC', '\u04CB'),
+  ('\u04CD', '\u04CE'),
+  ('\u04CE', '\u04CD'),
+  ('\u04CF', '\u04C0'),
+  ('\u04D0', '\u04D1'),
+  ('\u04D1', '\u04D0'),
+  ('\u04D2', '\u04D3'),
+  ('\u04D3', '\u04D2'),
+  ('\u04D4', '\u04D5'),
+  ('\u04D5', '\u04D4'),
+  ('\u04D6', '\u04D7'),
+  ('\u04D7', '\u04D6'),
+  ('\u04D8', '\u04D9'),
+  ('\u04D9', '\u04D8'),
+  ('\u04DA', '\u04DB'),
+  ('\u04DB', '\u04DA'),
+  ('\u04DC', '\u04DD'),
+  ('\u04DD', '\u04DC'),
+  ('\u04DE', '\u04DF'),
+  ('\u04DF', '\u04DE'),
+  ('\u04E0', '\u04E1'),
+  ('\u04E1', '\u04E0'),
+  ('\u04E2', '\u04E3'),
+  ('\u04E3', '\u04E2'),
+  ('\u04E4', '\u04E5'),
+  ('\u04E5', '\u04E4'),
+  ('\u04E6', '\u04E7'),
+  ('\u04E7', '\u04E6'),
+  ('\u04E8', '\u04E9'),
+  ('\u04E9', '\u04E8'),
+  ('\u04EA', '\u04EB'),
+  ('\u04EB', '\u04EA'),
+  ('\u04EC', '\u04ED'),
+  ('\u04ED', '\u04EC'),
+  ('\u04EE', '\u04EF'),
+  ('\u04EF', '\u04EE'),
+  ('\u04F0', '\u04F1'),
+  ('\u04F1', '\u04F0'),
+  ('\u04F2', '\u04F3'),
+  ('\u04F3', '\u04F2'),
+  ('\u04F4', '\u04F5'),
+  ('\u04F5', '\u04F4'),
+  ('\u04F6', '\u04F7'),
+  ('\u04F7', '\u04F6'),
+  ('\u04F8', '\u04F9'),
+  ('\u04F9', '\u04F8'),
+  ('\u04FA', '\u04FB'),
+  ('\u04FB', '\u04FA'),
+  ('\u04FC', '\u04FD'),
+  ('\u04FD', '\u04FC'),
+  ('\u04FE', '\u04FF'),
+  ('\u04FF', '\u04FE'),
+  ('\u0500', '\u0501'),
+  ('\u0501', '\u0500'),
+  ('\u0502', '\u0503'),
+  ('\u0503', '\u0502'),
+  ('\u0504', '\u0505'),
+  ('\u0505', '\u0504'),
+  ('\u0506', '\u0507'),
+  ('\u0507', '\u0506'),
+  ('\u0508', '\u0509'),
+  ('\u0509', '\u0508'),
+  ('\u050A', '\u050B'),
+  ('\u050B', '\u050A'),
+  ('\u050C', '\u050D'),
+  ('\u050D', '\u050C'),
+  ('\u050E', '\u050F'),
+  ('\u050F', '\u050E'),
+  ('\u0510', '\u0511'),
+  ('\u0511', '\u0510'),
+  ('\u0512', '\u0513'),
+  ('\u0513', '\u0512'),
+  ('\u0514', '\u0515'),
+  ('\u0515', '\u0514'),
+  ('\u0516', '\u0517'),
+  ('\u0517', '\u0516'),
+  ('\u0518', '\u0519'),
+  ('\u0519', '\u0518'),
+  ('\u051A', '\u051B'),
+  ('\u051B', '\u051A'),
+  ('\u051C', '\u051D'),
+  ('\u051D', '\u051C'),
+  ('\u051E', '\u051F'),
+  ('\u051F', '\u051E'),
+  ('\u0520', '\u0521'),
+  ('\u0521', '\u0520'),
+  ('\u0522', '\u0523'),
+  ('\u0523', '\u0522'),
+  ('\u0524', '\u0525'),
+  ('\u0525', '\u0524'),
+  ('\u0526', '\u0527'),
+  ('\u0527', '\u0526'),
+  ('\u0528', '\u0529'),
+  ('\u0529', '\u0528'),
+  ('\u052A', '\u052B'),
+  ('\u052B', '\u052A'),
+  ('\u052C', '\u052D'),
+  ('\u052D', '\u052C'),
+  ('\u052E', '\u052F'),
+  ('\u052F', '\u052E'),
+  ('\u0531', '\u0561'),
+  ('\u0532', '\u0562'),
+  ('\u0533', '\u0563'),
+  ('\u0534', '\u0564'),
+  ('\u0535', '\u0565'),
+  ('\u0536', '\u0566'),
+  ('\u0537', '\u0567'),
+  ('\u0538', '\u0568'),
+  ('\u0539', '\u0569'),
+  ('\u053A', '\u056A'),
+  ('\u053B', '\u056B'),
+  ('\u053C', '\u056C'),
+  ('\u053D', '\u056D'),
+  ('\u053E', '\u056E'),
+  ('\u053F', '\u056F'),
+  ('\u0540', '\u0570'),
+  ('\u0541', '\u0571'),
+  ('\u0542', '\u0572'),
+  ('\u0543', '\u0573'),
+  ('\u0544', '\u0574'),
+  ('\u0545', '\u0575'),
+  ('\u0546', '\u0576'),
+  ('\u0547', '\u0577'),
+  ('\u0548', '\u0578'),
+  ('\u0549', '\u0579'),
+  ('\u054A', '\u057A'),
+  ('\u054B', '\u057B'),
+  ('\u054C', '\u057C'),
+  ('\u054D', '\u057D'),
+  ('\u054E', '\u057E'),
+  ('\u054F', '\u057F'),
+  ('\u0550', '\u0580'),
+  ('\u0551', '\u0581'),
+  ('\u0552', '\u0582'),
+  ('\u0553', '\u0583'),
+  ('\u0554', '\u0584'),
+  ('\u0555', '\u0585'),
+  ('\u0556', '\u0586'),
+  ('\u0561', '\u0531'),
+  ('\u0562', '\u0532'),
+  ('\u0563', '\u0533'),
+  ('\u0564', '\u0534'),
+  ('\u0565', '\u0535'),
+  ('\u0566', '\u0536'),
+  ('\u0567', '\u0537'),
+  ('\u0568', '\u0538'),
+  ('\u0569', '\u0539'),
+  ('\u056A', '\u053A'),
+  ('\u056B', '\u053B'),
+  ('\u056C', '\u053C'),
+  ('\u056D', '\u053D'),
+  ('\u056E', '\u053E'),
+  ('\u056F', '\u053F'),
+  ('\u0570', '\u0540'),
+  ('\u0571', '\u0541'),
+  ('\u0572', '\u0542'),
+  ('\u0573', '\u0543'),
+  ('\u0574', '\u0544'),
+  ('\u0575', '\u0545'),
+  ('\u0576', '\u0546'),
+  ('\u0577', '\u0547'),
+  ('\u0578', '\u0548'),
+  ('\u0579', '\u0549'),
+  ('\u057A', '\u054A'),
+  ('\u057B', '\u054B'),
+  ('\u057C', '\u054C'),
+  ('\u057D', '\u054D'),
+  ('\u057E', '\u054E'),
+  ('\u057F', '\u054F'),
+  ('\u0580', '\u0550'),
+  ('\u0581', '\u0551'),
+  ('\u0582', '\u0552'),
+  ('\u0583', '\u0553'),
+  ('\u0584', '\u0554'),
+  ('\u0585', '\u0555'),
+  ('\u0586', '\u0556'),
+  ('\u10A0', '\u2D00'),
+  ('\u10A1', '\u2D01'),
+  ('\u10A2', '\u2D02'),
+  ('\u10A3', '\u2D03'),
+  ('\u10A4', '\u2D04'),
+  ('\u10A5', '\u2D05'),
+  ('\u10A6', '\u2D06'),
+  ('\u10A7', '\u2D07'),
+  ('\u10A8', '\u2D08'),
+  ('\u10A9', '\u2D09'),
+  ('\u10AA', '\u2D0A'),
+  ('\u10AB', '\u2D0B'),
+  ('\u10AC', '\u2D0C'),
+  ('\u10AD', '\u2D0D'),
+  ('\u10AE', '\u2D0E'),
+  ('\u10AF', '\u2D0F'),
+  ('\u10B0', '\u2D10'),
+  ('\u10B1', '\u2D11'),
+  ('\u10B2', '\u2D12'),
+  ('\u10B3', '\u2D13'),
+  ('\u10B4', '\u2D14'),
+  ('\u10B5', '\u2D15'),
+  ('\u10B6', '\u2D16'),
+  ('\u10B7', '\u2D17'),
+  ('\u10B8', '\u2D18'),
+  ('\u10B9', '\u2D19'),
+  ('\u10BA', '\u2D1A'),
+  ('\u10BB', '\u2D1B'),
+  ('\u10BC', '\u2D1C'),
+  ('\u10BD', '\u2D1D'),
+  ('\u10BE', '\u2D1E'),
+  ('\u10BF', '\u2D1F'),
+  ('\u10C0', '\u2D20'),
+  ('\u10C1', '\u2D21'),
+  ('\u10C2', '\u2D22'),
+  ('\u10C3', '\u2D23'),
+  ('\u10C4', '\u2D24'),
+  ('\u10C5', '\u2D25'),
+  ('\u10C7', '\u2D27'),
+  ('\u10CD', '\u2D2D'),
+  ('\u10D0', '\u1C90'),
+  ('\u10D1', '\u1C91'),
+  ('\u10D2', '\u1C92'),
+  ('\u10D3', '\u1C93'),
+  ('\u10D4', '\u1C94'),
+  ('\u10D5', '\u1C95'),
+  ('\u10D6', '\u1C96'),
+  ('\u10D7', '\u1C97'),
+  ('\u10D8', '\u1C98'),
+  ('\u10D9', '\u1C99'),
+  ('\u10DA', '\u1C9A'),
+  ('\u10DB', '\u1C9B'),
+  ('\u10DC', '\u1C9C'),
+  ('\u10DD', '\u1C9D'),
+  ('\u10DE', '\u1C9E'),
+  ('\u10DF', '\u1C9F'),
+  ('\u10E0', '\u1CA0'),
+  ('\u10E1', '\u1CA1'),
+  ('\u10E2', '\u1CA2'),
+  ('\u10E3', '\u1CA3'),
+  ('\u10E4', '\u1CA4'),
+  ('\u10E5', '\u1CA5'),
+  ('\u10E6', '\u1CA6'),
+  ('\u10E7', '\u1CA7'),
+  ('\u10E8', '\u1CA8'),
+  ('\u10E9', '\u1CA9'),
+  ('\u10EA', '\u1CAA'),
+  ('\u10EB', '\u1CAB'),
+  ('\u10EC', '\u1CAC'),
+  ('\u10ED', '\u1CAD'),
+  ('\u10EE', '\u1CAE'),
+  ('\u10EF', '\u1CAF'),
+  ('\u10F0', '\u1CB0'),
+  ('\u10F1', '\u1CB1'),
+  ('\u10F2', '\u1CB2'),
+  ('\u10F3', '\u1CB3'),
+  ('\u10F4', '\u1CB4'),
+  ('\u10F5', '\u1CB5'),
+  ('\u10F6', '\u1CB6'),
+  ('\u10F7', '\u1CB7'),
+  ('\u10F8', '\u1CB8'),
+  ('\u10F9', '\u1CB9'),
+  ('\u10FA', '\u1CBA'),
+  ('\u10FD', '\u1CBD'),
+  ('\u10FE', '\u1CBE'),
+  ('\u10FF', '\u1CBF'),
+  ('\u13A0', '\uAB70'),
+  ('\u13A1', '\uAB71'),
+  ('\u13A2', '\uAB72'),
+  ('\u13A3', '\uAB73'),
+  ('\u13A4', '\uAB74'),
+  ('\u13A5', '\uAB75'),
+  ('\u13A6', '\uAB76'),
+  ('\u13A7', '\uAB77'),
+  ('\u13A8', '\uAB78'),
+  ('\u13A9', '\uAB79'),
+  ('\u13AA', '\uAB7A'),
+  ('\u13AB', '\uAB7B'),
+  ('\u13AC', '\uAB7C'),
+  ('\u13AD', '\uAB7D'),
+  ('\u13AE', '\uAB7E'),
+  ('\u13AF', '\uAB7F'),
+  ('\u13B0', '\uAB80'),
+  ('\u13B1', '\uAB81'),
+  ('\u13B2', '\uAB82'),
+  ('\u13B3', '\uAB83'),
+  ('\u13B4', '\uAB84'),
+  ('\u13B5', '\uAB85'),
+  ('\u13B6', '\uAB86'),
+  ('\u13B7', '\uAB87'),
+  ('\u13B8', '\uAB88'),
+  ('\u13B9', '\uAB89'),
+  ('\u13BA', '\uAB8A'),
+  ('\u13BB', '\uAB8B'),
+  ('\u13BC', '\uAB8C'),
+  ('\u13BD', '\uAB8D'),
+  ('\u13BE', '\uAB8E'),
+  ('\u13BF', '\uAB8F'),
+  ('\u13C0', '\uAB90'),
+  ('\u13C1', '\uAB91'),
+  ('\u13C2', '\uAB92'),
+  ('\u13C3', '\uAB93'),
+  ('\u13C4', '\uAB94'),
+  ('\u13C5', '\uAB95'),
+  ('\u13C6', '\uAB96'),
+  ('\u13C7', '\uAB97'),
+  ('\u13C8', '\uAB98'),
+  ('\u13C9', '\uAB99'),
+  ('\u13CA', '\uAB9A'),
+  ('\u13CB', '\uAB9B'),
+  ('\u13CC', '\uAB9C'),
+  ('\u13CD', '\uAB9D'),
+  ('\u13CE', '\uAB9E'),
+  ('\u13CF', '\uAB9F'),
+  ('\u13D0', '\uABA0'),
+  ('\u13D1', '\uABA1'),
+  ('\u13D2', '\uABA2'),
+  ('\u13D3', '\uABA3'),
+  ('\u13D4', '\uABA4'),
+  ('\u13D5', '\uABA5'),
+  ('\u13D6', '\uABA6'),
+  ('\u13D7', '\uABA7'),
+  ('\u13D8', '\uABA8'),
+  ('\u13D9', '\uABA9'),
+  ('\u13DA', '\uABAA'),
+  ('\u13DB', '\uABAB'),
+  ('\u13DC', '\uABAC'),
+  ('\u13DD', '\uABAD'),
+  ('\u13DE', '\uABAE'),
+  ('\u13DF', '\uABAF'),
+  ('\u13E0', '\uABB0'),
+  ('\u13E1', '\uABB1'),
+  ('\u13E2', '\uABB2'),
+  ('\u13E3', '\uABB3'),
+  ('\u13E4', '\uABB4'),
+  ('\u13E5', '\uABB5'),
+  ('\u13E6', '\uABB6'),
+  ('\u13E7', '\uABB7'),
+  ('\u13E8', '\uABB8'),
+  ('\u13E9', '\uABB9'),
+  ('\u13EA', '\uABBA'),
+  ('\u13EB', '\uABBB'),
+  ('\u13EC', '\uABBC'),
+  ('\u13ED', '\uABBD'),
+  ('\u13EE', '\uABBE'),
+  ('\u13EF', '\uABBF'),
+  ('\u13F0', '\u13F8'),
+  ('\u13F1', '\u13F9'),
+  ('\u13F2', '\u13FA'),
+  ('\u13F3', '\u13FB'),
+  ('\u13F4', '\u13FC'),
+  ('\u13F5', '\u13FD'),
+  ('\u13F8', '\u13F0'),
+  ('\u13F9', '\u13F1'),
+  ('\u13FA', '\u13F2'),
+  ('\u13FB', '\u13F3'),
+  ('\u13FC', '\u13F4'),
+  ('\u13FD', '\u13F5'),
+  ('\u1C80', '\u0412'),
+  ('\u1C81', '\u0414'),
+  ('\u1C82', '\u041E'),
+  ('\u1C83', '\u0421'),
+  ('\u1C84', '\u1C85'),
+  ('\u1C85', '\u0422'),
+  ('\u1C86', '\u042A'),
+  ('\u1C87', '\u0462'),
+  ('\u1C88', '\uA64A'),
+  ('\u1C90', '\u10D0'),
+  ('\u1C91', '\u10D1'),
+  ('\u1C92', '\u10D2'),
+  ('\u1C93', '\u10D3'),
+  ('\u1C94', '\u10D4'),
+  ('\u1C95', '\u10D5'),
+  ('\u1C96', '\u10D6'),
+  ('\u1C97', '\u10D7'),
+  ('\u1C98', '\u10D8'),
+  ('\u1C99', '\u10D9'),
+  ('\u1C9A', '\u10DA'),
+  ('\u1C9B', '\u10DB'),
+  ('\u1C9C', '\u10DC'),
+  ('\u1C9D', '\u10DD'),
+  ('\u1C9E', '\u10DE'),
+  ('\u1C9F', '\u10DF'),
+  ('\u1CA0', '\u10E0'),
+  ('\u1CA1', '\u10E1'),
+  ('\u1CA2', '\u10E2'),
+  ('\u1CA3', '\u10E3'),
+  ('\u1CA4', '\u10E4'),
+  ('\u1CA5', '\u10E5'),
+  ('\u1CA6', '\u10E6'),
+  ('\u1CA7', '\u10E7'),
+  ('\u1CA8', '\u10E8'),
+  ('\u1CA9', '\u10E9'),
+  ('\u1CAA', '\u10EA'),
+  ('\u1CAB', '\u10EB'),
+  ('\u1CAC', '\u10EC'),
+  ('\u1CAD', '\u10ED'),
+  ('\u1CAE', '\u10EE'),
+  ('\u1CAF', '\u10EF'),
+  ('\u1CB0', '\u10F0'),
+  ('\u1CB1', '\u10F1'),
+  ('\u1CB2', '\u10F2'),
+  ('\u1CB3', '\u10F3'),
+  ('\u1CB4', '\u10F4'),
+  ('\u1CB5', '\u10F5'),
+  ('\u1CB6', '\u10F6'),
+  ('\u1CB7', '\u10F7'),
+  ('\u1CB8', '\u10F8'),
+  ('\u1CB9', '\u10F9'),
+  ('\u1CBA', '\u10FA'),
+  ('\u1CBD', '\u10FD'),
+  ('\u1CBE', '\u10FE'),
+  ('\u1CBF', '\u10FF'),
+  ('\u1D79', '\uA77D'),
+  ('\u1D7D', '\u2C63'),
+  ('\u1D8E', '\uA7C6'),
+  ('\u1E00', '\u1E01'),
+  ('\u1E01', '\u1E00'),
+  ('\u1E02', '\u1E03'),
+  ('\u1E03', '\u1E02'),
+  ('\u1E04', '\u1E05'),
+  ('\u1E05', '\u1E04'),
+  ('\u1E06', '\u1E07'),
+  ('\u1E07', '\u1E06'),
+  ('\u1E08', '\u1E09'),
+  ('\u1E09', '\u1E08'),
+  ('\u1E0A', '\u1E0B'),
+  ('\u1E0B', '\u1E0A'),
+  ('\u1E0C', '\u1E0D'),
+  ('\u1E0D', '\u1E0C'),
+  ('\u1E0E', '\u1E0F'),
+  ('\u1E0F', '\u1E0E'),
+  ('\u1E10', '\u1E11'),
+  ('\u1E11', '\u1E10'),
+  ('\u1E12', '\u1E13'),
+  ('\u1E13', '\u1E12'),
+  ('\u1E14', '\u1E15'),
+  ('\u1E15', '\u1E14'),
+  ('\u1E16', '\u1E17'),
+  ('\u1E17', '\u1E16'),
+  ('\u1E18', '\u1E19'),
+  ('\u1E19', '\u1E18'),
+  ('\u1E1A', '\u1E1B'),
+  ('\u1E1B', '\u1E1A'),
+  ('\u1E1C', '\u1E1D'),
+  ('\u1E1D', '\u1E1C'),
+  ('\u1E1E', '\u1E1F'),
+  ('\u1E1F', '\u1E1E'),
+  ('\u1E20', '\u1E21'),
+  ('\u1E21', '\u1E20'),
+  ('\u1E22', '\u1E23'),
+  ('\u1E23', '\u1E22'),
+  ('\u1E24', '\u1E25'),
+  ('\u1E25', '\u1E24'),
+  ('\u1E26', '\u1E27'),
+  ('\u1E27', '\u1E26'),
+  ('\u1E28', '\u1E29'),
+  ('\u1E29', '\u1E28'),
+  ('\u1E2A', '\u1E2B'),
+  ('\u1E2B', '\u1E2A'),
+  ('\u1E2C', '\u1E2D'),
+  ('\u1E2D', '\u1E2C'),
+  ('\u1E2E', '\u1E2F'),
+  ('\u1E2F', '\u1E2E'),
+  ('\u1E30', '\u1E31'),
+  ('\u1E31', '\u1E30'),
+  ('\u1E32', '\u1E33'),
+  ('\u1E33', '\u1E32'),
+  ('\u1E34', '\u1E35'),
+  ('\u1E35', '\u1E34'),
+  ('\u1E36', '\u1E37'),
+  ('\u1E37', '\u1E36'),
+  ('\u1E38', '\u1E39'),
+  ('\u1E39', '\u1E38'),
+  ('\u1E3A', '\u1E3B'),
+  ('\u1E3B', '\u1E3A'),
+  ('\u1E3C', '\u1E3D'),
+  ('\u1E3D', '\u1E3C'),
+  ('\u1E3E', '\u1E3F'),
+  ('\u1E3F', '\u1E3E'),
+  ('\u1E40', '\u1E41'),
+  ('\u1E41', '\u1E40'),
+  ('\u1E42', '\u1E43'),
+  ('\u1E43', '\u1E42'),
+  ('\u1E44', '\u1E45'),
+  ('\u1E45', '\u1E44'),
+  ('\u1E46', '\u1E47'),
+  ('\u1E47', '\u1E46'),
+  ('\u1E48', '\u1E49'),
+  ('\u1E49', '\u1E48'),
+  ('\u1E4A', '\u1E4B'),
+  ('\u1E4B', '\u1E4A'),
+  ('\u1E4C', '\u1E4D'),
+  ('\u1E4D', '\u1E4C'),
+  ('\u1E4E', '\u1E4F'),
+  ('\u1E4F', '\u1E4E'),
+  ('\u1E50', '\u1E51'),
+  ('\u1E51', '\u1E50'),
+  ('\u1E52', '\u1E53'),
+  ('\u1E53', '\u1E52'),
+  ('\u1E54', '\u1E55'),
+  ('\u1E55', '\u1E54'),
+  ('\u1E56', '\u1E57'),
+  ('\u1E57', '\u1E56'),
+  ('\u1E58', '\u1E59'),
+  ('\u1E59', '\u1E58'),
+  ('\u1E5A', '\u1E5B'),
+  ('\u1E5B', '\u1E5A'),
+  ('\u1E5C', '\u1E5D'),
+  ('\u1E5D', '\u1E5C'),
+  ('\u1E5E', '\u1E5F'),
+  ('\u1E5F', '\u1E5E'),
+  ('\u1E60', '\u1E61'),
+  ('\u1E61', '\u1E9B'),
+  ('\u1E62', '\u1E63'),
+  ('\u1E63', '\u1E62'),
+  ('\u1E64', '\u1E65'),
+  ('\u1E65', '\u1E64'),
+  ('\u1E66', '\u1E67'),
+  ('\u1E67', '\u1E66'),
+  ('\u1E68', '\u1E69'),
+  ('\u1E69', '\u1E68'),
+  ('\u1E6A', '\u1E6B'),
+  ('\u1E6B', '\u1E6A'),
+  ('\u1E6C', '\u1E6D'),
+  ('\u1E6D', '\u1E6C'),
+  ('\u1E6E', '\u1E6F'),
+  ('\u1E6F', '\u1E6E'),
+  ('\u1E70', '\u1E71'),
+  ('\u1E71', '\u1E70'),
+  ('\u1E72', '\u1E73'),
+  ('\u1E73', '\u1E72'),
+  ('\u1E74', '\u1E75'),
+  ('\u1E75', '\u1E74'),
+  ('\u1E76', '\u1E77'),
+  ('\u1E77', '\u1E76'),
+  ('\u1E78', '\u1E79'),
+  ('\u1E79', '\u1E78'),
+  ('\u1E7A', '\u1E7B'),
+  ('\u1E7B', '\u1E7A'),
+  ('\u1E7C', '\u1E7D'),
+  ('\u1E7D', '\u1E7C'),
+  ('\u1E7E', '\u1E7F'),
+  ('\u1E7F', '\u1E7E'),
+  ('\u1E80', '\u1E81'),
+  ('\u1E81', '\u1E80'),
+  ('\u1E82', '\u1E83'),
+  ('\u1E83', '\u1E82'),
+  ('\u1E84', '\u1E85'),
+  ('\u1E85', '\u1E84'),
+  ('\u1E86', '\u1E87'),
+  ('\u1E87', '\u1E86'),
+  ('\u1E88', '\u1E89'),
+  ('\u1E89', '\u1E88'),
+  ('\u1E8A', '\u1E8B'),
+  ('\u1E8B', '\u1E8A'),
+  ('\u1E8C', '\u1E8D'),
+  ('\u1E8D', '\u1E8C'),
+  ('\u1E8E', '\u1E8F'),
+  ('\u1E8F', '\u1E8E'),
+  ('\u1E90', '\u1E91'),
+  ('\u1E91', '\u1E90'),
+  ('\u1E92', '\u1E93'),
+  ('\u1E93', '\u1E92'),
+  ('\u1E94', '\u1E95'),
+  ('\u1E95', '\u1E94'),
+  ('\u1E9B', '\u1E60'),
+  ('\u1E9E', '\u00DF'),
+  ('\u1EA0', '\u1EA1'),
+  ('\u1EA1', '\u1EA0'),
+  ('\u1EA2', '\u1EA3'),
+  ('\u1EA3', '\u1EA2'),
+  ('\u1EA4', '\u1EA5'),
+  ('\u1EA5', '\u1EA4'),
+  ('\u1EA6', '\u1EA7'),
+  ('\u1EA7', '\u1EA6'),
+  ('\u1EA8', '\u1EA9'),
+  ('\u1EA9', '\u1EA8'),
+  ('\u1EAA', '\u1EAB'),
+  ('\u1EAB', '\u1EAA'),
+  ('\u1EAC', '\u1EAD'),
+  ('\u1EAD', '\u1EAC'),
+  ('\u1EAE', '\u1EAF'),
+  ('\u1EAF', '\u1EAE'),
+  ('\u1EB0', '\u1EB1'),
+  ('\u1EB1', '\u1EB0'),
+  ('\u1EB2', '\u1EB3'),
+  ('\u1EB3', '\u1EB2'),
+  ('\u1EB4', '\u1EB5'),
+  ('\u1EB5', '\u1EB4'),
+  ('\u1EB6', '\u1EB7'),
+  ('\u1EB7', '\u1EB6'),
+  ('\u1EB8', '\u1EB9'),
+  ('\u1EB9', '\u1EB8'),
+  ('\u1EBA', '\u1EBB'),
+  ('\u1EBB', '\u1EBA'),
+  ('\u1EBC', '\u1EBD'),
+  ('\u1EBD', '\u1EBC'),
+  ('\u1EBE', '\u1EBF'),
+  ('\u1EBF', '\u1EBE'),
+  ('\u1EC0', '\u1EC1'),
+  ('\u1EC1', '\u1EC0'),
+  ('\u1EC2', '\u1EC3'),
+  ('\u1EC3', '\u1EC2'),
+  ('\u1EC4', '\u1EC5'),
+  ('\u1EC5', '\u1EC4'),
+  ('\u1EC6', '\u1EC7'),
+  ('\u1EC7', '\u1EC6'),
+  ('\u1EC8', '\u1EC9'),
+  ('\u1EC9', '\u1EC8'),
+  ('\u1ECA', '\u1ECB'),
+  ('\u1ECB', '\u1ECA'),
+  ('\u1ECC', '\u1ECD'),
+  ('\u1ECD', '\u1ECC'),
+  ('\u1ECE', '\u1ECF'),
+  ('\u1ECF', '\u1ECE'),
+  ('\u1ED0', '\u1ED1'),
+  ('\u1ED1', '\u1ED0'),
+  ('\u1ED2', '\u1ED3'),
+  ('\u1ED3', '\u1ED2'),
+  ('\u1ED4', '\u1ED5'),
+  ('\u1ED5', '\u1ED4'),
+  ('\u1ED6', '\u1ED7'),
+  ('\u1ED7', '\u1ED6'),
+  ('\u1ED8', '\u1ED9'),
+  ('\u1ED9', '\u1ED8'),
+  ('\u1EDA', '\u1EDB'),
+  ('\u1EDB', '\u1EDA'),
+  ('\u1EDC', '\u1EDD'),
+  ('\u1EDD', '\u1EDC'),
+  ('\u1EDE', '\u1EDF'),
+  ('\u1EDF', '\u1EDE'),
+  ('\u1EE0', '\u1EE1'),
+  ('\u1EE1', '\u1EE0'),
+  ('\u1EE2', '\u1EE3'),
+  ('\u1EE3', '\u1EE2'),
+  ('\u1EE4', '\u1EE5'),
+  ('\u1EE5', '\u1EE4'),
+  ('\u1EE6', '\u1EE7'),
+  ('\u1EE7', '\u1EE6'),
+  ('\u1EE8', '\u1EE9'),
+  ('\u1EE9', '\u1EE8'),
+  ('\u1EEA', '\u1EEB'),
+  ('\u1EEB', '\u1EEA'),
+  ('\u1EEC', '\u1EED'),
+  ('\u1EED', '\u1EEC'),
+  ('\u1EEE', '\u1EEF'),
+  ('\u1EEF', '\u1EEE'),
+  ('\u1EF0', '\u1EF1'),
+  ('\u1EF1', '\u1EF0'),
+  ('\u1EF2', '\u1EF3'),
+  ('\u1EF3', '\u1EF2'),
+  ('\u1EF4', '\u1EF5'),
+  ('\u1EF5', '\u1EF4'),
+  ('\u1EF6', '\u1EF7'),
+  ('\u1EF7', '\u1EF6'),
+  ('\u1EF8', '\u1EF9'),
+  ('\u1EF9', '\u1EF8'),
+  ('\u1EFA', '\u1EFB'),
+  ('\u1EFB', '\u1EFA'),
+  ('\u1EFC', '\u1EFD'),
+  ('\u1EFD', '\u1EFC'),
+  ('\u1EFE', '\u1EFF'),
+  ('\u1EFF', '\u1EFE'),
+  ('\u1F00', '\u1F08'),
+  ('\u1F01', '\u1F09'),
+  ('\u1F02', '\u1F0A'),
+  ('\u1F03', '\u1F0B'),
+  ('\u1F04', '\u1F0C'),
+  ('\u1F05', '\u1F0D'),
+  ('\u1F06', '\u1F0E'),
+  ('\u1F07', '\u1F0F'),
+  ('\u1F08', '\u1F00'),
+  ('\u1F09', '\u1F01'),
+  ('\u1F0A', '\u1F02'),
+  ('\u1F0B', '\u1F03'),
+  ('\u1F0C', '\u1F04'),
+  ('\u1F0D', '\u1F05'),
+  ('\u1F0E', '\u1F06'),
+  ('\u1F0F', '\u1F07'),
+  ('\u1F10', '\u1F18'),
+  ('\u1F11', '\u1F19'),
+  ('\u1F12', '\u1F1A'),
+  ('\u1F13', '\u1F1B'),
+  ('\u1F14', '\u1F1C'),
+  ('\u1F15', '\u1F1D'),
+  ('\u1F18', '\u1F10'),
+  ('\u1F19', '\u1F11'),
+  ('\u1F1A', '\u1F12'),
+  ('\u1F1B', '\u1F13'),
+  ('\u1F1C', '\u1F14'),
+  ('\u1F1D', '\u1F15'),
+  ('\u1F20', '\u1F28'),
+  ('\u1F21', '\u1F29'),
+  ('\u1F22', '\u1F2A'),
+  ('\u1F23', '\u1F2B'),
+  ('\u1F24', '\u1F2C'),
+  ('\u1F25', '\u1F2D'),
+  ('\u1F26', '\u1F2E'),
+  ('\u1F27', '\u1F2F'),
+  ('\u1F28', '\u1F20'),
+  ('\u1F29', '\u1F21'),
+  ('\u1F2A', '\u1F22'),
+  ('\u1F2B', '\u1F23'),
+  ('\u1F2C', '\u1F24'),
+  ('\u1F2D', '\u1F25'),
+  ('\u1F2E', '\u1F26'),
+  ('\u1F2F', '\u1F27'),
+  ('\u1F30', '\u1F38'),
+  ('\u1F31', '\u1F39'),
+  ('\u1F32', '\u1F3A'),
+  ('\u1F33', '\u1F3B'),
+  ('\u1F34', '\u1F3C'),
+  ('\u1F35', '\u1F3D'),
+  ('\u1F36', '\u1F3E'),
+  ('\u1F37', '\u1F3F'),
+  ('\u1F38', '\u1F30'),
+  ('\u1F39', '\u1F31'),
+  ('\u1F3A', '\u1F32'),
+  ('\u1F3B', '\u1F33'),
+  ('\u1F3C', '\u1F34'),
+  ('\u1F3D', '\u1F35'),
+  ('\u1F3E', '\u1F36'),
+  ('\u1F3F', '\u1F37'),
+  ('\u1F40', '\u1F48'),
+  ('\u1F41', '\u1F49'),
+  ('\u1F42', '\u1F4A'),
+  ('\u1F43', '\u1F4B'),
+  ('\u1F44', '\u1F4C'),
+  ('\u1F45', '\u1F4D'),
+  ('\u1F48', '\u1F40'),
+  ('\u1F49', '\u1F41'),
+  ('\u1F4A', '\u1F42'),
+  ('\u1F4B', '\u1F43'),
+  ('\u1F4C', '\u1F44'),
+  ('\u1F4D', '\u1F45'),
+  ('\u1F51', '\u1F59'),
+  ('\u1F53', '\u1F5B'),
+  ('\u1F55', '\u1F5D'),
+  ('\u1F57', '\u1F5F'),
+  ('\u1F59', '\u1F51'),
+  ('\u1F5B', '\u1F53'),
+  ('\u1F5D', '\u1F55'),
+  ('\u1F5F', '\u1F57'),
+  ('\u1F60', '\u1F68'),
+  ('\u1F61', '\u1F69'),
+  ('\u1F62', '\u1F6A'),
+  ('\u1F63', '\u1F6B'),
+  ('\u1F64', '\u1F6C'),
+  ('\u1F65', '\u1F6D'),
+  ('\u1F66', '\u1F6E'),
+  ('\u1F67', '\u1F6F'),
+  ('\u1F68', '\u1F60'),
+  ('\u1F69', '\u1F61'),
+  ('\u1F6A', '\u1F62'),
+  ('\u1F6B', '\u1F63'),
+  ('\u1F6C', '\u1F64'),
+  ('\u1F6D', '\u1F65'),
+  ('\u1F6E', '\u1F66'),
+  ('\u1F6F', '\u1F67'),
+  ('\u1F70', '\u1FBA'),
+  ('\u1F71', '\u1FBB'),
+  ('\u1F72', '\u1FC8'),
+  ('\u1F73', '\u1FC9'),
+  ('\u1F74', '\u1FCA'),
+  ('\u1F75', '\u1FCB'),
+  ('\u1F76', '\u1FDA'),
+  ('\u1F77', '\u1FDB'),
+  ('\u1F78', '\u1FF8'),
+  ('\u1F79', '\u1FF9'),
+  ('\u1F7A', '\u1FEA'),
+  ('\u1F7B', '\u1FEB'),
+  ('\u1F7C', '\u1FFA'),
+  ('\u1F7D', '\u1FFB'),
+  ('\u1F80', '\u1F88'),
+  ('\u1F81', '\u1F89'),
+  ('\u1F82', '\u1F8A'),
+  ('\u1F83', '\u1F8B'),
+  ('\u1F84', '\u1F8C'),
+  ('\u1F85', '\u1F8D'),
+  ('\u1F86', '\u1F8E'),
+  ('\u1F87', '\u1F8F'),
+  ('\u1F88', '\u1F80'),
+  ('\u1F89', '\u1F81'),
+  ('\u1F8A', '\u1F82'),
+  ('\u1F8B', '\u1F83'),
+  ('\u1F8C', '\u1F84'),
+  ('\u1F8D', '\u1F85'),
+  ('\u1F8E', '\u1F86'),
+  ('\u1F8F', '\u1F87'),
+  ('\u1F90', '\u1F98'),
+  ('\u1F91', '\u1F99'),
+  ('\u1F92', '\u1F9A'),
+  ('\u1F93', '\u1F9B'),
+  ('\u1F94', '\u1F9C'),
+  ('\u1F95', '\u1F9D'),
+  ('\u1F96', '\u1F9E'),
+  ('\u1F97', '\u1F9F'),
+  ('\u1F98', '\u1F90'),
+  ('\u1F99', '\u1F91'),
+  ('\u1F9A', '\u1F92'),
+  ('\u1F9B', '\u1F93'),
+  ('\u1F9C', '\u1F94'),
+  ('\u1F9D', '\u1F95'),
+  ('\u1F9E', '\u1F96'),
+  ('\u1F9F', '\u1F97'),
+  ('\u1FA0', '\u1FA8'),
+  ('\u1FA1', '\u1FA9'),
+  ('\u1FA2', '\u1FAA'),
+  ('\u1FA3', '\u1FAB'),
+  ('\u1FA4', '\u1FAC'),
+  ('\u1FA5', '\u1FAD'),
+  ('\u1FA6', '\u1FAE'),
+  ('\u1FA7', '\u1FAF'),
+  ('\u1FA8', '\u1FA0'),
+  ('\u1FA9', '\u1FA1'),
+  ('\u1FAA', '\u1FA2'),
+  ('\u1FAB', '\u1FA3'),
+  ('\u1FAC', '\u1FA4'),
+  ('\u1FAD', '\u1FA5'),
+  ('\u1FAE', '\u1FA6'),
+  ('\u1FAF', '\u1FA7'),
+  ('\u1FB0', '\u1FB8'),
+  ('\u1FB1', '\u1FB9'),
+  ('\u1FB3', '\u1FBC'),
+  ('\u1FB8', '\u1FB0'),
+  ('\u1FB9', '\u1FB1'),
+  ('\u1FBA', '\u1F70'),
+  ('\u1FBB', '\u1F71'),
+  ('\u1FBC', '\u1FB3'),
+  ('\u1FBE', '\u0345'),
+  ('\u1FC3', '\u1FCC'),
+  ('\u1FC8', '\u1F72'),
+  ('\u1FC9', '\u1F73'),
+  ('\u1FCA', '\u1F74'),
+  ('\u1FCB', '\u1F75'),
+  ('\u1FCC', '\u1FC3'),
+  ('\u1FD0', '\u1FD8'),
+  ('\u1FD1', '\u1FD9'),
+  ('\u1FD8', '\u1FD0'),
+  ('\u1FD9', '\u1FD1'),
+  ('\u1FDA', '\u1F76'),
+  ('\u1FDB', '\u1F77'),
+  ('\u1FE0', '\u1FE8'),
+  ('\u1FE1', '\u1FE9'),
+  ('\u1FE5', '\u1FEC'),
+  ('\u1FE8', '\u1FE0'),
+  ('\u1FE9', '\u1FE1'),
+  ('\u1FEA', '\u1F7A'),
+  ('\u1FEB', '\u1F7B'),
+  ('\u1FEC', '\u1FE5'),
+  ('\u1FF3', '\u1FFC'),
+  ('\u1FF8', '\u1F78'),
+  ('\u1FF9', '\u1F79'),
+  ('\u1FFA', '\u1F7C'),
+  ('\u1FFB', '\u1F7D'),
+  ('\u1FFC', '\u1FF3'),
+  ('\u2126', '\u03A9'),
+  ('\u212A', '\u004B'),
+  ('\u212B', '\u00C5'),
+  ('\u2132', '\u214E'),
+  ('\u214E', '\u2132'),
+  ('\u2160', '\u2170'),
+  ('\u2161', '\u2171'),
+  ('\u2162', '\u2172'),
+  ('\u2163', '\u2173'),
+  ('\u2164', '\u2174'),
+  ('\u2165', '\u2175'),
+  ('\u2166', '\u2176'),
+  ('\u2167', '\u2177'),
+  ('\u2168', '\u2178'),
+  ('\u2169', '\u2179'),
+  ('\u216A', '\u217A'),
+  ('\u216B', '\u217B'),
+  ('\u216C', '\u217C'),
+  ('\u216D', '\u217D'),
+  ('\u216E', '\u217E'),
+  ('\u216F', '\u217F'),
+  ('\u2170', '\u2160'),
+  ('\u2171', '\u2161'),
+  ('\u2172', '\u2162'),
+  ('\u2173', '\u2163'),
+  ('\u2174', '\u2164'),
+  ('\u2175', '\u2165'),
+  ('\u2176', '\u2166'),
+  ('\u2177', '\u2167'),
+  ('\u2178', '\u2168'),
+  ('\u2179', '\u2169'),
+  ('\u217A', '\u216A'),
+  ('\u217B', '\u216B'),
+  ('\u217C', '\u216C'),
+  ('\u217D', '\u216D'),
+  ('\u217E', '\u216E'),
+  ('\u217F', '\u216F'),
+  ('\u2183', '\u2184'),
+  ('\u2184', '\u2183'),
+  ('\u24B6', '\u24D0'),
+  ('\u24B7', '\u24D1'),
+  ('\u24B8', '\u24D2'),
+  ('\u24B9', '\u24D3'),
+  ('\u24BA', '\u24D4'),
+  ('\u24BB', '\u24D5'),
+  ('\u24BC', '\u24D6'),
+  ('\u24BD', '\u24D7'),
+  ('\u24BE', '\u24D8'),
+  ('\u24BF', '\u24D9'),
+  ('\u24C0', '\u24DA'),
+  ('\u24C1', '\u24DB'),
+  ('\u24C2', '\u24DC'),
+  ('\u24C3', '\u24DD'),
+  ('\u24C4', '\u24DE'),
+  ('\u24C5', '\u24DF'),
+  ('\u24C6', '\u24E0'),
+  ('\u24C7', '\u24E1'),
+  ('\u24C8', '\u24E2'),
+  ('\u24C9', '\u24E3'),
+  ('\u24CA', '\u24E4'),
+  ('\u24CB', '\u24E5'),
+  ('\u24CC', '\u24E6'),
+  ('\u24CD', '\u24E7'),
+  ('\u24CE', '\u24E8'),
+  ('\u24CF', '\u24E9'),
+  ('\u24D0', '\u24B6'),
+  ('\u24D1', '\u24B7'),
+  ('\u24D2', '\u24B8'),
+  ('\u24D3', '\u24B9'),
+  ('\u24D4', '\u24BA'),
+  ('\u24D5', '\u24BB'),
+  ('\u24D6', '\u24BC'),
+  ('\u24D7', '\u24BD'),
+  ('\u24D8', '\u24BE'),
+  ('\u24D9', '\u24BF'),
+  ('\u24DA', '\u24C0'),
+  ('\u24DB', '\u24C1'),
+  ('\u24DC', '\u24C2'),
+  ('\u24DD', '\u24C3'),
+  ('\u24DE', '\u24C4'),
+  ('\u24DF', '\u24C5'),
+  ('\u24E0', '\u24C6'),
+  ('\u24E1', '\u24C7'),
+  ('\u24E2', '\u24C8'),
+  ('\u24E3', '\u24C9'),
+  ('\u24E4', '\u24CA'),
+  ('\u24E5', '\u24CB'),
+  ('\u24E6', '\u24CC'),
+  ('\u24E7', '\u24CD'),
+  ('\u24E8', '\u24CE'),
+  ('\u24E9', '\u24CF'),
+  ('\u2C00', '\u2C30'),
+  ('\u2C01', '\u2C31'),
+  ('\u2C02', '\u2C32'),
+  ('\u2C03', '\u2C33'),
+  ('\u2C04', '\u2C34'),
+  ('\u2C05', '\u2C35'),
+  ('\u2C06', '\u2C36'),
+  ('\u2C07', '\u2C37'),
+  ('\u2C08', '\u2C38'),
+  ('\u2C09', '\u2C39'),
+  ('\u2C0A', '\u2C3A'),
+  ('\u2C0B', '\u2C3B'),
+  ('\u2C0C', '\u2C3C'),
+  ('\u2C0D', '\u2C3D'),
+  ('\u2C0E', '\u2C3E'),
+  ('\u2C0F', '\u2C3F'),
+  ('\u2C10', '\u2C40'),
+  ('\u2C11', '\u2C41'),
+  ('\u2C12', '\u2C42'),
+  ('\u2C13', '\u2C43'),
+  ('\u2C14', '\u2C44'),
+  ('\u2C15', '\u2C45'),
+  ('\u2C16', '\u2C46'),
+  ('\u2C17', '\u2C47'),
+  ('\u2C18', '\u2C48'),
+  ('\u2C19', '\u2C49'),
+  ('\u2C1A', '\u2C4A'),
+  ('\u2C1B', '\u2C4B'),
+  ('\u2C1C', '\u2C4C'),
+  ('\u2C1D', '\u2C4D'),
+  ('\u2C1E', '\u2C4E'),
+  ('\u2C1F', '\u2C4F'),
+  ('\u2C20', '\u2C50'),
+  ('\u2C21', '\u2C51'),
+  ('\u2C22', '\u2C52'),
+  ('\u2C23', '\u2C53'),
+  ('\u2C24', '\u2C54'),
+  ('\u2C25', '\u2C55'),
+  ('\u2C26', '\u2C56'),
+  ('\u2C27', '\u2C57'),
+  ('\u2C28', '\u2C58'),
+  ('\u2C29', '\u2C59'),
+  ('\u2C2A', '\u2C5A'),
+  ('\u2C2B', '\u2C5B'),
+  ('\u2C2C', '\u2C5C'),
+  ('\u2C2D', '\u2C5D'),
+  ('\u2C2E', '\u2C5E'),
+  ('\u2C2F', '\u2C5F'),
+  ('\u2C30', '\u2C00'),
+  ('\u2C31', '\u2C01'),
+  ('\u2C32', '\u2C02'),
+  ('\u2C33', '\u2C03'),
+  ('\u2C34', '\u2C04'),
+  ('\u2C35', '\u2C05'),
+  ('\u2C36', '\u2C06'),
+  ('\u2C37', '\u2C07'),
+  ('\u2C38', '\u2C08'),
+  ('\u2C39', '\u2C09'),
+  ('\u2C3A', '\u2C0A'),
+  ('\u2C3B', '\u2C0B'),
+  ('\u2C3C', '\u2C0C'),
+  ('\u2C3D', '\u2C0D'),
+  ('\u2C3E', '\u2C0E'),
+  ('\u2C3F', '\u2C0F'),
+  ('\u2C40', '\u2C10'),
+  ('\u2C41', '\u2C11'),
+  ('\u2C42', '\u2C12'),
+  ('\u2C43', '\u2C13'),
+  ('\u2C44', '\u2C14'),
+  ('\u2C45', '\u2C15'),
+  ('\u2C46', '\u2C16'),
+  ('\u2C47', '\u2C17'),
+  ('\u2C48', '\u2C18'),
+  ('\u2C49', '\u2C19'),
+  ('\u2C4A', '\u2C1A'),
+  ('\u2C4B', '\u2C1B'),
+  ('\u2C4C', '\u2C1C'),
+  ('\u2C4D', '\u2C1D'),
+  ('\u2C4E', '\u2C1E'),
+  ('\u2C4F', '\u2C1F'),
+  ('\u2C50', '\u2C20'),
+  ('\u2C51', '\u2C21'),
+  ('\u2C52', '\u2C22'),
+  ('\u2C53', '\u2C23'),
+  ('\u2C54', '\u2C24'),
+  ('\u2C55', '\u2C25'),
+  ('\u2C56', '\u2C26'),
+  ('\u2C57', '\u2C27'),
+  ('\u2C58', '\u2C28'),
+  ('\u2C59', '\u2C29'),
+  ('\u2C5A', '\u2C2A'),
+  ('\u2C5B', '\u2C2B'),
+  ('\u2C5C', '\u2C2C'),
+  ('\u2C5D', '\u2C2D'),
+  ('\u2C5E', '\u2C2E'),
+  ('\u2C5F', '\u2C2F'),
+  ('\u2C60', '\u2C61'),
+  ('\u2C61', '\u2C60'),
+  ('\u2C62', '\u026B'),
+  ('\u2C63', '\u1D7D'),
+  ('\u2C64', '\u027D'),
+  ('\u2C65', '\u023A'),
+  ('\u2C66', '\u023E'),
+  ('\u2C67', '\u2C68'),
+  ('\u2C68', '\u2C67'),
+  ('\u2C69', '\u2C6A'),
+  ('\u2C6A', '\u2C69'),
+  ('\u2C6B', '\u2C6C'),
+  ('\u2C6C', '\u2C6B'),
+  ('\u2C6D', '\u0251'),
+  ('\u2C6E', '\u0271'),
+  ('\u2C6F', '\u0250'),
+  ('\u2C70', '\u0252'),
+  ('\u2C72', '\u2C73'),
+  ('\u2C73', '\u2C72'),
+  ('\u2C75', '\u2C76'),
+  ('\u2C76', '\u2C75'),
+  ('\u2C7E', '\u023F'),
+  ('\u2C7F', '\u0240'),
+  ('\u2C80', '\u2C81'),
+  ('\u2C81', '\u2C80'),
+  ('\u2C82', '\u2C83'),
+  ('\u2C83', '\u2C82'),
+  ('\u2C84', '\u2C85'),
+  ('\u2C85', '\u2C84'),
+  ('\u2C86', '\u2C87'),
+  ('\u2C87', '\u2C86'),
+  ('\u2C88', '\u2C89'),
+  ('\u2C89', '\u2C88'),
+  ('\u2C8A', '\u2C8B'),
+  ('\u2C8B', '\u2C8A'),
+  ('\u2C8C', '\u2C8D'),
+  ('\u2C8D', '\u2C8C'),
+  ('\u2C8E', '\u2C8F'),
+  ('\u2C8F', '\u2C8E'),
+  ('\u2C90', '\u2C91'),
+  ('\u2C91', '\u2C90'),
+  ('\u2C92', '\u2C93'),
+  ('\u2C93', '\u2C92'),
+  ('\u2C94', '\u2C95'),
+  ('\u2C95', '\u2C94'),
+  ('\u2C96', '\u2C97'),
+  ('\u2C97', '\u2C96'),
+  ('\u2C98', '\u2C99'),
+  ('\u2C99', '\u2C98'),
+  ('\u2C9A', '\u2C9B'),
+  ('\u2C9B', '\u2C9A'),
+  ('\u2C9C', '\u2C9D'),
+  ('\u2C9D', '\u2C9C'),
+  ('\u2C9E', '\u2C9F'),
+  ('\u2C9F', '\u2C9E'),
+  ('\u2CA0', '\u2CA1'),
+  ('\u2CA1', '\u2CA0'),
+  ('\u2CA2', '\u2CA3'),
+  ('\u2CA3', '\u2CA2'),
+  ('\u2CA4', '\u2CA5'),
+  ('\u2CA5', '\u2CA4'),
+  ('\u2CA6', '\u2CA7'),
+  ('\u2CA7', '\u2CA6'),
+  ('\u2CA8', '\u2CA9'),
+  ('\u2CA9', '\u2CA8'),
+  ('\u2CAA', '\u2CAB'),
+  ('\u2CAB', '\u2CAA'),
+  ('\u2CAC', '\u2CAD'),
+  ('\u2CAD', '\u2CAC'),
+  ('\u2CAE', '\u2CAF'),
+  ('\u2CAF', '\u2CAE'),
+  ('\u2CB0', '\u2CB1'),
+  ('\u2CB1', '\u2CB0'),
+  ('\u2CB2', '\u2CB3'),
+  ('\u2CB3', '\u2CB2'),
+  ('\u2CB4', '\u2CB5'),
+  ('\u2CB5', '\u2CB4'),
+  ('\u2CB6', '\u2CB7'),
+  ('\u2CB7', '\u2CB6'),
+  ('\u2CB8', '\u2CB9'),
+  ('\u2CB9', '\u2CB8'),
+  ('\u2CBA', '\u2CBB'),
+  ('\u2CBB', '\u2CBA'),
+  ('\u2CBC', '\u2CBD'),
+  ('\u2CBD', '\u2CBC'),
+  ('\u2CBE', '\u2CBF'),
+  ('\u2CBF', '\u2CBE'),
+  ('\u2CC0', '\u2CC1'),
+  ('\u2CC1', '\u2CC0'),
+  ('\u2CC2', '\u2CC3'),
+  ('\u2CC3', '\u2CC2'),
+  ('\u2CC4', '\u2CC5'),
+  ('\u2CC5', '\u2CC4'),
+  ('\u2CC6', '\u2CC7'),
+  ('\u2CC7', '\u2CC6'),
+  ('\u2CC8', '\u2CC9'),
+  ('\u2CC9', '\u2CC8'),
+  ('\u2CCA', '\u2CCB'),
+  ('\u2CCB', '\u2CCA'),
+  ('\u2CCC', '\u2CCD'),
+  ('\u2CCD', '\u2CCC'),
+  ('\u2CCE', '\u2CCF'),
+  ('\u2CCF', '\u2CCE'),
+  ('\u2CD0', '\u2CD1'),
+  ('\u2CD1', '\u2CD0'),
+  ('\u2CD2', '\u2CD3'),
+  ('\u2CD3', '\u2CD2'),
+  ('\u2CD4', '\u2CD5'),
+  ('\u2CD5', '\u2CD4'),
+  ('\u2CD6', '\u2CD7'),
+  ('\u2CD7', '\u2CD6'),
+  ('\u2CD8', '\u2CD9'),
+  ('\u2CD9', '\u2CD8'),
+  ('\u2CDA', '\u2CDB'),
+  ('\u2CDB', '\u2CDA'),
+  ('\u2CDC', '\u2CDD'),
+  ('\u2CDD', '\u2CDC'),
+  ('\u2CDE', '\u2CDF'),
+  ('\u2CDF', '\u2CDE'),
+  ('\u2CE0', '\u2CE1'),
+  ('\u2CE1', '\u2CE0'),
+  ('\u2CE2', '\u2CE3'),
+  ('\u2CE3', '\u2CE2'),
+  ('\u2CEB', '\u2CEC'),
+  ('\u2CEC', '\u2CEB'),
+  ('\u2CED', '\u2CEE'),
+  ('\u2CEE', '\u2CED'),
+  ('\u2CF2', '\u2CF3'),
+  ('\u2CF3', '\u2CF2'),
+  ('\u2D00', '\u10A0'),
+  ('\u2D01', '\u10A1'),
+  ('\u2D02', '\u10A2'),
+  ('\u2D03', '\u10A3'),
+  ('\u2D04', '\u10A4'),
+  ('\u2D05', '\u10A5'),
+  ('\u2D06', '\u10A6'),
+  ('\u2D07', '\u10A7'),
+  ('\u2D08', '\u10A8'),
+  ('\u2D09', '\u10A9'),
+  ('\u2D0A', '\u10AA'),
+  ('\u2D0B', '\u10AB'),
+  ('\u2D0C', '\u10AC'),
+  ('\u2D0D', '\u10AD'),
+  ('\u2D0E', '\u10AE'),
+  ('\u2D0F', '\u10AF'),
+  ('\u2D10', '\u10B0'),
+  ('\u2D11', '\u10B1'),
+  ('\u2D12', '\u10B2'),
+  ('\u2D13', '\u10B3'),
+  ('\u2D14', '\u10B4'),
+  ('\u2D15', '\u10B5'),
+  ('\u2D16', '\u10B6'),
+  ('\u2D17', '\u10B7'),
+  ('\u2D18', '\u10B8'),
+  ('\u2D19', '\u10B9'),
+  ('\u2D1A', '\u10BA'),
+  ('\u2D1B', '\u10BB'),
+  ('\u2D1C', '\u10BC'),
+  ('\u2D1D', '\u10BD'),
+  ('\u2D1E', '\u10BE'),
+  ('\u2D1F', '\u10BF'),
+  ('\u2D20', '\u10C0'),
+  ('\u2D21', '\u10C1'),
+  ('\u2D22', '\u10C2'),
+  ('\u2D23', '\u10C3'),
+  ('\u2D24', '\u10C4'),
+  ('\u2D25', '\u10C5'),
+  ('\u2D27', '\u10C7'),
+  ('\u2D2D', '\u10CD'),
+  ('\uA640', '\uA641'),
+  ('\uA641', '\uA640'),
+  ('\uA642', '\uA643'),
+  ('\uA643', '\uA642'),
+  ('\uA644', '\uA645'),
+  ('\uA645', '\uA644'),
+  ('\uA646', '\uA647'),
+  ('\uA647', '\uA646'),
+  ('\uA648', '\uA649'),
+  ('\uA649', '\uA648'),
+  ('\uA64A', '\uA64B'),
+  ('\uA64B', '\u1C88'),
+  ('\uA64C', '\uA64D'),
+  ('\uA64D', '\uA64C'),
+  ('\uA64E', '\uA64F'),
+  ('\uA64F', '\uA64E'),
+  ('\uA650', '\uA651'),
+  ('\uA651', '\uA650'),
+  ('\uA652', '\uA653'),
+  ('\uA653', '\uA652'),
+  ('\uA654', '\uA655'),
+  ('\uA655', '\uA654'),
+  ('\uA656', '\uA657'),
+  ('\uA657', '\uA656'),
+  ('\uA658', '\uA659'),
+  ('\uA659', '\uA658'),
+  ('\uA65A', '\uA65B'),
+  ('\uA65B', '\uA65A'),
+  ('\uA65C', '\uA65D'),
+  ('\uA65D', '\uA65C'),
+  ('\uA65E', '\uA65F'),
+  ('\uA65F', '\uA65E'),
+  ('\uA660', '\uA661'),
+  ('\uA661', '\uA660'),
+  ('\uA662', '\uA663'),
+  ('\uA663', '\uA662'),
+  ('\uA664', '\uA665'),
+  ('\uA665', '\uA664'),
+  ('\uA666', '\uA667'),
+  ('\uA667', '\uA666'),
+  ('\uA668', '\uA669'),
+  ('\uA669', '\uA668'),
+  ('\uA66A', '\uA66B'),
+  ('\uA66B', '\uA66A'),
+  ('\uA66C', '\uA66D'),
+  ('\uA66D', '\uA66C'),
+  ('\uA680', '\uA681'),
+  ('\uA681', '\uA680'),
+  ('\uA682', '\uA683'),
+  ('\uA683', '\uA682'),
+  ('\uA684', '\uA685'),
+  ('\uA685', '\uA684'),
+  ('\uA686', '\uA687'),
+  ('\uA687', '\uA686'),
+  ('\uA688', '\uA689'),
+  ('\uA689', '\uA688'),
+  ('\uA68A', '\uA68B'),
+  ('\uA68B', '\uA68A'),
+  ('\uA68C', '\uA68D'),
+  ('\uA68D', '\uA68C'),
+  ('\uA68E', '\uA68F'),
+  ('\uA68F', '\uA68E'),
+  ('\uA690', '\uA691'),
+  ('\uA691', '\uA690'),
+  ('\uA692', '\uA693'),
+  ('\uA693', '\uA692'),
+  ('\uA694', '\uA695'),
+  ('\uA695', '\uA694'),
+  ('\uA696', '\uA697'),
+  ('\uA697', '\uA696'),
+  ('\uA698', '\uA699'),
+  ('\uA699', '\uA698'),
+  ('\uA69A', '\uA69B'),
+  ('\uA69B', '\uA69A'),
+  ('\uA722', '\uA723'),
+  ('\uA723', '\uA722'),
+  ('\uA724', '\uA725'),
+  ('\uA725', '\uA724'),
+  ('\uA726', '\uA727'),
+  ('\uA727', '\uA726'),
+  ('\uA728', '\uA729'),
+  ('\uA729', '\uA728'),
+  ('\uA72A', '\uA72B'),
+  ('\uA72B', '\uA72A'),
+  ('\uA72C', '\uA72D'),
+  ('\uA72D', '\uA72C'),
+  ('\uA72E', '\uA72F'),
+  ('\uA72F', '\uA72E'),
+  ('\uA732', '\uA733'),
+  ('\uA733', '\uA732'),
+  ('\uA734', '\uA735'),
+  ('\uA735', '\uA734'),
+  ('\uA736', '\uA737'),
+  ('\uA737', '\uA736'),
+  ('\uA738', '\uA739'),
+  ('\uA739', '\uA738'),
+  ('\uA73A', '\uA73B'),
+  ('\uA73B', '\uA73A'),
+  ('\uA73C', '\uA73D'),
+  ('\uA73D', '\uA73C'),
+  ('\uA73E', '\uA73F'),
+  ('\uA73F', '\uA73E'),
+  ('\uA740', '\uA741'),
+  ('\uA741', '\uA740'),
+  ('\uA742', '\uA743'),
+  ('\uA743', '\uA742'),
+  ('\uA744', '\uA745'),
+  ('\uA745', '\uA744'),
+  ('\uA746', '\uA747'),
+  ('\uA747', '\uA746'),
+  ('\uA748', '\uA749'),
+  ('\uA749', '\uA748'),
+  ('\uA74A', '\uA74B'),
+  ('\uA74B', '\uA74A'),
+  ('\uA74C', '\uA74D'),
+  ('\uA74D', '\uA74C'),
+  ('\uA74E', '\uA74F'),
+  ('\uA74F', '\uA74E'),
+  ('\uA750', '\uA751'),
+  ('\uA751', '\uA750'),
+  ('\uA752', '\uA753'),
+  ('\uA753', '\uA752'),
+  ('\uA754', '\uA755'),
+  ('\uA755', '\uA754'),
+  ('\uA756', '\uA757'),
+  ('\uA757', '\uA756'),
+  ('\uA758', '\uA759'),
+  ('\uA759', '\uA758'),
+  ('\uA75A', '\uA75B'),
+  ('\uA75B', '\uA75A'),
+  ('\uA75C', '\uA75D'),
+  ('\uA75D', '\uA75C'),
+  ('\uA75E', '\uA75F'),
+  ('\uA75F', '\uA75E'),
+  ('\uA760', '\uA761'),
+  ('\uA761', '\uA760'),
+  ('\uA762', '\uA763'),
+  ('\uA763', '\uA762'),
+  ('\uA764', '\uA765'),
+  ('\uA765', '\uA764'),
+  ('\uA766', '\uA767'),
+  ('\uA767', '\uA766'),
+  ('\uA768', '\uA769'),
+  ('\uA769', '\uA768'),
+  ('\uA76A', '\uA76B'),
+  ('\uA76B', '\uA76A'),
+  ('\uA76C', '\uA76D'),
+  ('\uA76D', '\uA76C'),
+  ('\uA76E', '\uA76F'),
+  ('\uA76F', '\uA76E'),
+  ('\uA779', '\uA77A'),
+  ('\uA77A', '\uA779'),
+  ('\uA77B', '\uA77C'),
+  ('\uA77C', '\uA77B'),
+  ('\uA77D', '\u1D79'),
+  ('\uA77E', '\uA77F'),
+  ('\uA77F', '\uA77E'),
+  ('\uA780', '\uA781'),
+  ('\uA781', '\uA780'),
+  ('\uA782', '\uA783'),
+  ('\uA783', '\uA782'),
+  ('\uA784', '\uA785'),
+  ('\uA785', '\uA784'),
+  ('\uA786', '\uA787'),
+  ('\uA787', '\uA786'),
+  ('\uA78B', '\uA78C'),
+  ('\uA78C', '\uA78B'),
+  ('\uA78D', '\u0265'),
+  ('\uA790', '\uA791'),
+  ('\uA791', '\uA790'),
+  ('\uA792', '\uA793'),
+  ('\uA793', '\uA792'),
+  ('\uA794', '\uA7C4'),
+  ('\uA796', '\uA797'),
+  ('\uA797', '\uA796'),
+  ('\uA798', '\uA799'),
+  ('\uA799', '\uA798'),
+  ('\uA79A', '\uA79B'),
+  ('\uA79B', '\uA79A'),
+  ('\uA79C', '\uA79D'),
+  ('\uA79D', '\uA79C'),
+  ('\uA79E', '\uA79F'),
+  ('\uA79F', '\uA79E'),
+  ('\uA7A0', '\uA7A1'),
+  ('\uA7A1', '\uA7A0'),
+  ('\uA7A2', '\uA7A3'),
+  ('\uA7A3', '\uA7A2'),
+  ('\uA7A4', '\uA7A5'),
+  ('\uA7A5', '\uA7A4'),
+  ('\uA7A6', '\uA7A7'),
+  ('\uA7A7', '\uA7A6'),
+  ('\uA7A8', '\uA7A9'),
+  ('\uA7A9', '\uA7A8'),
+  ('\uA7AA', '\u0266'),
+  ('\uA7AB', '\u025C'),
+  ('\uA7AC', '\u0261'),
+  ('\uA7AD', '\u026C'),
+  ('\uA7AE', '\u026A'),
+  ('\uA7B0', '\u029E'),
+  ('\uA7B1', '\u0287'),
+  ('\uA7B2', '\u029D'),
+  ('\uA7B3', '\uAB53'),
+  ('\uA7B4', '\uA7B5'),
+  ('\uA7B5', '\uA7B4'),
+  ('\uA7B6', '\uA7B7'),
+  ('\uA7B7', '\uA7B6'),
+  ('\uA7B8', '\uA7B9'),
+  ('\uA7B9', '\uA7B8'),
+  ('\uA7BA', '\uA7BB'),
+  ('\uA7BB', '\uA7BA'),
+  ('\uA7BC', '\uA7BD'),
+  ('\uA7BD', '\uA7BC'),
+  ('\uA7BE', '\uA7BF'),
+  ('\uA7BF', '\uA7BE'),
+  ('\uA7C0', '\uA7C1'),
+  ('\uA7C1', '\uA7C0'),
+  ('\uA7C2', '\uA7C3'),
+  ('\uA7C3', '\uA7C2'),
+  ('\uA7C4', '\uA794'),
+  ('\uA7C5', '\u0282'),
+  ('\uA7C6', '\u1D8E'),
+  ('\uA7C7', '\uA7C8'),
+  ('\uA7C8', '\uA7C7'),
+  ('\uA7C9', '\uA7CA'),
+  ('\uA7CA', '\uA7C9'),
+  ('\uA7D0', '\uA7D1'),
+  ('\uA7D1', '\uA7D0'),
+  ('\uA7D6', '\uA7D7'),
+  ('\uA7D7', '\uA7D6'),
+  ('\uA7D8', '\uA7D9'),
+  ('\uA7D9', '\uA7D8'),
+  ('\uA7F5', '\uA7F6'),
+  ('\uA7F6', '\uA7F5'),
+  ('\uAB53', '\uA7B3'),
+  ('\uAB70', '\u13A0'),
+  ('\uAB71', '\u13A1'),
+  ('\uAB72', '\u13A2'),
+  ('\uAB73', '\u13A3'),
+  ('\uAB74', '\u13A4'),
+  ('\uAB75', '\u13A5'),
+  ('\uAB76', '\u13A6'),
+  ('\uAB77', '\u13A7'),
+  ('\uAB78', '\u13A8'),
+  ('\uAB79', '\u13A9'),
+  ('\uAB7A', '\u13AA'),
+  ('\uAB7B', '\u13AB'),
+  ('\uAB7C', '\u13AC'),
+  ('\uAB7D', '\u13AD'),
+  ('\uAB7E', '\u13AE'),
+  ('\uAB7F', '\u13AF'),
+  ('\uAB80', '\u13B0'),
+  ('\uAB81', '\u13B1'),
+  ('\uAB82', '\u13B2'),
+  ('\uAB83', '\u13B3'),
+  ('\uAB84', '\u13B4'),
+  ('\uAB85', '\u13B5'),
+  ('\uAB86', '\u13B6'),
+  ('\uAB87', '\u13B7'),
+  ('\uAB88', '\u13B8'),
+  ('\uAB89', '\u13B9'),
+  ('\uAB8A', '\u13BA'),
+  ('\uAB8B', '\u13BB'),
+  ('\uAB8C', '\u13BC'),
+  ('\uAB8D', '\u13BD'),
+  ('\uAB8E', '\u13BE'),
+  ('\uAB8F', '\u13BF'),
+  ('\uAB90', '\u13C0'),
+  ('\uAB91', '\u13C1'),
+  ('\uAB92', '\u13C2'),
+  ('\uAB93', '\u13C3'),
+  ('\uAB94', '\u13C4'),
+  ('\uAB95', '\u13C5'),
+  ('\uAB96', '\u13C6'),
+  ('\uAB97', '\u13C7'),
+  ('\uAB98', '\u13C8'),
+  ('\uAB99', '\u13C9'),
+  ('\uAB9A', '\u13CA'),
+  ('\uAB9B', '\u13CB'),
+  ('\uAB9C', '\u13CC'),
+  ('\uAB9D', '\u13CD'),
+  ('\uAB9E', '\u13CE'),
+  ('\uAB9F', '\u13CF'),
+  ('\uABA0', '\u13D0'),
+  ('\uABA1', '\u13D1'),
+  ('\uABA2', '\u13D2'),
+  ('\uABA3', '\u13D3'),
+  ('\uABA4', '\u13D4'),
+  ('\uABA5', '\u13D5'),
+  ('\uABA6', '\u13D6'),
+  ('\uABA7', '\u13D7'),
+  ('\uABA8', '\u13D8'),
+  ('\uABA9', '\u13D9'),
+  ('\uABAA', '\u13DA'),
+  ('\uABAB', '\u13DB'),
+  ('\uABAC', '\u13DC'),
+  ('\uABAD', '\u13DD'),
+  ('\uABAE', '\u13DE'),
+  ('\uABAF', '\u13DF'),
+  ('\uABB0', '\u13E0'),
+  ('\uABB1', '\u13E1'),
+  ('\uABB2', '\u13E2'),
+  ('\uABB3', '\u13E3'),
+  ('\uABB4', '\u13E4'),
+  ('\uABB5', '\u13E5'),
+  ('\uABB6', '\u13E6'),
+  ('\uABB7', '\u13E7'),
+  ('\uABB8', '\u13E8'),
+  ('\uABB9', '\u13E9'),
+  ('\uABBA', '\u13EA'),
+  ('\uABBB', '\u13EB'),
+  ('\uABBC', '\u13EC'),
+  ('\uABBD', '\u13ED'),
+  ('\uABBE', '\u13EE'),
+  ('\uABBF', '\u13EF'),
+  ('\uFF21', '\uFF41'),
+  ('\uFF22', '\uFF42'),
+  ('\uFF23', '\uFF43'),
+  ('\uFF24', '\uFF44'),
+  ('\uFF25', '\uFF45'),
+  ('\uFF26', '\uFF46'),
+  ('\uFF27', '\uFF47'),
+  ('\uFF28', '\uFF48'),
+  ('\uFF29', '\uFF49'),
+  ('\uFF2A', '\uFF4A'),
+  ('\uFF2B', '\uFF4B'),
+  ('\uFF2C', '\uFF4C'),
+  ('\uFF2D', '\uFF4D'),
+  ('\uFF2E', '\uFF4E'),
+  ('\uFF2F', '\uFF4F'),
+  ('\uFF30', '\uFF50'),
+  ('\uFF31', '\uFF51'),
+  ('\uFF32', '\uFF52'),
+  ('\uFF33', '\uFF53'),
+  ('\uFF34', '\uFF54'),
+  ('\uFF35', '\uFF55'),
+  ('\uFF36', '\uFF56'),
+  ('\uFF37', '\uFF57'),
+  ('\uFF38', '\uFF58'),
+  ('\uFF39', '\uFF59'),
+  ('\uFF3A', '\uFF5A'),
+  ('\uFF41', '\uFF21'),
+  ('\uFF42', '\uFF22'),
+  ('\uFF43', '\uFF23'),
+  ('\uFF44', '\uFF24'),
+  ('\uFF45', '\uFF25'),
+  ('\uFF46', '\uFF26'),
+  ('\uFF47', '\uFF27'),
+  ('\uFF48', '\uFF28'),
+  ('\uFF49', '\uFF29'),
+  ('\uFF4A', '\uFF2A'),
+  ('\uFF4B', '\uFF2B'),
+  ('\uFF4C', '\uFF2C'),
+  ('\uFF4D', '\uFF2D'),
+  ('\uFF4E', '\uFF2E'),
+  ('\uFF4F', '\uFF2F'),
+  ('\uFF50', '\uFF30'),
+  ('\uFF51', '\uFF31'),
+  ('\uFF52', '\uFF32'),
+  ('\uFF53', '\uFF33'),
+  ('\uFF54', '\uFF34'),
+  ('\uFF55', '\uFF35'),
+  ('\uFF56', '\uFF36'),
+  ('\uFF57', '\uFF37'),
+  ('\uFF58', '\uFF38'),
+  ('\uFF59', '\uFF39'),
+  ('\uFF5A', '\uFF3A'),
+  ('\U00010400', '\U00010428'),
+  ('\U00010401', '\U00010429'),
+  ('\U00010402', '\U0001042A'),
+  ('\U00010403', '\U0001042B'),
+  ('\U00010404', '\U0001042C'),
+  ('\U00010405', '\U0001042D'),
+  ('\U00010406', '\U0001042E'),
+  ('\U00010407', '\U0001042F'),
+  ('\U00010408', '\U00010430'),
+  ('\U00010409', '\U00010431'),
+  ('\U0001040A', '\U00010432'),
+  ('\U0001040B', '\U00010433'),
+  ('\U0001040C', '\U00010434'),
+  ('\U0001040D', '\U00010435'),
+  ('\U0001040E', '\U00010436'),
+  ('\U0001040F', '\U00010437'),
+  ('\U00010410', '\U00010438'),
+  ('\U00010411', '\U00010439'),
+  ('\U00010412', '\U0001043A'),
+  ('\U00010413', '\U0001043B'),
+  ('\U00010414', '\U0001043C'),
+  ('\U00010415', '\U0001043D'),
+  ('\U00010416', '\U0001043E'),
+  ('\U00010417', '\U0001043F'),
+  ('\U00010418', '\U00010440'),
+  ('\U00010419', '\U00010441'),
+  ('\U0001041A', '\U00010442'),
+  ('\U0001041B', '\U00010443'),
+  ('\U0001041C', '\U00010444'),
+  ('\U0001041D', '\U00010445'),
+  ('\U0001041E', '\U00010446'),
+  ('\U0001041F', '\U00010447'),
+  ('\U00010420', '\U00010448'),
+  ('\U00010421', '\U00010449'),
+  ('\U00010422', '\U0001044A'),
+  ('\U00010423', '\U0001044B'),
+  ('\U00010424', '\U0001044C'),
+  ('\U00010425', '\U0001044D'),
+  ('\U00010426', '\U0001044E'),
+  ('\U00010427', '\U0001044F'),
+  ('\U00010428', '\U00010400'),
+  ('\U00010429', '\U00010401'),
+  ('\U0001042A', '\U00010402'),
+  ('\U0001042B', '\U00010403'),
+  ('\U0001042C', '\U00010404'),
+  ('\U0001042D', '\U00010405'),
+  ('\U0001042E', '\U00010406'),
+  ('\U0001042F', '\U00010407'),
+  ('\U00010430', '\U00010408'),
+  ('\U00010431', '\U00010409'),
+  ('\U00010432', '\U0001040A'),
+  ('\U00010433', '\U0001040B'),
+  ('\U00010434', '\U0001040C'),
+  ('\U00010435', '\U0001040D'),
+  ('\U00010436', '\U0001040E'),
+  ('\U00010437', '\U0001040F'),
+  ('\U00010438', '\U00010410'),
+  ('\U00010439', '\U00010411'),
+  ('\U0001043A', '\U00010412'),
+  ('\U0001043B', '\U00010413'),
+  ('\U0001043C', '\U00010414'),
+  ('\U0001043D', '\U00010415'),
+  ('\U0001043E', '\U00010416'),
+  ('\U0001043F', '\U00010417'),
+  ('\U00010440', '\U00010418'),
+  ('\U00010441', '\U00010419'),
+  ('\U00010442', '\U0001041A'),
+  ('\U00010443', '\U0001041B'),
+  ('\U00010444', '\U0001041C'),
+  ('\U00010445', '\U0001041D'),
+  ('\U00010446', '\U0001041E'),
+  ('\U00010447', '\U0001041F'),
+  ('\U00010448', '\U00010420'),
+  ('\U00010449', '\U00010421'),
+  ('\U0001044A', '\U00010422'),
+  ('\U0001044B', '\U00010423'),
+  ('\U0001044C', '\U00010424'),
+  ('\U0001044D', '\U00010425'),
+  ('\U0001044E', '\U00010426'),
+  ('\U0001044F', '\U00010427'),
+  ('\U000104B0', '\U000104D8'),
+  ('\U000104B1', '\U000104D9'),
+  ('\U000104B2', '\U000104DA'),
+  ('\U000104B3', '\U000104DB'),
+  ('\U000104B4', '\U000104DC'),
+  ('\U000104B5', '\U000104DD'),
+  ('\U000104B6', '\U000104DE'),
+  ('\U000104B7', '\U000104DF'),
+  ('\U000104B8', '\U000104E0'),
+  ('\U000104B9', '\U000104E1'),
+  ('\U000104BA', '\U000104E2'),
+  ('\U000104BB', '\U000104E3'),
+  ('\U000104BC', '\U000104E4'),
+  ('\U000104BD', '\U000104E5'),
+  ('\U000104BE', '\U000104E6'),
+  ('\U000104BF', '\U000104E7'),
+  ('\U000104C0', '\U000104E8'),
+  ('\U000104C1', '\U000104E9'),
+  ('\U000104C2', '\U000104EA'),
+  ('\U000104C3', '\U000104EB'),
+  ('\U000104C4', '\U000104EC'),
+  ('\U000104C5', '\U000104ED'),
+  ('\U000104C6', '\U000104EE'),
+  ('\U000104C7', '\U000104EF'),
+  ('\U000104C8', '\U000104F0'),
+  ('\U000104C9', '\U000104F1'),
+  ('\U000104CA', '\U000104F2'),
+  ('\U000104CB', '\U000104F3'),
+  ('\U000104CC', '\U000104F4'),
+  ('\U000104CD', '\U000104F5'),
+  ('\U000104CE', '\U000104F6'),
+  ('\U000104CF', '\U000104F7'),
+  ('\U000104D0', '\U000104F8'),
+  ('\U000104D1', '\U000104F9'),
+  ('\U000104D2', '\U000104FA'),
+  ('\U000104D3', '\U000104FB'),
+  ('\U000104D8', '\U000104B0'),
+  ('\U000104D9', '\U000104B1'),
+  ('\U000104DA', '\U000104B2'),
+  ('\U000104DB', '\U000104B3'),
+  ('\U000104DC', '\U000104B4'),
+  ('\U000104DD', '\U000104B5'),
+  ('\U000104DE', '\U000104B6'),
+  ('\U000104DF', '\U000104B7'),
+  ('\U000104E0', '\U000104B8'),
+  ('\U000104E1', '\U000104B9'),
+  ('\U000104E2', '\U000104BA'),
+  ('\U000104E3', '\U000104BB'),
+  ('\U000104E4', '\U000104BC'),
+  ('\U000104E5', '\U000104BD'),
+  ('\U000104E6', '\U000104BE'),
+  ('\U000104E7', '\U000104BF'),
+  ('\U000104E8', '\U000104C0'),
+  ('\U000104E9', '\U000104C1'),
+  ('\U000104EA', '\U000104C2'),
+  ('\U000104EB', '\U000104C3'),
+  ('\U000104EC', '\U000104C4'),
+  ('\U000104ED', '\U000104C5'),
+  ('\U000104EE', '\U000104C6'),
+  ('\U000104EF', '\U000104C7'),
+  ('\U000104F0', '\U000104C8'),
+  ('\U000104F1', '\U000104C9'),
+  ('\U000104F2', '\U000104CA'),
+  ('\U000104F3', '\U000104CB'),
+  ('\U000104F4', '\U000104CC'),
+  ('\U000104F5', '\U000104CD'),
+  ('\U000104F6', '\U000104CE'),
+  ('\U000104F7', '\U000104CF'),
+  ('\U000104F8', '\U000104D0'),
+  ('\U000104F9', '\U000104D1'),
+  ('\U000104FA', '\U000104D2'),
+  ('\U000104FB', '\U000104D3'),
+  ('\U00010570', '\U00010597'),
+  ('\U00010571', '\U00010598'),
+  ('\U00010572', '\U00010599'),
+  ('\U00010573', '\U0001059A'),
+  ('\U00010574', '\U0001059B'),
+  ('\U00010575', '\U0001059C'),
+  ('\U00010576', '\U0001059D'),
+  ('\U00010577', '\U0001059E'),
+  ('\U00010578', '\U0001059F'),
+  ('\U00010579', '\U000105A0'),
+  ('\U0001057A', '\U000105A1'),
+  ('\U0001057C', '\U000105A3'),
+  ('\U0001057D', '\U000105A4'),
+  ('\U0001057E', '\U000105A5'),
+  ('\U0001057F', '\U000105A6'),
+  ('\U00010580', '\U000105A7'),
+  ('\U00010581', '\U000105A8'),
+  ('\U00010582', '\U000105A9'),
+  ('\U00010583', '\U000105AA'),
+  ('\U00010584', '\U000105AB'),
+  ('\U00010585', '\U000105AC'),
+  ('\U00010586', '\U000105AD'),
+  ('\U00010587', '\U000105AE'),
+  ('\U00010588', '\U000105AF'),
+  ('\U00010589', '\U000105B0'),
+  ('\U0001058A', '\U000105B1'),
+  ('\U0001058C', '\U000105B3'),
+  ('\U0001058D', '\U000105B4'),
+  ('\U0001058E', '\U000105B5'),
+  ('\U0001058F', '\U000105B6'),
+  ('\U00010590', '\U000105B7'),
+  ('\U00010591', '\U000105B8'),
+  ('\U00010592', '\U000105B9'),
+  ('\U00010594', '\U000105BB'),
+  ('\U00010595', '\U000105BC'),
+  ('\U00010597', '\U00010570'),
+  ('\U00010598', '\U00010571'),
+  ('\U00010599', '\U00010572'),
+  ('\U0001059A', '\U00010573'),
+  ('\U0001059B', '\U00010574'),
+  ('\U0001059C', '\U00010575'),
+  ('\U0001059D', '\U00010576'),
+  ('\U0001059E', '\U00010577'),
+  ('\U0001059F', '\U00010578'),
+  ('\U000105A0', '\U00010579'),
+  ('\U000105A1', '\U0001057A'),
+  ('\U000105A3', '\U0001057C'),
+  ('\U000105A4', '\U0001057D'),
+  ('\U000105A5', '\U0001057E'),
+  ('\U000105A6', '\U0001057F'),
+  ('\U000105A7', '\U00010580'),
+  ('\U000105A8', '\U00010581'),
+  ('\U000105A9', '\U00010582'),
+  ('\U000105AA', '\U00010583'),
+  ('\U000105AB', '\U00010584'),
+  ('\U000105AC', '\U00010585'),
+  ('\U000105AD', '\U00010586'),
+  ('\U000105AE', '\U00010587'),
+  ('\U000105AF', '\U00010588'),
+  ('\U000105B0', '\U00010589'),
+  ('\U000105B1', '\U0001058A'),
+  ('\U000105B3', '\U0001058C'),
+  ('\U000105B4', '\U0001058D'),
+  ('\U000105B5', '\U0001058E'),
+  ('\U000105B6', '\U0001058F'),
+  ('\U000105B7', '\U00010590'),
+  ('\U000105B8', '\U00010591'),
+  ('\U000105B9', '\U00010592'),
+  ('\U000105BB', '\U00010594'),
+  ('\U000105BC', '\U00010595'),
+  ('\U00010C80', '\U00010CC0'),
+  ('\U00010C81', '\U00010CC1'),
+  ('\U00010C82', '\U00010CC2'),
+  ('\U00010C83', '\U00010CC3'),
+  ('\U00010C84', '\U00010CC4'),
+  ('\U00010C85', '\U00010CC5'),
+  ('\U00010C86', '\U00010CC6'),
+  ('\U00010C87', '\U00010CC7'),
+  ('\U00010C88', '\U00010CC8'),
+  ('\U00010C89', '\U00010CC9'),
+  ('\U00010C8A', '\U00010CCA'),
+  ('\U00010C8B', '\U00010CCB'),
+  ('\U00010C8C', '\U00010CCC'),
+  ('\U00010C8D', '\U00010CCD'),
+  ('\U00010C8E', '\U00010CCE'),
+  ('\U00010C8F', '\U00010CCF'),
+  ('\U00010C90', '\U00010CD0'),
+  ('\U00010C91', '\U00010CD1'),
+  ('\U00010C92', '\U00010CD2'),
+  ('\U00010C93', '\U00010CD3'),
+  ('\U00010C94', '\U00010CD4'),
+  ('\U00010C95', '\U00010CD5'),
+  ('\U00010C96', '\U00010CD6'),
+  ('\U00010C97', '\U00010CD7'),
+  ('\U00010C98', '\U00010CD8'),
+  ('\U00010C99', '\U00010CD9'),
+  ('\U00010C9A', '\U00010CDA'),
+  ('\U00010C9B', '\U00010CDB'),
+  ('\U00010C9C', '\U00010CDC'),
+  ('\U00010C9D', '\U00010CDD'),
+  ('\U00010C9E', '\U00010CDE'),
+  ('\U00010C9F', '\U00010CDF'),
+  ('\U00010CA0', '\U00010CE0'),
+  ('\U00010CA1', '\U00010CE1'),
+  ('\U00010CA2', '\U00010CE2'),
+  ('\U00010CA3', '\U00010CE3'),
+  ('\U00010CA4', '\U00010CE4'),
+  ('\U00010CA5', '\U00010CE5'),
+  ('\U00010CA6', '\U00010CE6'),
+  ('\U00010CA7', '\U00010CE7'),
+  ('\U00010CA8', '\U00010CE8'),
+  ('\U00010CA9', '\U00010CE9'),
+  ('\U00010CAA', '\U00010CEA'),
+  ('\U00010CAB', '\U00010CEB'),
+  ('\U00010CAC', '\U00010CEC'),
+  ('\U00010CAD', '\U00010CED'),
+  ('\U00010CAE', '\U00010CEE'),
+  ('\U00010CAF', '\U00010CEF'),
+  ('\U00010CB0', '\U00010CF0'),
+  ('\U00010CB1', '\U00010CF1'),
+  ('\U00010CB2', '\U00010CF2'),
+  ('\U00010CC0', '\U00010C80'),
+  ('\U00010CC1', '\U00010C81'),
+  ('\U00010CC2', '\U00010C82'),
+  ('\U00010CC3', '\U00010C83'),
+  ('\U00010CC4', '\U00010C84'),
+  ('\U00010CC5', '\U00010C85'),
+  ('\U00010CC6', '\U00010C86'),
+  ('\U00010CC7', '\U00010C87'),
+  ('\U00010CC8', '\U00010C88'),
+  ('\U00010CC9', '\U00010C89'),
+  ('\U00010CCA', '\U00010C8A'),
+  ('\U00010CCB', '\U00010C8B'),
+  ('\U00010CCC', '\U00010C8C'),
+  ('\U00010CCD', '\U00010C8D'),
+  ('\U00010CCE', '\U00010C8E'),
+  ('\U00010CCF', '\U00010C8F'),
+  ('\U00010CD0', '\U00010C90'),
+  ('\U00010CD1', '\U00010C91'),
+  ('\U00010CD2', '\U00010C92'),
+  ('\U00010CD3', '\U00010C93'),
+  ('\U00010CD4', '\U00010C94'),
+  ('\U00010CD5', '\U00010C95'),
+  ('\U00010CD6', '\U00010C96'),
+  ('\U00010CD7', '\U00010C97'),
+  ('\U00010CD8', '\U00010C98'),
+  ('\U00010CD9', '\U00010C99'),
+  ('\U00010CDA', '\U00010C9A'),
+  ('\U00010CDB', '\U00010C9B'),
+  ('\U00010CDC', '\U00010C9C'),
+  ('\U00010CDD', '\U00010C9D'),
+  ('\U00010CDE', '\U00010C9E'),
+  ('\U00010CDF', '\U00010C9F'),
+  ('\U00010CE0', '\U00010CA0'),
+  ('\U00010CE1', '\U00010CA1'),
+  ('\U00010CE2', '\U00010CA2'),
+  ('\U00010CE3', '\U00010CA3'),
+  ('\U00010CE4', '\U00010CA4'),
+  ('\U00010CE5', '\U00010CA5'),
+  ('\U00010CE6', '\U00010CA6'),
+  ('\U00010CE7', '\U00010CA7'),
+  ('\U00010CE8', '\U00010CA8'),
+  ('\U00010CE9', '\U00010CA9'),
+  ('\U00010CEA', '\U00010CAA'),
+  ('\U00010CEB', '\U00010CAB'),
+  ('\U00010CEC', '\U00010CAC'),
+  ('\U00010CED', '\U00010CAD'),
+  ('\U00010CEE', '\U00010CAE'),
+  ('\U00010CEF', '\U00010CAF'),
+  ('\U00010CF0', '\U00010CB0'),
+  ('\U00010CF1', '\U00010CB1'),
+  ('\U00010CF2', '\U00010CB2'),
+  ('\U000118A0', '\U000118C0'),
+  ('\U000118A1', '\U000118C1'),
+  ('\U000118A2', '\U000118C2'),
+  ('\U000118A3', '\U000118C3'),
+  ('\U000118A4', '\U000118C4'),
+  ('\U000118A5', '\U000118C5'),
+  ('\U000118A6', '\U000118C6'),
+  ('\U000118A7', '\U000118C7'),
+  ('\U000118A8', '\U000118C8'),
+  ('\U000118A9', '\U000118C9'),
+  ('\U000118AA', '\U000118CA'),
+  ('\U000118AB', '\U000118CB'),
+  ('\U000118AC', '\U000118CC'),
+  ('\U000118AD', '\U000118CD'),
+  ('\U000118AE', '\U000118CE'),
+  ('\U000118AF', '\U000118CF'),
+  ('\U000118B0', '\U000118D0'),
+  ('\U000118B1', '\U000118D1'),
+  ('\U000118B2', '\U000118D2'),
+  ('\U000118B3', '\U000118D3'),
+  ('\U000118B4', '\U000118D4'),
+  ('\U000118B5', '\U000118D5'),
+  ('\U000118B6', '\U000118D6'),
+  ('\U000118B7', '\U000118D7'),
+  ('\U000118B8', '\U000118D8'),
+  ('\U000118B9', '\U000118D9'),
+  ('\U000118BA', '\U000118DA'),
+  ('\U000118BB', '\U000118DB'),
+  ('\U000118BC', '\U000118DC'),
+  ('\U000118BD', '\U000118DD'),
+  ('\U000118BE', '\U000118DE'),
+  ('\U000118BF', '\U000118DF'),
+  ('\U000118C0', '\U000118A0'),
+  ('\U000118C1', '\U000118A1'),
+  ('\U000118C2', '\U000118A2'),
+  ('\U000118C3', '\U000118A3'),
+  ('\U000118C4', '\U000118A4'),
+  ('\U000118C5', '\U000118A5'),
+  ('\U000118C6', '\U000118A6'),
+  ('\U000118C7', '\U000118A7'),
+  ('\U000118C8', '\U000118A8'),
+  ('\U000118C9', '\U000118A9'),
+  ('\U000118CA', '\U000118AA'),
+  ('\U000118CB', '\U000118AB'),
+  ('\U000118CC', '\U000118AC'),
+  ('\U000118CD', '\U000118AD'),
+  ('\U000118CE', '\U000118AE'),
+  ('\U000118CF', '\U000118AF'),
+  ('\U000118D0', '\U000118B0'),
+  ('\U000118D1', '\U000118B1'),
+  ('\U000118D2', '\U000118B2'),
+  ('\U000118D3', '\U000118B3'),
+  ('\U000118D4', '\U000118B4'),
+  ('\U000118D5', '\U000118B5'),
+  ('\U000118D6', '\U000118B6'),
+  ('\U000118D7', '\U000118B7'),
+  ('\U000118D8', '\U000118B8'),
+  ('\U000118D9', '\U000118B9'),
+  ('\U000118DA', '\U000118BA'),
+  ('\U000118DB', '\U000118BB'),
+  ('\U000118DC', '\U000118BC'),
+  ('\U000118DD', '\U000118BD'),
+  ('\U000118DE', '\U000118BE'),
+  ('\U000118DF', '\U000118BF'),
+  ('\U00016E40', '\U00016E60'),
+  ('\U00016E41', '\U00016E61'),
+  ('\U00016E42', '\U00016E62'),
+  ('\U00016E43', '\U00016E63'),
+  ('\U00016E44', '\U00016E64'),
+  ('\U00016E45', '\U00016E65'),
+  ('\U00016E46', '\U00016E66'),
+  ('\U00016E47', '\U00016E67'),
+  ('\U00016E48', '\U00016E68'),
+  ('\U00016E49', '\U00016E69'),
+  ('\U00016E4A', '\U00016E6A'),
+  ('\U00016E4B', '\U00016E6B'),
+  ('\U00016E4C', '\U00016E6C'),
+  ('\U00016E4D', '\U00016E6D'),
+  ('\U00016E4E', '\U00016E6E'),
+  ('\U00016E4F', '\U00016E6F'),
+  ('\U00016E50', '\U00016E70'),
+  ('\U00016E51', '\U00016E71'),
+  ('\U00016E52', '\U00016E72'),
+  ('\U00016E53', '\U00016E73'),
+  ('\U00016E54', '\U00016E74'),
+  ('\U00016E55', '\U00016E75'),
+  ('\U00016E56', '\U00016E76'),
+  ('\U00016E57', '\U00016E77'),
+  ('\U00016E58', '\U00016E78'),
+  ('\U00016E59', '\U00016E79'),
+  ('\U00016E5A', '\U00016E7A'),
+  ('\U00016E5B', '\U00016E7B'),
+  ('\U00016E5C', '\U00016E7C'),
+  ('\U00016E5D', '\U00016E7D'),
+  ('\U00016E5E', '\U00016E7E'),
+  ('\U00016E5F', '\U00016E7F'),
+  ('\U00016E60', '\U00016E40'),
+  ('\U00016E61', '\U00016E41'),
+  ('\U00016E62', '\U00016E42'),
+  ('\U00016E63', '\U00016E43'),
+  ('\U00016E64', '\U00016E44'),
+  ('\U00016E65', '\U00016E45'),
+  ('\U00016E66', '\U00016E46'),
+  ('\U00016E67', '\U00016E47'),
+  ('\U00016E68', '\U00016E48'),
+  ('\U00016E69', '\U00016E49'),
+  ('\U00016E6A', '\U00016E4A'),
+  ('\U00016E6B', '\U00016E4B'),
+  ('\U00016E6C', '\U00016E4C'),
+  ('\U00016E6D', '\U00016E4D'),
+  ('\U00016E6E', '\U00016E4E'),
+  ('\U00016E6F', '\U00016E4F'),
+  ('\U00016E70', '\U00016E50'),
+  ('\U00016E71', '\U00016E51'),
+  ('\U00016E72', '\U00016E52'),
+  ('\U00016E73', '\U00016E53'),
+  ('\U00016E74', '\U00016E54'),
+  ('\U00016E75', '\U00016E55'),
+  ('\U00016E76', '\U00016E56'),
+  ('\U00016E77', '\U00016E57'),
+  ('\U00016E78', '\U00016E58'),
+  ('\U00016E79', '\U00016E59'),
+  ('\U00016E7A', '\U00016E5A'),
+  ('\U00016E7B', '\U00016E5B'),
+  ('\U00016E7C', '\U00016E5C'),
+  ('\U00016E7D', '\U00016E5D'),
+  ('\U00016E7E', '\U00016E5E'),
+  ('\U00016E7F', '\U00016E5F'),
+  ('\U0001E900', '\U0001E922'),
+  ('\U0001E901', '\U0001E923'),
+  ('\U0001E902', '\U0001E924'),
+  ('\U0001E903', '\U0001E925'),
+  ('\U0001E904', '\U0001E926'),
+  ('\U0001E905', '\U0001E927'),
+  ('\U0001E906', '\U0001E928'),
+  ('\U0001E907', '\U0001E929'),
+  ('\U0001E908', '\U0001E92A'),
+  ('\U0001E909', '\U0001E92B'),
+  ('\U0001E90A', '\U0001E92C'),
+  ('\U0001E90B', '\U0001E92D'),
+  ('\U0001E90C', '\U0001E92E'),
+  ('\U0001E90D', '\U0001E92F'),
+  ('\U0001E90E', '\U0001E930'),
+  ('\U0001E90F', '\U0001E931'),
+  ('\U0001E910', '\U0001E932'),
+  ('\U0001E911', '\U0001E933'),
+  ('\U0001E912', '\U0001E934'),
+  ('\U0001E913', '\U0001E935'),
+  ('\U0001E914', '\U0001E936'),
+  ('\U0001E915', '\U0001E937'),
+  ('\U0001E916', '\U0001E938'),
+  ('\U0001E917', '\U0001E939'),
+  ('\U0001E918', '\U0001E93A'),
+  ('\U0001E919', '\U0001E93B'),
+  ('\U0001E91A', '\U0001E93C'),
+  ('\U0001E91B', '\U0001E93D'),
+  ('\U0001E91C', '\U0001E93E'),
+  ('\U0001E91D', '\U0001E93F'),
+  ('\U0001E91E', '\U0001E940'),
+  ('\U0001E91F', '\U0001E941'),
+  ('\U0001E920', '\U0001E942'),
+  ('\U0001E921', '\U0001E943'),
+  ('\U0001E922', '\U0001E900'),
+  ('\U0001E923', '\U0001E901'),
+  ('\U0001E924', '\U0001E902'),
+  ('\U0001E925', '\U0001E903'),
+  ('\U0001E926', '\U0001E904'),
+  ('\U0001E927', '\U0001E905'),
+  ('\U0001E928', '\U0001E906'),
+  ('\U0001E929', '\U0001E907'),
+  ('\U0001E92A', '\U0001E908'),
+  ('\U0001E92B', '\U0001E909'),
+  ('\U0001E92C', '\U0001E90A'),
+  ('\U0001E92D', '\U0001E90B'),
+  ('\U0001E92E', '\U0001E90C'),
+  ('\U0001E92F', '\U0001E90D'),
+  ('\U0001E930', '\U0001E90E'),
+  ('\U0001E931', '\U0001E90F'),
+  ('\U0001E932', '\U0001E910'),
+  ('\U0001E933', '\U0001E911'),
+  ('\U0001E934', '\U0001E912'),
+  ('\U0001E935', '\U0001E913'),
+  ('\U0001E936', '\U0001E914'),
+  ('\U0001E937', '\U0001E915'),
+  ('\U0001E938', '\U0001E916'),
+  ('\U0001E939', '\U0001E917'),
+  ('\U0001E93A', '\U0001E918'),
+  ('\U0001E93B', '\U0001E919'),
+  ('\U0001E93C', '\U0001E91A'),
+  ('\U0001E93D', '\U0001E91B'),
+  ('\U0001E93E', '\U0001E91C'),
+  ('\U0001E93F', '\U0001E91D'),
+  ('\U0001E940', '\U0001E91E'),
+  ('\U0001E941', '\U0001E91F'),
+  ('\U0001E942', '\U0001E920'),
+  ('\U0001E943', '\U0001E921')
+];
+
+/// Unicode `\d`
+pub static PerlDigit: &'static [(char, char)] = &[
+  ('\u0030', '\u0039'),
+  ('\u0660', '\u0669'),
+  ('\u06F0', '\u06F9'),
+  ('\u07C0', '\u07C9'),
+  ('\u0966', '\u096F'),
+  ('\u09E6', '\u09EF'),
+  ('\u0A66', '\u0A6F'),
+  ('\u0AE6', '\u0AEF'),
+  ('\u0B66', '\u0B6F'),
+  ('\u0BE6', '\u0BEF'),
+  ('\u0C66', '\u0C6F'),
+  ('\u0CE6', '\u0CEF'),
+  ('\u0D66', '\u0D6F'),
+  ('\u0DE6', '\u0DEF'),
+  ('\u0E50', '\u0E59'),
+  ('\u0ED0', '\u0ED9'),
+  ('\u0F20', '\u0F29'),
+  ('\u1040', '\u1049'),
+  ('\u1090', '\u1099'),
+  ('\u17E0', '\u17E9'),
+  ('\u1810', '\u1819'),
+  ('\u1946', '\u194F'),
+  ('\u19D0', '\u19D9'),
+  ('\u1A80', '\u1A89'),
+  ('\u1A90', '\u1A99'),
+  ('\u1B50', '\u1B59'),
+  ('\u1BB0', '\u1BB9'),
+  ('\u1C40', '\u1C49'),
+  ('\u1C50', '\u1C59'),
+  ('\uA620', '\uA629'),
+  ('\uA8D0', '\uA8D9'),
+  ('\uA900', '\uA909'),
+  ('\uA9D0', '\uA9D9'),
+  ('\uA9F0', '\uA9F9'),
+  ('\uAA50', '\uAA59'),
+  ('\uABF0', '\uABF9'),
+  ('\uFF10', '\uFF19'),
+  ('\U000104A0', '\U000104A9'),
+  ('\U00010D30', '\U00010D39'),
+  ('\U00011066', '\U0001106F'),
+  ('\U000110F0', '\U000110F9'),
+  ('\U00011136', '\U0001113F'),
+  ('\U000111D0', '\U000111D9'),
+  ('\U000112F0', '\U000112F9'),
+  ('\U00011450', '\U00011459'),
+  ('\U000114D0', '\U000114D9'),
+  ('\U00011650', '\U00011659'),
+  ('\U000116C0', '\U000116C9'),
+  ('\U00011730', '\U00011739'),
+  ('\U000118E0', '\U000118E9'),
+  ('\U00011950', '\U00011959'),
+  ('\U00011C50', '\U00011C59'),
+  ('\U00011D50', '\U00011D59'),
+  ('\U00011DA0', '\U00011DA9'),
+  ('\U00016A60', '\U00016A69'),
+  ('\U00016AC0', '\U00016AC9'),
+  ('\U00016B50', '\U00016B59'),
+  ('\U0001D7CE', '\U0001D7FF'),
+  ('\U0001E140', '\U0001E149'),
+  ('\U0001E2F0', '\U0001E2F9'),
+  ('\U0001E950', '\U0001E959'),
+  ('\U0001FBF0', '\U0001FBF9')
+];
+
+/// Unicode `\D`
+pub static NegatedPerlDigit: &'static [(char, char)] = &[
+  ('\u0000', '\u002F'),
+  ('\u003A', '\u065F'),
+  ('\u066A', '\u06EF'),
+  ('\u06FA', '\u07BF'),
+  ('\u07CA', '\u0965'),
+  ('\u0970', '\u09E5'),
+  ('\u09F0', '\u0A65'),
+  ('\u0A70', '\u0AE5'),
+  ('\u0AF0', '\u0B65'),
+  ('\u0B70', '\u0BE5'),
+  ('\u0BF0', '\u0C65'),
+  ('\u0C70', '\u0CE5'),
+  ('\u0CF0', '\u0D65'),
+  ('\u0D70', '\u0DE5'),
+  ('\u0DF0', '\u0E4F'),
+  ('\u0E5A', '\u0ECF'),
+  ('\u0EDA', '\u0F1F'),
+  ('\u0F2A', '\u103F'),
+  ('\u104A', '\u108F'),
+  ('\u109A', '\u17DF'),
+  ('\u17EA', '\u180F'),
+  ('\u181A', '\u1945'),
+  ('\u1950', '\u19CF'),
+  ('\u19DA', '\u1A7F'),
+  ('\u1A8A', '\u1A8F'),
+  ('\u1A9A', '\u1B4F'),
+  ('\u1B5A', '\u1BAF'),
+  ('\u1BBA', '\u1C3F'),
+  ('\u1C4A', '\u1C4F'),
+  ('\u1C5A', '\uA61F'),
+  ('\uA62A', '\uA8CF'),
+  ('\uA8DA', '\uA8FF'),
+  ('\uA90A', '\uA9CF'),
+  ('\uA9DA', '\uA9EF'),
+  ('\uA9FA', '\uAA4F'),
+  ('\uAA5A', '\uABEF'),
+  ('\uABFA', '\uD7FF'),
+  ('\uE000', '\uFF0F'),
+  ('\uFF1A', '\U0001049F'),
+  ('\U000104AA', '\U00010D2F'),
+  ('\U00010D3A', '\U00011065'),
+  ('\U00011070', '\U000110EF'),
+  ('\U000110FA', '\U00011135'),
+  ('\U00011140', '\U000111CF'),
+  ('\U000111DA', '\U000112EF'),
+  ('\U000112FA', '\U0001144F'),
+  ('\U0001145A', '\U000114CF'),
+  ('\U000114DA', '\U0001164F'),
+  ('\U0001165A', '\U000116BF'),
+  ('\U000116CA', '\U0001172F'),
+  ('\U0001173A', '\U000118DF'),
+  ('\U000118EA', '\U0001194F'),
+  ('\U0001195A', '\U00011C4F'),
+  ('\U00011C5A', '\U00011D4F'),
+  ('\U00011D5A', '\U00011D9F'),
+  ('\U00011DAA', '\U00016A5F'),
+  ('\U00016A6A', '\U00016ABF'),
+  ('\U00016ACA', '\U00016B4F'),
+  ('\U00016B5A', '\U0001D7CD'),
+  ('\U0001D800', '\U0001E13F'),
+  ('\U0001E14A', '\U0001E2EF'),
+  ('\U0001E2FA', '\U0001E94F'),
+  ('\U0001E95A', '\U0001FBEF'),
+  ('\U0001FBFA', '\U0010FFFF')
+];
+
+/// Unicode `\s`
+pub static PerlSpace: &'static [(char, char)] = &[
+  ('\u0009', '\u000D'),
+  ('\u0020', '\u0020'),
+  ('\u0085', '\u0085'),
+  ('\u00A0', '\u00A0'),
+  ('\u1680', '\u1680'),
+  ('\u2000', '\u200A'),
+  ('\u2028', '\u2029'),
+  ('\u202F', '\u202F'),
+  ('\u205F', '\u205F'),
+  ('\u3000', '\u3000')
+];
+
+/// Unicode `\S`
+pub static NegatedPerlSpace: &'static [(char, char)] = &[
+  ('\u0000', '\u0008'),
+  ('\u000E', '\u001F'),
+  ('\u0021', '\u0084'),
+  ('\u0086', '\u009F'),
+  ('\u00A1', '\u167F'),
+  ('\u1681', '\u1FFF'),
+  ('\u200B', '\u2027'),
+  ('\u202A', '\u202E'),
+  ('\u2030', '\u205E'),
+  ('\u2060', '\u2FFF'),
+  ('\u3001', '\uD7FF'),
+  ('\uE000', '\U0010FFFF')
+];
+
+/// Unicode `\w`
+pub static PerlWord: &'static [(char, char)] = &[
+  ('\u0030', '\u0039'),
+  ('\u0041', '\u005A'),
+  ('\u005F', '\u005F'),
+  ('\u0061', '\u007A'),
+  ('\u00AA', '\u00AA'),
+  ('\u00B5', '\u00B5'),
+  ('\u00BA', '\u00BA'),
+  ('\u00C0', '\u00D6'),
+  ('\u00D8', '\u00F6'),
+  ('\u00F8', '\u02C1'),
+  ('\u02C6', '\u02D1'),
+  ('\u02E0', '\u02E4'),
+  ('\u02EC', '\u02EC'),
+  ('\u02EE', '\u02EE'),
+  ('\u0300', '\u0374'),
+  ('\u0376', '\u0377'),
+  ('\u037A', '\u037D'),
+  ('\u037F', '\u037F'),
+  ('\u0386', '\u0386'),
+  ('\u0388', '\u038A'),
+  ('\u038C', '\u038C'),
+  ('\u038E', '\u03A1'),
+  ('\u03A3', '\u03F5'),
+  ('\u03F7', '\u0481'),
+  ('\u0483', '\u052F'),
+  ('\u0531', '\u0556'),
+  ('\u0559', '\u0559'),
+  ('\u0560', '\u0588'),
+  ('\u0591', '\u05BD'),
+  ('\u05BF', '\u05BF'),
+  ('\u05C1', '\u05C2'),
+  ('\u05C4', '\u05C5'),
+  ('\u05C7', '\u05C7'),
+  ('\u05D0', '\u05EA'),
+  ('\u05EF', '\u05F2'),
+  ('\u0610', '\u061A'),
+  ('\u0620', '\u0669'),
+  ('\u066E', '\u06D3'),
+  ('\u06D5', '\u06DC'),
+  ('\u06DF', '\u06E8'),
+  ('\u06EA', '\u06FC'),
+  ('\u06FF', '\u06FF'),
+  ('\u0710', '\u074A'),
+  ('\u074D', '\u07B1'),
+  ('\u07C0', '\u07F5'),
+  ('\u07FA', '\u07FA'),
+  ('\u07FD', '\u07FD'),
+  ('\u0800', '\u082D'),
+  ('\u0840', '\u085B'),
+  ('\u0860', '\u086A'),
+  ('\u0870', '\u0887'),
+  ('\u0889', '\u088E'),
+  ('\u0898', '\u08E1'),
+  ('\u08E3', '\u0963'),
+  ('\u0966', '\u096F'),
+  ('\u0971', '\u0983'),
+  ('\u0985', '\u098C'),
+  ('\u098F', '\u0990'),
+  ('\u0993', '\u09A8'),
+  ('\u09AA', '\u09B0'),
+  ('\u09B2', '\u09B2'),
+  ('\u09B6', '\u09B9'),
+  ('\u09BC', '\u09C4'),
+  ('\u09C7', '\u09C8'),
+  ('\u09CB', '\u09CE'),
+  ('\u09D7', '\u09D7'),
+  ('\u09DC', '\u09DD'),
+  ('\u09DF', '\u09E3'),
+  ('\u09E6', '\u09F1'),
+  ('\u09FC', '\u09FC'),
+  ('\u09FE', '\u09FE'),
+  ('\u0A01', '\u0A03'),
+  ('\u0A05', '\u0A0A'),
+  ('\u0A0F', '\u0A10'),
+  ('\u0A13', '\u0A28'),
+  ('\u0A2A', '\u0A30'),
+  ('\u0A32', '\u0A33'),
+  ('\u0A35', '\u0A36'),
+  ('\u0A38', '\u0A39'),
+  ('\u0A3C', '\u0A3C'),
+  ('\u0A3E', '\u0A42'),
+  ('\u0A47', '\u0A48'),
+  ('\u0A4B', '\u0A4D'),
+  ('\u0A51', '\u0A51'),
+  ('\u0A59', '\u0A5C'),
+  ('\u0A5E', '\u0A5E'),
+  ('\u0A66', '\u0A75'),
+  ('\u0A81', '\u0A83'),
+  ('\u0A85', '\u0A8D'),
+  ('\u0A8F', '\u0A91'),
+  ('\u0A93', '\u0AA8'),
+  ('\u0AAA', '\u0AB0'),
+  ('\u0AB2', '\u0AB3'),
+  ('\u0AB5', '\u0AB9'),
+  ('\u0ABC', '\u0AC5'),
+  ('\u0AC7', '\u0AC9'),
+  ('\u0ACB', '\u0ACD'),
+  ('\u0AD0', '\u0AD0'),
+  ('\u0AE0', '\u0AE3'),
+  ('\u0AE6', '\u0AEF'),
+  ('\u0AF9', '\u0AFF'),
+  ('\u0B01', '\u0B03'),
+  ('\u0B05', '\u0B0C'),
+  ('\u0B0F', '\u0B10'),
+  ('\u0B13', '\u0B28'),
+  ('\u0B2A', '\u0B30'),
+  ('\u0B32', '\u0B33'),
+  ('\u0B35', '\u0B39'),
+  ('\u0B3C', '\u0B44'),
+  ('\u0B47', '\u0B48'),
+  ('\u0B4B', '\u0B4D'),
+  ('\u0B55', '\u0B57'),
+  ('\u0B5C', '\u0B5D'),
+  ('\u0B5F', '\u0B63'),
+  ('\u0B66', '\u0B6F'),
+  ('\u0B71', '\u0B71'),
+  ('\u0B82', '\u0B83'),
+  ('\u0B85', '\u0B8A'),
+  ('\u0B8E', '\u0B90'),
+  ('\u0B92', '\u0B95'),
+  ('\u0B99', '\u0B9A'),
+  ('\u0B9C', '\u0B9C'),
+  ('\u0B9E', '\u0B9F'),
+  ('\u0BA3', '\u0BA4'),
+  ('\u0BA8', '\u0BAA'),
+  ('\u0BAE', '\u0BB9'),
+  ('\u0BBE', '\u0BC2'),
+  ('\u0BC6', '\u0BC8'),
+  ('\u0BCA', '\u0BCD'),
+  ('\u0BD0', '\u0BD0'),
+  ('\u0BD7', '\u0BD7'),
+  ('\u0BE6', '\u0BEF'),
+  ('\u0C00', '\u0C0C'),
+  ('\u0C0E', '\u0C10'),
+  ('\u0C12', '\u0C28'),
+  ('\u0C2A', '\u0C39'),
+  ('\u0C3C', '\u0C44'),
+  ('\u0C46', '\u0C48'),
+  ('\u0C4A', '\u0C4D'),
+  ('\u0C55', '\u0C56'),
+  ('\u0C58', '\u0C5A'),
+  ('\u0C5D', '\u0C5D'),
+  ('\u0C60', '\u0C63'),
+  ('\u0C66', '\u0C6F'),
+  ('\u0C80', '\u0C83'),
+  ('\u0C85', '\u0C8C'),
+  ('\u0C8E', '\u0C90'),
+  ('\u0C92', '\u0CA8'),
+  ('\u0CAA', '\u0CB3'),
+  ('\u0CB5', '\u0CB9'),
+  ('\u0CBC', '\u0CC4'),
+  ('\u0CC6', '\u0CC8'),
+  ('\u0CCA', '\u0CCD'),
+  ('\u0CD5', '\u0CD6'),
+  ('\u0CDD', '\u0CDE'),
+  ('\u0CE0', '\u0CE3'),
+  ('\u0CE6', '\u0CEF'),
+  ('\u0CF1', '\u0CF2'),
+  ('\u0D00', '\u0D0C'),
+  ('\u0D0E', '\u0D10'),
+  ('\u0D12', '\u0D44'),
+  ('\u0D46', '\u0D48'),
+  ('\u0D4A', '\u0D4E'),
+  ('\u0D54', '\u0D57'),
+  ('\u0D5F', '\u0D63'),
+  ('\u0D66', '\u0D6F'),
+  ('\u0D7A', '\u0D7F'),
+  ('\u0D81', '\u0D83'),
+  ('\u0D85', '\u0D96'),
+  ('\u0D9A', '\u0DB1'),
+  ('\u0DB3', '\u0DBB'),
+  ('\u0DBD', '\u0DBD'),
+  ('\u0DC0', '\u0DC6'),
+  ('\u0DCA', '\u0DCA'),
+  ('\u0DCF', '\u0DD4'),
+  ('\u0DD6', '\u0DD6'),
+  ('\u0DD8', '\u0DDF'),
+  ('\u0DE6', '\u0DEF'),
+  ('\u0DF2', '\u0DF3'),
+  ('\u0E01', '\u0E3A'),
+  ('\u0E40', '\u0E4E'),
+  ('\u0E50', '\u0E59'),
+  ('\u0E81', '\u0E82'),
+  ('\u0E84', '\u0E84'),
+  ('\u0E86', '\u0E8A'),
+  ('\u0E8C', '\u0EA3'),
+  ('\u0EA5', '\u0EA5'),
+  ('\u0EA7', '\u0EBD'),
+  ('\u0EC0', '\u0EC4'),
+  ('\u0EC6', '\u0EC6'),
+  ('\u0EC8', '\u0ECD'),
+  ('\u0ED0', '\u0ED9'),
+  ('\u0EDC', '\u0EDF'),
+  ('\u0F00', '\u0F00'),
+  ('\u0F18', '\u0F19'),
+  ('\u0F20', '\u0F29'),
+  ('\u0F35', '\u0F35'),
+  ('\u0F37', '\u0F37'),
+  ('\u0F39', '\u0F39'),
+  ('\u0F3E', '\u0F47'),
+  ('\u0F49', '\u0F6C'),
+  ('\u0F71', '\u0F84'),
+  ('\u0F86', '\u0F97'),
+  ('\u0F99', '\u0FBC'),
+  ('\u0FC6', '\u0FC6'),
+  ('\u1000', '\u1049'),
+  ('\u1050', '\u109D'),
+  ('\u10A0', '\u10C5'),
+  ('\u10C7', '\u10C7'),
+  ('\u10CD', '\u10CD'),
+  ('\u10D0', '\u10FA'),
+  ('\u10FC', '\u1248'),
+  ('\u124A', '\u124D'),
+  ('\u1250', '\u1256'),
+  ('\u1258', '\u1258'),
+  ('\u125A', '\u125D'),
+  ('\u1260', '\u1288'),
+  ('\u128A', '\u128D'),
+  ('\u1290', '\u12B0'),
+  ('\u12B2', '\u12B5'),
+  ('\u12B8', '\u12BE'),
+  ('\u12C0', '\u12C0'),
+  ('\u12C2', '\u12C5'),
+  ('\u12C8', '\u12D6'),
+  ('\u12D8', '\u1310'),
+  ('\u1312', '\u1315'),
+  ('\u1318', '\u135A'),
+  ('\u135D', '\u135F'),
+  ('\u1380', '\u138F'),
+  ('\u13A0', '\u13F5'),
+  ('\u13F8', '\u13FD'),
+  ('\u1401', '\u166C'),
+  ('\u166F', '\u167F'),
+  ('\u1681', '\u169A'),
+  ('\u16A0', '\u16EA'),
+  ('\u16EE', '\u16F8'),
+  ('\u1700', '\u1715'),
+  ('\u171F', '\u1734'),
+  ('\u1740', '\u1753'),
+  ('\u1760', '\u176C'),
+  ('\u176E', '\u1770'),
+  ('\u1772', '\u1773'),
+  ('\u1780', '\u17D3'),
+  ('\u17D7', '\u17D7'),
+  ('\u17DC', '\u17DD'),
+  ('\u17E0', '\u17E9'),
+  ('\u180B', '\u180D'),
+  ('\u180F', '\u1819'),
+  ('\u1820', '\u1878'),
+  ('\u1880', '\u18AA'),
+  ('\u18B0', '\u18F5'),
+  ('\u1900', '\u191E'),
+  ('\u1920', '\u192B'),
+  ('\u1930', '\u193B'),
+  ('\u1946', '\u196D'),
+  ('\u1970', '\u1974'),
+  ('\u1980', '\u19AB'),
+  ('\u19B0', '\u19C9'),
+  ('\u19D0', '\u19D9'),
+  ('\u1A00', '\u1A1B'),
+  ('\u1A20', '\u1A5E'),
+  ('\u1A60', '\u1A7C'),
+  ('\u1A7F', '\u1A89'),
+  ('\u1A90', '\u1A99'),
+  ('\u1AA7', '\u1AA7'),
+  ('\u1AB0', '\u1ACE'),
+  ('\u1B00', '\u1B4C'),
+  ('\u1B50', '\u1B59'),
+  ('\u1B6B', '\u1B73'),
+  ('\u1B80', '\u1BF3'),
+  ('\u1C00', '\u1C37'),
+  ('\u1C40', '\u1C49'),
+  ('\u1C4D', '\u1C7D'),
+  ('\u1C80', '\u1C88'),
+  ('\u1C90', '\u1CBA'),
+  ('\u1CBD', '\u1CBF'),
+  ('\u1CD0', '\u1CD2'),
+  ('\u1CD4', '\u1CFA'),
+  ('\u1D00', '\u1F15'),
+  ('\u1F18', '\u1F1D'),
+  ('\u1F20', '\u1F45'),
+  ('\u1F48', '\u1F4D'),
+  ('\u1F50', '\u1F57'),
+  ('\u1F59', '\u1F59'),
+  ('\u1F5B', '\u1F5B'),
+  ('\u1F5D', '\u1F5D'),
+  ('\u1F5F', '\u1F7D'),
+  ('\u1F80', '\u1FB4'),
+  ('\u1FB6', '\u1FBC'),
+  ('\u1FBE', '\u1FBE'),
+  ('\u1FC2', '\u1FC4'),
+  ('\u1FC6', '\u1FCC'),
+  ('\u1FD0', '\u1FD3'),
+  ('\u1FD6', '\u1FDB'),
+  ('\u1FE0', '\u1FEC'),
+  ('\u1FF2', '\u1FF4'),
+  ('\u1FF6', '\u1FFC'),
+  ('\u200C', '\u200D'),
+  ('\u203F', '\u2040'),
+  ('\u2054', '\u2054'),
+  ('\u2071', '\u2071'),
+  ('\u207F', '\u207F'),
+  ('\u2090', '\u209C'),
+  ('\u20D0', '\u20F0'),
+  ('\u2102', '\u2102'),
+  ('\u2107', '\u2107'),
+  ('\u210A', '\u2113'),
+  ('\u2115', '\u2115'),
+  ('\u2119', '\u211D'),
+  ('\u2124', '\u2124'),
+  ('\u2126', '\u2126'),
+  ('\u2128', '\u2128'),
+  ('\u212A', '\u212D'),
+  ('\u212F', '\u2139'),
+  ('\u213C', '\u213F'),
+  ('\u2145', '\u2149'),
+  ('\u214E', '\u214E'),
+  ('\u2160', '\u2188'),
+  ('\u24B6', '\u24E9'),
+  ('\u2C00', '\u2CE4'),
+  ('\u2CEB', '\u2CF3'),
+  ('\u2D00', '\u2D25'),
+  ('\u2D27', '\u2D27'),
+  ('\u2D2D', '\u2D2D'),
+  ('\u2D30', '\u2D67'),
+  ('\u2D6F', '\u2D6F'),
+  ('\u2D7F', '\u2D96'),
+  ('\u2DA0', '\u2DA6'),
+  ('\u2DA8', '\u2DAE'),
+  ('\u2DB0', '\u2DB6'),
+  ('\u2DB8', '\u2DBE'),
+  ('\u2DC0', '\u2DC6'),
+  ('\u2DC8', '\u2DCE'),
+  ('\u2DD0', '\u2DD6'),
+  ('\u2DD8', '\u2DDE'),
+  ('\u2DE0', '\u2DFF'),
+  ('\u2E2F', '\u2E2F'),
+  ('\u3005', '\u3007'),
+  ('\u3021', '\u302F'),
+  ('\u3031', '\u3035'),
+  ('\u3038', '\u303C'),
+  ('\u3041', '\u3096'),
+  ('\u3099', '\u309A'),
+  ('\u309D', '\u309F'),
+  ('\u30A1', '\u30FA'),
+  ('\u30FC', '\u30FF'),
+  ('\u3105', '\u312F'),
+  ('\u3131', '\u318E'),
+  ('\u31A0', '\u31BF'),
+  ('\u31F0', '\u31FF'),
+  ('\u3400', '\u4DBF'),
+  ('\u4E00', '\uA48C'),
+  ('\uA4D0', '\uA4FD'),
+  ('\uA500', '\uA60C'),
+  ('\uA610', '\uA62B'),
+  ('\uA640', '\uA672'),
+  ('\uA674', '\uA67D'),
+  ('\uA67F', '\uA6F1'),
+  ('\uA717', '\uA71F'),
+  ('\uA722', '\uA788'),
+  ('\uA78B', '\uA7CA'),
+  ('\uA7D0', '\uA7D1'),
+  ('\uA7D3', '\uA7D3'),
+  ('\uA7D5', '\uA7D9'),
+  ('\uA7F2', '\uA827'),
+  ('\uA82C', '\uA82C'),
+  ('\uA840', '\uA873'),
+  ('\uA880', '\uA8C5'),
+  ('\uA8D0', '\uA8D9'),
+  ('\uA8E0', '\uA8F7'),
+  ('\uA8FB', '\uA8FB'),
+  ('\uA8FD', '\uA92D'),
+  ('\uA930', '\uA953'),
+  ('\uA960', '\uA97C'),
+  ('\uA980', '\uA9C0'),
+  ('\uA9CF', '\uA9D9'),
+  ('\uA9E0', '\uA9FE'),
+  ('\uAA00', '\uAA36'),
+  ('\uAA40', '\uAA4D'),
+  ('\uAA50', '\uAA59'),
+  ('\uAA60', '\uAA76'),
+  ('\uAA7A', '\uAAC2'),
+  ('\uAADB', '\uAADD'),
+  ('\uAAE0', '\uAAEF'),
+  ('\uAAF2', '\uAAF6'),
+  ('\uAB01', '\uAB06'),
+  ('\uAB09', '\uAB0E'),
+  ('\uAB11', '\uAB16'),
+  ('\uAB20', '\uAB26'),
+  ('\uAB28', '\uAB2E'),
+  ('\uAB30', '\uAB5A'),
+  ('\uAB5C', '\uAB69'),
+  ('\uAB70', '\uABEA'),
+  ('\uABEC', '\uABED'),
+  ('\uABF0', '\uABF9'),
+  ('\uAC00', '\uD7A3'),
+  ('\uD7B0', '\uD7C6'),
+  ('\uD7CB', '\uD7FB'),
+  ('\uF900', '\uFA6D'),
+  ('\uFA70', '\uFAD9'),
+  ('\uFB00', '\uFB06'),
+  ('\uFB13', '\uFB17'),
+  ('\uFB1D', '\uFB28'),
+  ('\uFB2A', '\uFB36'),
+  ('\uFB38', '\uFB3C'),
+  ('\uFB3E', '\uFB3E'),
+  ('\uFB40', '\uFB41'),
+  ('\uFB43', '\uFB44'),
+  ('\uFB46', '\uFBB1'),
+  ('\uFBD3', '\uFD3D'),
+  ('\uFD50', '\uFD8F'),
+  ('\uFD92', '\uFDC7'),
+  ('\uFDF0', '\uFDFB'),
+  ('\uFE00', '\uFE0F'),
+  ('\uFE20', '\uFE2F'),
+  ('\uFE33', '\uFE34'),
+  ('\uFE4D', '\uFE4F'),
+  ('\uFE70', '\uFE74'),
+  ('\uFE76', '\uFEFC'),
+  ('\uFF10', '\uFF19'),
+  ('\uFF21', '\uFF3A'),
+  ('\uFF3F', '\uFF3F'),
+  ('\uFF41', '\uFF5A'),
+  ('\uFF66', '\uFFBE'),
+  ('\uFFC2', '\uFFC7'),
+  ('\uFFCA', '\uFFCF'),
+  ('\uFFD2', '\uFFD7'),
+  ('\uFFDA', '\uFFDC'),
+  ('\U00010000', '\U0001000B'),
+  ('\U0001000D', '\U00010026'),
+  ('\U00010028', '\U0001003A'),
+  ('\U0001003C', '\U0001003D'),
+  ('\U0001003F', '\U0001004D'),
+  ('\U00010050', '\U0001005D'),
+  ('\U00010080', '\U000100FA'),
+  ('\U00010140', '\U00010174'),
+  ('\U000101FD', '\U000101FD'),
+  ('\U00010280', '\U0001029C'),
+  ('\U000102A0', '\U000102D0'),
+  ('\U000102E0', '\U000102E0'),
+  ('\U00010300', '\U0001031F'),
+  ('\U0001032D', '\U0001034A'),
+  ('\U00010350', '\U0001037A'),
+  ('\U00010380', '\U0001039D'),
+  ('\U000103A0', '\U000103C3'),
+  ('\U000103C8', '\U000103CF'),
+  ('\U000103D1', '\U000103D5'),
+  ('\U00010400', '\U0001049D'),
+  ('\U000104A0', '\U000104A9'),
+  ('\U000104B0', '\U000104D3'),
+  ('\U000104D8', '\U000104FB'),
+  ('\U00010500', '\U00010527'),
+  ('\U00010530', '\U00010563'),
+  ('\U00010570', '\U0001057A'),
+  ('\U0001057C', '\U0001058A'),
+  ('\U0001058C', '\U00010592'),
+  ('\U00010594', '\U00010595'),
+  ('\U00010597', '\U000105A1'),
+  ('\U000105A3', '\U000105B1'),
+  ('\U000105B3', '\U000105B9'),
+  ('\U000105BB', '\U000105BC'),
+  ('\U00010600', '\U00010736'),
+  ('\U00010740', '\U00010755'),
+  ('\U00010760', '\U00010767'),
+  ('\U00010780', '\U00010785'),
+  ('\U00010787', '\U000107B0'),
+  ('\U000107B2', '\U000107BA'),
+  ('\U00010800', '\U00010805'),
+  ('\U00010808', '\U00010808'),
+  ('\U0001080A', '\U00010835'),
+  ('\U00010837', '\U00010838'),
+  ('\U0001083C', '\U0001083C'),
+  ('\U0001083F', '\U00010855'),
+  ('\U00010860', '\U00010876'),
+  ('\U00010880', '\U0001089E'),
+  ('\U000108E0', '\U000108F2'),
+  ('\U000108F4', '\U000108F5'),
+  ('\U00010900', '\U00010915'),
+  ('\U00010920', '\U00010939'),
+  ('\U00010980', '\U000109B7'),
+  ('\U000109BE', '\U000109BF'),
+  ('\U00010A00', '\U00010A03'),
+  ('\U00010A05', '\U00010A06'),
+  ('\U00010A0C', '\U00010A13'),
+  ('\U00010A15', '\U00010A17'),
+  ('\U00010A19', '\U00010A35'),
+  ('\U00010A38', '\U00010A3A'),
+  ('\U00010A3F', '\U00010A3F'),
+  ('\U00010A60', '\U00010A7C'),
+  ('\U00010A80', '\U00010A9C'),
+  ('\U00010AC0', '\U00010AC7'),
+  ('\U00010AC9', '\U00010AE6'),
+  ('\U00010B00', '\U00010B35'),
+  ('\U00010B40', '\U00010B55'),
+  ('\U00010B60', '\U00010B72'),
+  ('\U00010B80', '\U00010B91'),
+  ('\U00010C00', '\U00010C48'),
+  ('\U00010C80', '\U00010CB2'),
+  ('\U00010CC0', '\U00010CF2'),
+  ('\U00010D00', '\U00010D27'),
+  ('\U00010D30', '\U00010D39'),
+  ('\U00010E80', '\U00010EA9'),
+  ('\U00010EAB', '\U00010EAC'),
+  ('\U00010EB0', '\U00010EB1'),
+  ('\U00010F00', '\U00010F1C'),
+  ('\U00010F27', '\U00010F27'),
+  ('\U00010F30', '\U00010F50'),
+  ('\U00010F70', '\U00010F85'),
+  ('\U00010FB0', '\U00010FC4'),
+  ('\U00010FE0', '\U00010FF6'),
+  ('\U00011000', '\U00011046'),
+  ('\U00011066', '\U00011075'),
+  ('\U0001107F', '\U000110BA'),
+  ('\U000110C2', '\U000110C2'),
+  ('\U000110D0', '\U000110E8'),
+  ('\U000110F0', '\U000110F9'),
+  ('\U00011100', '\U00011134'),
+  ('\U00011136', '\U0001113F'),
+  ('\U00011144', '\U00011147'),
+  ('\U00011150', '\U00011173'),
+  ('\U00011176', '\U00011176'),
+  ('\U00011180', '\U000111C4'),
+  ('\U000111C9', '\U000111CC'),
+  ('\U000111CE', '\U000111DA'),
+  ('\U000111DC', '\U000111DC'),
+  ('\U00011200', '\U00011211'),
+  ('\U00011213', '\U00011237'),
+  ('\U0001123E', '\U0001123E'),
+  ('\U00011280', '\U00011286'),
+  ('\U00011288', '\U00011288'),
+  ('\U0001128A', '\U0001128D'),
+  ('\U0001128F', '\U0001129D'),
+  ('\U0001129F', '\U000112A8'),
+  ('\U000112B0', '\U000112EA'),
+  ('\U000112F0', '\U000112F9'),
+  ('\U00011300', '\U00011303'),
+  ('\U00011305', '\U0001130C'),
+  ('\U0001130F', '\U00011310'),
+  ('\U00011313', '\U00011328'),
+  ('\U0001132A', '\U00011330'),
+  ('\U00011332', '\U00011333'),
+  ('\U00011335', '\U00011339'),
+  ('\U0001133B', '\U00011344'),
+  ('\U00011347', '\U00011348'),
+  ('\U0001134B', '\U0001134D'),
+  ('\U00011350', '\U00011350'),
+  ('\U00011357', '\U00011357'),
+  ('\U0001135D', '\U00011363'),
+  ('\U00011366', '\U0001136C'),
+  ('\U00011370', '\U00011374'),
+  ('\U00011400', '\U0001144A'),
+  ('\U00011450', '\U00011459'),
+  ('\U0001145E', '\U00011461'),
+  ('\U00011480', '\U000114C5'),
+  ('\U000114C7', '\U000114C7'),
+  ('\U000114D0', '\U000114D9'),
+  ('\U00011580', '\U000115B5'),
+  ('\U000115B8', '\U000115C0'),
+  ('\U000115D8', '\U000115DD'),
+  ('\U00011600', '\U00011640'),
+  ('\U00011644', '\U00011644'),
+  ('\U00011650', '\U00011659'),
+  ('\U00011680', '\U000116B8'),
+  ('\U000116C0', '\U000116C9'),
+  ('\U00011700', '\U0001171A'),
+  ('\U0001171D', '\U0001172B'),
+  ('\U00011730', '\U00011739'),
+  ('\U00011740', '\U00011746'),
+  ('\U00011800', '\U0001183A'),
+  ('\U000118A0', '\U000118E9'),
+  ('\U000118FF', '\U00011906'),
+  ('\U00011909', '\U00011909'),
+  ('\U0001190C', '\U00011913'),
+  ('\U00011915', '\U00011916'),
+  ('\U00011918', '\U00011935'),
+  ('\U00011937', '\U00011938'),
+  ('\U0001193B', '\U00011943'),
+  ('\U00011950', '\U00011959'),
+  ('\U000119A0', '\U000119A7'),
+  ('\U000119AA', '\U000119D7'),
+  ('\U000119DA', '\U000119E1'),
+  ('\U000119E3', '\U000119E4'),
+  ('\U00011A00', '\U00011A3E'),
+  ('\U00011A47', '\U00011A47'),
+  ('\U00011A50', '\U00011A99'),
+  ('\U00011A9D', '\U00011A9D'),
+  ('\U00011AB0', '\U00011AF8'),
+  ('\U00011C00', '\U00011C08'),
+  ('\U00011C0A', '\U00011C36'),
+  ('\U00011C38', '\U00011C40'),
+  ('\U00011C50', '\U00011C59'),
+  ('\U00011C72', '\U00011C8F'),
+  ('\U00011C92', '\U00011CA7'),
+  ('\U00011CA9', '\U00011CB6'),
+  ('\U00011D00', '\U00011D06'),
+  ('\U00011D08', '\U00011D09'),
+  ('\U00011D0B', '\U00011D36'),
+  ('\U00011D3A', '\U00011D3A'),
+  ('\U00011D3C', '\U00011D3D'),
+  ('\U00011D3F', '\U00011D47'),
+  ('\U00011D50', '\U00011D59'),
+  ('\U00011D60', '\U00011D65'),
+  ('\U00011D67', '\U00011D68'),
+  ('\U00011D6A', '\U00011D8E'),
+  ('\U00011D90', '\U00011D91'),
+  ('\U00011D93', '\U00011D98'),
+  ('\U00011DA0', '\U00011DA9'),
+  ('\U00011EE0', '\U00011EF6'),
+  ('\U00011FB0', '\U00011FB0'),
+  ('\U00012000', '\U00012399'),
+  ('\U00012400', '\U0001246E'),
+  ('\U00012480', '\U00012543'),
+  ('\U00012F90', '\U00012FF0'),
+  ('\U00013000', '\U0001342E'),
+  ('\U00014400', '\U00014646'),
+  ('\U00016800', '\U00016A38'),
+  ('\U00016A40', '\U00016A5E'),
+  ('\U00016A60', '\U00016A69'),
+  ('\U00016A70', '\U00016ABE'),
+  ('\U00016AC0', '\U00016AC9'),
+  ('\U00016AD0', '\U00016AED'),
+  ('\U00016AF0', '\U00016AF4'),
+  ('\U00016B00', '\U00016B36'),
+  ('\U00016B40', '\U00016B43'),
+  ('\U00016B50', '\U00016B59'),
+  ('\U00016B63', '\U00016B77'),
+  ('\U00016B7D', '\U00016B8F'),
+  ('\U00016E40', '\U00016E7F'),
+  ('\U00016F00', '\U00016F4A'),
+  ('\U00016F4F', '\U00016F87'),
+  ('\U00016F8F', '\U00016F9F'),
+  ('\U00016FE0', '\U00016FE1'),
+  ('\U00016FE3', '\U00016FE4'),
+  ('\U00016FF0', '\U00016FF1'),
+  ('\U00017000', '\U000187F7'),
+  ('\U00018800', '\U00018CD5'),
+  ('\U00018D00', '\U00018D08'),
+  ('\U0001AFF0', '\U0001AFF3'),
+  ('\U0001AFF5', '\U0001AFFB'),
+  ('\U0001AFFD', '\U0001AFFE'),
+  ('\U0001B000', '\U0001B122'),
+  ('\U0001B150', '\U0001B152'),
+  ('\U0001B164', '\U0001B167'),
+  ('\U0001B170', '\U0001B2FB'),
+  ('\U0001BC00', '\U0001BC6A'),
+  ('\U0001BC70', '\U0001BC7C'),
+  ('\U0001BC80', '\U0001BC88'),
+  ('\U0001BC90', '\U0001BC99'),
+  ('\U0001BC9D', '\U0001BC9E'),
+  ('\U0001CF00', '\U0001CF2D'),
+  ('\U0001CF30', '\U0001CF46'),
+  ('\U0001D165', '\U0001D169'),
+  ('\U0001D16D', '\U0001D172'),
+  ('\U0001D17B', '\U0001D182'),
+  ('\U0001D185', '\U0001D18B'),
+  ('\U0001D1AA', '\U0001D1AD'),
+  ('\U0001D242', '\U0001D244'),
+  ('\U0001D400', '\U0001D454'),
+  ('\U0001D456', '\U0001D49C'),
+  ('\U0001D49E', '\U0001D49F'),
+  ('\U0001D4A2', '\U0001D4A2'),
+  ('\U0001D4A5', '\U0001D4A6'),
+  ('\U0001D4A9', '\U0001D4AC'),
+  ('\U0001D4AE', '\U0001D4B9'),
+  ('\U0001D4BB', '\U0001D4BB'),
+  ('\U0001D4BD', '\U0001D4C3'),
+  ('\U0001D4C5', '\U0001D505'),
+  ('\U0001D507', '\U0001D50A'),
+  ('\U0001D50D', '\U0001D514'),
+  ('\U0001D516', '\U0001D51C'),
+  ('\U0001D51E', '\U0001D539'),
+  ('\U0001D53B', '\U0001D53E'),
+  ('\U0001D540', '\U0001D544'),
+  ('\U0001D546', '\U0001D546'),
+  ('\U0001D54A', '\U0001D550'),
+  ('\U0001D552', '\U0001D6A5'),
+  ('\U0001D6A8', '\U0001D6C0'),
+  ('\U0001D6C2', '\U0001D6DA'),
+  ('\U0001D6DC', '\U0001D6FA'),
+  ('\U0001D6FC', '\U0001D714'),
+  ('\U0001D716', '\U0001D734'),
+  ('\U0001D736', '\U0001D74E'),
+  ('\U0001D750', '\U0001D76E'),
+  ('\U0001D770', '\U0001D788'),
+  ('\U0001D78A', '\U0001D7A8'),
+  ('\U0001D7AA', '\U0001D7C2'),
+  ('\U0001D7C4', '\U0001D7CB'),
+  ('\U0001D7CE', '\U0001D7FF'),
+  ('\U0001DA00', '\U0001DA36'),
+  ('\U0001DA3B', '\U0001DA6C'),
+  ('\U0001DA75', '\U0001DA75'),
+  ('\U0001DA84', '\U0001DA84'),
+  ('\U0001DA9B', '\U0001DA9F'),
+  ('\U0001DAA1', '\U0001DAAF'),
+  ('\U0001DF00', '\U0001DF1E'),
+  ('\U0001E000', '\U0001E006'),
+  ('\U0001E008', '\U0001E018'),
+  ('\U0001E01B', '\U0001E021'),
+  ('\U0001E023', '\U0001E024'),
+  ('\U0001E026', '\U0001E02A'),
+  ('\U0001E100', '\U0001E12C'),
+  ('\U0001E130', '\U0001E13D'),
+  ('\U0001E140', '\U0001E149'),
+  ('\U0001E14E', '\U0001E14E'),
+  ('\U0001E290', '\U0001E2AE'),
+  ('\U0001E2C0', '\U0001E2F9'),
+  ('\U0001E7E0', '\U0001E7E6'),
+  ('\U0001E7E8', '\U0001E7EB'),
+  ('\U0001E7ED', '\U0001E7EE'),
+  ('\U0001E7F0', '\U0001E7FE'),
+  ('\U0001E800', '\U0001E8C4'),
+  ('\U0001E8D0', '\U0001E8D6'),
+  ('\U0001E900', '\U0001E94B'),
+  ('\U0001E950', '\U0001E959'),
+  ('\U0001EE00', '\U0001EE03'),
+  ('\U0001EE05', '\U0001EE1F'),
+  ('\U0001EE21', '\U0001EE22'),
+  ('\U0001EE24', '\U0001EE24'),
+  ('\U0001EE27', '\U0001EE27'),
+  ('\U0001EE29', '\U0001EE32'),
+  ('\U0001EE34', '\U0001EE37'),
+  ('\U0001EE39', '\U0001EE39'),
+  ('\U0001EE3B', '\U0001EE3B'),
+  ('\U0001EE42', '\U0001EE42'),
+  ('\U0001EE47', '\U0001EE47'),
+  ('\U0001EE49', '\U0001EE49'),
+  ('\U0001EE4B', '\U0001EE4B'),
+  ('\U0001EE4D', '\U0001EE4F'),
+  ('\U0001EE51', '\U0001EE52'),
+  ('\U0001EE54', '\U0001EE54'),
+  ('\U0001EE57', '\U0001EE57'),
+  ('\U0001EE59', '\U0001EE59'),
+  ('\U0001EE5B', '\U0001EE5B'),
+  ('\U0001EE5D', '\U0001EE5D'),
+  ('\U0001EE5F', '\U0001EE5F'),
+  ('\U0001EE61', '\U0001EE62'),
+  ('\U0001EE64', '\U0001EE64'),
+  ('\U0001EE67', '\U0001EE6A'),
+  ('\U0001EE6C', '\U0001EE72'),
+  ('\U0001EE74', '\U0001EE77'),
+  ('\U0001EE79', '\U0001EE7C'),
+  ('\U0001EE7E', '\U0001EE7E'),
+  ('\U0001EE80', '\U0001EE89'),
+  ('\U0001EE8B', '\U0001EE9B'),
+  ('\U0001EEA1', '\U0001EEA3'),
+  ('\U0001EEA5', '\U0001EEA9'),
+  ('\U0001EEAB', '\U0001EEBB'),
+  ('\U0001F130', '\U0001F149'),
+  ('\U0001F150', '\U0001F169'),
+  ('\U0001F170', '\U0001F189'),
+  ('\U0001FBF0', '\U0001FBF9'),
+  ('\U00020000', '\U0002A6DF'),
+  ('\U0002A700', '\U0002B738'),
+  ('\U0002B740', '\U0002B81D'),
+  ('\U0002B820', '\U0002CEA1'),
+  ('\U0002CEB0', '\U0002EBE0'),
+  ('\U0002F800', '\U0002FA1D'),
+  ('\U00030000', '\U0003134A'),
+  ('\U000E0100', '\U000E01EF')
+];
+
+/// Unicode `\W`
+pub static NegatedPerlWord: &'static [(char, char)] = &[
+  ('\u0000', '\u002F'),
+  ('\u003A', '\u0040'),
+  ('\u005B', '\u005E'),
+  ('\u0060', '\u0060'),
+  ('\u007B', '\u00A9'),
+  ('\u00AB', '\u00B4'),
+  ('\u00B6', '\u00B9'),
+  ('\u00BB', '\u00BF'),
+  ('\u00D7', '\u00D7'),
+  ('\u00F7', '\u00F7'),
+  ('\u02C2', '\u02C5'),
+  ('\u02D2', '\u02DF'),
+  ('\u02E5', '\u02EB'),
+  ('\u02ED', '\u02ED'),
+  ('\u02EF', '\u02FF'),
+  ('\u0375', '\u0375'),
+  ('\u0378', '\u0379'),
+  ('\u037E', '\u037E'),
+  ('\u0380', '\u0385'),
+  ('\u0387', '\u0387'),
+  ('\u038B', '\u038B'),
+  ('\u038D', '\u038D'),
+  ('\u03A2', '\u03A2'),
+  ('\u03F6', '\u03F6'),
+  ('\u0482', '\u0482'),
+  ('\u0530', '\u0530'),
+  ('\u0557', '\u0558'),
+  ('\u055A', '\u055F'),
+  ('\u0589', '\u0590'),
+  ('\u05BE', '\u05BE'),
+  ('\u05C0', '\u05C0'),
+  ('\u05C3', '\u05C3'),
+  ('\u05C6', '\u05C6'),
+  ('\u05C8', '\u05CF'),
+  ('\u05EB', '\u05EE'),
+  ('\u05F3', '\u060F'),
+  ('\u061B', '\u061F'),
+  ('\u066A', '\u066D'),
+  ('\u06D4', '\u06D4'),
+  ('\u06DD', '\u06DE'),
+  ('\u06E9', '\u06E9'),
+  ('\u06FD', '\u06FE'),
+  ('\u0700', '\u070F'),
+  ('\u074B', '\u074C'),
+  ('\u07B2', '\u07BF'),
+  ('\u07F6', '\u07F9'),
+  ('\u07FB', '\u07FC'),
+  ('\u07FE', '\u07FF'),
+  ('\u082E', '\u083F'),
+  ('\u085C', '\u085F'),
+  ('\u086B', '\u086F'),
+  ('\u0888', '\u0888'),
+  ('\u088F', '\u0897'),
+  ('\u08E2', '\u08E2'),
+  ('\u0964', '\u0965'),
+  ('\u0970', '\u0970'),
+  ('\u0984', '\u0984'),
+  ('\u098D', '\u098E'),
+  ('\u0991', '\u0992'),
+  ('\u09A9', '\u09A9'),
+  ('\u09B1', '\u09B1'),
+  ('\u09B3', '\u09B5'),
+  ('\u09BA', '\u09BB'),
+  ('\u09C5', '\u09C6'),
+  ('\u09C9', '\u09CA'),
+  ('\u09CF', '\u09D6'),
+  ('\u09D8', '\u09DB'),
+  ('\u09DE', '\u09DE'),
+  ('\u09E4', '\u09E5'),
+  ('\u09F2', '\u09FB'),
+  ('\u09FD', '\u09FD'),
+  ('\u09FF', '\u0A00'),
+  ('\u0A04', '\u0A04'),
+  ('\u0A0B', '\u0A0E'),
+  ('\u0A11', '\u0A12'),
+  ('\u0A29', '\u0A29'),
+  ('\u0A31', '\u0A31'),
+  ('\u0A34', '\u0A34'),
+  ('\u0A37', '\u0A37'),
+  ('\u0A3A', '\u0A3B'),
+  ('\u0A3D', '\u0A3D'),
+  ('\u0A43', '\u0A46'),
+  ('\u0A49', '\u0A4A'),
+  ('\u0A4E', '\u0A50'),
+  ('\u0A52', '\u0A58'),
+  ('\u0A5D', '\u0A5D'),
+  ('\u0A5F', '\u0A65'),
+  ('\u0A76', '\u0A80'),
+  ('\u0A84', '\u0A84'),
+  ('\u0A8E', '\u0A8E'),
+  ('\u0A92', '\u0A92'),
+  ('\u0AA9', '\u0AA9'),
+  ('\u0AB1', '\u0AB1'),
+  ('\u0AB4', '\u0AB4'),
+  ('\u0ABA', '\u0ABB'),
+  ('\u0AC6', '\u0AC6'),
+  ('\u0ACA', '\u0ACA'),
+  ('\u0ACE', '\u0ACF'),
+  ('\u0AD1', '\u0ADF'),
+  ('\u0AE4', '\u0AE5'),
+  ('\u0AF0', '\u0AF8'),
+  ('\u0B00', '\u0B00'),
+  ('\u0B04', '\u0B04'),
+  ('\u0B0D', '\u0B0E'),
+  ('\u0B11', '\u0B12'),
+  ('\u0B29', '\u0B29'),
+  ('\u0B31', '\u0B31'),
+  ('\u0B34', '\u0B34'),
+  ('\u0B3A', '\u0B3B'),
+  ('\u0B45', '\u0B46'),
+  ('\u0B49', '\u0B4A'),
+  ('\u0B4E', '\u0B54'),
+  ('\u0B58', '\u0B5B'),
+  ('\u0B5E', '\u0B5E'),
+  ('\u0B64', '\u0B65'),
+  ('\u0B70', '\u0B70'),
+  ('\u0B72', '\u0B81'),
+  ('\u0B84', '\u0B84'),
+  ('\u0B8B', '\u0B8D'),
+  ('\u0B91', '\u0B91'),
+  ('\u0B96', '\u0B98'),
+  ('\u0B9B', '\u0B9B'),
+  ('\u0B9D', '\u0B9D'),
+  ('\u0BA0', '\u0BA2'),
+  ('\u0BA5', '\u0BA7'),
+  ('\u0BAB', '\u0BAD'),
+  ('\u0BBA', '\u0BBD'),
+  ('\u0BC3', '\u0BC5'),
+  ('\u0BC9', '\u0BC9'),
+  ('\u0BCE', '\u0BCF'),
+  ('\u0BD1', '\u0BD6'),
+  ('\u0BD8', '\u0BE5'),
+  ('\u0BF0', '\u0BFF'),
+  ('\u0C0D', '\u0C0D'),
+  ('\u0C11', '\u0C11'),
+  ('\u0C29', '\u0C29'),
+  ('\u0C3A', '\u0C3B'),
+  ('\u0C45', '\u0C45'),
+  ('\u0C49', '\u0C49'),
+  ('\u0C4E', '\u0C54'),
+  ('\u0C57', '\u0C57'),
+  ('\u0C5B', '\u0C5C'),
+  ('\u0C5E', '\u0C5F'),
+  ('\u0C64', '\u0C65'),
+  ('\u0C70', '\u0C7F'),
+  ('\u0C84', '\u0C84'),
+  ('\u0C8D', '\u0C8D'),
+  ('\u0C91', '\u0C91'),
+  ('\u0CA9', '\u0CA9'),
+  ('\u0CB4', '\u0CB4'),
+  ('\u0CBA', '\u0CBB'),
+  ('\u0CC5', '\u0CC5'),
+  ('\u0CC9', '\u0CC9'),
+  ('\u0CCE', '\u0CD4'),
+  ('\u0CD7', '\u0CDC'),
+  ('\u0CDF', '\u0CDF'),
+  ('\u0CE4', '\u0CE5'),
+  ('\u0CF0', '\u0CF0'),
+  ('\u0CF3', '\u0CFF'),
+  ('\u0D0D', '\u0D0D'),
+  ('\u0D11', '\u0D11'),
+  ('\u0D45', '\u0D45'),
+  ('\u0D49', '\u0D49'),
+  ('\u0D4F', '\u0D53'),
+  ('\u0D58', '\u0D5E'),
+  ('\u0D64', '\u0D65'),
+  ('\u0D70', '\u0D79'),
+  ('\u0D80', '\u0D80'),
+  ('\u0D84', '\u0D84'),
+  ('\u0D97', '\u0D99'),
+  ('\u0DB2', '\u0DB2'),
+  ('\u0DBC', '\u0DBC'),
+  ('\u0DBE', '\u0DBF'),
+  ('\u0DC7', '\u0DC9'),
+  ('\u0DCB', '\u0DCE'),
+  ('\u0DD5', '\u0DD5'),
+  ('\u0DD7', '\u0DD7'),
+  ('\u0DE0', '\u0DE5'),
+  ('\u0DF0', '\u0DF1'),
+  ('\u0DF4', '\u0E00'),
+  ('\u0E3B', '\u0E3F'),
+  ('\u0E4F', '\u0E4F'),
+  ('\u0E5A', '\u0E80'),
+  ('\u0E83', '\u0E83'),
+  ('\u0E85', '\u0E85'),
+  ('\u0E8B', '\u0E8B'),
+  ('\u0EA4', '\u0EA4'),
+  ('\u0EA6', '\u0EA6'),
+  ('\u0EBE', '\u0EBF'),
+  ('\u0EC5', '\u0EC5'),
+  ('\u0EC7', '\u0EC7'),
+  ('\u0ECE', '\u0ECF'),
+  ('\u0EDA', '\u0EDB'),
+  ('\u0EE0', '\u0EFF'),
+  ('\u0F01', '\u0F17'),
+  ('\u0F1A', '\u0F1F'),
+  ('\u0F2A', '\u0F34'),
+  ('\u0F36', '\u0F36'),
+  ('\u0F38', '\u0F38'),
+  ('\u0F3A', '\u0F3D'),
+  ('\u0F48', '\u0F48'),
+  ('\u0F6D', '\u0F70'),
+  ('\u0F85', '\u0F85'),
+  ('\u0F98', '\u0F98'),
+  ('\u0FBD', '\u0FC5'),
+  ('\u0FC7', '\u0FFF'),
+  ('\u104A', '\u104F'),
+  ('\u109E', '\u109F'),
+  ('\u10C6', '\u10C6'),
+  ('\u10C8', '\u10CC'),
+  ('\u10CE', '\u10CF'),
+  ('\u10FB', '\u10FB'),
+  ('\u1249', '\u1249'),
+  ('\u124E', '\u124F'),
+  ('\u1257', '\u1257'),
+  ('\u1259', '\u1259'),
+  ('\u125E', '\u125F'),
+  ('\u1289', '\u1289'),
+  ('\u128E', '\u128F'),
+  ('\u12B1', '\u12B1'),
+  ('\u12B6', '\u12B7'),
+  ('\u12BF', '\u12BF'),
+  ('\u12C1', '\u12C1'),
+  ('\u12C6', '\u12C7'),
+  ('\u12D7', '\u12D7'),
+  ('\u1311', '\u1311'),
+  ('\u1316', '\u1317'),
+  ('\u135B', '\u135C'),
+  ('\u1360', '\u137F'),
+  ('\u1390', '\u139F'),
+  ('\u13F6', '\u13F7'),
+  ('\u13FE', '\u1400'),
+  ('\u166D', '\u166E'),
+  ('\u1680', '\u1680'),
+  ('\u169B', '\u169F'),
+  ('\u16EB', '\u16ED'),
+  ('\u16F9', '\u16FF'),
+  ('\u1716', '\u171E'),
+  ('\u1735', '\u173F'),
+  ('\u1754', '\u175F'),
+  ('\u176D', '\u176D'),
+  ('\u1771', '\u1771'),
+  ('\u1774', '\u177F'),
+  ('\u17D4', '\u17D6'),
+  ('\u17D8', '\u17DB'),
+  ('\u17DE', '\u17DF'),
+  ('\u17EA', '\u180A'),
+  ('\u180E', '\u180E'),
+  ('\u181A', '\u181F'),
+  ('\u1879', '\u187F'),
+  ('\u18AB', '\u18AF'),
+  ('\u18F6', '\u18FF'),
+  ('\u191F', '\u191F'),
+  ('\u192C', '\u192F'),
+  ('\u193C', '\u1945'),
+  ('\u196E', '\u196F'),
+  ('\u1975', '\u197F'),
+  ('\u19AC', '\u19AF'),
+  ('\u19CA', '\u19CF'),
+  ('\u19DA', '\u19FF'),
+  ('\u1A1C', '\u1A1F'),
+  ('\u1A5F', '\u1A5F'),
+  ('\u1A7D', '\u1A7E'),
+  ('\u1A8A', '\u1A8F'),
+  ('\u1A9A', '\u1AA6'),
+  ('\u1AA8', '\u1AAF'),
+  ('\u1ACF', '\u1AFF'),
+  ('\u1B4D', '\u1B4F'),
+  ('\u1B5A', '\u1B6A'),
+  ('\u1B74', '\u1B7F'),
+  ('\u1BF4', '\u1BFF'),
+  ('\u1C38', '\u1C3F'),
+  ('\u1C4A', '\u1C4C'),
+  ('\u1C7E', '\u1C7F'),
+  ('\u1C89', '\u1C8F'),
+  ('\u1CBB', '\u1CBC'),
+  ('\u1CC0', '\u1CCF'),
+  ('\u1CD3', '\u1CD3'),
+  ('\u1CFB', '\u1CFF'),
+  ('\u1F16', '\u1F17'),
+  ('\u1F1E', '\u1F1F'),
+  ('\u1F46', '\u1F47'),
+  ('\u1F4E', '\u1F4F'),
+  ('\u1F58', '\u1F58'),
+  ('\u1F5A', '\u1F5A'),
+  ('\u1F5C', '\u1F5C'),
+  ('\u1F5E', '\u1F5E'),
+  ('\u1F7E', '\u1F7F'),
+  ('\u1FB5', '\u1FB5'),
+  ('\u1FBD', '\u1FBD'),
+  ('\u1FBF', '\u1FC1'),
+  ('\u1FC5', '\u1FC5'),
+  ('\u1FCD', '\u1FCF'),
+  ('\u1FD4', '\u1FD5'),
+  ('\u1FDC', '\u1FDF'),
+  ('\u1FED', '\u1FF1'),
+  ('\u1FF5', '\u1FF5'),
+  ('\u1FFD', '\u200B'),
+  ('\u200E', '\u203E'),
+  ('\u2041', '\u2053'),
+  ('\u2055', '\u2070'),
+  ('\u2072', '\u207E'),
+  ('\u2080', '\u208F'),
+  ('\u209D', '\u20CF'),
+  ('\u20F1', '\u2101'),
+  ('\u2103', '\u2106'),
+  ('\u2108', '\u2109'),
+  ('\u2114', '\u2114'),
+  ('\u2116', '\u2118'),
+  ('\u211E', '\u2123'),
+  ('\u2125', '\u2125'),
+  ('\u2127', '\u2127'),
+  ('\u2129', '\u2129'),
+  ('\u212E', '\u212E'),
+  ('\u213A', '\u213B'),
+  ('\u2140', '\u2144'),
+  ('\u214A', '\u214D'),
+  ('\u214F', '\u215F'),
+  ('\u2189', '\u24B5'),
+  ('\u24EA', '\u2BFF'),
+  ('\u2CE5', '\u2CEA'),
+  ('\u2CF4', '\u2CFF'),
+  ('\u2D26', '\u2D26'),
+  ('\u2D28', '\u2D2C'),
+  ('\u2D2E', '\u2D2F'),
+  ('\u2D68', '\u2D6E'),
+  ('\u2D70', '\u2D7E'),
+  ('\u2D97', '\u2D9F'),
+  ('\u2DA7', '\u2DA7'),
+  ('\u2DAF', '\u2DAF'),
+  ('\u2DB7', '\u2DB7'),
+  ('\u2DBF', '\u2DBF'),
+  ('\u2DC7', '\u2DC7'),
+  ('\u2DCF', '\u2DCF'),
+  ('\u2DD7', '\u2DD7'),
+  ('\u2DDF', '\u2DDF'),
+  ('\u2E00', '\u2E2E'),
+  ('\u2E30', '\u3004'),
+  ('\u3008', '\u3020'),
+  ('\u3030', '\u3030'),
+  ('\u3036', '\u3037'),
+  ('\u303D', '\u3040'),
+  ('\u3097', '\u3098'),
+  ('\u309B', '\u309C'),
+  ('\u30A0', '\u30A0'),
+  ('\u30FB', '\u30FB'),
+  ('\u3100', '\u3104'),
+  ('\u3130', '\u3130'),
+  ('\u318F', '\u319F'),
+  ('\u31C0', '\u31EF'),
+  ('\u3200', '\u33FF'),
+  ('\u4DC0', '\u4DFF'),
+  ('\uA48D', '\uA4CF'),
+  ('\uA4FE', '\uA4FF'),
+  ('\uA60D', '\uA60F'),
+  ('\uA62C', '\uA63F'),
+  ('\uA673', '\uA673'),
+  ('\uA67E', '\uA67E'),
+  ('\uA6F2', '\uA716'),
+  ('\uA720', '\uA721'),
+  ('\uA789', '\uA78A'),
+  ('\uA7CB', '\uA7CF'),
+  ('\uA7D2', '\uA7D2'),
+  ('\uA7D4', '\uA7D4'),
+  ('\uA7DA', '\uA7F1'),
+  ('\uA828', '\uA82B'),
+  ('\uA82D', '\uA83F'),
+  ('\uA874', '\uA87F'),
+  ('\uA8C6', '\uA8CF'),
+  ('\uA8DA', '\uA8DF'),
+  ('\uA8F8', '\uA8FA'),
+  ('\uA8FC', '\uA8FC'),
+  ('\uA92E', '\uA92F'),
+  ('\uA954', '\uA95F'),
+  ('\uA97D', '\uA97F'),
+  ('\uA9C1', '\uA9CE'),
+  ('\uA9DA', '\uA9DF'),
+  ('\uA9FF', '\uA9FF'),
+  ('\uAA37', '\uAA3F'),
+  ('\uAA4E', '\uAA4F'),
+  ('\uAA5A', '\uAA5F'),
+  ('\uAA77', '\uAA79'),
+  ('\uAAC3', '\uAADA'),
+  ('\uAADE', '\uAADF'),
+  ('\uAAF0', '\uAAF1'),
+  ('\uAAF7', '\uAB00'),
+  ('\uAB07', '\uAB08'),
+  ('\uAB0F', '\uAB10'),
+  ('\uAB17', '\uAB1F'),
+  ('\uAB27', '\uAB27'),
+  ('\uAB2F', '\uAB2F'),
+  ('\uAB5B', '\uAB5B'),
+  ('\uAB6A', '\uAB6F'),
+  ('\uABEB', '\uABEB'),
+  ('\uABEE', '\uABEF'),
+  ('\uABFA', '\uABFF'),
+  ('\uD7A4', '\uD7AF'),
+  ('\uD7C7', '\uD7CA'),
+  ('\uD7FC', '\uD7FF'),
+  ('\uE000', '\uF8FF'),
+  ('\uFA6E', '\uFA6F'),
+  ('\uFADA', '\uFAFF'),
+  ('\uFB07', '\uFB12'),
+  ('\uFB18', '\uFB1C'),
+  ('\uFB29', '\uFB29'),
+  ('\uFB37', '\uFB37'),
+  ('\uFB3D', '\uFB3D'),
+  ('\uFB3F', '\uFB3F'),
+  ('\uFB42', '\uFB42'),
+  ('\uFB45', '\uFB45'),
+  ('\uFBB2', '\uFBD2'),
+  ('\uFD3E', '\uFD4F'),
+  ('\uFD90', '\uFD91'),
+  ('\uFDC8', '\uFDEF'),
+  ('\uFDFC', '\uFDFF'),
+  ('\uFE10', '\uFE1F'),
+  ('\uFE30', '\uFE32'),
+  ('\uFE35', '\uFE4C'),
+  ('\uFE50', '\uFE6F'),
+  ('\uFE75', '\uFE75'),
+  ('\uFEFD', '\uFF0F'),
+  ('\uFF1A', '\uFF20'),
+  ('\uFF3B', '\uFF3E'),
+  ('\uFF40', '\uFF40'),
+  ('\uFF5B', '\uFF65'),
+  ('\uFFBF', '\uFFC1'),
+  ('\uFFC8', '\uFFC9'),
+  ('\uFFD0', '\uFFD1'),
+  ('\uFFD8', '\uFFD9'),
+  ('\uFFDD', '\uFFFF'),
+  ('\U0001000C', '\U0001000C'),
+  ('\U00010027', '\U00010027'),
+  ('\U0001003B', '\U0001003B'),
+  ('\U0001003E', '\U0001003E'),
+  ('\U0001004E', '\U0001004F'),
+  ('\U0001005E', '\U0001007F'),
+  ('\U000100FB', '\U0001013F'),
+  ('\U00010175', '\U000101FC'),
+  ('\U000101FE', '\U0001027F'),
+  ('\U0001029D', '\U0001029F'),
+  ('\U000102D1', '\U000102DF'),
+  ('\U000102E1', '\U000102FF'),
+  ('\U00010320', '\U0001032C'),
+  ('\U0001034B', '\U0001034F'),
+  ('\U0001037B', '\U0001037F'),
+  ('\U0001039E', '\U0001039F'),
+  ('\U000103C4', '\U000103C7'),
+  ('\U000103D0', '\U000103D0'),
+  ('\U000103D6', '\U000103FF'),
+  ('\U0001049E', '\U0001049F'),
+  ('\U000104AA', '\U000104AF'),
+  ('\U000104D4', '\U000104D7'),
+  ('\U000104FC', '\U000104FF'),
+  ('\U00010528', '\U0001052F'),
+  ('\U00010564', '\U0001056F'),
+  ('\U0001057B', '\U0001057B'),
+  ('\U0001058B', '\U0001058B'),
+  ('\U00010593', '\U00010593'),
+  ('\U00010596', '\U00010596'),
+  ('\U000105A2', '\U000105A2'),
+  ('\U000105B2', '\U000105B2'),
+  ('\U000105BA', '\U000105BA'),
+  ('\U000105BD', '\U000105FF'),
+  ('\U00010737', '\U0001073F'),
+  ('\U00010756', '\U0001075F'),
+  ('\U00010768', '\U0001077F'),
+  ('\U00010786', '\U00010786'),
+  ('\U000107B1', '\U000107B1'),
+  ('\U000107BB', '\U000107FF'),
+  ('\U00010806', '\U00010807'),
+  ('\U00010809', '\U00010809'),
+  ('\U00010836', '\U00010836'),
+  ('\U00010839', '\U0001083B'),
+  ('\U0001083D', '\U0001083E'),
+  ('\U00010856', '\U0001085F'),
+  ('\U00010877', '\U0001087F'),
+  ('\U0001089F', '\U000108DF'),
+  ('\U000108F3', '\U000108F3'),
+  ('\U000108F6', '\U000108FF'),
+  ('\U00010916', '\U0001091F'),
+  ('\U0001093A', '\U0001097F'),
+  ('\U000109B8', '\U000109BD'),
+  ('\U000109C0', '\U000109FF'),
+  ('\U00010A04', '\U00010A04'),
+  ('\U00010A07', '\U00010A0B'),
+  ('\U00010A14', '\U00010A14'),
+  ('\U00010A18', '\U00010A18'),
+  ('\U00010A36', '\U00010A37'),
+  ('\U00010A3B', '\U00010A3E'),
+  ('\U00010A40', '\U00010A5F'),
+  ('\U00010A7D', '\U00010A7F'),
+  ('\U00010A9D', '\U00010ABF'),
+  ('\U00010AC8', '\U00010AC8'),
+  ('\U00010AE7', '\U00010AFF'),
+  ('\U00010B36', '\U00010B3F'),
+  ('\U00010B56', '\U00010B5F'),
+  ('\U00010B73', '\U00010B7F'),
+  ('\U00010B92', '\U00010BFF'),
+  ('\U00010C49', '\U00010C7F'),
+  ('\U00010CB3', '\U00010CBF'),
+  ('\U00010CF3', '\U00010CFF'),
+  ('\U00010D28', '\U00010D2F'),
+  ('\U00010D3A', '\U00010E7F'),
+  ('\U00010EAA', '\U00010EAA'),
+  ('\U00010EAD', '\U00010EAF'),
+  ('\U00010EB2', '\U00010EFF'),
+  ('\U00010F1D', '\U00010F26'),
+  ('\U00010F28', '\U00010F2F'),
+  ('\U00010F51', '\U00010F6F'),
+  ('\U00010F86', '\U00010FAF'),
+  ('\U00010FC5', '\U00010FDF'),
+  ('\U00010FF7', '\U00010FFF'),
+  ('\U00011047', '\U00011065'),
+  ('\U00011076', '\U0001107E'),
+  ('\U000110BB', '\U000110C1'),
+  ('\U000110C3', '\U000110CF'),
+  ('\U000110E9', '\U000110EF'),
+  ('\U000110FA', '\U000110FF'),
+  ('\U00011135', '\U00011135'),
+  ('\U00011140', '\U00011143'),
+  ('\U00011148', '\U0001114F'),
+  ('\U00011174', '\U00011175'),
+  ('\U00011177', '\U0001117F'),
+  ('\U000111C5', '\U000111C8'),
+  ('\U000111CD', '\U000111CD'),
+  ('\U000111DB', '\U000111DB'),
+  ('\U000111DD', '\U000111FF'),
+  ('\U00011212', '\U00011212'),
+  ('\U00011238', '\U0001123D'),
+  ('\U0001123F', '\U0001127F'),
+  ('\U00011287', '\U00011287'),
+  ('\U00011289', '\U00011289'),
+  ('\U0001128E', '\U0001128E'),
+  ('\U0001129E', '\U0001129E'),
+  ('\U000112A9', '\U000112AF'),
+  ('\U000112EB', '\U000112EF'),
+  ('\U000112FA', '\U000112FF'),
+  ('\U00011304', '\U00011304'),
+  ('\U0001130D', '\U0001130E'),
+  ('\U00011311', '\U00011312'),
+  ('\U00011329', '\U00011329'),
+  ('\U00011331', '\U00011331'),
+  ('\U00011334', '\U00011334'),
+  ('\U0001133A', '\U0001133A'),
+  ('\U00011345', '\U00011346'),
+  ('\U00011349', '\U0001134A'),
+  ('\U0001134E', '\U0001134F'),
+  ('\U00011351', '\U00011356'),
+  ('\U00011358', '\U0001135C'),
+  ('\U00011364', '\U00011365'),
+  ('\U0001136D', '\U0001136F'),
+  ('\U00011375', '\U000113FF'),
+  ('\U0001144B', '\U0001144F'),
+  ('\U0001145A', '\U0001145D'),
+  ('\U00011462', '\U0001147F'),
+  ('\U000114C6', '\U000114C6'),
+  ('\U000114C8', '\U000114CF'),
+  ('\U000114DA', '\U0001157F'),
+  ('\U000115B6', '\U000115B7'),
+  ('\U000115C1', '\U000115D7'),
+  ('\U000115DE', '\U000115FF'),
+  ('\U00011641', '\U00011643'),
+  ('\U00011645', '\U0001164F'),
+  ('\U0001165A', '\U0001167F'),
+  ('\U000116B9', '\U000116BF'),
+  ('\U000116CA', '\U000116FF'),
+  ('\U0001171B', '\U0001171C'),
+  ('\U0001172C', '\U0001172F'),
+  ('\U0001173A', '\U0001173F'),
+  ('\U00011747', '\U000117FF'),
+  ('\U0001183B', '\U0001189F'),
+  ('\U000118EA', '\U000118FE'),
+  ('\U00011907', '\U00011908'),
+  ('\U0001190A', '\U0001190B'),
+  ('\U00011914', '\U00011914'),
+  ('\U00011917', '\U00011917'),
+  ('\U00011936', '\U00011936'),
+  ('\U00011939', '\U0001193A'),
+  ('\U00011944', '\U0001194F'),
+  ('\U0001195A', '\U0001199F'),
+  ('\U000119A8', '\U000119A9'),
+  ('\U000119D8', '\U000119D9'),
+  ('\U000119E2', '\U000119E2'),
+  ('\U000119E5', '\U000119FF'),
+  ('\U00011A3F', '\U00011A46'),
+  ('\U00011A48', '\U00011A4F'),
+  ('\U00011A9A', '\U00011A9C'),
+  ('\U00011A9E', '\U00011AAF'),
+  ('\U00011AF9', '\U00011BFF'),
+  ('\U00011C09', '\U00011C09'),
+  ('\U00011C37', '\U00011C37'),
+  ('\U00011C41', '\U00011C4F'),
+  ('\U00011C5A', '\U00011C71'),
+  ('\U00011C90', '\U00011C91'),
+  ('\U00011CA8', '\U00011CA8'),
+  ('\U00011CB7', '\U00011CFF'),
+  ('\U00011D07', '\U00011D07'),
+  ('\U00011D0A', '\U00011D0A'),
+  ('\U00011D37', '\U00011D39'),
+  ('\U00011D3B', '\U00011D3B'),
+  ('\U00011D3E', '\U00011D3E'),
+  ('\U00011D48', '\U00011D4F'),
+  ('\U00011D5A', '\U00011D5F'),
+  ('\U00011D66', '\U00011D66'),
+  ('\U00011D69', '\U00011D69'),
+  ('\U00011D8F', '\U00011D8F'),
+  ('\U00011D92', '\U00011D92'),
+  ('\U00011D99', '\U00011D9F'),
+  ('\U00011DAA', '\U00011EDF'),
+  ('\U00011EF7', '\U00011FAF'),
+  ('\U00011FB1', '\U00011FFF'),
+  ('\U0001239A', '\U000123FF'),
+  ('\U0001246F', '\U0001247F'),
+  ('\U00012544', '\U00012F8F'),
+  ('\U00012FF1', '\U00012FFF'),
+  ('\U0001342F', '\U000143FF'),
+  ('\U00014647', '\U000167FF'),
+  ('\U00016A39', '\U00016A3F'),
+  ('\U00016A5F', '\U00016A5F'),
+  ('\U00016A6A', '\U00016A6F'),
+  ('\U00016ABF', '\U00016ABF'),
+  ('\U00016ACA', '\U00016ACF'),
+  ('\U00016AEE', '\U00016AEF'),
+  ('\U00016AF5', '\U00016AFF'),
+  ('\U00016B37', '\U00016B3F'),
+  ('\U00016B44', '\U00016B4F'),
+  ('\U00016B5A', '\U00016B62'),
+  ('\U00016B78', '\U00016B7C'),
+  ('\U00016B90', '\U00016E3F'),
+  ('\U00016E80', '\U00016EFF'),
+  ('\U00016F4B', '\U00016F4E'),
+  ('\U00016F88', '\U00016F8E'),
+  ('\U00016FA0', '\U00016FDF'),
+  ('\U00016FE2', '\U00016FE2'),
+  ('\U00016FE5', '\U00016FEF'),
+  ('\U00016FF2', '\U00016FFF'),
+  ('\U000187F8', '\U000187FF'),
+  ('\U00018CD6', '\U00018CFF'),
+  ('\U00018D09', '\U0001AFEF'),
+  ('\U0001AFF4', '\U0001AFF4'),
+  ('\U0001AFFC', '\U0001AFFC'),
+  ('\U0001AFFF', '\U0001AFFF'),
+  ('\U0001B123', '\U0001B14F'),
+  ('\U0001B153', '\U0001B163'),
+  ('\U0001B168', '\U0001B16F'),
+  ('\U0001B2FC', '\U0001BBFF'),
+  ('\U0001BC6B', '\U0001BC6F'),
+  ('\U0001BC7D', '\U0001BC7F'),
+  ('\U0001BC89', '\U0001BC8F'),
+  ('\U0001BC9A', '\U0001BC9C'),
+  ('\U0001BC9F', '\U0001CEFF'),
+  ('\U0001CF2E', '\U0001CF2F'),
+  ('\U0001CF47', '\U0001D164'),
+  ('\U0001D16A', '\U0001D16C'),
+  ('\U0001D173', '\U0001D17A'),
+  ('\U0001D183', '\U0001D184'),
+  ('\U0001D18C', '\U0001D1A9'),
+  ('\U0001D1AE', '\U0001D241'),
+  ('\U0001D245', '\U0001D3FF'),
+  ('\U0001D455', '\U0001D455'),
+  ('\U0001D49D', '\U0001D49D'),
+  ('\U0001D4A0', '\U0001D4A1'),
+  ('\U0001D4A3', '\U0001D4A4'),
+  ('\U0001D4A7', '\U0001D4A8'),
+  ('\U0001D4AD', '\U0001D4AD'),
+  ('\U0001D4BA', '\U0001D4BA'),
+  ('\U0001D4BC', '\U0001D4BC'),
+  ('\U0001D4C4', '\U0001D4C4'),
+  ('\U0001D506', '\U0001D506'),
+  ('\U0001D50B', '\U0001D50C'),
+  ('\U0001D515', '\U0001D515'),
+  ('\U0001D51D', '\U0001D51D'),
+  ('\U0001D53A', '\U0001D53A'),
+  ('\U0001D53F', '\U0001D53F'),
+  ('\U0001D545', '\U0001D545'),
+  ('\U0001D547', '\U0001D549'),
+  ('\U0001D551', '\U0001D551'),
+  ('\U0001D6A6', '\U0001D6A7'),
+  ('\U0001D6C1', '\U0001D6C1'),
+  ('\U0001D6DB', '\U0001D6DB'),
+  ('\U0001D6FB', '\U0001D6FB'),
+  ('\U0001D715', '\U0001D715'),
+  ('\U0001D735', '\U0001D735'),
+  ('\U0001D74F', '\U0001D74F'),
+  ('\U0001D76F', '\U0001D76F'),
+  ('\U0001D789', '\U0001D789'),
+  ('\U0001D7A9', '\U0001D7A9'),
+  ('\U0001D7C3', '\U0001D7C3'),
+  ('\U0001D7CC', '\U0001D7CD'),
+  ('\U0001D800', '\U0001D9FF'),
+  ('\U0001DA37', '\U0001DA3A'),
+  ('\U0001DA6D', '\U0001DA74'),
+  ('\U0001DA76', '\U0001DA83'),
+  ('\U0001DA85', '\U0001DA9A'),
+  ('\U0001DAA0', '\U0001DAA0'),
+  ('\U0001DAB0', '\U0001DEFF'),
+  ('\U0001DF1F', '\U0001DFFF'),
+  ('\U0001E007', '\U0001E007'),
+  ('\U0001E019', '\U0001E01A'),
+  ('\U0001E022', '\U0001E022'),
+  ('\U0001E025', '\U0001E025'),
+  ('\U0001E02B', '\U0001E0FF'),
+  ('\U0001E12D', '\U0001E12F'),
+  ('\U0001E13E', '\U0001E13F'),
+  ('\U0001E14A', '\U0001E14D'),
+  ('\U0001E14F', '\U0001E28F'),
+  ('\U0001E2AF', '\U0001E2BF'),
+  ('\U0001E2FA', '\U0001E7DF'),
+  ('\U0001E7E7', '\U0001E7E7'),
+  ('\U0001E7EC', '\U0001E7EC'),
+  ('\U0001E7EF', '\U0001E7EF'),
+  ('\U0001E7FF', '\U0001E7FF'),
+  ('\U0001E8C5', '\U0001E8CF'),
+  ('\U0001E8D7', '\U0001E8FF'),
+  ('\U0001E94C', '\U0001E94F'),
+  ('\U0001E95A', '\U0001EDFF'),
+  ('\U0001EE04', '\U0001EE04'),
+  ('\U0001EE20', '\U0001EE20'),
+  ('\U0001EE23', '\U0001EE23'),
+  ('\U0001EE25', '\U0001EE26'),
+  ('\U0001EE28', '\U0001EE28'),
+  ('\U0001EE33', '\U0001EE33'),
+  ('\U0001EE38', '\U0001EE38'),
+  ('\U0001EE3A', '\U0001EE3A'),
+  ('\U0001EE3C', '\U0001EE41'),
+  ('\U0001EE43', '\U0001EE46'),
+  ('\U0001EE48', '\U0001EE48'),
+  ('\U0001EE4A', '\U0001EE4A'),
+  ('\U0001EE4C', '\U0001EE4C'),
+  ('\U0001EE50', '\U0001EE50'),
+  ('\U0001EE53', '\U0001EE53'),
+  ('\U0001EE55', '\U0001EE56'),
+  ('\U0001EE58', '\U0001EE58'),
+  ('\U0001EE5A', '\U0001EE5A'),
+  ('\U0001EE5C', '\U0001EE5C'),
+  ('\U0001EE5E', '\U0001EE5E'),
+  ('\U0001EE60', '\U0001EE60'),
+  ('\U0001EE63', '\U0001EE63'),
+  ('\U0001EE65', '\U0001EE66'),
+  ('\U0001EE6B', '\U0001EE6B'),
+  ('\U0001EE73', '\U0001EE73'),
+  ('\U0001EE78', '\U0001EE78'),
+  ('\U0001EE7D', '\U0001EE7D'),
+  ('\U0001EE7F', '\U0001EE7F'),
+  ('\U0001EE8A', '\U0001EE8A'),
+  ('\U0001EE9C', '\U0001EEA0'),
+  ('\U0001EEA4', '\U0001EEA4'),
+  ('\U0001EEAA', '\U0001EEAA'),
+  ('\U0001EEBC', '\U0001F12F'),
+  ('\U0001F14A', '\U0001F14F'),
+  ('\U0001F16A', '\U0001F16F'),
+  ('\U0001F18A', '\U0001FBEF'),
+  ('\U0001FBFA', '\U0001FFFF'),
+  ('\U0002A6E0', '\U0002A6FF'),
+  ('\U0002B739', '\U0002B73F'),
+  ('\U0002B81E', '\U0002B81F'),
+  ('\U0002CEA2', '\U0002CEAF'),
+  ('\U0002EBE1', '\U0002F7FF'),
+  ('\U0002FA1E', '\U0002FFFF'),
+  ('\U0003134B', '\U000E00FF'),
+  ('\U000E01F0', '\U0010FFFF')
+];
+
+/// General categories, and the one letter groups of categories (and `LC`),
+/// by normalized short name
+pub static GeneralCategories: &'static [(&'static str, &'static [(char, char)])] = &[
+  ("c", &[
+    ('\u0000', '\u001F'),
+    ('\u007F', '\u009F'),
+    ('\u00AD', '\u00AD'),
+    ('\u0378', '\u0379'),
+    ('\u0380', '\u0383'),
+    ('\u038B', '\u038B'),
+    ('\u038D', '\u038D'),
+    ('\u03A2', '\u03A2'),
+    ('\u0530', '\u0530'),
+    ('\u0557', '\u0558'),
+    ('\u058B', '\u058C'),
+    ('\u0590', '\u0590'),
+    ('\u05C8', '\u05CF'),
+    ('\u05EB', '\u05EE'),
+    ('\u05F5', '\u0605'),
+    ('\u061C', '\u061C'),
+    ('\u06DD', '\u06DD'),
+    ('\u070E', '\u070F'),
+    ('\u074B', '\u074C'),
+    ('\u07B2', '\u07BF'),
+    ('\u07FB', '\u07FC'),
+    ('\u082E', '\u082F'),
+    ('\u083F', '\u083F'),
+    ('\u085C', '\u085D'),
+    ('\u085F', '\u085F'),
+    ('\u086B', '\u086F'),
+    ('\u088F', '\u0897'),
+    ('\u08E2', '\u08E2'),
+    ('\u0984', '\u0984'),
+    ('\u098D', '\u098E'),
+    ('\u0991', '\u0992'),
+    ('\u09A9', '\u09A9'),
+    ('\u09B1', '\u09B1'),
+    ('\u09B3', '\u09B5'),
+    ('\u09BA', '\u09BB'),
+    ('\u09C5', '\u09C6'),
+    ('\u09C9', '\u09CA'),
+    ('\u09CF', '\u09D6'),
+    ('\u09D8', '\u09DB'),
+    ('\u09DE', '\u09DE'),
+    ('\u09E4', '\u09E5'),
+    ('\u09FF', '\u0A00'),
+    ('\u0A04', '\u0A04'),
+    ('\u0A0B', '\u0A0E'),
+    ('\u0A11', '\u0A12'),
+    ('\u0A29', '\u0A29'),
+    ('\u0A31', '\u0A31'),
+    ('\u0A34', '\u0A34'),
+    ('\u0A37', '\u0A37'),
+    ('\u0A3A', '\u0A3B'),
+    ('\u0A3D', '\u0A3D'),
+    ('\u0A43', '\u0A46'),
+    ('\u0A49', '\u0A4A'),
+    ('\u0A4E', '\u0A50'),
+    ('\u0A52', '\u0A58'),
+    ('\u0A5D', '\u0A5D'),
+    ('\u0A5F', '\u0A65'),
+    ('\u0A77', '\u0A80'),
+    ('\u0A84', '\u0A84'),
+    ('\u0A8E', '\u0A8E'),
+    ('\u0A92', '\u0A92'),
+    ('\u0AA9', '\u0AA9'),
+    ('\u0AB1', '\u0AB1'),
+    ('\u0AB4', '\u0AB4'),
+    ('\u0ABA', '\u0ABB'),
+    ('\u0AC6', '\u0AC6'),
+    ('\u0ACA', '\u0ACA'),
+    ('\u0ACE', '\u0ACF'),
+    ('\u0AD1', '\u0ADF'),
+    ('\u0AE4', '\u0AE5'),
+    ('\u0AF2', '\u0AF8'),
+    ('\u0B00', '\u0B00'),
+    ('\u0B04', '\u0B04'),
+    ('\u0B0D', '\u0B0E'),
+    ('\u0B11', '\u0B12'),
+    ('\u0B29', '\u0B29'),
+    ('\u0B31', '\u0B31'),
+    ('\u0B34', '\u0B34'),
+    ('\u0B3A', '\u0B3B'),
+    ('\u0B45', '\u0B46'),
+    ('\u0B49', '\u0B4A'),
+    ('\u0B4E', '\u0B54'),
+    ('\u0B58', '\u0B5B'),
+    ('\u0B5E', '\u0B5E'),
+    ('\u0B64', '\u0B65'),
+    ('\u0B78', '\u0B81'),
+    ('\u0B84', '\u0B84'),
+    ('\u0B8B', '\u0B8D'),
+    ('\u0B91', '\u0B91'),
+    ('\u0B96', '\u0B98'),
+    ('\u0B9B', '\u0B9B'),
+    ('\u0B9D', '\u0B9D'),
+    ('\u0BA0', '\u0BA2'),
+    ('\u0BA5', '\u0BA7'),
+    ('\u0BAB', '\u0BAD'),
+    ('\u0BBA', '\u0BBD'),
+    ('\u0BC3', '\u0BC5'),
+    ('\u0BC9', '\u0BC9'),
+    ('\u0BCE', '\u0BCF'),
+    ('\u0BD1', '\u0BD6'),
+    ('\u0BD8', '\u0BE5'),
+    ('\u0BFB', '\u0BFF'),
+    ('\u0C0D', '\u0C0D'),
+    ('\u0C11', '\u0C11'),
+    ('\u0C29', '\u0C29'),
+    ('\u0C3A', '\u0C3B'),
+    ('\u0C45', '\u0C45'),
+    ('\u0C49', '\u0C49'),
+    ('\u0C4E', '\u0C54'),
+    ('\u0C57', '\u0C57'),
+    ('\u0C5B', '\u0C5C'),
+    ('\u0C5E', '\u0C5F'),
+    ('\u0C64', '\u0C65'),
+    ('\u0C70', '\u0C76'),
+    ('\u0C8D', '\u0C8D'),
+    ('\u0C91', '\u0C91'),
+    ('\u0CA9', '\u0CA9'),
+    ('\u0CB4', '\u0CB4'),
+    ('\u0CBA', '\u0CBB'),
+    ('\u0CC5', '\u0CC5'),
+    ('\u0CC9', '\u0CC9'),
+    ('\u0CCE', '\u0CD4'),
+    ('\u0CD7', '\u0CDC'),
+    ('\u0CDF', '\u0CDF'),
+    ('\u0CE4', '\u0CE5'),
+    ('\u0CF0', '\u0CF0'),
+    ('\u0CF3', '\u0CFF'),
+    ('\u0D0D', '\u0D0D'),
+    ('\u0D11', '\u0D11'),
+    ('\u0D45', '\u0D45'),
+    ('\u0D49', '\u0D49'),
+    ('\u0D50', '\u0D53'),
+    ('\u0D64', '\u0D65'),
+    ('\u0D80', '\u0D80'),
+    ('\u0D84', '\u0D84'),
+    ('\u0D97', '\u0D99'),
+    ('\u0DB2', '\u0DB2'),
+    ('\u0DBC', '\u0DBC'),
+    ('\u0DBE', '\u0DBF'),
+    ('\u0DC7', '\u0DC9'),
+    ('\u0DCB', '\u0DCE'),
+    ('\u0DD5', '\u0DD5'),
+    ('\u0DD7', '\u0DD7'),
+    ('\u0DE0', '\u0DE5'),
+    ('\u0DF0', '\u0DF1'),
+    ('\u0DF5', '\u0E00'),
+    ('\u0E3B', '\u0E3E'),
+    ('\u0E5C', '\u0E80'),
+    ('\u0E83', '\u0E83'),
+    ('\u0E85', '\u0E85'),
+    ('\u0E8B', '\u0E8B'),
+    ('\u0EA4', '\u0EA4'),
+    ('\u0EA6', '\u0EA6'),
+    ('\u0EBE', '\u0EBF'),
+    ('\u0EC5', '\u0EC5'),
+    ('\u0EC7', '\u0EC7'),
+    ('\u0ECE', '\u0ECF'),
+    ('\u0EDA', '\u0EDB'),
+    ('\u0EE0', '\u0EFF'),
+    ('\u0F48', '\u0F48'),
+    ('\u0F6D', '\u0F70'),
+    ('\u0F98', '\u0F98'),
+    ('\u0FBD', '\u0FBD'),
+    ('\u0FCD', '\u0FCD'),
+    ('\u0FDB', '\u0FFF'),
+    ('\u10C6', '\u10C6'),
+    ('\u10C8', '\u10CC'),
+    ('\u10CE', '\u10CF'),
+    ('\u1249', '\u1249'),
+    ('\u124E', '\u124F'),
+    ('\u1257', '\u1257'),
+    ('\u1259', '\u1259'),
+    ('\u125E', '\u125F'),
+    ('\u1289', '\u1289'),
+    ('\u128E', '\u128F'),
+    ('\u12B1', '\u12B1'),
+    ('\u12B6', '\u12B7'),
+    ('\u12BF', '\u12BF'),
+    ('\u12C1', '\u12C1'),
+    ('\u12C6', '\u12C7'),
+    ('\u12D7', '\u12D7'),
+    ('\u1311', '\u1311'),
+    ('\u1316', '\u1317'),
+    ('\u135B', '\u135C'),
+    ('\u137D', '\u137F'),
+    ('\u139A', '\u139F'),
+    ('\u13F6', '\u13F7'),
+    ('\u13FE', '\u13FF'),
+    ('\u169D', '\u169F'),
+    ('\u16F9', '\u16FF'),
+    ('\u1716', '\u171E'),
+    ('\u1737', '\u173F'),
+    ('\u1754', '\u175F'),
+    ('\u176D', '\u176D'),
+    ('\u1771', '\u1771'),
+    ('\u1774', '\u177F'),
+    ('\u17DE', '\u17DF'),
+    ('\u17EA', '\u17EF'),
+    ('\u17FA', '\u17FF'),
+    ('\u180E', '\u180E'),
+    ('\u181A', '\u181F'),
+    ('\u1879', '\u187F'),
+    ('\u18AB', '\u18AF'),
+    ('\u18F6', '\u18FF'),
+    ('\u191F', '\u191F'),
+    ('\u192C', '\u192F'),
+    ('\u193C', '\u193F'),
+    ('\u1941', '\u1943'),
+    ('\u196E', '\u196F'),
+    ('\u1975', '\u197F'),
+    ('\u19AC', '\u19AF'),
+    ('\u19CA', '\u19CF'),
+    ('\u19DB', '\u19DD'),
+    ('\u1A1C', '\u1A1D'),
+    ('\u1A5F', '\u1A5F'),
+    ('\u1A7D', '\u1A7E'),
+    ('\u1A8A', '\u1A8F'),
+    ('\u1A9A', '\u1A9F'),
+    ('\u1AAE', '\u1AAF'),
+    ('\u1ACF', '\u1AFF'),
+    ('\u1B4D', '\u1B4F'),
+    ('\u1B7F', '\u1B7F'),
+    ('\u1BF4', '\u1BFB'),
+    ('\u1C38', '\u1C3A'),
+    ('\u1C4A', '\u1C4C'),
+    ('\u1C89', '\u1C8F'),
+    ('\u1CBB', '\u1CBC'),
+    ('\u1CC8', '\u1CCF'),
+    ('\u1CFB', '\u1CFF'),
+    ('\u1F16', '\u1F17'),
+    ('\u1F1E', '\u1F1F'),
+    ('\u1F46', '\u1F47'),
+    ('\u1F4E', '\u1F4F'),
+    ('\u1F58', '\u1F58'),
+    ('\u1F5A', '\u1F5A'),
+    ('\u1F5C', '\u1F5C'),
+    ('\u1F5E', '\u1F5E'),
+    ('\u1F7E', '\u1F7F'),
+    ('\u1FB5', '\u1FB5'),
+    ('\u1FC5', '\u1FC5'),
+    ('\u1FD4', '\u1FD5'),
+    ('\u1FDC', '\u1FDC'),
+    ('\u1FF0', '\u1FF1'),
+    ('\u1FF5', '\u1FF5'),
+    ('\u1FFF', '\u1FFF'),
+    ('\u200B', '\u200F'),
+    ('\u202A', '\u202E'),
+    ('\u2060', '\u206F'),
+    ('\u2072', '\u2073'),
+    ('\u208F', '\u208F'),
+    ('\u209D', '\u209F'),
+    ('\u20C1', '\u20CF'),
+    ('\u20F1', '\u20FF'),
+    ('\u218C', '\u218F'),
+    ('\u2427', '\u243F'),
+    ('\u244B', '\u245F'),
+    ('\u2B74', '\u2B75'),
+    ('\u2B96', '\u2B96'),
+    ('\u2CF4', '\u2CF8'),
+    ('\u2D26', '\u2D26'),
+    ('\u2D28', '\u2D2C'),
+    ('\u2D2E', '\u2D2F'),
+    ('\u2D68', '\u2D6E'),
+    ('\u2D71', '\u2D7E'),
+    ('\u2D97', '\u2D9F'),
+    ('\u2DA7', '\u2DA7'),
+    ('\u2DAF', '\u2DAF'),
+    ('\u2DB7', '\u2DB7'),
+    ('\u2DBF', '\u2DBF'),
+    ('\u2DC7', '\u2DC7'),
+    ('\u2DCF', '\u2DCF'),
+    ('\u2DD7', '\u2DD7'),
+    ('\u2DDF', '\u2DDF'),
+    ('\u2E5E', '\u2E7F'),
+    ('\u2E9A', '\u2E9A'),
+    ('\u2EF4', '\u2EFF'),
+    ('\u2FD6', '\u2FEF'),
+    ('\u2FFC', '\u2FFF'),
+    ('\u3040', '\u3040'),
+    ('\u3097', '\u3098'),
+    ('\u3100', '\u3104'),
+    ('\u3130', '\u3130'),
+    ('\u318F', '\u318F'),
+    ('\u31E4', '\u31EF'),
+    ('\u321F', '\u321F'),
+    ('\uA48D', '\uA48F'),
+    ('\uA4C7', '\uA4CF'),
+    ('\uA62C', '\uA63F'),
+    ('\uA6F8', '\uA6FF'),
+    ('\uA7CB', '\uA7CF'),
+    ('\uA7D2', '\uA7D2'),
+    ('\uA7D4', '\uA7D4'),
+    ('\uA7DA', '\uA7F1'),
+    ('\uA82D', '\uA82F'),
+    ('\uA83A', '\uA83F'),
+    ('\uA878', '\uA87F'),
+    ('\uA8C6', '\uA8CD'),
+    ('\uA8DA', '\uA8DF'),
+    ('\uA954', '\uA95E'),
+    ('\uA97D', '\uA97F'),
+    ('\uA9CE', '\uA9CE'),
+    ('\uA9DA', '\uA9DD'),
+    ('\uA9FF', '\uA9FF'),
+    ('\uAA37', '\uAA3F'),
+    ('\uAA4E', '\uAA4F'),
+    ('\uAA5A', '\uAA5B'),
+    ('\uAAC3', '\uAADA'),
+    ('\uAAF7', '\uAB00'),
+    ('\uAB07', '\uAB08'),
+    ('\uAB0F', '\uAB10'),
+    ('\uAB17', '\uAB1F'),
+    ('\uAB27', '\uAB27'),
+    ('\uAB2F', '\uAB2F'),
+    ('\uAB6C', '\uAB6F'),
+    ('\uABEE', '\uABEF'),
+    ('\uABFA', '\uABFF'),
+    ('\uD7A4', '\uD7AF'),
+    ('\uD7C7', '\uD7CA'),
+    ('\uD7FC', '\uD7FF'),
+    ('\uE000', '\uF8FF'),
+    ('\uFA6E', '\uFA6F'),
+    ('\uFADA', '\uFAFF'),
+    ('\uFB07', '\uFB12'),
+    ('\uFB18', '\uFB1C'),
+    ('\uFB37', '\uFB37'),
+    ('\uFB3D', '\uFB3D'),
+    ('\uFB3F', '\uFB3F'),
+    ('\uFB42', '\uFB42'),
+    ('\uFB45', '\uFB45'),
+    ('\uFBC3', '\uFBD2'),
+    ('\uFD90', '\uFD91'),
+    ('\uFDC8', '\uFDCE'),
+    ('\uFDD0', '\uFDEF'),
+    ('\uFE1A', '\uFE1F'),
+    ('\uFE53', '\uFE53'),
+    ('\uFE67', '\uFE67'),
+    ('\uFE6C', '\uFE6F'),
+    ('\uFE75', '\uFE75'),
+    ('\uFEFD', '\uFF00'),
+    ('\uFFBF', '\uFFC1'),
+    ('\uFFC8', '\uFFC9'),
+    ('\uFFD0', '\uFFD1'),
+    ('\uFFD8', '\uFFD9'),
+    ('\uFFDD', '\uFFDF'),
+    ('\uFFE7', '\uFFE7'),
+    ('\uFFEF', '\uFFFB'),
+    ('\uFFFE', '\uFFFF'),
+    ('\U0001000C', '\U0001000C'),
+    ('\U00010027', '\U00010027'),
+    ('\U0001003B', '\U0001003B'),
+    ('\U0001003E', '\U0001003E'),
+    ('\U0001004E', '\U0001004F'),
+    ('\U0001005E', '\U0001007F'),
+    ('\U000100FB', '\U000100FF'),
+    ('\U00010103', '\U00010106'),
+    ('\U00010134', '\U00010136'),
+    ('\U0001018F', '\U0001018F'),
+    ('\U0001019D', '\U0001019F'),
+    ('\U000101A1', '\U000101CF'),
+    ('\U000101FE', '\U0001027F'),
+    ('\U0001029D', '\U0001029F'),
+    ('\U000102D1', '\U000102DF'),
+    ('\U000102FC', '\U000102FF'),
+    ('\U00010324', '\U0001032C'),
+    ('\U0001034B', '\U0001034F'),
+    ('\U0001037B', '\U0001037F'),
+    ('\U0001039E', '\U0001039E'),
+    ('\U000103C4', '\U000103C7'),
+    ('\U000103D6', '\U000103FF'),
+    ('\U0001049E', '\U0001049F'),
+    ('\U000104AA', '\U000104AF'),
+    ('\U000104D4', '\U000104D7'),
+    ('\U000104FC', '\U000104FF'),
+    ('\U00010528', '\U0001052F'),
+    ('\U00010564', '\U0001056E'),
+    ('\U0001057B', '\U0001057B'),
+    ('\U0001058B', '\U0001058B'),
+    ('\U00010593', '\U00010593'),
+    ('\U00010596', '\U00010596'),
+    ('\U000105A2', '\U000105A2'),
+    ('\U000105B2', '\U000105B2'),
+    ('\U000105BA', '\U000105BA'),
+    ('\U000105BD', '\U000105FF'),
+    ('\U00010737', '\U0001073F'),
+    ('\U00010756', '\U0001075F'),
+    ('\U00010768', '\U0001077F'),
+    ('\U00010786', '\U00010786'),
+    ('\U000107B1', '\U000107B1'),
+    ('\U000107BB', '\U000107FF'),
+    ('\U00010806', '\U00010807'),
+    ('\U00010809', '\U00010809'),
+    ('\U00010836', '\U00010836'),
+    ('\U00010839', '\U0001083B'),
+    ('\U0001083D', '\U0001083E'),
+    ('\U00010856', '\U00010856'),
+    ('\U0001089F', '\U000108A6'),
+    ('\U000108B0', '\U000108DF'),
+    ('\U000108F3', '\U000108F3'),
+    ('\U000108F6', '\U000108FA'),
+    ('\U0001091C', '\U0001091E'),
+    ('\U0001093A', '\U0001093E'),
+    ('\U00010940', '\U0001097F'),
+    ('\U000109B8', '\U000109BB'),
+    ('\U000109D0', '\U000109D1'),
+    ('\U00010A04', '\U00010A04'),
+    ('\U00010A07', '\U00010A0B'),
+    ('\U00010A14', '\U00010A14'),
+    ('\U00010A18', '\U00010A18'),
+    ('\U00010A36', '\U00010A37'),
+    ('\U00010A3B', '\U00010A3E'),
+    ('\U00010A49', '\U00010A4F'),
+    ('\U00010A59', '\U00010A5F'),
+    ('\U00010AA0', '\U00010ABF'),
+    ('\U00010AE7', '\U00010AEA'),
+    ('\U00010AF7', '\U00010AFF'),
+    ('\U00010B36', '\U00010B38'),
+    ('\U00010B56', '\U00010B57'),
+    ('\U00010B73', '\U00010B77'),
+    ('\U00010B92', '\U00010B98'),
+    ('\U00010B9D', '\U00010BA8'),
+    ('\U00010BB0', '\U00010BFF'),
+    ('\U00010C49', '\U00010C7F'),
+    ('\U00010CB3', '\U00010CBF'),
+    ('\U00010CF3', '\U00010CF9'),
+    ('\U00010D28', '\U00010D2F'),
+    ('\U00010D3A', '\U00010E5F'),
+    ('\U00010E7F', '\U00010E7F'),
+    ('\U00010EAA', '\U00010EAA'),
+    ('\U00010EAE', '\U00010EAF'),
+    ('\U00010EB2', '\U00010EFF'),
+    ('\U00010F28', '\U00010F2F'),
+    ('\U00010F5A', '\U00010F6F'),
+    ('\U00010F8A', '\U00010FAF'),
+    ('\U00010FCC', '\U00010FDF'),
+    ('\U00010FF7', '\U00010FFF'),
+    ('\U0001104E', '\U00011051'),
+    ('\U00011076', '\U0001107E'),
+    ('\U000110BD', '\U000110BD'),
+    ('\U000110C3', '\U000110CF'),
+    ('\U000110E9', '\U000110EF'),
+    ('\U000110FA', '\U000110FF'),
+    ('\U00011135', '\U00011135'),
+    ('\U00011148', '\U0001114F'),
+    ('\U00011177', '\U0001117F'),
+    ('\U000111E0', '\U000111E0'),
+    ('\U000111F5', '\U000111FF'),
+    ('\U00011212', '\U00011212'),
+    ('\U0001123F', '\U0001127F'),
+    ('\U00011287', '\U00011287'),
+    ('\U00011289', '\U00011289'),
+    ('\U0001128E', '\U0001128E'),
+    ('\U0001129E', '\U0001129E'),
+    ('\U000112AA', '\U000112AF'),
+    ('\U000112EB', '\U000112EF'),
+    ('\U000112FA', '\U000112FF'),
+    ('\U00011304', '\U00011304'),
+    ('\U0001130D', '\U0001130E'),
+    ('\U00011311', '\U00011312'),
+    ('\U00011329', '\U00011329'),
+    ('\U00011331', '\U00011331'),
+    ('\U00011334', '\U00011334'),
+    ('\U0001133A', '\U0001133A'),
+    ('\U00011345', '\U00011346'),
+    ('\U00011349', '\U0001134A'),
+    ('\U0001134E', '\U0001134F'),
+    ('\U00011351', '\U00011356'),
+    ('\U00011358', '\U0001135C'),
+    ('\U00011364', '\U00011365'),
+    ('\U0001136D', '\U0001136F'),
+    ('\U00011375', '\U000113FF'),
+    ('\U0001145C', '\U0001145C'),
+    ('\U00011462', '\U0001147F'),
+    ('\U000114C8', '\U000114CF'),
+    ('\U000114DA', '\U0001157F'),
+    ('\U000115B6', '\U000115B7'),
+    ('\U000115DE', '\U000115FF'),
+    ('\U00011645', '\U0001164F'),
+    ('\U0001165A', '\U0001165F'),
+    ('\U0001166D', '\U0001167F'),
+    ('\U000116BA', '\U000116BF'),
+    ('\U000116CA', '\U000116FF'),
+    ('\U0001171B', '\U0001171C'),
+    ('\U0001172C', '\U0001172F'),
+    ('\U00011747', '\U000117FF'),
+    ('\U0001183C', '\U0001189F'),
+    ('\U000118F3', '\U000118FE'),
+    ('\U00011907', '\U00011908'),
+    ('\U0001190A', '\U0001190B'),
+    ('\U00011914', '\U00011914'),
+    ('\U00011917', '\U00011917'),
+    ('\U00011936', '\U00011936'),
+    ('\U00011939', '\U0001193A'),
+    ('\U00011947', '\U0001194F'),
+    ('\U0001195A', '\U0001199F'),
+    ('\U000119A8', '\U000119A9'),
+    ('\U000119D8', '\U000119D9'),
+    ('\U000119E5', '\U000119FF'),
+    ('\U00011A48', '\U00011A4F'),
+    ('\U00011AA3', '\U00011AAF'),
+    ('\U00011AF9', '\U00011BFF'),
+    ('\U00011C09', '\U00011C09'),
+    ('\U00011C37', '\U00011C37'),
+    ('\U00011C46', '\U00011C4F'),
+    ('\U00011C6D', '\U00011C6F'),
+    ('\U00011C90', '\U00011C91'),
+    ('\U00011CA8', '\U00011CA8'),
+    ('\U00011CB7', '\U00011CFF'),
+    ('\U00011D07', '\U00011D07'),
+    ('\U00011D0A', '\U00011D0A'),
+    ('\U00011D37', '\U00011D39'),
+    ('\U00011D3B', '\U00011D3B'),
+    ('\U00011D3E', '\U00011D3E'),
+    ('\U00011D48', '\U00011D4F'),
+    ('\U00011D5A', '\U00011D5F'),
+    ('\U00011D66', '\U00011D66'),
+    ('\U00011D69', '\U00011D69'),
+    ('\U00011D8F', '\U00011D8F'),
+    ('\U00011D92', '\U00011D92'),
+    ('\U00011D99', '\U00011D9F'),
+    ('\U00011DAA', '\U00011EDF'),
+    ('\U00011EF9', '\U00011FAF'),
+    ('\U00011FB1', '\U00011FBF'),
+    ('\U00011FF2', '\U00011FFE'),
+    ('\U0001239A', '\U000123FF'),
+    ('\U0001246F', '\U0001246F'),
+    ('\U00012475', '\U0001247F'),
+    ('\U00012544', '\U00012F8F'),
+    ('\U00012FF3', '\U00012FFF'),
+    ('\U0001342F', '\U000143FF'),
+    ('\U00014647', '\U000167FF'),
+    ('\U00016A39', '\U00016A3F'),
+    ('\U00016A5F', '\U00016A5F'),
+    ('\U00016A6A', '\U00016A6D'),
+    ('\U00016ABF', '\U00016ABF'),
+    ('\U00016ACA', '\U00016ACF'),
+    ('\U00016AEE', '\U00016AEF'),
+    ('\U00016AF6', '\U00016AFF'),
+    ('\U00016B46', '\U00016B4F'),
+    ('\U00016B5A', '\U00016B5A'),
+    ('\U00016B62', '\U00016B62'),
+    ('\U00016B78', '\U00016B7C'),
+    ('\U00016B90', '\U00016E3F'),
+    ('\U00016E9B', '\U00016EFF'),
+    ('\U00016F4B', '\U00016F4E'),
+    ('\U00016F88', '\U00016F8E'),
+    ('\U00016FA0', '\U00016FDF'),
+    ('\U00016FE5', '\U00016FEF'),
+    ('\U00016FF2', '\U00016FFF'),
+    ('\U000187F8', '\U000187FF'),
+    ('\U00018CD6', '\U00018CFF'),
+    ('\U00018D09', '\U0001AFEF'),
+    ('\U0001AFF4', '\U0001AFF4'),
+    ('\U0001AFFC', '\U0001AFFC'),
+    ('\U0001AFFF', '\U0001AFFF'),
+    ('\U0001B123', '\U0001B14F'),
+    ('\U0001B153', '\U0001B163'),
+    ('\U0001B168', '\U0001B16F'),
+    ('\U0001B2FC', '\U0001BBFF'),
+    ('\U0001BC6B', '\U0001BC6F'),
+    ('\U0001BC7D', '\U0001BC7F'),
+    ('\U0001BC89', '\U0001BC8F'),
+    ('\U0001BC9A', '\U0001BC9B'),
+    ('\U0001BCA0', '\U0001CEFF'),
+    ('\U0001CF2E', '\U0001CF2F'),
+    ('\U0001CF47', '\U0001CF4F'),
+    ('\U0001CFC4', '\U0001CFFF'),
+    ('\U0001D0F6', '\U0001D0FF'),
+    ('\U0001D127', '\U0001D128'),
+    ('\U0001D173', '\U0001D17A'),
+    ('\U0001D1EB', '\U0001D1FF'),
+    ('\U0001D246', '\U0001D2DF'),
+    ('\U0001D2F4', '\U0001D2FF'),
+    ('\U0001D357', '\U0001D35F'),
+    ('\U0001D379', '\U0001D3FF'),
+    ('\U0001D455', '\U0001D455'),
+    ('\U0001D49D', '\U0001D49D'),
+    ('\U0001D4A0', '\U0001D4A1'),
+    ('\U0001D4A3', '\U0001D4A4'),
+    ('\U0001D4A7', '\U0001D4A8'),
+    ('\U0001D4AD', '\U0001D4AD'),
+    ('\U0001D4BA', '\U0001D4BA'),
+    ('\U0001D4BC', '\U0001D4BC'),
+    ('\U0001D4C4', '\U0001D4C4'),
+    ('\U0001D506', '\U0001D506'),
+    ('\U0001D50B', '\U0001D50C'),
+    ('\U0001D515', '\U0001D515'),
+    ('\U0001D51D', '\U0001D51D'),
+    ('\U0001D53A', '\U0001D53A'),
+    ('\U0001D53F', '\U0001D53F'),
+    ('\U0001D545', '\U0001D545'),
+    ('\U0001D547', '\U0001D549'),
+    ('\U0001D551', '\U0001D551'),
+    ('\U0001D6A6', '\U0001D6A7'),
+    ('\U0001D7CC', '\U0001D7CD'),
+    ('\U0001DA8C', '\U0001DA9A'),
+    ('\U0001DAA0', '\U0001DAA0'),
+    ('\U0001DAB0', '\U0001DEFF'),
+    ('\U0001DF1F', '\U0001DFFF'),
+    ('\U0001E007', '\U0001E007'),
+    ('\U0001E019', '\U0001E01A'),
+    ('\U0001E022', '\U0001E022'),
+    ('\U0001E025', '\U0001E025'),
+    ('\U0001E02B', '\U0001E0FF'),
+    ('\U0001E12D', '\U0001E12F'),
+    ('\U0001E13E', '\U0001E13F'),
+    ('\U0001E14A', '\U0001E14D'),
+    ('\U0001E150', '\U0001E28F'),
+    ('\U0001E2AF', '\U0001E2BF'),
+    ('\U0001E2FA', '\U0001E2FE'),
+    ('\U0001E300', '\U0001E7DF'),
+    ('\U0001E7E7', '\U0001E7E7'),
+    ('\U0001E7EC', '\U0001E7EC'),
+    ('\U0001E7EF', '\U0001E7EF'),
+    ('\U0001E7FF', '\U0001E7FF'),
+    ('\U0001E8C5', '\U0001E8C6'),
+    ('\U0001E8D7', '\U0001E8FF'),
+    ('\U0001E94C', '\U0001E94F'),
+    ('\U0001E95A', '\U0001E95D'),
+    ('\U0001E960', '\U0001EC70'),
+    ('\U0001ECB5', '\U0001ED00'),
+    ('\U0001ED3E', '\U0001EDFF'),
+    ('\U0001EE04', '\U0001EE04'),
+    ('\U0001EE20', '\U0001EE20'),
+    ('\U0001EE23', '\U0001EE23'),
+    ('\U0001EE25', '\U0001EE26'),
+    ('\U0001EE28', '\U0001EE28'),
+    ('\U0001EE33', '\U0001EE33'),
+    ('\U0001EE38', '\U0001EE38'),
+    ('\U0001EE3A', '\U0001EE3A'),
+    ('\U0001EE3C', '\U0001EE41'),
+    ('\U0001EE43', '\U0001EE46'),
+    ('\U0001EE48', '\U0001EE48'),
+    ('\U0001EE4A', '\U0001EE4A'),
+    ('\U0001EE4C', '\U0001EE4C'),
+    ('\U0001EE50', '\U0001EE50'),
+    ('\U0001EE53', '\U0001EE53'),
+    ('\U0001EE55', '\U0001EE56'),
+    ('\U0001EE58', '\U0001EE58'),
+    ('\U0001EE5A', '\U0001EE5A'),
+    ('\U0001EE5C', '\U0001EE5C'),
+    ('\U0001EE5E', '\U0001EE5E'),
+    ('\U0001EE60', '\U0001EE60'),
+    ('\U0001EE63', '\U0001EE63'),
+    ('\U0001EE65', '\U0001EE66'),
+    ('\U0001EE6B', '\U0001EE6B'),
+    ('\U0001EE73', '\U0001EE73'),
+    ('\U0001EE78', '\U0001EE78'),
+    ('\U0001EE7D', '\U0001EE7D'),
+    ('\U0001EE7F', '\U0001EE7F'),
+    ('\U0001EE8A', '\U0001EE8A'),
+    ('\U0001EE9C', '\U0001EEA0'),
+    ('\U0001EEA4', '\U0001EEA4'),
+    ('\U0001EEAA', '\U0001EEAA'),
+    ('\U0001EEBC', '\U0001EEEF'),
+    ('\U0001EEF2', '\U0001EFFF'),
+    ('\U0001F02C', '\U0001F02F'),
+    ('\U0001F094', '\U0001F09F'),
+    ('\U0001F0AF', '\U0001F0B0'),
+    ('\U0001F0C0', '\U0001F0C0'),
+    ('\U0001F0D0', '\U0001F0D0'),
+    ('\U0001F0F6', '\U0001F0FF'),
+    ('\U0001F1AE', '\U0001F1E5'),
+    ('\U0001F203', '\U0001F20F'),
+    ('\U0001F23C', '\U0001F23F'),
+    ('\U0001F249', '\U0001F24F'),
+    ('\U0001F252', '\U0001F25F'),
+    ('\U0001F266', '\U0001F2FF'),
+    ('\U0001F6D8', '\U0001F6DC'),
+    ('\U0001F6ED', '\U0001F6EF'),
+    ('\U0001F6FD', '\U0001F6FF'),
+    ('\U0001F774', '\U0001F77F'),
+    ('\U0001F7D9', '\U0001F7DF'),
+    ('\U0001F7EC', '\U0001F7EF'),
+    ('\U0001F7F1', '\U0001F7FF'),
+    ('\U0001F80C', '\U0001F80F'),
+    ('\U0001F848', '\U0001F84F'),
+    ('\U0001F85A', '\U0001F85F'),
+    ('\U0001F888', '\U0001F88F'),
+    ('\U0001F8AE', '\U0001F8AF'),
+    ('\U0001F8B2', '\U0001F8FF'),
+    ('\U0001FA54', '\U0001FA5F'),
+    ('\U0001FA6E', '\U0001FA6F'),
+    ('\U0001FA75', '\U0001FA77'),
+    ('\U0001FA7D', '\U0001FA7F'),
+    ('\U0001FA87', '\U0001FA8F'),
+    ('\U0001FAAD', '\U0001FAAF'),
+    ('\U0001FABB', '\U0001FABF'),
+    ('\U0001FAC6', '\U0001FACF'),
+    ('\U0001FADA', '\U0001FADF'),
+    ('\U0001FAE8', '\U0001FAEF'),
+    ('\U0001FAF7', '\U0001FAFF'),
+    ('\U0001FB93', '\U0001FB93'),
+    ('\U0001FBCB', '\U0001FBEF'),
+    ('\U0001FBFA', '\U0001FFFF'),
+    ('\U0002A6E0', '\U0002A6FF'),
+    ('\U0002B739', '\U0002B73F'),
+    ('\U0002B81E', '\U0002B81F'),
+    ('\U0002CEA2', '\U0002CEAF'),
+    ('\U0002EBE1', '\U0002F7FF'),
+    ('\U0002FA1E', '\U0002FFFF'),
+    ('\U0003134B', '\U000E00FF'),
+    ('\U000E01F0', '\U0010FFFF')
+  ]),
+  ("cc", &[
+    ('\u0000', '\u001F'),
+    ('\u007F', '\u009F')
+  ]),
+  ("cf", &[
+    ('\u00AD', '\u00AD'),
+    ('\u0600', '\u0605'),
+    ('\u061C', '\u061C'),
+    ('\u06DD', '\u06DD'),
+    ('\u070F', '\u070F'),
+    ('\u0890', '\u0891'),
+    ('\u08E2', '\u08E2'),
+    ('\u180E', '\u180E'),
+    ('\u200B', '\u200F'),
+    ('\u202A', '\u202E'),
+    ('\u2060', '\u2064'),
+    ('\u2066', '\u206F'),
+    ('\uFEFF', '\uFEFF'),
+    ('\uFFF9', '\uFFFB'),
+    ('\U000110BD', '\U000110BD'),
+    ('\U000110CD', '\U000110CD'),
+    ('\U00013430', '\U00013438'),
+    ('\U0001BCA0', '\U0001BCA3'),
+    ('\U0001D173', '\U0001D17A'),
+    ('\U000E0001', '\U000E0001'),
+    ('\U000E0020', '\U000E007F')
+  ]),
+  ("cn", &[
+    ('\u0378', '\u0379'),
+    ('\u0380', '\u0383'),
+    ('\u038B', '\u038B'),
+    ('\u038D', '\u038D'),
+    ('\u03A2', '\u03A2'),
+    ('\u0530', '\u0530'),
+    ('\u0557', '\u0558'),
+    ('\u058B', '\u058C'),
+    ('\u0590', '\u0590'),
+    ('\u05C8', '\u05CF'),
+    ('\u05EB', '\u05EE'),
+    ('\u05F5', '\u05FF'),
+    ('\u070E', '\u070E'),
+    ('\u074B', '\u074C'),
+    ('\u07B2', '\u07BF'),
+    ('\u07FB', '\u07FC'),
+    ('\u082E', '\u082F'),
+    ('\u083F', '\u083F'),
+    ('\u085C', '\u085D'),
+    ('\u085F', '\u085F'),
+    ('\u086B', '\u086F'),
+    ('\u088F', '\u088F'),
+    ('\u0892', '\u0897'),
+    ('\u0984', '\u0984'),
+    ('\u098D', '\u098E'),
+    ('\u0991', '\u0992'),
+    ('\u09A9', '\u09A9'),
+    ('\u09B1', '\u09B1'),
+    ('\u09B3', '\u09B5'),
+    ('\u09BA', '\u09BB'),
+    ('\u09C5', '\u09C6'),
+    ('\u09C9', '\u09CA'),
+    ('\u09CF', '\u09D6'),
+    ('\u09D8', '\u09DB'),
+    ('\u09DE', '\u09DE'),
+    ('\u09E4', '\u09E5'),
+    ('\u09FF', '\u0A00'),
+    ('\u0A04', '\u0A04'),
+    ('\u0A0B', '\u0A0E'),
+    ('\u0A11', '\u0A12'),
+    ('\u0A29', '\u0A29'),
+    ('\u0A31', '\u0A31'),
+    ('\u0A34', '\u0A34'),
+    ('\u0A37', '\u0A37'),
+    ('\u0A3A', '\u0A3B'),
+    ('\u0A3D', '\u0A3D'),
+    ('\u0A43', '\u0A46'),
+    ('\u0A49', '\u0A4A'),
+    ('\u0A4E', '\u0A50'),
+    ('\u0A52', '\u0A58'),
+    ('\u0A5D', '\u0A5D'),
+    ('\u0A5F', '\u0A65'),
+    ('\u0A77', '\u0A80'),
+    ('\u0A84', '\u0A84'),
+    ('\u0A8E', '\u0A8E'),
+    ('\u0A92', '\u0A92'),
+    ('\u0AA9', '\u0AA9'),
+    ('\u0AB1', '\u0AB1'),
+    ('\u0AB4', '\u0AB4'),
+    ('\u0ABA', '\u0ABB'),
+    ('\u0AC6', '\u0AC6'),
+    ('\u0ACA', '\u0ACA'),
+    ('\u0ACE', '\u0ACF'),
+    ('\u0AD1', '\u0ADF'),
+    ('\u0AE4', '\u0AE5'),
+    ('\u0AF2', '\u0AF8'),
+    ('\u0B00', '\u0B00'),
+    ('\u0B04', '\u0B04'),
+    ('\u0B0D', '\u0B0E'),
+    ('\u0B11', '\u0B12'),
+    ('\u0B29', '\u0B29'),
+    ('\u0B31', '\u0B31'),
+    ('\u0B34', '\u0B34'),
+    ('\u0B3A', '\u0B3B'),
+    ('\u0B45', '\u0B46'),
+    ('\u0B49', '\u0B4A'),
+    ('\u0B4E', '\u0B54'),
+    ('\u0B58', '\u0B5B'),
+    ('\u0B5E', '\u0B5E'),
+    ('\u0B64', '\u0B65'),
+    ('\u0B78', '\u0B81'),
+    ('\u0B84', '\u0B84'),
+    ('\u0B8B', '\u0B8D'),
+    ('\u0B91', '\u0B91'),
+    ('\u0B96', '\u0B98'),
+    ('\u0B9B', '\u0B9B'),
+    ('\u0B9D', '\u0B9D'),
+    ('\u0BA0', '\u0BA2'),
+    ('\u0BA5', '\u0BA7'),
+    ('\u0BAB', '\u0BAD'),
+    ('\u0BBA', '\u0BBD'),
+    ('\u0BC3', '\u0BC5'),
+    ('\u0BC9', '\u0BC9'),
+    ('\u0BCE', '\u0BCF'),
+    ('\u0BD1', '\u0BD6'),
+    ('\u0BD8', '\u0BE5'),
+    ('\u0BFB', '\u0BFF'),
+    ('\u0C0D', '\u0C0D'),
+    ('\u0C11', '\u0C11'),
+    ('\u0C29', '\u0C29'),
+    ('\u0C3A', '\u0C3B'),
+    ('\u0C45', '\u0C45'),
+    ('\u0C49', '\u0C49'),
+    ('\u0C4E', '\u0C54'),
+    ('\u0C57', '\u0C57'),
+    ('\u0C5B', '\u0C5C'),
+    ('\u0C5E', '\u0C5F'),
+    ('\u0C64', '\u0C65'),
+    ('\u0C70', '\u0C76'),
+    ('\u0C8D', '\u0C8D'),
+    ('\u0C91', '\u0C91'),
+    ('\u0CA9', '\u0CA9'),
+    ('\u0CB4', '\u0CB4'),
+    ('\u0CBA', '\u0CBB'),
+    ('\u0CC5', '\u0CC5'),
+    ('\u0CC9', '\u0CC9'),
+    ('\u0CCE', '\u0CD4'),
+    ('\u0CD7', '\u0CDC'),
+    ('\u0CDF', '\u0CDF'),
+    ('\u0CE4', '\u0CE5'),
+    ('\u0CF0', '\u0CF0'),
+    ('\u0CF3', '\u0CFF'),
+    ('\u0D0D', '\u0D0D'),
+    ('\u0D11', '\u0D11'),
+    ('\u0D45', '\u0D45'),
+    ('\u0D49', '\u0D49'),
+    ('\u0D50', '\u0D53'),
+    ('\u0D64', '\u0D65'),
+    ('\u0D80', '\u0D80'),
+    ('\u0D84', '\u0D84'),
+    ('\u0D97', '\u0D99'),
+    ('\u0DB2', '\u0DB2'),
+    ('\u0DBC', '\u0DBC'),
+    ('\u0DBE', '\u0DBF'),
+    ('\u0DC7', '\u0DC9'),
+    ('\u0DCB', '\u0DCE'),
+    ('\u0DD5', '\u0DD5'),
+    ('\u0DD7', '\u0DD7'),
+    ('\u0DE0', '\u0DE5'),
+    ('\u0DF0', '\u0DF1'),
+    ('\u0DF5', '\u0E00'),
+    ('\u0E3B', '\u0E3E'),
+    ('\u0E5C', '\u0E80'),
+    ('\u0E83', '\u0E83'),
+    ('\u0E85', '\u0E85'),
+    ('\u0E8B', '\u0E8B'),
+    ('\u0EA4', '\u0EA4'),
+    ('\u0EA6', '\u0EA6'),
+    ('\u0EBE', '\u0EBF'),
+    ('\u0EC5', '\u0EC5'),
+    ('\u0EC7', '\u0EC7'),
+    ('\u0ECE', '\u0ECF'),
+    ('\u0EDA', '\u0EDB'),
+    ('\u0EE0', '\u0EFF'),
+    ('\u0F48', '\u0F48'),
+    ('\u0F6D', '\u0F70'),
+    ('\u0F98', '\u0F98'),
+    ('\u0FBD', '\u0FBD'),
+    ('\u0FCD', '\u0FCD'),
+    ('\u0FDB', '\u0FFF'),
+    ('\u10C6', '\u10C6'),
+    ('\u10C8', '\u10CC'),
+    ('\u10CE', '\u10CF'),
+    ('\u1249', '\u1249'),
+    ('\u124E', '\u124F'),
+    ('\u1257', '\u1257'),
+    ('\u1259', '\u1259'),
+    ('\u125E', '\u125F'),
+    ('\u1289', '\u1289'),
+    ('\u128E', '\u128F'),
+    ('\u12B1', '\u12B1'),
+    ('\u12B6', '\u12B7'),
+    ('\u12BF', '\u12BF'),
+    ('\u12C1', '\u12C1'),
+    ('\u12C6', '\u12C7'),
+    ('\u12D7', '\u12D7'),
+    ('\u1311', '\u1311'),
+    ('\u1316', '\u1317'),
+    ('\u135B', '\u135C'),
+    ('\u137D', '\u137F'),
+    ('\u139A', '\u139F'),
+    ('\u13F6', '\u13F7'),
+    ('\u13FE', '\u13FF'),
+    ('\u169D', '\u169F'),
+    ('\u16F9', '\u16FF'),
+    ('\u1716', '\u171E'),
+    ('\u1737', '\u173F'),
+    ('\u1754', '\u175F'),
+    ('\u176D', '\u176D'),
+    ('\u1771', '\u1771'),
+    ('\u1774', '\u177F'),
+    ('\u17DE', '\u17DF'),
+    ('\u17EA', '\u17EF'),
+    ('\u17FA', '\u17FF'),
+    ('\u181A', '\u181F'),
+    ('\u1879', '\u187F'),
+    ('\u18AB', '\u18AF'),
+    ('\u18F6', '\u18FF'),
+    ('\u191F', '\u191F'),
+    ('\u192C', '\u192F'),
+    ('\u193C', '\u193F'),
+    ('\u1941', '\u1943'),
+    ('\u196E', '\u196F'),
+    ('\u1975', '\u197F'),
+    ('\u19AC', '\u19AF'),
+    ('\u19CA', '\u19CF'),
+    ('\u19DB', '\u19DD'),
+    ('\u1A1C', '\u1A1D'),
+    ('\u1A5F', '\u1A5F'),
+    ('\u1A7D', '\u1A7E'),
+    ('\u1A8A', '\u1A8F'),
+    ('\u1A9A', '\u1A9F'),
+    ('\u1AAE', '\u1AAF'),
+    ('\u1ACF', '\u1AFF'),
+    ('\u1B4D', '\u1B4F'),
+    ('\u1B7F', '\u1B7F'),
+    ('\u1BF4', '\u1BFB'),
+    ('\u1C38', '\u1C3A'),
+    ('\u1C4A', '\u1C4C'),
+    ('\u1C89', '\u1C8F'),
+    ('\u1CBB', '\u1CBC'),
+    ('\u1CC8', '\u1CCF'),
+    ('\u1CFB', '\u1CFF'),
+    ('\u1F16', '\u1F17'),
+    ('\u1F1E', '\u1F1F'),
+    ('\u1F46', '\u1F47'),
+    ('\u1F4E', '\u1F4F'),
+    ('\u1F58', '\u1F58'),
+    ('\u1F5A', '\u1F5A'),
+    ('\u1F5C', '\u1F5C'),
+    ('\u1F5E', '\u1F5E'),
+    ('\u1F7E', '\u1F7F'),
+    ('\u1FB5', '\u1FB5'),
+    ('\u1FC5', '\u1FC5'),
+    ('\u1FD4', '\u1FD5'),
+    ('\u1FDC', '\u1FDC'),
+    ('\u1FF0', '\u1FF1'),
+    ('\u1FF5', '\u1FF5'),
+    ('\u1FFF', '\u1FFF'),
+    ('\u2065', '\u2065'),
+    ('\u2072', '\u2073'),
+    ('\u208F', '\u208F'),
+    ('\u209D', '\u209F'),
+    ('\u20C1', '\u20CF'),
+    ('\u20F1', '\u20FF'),
+    ('\u218C', '\u218F'),
+    ('\u2427', '\u243F'),
+    ('\u244B', '\u245F'),
+    ('\u2B74', '\u2B75'),
+    ('\u2B96', '\u2B96'),
+    ('\u2CF4', '\u2CF8'),
+    ('\u2D26', '\u2D26'),
+    ('\u2D28', '\u2D2C'),
+    ('\u2D2E', '\u2D2F'),
+    ('\u2D68', '\u2D6E'),
+    ('\u2D71', '\u2D7E'),
+    ('\u2D97', '\u2D9F'),
+    ('\u2DA7', '\u2DA7'),
+    ('\u2DAF', '\u2DAF'),
+    ('\u2DB7', '\u2DB7'),
+    ('\u2DBF', '\u2DBF'),
+    ('\u2DC7', '\u2DC7'),
+    ('\u2DCF', '\u2DCF'),
+    ('\u2DD7', '\u2DD7'),
+    ('\u2DDF', '\u2DDF'),
+    ('\u2E5E', '\u2E7F'),
+    ('\u2E9A', '\u2E9A'),
+    ('\u2EF4', '\u2EFF'),
+    ('\u2FD6', '\u2FEF'),
+    ('\u2FFC', '\u2FFF'),
+    ('\u3040', '\u3040'),
+    ('\u3097', '\u3098'),
+    ('\u3100', '\u3104'),
+    ('\u3130', '\u3130'),
+    ('\u318F', '\u318F'),
+    ('\u31E4', '\u31EF'),
+    ('\u321F', '\u321F'),
+    ('\uA48D', '\uA48F'),
+    ('\uA4C7', '\uA4CF'),
+    ('\uA62C', '\uA63F'),
+    ('\uA6F8', '\uA6FF'),
+    ('\uA7CB', '\uA7CF'),
+    ('\uA7D2', '\uA7D2'),
+    ('\uA7D4', '\uA7D4'),
+    ('\uA7DA', '\uA7F1'),
+    ('\uA82D', '\uA82F'),
+    ('\uA83A', '\uA83F'),
+    ('\uA878', '\uA87F'),
+    ('\uA8C6', '\uA8CD'),
+    ('\uA8DA', '\uA8DF'),
+    ('\uA954', '\uA95E'),
+    ('\uA97D', '\uA97F'),
+    ('\uA9CE', '\uA9CE'),
+    ('\uA9DA', '\uA9DD'),
+    ('\uA9FF', '\uA9FF'),
+    ('\uAA37', '\uAA3F'),
+    ('\uAA4E', '\uAA4F'),
+    ('\uAA5A', '\uAA5B'),
+    ('\uAAC3', '\uAADA'),
+    ('\uAAF7', '\uAB00'),
+    ('\uAB07', '\uAB08'),
+    ('\uAB0F', '\uAB10'),
+    ('\uAB17', '\uAB1F'),
+    ('\uAB27', '\uAB27'),
+    ('\uAB2F', '\uAB2F'),
+    ('\uAB6C', '\uAB6F'),
+    ('\uABEE', '\uABEF'),
+    ('\uABFA', '\uABFF'),
+    ('\uD7A4', '\uD7AF'),
+    ('\uD7C7', '\uD7CA'),
+    ('\uD7FC', '\uD7FF'),
+    ('\uFA6E', '\uFA6F'),
+    ('\uFADA', '\uFAFF'),
+    ('\uFB07', '\uFB12'),
+    ('\uFB18', '\uFB1C'),
+    ('\uFB37', '\uFB37'),
+    ('\uFB3D', '\uFB3D'),
+    ('\uFB3F', '\uFB3F'),
+    ('\uFB42', '\uFB42'),
+    ('\uFB45', '\uFB45'),
+    ('\uFBC3', '\uFBD2'),
+    ('\uFD90', '\uFD91'),
+    ('\uFDC8', '\uFDCE'),
+    ('\uFDD0', '\uFDEF'),
+    ('\uFE1A', '\uFE1F'),
+    ('\uFE53', '\uFE53'),
+    ('\uFE67', '\uFE67'),
+    ('\uFE6C', '\uFE6F'),
+    ('\uFE75', '\uFE75'),
+    ('\uFEFD', '\uFEFE'),
+    ('\uFF00', '\uFF00'),
+    ('\uFFBF', '\uFFC1'),
+    ('\uFFC8', '\uFFC9'),
+    ('\uFFD0', '\uFFD1'),
+    ('\uFFD8', '\uFFD9'),
+    ('\uFFDD', '\uFFDF'),
+    ('\uFFE7', '\uFFE7'),
+    ('\uFFEF', '\uFFF8'),
+    ('\uFFFE', '\uFFFF'),
+    ('\U0001000C', '\U0001000C'),
+    ('\U00010027', '\U00010027'),
+    ('\U0001003B', '\U0001003B'),
+    ('\U0001003E', '\U0001003E'),
+    ('\U0001004E', '\U0001004F'),
+    ('\U0001005E', '\U0001007F'),
+    ('\U000100FB', '\U000100FF'),
+    ('\U00010103', '\U00010106'),
+    ('\U00010134', '\U00010136'),
+    ('\U0001018F', '\U0001018F'),
+    ('\U0001019D', '\U0001019F'),
+    ('\U000101A1', '\U000101CF'),
+    ('\U000101FE', '\U0001027F'),
+    ('\U0001029D', '\U0001029F'),
+    ('\U000102D1', '\U000102DF'),
+    ('\U000102FC', '\U000102FF'),
+    ('\U00010324', '\U0001032C'),
+    ('\U0001034B', '\U0001034F'),
+    ('\U0001037B', '\U0001037F'),
+    ('\U0001039E', '\U0001039E'),
+    ('\U000103C4', '\U000103C7'),
+    ('\U000103D6', '\U000103FF'),
+    ('\U0001049E', '\U0001049F'),
+    ('\U000104AA', '\U000104AF'),
+    ('\U000104D4', '\U000104D7'),
+    ('\U000104FC', '\U000104FF'),
+    ('\U00010528', '\U0001052F'),
+    ('\U00010564', '\U0001056E'),
+    ('\U0001057B', '\U0001057B'),
+    ('\U0001058B', '\U0001058B'),
+    ('\U00010593', '\U00010593'),
+    ('\U00010596', '\U00010596'),
+    ('\U000105A2', '\U000105A2'),
+    ('\U000105B2', '\U000105B2'),
+    ('\U000105BA', '\U000105BA'),
+    ('\U000105BD', '\U000105FF'),
+    ('\U00010737', '\U0001073F'),
+    ('\U00010756', '\U0001075F'),
+    ('\U00010768', '\U0001077F'),
+    ('\U00010786', '\U00010786'),
+    ('\U000107B1', '\U000107B1'),
+    ('\U000107BB', '\U000107FF'),
+    ('\U00010806', '\U00010807'),
+    ('\U00010809', '\U00010809'),
+    ('\U00010836', '\U00010836'),
+    ('\U00010839', '\U0001083B'),
+    ('\U0001083D', '\U0001083E'),
+    ('\U00010856', '\U00010856'),
+    ('\U0001089F', '\U000108A6'),
+    ('\U000108B0', '\U000108DF'),
+    ('\U000108F3', '\U000108F3'),
+    ('\U000108F6', '\U000108FA'),
+    ('\U0001091C', '\U0001091E'),
+    ('\U0001093A', '\U0001093E'),
+    ('\U00010940', '\U0001097F'),
+    ('\U000109B8', '\U000109BB'),
+    ('\U000109D0', '\U000109D1'),
+    ('\U00010A04', '\U00010A04'),
+    ('\U00010A07', '\U00010A0B'),
+    ('\U00010A14', '\U00010A14'),
+    ('\U00010A18', '\U00010A18'),
+    ('\U00010A36', '\U00010A37'),
+    ('\U00010A3B', '\U00010A3E'),
+    ('\U00010A49', '\U00010A4F'),
+    ('\U00010A59', '\U00010A5F'),
+    ('\U00010AA0', '\U00010ABF'),
+    ('\U00010AE7', '\U00010AEA'),
+    ('\U00010AF7', '\U00010AFF'),
+    ('\U00010B36', '\U00010B38'),
+    ('\U00010B56', '\U00010B57'),
+    ('\U00010B73', '\U00010B77'),
+    ('\U00010B92', '\U00010B98'),
+    ('\U00010B9D', '\U00010BA8'),
+    ('\U00010BB0', '\U00010BFF'),
+    ('\U00010C49', '\U00010C7F'),
+    ('\U00010CB3', '\U00010CBF'),
+    ('\U00010CF3', '\U00010CF9'),
+    ('\U00010D28', '\U00010D2F'),
+    ('\U00010D3A', '\U00010E5F'),
+    ('\U00010E7F', '\U00010E7F'),
+    ('\U00010EAA', '\U00010EAA'),
+    ('\U00010EAE', '\U00010EAF'),
+    ('\U00010EB2', '\U00010EFF'),
+    ('\U00010F28', '\U00010F2F'),
+    ('\U00010F5A', '\U00010F6F'),
+    ('\U00010F8A', '\U00010FAF'),
+    ('\U00010FCC', '\U00010FDF'),
+    ('\U00010FF7', '\U00010FFF'),
+    ('\U0001104E', '\U00011051'),
+    ('\U00011076', '\U0001107E'),
+    ('\U000110C3', '\U000110CC'),
+    ('\U000110CE', '\U000110CF'),
+    ('\U000110E9', '\U000110EF'),
+    ('\U000110FA', '\U000110FF'),
+    ('\U00011135', '\U00011135'),
+    ('\U00011148', '\U0001114F'),
+    ('\U00011177', '\U0001117F'),
+    ('\U000111E0', '\U000111E0'),
+    ('\U000111F5', '\U000111FF'),
+    ('\U00011212', '\U00011212'),
+    ('\U0001123F', '\U0001127F'),
+    ('\U00011287', '\U00011287'),
+    ('\U00011289', '\U00011289'),
+    ('\U0001128E', '\U0001128E'),
+    ('\U0001129E', '\U0001129E'),
+    ('\U000112AA', '\U000112AF'),
+    ('\U000112EB', '\U000112EF'),
+    ('\U000112FA', '\U000112FF'),
+    ('\U00011304', '\U00011304'),
+    ('\U0001130D', '\U0001130E'),
+    ('\U00011311', '\U00011312'),
+    ('\U00011329', '\U00011329'),
+    ('\U00011331', '\U00011331'),
+    ('\U00011334', '\U00011334'),
+    ('\U0001133A', '\U0001133A'),
+    ('\U00011345', '\U00011346'),
+    ('\U00011349', '\U0001134A'),
+    ('\U0001134E', '\U0001134F'),
+    ('\U00011351', '\U00011356'),
+    ('\U00011358', '\U0001135C'),
+    ('\U00011364', '\U00011365'),
+    ('\U0001136D', '\U0001136F'),
+    ('\U00011375', '\U000113FF'),
+    ('\U0001145C', '\U0001145C'),
+    ('\U00011462', '\U0001147F'),
+    ('\U000114C8', '\U000114CF'),
+    ('\U000114DA', '\U0001157F'),
+    ('\U000115B6', '\U000115B7'),
+    ('\U000115DE', '\U000115FF'),
+    ('\U00011645', '\U0001164F'),
+    ('\U0001165A', '\U0001165F'),
+    ('\U0001166D', '\U0001167F'),
+    ('\U000116BA', '\U000116BF'),
+    ('\U000116CA', '\U000116FF'),
+    ('\U0001171B', '\U0001171C'),
+    ('\U0001172C', '\U0001172F'),
+    ('\U00011747', '\U000117FF'),
+    ('\U0001183C', '\U0001189F'),
+    ('\U000118F3', '\U000118FE'),
+    ('\U00011907', '\U00011908'),
+    ('\U0001190A', '\U0001190B'),
+    ('\U00011914', '\U00011914'),
+    ('\U00011917', '\U00011917'),
+    ('\U00011936', '\U00011936'),
+    ('\U00011939', '\U0001193A'),
+    ('\U00011947', '\U0001194F'),
+    ('\U0001195A', '\U0001199F'),
+    ('\U000119A8', '\U000119A9'),
+    ('\U000119D8', '\U000119D9'),
+    ('\U000119E5', '\U000119FF'),
+    ('\U00011A48', '\U00011A4F'),
+    ('\U00011AA3', '\U00011AAF'),
+    ('\U00011AF9', '\U00011BFF'),
+    ('\U00011C09', '\U00011C09'),
+    ('\U00011C37', '\U00011C37'),
+    ('\U00011C46', '\U00011C4F'),
+    ('\U00011C6D', '\U00011C6F'),
+    ('\U00011C90', '\U00011C91'),
+    ('\U00011CA8', '\U00011CA8'),
+    ('\U00011CB7', '\U00011CFF'),
+    ('\U00011D07', '\U00011D07'),
+    ('\U00011D0A', '\U00011D0A'),
+    ('\U00011D37', '\U00011D39'),
+    ('\U00011D3B', '\U00011D3B'),
+    ('\U00011D3E', '\U00011D3E'),
+    ('\U00011D48', '\U00011D4F'),
+    ('\U00011D5A', '\U00011D5F'),
+    ('\U00011D66', '\U00011D66'),
+    ('\U00011D69', '\U00011D69'),
+    ('\U00011D8F', '\U00011D8F'),
+    ('\U00011D92', '\U00011D92'),
+    ('\U00011D99', '\U00011D9F'),
+    ('\U00011DAA', '\U00011EDF'),
+    ('\U00011EF9', '\U00011FAF'),
+    ('\U00011FB1', '\U00011FBF'),
+    ('\U00011FF2', '\U00011FFE'),
+    ('\U0001239A', '\U000123FF'),
+    ('\U0001246F', '\U0001246F'),
+    ('\U00012475', '\U0001247F'),
+    ('\U00012544', '\U00012F8F'),
+    ('\U00012FF3', '\U00012FFF'),
+    ('\U0001342F', '\U0001342F'),
+    ('\U00013439', '\U000143FF'),
+    ('\U00014647', '\U000167FF'),
+    ('\U00016A39', '\U00016A3F'),
+    ('\U00016A5F', '\U00016A5F'),
+    ('\U00016A6A', '\U00016A6D'),
+    ('\U00016ABF', '\U00016ABF'),
+    ('\U00016ACA', '\U00016ACF'),
+    ('\U00016AEE', '\U00016AEF'),
+    ('\U00016AF6', '\U00016AFF'),
+    ('\U00016B46', '\U00016B4F'),
+    ('\U00016B5A', '\U00016B5A'),
+    ('\U00016B62', '\U00016B62'),
+    ('\U00016B78', '\U00016B7C'),
+    ('\U00016B90', '\U00016E3F'),
+    ('\U00016E9B', '\U00016EFF'),
+    ('\U00016F4B', '\U00016F4E'),
+    ('\U00016F88', '\U00016F8E'),
+    ('\U00016FA0', '\U00016FDF'),
+    ('\U00016FE5', '\U00016FEF'),
+    ('\U00016FF2', '\U00016FFF'),
+    ('\U000187F8', '\U000187FF'),
+    ('\U00018CD6', '\U00018CFF'),
+    ('\U00018D09', '\U0001AFEF'),
+    ('\U0001AFF4', '\U0001AFF4'),
+    ('\U0001AFFC', '\U0001AFFC'),
+    ('\U0001AFFF', '\U0001AFFF'),
+    ('\U0001B123', '\U0001B14F'),
+    ('\U0001B153', '\U0001B163'),
+    ('\U0001B168', '\U0001B16F'),
+    ('\U0001B2FC', '\U0001BBFF'),
+    ('\U0001BC6B', '\U0001BC6F'),
+    ('\U0001BC7D', '\U0001BC7F'),
+    ('\U0001BC89', '\U0001BC8F'),
+    ('\U0001BC9A', '\U0001BC9B'),
+    ('\U0001BCA4', '\U0001CEFF'),
+    ('\U0001CF2E', '\U0001CF2F'),
+    ('\U0001CF47', '\U0001CF4F'),
+    ('\U0001CFC4', '\U0001CFFF'),
+    ('\U0001D0F6', '\U0001D0FF'),
+    ('\U0001D127', '\U0001D128'),
+    ('\U0001D1EB', '\U0001D1FF'),
+    ('\U0001D246', '\U0001D2DF'),
+    ('\U0001D2F4', '\U0001D2FF'),
+    ('\U0001D357', '\U0001D35F'),
+    ('\U0001D379', '\U0001D3FF'),
+    ('\U0001D455', '\U0001D455'),
+    ('\U0001D49D', '\U0001D49D'),
+    ('\U0001D4A0', '\U0001D4A1'),
+    ('\U0001D4A3', '\U0001D4A4'),
+    ('\U0001D4A7', '\U0001D4A8'),
+    ('\U0001D4AD', '\U0001D4AD'),
+    ('\U0001D4BA', '\U0001D4BA'),
+    ('\U0001D4BC', '\U0001D4BC'),
+    ('\U0001D4C4', '\U0001D4C4'),
+    ('\U0001D506', '\U0001D506'),
+    ('\U0001D50B', '\U0001D50C'),
+    ('\U0001D515', '\U0001D515'),
+    ('\U0001D51D', '\U0001D51D'),
+    ('\U0001D53A', '\U0001D53A'),
+    ('\U0001D53F', '\U0001D53F'),
+    ('\U0001D545', '\U0001D545'),
+    ('\U0001D547', '\U0001D549'),
+    ('\U0001D551', '\U0001D551'),
+    ('\U0001D6A6', '\U0001D6A7'),
+    ('\U0001D7CC', '\U0001D7CD'),
+    ('\U0001DA8C', '\U0001DA9A'),
+    ('\U0001DAA0', '\U0001DAA0'),
+    ('\U0001DAB0', '\U0001DEFF'),
+    ('\U0001DF1F', '\U0001DFFF'),
+    ('\U0001E007', '\U0001E007'),
+    ('\U0001E019', '\U0001E01A'),
+    ('\U0001E022', '\U0001E022'),
+    ('\U0001E025', '\U0001E025'),
+    ('\U0001E02B', '\U0001E0FF'),
+    ('\U0001E12D', '\U0001E12F'),
+    ('\U0001E13E', '\U0001E13F'),
+    ('\U0001E14A', '\U0001E14D'),
+    ('\U0001E150', '\U0001E28F'),
+    ('\U0001E2AF', '\U0001E2BF'),
+    ('\U0001E2FA', '\U0001E2FE'),
+    ('\U0001E300', '\U0001E7DF'),
+    ('\U0001E7E7', '\U0001E7E7'),
+    ('\U0001E7EC', '\U0001E7EC'),
+    ('\U0001E7EF', '\U0001E7EF'),
+    ('\U0001E7FF', '\U0001E7FF'),
+    ('\U0001E8C5', '\U0001E8C6'),
+    ('\U0001E8D7', '\U0001E8FF'),
+    ('\U0001E94C', '\U0001E94F'),
+    ('\U0001E95A', '\U0001E95D'),
+    ('\U0001E960', '\U0001EC70'),
+    ('\U0001ECB5', '\U0001ED00'),
+    ('\U0001ED3E', '\U0001EDFF'),
+    ('\U0001EE04', '\U0001EE04'),
+    ('\U0001EE20', '\U0001EE20'),
+    ('\U0001EE23', '\U0001EE23'),
+    ('\U0001EE25', '\U0001EE26'),
+    ('\U0001EE28', '\U0001EE28'),
+    ('\U0001EE33', '\U0001EE33'),
+    ('\U0001EE38', '\U0001EE38'),
+    ('\U0001EE3A', '\U0001EE3A'),
+    ('\U0001EE3C', '\U0001EE41'),
+    ('\U0001EE43', '\U0001EE46'),
+    ('\U0001EE48', '\U0001EE48'),
+    ('\U0001EE4A', '\U0001EE4A'),
+    ('\U0001EE4C', '\U0001EE4C'),
+    ('\U0001EE50', '\U0001EE50'),
+    ('\U0001EE53', '\U0001EE53'),
+    ('\U0001EE55', '\U0001EE56'),
+    ('\U0001EE58', '\U0001EE58'),
+    ('\U0001EE5A', '\U0001EE5A'),
+    ('\U0001EE5C', '\U0001EE5C'),
+    ('\U0001EE5E', '\U0001EE5E'),
+    ('\U0001EE60', '\U0001EE60'),
+    ('\U0001EE63', '\U0001EE63'),
+    ('\U0001EE65', '\U0001EE66'),
+    ('\U0001EE6B', '\U0001EE6B'),
+    ('\U0001EE73', '\U0001EE73'),
+    ('\U0001EE78', '\U0001EE78'),
+    ('\U0001EE7D', '\U0001EE7D'),
+    ('\U0001EE7F', '\U0001EE7F'),
+    ('\U0001EE8A', '\U0001EE8A'),
+    ('\U0001EE9C', '\U0001EEA0'),
+    ('\U0001EEA4', '\U0001EEA4'),
+    ('\U0001EEAA', '\U0001EEAA'),
+    ('\U0001EEBC', '\U0001EEEF'),
+    ('\U0001EEF2', '\U0001EFFF'),
+    ('\U0001F02C', '\U0001F02F'),
+    ('\U0001F094', '\U0001F09F'),
+    ('\U0001F0AF', '\U0001F0B0'),
+    ('\U0001F0C0', '\U0001F0C0'),
+    ('\U0001F0D0', '\U0001F0D0'),
+    ('\U0001F0F6', '\U0001F0FF'),
+    ('\U0001F1AE', '\U0001F1E5'),
+    ('\U0001F203', '\U0001F20F'),
+    ('\U0001F23C', '\U0001F23F'),
+    ('\U0001F249', '\U0001F24F'),
+    ('\U0001F252', '\U0001F25F'),
+    ('\U0001F266', '\U0001F2FF'),
+    ('\U0001F6D8', '\U0001F6DC'),
+    ('\U0001F6ED', '\U0001F6EF'),
+    ('\U0001F6FD', '\U0001F6FF'),
+    ('\U0001F774', '\U0001F77F'),
+    ('\U0001F7D9', '\U0001F7DF'),
+    ('\U0001F7EC', '\U0001F7EF'),
+    ('\U0001F7F1', '\U0001F7FF'),
+    ('\U0001F80C', '\U0001F80F'),
+    ('\U0001F848', '\U0001F84F'),
+    ('\U0001F85A', '\U0001F85F'),
+    ('\U0001F888', '\U0001F88F'),
+    ('\U0001F8AE', '\U0001F8AF'),
+    ('\U0001F8B2', '\U0001F8FF'),
+    ('\U0001FA54', '\U0001FA5F'),
+    ('\U0001FA6E', '\U0001FA6F'),
+    ('\U0001FA75', '\U0001FA77'),
+    ('\U0001FA7D', '\U0001FA7F'),
+    ('\U0001FA87', '\U0001FA8F'),
+    ('\U0001FAAD', '\U0001FAAF'),
+    ('\U0001FABB', '\U0001FABF'),
+    ('\U0001FAC6', '\U0001FACF'),
+    ('\U0001FADA', '\U0001FADF'),
+    ('\U0001FAE8', '\U0001FAEF'),
+    ('\U0001FAF7', '\U0001FAFF'),
+    ('\U0001FB93', '\U0001FB93'),
+    ('\U0001FBCB', '\U0001FBEF'),
+    ('\U0001FBFA', '\U0001FFFF'),
+    ('\U0002A6E0', '\U0002A6FF'),
+    ('\U0002B739', '\U0002B73F'),
+    ('\U0002B81E', '\U0002B81F'),
+    ('\U0002CEA2', '\U0002CEAF'),
+    ('\U0002EBE1', '\U0002F7FF'),
+    ('\U0002FA1E', '\U0002FFFF'),
+    ('\U0003134B', '\U000E0000'),
+    ('\U000E0002', '\U000E001F'),
+    ('\U000E0080', '\U000E00FF'),
+    ('\U000E01F0', '\U000EFFFF'),
+    ('\U000FFFFE', '\U000FFFFF'),
+    ('\U0010FFFE', '\U0010FFFF')
+  ]),
+  ("co", &[
+    ('\uE000', '\uF8FF'),
+    ('\U000F0000', '\U000FFFFD'),
+    ('\U00100000', '\U0010FFFD')
+  ]),
+  ("cs", &[]),
+  ("l", &[
+    ('\u0041', '\u005A'),
+    ('\u0061', '\u007A'),
+    ('\u00AA', '\u00AA'),
+    ('\u00B5', '\u00B5'),
+    ('\u00BA', '\u00BA'),
+    ('\u00C0', '\u00D6'),
+    ('\u00D8', '\u00F6'),
+    ('\u00F8', '\u02C1'),
+    ('\u02C6', '\u02D1'),
+    ('\u02E0', '\u02E4'),
+    ('\u02EC', '\u02EC'),
+    ('\u02EE', '\u02EE'),
+    ('\u0370', '\u0374'),
+    ('\u0376', '\u0377'),
+    ('\u037A', '\u037D'),
+    ('\u037F', '\u037F'),
+    ('\u0386', '\u0386'),
+    ('\u0388', '\u038A'),
+    ('\u038C', '\u038C'),
+    ('\u038E', '\u03A1'),
+    ('\u03A3', '\u03F5'),
+    ('\u03F7', '\u0481'),
+    ('\u048A', '\u052F'),
+    ('\u0531', '\u0556'),
+    ('\u0559', '\u0559'),
+    ('\u0560', '\u0588'),
+    ('\u05D0', '\u05EA'),
+    ('\u05EF', '\u05F2'),
+    ('\u0620', '\u064A'),
+    ('\u066E', '\u066F'),
+    ('\u0671', '\u06D3'),
+    ('\u06D5', '\u06D5'),
+    ('\u06E5', '\u06E6'),
+    ('\u06EE', '\u06EF'),
+    ('\u06FA', '\u06FC'),
+    ('\u06FF', '\u06FF'),
+    ('\u0710', '\u0710'),
+    ('\u0712', '\u072F'),
+    ('\u074D', '\u07A5'),
+    ('\u07B1', '\u07B1'),
+    ('\u07CA', '\u07EA'),
+    ('\u07F4', '\u07F5'),
+    ('\u07FA', '\u07FA'),
+    ('\u0800', '\u0815'),
+    ('\u081A', '\u081A'),
+    ('\u0824', '\u0824'),
+    ('\u0828', '\u0828'),
+    ('\u0840', '\u0858'),
+    ('\u0860', '\u086A'),
+    ('\u0870', '\u0887'),
+    ('\u0889', '\u088E'),
+    ('\u08A0', '\u08C9'),
+    ('\u0904', '\u0939'),
+    ('\u093D', '\u093D'),
+    ('\u0950', '\u0950'),
+    ('\u0958', '\u0961'),
+    ('\u0971', '\u0980'),
+    ('\u0985', '\u098C'),
+    ('\u098F', '\u0990'),
+    ('\u0993', '\u09A8'),
+    ('\u09AA', '\u09B0'),
+    ('\u09B2', '\u09B2'),
+    ('\u09B6', '\u09B9'),
+    ('\u09BD', '\u09BD'),
+    ('\u09CE', '\u09CE'),
+    ('\u09DC', '\u09DD'),
+    ('\u09DF', '\u09E1'),
+    ('\u09F0', '\u09F1'),
+    ('\u09FC', '\u09FC'),
+    ('\u0A05', '\u0A0A'),
+    ('\u0A0F', '\u0A10'),
+    ('\u0A13', '\u0A28'),
+    ('\u0A2A', '\u0A30'),
+    ('\u0A32', '\u0A33'),
+    ('\u0A35', '\u0A36'),
+    ('\u0A38', '\u0A39'),
+    ('\u0A59', '\u0A5C'),
+    ('\u0A5E', '\u0A5E'),
+    ('\u0A72', '\u0A74'),
+    ('\u0A85', '\u0A8D'),
+    ('\u0A8F', '\u0A91'),
+    ('\u0A93', '\u0AA8'),
+    ('\u0AAA', '\u0AB0'),
+    ('\u0AB2', '\u0AB3'),
+    ('\u0AB5', '\u0AB9'),
+    ('\u0ABD', '\u0ABD'),
+    ('\u0AD0', '\u0AD0'),
+    ('\u0AE0', '\u0AE1'),
+    ('\u0AF9', '\u0AF9'),
+    ('\u0B05', '\u0B0C'),
+    ('\u0B0F', '\u0B10'),
+    ('\u0B13', '\u0B28'),
+    ('\u0B2A', '\u0B30'),
+    ('\u0B32', '\u0B33'),
+    ('\u0B35', '\u0B39'),
+    ('\u0B3D', '\u0B3D'),
+    ('\u0B5C', '\u0B5D'),
+    ('\u0B5F', '\u0B61'),
+    ('\u0B71', '\u0B71'),
+    ('\u0B83', '\u0B83'),
+    ('\u0B85', '\u0B8A'),
+    ('\u0B8E', '\u0B90'),
+    ('\u0B92', '\u0B95'),
+    ('\u0B99', '\u0B9A'),
+    ('\u0B9C', '\u0B9C'),
+    ('\u0B9E', '\u0B9F'),
+    ('\u0BA3', '\u0BA4'),
+    ('\u0BA8', '\u0BAA'),
+    ('\u0BAE', '\u0BB9'),
+    ('\u0BD0', '\u0BD0'),
+    ('\u0C05', '\u0C0C'),
+    ('\u0C0E', '\u0C10'),
+    ('\u0C12', '\u0C28'),
+    ('\u0C2A', '\u0C39'),
+    ('\u0C3D', '\u0C3D'),
+    ('\u0C58', '\u0C5A'),
+    ('\u0C5D', '\u0C5D'),
+    ('\u0C60', '\u0C61'),
+    ('\u0C80', '\u0C80'),
+    ('\u0C85', '\u0C8C'),
+    ('\u0C8E', '\u0C90'),
+    ('\u0C92', '\u0CA8'),
+    ('\u0CAA', '\u0CB3'),
+    ('\u0CB5', '\u0CB9'),
+    ('\u0CBD', '\u0CBD'),
+    ('\u0CDD', '\u0CDE'),
+    ('\u0CE0', '\u0CE1'),
+    ('\u0CF1', '\u0CF2'),
+    ('\u0D04', '\u0D0C'),
+    ('\u0D0E', '\u0D10'),
+    ('\u0D12', '\u0D3A'),
+    ('\u0D3D', '\u0D3D'),
+    ('\u0D4E', '\u0D4E'),
+    ('\u0D54', '\u0D56'),
+    ('\u0D5F', '\u0D61'),
+    ('\u0D7A', '\u0D7F'),
+    ('\u0D85', '\u0D96'),
+    ('\u0D9A', '\u0DB1'),
+    ('\u0DB3', '\u0DBB'),
+    ('\u0DBD', '\u0DBD'),
+    ('\u0DC0', '\u0DC6'),
+    ('\u0E01', '\u0E30'),
+    ('\u0E32', '\u0E33'),
+    ('\u0E40', '\u0E46'),
+    ('\u0E81', '\u0E82'),
+    ('\u0E84', '\u0E84'),
+    ('\u0E86', '\u0E8A'),
+    ('\u0E8C', '\u0EA3'),
+    ('\u0EA5', '\u0EA5'),
+    ('\u0EA7', '\u0EB0'),
+    ('\u0EB2', '\u0EB3'),
+    ('\u0EBD', '\u0EBD'),
+    ('\u0EC0', '\u0EC4'),
+    ('\u0EC6', '\u0EC6'),
+    ('\u0EDC', '\u0EDF'),
+    ('\u0F00', '\u0F00'),
+    ('\u0F40', '\u0F47'),
+    ('\u0F49', '\u0F6C'),
+    ('\u0F88', '\u0F8C'),
+    ('\u1000', '\u102A'),
+    ('\u103F', '\u103F'),
+    ('\u1050', '\u1055'),
+    ('\u105A', '\u105D'),
+    ('\u1061', '\u1061'),
+    ('\u1065', '\u1066'),
+    ('\u106E', '\u1070'),
+    ('\u1075', '\u1081'),
+    ('\u108E', '\u108E'),
+    ('\u10A0', '\u10C5'),
+    ('\u10C7', '\u10C7'),
+    ('\u10CD', '\u10CD'),
+    ('\u10D0', '\u10FA'),
+    ('\u10FC', '\u1248'),
+    ('\u124A', '\u124D'),
+    ('\u1250', '\u1256'),
+    ('\u1258', '\u1258'),
+    ('\u125A', '\u125D'),
+    ('\u1260', '\u1288'),
+    ('\u128A', '\u128D'),
+    ('\u1290', '\u12B0'),
+    ('\u12B2', '\u12B5'),
+    ('\u12B8', '\u12BE'),
+    ('\u12C0', '\u12C0'),
+    ('\u12C2', '\u12C5'),
+    ('\u12C8', '\u12D6'),
+    ('\u12D8', '\u1310'),
+    ('\u1312', '\u1315'),
+    ('\u1318', '\u135A'),
+    ('\u1380', '\u138F'),
+    ('\u13A0', '\u13F5'),
+    ('\u13F8', '\u13FD'),
+    ('\u1401', '\u166C'),
+    ('\u166F', '\u167F'),
+    ('\u1681', '\u169A'),
+    ('\u16A0', '\u16EA'),
+    ('\u16F1', '\u16F8'),
+    ('\u1700', '\u1711'),
+    ('\u171F', '\u1731'),
+    ('\u1740', '\u1751'),
+    ('\u1760', '\u176C'),
+    ('\u176E', '\u1770'),
+    ('\u1780', '\u17B3'),
+    ('\u17D7', '\u17D7'),
+    ('\u17DC', '\u17DC'),
+    ('\u1820', '\u1878'),
+    ('\u1880', '\u1884'),
+    ('\u1887', '\u18A8'),
+    ('\u18AA', '\u18AA'),
+    ('\u18B0', '\u18F5'),
+    ('\u1900', '\u191E'),
+    ('\u1950', '\u196D'),
+    ('\u1970', '\u1974'),
+    ('\u1980', '\u19AB'),
+    ('\u19B0', '\u19C9'),
+    ('\u1A00', '\u1A16'),
+    ('\u1A20', '\u1A54'),
+    ('\u1AA7', '\u1AA7'),
+    ('\u1B05', '\u1B33'),
+    ('\u1B45', '\u1B4C'),
+    ('\u1B83', '\u1BA0'),
+    ('\u1BAE', '\u1BAF'),
+    ('\u1BBA', '\u1BE5'),
+    ('\u1C00', '\u1C23'),
+    ('\u1C4D', '\u1C4F'),
+    ('\u1C5A', '\u1C7D'),
+    ('\u1C80', '\u1C88'),
+    ('\u1C90', '\u1CBA'),
+    ('\u1CBD', '\u1CBF'),
+    ('\u1CE9', '\u1CEC'),
+    ('\u1CEE', '\u1CF3'),
+    ('\u1CF5', '\u1CF6'),
+    ('\u1CFA', '\u1CFA'),
+    ('\u1D00', '\u1DBF'),
+    ('\u1E00', '\u1F15'),
+    ('\u1F18', '\u1F1D'),
+    ('\u1F20', '\u1F45'),
+    ('\u1F48', '\u1F4D'),
+    ('\u1F50', '\u1F57'),
+    ('\u1F59', '\u1F59'),
+    ('\u1F5B', '\u1F5B'),
+    ('\u1F5D', '\u1F5D'),
+    ('\u1F5F', '\u1F7D'),
+    ('\u1F80', '\u1FB4'),
+    ('\u1FB6', '\u1FBC'),
+    ('\u1FBE', '\u1FBE'),
+    ('\u1FC2', '\u1FC4'),
+    ('\u1FC6', '\u1FCC'),
+    ('\u1FD0', '\u1FD3'),
+    ('\u1FD6', '\u1FDB'),
+    ('\u1FE0', '\u1FEC'),
+    ('\u1FF2', '\u1FF4'),
+    ('\u1FF6', '\u1FFC'),
+    ('\u2071', '\u2071'),
+    ('\u207F', '\u207F'),
+    ('\u2090', '\u209C'),
+    ('\u2102', '\u2102'),
+    ('\u2107', '\u2107'),
+    ('\u210A', '\u2113'),
+    ('\u2115', '\u2115'),
+    ('\u2119', '\u211D'),
+    ('\u2124', '\u2124'),
+    ('\u2126', '\u2126'),
+    ('\u2128', '\u2128'),
+    ('\u212A', '\u212D'),
+    ('\u212F', '\u2139'),
+    ('\u213C', '\u213F'),
+    ('\u2145', '\u2149'),
+    ('\u214E', '\u214E'),
+    ('\u2183', '\u2184'),
+    ('\u2C00', '\u2CE4'),
+    ('\u2CEB', '\u2CEE'),
+    ('\u2CF2', '\u2CF3'),
+    ('\u2D00', '\u2D25'),
+    ('\u2D27', '\u2D27'),
+    ('\u2D2D', '\u2D2D'),
+    ('\u2D30', '\u2D67'),
+    ('\u2D6F', '\u2D6F'),
+    ('\u2D80', '\u2D96'),
+    ('\u2DA0', '\u2DA6'),
+    ('\u2DA8', '\u2DAE'),
+    ('\u2DB0', '\u2DB6'),
+    ('\u2DB8', '\u2DBE'),
+    ('\u2DC0', '\u2DC6'),
+    ('\u2DC8', '\u2DCE'),
+    ('\u2DD0', '\u2DD6'),
+    ('\u2DD8', '\u2DDE'),
+    ('\u2E2F', '\u2E2F'),
+    ('\u3005', '\u3006'),
+    ('\u3031', '\u3035'),
+    ('\u303B', '\u303C'),
+    ('\u3041', '\u3096'),
+    ('\u309D', '\u309F'),
+    ('\u30A1', '\u30FA'),
+    ('\u30FC', '\u30FF'),
+    ('\u3105', '\u312F'),
+    ('\u3131', '\u318E'),
+    ('\u31A0', '\u31BF'),
+    ('\u31F0', '\u31FF'),
+    ('\u3400', '\u4DBF'),
+    ('\u4E00', '\uA48C'),
+    ('\uA4D0', '\uA4FD'),
+    ('\uA500', '\uA60C'),
+    ('\uA610', '\uA61F'),
+    ('\uA62A', '\uA62B'),
+    ('\uA640', '\uA66E'),
+    ('\uA67F', '\uA69D'),
+    ('\uA6A0', '\uA6E5'),
+    ('\uA717', '\uA71F'),
+    ('\uA722', '\uA788'),
+    ('\uA78B', '\uA7CA'),
+    ('\uA7D0', '\uA7D1'),
+    ('\uA7D3', '\uA7D3'),
+    ('\uA7D5', '\uA7D9'),
+    ('\uA7F2', '\uA801'),
+    ('\uA803', '\uA805'),
+    ('\uA807', '\uA80A'),
+    ('\uA80C', '\uA822'),
+    ('\uA840', '\uA873'),
+    ('\uA882', '\uA8B3'),
+    ('\uA8F2', '\uA8F7'),
+    ('\uA8FB', '\uA8FB'),
+    ('\uA8FD', '\uA8FE'),
+    ('\uA90A', '\uA925'),
+    ('\uA930', '\uA946'),
+    ('\uA960', '\uA97C'),
+    ('\uA984', '\uA9B2'),
+    ('\uA9CF', '\uA9CF'),
+    ('\uA9E0', '\uA9E4'),
+    ('\uA9E6', '\uA9EF'),
+    ('\uA9FA', '\uA9FE'),
+    ('\uAA00', '\uAA28'),
+    ('\uAA40', '\uAA42'),
+    ('\uAA44', '\uAA4B'),
+    ('\uAA60', '\uAA76'),
+    ('\uAA7A', '\uAA7A'),
+    ('\uAA7E', '\uAAAF'),
+    ('\uAAB1', '\uAAB1'),
+    ('\uAAB5', '\uAAB6'),
+    ('\uAAB9', '\uAABD'),
+    ('\uAAC0', '\uAAC0'),
+    ('\uAAC2', '\uAAC2'),
+    ('\uAADB', '\uAADD'),
+    ('\uAAE0', '\uAAEA'),
+    ('\uAAF2', '\uAAF4'),
+    ('\uAB01', '\uAB06'),
+    ('\uAB09', '\uAB0E'),
+    ('\uAB11', '\uAB16'),
+    ('\uAB20', '\uAB26'),
+    ('\uAB28', '\uAB2E'),
+    ('\uAB30', '\uAB5A'),
+    ('\uAB5C', '\uAB69'),
+    ('\uAB70', '\uABE2'),
+    ('\uAC00', '\uD7A3'),
+    ('\uD7B0', '\uD7C6'),
+    ('\uD7CB', '\uD7FB'),
+    ('\uF900', '\uFA6D'),
+    ('\uFA70', '\uFAD9'),
+    ('\uFB00', '\uFB06'),
+    ('\uFB13', '\uFB17'),
+    ('\uFB1D', '\uFB1D'),
+    ('\uFB1F', '\uFB28'),
+    ('\uFB2A', '\uFB36'),
+    ('\uFB38', '\uFB3C'),
+    ('\uFB3E', '\uFB3E'),
+    ('\uFB40', '\uFB41'),
+    ('\uFB43', '\uFB44'),
+    ('\uFB46', '\uFBB1'),
+    ('\uFBD3', '\uFD3D'),
+    ('\uFD50', '\uFD8F'),
+    ('\uFD92', '\uFDC7'),
+    ('\uFDF0', '\uFDFB'),
+    ('\uFE70', '\uFE74'),
+    ('\uFE76', '\uFEFC'),
+    ('\uFF21', '\uFF3A'),
+    ('\uFF41', '\uFF5A'),
+    ('\uFF66', '\uFFBE'),
+    ('\uFFC2', '\uFFC7'),
+    ('\uFFCA', '\uFFCF'),
+    ('\uFFD2', '\uFFD7'),
+    ('\uFFDA', '\uFFDC'),
+    ('\U00010000', '\U0001000B'),
+    ('\U0001000D', '\U00010026'),
+    ('\U00010028', '\U0001003A'),
+    ('\U0001003C', '\U0001003D'),
+    ('\U0001003F', '\U0001004D'),
+    ('\U00010050', '\U0001005D'),
+    ('\U00010080', '\U000100FA'),
+    ('\U00010280', '\U0001029C'),
+    ('\U000102A0', '\U000102D0'),
+    ('\U00010300', '\U0001031F'),
+    ('\U0001032D', '\U00010340'),
+    ('\U00010342', '\U00010349'),
+    ('\U00010350', '\U00010375'),
+    ('\U00010380', '\U0001039D'),
+    ('\U000103A0', '\U000103C3'),
+    ('\U000103C8', '\U000103CF'),
+    ('\U00010400', '\U0001049D'),
+    ('\U000104B0', '\U000104D3'),
+    ('\U000104D8', '\U000104FB'),
+    ('\U00010500', '\U00010527'),
+    ('\U00010530', '\U00010563'),
+    ('\U00010570', '\U0001057A'),
+    ('\U0001057C', '\U0001058A'),
+    ('\U0001058C', '\U00010592'),
+    ('\U00010594', '\U00010595'),
+    ('\U00010597', '\U000105A1'),
+    ('\U000105A3', '\U000105B1'),
+    ('\U000105B3', '\U000105B9'),
+    ('\U000105BB', '\U000105BC'),
+    ('\U00010600', '\U00010736'),
+    ('\U00010740', '\U00010755'),
+    ('\U00010760', '\U00010767'),
+    ('\U00010780', '\U00010785'),
+    ('\U00010787', '\U000107B0'),
+    ('\U000107B2', '\U000107BA'),
+    ('\U00010800', '\U00010805'),
+    ('\U00010808', '\U00010808'),
+    ('\U0001080A', '\U00010835'),
+    ('\U00010837', '\U00010838'),
+    ('\U0001083C', '\U0001083C'),
+    ('\U0001083F', '\U00010855'),
+    ('\U00010860', '\U00010876'),
+    ('\U00010880', '\U0001089E'),
+    ('\U000108E0', '\U000108F2'),
+    ('\U000108F4', '\U000108F5'),
+    ('\U00010900', '\U00010915'),
+    ('\U00010920', '\U00010939'),
+    ('\U00010980', '\U000109B7'),
+    ('\U000109BE', '\U000109BF'),
+    ('\U00010A00', '\U00010A00'),
+    ('\U00010A10', '\U00010A13'),
+    ('\U00010A15', '\U00010A17'),
+    ('\U00010A19', '\U00010A35'),
+    ('\U00010A60', '\U00010A7C'),
+    ('\U00010A80', '\U00010A9C'),
+    ('\U00010AC0', '\U00010AC7'),
+    ('\U00010AC9', '\U00010AE4'),
+    ('\U00010B00', '\U00010B35'),
+    ('\U00010B40', '\U00010B55'),
+    ('\U00010B60', '\U00010B72'),
+    ('\U00010B80', '\U00010B91'),
+    ('\U00010C00', '\U00010C48'),
+    ('\U00010C80', '\U00010CB2'),
+    ('\U00010CC0', '\U00010CF2'),
+    ('\U00010D00', '\U00010D23'),
+    ('\U00010E80', '\U00010EA9'),
+    ('\U00010EB0', '\U00010EB1'),
+    ('\U00010F00', '\U00010F1C'),
+    ('\U00010F27', '\U00010F27'),
+    ('\U00010F30', '\U00010F45'),
+    ('\U00010F70', '\U00010F81'),
+    ('\U00010FB0', '\U00010FC4'),
+    ('\U00010FE0', '\U00010FF6'),
+    ('\U00011003', '\U00011037'),
+    ('\U00011071', '\U00011072'),
+    ('\U00011075', '\U00011075'),
+    ('\U00011083', '\U000110AF'),
+    ('\U000110D0', '\U000110E8'),
+    ('\U00011103', '\U00011126'),
+    ('\U00011144', '\U00011144'),
+    ('\U00011147', '\U00011147'),
+    ('\U00011150', '\U00011172'),
+    ('\U00011176', '\U00011176'),
+    ('\U00011183', '\U000111B2'),
+    ('\U000111C1', '\U000111C4'),
+    ('\U000111DA', '\U000111DA'),
+    ('\U000111DC', '\U000111DC'),
+    ('\U00011200', '\U00011211'),
+    ('\U00011213', '\U0001122B'),
+    ('\U00011280', '\U00011286'),
+    ('\U00011288', '\U00011288'),
+    ('\U0001128A', '\U0001128D'),
+    ('\U0001128F', '\U0001129D'),
+    ('\U0001129F', '\U000112A8'),
+    ('\U000112B0', '\U000112DE'),
+    ('\U00011305', '\U0001130C'),
+    ('\U0001130F', '\U00011310'),
+    ('\U00011313', '\U00011328'),
+    ('\U0001132A', '\U00011330'),
+    ('\U00011332', '\U00011333'),
+    ('\U00011335', '\U00011339'),
+    ('\U0001133D', '\U0001133D'),
+    ('\U00011350', '\U00011350'),
+    ('\U0001135D', '\U00011361'),
+    ('\U00011400', '\U00011434'),
+    ('\U00011447', '\U0001144A'),
+    ('\U0001145F', '\U00011461'),
+    ('\U00011480', '\U000114AF'),
+    ('\U000114C4', '\U000114C5'),
+    ('\U000114C7', '\U000114C7'),
+    ('\U00011580', '\U000115AE'),
+    ('\U000115D8', '\U000115DB'),
+    ('\U00011600', '\U0001162F'),
+    ('\U00011644', '\U00011644'),
+    ('\U00011680', '\U000116AA'),
+    ('\U000116B8', '\U000116B8'),
+    ('\U00011700', '\U0001171A'),
+    ('\U00011740', '\U00011746'),
+    ('\U00011800', '\U0001182B'),
+    ('\U000118A0', '\U000118DF'),
+    ('\U000118FF', '\U00011906'),
+    ('\U00011909', '\U00011909'),
+    ('\U0001190C', '\U00011913'),
+    ('\U00011915', '\U00011916'),
+    ('\U00011918', '\U0001192F'),
+    ('\U0001193F', '\U0001193F'),
+    ('\U00011941', '\U00011941'),
+    ('\U000119A0', '\U000119A7'),
+    ('\U000119AA', '\U000119D0'),
+    ('\U000119E1', '\U000119E1'),
+    ('\U000119E3', '\U000119E3'),
+    ('\U00011A00', '\U00011A00'),
+    ('\U00011A0B', '\U00011A32'),
+    ('\U00011A3A', '\U00011A3A'),
+    ('\U00011A50', '\U00011A50'),
+    ('\U00011A5C', '\U00011A89'),
+    ('\U00011A9D', '\U00011A9D'),
+    ('\U00011AB0', '\U00011AF8'),
+    ('\U00011C00', '\U00011C08'),
+    ('\U00011C0A', '\U00011C2E'),
+    ('\U00011C40', '\U00011C40'),
+    ('\U00011C72', '\U00011C8F'),
+    ('\U00011D00', '\U00011D06'),
+    ('\U00011D08', '\U00011D09'),
+    ('\U00011D0B', '\U00011D30'),
+    ('\U00011D46', '\U00011D46'),
+    ('\U00011D60', '\U00011D65'),
+    ('\U00011D67', '\U00011D68'),
+    ('\U00011D6A', '\U00011D89'),
+    ('\U00011D98', '\U00011D98'),
+    ('\U00011EE0', '\U00011EF2'),
+    ('\U00011FB0', '\U00011FB0'),
+    ('\U00012000', '\U00012399'),
+    ('\U00012480', '\U00012543'),
+    ('\U00012F90', '\U00012FF0'),
+    ('\U00013000', '\U0001342E'),
+    ('\U00014400', '\U00014646'),
+    ('\U00016800', '\U00016A38'),
+    ('\U00016A40', '\U00016A5E'),
+    ('\U00016A70', '\U00016ABE'),
+    ('\U00016AD0', '\U00016AED'),
+    ('\U00016B00', '\U00016B2F'),
+    ('\U00016B40', '\U00016B43'),
+    ('\U00016B63', '\U00016B77'),
+    ('\U00016B7D', '\U00016B8F'),
+    ('\U00016E40', '\U00016E7F'),
+    ('\U00016F00', '\U00016F4A'),
+    ('\U00016F50', '\U00016F50'),
+    ('\U00016F93', '\U00016F9F'),
+    ('\U00016FE0', '\U00016FE1'),
+    ('\U00016FE3', '\U00016FE3'),
+    ('\U00017000', '\U000187F7'),
+    ('\U00018800', '\U00018CD5'),
+    ('\U00018D00', '\U00018D08'),
+    ('\U0001AFF0', '\U0001AFF3'),
+    ('\U0001AFF5', '\U0001AFFB'),
+    ('\U0001AFFD', '\U0001AFFE'),
+    ('\U0001B000', '\U0001B122'),
+    ('\U0001B150', '\U0001B152'),
+    ('\U0001B164', '\U0001B167'),
+    ('\U0001B170', '\U0001B2FB'),
+    ('\U0001BC00', '\U0001BC6A'),
+    ('\U0001BC70', '\U0001BC7C'),
+    ('\U0001BC80', '\U0001BC88'),
+    ('\U0001BC90', '\U0001BC99'),
+    ('\U0001D400', '\U0001D454'),
+    ('\U0001D456', '\U0001D49C'),
+    ('\U0001D49E', '\U0001D49F'),
+    ('\U0001D4A2', '\U0001D4A2'),
+    ('\U0001D4A5', '\U0001D4A6'),
+    ('\U0001D4A9', '\U0001D4AC'),
+    ('\U0001D4AE', '\U0001D4B9'),
+    ('\U0001D4BB', '\U0001D4BB'),
+    ('\U0001D4BD', '\U0001D4C3'),
+    ('\U0001D4C5', '\U0001D505'),
+    ('\U0001D507', '\U0001D50A'),
+    ('\U0001D50D', '\U0001D514'),
+    ('\U0001D516', '\U0001D51C'),
+    ('\U0001D51E', '\U0001D539'),
+    ('\U0001D53B', '\U0001D53E'),
+    ('\U0001D540', '\U0001D544'),
+    ('\U0001D546', '\U0001D546'),
+    ('\U0001D54A', '\U0001D550'),
+    ('\U0001D552', '\U0001D6A5'),
+    ('\U0001D6A8', '\U0001D6C0'),
+    ('\U0001D6C2', '\U0001D6DA'),
+    ('\U0001D6DC', '\U0001D6FA'),
+    ('\U0001D6FC', '\U0001D714'),
+    ('\U0001D716', '\U0001D734'),
+    ('\U0001D736', '\U0001D74E'),
+    ('\U0001D750', '\U0001D76E'),
+    ('\U0001D770', '\U0001D788'),
+    ('\U0001D78A', '\U0001D7A8'),
+    ('\U0001D7AA', '\U0001D7C2'),
+    ('\U0001D7C4', '\U0001D7CB'),
+    ('\U0001DF00', '\U0001DF1E'),
+    ('\U0001E100', '\U0001E12C'),
+    ('\U0001E137', '\U0001E13D'),
+    ('\U0001E14E', '\U0001E14E'),
+    ('\U0001E290', '\U0001E2AD'),
+    ('\U0001E2C0', '\U0001E2EB'),
+    ('\U0001E7E0', '\U0001E7E6'),
+    ('\U0001E7E8', '\U0001E7EB'),
+    ('\U0001E7ED', '\U0001E7EE'),
+    ('\U0001E7F0', '\U0001E7FE'),
+    ('\U0001E800', '\U0001E8C4'),
+    ('\U0001E900', '\U0001E943'),
+    ('\U0001E94B', '\U0001E94B'),
+    ('\U0001EE00', '\U0001EE03'),
+    ('\U0001EE05', '\U0001EE1F'),
+    ('\U0001EE21', '\U0001EE22'),
+    ('\U0001EE24', '\U0001EE24'),
+    ('\U0001EE27', '\U0001EE27'),
+    ('\U0001EE29', '\U0001EE32'),
+    ('\U0001EE34', '\U0001EE37'),
+    ('\U0001EE39', '\U0001EE39'),
+    ('\U0001EE3B', '\U0001EE3B'),
+    ('\U0001EE42', '\U0001EE42'),
+    ('\U0001EE47', '\U0001EE47'),
+    ('\U0001EE49', '\U0001EE49'),
+    ('\U0001EE4B', '\U0001EE4B'),
+    ('\U0001EE4D', '\U0001EE4F'),
+    ('\U0001EE51', '\U0001EE52'),
+    ('\U0001EE54', '\U0001EE54'),
+    ('\U0001EE57', '\U0001EE57'),
+    ('\U0001EE59', '\U0001EE59'),
+    ('\U0001EE5B', '\U0001EE5B'),
+    ('\U0001EE5D', '\U0001EE5D'),
+    ('\U0001EE5F', '\U0001EE5F'),
+    ('\U0001EE61', '\U0001EE62'),
+    ('\U0001EE64', '\U0001EE64'),
+    ('\U0001EE67', '\U0001EE6A'),
+    ('\U0001EE6C', '\U0001EE72'),
+    ('\U0001EE74', '\U0001EE77'),
+    ('\U0001EE79', '\U0001EE7C'),
+    ('\U0001EE7E', '\U0001EE7E'),
+    ('\U0001EE80', '\U0001EE89'),
+    ('\U0001EE8B', '\U0001EE9B'),
+    ('\U0001EEA1', '\U0001EEA3'),
+    ('\U0001EEA5', '\U0001EEA9'),
+    ('\U0001EEAB', '\U0001EEBB'),
+    ('\U00020000', '\U0002A6DF'),
+    ('\U0002A700', '\U0002B738'),
+    ('\U0002B740', '\U0002B81D'),
+    ('\U0002B820', '\U0002CEA1'),
+    ('\U0002CEB0', '\U0002EBE0'),
+    ('\U0002F800', '\U0002FA1D'),
+    ('\U00030000', '\U0003134A')
+  ]),
+  ("lc", &[
+    ('\u0041', '\u005A'),
+    ('\u0061', '\u007A'),
+    ('\u00B5', '\u00B5'),
+    ('\u00C0', '\u00D6'),
+    ('\u00D8', '\u00F6'),
+    ('\u00F8', '\u01BA'),
+    ('\u01BC', '\u01BF'),
+    ('\u01C4', '\u0293'),
+    ('\u0295', '\u02AF'),
+    ('\u0370', '\u0373'),
+    ('\u0376', '\u0377'),
+    ('\u037B', '\u037D'),
+    ('\u037F', '\u037F'),
+    ('\u0386', '\u0386'),
+    ('\u0388', '\u038A'),
+    ('\u038C', '\u038C'),
+    ('\u038E', '\u03A1'),
+    ('\u03A3', '\u03F5'),
+    ('\u03F7', '\u0481'),
+    ('\u048A', '\u052F'),
+    ('\u0531', '\u0556'),
+    ('\u0560', '\u0588'),
+    ('\u10A0', '\u10C5'),
+    ('\u10C7', '\u10C7'),
+    ('\u10CD', '\u10CD'),
+    ('\u10D0', '\u10FA'),
+    ('\u10FD', '\u10FF'),
+    ('\u13A0', '\u13F5'),
+    ('\u13F8', '\u13FD'),
+    ('\u1C80', '\u1C88'),
+    ('\u1C90', '\u1CBA'),
+    ('\u1CBD', '\u1CBF'),
+    ('\u1D00', '\u1D2B'),
+    ('\u1D6B', '\u1D77'),
+    ('\u1D79', '\u1D9A'),
+    ('\u1E00', '\u1F15'),
+    ('\u1F18', '\u1F1D'),
+    ('\u1F20', '\u1F45'),
+    ('\u1F48', '\u1F4D'),
+    ('\u1F50', '\u1F57'),
+    ('\u1F59', '\u1F59'),
+    ('\u1F5B', '\u1F5B'),
+    ('\u1F5D', '\u1F5D'),
+    ('\u1F5F', '\u1F7D'),
+    ('\u1F80', '\u1FB4'),
+    ('\u1FB6', '\u1FBC'),
+    ('\u1FBE', '\u1FBE'),
+    ('\u1FC2', '\u1FC4'),
+    ('\u1FC6', '\u1FCC'),
+    ('\u1FD0', '\u1FD3'),
+    ('\u1FD6', '\u1FDB'),
+    ('\u1FE0', '\u1FEC'),
+    ('\u1FF2', '\u1FF4'),
+    ('\u1FF6', '\u1FFC'),
+    ('\u2102', '\u2102'),
+    ('\u2107', '\u2107'),
+    ('\u210A', '\u2113'),
+    ('\u2115', '\u2115'),
+    ('\u2119', '\u211D'),
+    ('\u2124', '\u2124'),
+    ('\u2126', '\u2126'),
+    ('\u2128', '\u2128'),
+    ('\u212A', '\u212D'),
+    ('\u212F', '\u2134'),
+    ('\u2139', '\u2139'),
+    ('\u213C', '\u213F'),
+    ('\u2145', '\u2149'),
+    ('\u214E', '\u214E'),
+    ('\u2183', '\u2184'),
+    ('\u2C00', '\u2C7B'),
+    ('\u2C7E', '\u2CE4'),
+    ('\u2CEB', '\u2CEE'),
+    ('\u2CF2', '\u2CF3'),
+    ('\u2D00', '\u2D25'),
+    ('\u2D27', '\u2D27'),
+    ('\u2D2D', '\u2D2D'),
+    ('\uA640', '\uA66D'),
+    ('\uA680', '\uA69B'),
+    ('\uA722', '\uA76F'),
+    ('\uA771', '\uA787'),
+    ('\uA78B', '\uA78E'),
+    ('\uA790', '\uA7CA'),
+    ('\uA7D0', '\uA7D1'),
+    ('\uA7D3', '\uA7D3'),
+    ('\uA7D5', '\uA7D9'),
+    ('\uA7F5', '\uA7F6'),
+    ('\uA7FA', '\uA7FA'),
+    ('\uAB30', '\uAB5A'),
+    ('\uAB60', '\uAB68'),
+    ('\uAB70', '\uABBF'),
+    ('\uFB00', '\uFB06'),
+    ('\uFB13', '\uFB17'),
+    ('\uFF21', '\uFF3A'),
+    ('\uFF41', '\uFF5A'),
+    ('\U00010400', '\U0001044F'),
+    ('\U000104B0', '\U000104D3'),
+    ('\U000104D8', '\U000104FB'),
+    ('\U00010570', '\U0001057A'),
+    ('\U0001057C', '\U0001058A'),
+    ('\U0001058C', '\U00010592'),
+    ('\U00010594', '\U00010595'),
+    ('\U00010597', '\U000105A1'),
+    ('\U000105A3', '\U000105B1'),
+    ('\U000105B3', '\U000105B9'),
+    ('\U000105BB', '\U000105BC'),
+    ('\U00010C80', '\U00010CB2'),
+    ('\U00010CC0', '\U00010CF2'),
+    ('\U000118A0', '\U000118DF'),
+    ('\U00016E40', '\U00016E7F'),
+    ('\U0001D400', '\U0001D454'),
+    ('\U0001D456', '\U0001D49C'),
+    ('\U0001D49E', '\U0001D49F'),
+    ('\U0001D4A2', '\U0001D4A2'),
+    ('\U0001D4A5', '\U0001D4A6'),
+    ('\U0001D4A9', '\U0001D4AC'),
+    ('\U0001D4AE', '\U0001D4B9'),
+    ('\U0001D4BB', '\U0001D4BB'),
+    ('\U0001D4BD', '\U0001D4C3'),
+    ('\U0001D4C5', '\U0001D505'),
+    ('\U0001D507', '\U0001D50A'),
+    ('\U0001D50D', '\U0001D514'),
+    ('\U0001D516', '\U0001D51C'),
+    ('\U0001D51E', '\U0001D539'),
+    ('\U0001D53B', '\U0001D53E'),
+    ('\U0001D540', '\U0001D544'),
+    ('\U0001D546', '\U0001D546'),
+    ('\U0001D54A', '\U0001D550'),
+    ('\U0001D552', '\U0001D6A5'),
+    ('\U0001D6A8', '\U0001D6C0'),
+    ('\U0001D6C2', '\U0001D6DA'),
+    ('\U0001D6DC', '\U0001D6FA'),
+    ('\U0001D6FC', '\U0001D714'),
+    ('\U0001D716', '\U0001D734'),
+    ('\U0001D736', '\U0001D74E'),
+    ('\U0001D750', '\U0001D76E'),
+    ('\U0001D770', '\U0001D788'),
+    ('\U0001D78A', '\U0001D7A8'),
+    ('\U0001D7AA', '\U0001D7C2'),
+    ('\U0001D7C4', '\U0001D7CB'),
+    ('\U0001DF00', '\U0001DF09'),
+    ('\U0001DF0B', '\U0001DF1E'),
+    ('\U0001E900', '\U0001E943')
+  ]),
+  ("ll", &[
+    ('\u0061', '\u007A'),
+    ('\u00B5', '\u00B5'),
+    ('\u00DF', '\u00F6'),
+    ('\u00F8', '\u00FF'),
+    ('\u0101', '\u0101'),
+    ('\u0103', '\u0103'),
+    ('\u0105', '\u0105'),
+    ('\u0107', '\u0107'),
+    ('\u0109', '\u0109'),
+    ('\u010B', '\u010B'),
+    ('\u010D', '\u010D'),
+    ('\u010F', '\u010F'),
+    ('\u0111', '\u0111'),
+    ('\u0113', '\u0113'),
+    ('\u0115', '\u0115'),
+    ('\u0117', '\u0117'),
+    ('\u0119', '\u0119'),
+    ('\u011B', '\u011B'),
+    ('\u011D', '\u011D'),
+    ('\u011F', '\u011F'),
+    ('\u0121', '\u0121'),
+    ('\u0123', '\u0123'),
+    ('\u0125', '\u0125'),
+    ('\u0127', '\u0127'),
+    ('\u0129', '\u0129'),
+    ('\u012B', '\u012B'),
+    ('\u012D', '\u012D'),
+    ('\u012F', '\u012F'),
+    ('\u0131', '\u0131'),
+    ('\u0133', '\u0133'),
+    ('\u0135', '\u0135'),
+    ('\u0137', '\u0138'),
+    ('\u013A', '\u013A'),
+    ('\u013C', '\u013C'),
+    ('\u013E', '\u013E'),
+    ('\u0140', '\u0140'),
+    ('\u0142', '\u0142'),
+    ('\u0144', '\u0144'),
+    ('\u0146', '\u0146'),
+    ('\u0148', '\u0149'),
+    ('\u014B', '\u014B'),
+    ('\u014D', '\u014D'),
+    ('\u014F', '\u014F'),
+    ('\u0151', '\u0151'),
+    ('\u0153', '\u0153'),
+    ('\u0155', '\u0155'),
+    ('\u0157', '\u0157'),
+    ('\u0159', '\u0159'),
+    ('\u015B', '\u015B'),
+    ('\u015D', '\u015D'),
+    ('\u015F', '\u015F'),
+    ('\u0161', '\u0161'),
+    ('\u0163', '\u0163'),
+    ('\u0165', '\u0165'),
+    ('\u0167', '\u0167'),
+    ('\u0169', '\u0169'),
+    ('\u016B', '\u016B'),
+    ('\u016D', '\u016D'),
+    ('\u016F', '\u016F'),
+    ('\u0171', '\u0171'),
+    ('\u0173', '\u0173'),
+    ('\u0175', '\u0175'),
+    ('\u0177', '\u0177'),
+    ('\u017A', '\u017A'),
+    ('\u017C', '\u017C'),
+    ('\u017E', '\u0180'),
+    ('\u0183', '\u0183'),
+    ('\u0185', '\u0185'),
+    ('\u0188', '\u0188'),
+    ('\u018C', '\u018D'),
+    ('\u0192', '\u0192'),
+    ('\u0195', '\u0195'),
+    ('\u0199', '\u019B'),
+    ('\u019E', '\u019E'),
+    ('\u01A1', '\u01A1'),
+    ('\u01A3', '\u01A3'),
+    ('\u01A5', '\u01A5'),
+    ('\u01A8', '\u01A8'),
+    ('\u01AA', '\u01AB'),
+    ('\u01AD', '\u01AD'),
+    ('\u01B0', '\u01B0'),
+    ('\u01B4', '\u01B4'),
+    ('\u01B6', '\u01B6'),
+    ('\u01B9', '\u01BA'),
+    ('\u01BD', '\u01BF'),
+    ('\u01C6', '\u01C6'),
+    ('\u01C9', '\u01C9'),
+    ('\u01CC', '\u01CC'),
+    ('\u01CE', '\u01CE'),
+    ('\u01D0', '\u01D0'),
+    ('\u01D2', '\u01D2'),
+    ('\u01D4', '\u01D4'),
+    ('\u01D6', '\u01D6'),
+    ('\u01D8', '\u01D8'),
+    ('\u01DA', '\u01DA'),
+    ('\u01DC', '\u01DD'),
+    ('\u01DF', '\u01DF'),
+    ('\u01E1', '\u01E1'),
+    ('\u01E3', '\u01E3'),
+    ('\u01E5', '\u01E5'),
+    ('\u01E7', '\u01E7'),
+    ('\u01E9', '\u01E9'),
+    ('\u01EB', '\u01EB'),
+    ('\u01ED', '\u01ED'),
+    ('\u01EF', '\u01F0'),
+    ('\u01F3', '\u01F3'),
+    ('\u01F5', '\u01F5'),
+    ('\u01F9', '\u01F9'),
+    ('\u01FB', '\u01FB'),
+    ('\u01FD', '\u01FD'),
+    ('\u01FF', '\u01FF'),
+    ('\u0201', '\u0201'),
+    ('\u0203', '\u0203'),
+    ('\u0205', '\u0205'),
+    ('\u0207', '\u0207'),
+    ('\u0209', '\u0209'),
+    ('\u020B', '\u020B'),
+    ('\u020D', '\u020D'),
+    ('\u020F', '\u020F'),
+    ('\u0211', '\u0211'),
+    ('\u0213', '\u0213'),
+    ('\u0215', '\u0215'),
+    ('\u0217', '\u0217'),
+    ('\u0219', '\u0219'),
+    ('\u021B', '\u021B'),
+    ('\u021D', '\u021D'),
+    ('\u021F', '\u021F'),
+    ('\u0221', '\u0221'),
+    ('\u0223', '\u0223'),
+    ('\u0225', '\u0225'),
+    ('\u0227', '\u0227'),
+    ('\u0229', '\u0229'),
+    ('\u022B', '\u022B'),
+    ('\u022D', '\u022D'),
+    ('\u022F', '\u022F'),
+    ('\u0231', '\u0231'),
+    ('\u0233', '\u0239'),
+    ('\u023C', '\u023C'),
+    ('\u023F', '\u0240'),
+    ('\u0242', '\u0242'),
+    ('\u0247', '\u0247'),
+    ('\u0249', '\u0249'),
+    ('\u024B', '\u024B'),
+    ('\u024D', '\u024D'),
+    ('\u024F', '\u0293'),
+    ('\u0295', '\u02AF'),
+    ('\u0371', '\u0371'),
+    ('\u0373', '\u0373'),
+    ('\u0377', '\u0377'),
+    ('\u037B', '\u037D'),
+    ('\u0390', '\u0390'),
+    ('\u03AC', '\u03CE'),
+    ('\u03D0', '\u03D1'),
+    ('\u03D5', '\u03D7'),
+    ('\u03D9', '\u03D9'),
+    ('\u03DB', '\u03DB'),
+    ('\u03DD', '\u03DD'),
+    ('\u03DF', '\u03DF'),
+    ('\u03E1', '\u03E1'),
+    ('\u03E3', '\u03E3'),
+    ('\u03E5', '\u03E5'),
+    ('\u03E7', '\u03E7'),
+    ('\u03E9', '\u03E9'),
+    ('\u03EB', '\u03EB'),
+    ('\u03ED', '\u03ED'),
+    ('\u03EF', '\u03F3'),
+    ('\u03F5', '\u03F5'),
+    ('\u03F8', '\u03F8'),
+    ('\u03FB', '\u03FC'),
+    ('\u0430', '\u045F'),
+    ('\u0461', '\u0461'),
+    ('\u0463', '\u0463'),
+    ('\u0465', '\u0465'),
+    ('\u0467', '\u0467'),
+    ('\u0469', '\u0469'),
+    ('\u046B', '\u046B'),
+    ('\u046D', '\u046D'),
+    ('\u046F', '\u046F'),
+    ('\u0471', '\u0471'),
+    ('\u0473', '\u0473'),
+    ('\u0475', '\u0475'),
+    ('\u0477', '\u0477'),
+    ('\u0479', '\u0479'),
+    ('\u047B', '\u047B'),
+    ('\u047D', '\u047D'),
+    ('\u047F', '\u047F'),
+    ('\u0481', '\u0481'),
+    ('\u048B', '\u048B'),
+    ('\u048D', '\u048D'),
+    ('\u048F', '\u048F'),
+    ('\u0491', '\u0491'),
+    ('\u0493', '\u0493'),
+    ('\u0495', '\u0495'),
+    ('\u0497', '\u0497'),
+    ('\u0499', '\u0499'),
+    ('\u049B', '\u049B'),
+    ('\u049D', '\u049D'),
+    ('\u049F', '\u049F'),
+    ('\u04A1', '\u04A1'),
+    ('\u04A3', '\u04A3'),
+    ('\u04A5', '\u04A5'),
+    ('\u04A7', '\u04A7'),
+    ('\u04A9', '\u04A9'),
+    ('\u04AB', '\u04AB'),
+    ('\u04AD', '\u04AD'),
+    ('\u04AF', '\u04AF'),
+    ('\u04B1', '\u04B1'),
+    ('\u04B3', '\u04B3'),
+    ('\u04B5', '\u04B5'),
+    ('\u04B7', '\u04B7'),
+    ('\u04B9', '\u04B9'),
+    ('\u04BB', '\u04BB'),
+    ('\u04BD', '\u04BD'),
+    ('\u04BF', '\u04BF'),
+    ('\u04C2', '\u04C2'),
+    ('\u04C4', '\u04C4'),
+    ('\u04C6', '\u04C6'),
+    ('\u04C8', '\u04C8'),
+    ('\u04CA', '\u04CA'),
+    ('\u04CC', '\u04CC'),
+    ('\u04CE', '\u04CF'),
+    ('\u04D1', '\u04D1'),
+    ('\u04D3', '\u04D3'),
+    ('\u04D5', '\u04D5'),
+    ('\u04D7', '\u04D7'),
+    ('\u04D9', '\u04D9'),
+    ('\u04DB', '\u04DB'),
+    ('\u04DD', '\u04DD'),
+    ('\u04DF', '\u04DF'),
+    ('\u04E1', '\u04E1'),
+    ('\u04E3', '\u04E3'),
+    ('\u04E5', '\u04E5'),
+    ('\u04E7', '\u04E7'),
+    ('\u04E9', '\u04E9'),
+    ('\u04EB', '\u04EB'),
+    ('\u04ED', '\u04ED'),
+    ('\u04EF', '\u04EF'),
+    ('\u04F1', '\u04F1'),
+    ('\u04F3', '\u04F3'),
+    ('\u04F5', '\u04F5'),
+    ('\u04F7', '\u04F7'),
+    ('\u04F9', '\u04F9'),
+    ('\u04FB', '\u04FB'),
+    ('\u04FD', '\u04FD'),
+    ('\u04FF', '\u04FF'),
+    ('\u0501', '\u0501'),
+    ('\u0503', '\u0503'),
+    ('\u0505', '\u0505'),
+    ('\u0507', '\u0507'),
+    ('\u0509', '\u0509'),
+    ('\u050B', '\u050B'),
+    ('\u050D', '\u050D'),
+    ('\u050F', '\u050F'),
+    ('\u0511', '\u0511'),
+    ('\u0513', '\u0513'),
+    ('\u0515', '\u0515'),
+    ('\u0517', '\u0517'),
+    ('\u0519', '\u0519'),
+    ('\u051B', '\u051B'),
+    ('\u051D', '\u051D'),
+    ('\u051F', '\u051F'),
+    ('\u0521', '\u0521'),
+    ('\u0523', '\u0523'),
+    ('\u0525', '\u0525'),
+    ('\u0527', '\u0527'),
+    ('\u0529', '\u0529'),
+    ('\u052B', '\u052B'),
+    ('\u052D', '\u052D'),
+    ('\u052F', '\u052F'),
+    ('\u0560', '\u0588'),
+    ('\u10D0', '\u10FA'),
+    ('\u10FD', '\u10FF'),
+    ('\u13F8', '\u13FD'),
+    ('\u1C80', '\u1C88'),
+    ('\u1D00', '\u1D2B'),
+    ('\u1D6B', '\u1D77'),
+    ('\u1D79', '\u1D9A'),
+    ('\u1E01', '\u1E01'),
+    ('\u1E03', '\u1E03'),
+    ('\u1E05', '\u1E05'),
+    ('\u1E07', '\u1E07'),
+    ('\u1E09', '\u1E09'),
+    ('\u1E0B', '\u1E0B'),
+    ('\u1E0D', '\u1E0D'),
+    ('\u1E0F', '\u1E0F'),
+    ('\u1E11', '\u1E11'),
+    ('\u1E13', '\u1E13'),
+    ('\u1E15', '\u1E15'),
+    ('\u1E17', '\u1E17'),
+    ('\u1E19', '\u1E19'),
+    ('\u1E1B', '\u1E1B'),
+    ('\u1E1D', '\u1E1D'),
+    ('\u1E1F', '\u1E1F'),
+    ('\u1E21', '\u1E21'),
+    ('\u1E23', '\u1E23'),
+    ('\u1E25', '\u1E25'),
+    ('\u1E27', '\u1E27'),
+    ('\u1E29', '\u1E29'),
+    ('\u1E2B', '\u1E2B'),
+    ('\u1E2D', '\u1E2D'),
+    ('\u1E2F', '\u1E2F'),
+    ('\u1E31', '\u1E31'),
+    ('\u1E33', '\u1E33'),
+    ('\u1E35', '\u1E35'),
+    ('\u1E37', '\u1E37'),
+    ('\u1E39', '\u1E39'),
+    ('\u1E3B', '\u1E3B'),
+    ('\u1E3D', '\u1E3D'),
+    ('\u1E3F', '\u1E3F'),
+    ('\u1E41', '\u1E41'),
+    ('\u1E43', '\u1E43'),
+    ('\u1E45', '\u1E45'),
+    ('\u1E47', '\u1E47'),
+    ('\u1E49', '\u1E49'),
+    ('\u1E4B', '\u1E4B'),
+    ('\u1E4D', '\u1E4D'),
+    ('\u1E4F', '\u1E4F'),
+    ('\u1E51', '\u1E51'),
+    ('\u1E53', '\u1E53'),
+    ('\u1E55', '\u1E55'),
+    ('\u1E57', '\u1E57'),
+    ('\u1E59', '\u1E59'),
+    ('\u1E5B', '\u1E5B'),
+    ('\u1E5D', '\u1E5D'),
+    ('\u1E5F', '\u1E5F'),
+    ('\u1E61', '\u1E61'),
+    ('\u1E63', '\u1E63'),
+    ('\u1E65', '\u1E65'),
+    ('\u1E67', '\u1E67'),
+    ('\u1E69', '\u1E69'),
+    ('\u1E6B', '\u1E6B'),
+    ('\u1E6D', '\u1E6D'),
+    ('\u1E6F', '\u1E6F'),
+    ('\u1E71', '\u1E71'),
+    ('\u1E73', '\u1E73'),
+    ('\u1E75', '\u1E75'),
+    ('\u1E77', '\u1E77'),
+    ('\u1E79', '\u1E79'),
+    ('\u1E7B', '\u1E7B'),
+    ('\u1E7D', '\u1E7D'),
+    ('\u1E7F', '\u1E7F'),
+    ('\u1E81', '\u1E81'),
+    ('\u1E83', '\u1E83'),
+    ('\u1E85', '\u1E85'),
+    ('\u1E87', '\u1E87'),
+    ('\u1E89', '\u1E89'),
+    ('\u1E8B', '\u1E8B'),
+    ('\u1E8D', '\u1E8D'),
+    ('\u1E8F', '\u1E8F'),
+    ('\u1E91', '\u1E91'),
+    ('\u1E93', '\u1E93'),
+    ('\u1E95', '\u1E9D'),
+    ('\u1E9F', '\u1E9F'),
+    ('\u1EA1', '\u1EA1'),
+    ('\u1EA3', '\u1EA3'),
+    ('\u1EA5', '\u1EA5'),
+    ('\u1EA7', '\u1EA7'),
+    ('\u1EA9', '\u1EA9'),
+    ('\u1EAB', '\u1EAB'),
+    ('\u1EAD', '\u1EAD'),
+    ('\u1EAF', '\u1EAF'),
+    ('\u1EB1', '\u1EB1'),
+    ('\u1EB3', '\u1EB3'),
+    ('\u1EB5', '\u1EB5'),
+    ('\u1EB7', '\u1EB7'),
+    ('\u1EB9', '\u1EB9'),
+    ('\u1EBB', '\u1EBB'),
+    ('\u1EBD', '\u1EBD'),
+    ('\u1EBF', '\u1EBF'),
+    ('\u1EC1', '\u1EC1'),
+    ('\u1EC3', '\u1EC3'),
+    ('\u1EC5', '\u1EC5'),
+    ('\u1EC7', '\u1EC7'),
+    ('\u1EC9', '\u1EC9'),
+    ('\u1ECB', '\u1ECB'),
+    ('\u1ECD', '\u1ECD'),
+    ('\u1ECF', '\u1ECF'),
+    ('\u1ED1', '\u1ED1'),
+    ('\u1ED3', '\u1ED3'),
+    ('\u1ED5', '\u1ED5'),
+    ('\u1ED7', '\u1ED7'),
+    ('\u1ED9', '\u1ED9'),
+    ('\u1EDB', '\u1EDB'),
+    ('\u1EDD', '\u1EDD'),
+    ('\u1EDF', '\u1EDF'),
+    ('\u1EE1', '\u1EE1'),
+    ('\u1EE3', '\u1EE3'),
+    ('\u1EE5', '\u1EE5'),
+    ('\u1EE7', '\u1EE7'),
+    ('\u1EE9', '\u1EE9'),
+    ('\u1EEB', '\u1EEB'),
+    ('\u1EED', '\u1EED'),
+    ('\u1EEF', '\u1EEF'),
+    ('\u1EF1', '\u1EF1'),
+    ('\u1EF3', '\u1EF3'),
+    ('\u1EF5', '\u1EF5'),
+    ('\u1EF7', '\u1EF7'),
+    ('\u1EF9', '\u1EF9'),
+    ('\u1EFB', '\u1EFB'),
+    ('\u1EFD', '\u1EFD'),
+    ('\u1EFF', '\u1F07'),
+    ('\u1F10', '\u1F15'),
+    ('\u1F20', '\u1F27'),
+    ('\u1F30', '\u1F37'),
+    ('\u1F40', '\u1F45'),
+    ('\u1F50', '\u1F57'),
+    ('\u1F60', '\u1F67'),
+    ('\u1F70', '\u1F7D'),
+    ('\u1F80', '\u1F87'),
+    ('\u1F90', '\u1F97'),
+    ('\u1FA0', '\u1FA7'),
+    ('\u1FB0', '\u1FB4'),
+    ('\u1FB6', '\u1FB7'),
+    ('\u1FBE', '\u1FBE'),
+    ('\u1FC2', '\u1FC4'),
+    ('\u1FC6', '\u1FC7'),
+    ('\u1FD0', '\u1FD3'),
+    ('\u1FD6', '\u1FD7'),
+    ('\u1FE0', '\u1FE7'),
+    ('\u1FF2', '\u1FF4'),
+    ('\u1FF6', '\u1FF7'),
+    ('\u210A', '\u210A'),
+    ('\u210E', '\u210F'),
+    ('\u2113', '\u2113'),
+    ('\u212F', '\u212F'),
+    ('\u2134', '\u2134'),
+    ('\u2139', '\u2139'),
+    ('\u213C', '\u213D'),
+    ('\u2146', '\u2149'),
+    ('\u214E', '\u214E'),
+    ('\u2184', '\u2184'),
+    ('\u2C30', '\u2C5F'),
+    ('\u2C61', '\u2C61'),
+    ('\u2C65', '\u2C66'),
+    ('\u2C68', '\u2C68'),
+    ('\u2C6A', '\u2C6A'),
+    ('\u2C6C', '\u2C6C'),
+    ('\u2C71', '\u2C71'),
+    ('\u2C73', '\u2C74'),
+    ('\u2C76', '\u2C7B'),
+    ('\u2C81', '\u2C81'),
+    ('\u2C83', '\u2C83'),
+    ('\u2C85', '\u2C85'),
+    ('\u2C87', '\u2C87'),
+    ('\u2C89', '\u2C89'),
+    ('\u2C8B', '\u2C8B'),
+    ('\u2C8D', '\u2C8D'),
+    ('\u2C8F', '\u2C8F'),
+    ('\u2C91', '\u2C91'),
+    ('\u2C93', '\u2C93'),
+    ('\u2C95', '\u2C95'),
+    ('\u2C97', '\u2C97'),
+    ('\u2C99', '\u2C99'),
+    ('\u2C9B', '\u2C9B'),
+    ('\u2C9D', '\u2C9D'),
+    ('\u2C9F', '\u2C9F'),
+    ('\u2CA1', '\u2CA1'),
+    ('\u2CA3', '\u2CA3'),
+    ('\u2CA5', '\u2CA5'),
+    ('\u2CA7', '\u2CA7'),
+    ('\u2CA9', '\u2CA9'),
+    ('\u2CAB', '\u2CAB'),
+    ('\u2CAD', '\u2CAD'),
+    ('\u2CAF', '\u2CAF'),
+    ('\u2CB1', '\u2CB1'),
+    ('\u2CB3', '\u2CB3'),
+    ('\u2CB5', '\u2CB5'),
+    ('\u2CB7', '\u2CB7'),
+    ('\u2CB9', '\u2CB9'),
+    ('\u2CBB', '\u2CBB'),
+    ('\u2CBD', '\u2CBD'),
+    ('\u2CBF', '\u2CBF'),
+    ('\u2CC1', '\u2CC1'),
+    ('\u2CC3', '\u2CC3'),
+    ('\u2CC5', '\u2CC5'),
+    ('\u2CC7', '\u2CC7'),
+    ('\u2CC9', '\u2CC9'),
+    ('\u2CCB', '\u2CCB'),
+    ('\u2CCD', '\u2CCD'),
+    ('\u2CCF', '\u2CCF'),
+    ('\u2CD1', '\u2CD1'),
+    ('\u2CD3', '\u2CD3'),
+    ('\u2CD5', '\u2CD5'),
+    ('\u2CD7', '\u2CD7'),
+    ('\u2CD9', '\u2CD9'),
+    ('\u2CDB', '\u2CDB'),
+    ('\u2CDD', '\u2CDD'),
+    ('\u2CDF', '\u2CDF'),
+    ('\u2CE1', '\u2CE1'),
+    ('\u2CE3', '\u2CE4'),
+    ('\u2CEC', '\u2CEC'),
+    ('\u2CEE', '\u2CEE'),
+    ('\u2CF3', '\u2CF3'),
+    ('\u2D00', '\u2D25'),
+    ('\u2D27', '\u2D27'),
+    ('\u2D2D', '\u2D2D'),
+    ('\uA641', '\uA641'),
+    ('\uA643', '\uA643'),
+    ('\uA645', '\uA645'),
+    ('\uA647', '\uA647'),
+    ('\uA649', '\uA649'),
+    ('\uA64B', '\uA64B'),
+    ('\uA64D', '\uA64D'),
+    ('\uA64F', '\uA64F'),
+    ('\uA651', '\uA651'),
+    ('\uA653', '\uA653'),
+    ('\uA655', '\uA655'),
+    ('\uA657', '\uA657'),
+    ('\uA659', '\uA659'),
+    ('\uA65B', '\uA65B'),
+    ('\uA65D', '\uA65D'),
+    ('\uA65F', '\uA65F'),
+    ('\uA661', '\uA661'),
+    ('\uA663', '\uA663'),
+    ('\uA665', '\uA665'),
+    ('\uA667', '\uA667'),
+    ('\uA669', '\uA669'),
+    ('\uA66B', '\uA66B'),
+    ('\uA66D', '\uA66D'),
+    ('\uA681', '\uA681'),
+    ('\uA683', '\uA683'),
+    ('\uA685', '\uA685'),
+    ('\uA687', '\uA687'),
+    ('\uA689', '\uA689'),
+    ('\uA68B', '\uA68B'),
+    ('\uA68D', '\uA68D'),
+    ('\uA68F', '\uA68F'),
+    ('\uA691', '\uA691'),
+    ('\uA693', '\uA693'),
+    ('\uA695', '\uA695'),
+    ('\uA697', '\uA697'),
+    ('\uA699', '\uA699'),
+    ('\uA69B', '\uA69B'),
+    ('\uA723', '\uA723'),
+    ('\uA725', '\uA725'),
+    ('\uA727', '\uA727'),
+    ('\uA729', '\uA729'),
+    ('\uA72B', '\uA72B'),
+    ('\uA72D', '\uA72D'),
+    ('\uA72F', '\uA731'),
+    ('\uA733', '\uA733'),
+    ('\uA735', '\uA735'),
+    ('\uA737', '\uA737'),
+    ('\uA739', '\uA739'),
+    ('\uA73B', '\uA73B'),
+    ('\uA73D', '\uA73D'),
+    ('\uA73F', '\uA73F'),
+    ('\uA741', '\uA741'),
+    ('\uA743', '\uA743'),
+    ('\uA745', '\uA745'),
+    ('\uA747', '\uA747'),
+    ('\uA749', '\uA749'),
+    ('\uA74B', '\uA74B'),
+    ('\uA74D', '\uA74D'),
+    ('\uA74F', '\uA74F'),
+    ('\uA751', '\uA751'),
+    ('\uA753', '\uA753'),
+    ('\uA755', '\uA755'),
+    ('\uA757', '\uA757'),
+    ('\uA759', '\uA759'),
+    ('\uA75B', '\uA75B'),
+    ('\uA75D', '\uA75D'),
+    ('\uA75F', '\uA75F'),
+    ('\uA761', '\uA761'),
+    ('\uA763', '\uA763'),
+    ('\uA765', '\uA765'),
+    ('\uA767', '\uA767'),
+    ('\uA769', '\uA769'),
+    ('\uA76B', '\uA76B'),
+    ('\uA76D', '\uA76D'),
+    ('\uA76F', '\uA76F'),
+    ('\uA771', '\uA778'),
+    ('\uA77A', '\uA77A'),
+    ('\uA77C', '\uA77C'),
+    ('\uA77F', '\uA77F'),
+    ('\uA781', '\uA781'),
+    ('\uA783', '\uA783'),
+    ('\uA785', '\uA785'),
+    ('\uA787', '\uA787'),
+    ('\uA78C', '\uA78C'),
+    ('\uA78E', '\uA78E'),
+    ('\uA791', '\uA791'),
+    ('\uA793', '\uA795'),
+    ('\uA797', '\uA797'),
+    ('\uA799', '\uA799'),
+    ('\uA79B', '\uA79B'),
+    ('\uA79D', '\uA79D'),
+    ('\uA79F', '\uA79F'),
+    ('\uA7A1', '\uA7A1'),
+    ('\uA7A3', '\uA7A3'),
+    ('\uA7A5', '\uA7A5'),
+    ('\uA7A7', '\uA7A7'),
+    ('\uA7A9', '\uA7A9'),
+    ('\uA7AF', '\uA7AF'),
+    ('\uA7B5', '\uA7B5'),
+    ('\uA7B7', '\uA7B7'),
+    ('\uA7B9', '\uA7B9'),
+    ('\uA7BB', '\uA7BB'),
+    ('\uA7BD', '\uA7BD'),
+    ('\uA7BF', '\uA7BF'),
+    ('\uA7C1', '\uA7C1'),
+    ('\uA7C3', '\uA7C3'),
+    ('\uA7C8', '\uA7C8'),
+    ('\uA7CA', '\uA7CA'),
+    ('\uA7D1', '\uA7D1'),
+    ('\uA7D3', '\uA7D3'),
+    ('\uA7D5', '\uA7D5'),
+    ('\uA7D7', '\uA7D7'),
+    ('\uA7D9', '\uA7D9'),
+    ('\uA7F6', '\uA7F6'),
+    ('\uA7FA', '\uA7FA'),
+    ('\uAB30', '\uAB5A'),
+    ('\uAB60', '\uAB68'),
+    ('\uAB70', '\uABBF'),
+    ('\uFB00', '\uFB06'),
+    ('\uFB13', '\uFB17'),
+    ('\uFF41', '\uFF5A'),
+    ('\U00010428', '\U0001044F'),
+    ('\U000104D8', '\U000104FB'),
+    ('\U00010597', '\U000105A1'),
+    ('\U000105A3', '\U000105B1'),
+    ('\U000105B3', '\U000105B9'),
+    ('\U000105BB', '\U000105BC'),
+    ('\U00010CC0', '\U00010CF2'),
+    ('\U000118C0', '\U000118DF'),
+    ('\U00016E60', '\U00016E7F'),
+    ('\U0001D41A', '\U0001D433'),
+    ('\U0001D44E', '\U0001D454'),
+    ('\U0001D456', '\U0001D467'),
+    ('\U0001D482', '\U0001D49B'),
+    ('\U0001D4B6', '\U0001D4B9'),
+    ('\U0001D4BB', '\U0001D4BB'),
+    ('\U0001D4BD', '\U0001D4C3'),
+    ('\U0001D4C5', '\U0001D4CF'),
+    ('\U0001D4EA', '\U0001D503'),
+    ('\U0001D51E', '\U0001D537'),
+    ('\U0001D552', '\U0001D56B'),
+    ('\U0001D586', '\U0001D59F'),
+    ('\U0001D5BA', '\U0001D5D3'),
+    ('\U0001D5EE', '\U0001D607'),
+    ('\U0001D622', '\U0001D63B'),
+    ('\U0001D656', '\U0001D66F'),
+    ('\U0001D68A', '\U0001D6A5'),
+    ('\U0001D6C2', '\U0001D6DA'),
+    ('\U0001D6DC', '\U0001D6E1'),
+    ('\U0001D6FC', '\U0001D714'),
+    ('\U0001D716', '\U0001D71B'),
+    ('\U0001D736', '\U0001D74E'),
+    ('\U0001D750', '\U0001D755'),
+    ('\U0001D770', '\U0001D788'),
+    ('\U0001D78A', '\U0001D78F'),
+    ('\U0001D7AA', '\U0001D7C2'),
+    ('\U0001D7C4', '\U0001D7C9'),
+    ('\U0001D7CB', '\U0001D7CB'),
+    ('\U0001DF00', '\U0001DF09'),
+    ('\U0001DF0B', '\U0001DF1E'),
+    ('\U0001E922', '\U0001E943')
+  ]),
+  ("lm", &[
+    ('\u02B0', '\u02C1'),
+    ('\u02C6', '\u02D1'),
+    ('\u02E0', '\u02E4'),
+    ('\u02EC', '\u02EC'),
+    ('\u02EE', '\u02EE'),
+    ('\u0374', '\u0374'),
+    ('\u037A', '\u037A'),
+    ('\u0559', '\u0559'),
+    ('\u0640', '\u0640'),
+    ('\u06E5', '\u06E6'),
+    ('\u07F4', '\u07F5'),
+    ('\u07FA', '\u07FA'),
+    ('\u081A', '\u081A'),
+    ('\u0824', '\u0824'),
+    ('\u0828', '\u0828'),
+    ('\u08C9', '\u08C9'),
+    ('\u0971', '\u0971'),
+    ('\u0E46', '\u0E46'),
+    ('\u0EC6', '\u0EC6'),
+    ('\u10FC', '\u10FC'),
+    ('\u17D7', '\u17D7'),
+    ('\u1843', '\u1843'),
+    ('\u1AA7', '\u1AA7'),
+    ('\u1C78', '\u1C7D'),
+    ('\u1D2C', '\u1D6A'),
+    ('\u1D78', '\u1D78'),
+    ('\u1D9B', '\u1DBF'),
+    ('\u2071', '\u2071'),
+    ('\u207F', '\u207F'),
+    ('\u2090', '\u209C'),
+    ('\u2C7C', '\u2C7D'),
+    ('\u2D6F', '\u2D6F'),
+    ('\u2E2F', '\u2E2F'),
+    ('\u3005', '\u3005'),
+    ('\u3031', '\u3035'),
+    ('\u303B', '\u303B'),
+    ('\u309D', '\u309E'),
+    ('\u30FC', '\u30FE'),
+    ('\uA015', '\uA015'),
+    ('\uA4F8', '\uA4FD'),
+    ('\uA60C', '\uA60C'),
+    ('\uA67F', '\uA67F'),
+    ('\uA69C', '\uA69D'),
+    ('\uA717', '\uA71F'),
+    ('\uA770', '\uA770'),
+    ('\uA788', '\uA788'),
+    ('\uA7F2', '\uA7F4'),
+    ('\uA7F8', '\uA7F9'),
+    ('\uA9CF', '\uA9CF'),
+    ('\uA9E6', '\uA9E6'),
+    ('\uAA70', '\uAA70'),
+    ('\uAADD', '\uAADD'),
+    ('\uAAF3', '\uAAF4'),
+    ('\uAB5C', '\uAB5F'),
+    ('\uAB69', '\uAB69'),
+    ('\uFF70', '\uFF70'),
+    ('\uFF9E', '\uFF9F'),
+    ('\U00010780', '\U00010785'),
+    ('\U00010787', '\U000107B0'),
+    ('\U000107B2', '\U000107BA'),
+    ('\U00016B40', '\U00016B43'),
+    ('\U00016F93', '\U00016F9F'),
+    ('\U00016FE0', '\U00016FE1'),
+    ('\U00016FE3', '\U00016FE3'),
+    ('\U0001AFF0', '\U0001AFF3'),
+    ('\U0001AFF5', '\U0001AFFB'),
+    ('\U0001AFFD', '\U0001AFFE'),
+    ('\U0001E137', '\U0001E13D'),
+    ('\U0001E94B', '\U0001E94B')
+  ]),
+  ("lo", &[
+    ('\u00AA', '\u00AA'),
+    ('\u00BA', '\u00BA'),
+    ('\u01BB', '\u01BB'),
+    ('\u01C0', '\u01C3'),
+    ('\u0294', '\u0294'),
+    ('\u05D0', '\u05EA'),
+    ('\u05EF', '\u05F2'),
+    ('\u0620', '\u063F'),
+    ('\u0641', '\u064A'),
+    ('\u066E', '\u066F'),
+    ('\u0671', '\u06D3'),
+    ('\u06D5', '\u06D5'),
+    ('\u06EE', '\u06EF'),
+    ('\u06FA', '\u06FC'),
+    ('\u06FF', '\u06FF'),
+    ('\u0710', '\u0710'),
+    ('\u0712', '\u072F'),
+    ('\u074D', '\u07A5'),
+    ('\u07B1', '\u07B1'),
+    ('\u07CA', '\u07EA'),
+    ('\u0800', '\u0815'),
+    ('\u0840', '\u0858'),
+    ('\u0860', '\u086A'),
+    ('\u0870', '\u0887'),
+    ('\u0889', '\u088E'),
+    ('\u08A0', '\u08C8'),
+    ('\u0904', '\u0939'),
+    ('\u093D', '\u093D'),
+    ('\u0950', '\u0950'),
+    ('\u0958', '\u0961'),
+    ('\u0972', '\u0980'),
+    ('\u0985', '\u098C'),
+    ('\u098F', '\u0990'),
+    ('\u0993', '\u09A8'),
+    ('\u09AA', '\u09B0'),
+    ('\u09B2', '\u09B2'),
+    ('\u09B6', '\u09B9'),
+    ('\u09BD', '\u09BD'),
+    ('\u09CE', '\u09CE'),
+    ('\u09DC', '\u09DD'),
+    ('\u09DF', '\u09E1'),
+    ('\u09F0', '\u09F1'),
+    ('\u09FC', '\u09FC'),
+    ('\u0A05', '\u0A0A'),
+    ('\u0A0F', '\u0A10'),
+    ('\u0A13', '\u0A28'),
+    ('\u0A2A', '\u0A30'),
+    ('\u0A32', '\u0A33'),
+    ('\u0A35', '\u0A36'),
+    ('\u0A38', '\u0A39'),
+    ('\u0A59', '\u0A5C'),
+    ('\u0A5E', '\u0A5E'),
+    ('\u0A72', '\u0A74'),
+    ('\u0A85', '\u0A8D'),
+    ('\u0A8F', '\u0A91'),
+    ('\u0A93', '\u0AA8'),
+    ('\u0AAA', '\u0AB0'),
+    ('\u0AB2', '\u0AB3'),
+    ('\u0AB5', '\u0AB9'),
+    ('\u0ABD', '\u0ABD'),
+    ('\u0AD0', '\u0AD0'),
+    ('\u0AE0', '\u0AE1'),
+    ('\u0AF9', '\u0AF9'),
+    ('\u0B05', '\u0B0C'),
+    ('\u0B0F', '\u0B10'),
+    ('\u0B13', '\u0B28'),
+    ('\u0B2A', '\u0B30'),
+    ('\u0B32', '\u0B33'),
+    ('\u0B35', '\u0B39'),
+    ('\u0B3D', '\u0B3D'),
+    ('\u0B5C', '\u0B5D'),
+    ('\u0B5F', '\u0B61'),
+    ('\u0B71', '\u0B71'),
+    ('\u0B83', '\u0B83'),
+    ('\u0B85', '\u0B8A'),
+    ('\u0B8E', '\u0B90'),
+    ('\u0B92', '\u0B95'),
+    ('\u0B99', '\u0B9A'),
+    ('\u0B9C', '\u0B9C'),
+    ('\u0B9E', '\u0B9F'),
+    ('\u0BA3', '\u0BA4'),
+    ('\u0BA8', '\u0BAA'),
+    ('\u0BAE', '\u0BB9'),
+    ('\u0BD0', '\u0BD0'),
+    ('\u0C05', '\u0C0C'),
+    ('\u0C0E', '\u0C10'),
+    ('\u0C12', '\u0C28'),
+    ('\u0C2A', '\u0C39'),
+    ('\u0C3D', '\u0C3D'),
+    ('\u0C58', '\u0C5A'),
+    ('\u0C5D', '\u0C5D'),
+    ('\u0C60', '\u0C61'),
+    ('\u0C80', '\u0C80'),
+    ('\u0C85', '\u0C8C'),
+    ('\u0C8E', '\u0C90'),
+    ('\u0C92', '\u0CA8'),
+    ('\u0CAA', '\u0CB3'),
+    ('\u0CB5', '\u0CB9'),
+    ('\u0CBD', '\u0CBD'),
+    ('\u0CDD', '\u0CDE'),
+    ('\u0CE0', '\u0CE1'),
+    ('\u0CF1', '\u0CF2'),
+    ('\u0D04', '\u0D0C'),
+    ('\u0D0E', '\u0D10'),
+    ('\u0D12', '\u0D3A'),
+    ('\u0D3D', '\u0D3D'),
+    ('\u0D4E', '\u0D4E'),
+    ('\u0D54', '\u0D56'),
+    ('\u0D5F', '\u0D61'),
+    ('\u0D7A', '\u0D7F'),
+    ('\u0D85', '\u0D96'),
+    ('\u0D9A', '\u0DB1'),
+    ('\u0DB3', '\u0DBB'),
+    ('\u0DBD', '\u0DBD'),
+    ('\u0DC0', '\u0DC6'),
+    ('\u0E01', '\u0E30'),
+    ('\u0E32', '\u0E33'),
+    ('\u0E40', '\u0E45'),
+    ('\u0E81', '\u0E82'),
+    ('\u0E84', '\u0E84'),
+    ('\u0E86', '\u0E8A'),
+    ('\u0E8C', '\u0EA3'),
+    ('\u0EA5', '\u0EA5'),
+    ('\u0EA7', '\u0EB0'),
+    ('\u0EB2', '\u0EB3'),
+    ('\u0EBD', '\u0EBD'),
+    ('\u0EC0', '\u0EC4'),
+    ('\u0EDC', '\u0EDF'),
+    ('\u0F00', '\u0F00'),
+    ('\u0F40', '\u0F47'),
+    ('\u0F49', '\u0F6C'),
+    ('\u0F88', '\u0F8C'),
+    ('\u1000', '\u102A'),
+    ('\u103F', '\u103F'),
+    ('\u1050', '\u1055'),
+    ('\u105A', '\u105D'),
+    ('\u1061', '\u1061'),
+    ('\u1065', '\u1066'),
+    ('\u106E', '\u1070'),
+    ('\u1075', '\u1081'),
+    ('\u108E', '\u108E'),
+    ('\u1100', '\u1248'),
+    ('\u124A', '\u124D'),
+    ('\u1250', '\u1256'),
+    ('\u1258', '\u1258'),
+    ('\u125A', '\u125D'),
+    ('\u1260', '\u1288'),
+    ('\u128A', '\u128D'),
+    ('\u1290', '\u12B0'),
+    ('\u12B2', '\u12B5'),
+    ('\u12B8', '\u12BE'),
+    ('\u12C0', '\u12C0'),
+    ('\u12C2', '\u12C5'),
+    ('\u12C8', '\u12D6'),
+    ('\u12D8', '\u1310'),
+    ('\u1312', '\u1315'),
+    ('\u1318', '\u135A'),
+    ('\u1380', '\u138F'),
+    ('\u1401', '\u166C'),
+    ('\u166F', '\u167F'),
+    ('\u1681', '\u169A'),
+    ('\u16A0', '\u16EA'),
+    ('\u16F1', '\u16F8'),
+    ('\u1700', '\u1711'),
+    ('\u171F', '\u1731'),
+    ('\u1740', '\u1751'),
+    ('\u1760', '\u176C'),
+    ('\u176E', '\u1770'),
+    ('\u1780', '\u17B3'),
+    ('\u17DC', '\u17DC'),
+    ('\u1820', '\u1842'),
+    ('\u1844', '\u1878'),
+    ('\u1880', '\u1884'),
+    ('\u1887', '\u18A8'),
+    ('\u18AA', '\u18AA'),
+    ('\u18B0', '\u18F5'),
+    ('\u1900', '\u191E'),
+    ('\u1950', '\u196D'),
+    ('\u1970', '\u1974'),
+    ('\u1980', '\u19AB'),
+    ('\u19B0', '\u19C9'),
+    ('\u1A00', '\u1A16'),
+    ('\u1A20', '\u1A54'),
+    ('\u1B05', '\u1B33'),
+    ('\u1B45', '\u1B4C'),
+    ('\u1B83', '\u1BA0'),
+    ('\u1BAE', '\u1BAF'),
+    ('\u1BBA', '\u1BE5'),
+    ('\u1C00', '\u1C23'),
+    ('\u1C4D', '\u1C4F'),
+    ('\u1C5A', '\u1C77'),
+    ('\u1CE9', '\u1CEC'),
+    ('\u1CEE', '\u1CF3'),
+    ('\u1CF5', '\u1CF6'),
+    ('\u1CFA', '\u1CFA'),
+    ('\u2135', '\u2138'),
+    ('\u2D30', '\u2D67'),
+    ('\u2D80', '\u2D96'),
+    ('\u2DA0', '\u2DA6'),
+    ('\u2DA8', '\u2DAE'),
+    ('\u2DB0', '\u2DB6'),
+    ('\u2DB8', '\u2DBE'),
+    ('\u2DC0', '\u2DC6'),
+    ('\u2DC8', '\u2DCE'),
+    ('\u2DD0', '\u2DD6'),
+    ('\u2DD8', '\u2DDE'),
+    ('\u3006', '\u3006'),
+    ('\u303C', '\u303C'),
+    ('\u3041', '\u3096'),
+    ('\u309F', '\u309F'),
+    ('\u30A1', '\u30FA'),
+    ('\u30FF', '\u30FF'),
+    ('\u3105', '\u312F'),
+    ('\u3131', '\u318E'),
+    ('\u31A0', '\u31BF'),
+    ('\u31F0', '\u31FF'),
+    ('\u3400', '\u4DBF'),
+    ('\u4E00', '\uA014'),
+    ('\uA016', '\uA48C'),
+    ('\uA4D0', '\uA4F7'),
+    ('\uA500', '\uA60B'),
+    ('\uA610', '\uA61F'),
+    ('\uA62A', '\uA62B'),
+    ('\uA66E', '\uA66E'),
+    ('\uA6A0', '\uA6E5'),
+    ('\uA78F', '\uA78F'),
+    ('\uA7F7', '\uA7F7'),
+    ('\uA7FB', '\uA801'),
+    ('\uA803', '\uA805'),
+    ('\uA807', '\uA80A'),
+    ('\uA80C', '\uA822'),
+    ('\uA840', '\uA873'),
+    ('\uA882', '\uA8B3'),
+    ('\uA8F2', '\uA8F7'),
+    ('\uA8FB', '\uA8FB'),
+    ('\uA8FD', '\uA8FE'),
+    ('\uA90A', '\uA925'),
+    ('\uA930', '\uA946'),
+    ('\uA960', '\uA97C'),
+    ('\uA984', '\uA9B2'),
+    ('\uA9E0', '\uA9E4'),
+    ('\uA9E7', '\uA9EF'),
+    ('\uA9FA', '\uA9FE'),
+    ('\uAA00', '\uAA28'),
+    ('\uAA40', '\uAA42'),
+    ('\uAA44', '\uAA4B'),
+    ('\uAA60', '\uAA6F'),
+    ('\uAA71', '\uAA76'),
+    ('\uAA7A', '\uAA7A'),
+    ('\uAA7E', '\uAAAF'),
+    ('\uAAB1', '\uAAB1'),
+    ('\uAAB5', '\uAAB6'),
+    ('\uAAB9', '\uAABD'),
+    ('\uAAC0', '\uAAC0'),
+    ('\uAAC2', '\uAAC2'),
+    ('\uAADB', '\uAADC'),
+    ('\uAAE0', '\uAAEA'),
+    ('\uAAF2', '\uAAF2'),
+    ('\uAB01', '\uAB06'),
+    ('\uAB09', '\uAB0E'),
+    ('\uAB11', '\uAB16'),
+    ('\uAB20', '\uAB26'),
+    ('\uAB28', '\uAB2E'),
+    ('\uABC0', '\uABE2'),
+    ('\uAC00', '\uD7A3'),
+    ('\uD7B0', '\uD7C6'),
+    ('\uD7CB', '\uD7FB'),
+    ('\uF900', '\uFA6D'),
+    ('\uFA70', '\uFAD9'),
+    ('\uFB1D', '\uFB1D'),
+    ('\uFB1F', '\uFB28'),
+    ('\uFB2A', '\uFB36'),
+    ('\uFB38', '\uFB3C'),
+    ('\uFB3E', '\uFB3E'),
+    ('\uFB40', '\uFB41'),
+    ('\uFB43', '\uFB44'),
+    ('\uFB46', '\uFBB1'),
+    ('\uFBD3', '\uFD3D'),
+    ('\uFD50', '\uFD8F'),
+    ('\uFD92', '\uFDC7'),
+    ('\uFDF0', '\uFDFB'),
+    ('\uFE70', '\uFE74'),
+    ('\uFE76', '\uFEFC'),
+    ('\uFF66', '\uFF6F'),
+    ('\uFF71', '\uFF9D'),
+    ('\uFFA0', '\uFFBE'),
+    ('\uFFC2', '\uFFC7'),
+    ('\uFFCA', '\uFFCF'),
+    ('\uFFD2', '\uFFD7'),
+    ('\uFFDA', '\uFFDC'),
+    ('\U00010000', '\U0001000B'),
+    ('\U0001000D', '\U00010026'),
+    ('\U00010028', '\U0001003A'),
+    ('\U0001003C', '\U0001003D'),
+    ('\U0001003F', '\U0001004D'),
+    ('\U00010050', '\U0001005D'),
+    ('\U00010080', '\U000100FA'),
+    ('\U00010280', '\U0001029C'),
+    ('\U000102A0', '\U000102D0'),
+    ('\U00010300', '\U0001031F'),
+    ('\U0001032D', '\U00010340'),
+    ('\U00010342', '\U00010349'),
+    ('\U00010350', '\U00010375'),
+    ('\U00010380', '\U0001039D'),
+    ('\U000103A0', '\U000103C3'),
+    ('\U000103C8', '\U000103CF'),
+    ('\U00010450', '\U0001049D'),
+    ('\U00010500', '\U00010527'),
+    ('\U00010530', '\U00010563'),
+    ('\U00010600', '\U00010736'),
+    ('\U00010740', '\U00010755'),
+    ('\U00010760', '\U00010767'),
+    ('\U00010800', '\U00010805'),
+    ('\U00010808', '\U00010808'),
+    ('\U0001080A', '\U00010835'),
+    ('\U00010837', '\U00010838'),
+    ('\U0001083C', '\U0001083C'),
+    ('\U0001083F', '\U00010855'),
+    ('\U00010860', '\U00010876'),
+    ('\U00010880', '\U0001089E'),
+    ('\U000108E0', '\U000108F2'),
+    ('\U000108F4', '\U000108F5'),
+    ('\U00010900', '\U00010915'),
+    ('\U00010920', '\U00010939'),
+    ('\U00010980', '\U000109B7'),
+    ('\U000109BE', '\U000109BF'),
+    ('\U00010A00', '\U00010A00'),
+    ('\U00010A10', '\U00010A13'),
+    ('\U00010A15', '\U00010A17'),
+    ('\U00010A19', '\U00010A35'),
+    ('\U00010A60', '\U00010A7C'),
+    ('\U00010A80', '\U00010A9C'),
+    ('\U00010AC0', '\U00010AC7'),
+    ('\U00010AC9', '\U00010AE4'),
+    ('\U00010B00', '\U00010B35'),
+    ('\U00010B40', '\U00010B55'),
+    ('\U00010B60', '\U00010B72'),
+    ('\U00010B80', '\U00010B91'),
+    ('\U00010C00', '\U00010C48'),
+    ('\U00010D00', '\U00010D23'),
+    ('\U00010E80', '\U00010EA9'),
+    ('\U00010EB0', '\U00010EB1'),
+    ('\U00010F00', '\U00010F1C'),
+    ('\U00010F27', '\U00010F27'),
+    ('\U00010F30', '\U00010F45'),
+    ('\U00010F70', '\U00010F81'),
+    ('\U00010FB0', '\U00010FC4'),
+    ('\U00010FE0', '\U00010FF6'),
+    ('\U00011003', '\U00011037'),
+    ('\U00011071', '\U00011072'),
+    ('\U00011075', '\U00011075'),
+    ('\U00011083', '\U000110AF'),
+    ('\U000110D0', '\U000110E8'),
+    ('\U00011103', '\U00011126'),
+    ('\U00011144', '\U00011144'),
+    ('\U00011147', '\U00011147'),
+    ('\U00011150', '\U00011172'),
+    ('\U00011176', '\U00011176'),
+    ('\U00011183', '\U000111B2'),
+    ('\U000111C1', '\U000111C4'),
+    ('\U000111DA', '\U000111DA'),
+    ('\U000111DC', '\U000111DC'),
+    ('\U00011200', '\U00011211'),
+    ('\U00011213', '\U0001122B'),
+    ('\U00011280', '\U00011286'),
+    ('\U00011288', '\U00011288'),
+    ('\U0001128A', '\U0001128D'),
+    ('\U0001128F', '\U0001129D'),
+    ('\U0001129F', '\U000112A8'),
+    ('\U000112B0', '\U000112DE'),
+    ('\U00011305', '\U0001130C'),
+    ('\U0001130F', '\U00011310'),
+    ('\U00011313', '\U00011328'),
+    ('\U0001132A', '\U00011330'),
+    ('\U00011332', '\U00011333'),
+    ('\U00011335', '\U00011339'),
+    ('\U0001133D', '\U0001133D'),
+    ('\U00011350', '\U00011350'),
+    ('\U0001135D', '\U00011361'),
+    ('\U00011400', '\U00011434'),
+    ('\U00011447', '\U0001144A'),
+    ('\U0001145F', '\U00011461'),
+    ('\U00011480', '\U000114AF'),
+    ('\U000114C4', '\U000114C5'),
+    ('\U000114C7', '\U000114C7'),
+    ('\U00011580', '\U000115AE'),
+    ('\U000115D8', '\U000115DB'),
+    ('\U00011600', '\U0001162F'),
+    ('\U00011644', '\U00011644'),
+    ('\U00011680', '\U000116AA'),
+    ('\U000116B8', '\U000116B8'),
+    ('\U00011700', '\U0001171A'),
+    ('\U00011740', '\U00011746'),
+    ('\U00011800', '\U0001182B'),
+    ('\U000118FF', '\U00011906'),
+    ('\U00011909', '\U00011909'),
+    ('\U0001190C', '\U00011913'),
+    ('\U00011915', '\U00011916'),
+    ('\U00011918', '\U0001192F'),
+    ('\U0001193F', '\U0001193F'),
+    ('\U00011941', '\U00011941'),
+    ('\U000119A0', '\U000119A7'),
+    ('\U000119AA', '\U000119D0'),
+    ('\U000119E1', '\U000119E1'),
+    ('\U000119E3', '\U000119E3'),
+    ('\U00011A00', '\U00011A00'),
+    ('\U00011A0B', '\U00011A32'),
+    ('\U00011A3A', '\U00011A3A'),
+    ('\U00011A50', '\U00011A50'),
+    ('\U00011A5C', '\U00011A89'),
+    ('\U00011A9D', '\U00011A9D'),
+    ('\U00011AB0', '\U00011AF8'),
+    ('\U00011C00', '\U00011C08'),
+    ('\U00011C0A', '\U00011C2E'),
+    ('\U00011C40', '\U00011C40'),
+    ('\U00011C72', '\U00011C8F'),
+    ('\U00011D00', '\U00011D06'),
+    ('\U00011D08', '\U00011D09'),
+    ('\U00011D0B', '\U00011D30'),
+    ('\U00011D46', '\U00011D46'),
+    ('\U00011D60', '\U00011D65'),
+    ('\U00011D67', '\U00011D68'),
+    ('\U00011D6A', '\U00011D89'),
+    ('\U00011D98', '\U00011D98'),
+    ('\U00011EE0', '\U00011EF2'),
+    ('\U00011FB0', '\U00011FB0'),
+    ('\U00012000', '\U00012399'),
+    ('\U00012480', '\U00012543'),
+    ('\U00012F90', '\U00012FF0'),
+    ('\U00013000', '\U0001342E'),
+    ('\U00014400', '\U00014646'),
+    ('\U00016800', '\U00016A38'),
+    ('\U00016A40', '\U00016A5E'),
+    ('\U00016A70', '\U00016ABE'),
+    ('\U00016AD0', '\U00016AED'),
+    ('\U00016B00', '\U00016B2F'),
+    ('\U00016B63', '\U00016B77'),
+    ('\U00016B7D', '\U00016B8F'),
+    ('\U00016F00', '\U00016F4A'),
+    ('\U00016F50', '\U00016F50'),
+    ('\U00017000', '\U000187F7'),
+    ('\U00018800', '\U00018CD5'),
+    ('\U00018D00', '\U00018D08'),
+    ('\U0001B000', '\U0001B122'),
+    ('\U0001B150', '\U0001B152'),
+    ('\U0001B164', '\U0001B167'),
+    ('\U0001B170', '\U0001B2FB'),
+    ('\U0001BC00', '\U0001BC6A'),
+    ('\U0001BC70', '\U0001BC7C'),
+    ('\U0001BC80', '\U0001BC88'),
+    ('\U0001BC90', '\U0001BC99'),
+    ('\U0001DF0A', '\U0001DF0A'),
+    ('\U0001E100', '\U0001E12C'),
+    ('\U0001E14E', '\U0001E14E'),
+    ('\U0001E290', '\U0001E2AD'),
+    ('\U0001E2C0', '\U0001E2EB'),
+    ('\U0001E7E0', '\U0001E7E6'),
+    ('\U0001E7E8', '\U0001E7EB'),
+    ('\U0001E7ED', '\U0001E7EE'),
+    ('\U0001E7F0', '\U0001E7FE'),
+    ('\U0001E800', '\U0001E8C4'),
+    ('\U0001EE00', '\U0001EE03'),
+    ('\U0001EE05', '\U0001EE1F'),
+    ('\U0001EE21', '\U0001EE22'),
+    ('\U0001EE24', '\U0001EE24'),
+    ('\U0001EE27', '\U0001EE27'),
+    ('\U0001EE29', '\U0001EE32'),
+    ('\U0001EE34', '\U0001EE37'),
+    ('\U0001EE39', '\U0001EE39'),
+    ('\U0001EE3B', '\U0001EE3B'),
+    ('\U0001EE42', '\U0001EE42'),
+    ('\U0001EE47', '\U0001EE47'),
+    ('\U0001EE49', '\U0001EE49'),
+    ('\U0001EE4B', '\U0001EE4B'),
+    ('\U0001EE4D', '\U0001EE4F'),
+    ('\U0001EE51', '\U0001EE52'),
+    ('\U0001EE54', '\U0001EE54'),
+    ('\U0001EE57', '\U0001EE57'),
+    ('\U0001EE59', '\U0001EE59'),
+    ('\U0001EE5B', '\U0001EE5B'),
+    ('\U0001EE5D', '\U0001EE5D'),
+    ('\U0001EE5F', '\U0001EE5F'),
+    ('\U0001EE61', '\U0001EE62'),
+    ('\U0001EE64', '\U0001EE64'),
+    ('\U0001EE67', '\U0001EE6A'),
+    ('\U0001EE6C', '\U0001EE72'),
+    ('\U0001EE74', '\U0001EE77'),
+    ('\U0001EE79', '\U0001EE7C'),
+    ('\U0001EE7E', '\U0001EE7E'),
+    ('\U0001EE80', '\U0001EE89'),
+    ('\U0001EE8B', '\U0001EE9B'),
+    ('\U0001EEA1', '\U0001EEA3'),
+    ('\U0001EEA5', '\U0001EEA9'),
+    ('\U0001EEAB', '\U0001EEBB'),
+    ('\U00020000', '\U0002A6DF'),
+    ('\U0002A700', '\U0002B738'),
+    ('\U0002B740', '\U0002B81D'),
+    ('\U0002B820', '\U0002CEA1'),
+    ('\U0002CEB0', '\U0002EBE0'),
+    ('\U0002F800', '\U0002FA1D'),
+    ('\U00030000', '\U0003134A')
+  ]),
+  ("lt", &[
+    ('\u01C5', '\u01C5'),
+    ('\u01C8', '\u01C8'),
+    ('\u01CB', '\u01CB'),
+    ('\u01F2', '\u01F2'),
+    ('\u1F88', '\u1F8F'),
+    ('\u1F98', '\u1F9F'),
+    ('\u1FA8', '\u1FAF'),
+    ('\u1FBC', '\u1FBC'),
+    ('\u1FCC', '\u1FCC'),
+    ('\u1FFC', '\u1FFC')
+  ]),
+  ("lu", &[
+    ('\u0041', '\u005A'),
+    ('\u00C0', '\u00D6'),
+    ('\u00D8', '\u00DE'),
+    ('\u0100', '\u0100'),
+    ('\u0102', '\u0102'),
+    ('\u0104', '\u0104'),
+    ('\u0106', '\u0106'),
+    ('\u0108', '\u0108'),
+    ('\u010A', '\u010A'),
+    ('\u010C', '\u010C'),
+    ('\u010E', '\u010E'),
+    ('\u0110', '\u0110'),
+    ('\u0112', '\u0112'),
+    ('\u0114', '\u0114'),
+    ('\u0116', '\u0116'),
+    ('\u0118', '\u0118'),
+    ('\u011A', '\u011A'),
+    ('\u011C', '\u011C'),
+    ('\u011E', '\u011E'),
+    ('\u0120', '\u0120'),
+    ('\u0122', '\u0122'),
+    ('\u0124', '\u0124'),
+    ('\u0126', '\u0126'),
+    ('\u0128', '\u0128'),
+    ('\u012A', '\u012A'),
+    ('\u012C', '\u012C'),
+    ('\u012E', '\u012E'),
+    ('\u0130', '\u0130'),
+    ('\u0132', '\u0132'),
+    ('\u0134', '\u0134'),
+    ('\u0136', '\u0136'),
+    ('\u0139', '\u0139'),
+    ('\u013B', '\u013B'),
+    ('\u013D', '\u013D'),
+    ('\u013F', '\u013F'),
+    ('\u0141', '\u0141'),
+    ('\u0143', '\u0143'),
+    ('\u0145', '\u0145'),
+    ('\u0147', '\u0147'),
+    ('\u014A', '\u014A'),
+    ('\u014C', '\u014C'),
+    ('\u014E', '\u014E'),
+    ('\u0150', '\u0150'),
+    ('\u0152', '\u0152'),
+    ('\u0154', '\u0154'),
+    ('\u0156', '\u0156'),
+    ('\u0158', '\u0158'),
+    ('\u015A', '\u015A'),
+    ('\u015C', '\u015C'),
+    ('\u015E', '\u015E'),
+    ('\u0160', '\u0160'),
+    ('\u0162', '\u0162'),
+    ('\u0164', '\u0164'),
+    ('\u0166', '\u0166'),
+    ('\u0168', '\u0168'),
+    ('\u016A', '\u016A'),
+    ('\u016C', '\u016C'),
+    ('\u016E', '\u016E'),
+    ('\u0170', '\u0170'),
+    ('\u0172', '\u0172'),
+    ('\u0174', '\u0174'),
+    ('\u0176', '\u0176'),
+    ('\u0178', '\u0179'),
+    ('\u017B', '\u017B'),
+    ('\u017D', '\u017D'),
+    ('\u0181', '\u0182'),
+    ('\u0184', '\u0184'),
+    ('\u0186', '\u0187'),
+    ('\u0189', '\u018B'),
+    ('\u018E', '\u0191'),
+    ('\u0193', '\u0194'),
+    ('\u0196', '\u0198'),
+    ('\u019C', '\u019D'),
+    ('\u019F', '\u01A0'),
+    ('\u01A2', '\u01A2'),
+    ('\u01A4', '\u01A4'),
+    ('\u01A6', '\u01A7'),
+    ('\u01A9', '\u01A9'),
+    ('\u01AC', '\u01AC'),
+    ('\u01AE', '\u01AF'),
+    ('\u01B1', '\u01B3'),
+    ('\u01B5', '\u01B5'),
+    ('\u01B7', '\u01B8'),
+    ('\u01BC', '\u01BC'),
+    ('\u01C4', '\u01C4'),
+    ('\u01C7', '\u01C7'),
+    ('\u01CA', '\u01CA'),
+    ('\u01CD', '\u01CD'),
+    ('\u01CF', '\u01CF'),
+    ('\u01D1', '\u01D1'),
+    ('\u01D3', '\u01D3'),
+    ('\u01D5', '\u01D5'),
+    ('\u01D7', '\u01D7'),
+    ('\u01D9', '\u01D9'),
+    ('\u01DB', '\u01DB'),
+    ('\u01DE', '\u01DE'),
+    ('\u01E0', '\u01E0'),
+    ('\u01E2', '\u01E2'),
+    ('\u01E4', '\u01E4'),
+    ('\u01E6', '\u01E6'),
+    ('\u01E8', '\u01E8'),
+    ('\u01EA', '\u01EA'),
+    ('\u01EC', '\u01EC'),
+    ('\u01EE', '\u01EE'),
+    ('\u01F1', '\u01F1'),
+    ('\u01F4', '\u01F4'),
+    ('\u01F6', '\u01F8'),
+    ('\u01FA', '\u01FA'),
+    ('\u01FC', '\u01FC'),
+    ('\u01FE', '\u01FE'),
+    ('\u0200', '\u0200'),
+    ('\u0202', '\u0202'),
+    ('\u0204', '\u0204'),
+    ('\u0206', '\u0206'),
+    ('\u0208', '\u0208'),
+    ('\u020A', '\u020A'),
+    ('\u020C', '\u020C'),
+    ('\u020E', '\u020E'),
+    ('\u0210', '\u0210'),
+    ('\u0212', '\u0212'),
+    ('\u0214', '\u0214'),
+    ('\u0216', '\u0216'),
+    ('\u0218', '\u0218'),
+    ('\u021A', '\u021A'),
+    ('\u021C', '\u021C'),
+    ('\u021E', '\u021E'),
+    ('\u0220', '\u0220'),
+    ('\u0222', '\u0222'),
+    ('\u0224', '\u0224'),
+    ('\u0226', '\u0226'),
+    ('\u0228', '\u0228'),
+    ('\u022A', '\u022A'),
+    ('\u022C', '\u022C'),
+    ('\u022E', '\u022E'),
+    ('\u0230', '\u0230'),
+    ('\u0232', '\u0232'),
+    ('\u023A', '\u023B'),
+    ('\u023D', '\u023E'),
+    ('\u0241', '\u0241'),
+    ('\u0243', '\u0246'),
+    ('\u0248', '\u0248'),
+    ('\u024A', '\u024A'),
+    ('\u024C', '\u024C'),
+    ('\u024E', '\u024E'),
+    ('\u0370', '\u0370'),
+    ('\u0372', '\u0372'),
+    ('\u0376', '\u0376'),
+    ('\u037F', '\u037F'),
+    ('\u0386', '\u0386'),
+    ('\u0388', '\u038A'),
+    ('\u038C', '\u038C'),
+    ('\u038E', '\u038F'),
+    ('\u0391', '\u03A1'),
+    ('\u03A3', '\u03AB'),
+    ('\u03CF', '\u03CF'),
+    ('\u03D2', '\u03D4'),
+    ('\u03D8', '\u03D8'),
+    ('\u03DA', '\u03DA'),
+    ('\u03DC', '\u03DC'),
+    ('\u03DE', '\u03DE'),
+    ('\u03E0', '\u03E0'),
+    ('\u03E2', '\u03E2'),
+    ('\u03E4', '\u03E4'),
+    ('\u03E6', '\u03E6'),
+    ('\u03E8', '\u03E8'),
+    ('\u03EA', '\u03EA'),
+    ('\u03EC', '\u03EC'),
+    ('\u03EE', '\u03EE'),
+    ('\u03F4', '\u03F4'),
+    ('\u03F7', '\u03F7'),
+    ('\u03F9', '\u03FA'),
+    ('\u03FD', '\u042F'),
+    ('\u0460', '\u0460'),
+    ('\u0462', '\u0462'),
+    ('\u0464', '\u0464'),
+    ('\u0466', '\u0466'),
+    ('\u0468', '\u0468'),
+    ('\u046A', '\u046A'),
+    ('\u046C', '\u046C'),
+    ('\u046E', '\u046E'),
+    ('\u0470', '\u0470'),
+    ('\u0472', '\u0472'),
+    ('\u0474', '\u0474'),
+    ('\u0476', '\u0476'),
+    ('\u0478', '\u0478'),
+    ('\u047A', '\u047A'),
+    ('\u047C', '\u047C'),
+    ('\u047E', '\u047E'),
+    ('\u0480', '\u0480'),
+    ('\u048A', '\u048A'),
+    ('\u048C', '\u048C'),
+    ('\u048E', '\u048E'),
+    ('\u0490', '\u0490'),
+    ('\u0492', '\u0492'),
+    ('\u0494', '\u0494'),
+    ('\u0496', '\u0496'),
+    ('\u0498', '\u0498'),
+    ('\u049A', '\u049A'),
+    ('\u049C', '\u049C'),
+    ('\u049E', '\u049E'),
+    ('\u04A0', '\u04A0'),
+    ('\u04A2', '\u04A2'),
+    ('\u04A4', '\u04A4'),
+    ('\u04A6', '\u04A6'),
+    ('\u04A8', '\u04A8'),
+    ('\u04AA', '\u04AA'),
+    ('\u04AC', '\u04AC'),
+    ('\u04AE', '\u04AE'),
+    ('\u04B0', '\u04B0'),
+    ('\u04B2', '\u04B2'),
+    ('\u04B4', '\u04B4'),
+    ('\u04B6', '\u04B6'),
+    ('\u04B8', '\u04B8'),
+    ('\u04BA', '\u04BA'),
+    ('\u04BC', '\u04BC'),
+    ('\u04BE', '\u04BE'),
+    ('\u04C0', '\u04C1'),
+    ('\u04C3', '\u04C3'),
+    ('\u04C5', '\u04C5'),
+    ('\u04C7', '\u04C7'),
+    ('\u04C9', '\u04C9'),
+    ('\u04CB', '\u04CB'),
+    ('\u04CD', '\u04CD'),
+    ('\u04D0', '\u04D0'),
+    ('\u04D2', '\u04D2'),
+    ('\u04D4', '\u04D4'),
+    ('\u04D6', '\u04D6'),
+    ('\u04D8', '\u04D8'),
+    ('\u04DA', '\u04DA'),
+    ('\u04DC', '\u04DC'),
+    ('\u04DE', '\u04DE'),
+    ('\u04E0', '\u04E0'),
+    ('\u04E2', '\u04E2'),
+    ('\u04E4', '\u04E4'),
+    ('\u04E6', '\u04E6'),
+    ('\u04E8', '\u04E8'),
+    ('\u04EA', '\u04EA'),
+    ('\u04EC', '\u04EC'),
+    ('\u04EE', '\u04EE'),
+    ('\u04F0', '\u04F0'),
+    ('\u04F2', '\u04F2'),
+    ('\u04F4', '\u04F4'),
+    ('\u04F6', '\u04F6'),
+    ('\u04F8', '\u04F8'),
+    ('\u04FA', '\u04FA'),
+    ('\u04FC', '\u04FC'),
+    ('\u04FE', '\u04FE'),
+    ('\u0500', '\u0500'),
+    ('\u0502', '\u0502'),
+    ('\u0504', '\u0504'),
+    ('\u0506', '\u0506'),
+    ('\u0508', '\u0508'),
+    ('\u050A', '\u050A'),
+    ('\u050C', '\u050C'),
+    ('\u050E', '\u050E'),
+    ('\u0510', '\u0510'),
+    ('\u0512', '\u0512'),
+    ('\u0514', '\u0514'),
+    ('\u0516', '\u0516'),
+    ('\u0518', '\u0518'),
+    ('\u051A', '\u051A'),
+    ('\u051C', '\u051C'),
+    ('\u051E', '\u051E'),
+    ('\u0520', '\u0520'),
+    ('\u0522', '\u0522'),
+    ('\u0524', '\u0524'),
+    ('\u0526', '\u0526'),
+    ('\u0528', '\u0528'),
+    ('\u052A', '\u052A'),
+    ('\u052C', '\u052C'),
+    ('\u052E', '\u052E'),
+    ('\u0531', '\u0556'),
+    ('\u10A0', '\u10C5'),
+    ('\u10C7', '\u10C7'),
+    ('\u10CD', '\u10CD'),
+    ('\u13A0', '\u13F5'),
+    ('\u1C90', '\u1CBA'),
+    ('\u1CBD', '\u1CBF'),
+    ('\u1E00', '\u1E00'),
+    ('\u1E02', '\u1E02'),
+    ('\u1E04', '\u1E04'),
+    ('\u1E06', '\u1E06'),
+    ('\u1E08', '\u1E08'),
+    ('\u1E0A', '\u1E0A'),
+    ('\u1E0C', '\u1E0C'),
+    ('\u1E0E', '\u1E0E'),
+    ('\u1E10', '\u1E10'),
+    ('\u1E12', '\u1E12'),
+    ('\u1E14', '\u1E14'),
+    ('\u1E16', '\u1E16'),
+    ('\u1E18', '\u1E18'),
+    ('\u1E1A', '\u1E1A'),
+    ('\u1E1C', '\u1E1C'),
+    ('\u1E1E', '\u1E1E'),
+    ('\u1E20', '\u1E20'),
+    ('\u1E22', '\u1E22'),
+    ('\u1E24', '\u1E24'),
+    ('\u1E26', '\u1E26'),
+    ('\u1E28', '\u1E28'),
+    ('\u1E2A', '\u1E2A'),
+    ('\u1E2C', '\u1E2C'),
+    ('\u1E2E', '\u1E2E'),
+    ('\u1E30', '\u1E30'),
+    ('\u1E32', '\u1E32'),
+    ('\u1E34', '\u1E34'),
+    ('\u1E36', '\u1E36'),
+    ('\u1E38', '\u1E38'),
+    ('\u1E3A', '\u1E3A'),
+    ('\u1E3C', '\u1E3C'),
+    ('\u1E3E', '\u1E3E'),
+    ('\u1E40', '\u1E40'),
+    ('\u1E42', '\u1E42'),
+    ('\u1E44', '\u1E44'),
+    ('\u1E46', '\u1E46'),
+    ('\u1E48', '\u1E48'),
+    ('\u1E4A', '\u1E4A'),
+    ('\u1E4C', '\u1E4C'),
+    ('\u1E4E', '\u1E4E'),
+    ('\u1E50', '\u1E50'),
+    ('\u1E52', '\u1E52'),
+    ('\u1E54', '\u1E54'),
+    ('\u1E56', '\u1E56'),
+    ('\u1E58', '\u1E58'),
+    ('\u1E5A', '\u1E5A'),
+    ('\u1E5C', '\u1E5C'),
+    ('\u1E5E', '\u1E5E'),
+    ('\u1E60', '\u1E60'),
+    ('\u1E62', '\u1E62'),
+    ('\u1E64', '\u1E64'),
+    ('\u1E66', '\u1E66'),
+    ('\u1E68', '\u1E68'),
+    ('\u1E6A', '\u1E6A'),
+    ('\u1E6C', '\u1E6C'),
+    ('\u1E6E', '\u1E6E'),
+    ('\u1E70', '\u1E70'),
+    ('\u1E72', '\u1E72'),
+    ('\u1E74', '\u1E74'),
+    ('\u1E76', '\u1E76'),
+    ('\u1E78', '\u1E78'),
+    ('\u1E7A', '\u1E7A'),
+    ('\u1E7C', '\u1E7C'),
+    ('\u1E7E', '\u1E7E'),
+    ('\u1E80', '\u1E80'),
+    ('\u1E82', '\u1E82'),
+    ('\u1E84', '\u1E84'),
+    ('\u1E86', '\u1E86'),
+    ('\u1E88', '\u1E88'),
+    ('\u1E8A', '\u1E8A'),
+    ('\u1E8C', '\u1E8C'),
+    ('\u1E8E', '\u1E8E'),
+    ('\u1E90', '\u1E90'),
+    ('\u1E92', '\u1E92'),
+    ('\u1E94', '\u1E94'),
+    ('\u1E9E', '\u1E9E'),
+    ('\u1EA0', '\u1EA0'),
+    ('\u1EA2', '\u1EA2'),
+    ('\u1EA4', '\u1EA4'),
+    ('\u1EA6', '\u1EA6'),
+    ('\u1EA8', '\u1EA8'),
+    ('\u1EAA', '\u1EAA'),
+    ('\u1EAC', '\u1EAC'),
+    ('\u1EAE', '\u1EAE'),
+    ('\u1EB0', '\u1EB0'),
+    ('\u1EB2', '\u1EB2'),
+    ('\u1EB4', '\u1EB4'),
+    ('\u1EB6', '\u1EB6'),
+    ('\u1EB8', '\u1EB8'),
+    ('\u1EBA', '\u1EBA'),
+    ('\u1EBC', '\u1EBC'),
+    ('\u1EBE', '\u1EBE'),
+    ('\u1EC0', '\u1EC0'),
+    ('\u1EC2', '\u1EC2'),
+    ('\u1EC4', '\u1EC4'),
+    ('\u1EC6', '\u1EC6'),
+    ('\u1EC8', '\u1EC8'),
+    ('\u1ECA', '\u1ECA'),
+    ('\u1ECC', '\u1ECC'),
+    ('\u1ECE', '\u1ECE'),
+    ('\u1ED0', '\u1ED0'),
+    ('\u1ED2', '\u1ED2'),
+    ('\u1ED4', '\u1ED4'),
+    ('\u1ED6', '\u1ED6'),
+    ('\u1ED8', '\u1ED8'),
+    ('\u1EDA', '\u1EDA'),
+    ('\u1EDC', '\u1EDC'),
+    ('\u1EDE', '\u1EDE'),
+    ('\u1EE0', '\u1EE0'),
+    ('\u1EE2', '\u1EE2'),
+    ('\u1EE4', '\u1EE4'),
+    ('\u1EE6', '\u1EE6'),
+    ('\u1EE8', '\u1EE8'),
+    ('\u1EEA', '\u1EEA'),
+    ('\u1EEC', '\u1EEC'),
+    ('\u1EEE', '\u1EEE'),
+    ('\u1EF0', '\u1EF0'),
+    ('\u1EF2', '\u1EF2'),
+    ('\u1EF4', '\u1EF4'),
+    ('\u1EF6', '\u1EF6'),
+    ('\u1EF8', '\u1EF8'),
+    ('\u1EFA', '\u1EFA'),
+    ('\u1EFC', '\u1EFC'),
+    ('\u1EFE', '\u1EFE'),
+    ('\u1F08', '\u1F0F'),
+    ('\u1F18', '\u1F1D'),
+    ('\u1F28', '\u1F2F'),
+    ('\u1F38', '\u1F3F'),
+    ('\u1F48', '\u1F4D'),
+    ('\u1F59', '\u1F59'),
+    ('\u1F5B', '\u1F5B'),
+    ('\u1F5D', '\u1F5D'),
+    ('\u1F5F', '\u1F5F'),
+    ('\u1F68', '\u1F6F'),
+    ('\u1FB8', '\u1FBB'),
+    ('\u1FC8', '\u1FCB'),
+    ('\u1FD8', '\u1FDB'),
+    ('\u1FE8', '\u1FEC'),
+    ('\u1FF8', '\u1FFB'),
+    ('\u2102', '\u2102'),
+    ('\u2107', '\u2107'),
+    ('\u210B', '\u210D'),
+    ('\u2110', '\u2112'),
+    ('\u2115', '\u2115'),
+    ('\u2119', '\u211D'),
+    ('\u2124', '\u2124'),
+    ('\u2126', '\u2126'),
+    ('\u2128', '\u2128'),
+    ('\u212A', '\u212D'),
+    ('\u2130', '\u2133'),
+    ('\u213E', '\u213F'),
+    ('\u2145', '\u2145'),
+    ('\u2183', '\u2183'),
+    ('\u2C00', '\u2C2F'),
+    ('\u2C60', '\u2C60'),
+    ('\u2C62', '\u2C64'),
+    ('\u2C67', '\u2C67'),
+    ('\u2C69', '\u2C69'),
+    ('\u2C6B', '\u2C6B'),
+    ('\u2C6D', '\u2C70'),
+    ('\u2C72', '\u2C72'),
+    ('\u2C75', '\u2C75'),
+    ('\u2C7E', '\u2C80'),
+    ('\u2C82', '\u2C82'),
+    ('\u2C84', '\u2C84'),
+    ('\u2C86', '\u2C86'),
+    ('\u2C88', '\u2C88'),
+    ('\u2C8A', '\u2C8A'),
+    ('\u2C8C', '\u2C8C'),
+    ('\u2C8E', '\u2C8E'),
+    ('\u2C90', '\u2C90'),
+    ('\u2C92', '\u2C92'),
+    ('\u2C94', '\u2C94'),
+    ('\u2C96', '\u2C96'),
+    ('\u2C98', '\u2C98'),
+    ('\u2C9A', '\u2C9A'),
+    ('\u2C9C', '\u2C9C'),
+    ('\u2C9E', '\u2C9E'),
+    ('\u2CA0', '\u2CA0'),
+    ('\u2CA2', '\u2CA2'),
+    ('\u2CA4', '\u2CA4'),
+    ('\u2CA6', '\u2CA6'),
+    ('\u2CA8', '\u2CA8'),
+    ('\u2CAA', '\u2CAA'),
+    ('\u2CAC', '\u2CAC'),
+    ('\u2CAE', '\u2CAE'),
+    ('\u2CB0', '\u2CB0'),
+    ('\u2CB2', '\u2CB2'),
+    ('\u2CB4', '\u2CB4'),
+    ('\u2CB6', '\u2CB6'),
+    ('\u2CB8', '\u2CB8'),
+    ('\u2CBA', '\u2CBA'),
+    ('\u2CBC', '\u2CBC'),
+    ('\u2CBE', '\u2CBE'),
+    ('\u2CC0', '\u2CC0'),
+    ('\u2CC2', '\u2CC2'),
+    ('\u2CC4', '\u2CC4'),
+    ('\u2CC6', '\u2CC6'),
+    ('\u2CC8', '\u2CC8'),
+    ('\u2CCA', '\u2CCA'),
+    ('\u2CCC', '\u2CCC'),
+    ('\u2CCE', '\u2CCE'),
+    ('\u2CD0', '\u2CD0'),
+    ('\u2CD2', '\u2CD2'),
+    ('\u2CD4', '\u2CD4'),
+    ('\u2CD6', '\u2CD6'),
+    ('\u2CD8', '\u2CD8'),
+    ('\u2CDA', '\u2CDA'),
+    ('\u2CDC', '\u2CDC'),
+    ('\u2CDE', '\u2CDE'),
+    ('\u2CE0', '\u2CE0'),
+    ('\u2CE2', '\u2CE2'),
+    ('\u2CEB', '\u2CEB'),
+    ('\u2CED', '\u2CED'),
+    ('\u2CF2', '\u2CF2'),
+    ('\uA640', '\uA640'),
+    ('\uA642', '\uA642'),
+    ('\uA644', '\uA644'),
+    ('\uA646', '\uA646'),
+    ('\uA648', '\uA648'),
+    ('\uA64A', '\uA64A'),
+    ('\uA64C', '\uA64C'),
+    ('\uA64E', '\uA64E'),
+    ('\uA650', '\uA650'),
+    ('\uA652', '\uA652'),
+    ('\uA654', '\uA654'),
+    ('\uA656', '\uA656'),
+    ('\uA658', '\uA658'),
+    ('\uA65A', '\uA65A'),
+    ('\uA65C', '\uA65C'),
+    ('\uA65E', '\uA65E'),
+    ('\uA660', '\uA660'),
+    ('\uA662', '\uA662'),
+    ('\uA664', '\uA664'),
+    ('\uA666', '\uA666'),
+    ('\uA668', '\uA668'),
+    ('\uA66A', '\uA66A'),
+    ('\uA66C', '\uA66C'),
+    ('\uA680', '\uA680'),
+    ('\uA682', '\uA682'),
+    ('\uA684', '\uA684'),
+    ('\uA686', '\uA686'),
+    ('\uA688', '\uA688'),
+    ('\uA68A', '\uA68A'),
+    ('\uA68C', '\uA68C'),
+    ('\uA68E', '\uA68E'),
+    ('\uA690', '\uA690'),
+    ('\uA692', '\uA692'),
+    ('\uA694', '\uA694'),
+    ('\uA696', '\uA696'),
+    ('\uA698', '\uA698'),
+    ('\uA69A', '\uA69A'),
+    ('\uA722', '\uA722'),
+    ('\uA724', '\uA724'),
+    ('\uA726', '\uA726'),
+    ('\uA728', '\uA728'),
+    ('\uA72A', '\uA72A'),
+    ('\uA72C', '\uA72C'),
+    ('\uA72E', '\uA72E'),
+    ('\uA732', '\uA732'),
+    ('\uA734', '\uA734'),
+    ('\uA736', '\uA736'),
+    ('\uA738', '\uA738'),
+    ('\uA73A', '\uA73A'),
+    ('\uA73C', '\uA73C'),
+    ('\uA73E', '\uA73E'),
+    ('\uA740', '\uA740'),
+    ('\uA742', '\uA742'),
+    ('\uA744', '\uA744'),
+    ('\uA746', '\uA746'),
+    ('\uA748', '\uA748'),
+    ('\uA74A', '\uA74A'),
+    ('\uA74C', '\uA74C'),
+    ('\uA74E', '\uA74E'),
+    ('\uA750', '\uA750'),
+    ('\uA752', '\uA752'),
+    ('\uA754', '\uA754'),
+    ('\uA756', '\uA756'),
+    ('\uA758', '\uA758'),
+    ('\uA75A', '\uA75A'),
+    ('\uA75C', '\uA75C'),
+    ('\uA75E', '\uA75E'),
+    ('\uA760', '\uA760'),
+    ('\uA762', '\uA762'),
+    ('\uA764', '\uA764'),
+    ('\uA766', '\uA766'),
+    ('\uA768', '\uA768'),
+    ('\uA76A', '\uA76A'),
+    ('\uA76C', '\uA76C'),
+    ('\uA76E', '\uA76E'),
+    ('\uA779', '\uA779'),
+    ('\uA77B', '\uA77B'),
+    ('\uA77D', '\uA77E'),
+    ('\uA780', '\uA780'),
+    ('\uA782', '\uA782'),
+    ('\uA784', '\uA784'),
+    ('\uA786', '\uA786'),
+    ('\uA78B', '\uA78B'),
+    ('\uA78D', '\uA78D'),
+    ('\uA790', '\uA790'),
+    ('\uA792', '\uA792'),
+    ('\uA796', '\uA796'),
+    ('\uA798', '\uA798'),
+    ('\uA79A', '\uA79A'),
+    ('\uA79C', '\uA79C'),
+    ('\uA79E', '\uA79E'),
+    ('\uA7A0', '\uA7A0'),
+    ('\uA7A2', '\uA7A2'),
+    ('\uA7A4', '\uA7A4'),
+    ('\uA7A6', '\uA7A6'),
+    ('\uA7A8', '\uA7A8'),
+    ('\uA7AA', '\uA7AE'),
+    ('\uA7B0', '\uA7B4'),
+    ('\uA7B6', '\uA7B6'),
+    ('\uA7B8', '\uA7B8'),
+    ('\uA7BA', '\uA7BA'),
+    ('\uA7BC', '\uA7BC'),
+    ('\uA7BE', '\uA7BE'),
+    ('\uA7C0', '\uA7C0'),
+    ('\uA7C2', '\uA7C2'),
+    ('\uA7C4', '\uA7C7'),
+    ('\uA7C9', '\uA7C9'),
+    ('\uA7D0', '\uA7D0'),
+    ('\uA7D6', '\uA7D6'),
+    ('\uA7D8', '\uA7D8'),
+    ('\uA7F5', '\uA7F5'),
+    ('\uFF21', '\uFF3A'),
+    ('\U00010400', '\U00010427'),
+    ('\U000104B0', '\U000104D3'),
+    ('\U00010570', '\U0001057A'),
+    ('\U0001057C', '\U0001058A'),
+    ('\U0001058C', '\U00010592'),
+    ('\U00010594', '\U00010595'),
+    ('\U00010C80', '\U00010CB2'),
+    ('\U000118A0', '\U000118BF'),
+    ('\U00016E40', '\U00016E5F'),
+    ('\U0001D400', '\U0001D419'),
+    ('\U0001D434', '\U0001D44D'),
+    ('\U0001D468', '\U0001D481'),
+    ('\U0001D49C', '\U0001D49C'),
+    ('\U0001D49E', '\U0001D49F'),
+    ('\U0001D4A2', '\U0001D4A2'),
+    ('\U0001D4A5', '\U0001D4A6'),
+    ('\U0001D4A9', '\U0001D4AC'),
+    ('\U0001D4AE', '\U0001D4B5'),
+    ('\U0001D4D0', '\U0001D4E9'),
+    ('\U0001D504', '\U0001D505'),
+    ('\U0001D507', '\U0001D50A'),
+    ('\U0001D50D', '\U0001D514'),
+    ('\U0001D516', '\U0001D51C'),
+    ('\U0001D538', '\U0001D539'),
+    ('\U0001D53B', '\U0001D53E'),
+    ('\U0001D540', '\U0001D544'),
+    ('\U0001D546', '\U0001D546'),
+    ('\U0001D54A', '\U0001D550'),
+    ('\U0001D56C', '\U0001D585'),
+    ('\U0001D5A0', '\U0001D5B9'),
+    ('\U0001D5D4', '\U0001D5ED'),
+    ('\U0001D608', '\U0001D621'),
+    ('\U0001D63C', '\U0001D655'),
+    ('\U0001D670', '\U0001D689'),
+    ('\U0001D6A8', '\U0001D6C0'),
+    ('\U0001D6E2', '\U0001D6FA'),
+    ('\U0001D71C', '\U0001D734'),
+    ('\U0001D756', '\U0001D76E'),
+    ('\U0001D790', '\U0001D7A8'),
+    ('\U0001D7CA', '\U0001D7CA'),
+    ('\U0001E900', '\U0001E921')
+  ]),
+  ("m", &[
+    ('\u0300', '\u036F'),
+    ('\u0483', '\u0489'),
+    ('\u0591', '\u05BD'),
+    ('\u05BF', '\u05BF'),
+    ('\u05C1', '\u05C2'),
+    ('\u05C4', '\u05C5'),
+    ('\u05C7', '\u05C7'),
+    ('\u0610', '\u061A'),
+    ('\u064B', '\u065F'),
+    ('\u0670', '\u0670'),
+    ('\u06D6', '\u06DC'),
+    ('\u06DF', '\u06E4'),
+    ('\u06E7', '\u06E8'),
+    ('\u06EA', '\u06ED'),
+    ('\u0711', '\u0711'),
+    ('\u0730', '\u074A'),
+    ('\u07A6', '\u07B0'),
+    ('\u07EB', '\u07F3'),
+    ('\u07FD', '\u07FD'),
+    ('\u0816', '\u0819'),
+    ('\u081B', '\u0823'),
+    ('\u0825', '\u0827'),
+    ('\u0829', '\u082D'),
+    ('\u0859', '\u085B'),
+    ('\u0898', '\u089F'),
+    ('\u08CA', '\u08E1'),
+    ('\u08E3', '\u0903'),
+    ('\u093A', '\u093C'),
+    ('\u093E', '\u094F'),
+    ('\u0951', '\u0957'),
+    ('\u0962', '\u0963'),
+    ('\u0981', '\u0983'),
+    ('\u09BC', '\u09BC'),
+    ('\u09BE', '\u09C4'),
+    ('\u09C7', '\u09C8'),
+    ('\u09CB', '\u09CD'),
+    ('\u09D7', '\u09D7'),
+    ('\u09E2', '\u09E3'),
+    ('\u09FE', '\u09FE'),
+    ('\u0A01', '\u0A03'),
+    ('\u0A3C', '\u0A3C'),
+    ('\u0A3E', '\u0A42'),
+    ('\u0A47', '\u0A48'),
+    ('\u0A4B', '\u0A4D'),
+    ('\u0A51', '\u0A51'),
+    ('\u0A70', '\u0A71'),
+    ('\u0A75', '\u0A75'),
+    ('\u0A81', '\u0A83'),
+    ('\u0ABC', '\u0ABC'),
+    ('\u0ABE', '\u0AC5'),
+    ('\u0AC7', '\u0AC9'),
+    ('\u0ACB', '\u0ACD'),
+    ('\u0AE2', '\u0AE3'),
+    ('\u0AFA', '\u0AFF'),
+    ('\u0B01', '\u0B03'),
+    ('\u0B3C', '\u0B3C'),
+    ('\u0B3E', '\u0B44'),
+    ('\u0B47', '\u0B48'),
+    ('\u0B4B', '\u0B4D'),
+    ('\u0B55', '\u0B57'),
+    ('\u0B62', '\u0B63'),
+    ('\u0B82', '\u0B82'),
+    ('\u0BBE', '\u0BC2'),
+    ('\u0BC6', '\u0BC8'),
+    ('\u0BCA', '\u0BCD'),
+    ('\u0BD7', '\u0BD7'),
+    ('\u0C00', '\u0C04'),
+    ('\u0C3C', '\u0C3C'),
+    ('\u0C3E', '\u0C44'),
+    ('\u0C46', '\u0C48'),
+    ('\u0C4A', '\u0C4D'),
+    ('\u0C55', '\u0C56'),
+    ('\u0C62', '\u0C63'),
+    ('\u0C81', '\u0C83'),
+    ('\u0CBC', '\u0CBC'),
+    ('\u0CBE', '\u0CC4'),
+    ('\u0CC6', '\u0CC8'),
+    ('\u0CCA', '\u0CCD'),
+    ('\u0CD5', '\u0CD6'),
+    ('\u0CE2', '\u0CE3'),
+    ('\u0D00', '\u0D03'),
+    ('\u0D3B', '\u0D3C'),
+    ('\u0D3E', '\u0D44'),
+    ('\u0D46', '\u0D48'),
+    ('\u0D4A', '\u0D4D'),
+    ('\u0D57', '\u0D57'),
+    ('\u0D62', '\u0D63'),
+    ('\u0D81', '\u0D83'),
+    ('\u0DCA', '\u0DCA'),
+    ('\u0DCF', '\u0DD4'),
+    ('\u0DD6', '\u0DD6'),
+    ('\u0DD8', '\u0DDF'),
+    ('\u0DF2', '\u0DF3'),
+    ('\u0E31', '\u0E31'),
+    ('\u0E34', '\u0E3A'),
+    ('\u0E47', '\u0E4E'),
+    ('\u0EB1', '\u0EB1'),
+    ('\u0EB4', '\u0EBC'),
+    ('\u0EC8', '\u0ECD'),
+    ('\u0F18', '\u0F19'),
+    ('\u0F35', '\u0F35'),
+    ('\u0F37', '\u0F37'),
+    ('\u0F39', '\u0F39'),
+    ('\u0F3E', '\u0F3F'),
+    ('\u0F71', '\u0F84'),
+    ('\u0F86', '\u0F87'),
+    ('\u0F8D', '\u0F97'),
+    ('\u0F99', '\u0FBC'),
+    ('\u0FC6', '\u0FC6'),
+    ('\u102B', '\u103E'),
+    ('\u1056', '\u1059'),
+    ('\u105E', '\u1060'),
+    ('\u1062', '\u1064'),
+    ('\u1067', '\u106D'),
+    ('\u1071', '\u1074'),
+    ('\u1082', '\u108D'),
+    ('\u108F', '\u108F'),
+    ('\u109A', '\u109D'),
+    ('\u135D', '\u135F'),
+    ('\u1712', '\u1715'),
+    ('\u1732', '\u1734'),
+    ('\u1752', '\u1753'),
+    ('\u1772', '\u1773'),
+    ('\u17B4', '\u17D3'),
+    ('\u17DD', '\u17DD'),
+    ('\u180B', '\u180D'),
+    ('\u180F', '\u180F'),
+    ('\u1885', '\u1886'),
+    ('\u18A9', '\u18A9'),
+    ('\u1920', '\u192B'),
+    ('\u1930', '\u193B'),
+    ('\u1A17', '\u1A1B'),
+    ('\u1A55', '\u1A5E'),
+    ('\u1A60', '\u1A7C'),
+    ('\u1A7F', '\u1A7F'),
+    ('\u1AB0', '\u1ACE'),
+    ('\u1B00', '\u1B04'),
+    ('\u1B34', '\u1B44'),
+    ('\u1B6B', '\u1B73'),
+    ('\u1B80', '\u1B82'),
+    ('\u1BA1', '\u1BAD'),
+    ('\u1BE6', '\u1BF3'),
+    ('\u1C24', '\u1C37'),
+    ('\u1CD0', '\u1CD2'),
+    ('\u1CD4', '\u1CE8'),
+    ('\u1CED', '\u1CED'),
+    ('\u1CF4', '\u1CF4'),
+    ('\u1CF7', '\u1CF9'),
+    ('\u1DC0', '\u1DFF'),
+    ('\u20D0', '\u20F0'),
+    ('\u2CEF', '\u2CF1'),
+    ('\u2D7F', '\u2D7F'),
+    ('\u2DE0', '\u2DFF'),
+    ('\u302A', '\u302F'),
+    ('\u3099', '\u309A'),
+    ('\uA66F', '\uA672'),
+    ('\uA674', '\uA67D'),
+    ('\uA69E', '\uA69F'),
+    ('\uA6F0', '\uA6F1'),
+    ('\uA802', '\uA802'),
+    ('\uA806', '\uA806'),
+    ('\uA80B', '\uA80B'),
+    ('\uA823', '\uA827'),
+    ('\uA82C', '\uA82C'),
+    ('\uA880', '\uA881'),
+    ('\uA8B4', '\uA8C5'),
+    ('\uA8E0', '\uA8F1'),
+    ('\uA8FF', '\uA8FF'),
+    ('\uA926', '\uA92D'),
+    ('\uA947', '\uA953'),
+    ('\uA980', '\uA983'),
+    ('\uA9B3', '\uA9C0'),
+    ('\uA9E5', '\uA9E5'),
+    ('\uAA29', '\uAA36'),
+    ('\uAA43', '\uAA43'),
+    ('\uAA4C', '\uAA4D'),
+    ('\uAA7B', '\uAA7D'),
+    ('\uAAB0', '\uAAB0'),
+    ('\uAAB2', '\uAAB4'),
+    ('\uAAB7', '\uAAB8'),
+    ('\uAABE', '\uAABF'),
+    ('\uAAC1', '\uAAC1'),
+    ('\uAAEB', '\uAAEF'),
+    ('\uAAF5', '\uAAF6'),
+    ('\uABE3', '\uABEA'),
+    ('\uABEC', '\uABED'),
+    ('\uFB1E', '\uFB1E'),
+    ('\uFE00', '\uFE0F'),
+    ('\uFE20', '\uFE2F'),
+    ('\U000101FD', '\U000101FD'),
+    ('\U000102E0', '\U000102E0'),
+    ('\U00010376', '\U0001037A'),
+    ('\U00010A01', '\U00010A03'),
+    ('\U00010A05', '\U00010A06'),
+    ('\U00010A0C', '\U00010A0F'),
+    ('\U00010A38', '\U00010A3A'),
+    ('\U00010A3F', '\U00010A3F'),
+    ('\U00010AE5', '\U00010AE6'),
+    ('\U00010D24', '\U00010D27'),
+    ('\U00010EAB', '\U00010EAC'),
+    ('\U00010F46', '\U00010F50'),
+    ('\U00010F82', '\U00010F85'),
+    ('\U00011000', '\U00011002'),
+    ('\U00011038', '\U00011046'),
+    ('\U00011070', '\U00011070'),
+    ('\U00011073', '\U00011074'),
+    ('\U0001107F', '\U00011082'),
+    ('\U000110B0', '\U000110BA'),
+    ('\U000110C2', '\U000110C2'),
+    ('\U00011100', '\U00011102'),
+    ('\U00011127', '\U00011134'),
+    ('\U00011145', '\U00011146'),
+    ('\U00011173', '\U00011173'),
+    ('\U00011180', '\U00011182'),
+    ('\U000111B3', '\U000111C0'),
+    ('\U000111C9', '\U000111CC'),
+    ('\U000111CE', '\U000111CF'),
+    ('\U0001122C', '\U00011237'),
+    ('\U0001123E', '\U0001123E'),
+    ('\U000112DF', '\U000112EA'),
+    ('\U00011300', '\U00011303'),
+    ('\U0001133B', '\U0001133C'),
+    ('\U0001133E', '\U00011344'),
+    ('\U00011347', '\U00011348'),
+    ('\U0001134B', '\U0001134D'),
+    ('\U00011357', '\U00011357'),
+    ('\U00011362', '\U00011363'),
+    ('\U00011366', '\U0001136C'),
+    ('\U00011370', '\U00011374'),
+    ('\U00011435', '\U00011446'),
+    ('\U0001145E', '\U0001145E'),
+    ('\U000114B0', '\U000114C3'),
+    ('\U000115AF', '\U000115B5'),
+    ('\U000115B8', '\U000115C0'),
+    ('\U000115DC', '\U000115DD'),
+    ('\U00011630', '\U00011640'),
+    ('\U000116AB', '\U000116B7'),
+    ('\U0001171D', '\U0001172B'),
+    ('\U0001182C', '\U0001183A'),
+    ('\U00011930', '\U00011935'),
+    ('\U00011937', '\U00011938'),
+    ('\U0001193B', '\U0001193E'),
+    ('\U00011940', '\U00011940'),
+    ('\U00011942', '\U00011943'),
+    ('\U000119D1', '\U000119D7'),
+    ('\U000119DA', '\U000119E0'),
+    ('\U000119E4', '\U000119E4'),
+    ('\U00011A01', '\U00011A0A'),
+    ('\U00011A33', '\U00011A39'),
+    ('\U00011A3B', '\U00011A3E'),
+    ('\U00011A47', '\U00011A47'),
+    ('\U00011A51', '\U00011A5B'),
+    ('\U00011A8A', '\U00011A99'),
+    ('\U00011C2F', '\U00011C36'),
+    ('\U00011C38', '\U00011C3F'),
+    ('\U00011C92', '\U00011CA7'),
+    ('\U00011CA9', '\U00011CB6'),
+    ('\U00011D31', '\U00011D36'),
+    ('\U00011D3A', '\U00011D3A'),
+    ('\U00011D3C', '\U00011D3D'),
+    ('\U00011D3F', '\U00011D45'),
+    ('\U00011D47', '\U00011D47'),
+    ('\U00011D8A', '\U00011D8E'),
+    ('\U00011D90', '\U00011D91'),
+    ('\U00011D93', '\U00011D97'),
+    ('\U00011EF3', '\U00011EF6'),
+    ('\U00016AF0', '\U00016AF4'),
+    ('\U00016B30', '\U00016B36'),
+    ('\U00016F4F', '\U00016F4F'),
+    ('\U00016F51', '\U00016F87'),
+    ('\U00016F8F', '\U00016F92'),
+    ('\U00016FE4', '\U00016FE4'),
+    ('\U00016FF0', '\U00016FF1'),
+    ('\U0001BC9D', '\U0001BC9E'),
+    ('\U0001CF00', '\U0001CF2D'),
+    ('\U0001CF30', '\U0001CF46'),
+    ('\U0001D165', '\U0001D169'),
+    ('\U0001D16D', '\U0001D172'),
+    ('\U0001D17B', '\U0001D182'),
+    ('\U0001D185', '\U0001D18B'),
+    ('\U0001D1AA', '\U0001D1AD'),
+    ('\U0001D242', '\U0001D244'),
+    ('\U0001DA00', '\U0001DA36'),
+    ('\U0001DA3B', '\U0001DA6C'),
+    ('\U0001DA75', '\U0001DA75'),
+    ('\U0001DA84', '\U0001DA84'),
+    ('\U0001DA9B', '\U0001DA9F'),
+    ('\U0001DAA1', '\U0001DAAF'),
+    ('\U0001E000', '\U0001E006'),
+    ('\U0001E008', '\U0001E018'),
+    ('\U0001E01B', '\U0001E021'),
+    ('\U0001E023', '\U0001E024'),
+    ('\U0001E026', '\U0001E02A'),
+    ('\U0001E130', '\U0001E136'),
+    ('\U0001E2AE', '\U0001E2AE'),
+    ('\U0001E2EC', '\U0001E2EF'),
+    ('\U0001E8D0', '\U0001E8D6'),
+    ('\U0001E944', '\U0001E94A'),
+    ('\U000E0100', '\U000E01EF')
+  ]),
+  ("mc", &[
+    ('\u0903', '\u0903'),
+    ('\u093B', '\u093B'),
+    ('\u093E', '\u0940'),
+    ('\u0949', '\u094C'),
+    ('\u094E', '\u094F'),
+    ('\u0982', '\u0983'),
+    ('\u09BE', '\u09C0'),
+    ('\u09C7', '\u09C8'),
+    ('\u09CB', '\u09CC'),
+    ('\u09D7', '\u09D7'),
+    ('\u0A03', '\u0A03'),
+    ('\u0A3E', '\u0A40'),
+    ('\u0A83', '\u0A83'),
+    ('\u0ABE', '\u0AC0'),
+    ('\u0AC9', '\u0AC9'),
+    ('\u0ACB', '\u0ACC'),
+    ('\u0B02', '\u0B03'),
+    ('\u0B3E', '\u0B3E'),
+    ('\u0B40', '\u0B40'),
+    ('\u0B47', '\u0B48'),
+    ('\u0B4B', '\u0B4C'),
+    ('\u0B57', '\u0B57'),
+    ('\u0BBE', '\u0BBF'),
+    ('\u0BC1', '\u0BC2'),
+    ('\u0BC6', '\u0BC8'),
+    ('\u0BCA', '\u0BCC'),
+    ('\u0BD7', '\u0BD7'),
+    ('\u0C01', '\u0C03'),
+    ('\u0C41', '\u0C44'),
+    ('\u0C82', '\u0C83'),
+    ('\u0CBE', '\u0CBE'),
+    ('\u0CC0', '\u0CC4'),
+    ('\u0CC7', '\u0CC8'),
+    ('\u0CCA', '\u0CCB'),
+    ('\u0CD5', '\u0CD6'),
+    ('\u0D02', '\u0D03'),
+    ('\u0D3E', '\u0D40'),
+    ('\u0D46', '\u0D48'),
+    ('\u0D4A', '\u0D4C'),
+    ('\u0D57', '\u0D57'),
+    ('\u0D82', '\u0D83'),
+    ('\u0DCF', '\u0DD1'),
+    ('\u0DD8', '\u0DDF'),
+    ('\u0DF2', '\u0DF3'),
+    ('\u0F3E', '\u0F3F'),
+    ('\u0F7F', '\u0F7F'),
+    ('\u102B', '\u102C'),
+    ('\u1031', '\u1031'),
+    ('\u1038', '\u1038'),
+    ('\u103B', '\u103C'),
+    ('\u1056', '\u1057'),
+    ('\u1062', '\u1064'),
+    ('\u1067', '\u106D'),
+    ('\u1083', '\u1084'),
+    ('\u1087', '\u108C'),
+    ('\u108F', '\u108F'),
+    ('\u109A', '\u109C'),
+    ('\u1715', '\u1715'),
+    ('\u1734', '\u1734'),
+    ('\u17B6', '\u17B6'),
+    ('\u17BE', '\u17C5'),
+    ('\u17C7', '\u17C8'),
+    ('\u1923', '\u1926'),
+    ('\u1929', '\u192B'),
+    ('\u1930', '\u1931'),
+    ('\u1933', '\u1938'),
+    ('\u1A19', '\u1A1A'),
+    ('\u1A55', '\u1A55'),
+    ('\u1A57', '\u1A57'),
+    ('\u1A61', '\u1A61'),
+    ('\u1A63', '\u1A64'),
+    ('\u1A6D', '\u1A72'),
+    ('\u1B04', '\u1B04'),
+    ('\u1B35', '\u1B35'),
+    ('\u1B3B', '\u1B3B'),
+    ('\u1B3D', '\u1B41'),
+    ('\u1B43', '\u1B44'),
+    ('\u1B82', '\u1B82'),
+    ('\u1BA1', '\u1BA1'),
+    ('\u1BA6', '\u1BA7'),
+    ('\u1BAA', '\u1BAA'),
+    ('\u1BE7', '\u1BE7'),
+    ('\u1BEA', '\u1BEC'),
+    ('\u1BEE', '\u1BEE'),
+    ('\u1BF2', '\u1BF3'),
+    ('\u1C24', '\u1C2B'),
+    ('\u1C34', '\u1C35'),
+    ('\u1CE1', '\u1CE1'),
+    ('\u1CF7', '\u1CF7'),
+    ('\u302E', '\u302F'),
+    ('\uA823', '\uA824'),
+    ('\uA827', '\uA827'),
+    ('\uA880', '\uA881'),
+    ('\uA8B4', '\uA8C3'),
+    ('\uA952', '\uA953'),
+    ('\uA983', '\uA983'),
+    ('\uA9B4', '\uA9B5'),
+    ('\uA9BA', '\uA9BB'),
+    ('\uA9BE', '\uA9C0'),
+    ('\uAA2F', '\uAA30'),
+    ('\uAA33', '\uAA34'),
+    ('\uAA4D', '\uAA4D'),
+    ('\uAA7B', '\uAA7B'),
+    ('\uAA7D', '\uAA7D'),
+    ('\uAAEB', '\uAAEB'),
+    ('\uAAEE', '\uAAEF'),
+    ('\uAAF5', '\uAAF5'),
+    ('\uABE3', '\uABE4'),
+    ('\uABE6', '\uABE7'),
+    ('\uABE9', '\uABEA'),
+    ('\uABEC', '\uABEC'),
+    ('\U00011000', '\U00011000'),
+    ('\U00011002', '\U00011002'),
+    ('\U00011082', '\U00011082'),
+    ('\U000110B0', '\U000110B2'),
+    ('\U000110B7', '\U000110B8'),
+    ('\U0001112C', '\U0001112C'),
+    ('\U00011145', '\U00011146'),
+    ('\U00011182', '\U00011182'),
+    ('\U000111B3', '\U000111B5'),
+    ('\U000111BF', '\U000111C0'),
+    ('\U000111CE', '\U000111CE'),
+    ('\U0001122C', '\U0001122E'),
+    ('\U00011232', '\U00011233'),
+    ('\U00011235', '\U00011235'),
+    ('\U000112E0', '\U000112E2'),
+    ('\U00011302', '\U00011303'),
+    ('\U0001133E', '\U0001133F'),
+    ('\U00011341', '\U00011344'),
+    ('\U00011347', '\U00011348'),
+    ('\U0001134B', '\U0001134D'),
+    ('\U00011357', '\U00011357'),
+    ('\U00011362', '\U00011363'),
+    ('\U00011435', '\U00011437'),
+    ('\U00011440', '\U00011441'),
+    ('\U00011445', '\U00011445'),
+    ('\U000114B0', '\U000114B2'),
+    ('\U000114B9', '\U000114B9'),
+    ('\U000114BB', '\U000114BE'),
+    ('\U000114C1', '\U000114C1'),
+    ('\U000115AF', '\U000115B1'),
+    ('\U000115B8', '\U000115BB'),
+    ('\U000115BE', '\U000115BE'),
+    ('\U00011630', '\U00011632'),
+    ('\U0001163B', '\U0001163C'),
+    ('\U0001163E', '\U0001163E'),
+    ('\U000116AC', '\U000116AC'),
+    ('\U000116AE', '\U000116AF'),
+    ('\U000116B6', '\U000116B6'),
+    ('\U00011720', '\U00011721'),
+    ('\U00011726', '\U00011726'),
+    ('\U0001182C', '\U0001182E'),
+    ('\U00011838', '\U00011838'),
+    ('\U00011930', '\U00011935'),
+    ('\U00011937', '\U00011938'),
+    ('\U0001193D', '\U0001193D'),
+    ('\U00011940', '\U00011940'),
+    ('\U00011942', '\U00011942'),
+    ('\U000119D1', '\U000119D3'),
+    ('\U000119DC', '\U000119DF'),
+    ('\U000119E4', '\U000119E4'),
+    ('\U00011A39', '\U00011A39'),
+    ('\U00011A57', '\U00011A58'),
+    ('\U00011A97', '\U00011A97'),
+    ('\U00011C2F', '\U00011C2F'),
+    ('\U00011C3E', '\U00011C3E'),
+    ('\U00011CA9', '\U00011CA9'),
+    ('\U00011CB1', '\U00011CB1'),
+    ('\U00011CB4', '\U00011CB4'),
+    ('\U00011D8A', '\U00011D8E'),
+    ('\U00011D93', '\U00011D94'),
+    ('\U00011D96', '\U00011D96'),
+    ('\U00011EF5', '\U00011EF6'),
+    ('\U00016F51', '\U00016F87'),
+    ('\U00016FF0', '\U00016FF1'),
+    ('\U0001D165', '\U0001D166'),
+    ('\U0001D16D', '\U0001D172')
+  ]),
+  ("me", &[
+    ('\u0488', '\u0489'),
+    ('\u1ABE', '\u1ABE'),
+    ('\u20DD', '\u20E0'),
+    ('\u20E2', '\u20E4'),
+    ('\uA670', '\uA672')
+  ]),
+  ("mn", &[
+    ('\u0300', '\u036F'),
+    ('\u0483', '\u0487'),
+    ('\u0591', '\u05BD'),
+    ('\u05BF', '\u05BF'),
+    ('\u05C1', '\u05C2'),
+    ('\u05C4', '\u05C5'),
+    ('\u05C7', '\u05C7'),
+    ('\u0610', '\u061A'),
+    ('\u064B', '\u065F'),
+    ('\u0670', '\u0670'),
+    ('\u06D6', '\u06DC'),
+    ('\u06DF', '\u06E4'),
+    ('\u06E7', '\u06E8'),
+    ('\u06EA', '\u06ED'),
+    ('\u0711', '\u0711'),
+    ('\u0730', '\u074A'),
+    ('\u07A6', '\u07B0'),
+    ('\u07EB', '\u07F3'),
+    ('\u07FD', '\u07FD'),
+    ('\u0816', '\u0819'),
+    ('\u081B', '\u0823'),
+    ('\u0825', '\u0827'),
+    ('\u0829', '\u082D'),
+    ('\u0859', '\u085B'),
+    ('\u0898', '\u089F'),
+    ('\u08CA', '\u08E1'),
+    ('\u08E3', '\u0902'),
+    ('\u093A', '\u093A'),
+    ('\u093C', '\u093C'),
+    ('\u0941', '\u0948'),
+    ('\u094D', '\u094D'),
+    ('\u0951', '\u0957'),
+    ('\u0962', '\u0963'),
+    ('\u0981', '\u0981'),
+    ('\u09BC', '\u09BC'),
+    ('\u09C1', '\u09C4'),
+    ('\u09CD', '\u09CD'),
+    ('\u09E2', '\u09E3'),
+    ('\u09FE', '\u09FE'),
+    ('\u0A01', '\u0A02'),
+    ('\u0A3C', '\u0A3C'),
+    ('\u0A41', '\u0A42'),
+    ('\u0A47', '\u0A48'),
+    ('\u0A4B', '\u0A4D'),
+    ('\u0A51', '\u0A51'),
+    ('\u0A70', '\u0A71'),
+    ('\u0A75', '\u0A75'),
+    ('\u0A81', '\u0A82'),
+    ('\u0ABC', '\u0ABC'),
+    ('\u0AC1', '\u0AC5'),
+    ('\u0AC7', '\u0AC8'),
+    ('\u0ACD', '\u0ACD'),
+    ('\u0AE2', '\u0AE3'),
+    ('\u0AFA', '\u0AFF'),
+    ('\u0B01', '\u0B01'),
+    ('\u0B3C', '\u0B3C'),
+    ('\u0B3F', '\u0B3F'),
+    ('\u0B41', '\u0B44'),
+    ('\u0B4D', '\u0B4D'),
+    ('\u0B55', '\u0B56'),
+    ('\u0B62', '\u0B63'),
+    ('\u0B82', '\u0B82'),
+    ('\u0BC0', '\u0BC0'),
+    ('\u0BCD', '\u0BCD'),
+    ('\u0C00', '\u0C00'),
+    ('\u0C04', '\u0C04'),
+    ('\u0C3C', '\u0C3C'),
+    ('\u0C3E', '\u0C40'),
+    ('\u0C46', '\u0C48'),
+    ('\u0C4A', '\u0C4D'),
+    ('\u0C55', '\u0C56'),
+    ('\u0C62', '\u0C63'),
+    ('\u0C81', '\u0C81'),
+    ('\u0CBC', '\u0CBC'),
+    ('\u0CBF', '\u0CBF'),
+    ('\u0CC6', '\u0CC6'),
+    ('\u0CCC', '\u0CCD'),
+    ('\u0CE2', '\u0CE3'),
+    ('\u0D00', '\u0D01'),
+    ('\u0D3B', '\u0D3C'),
+    ('\u0D41', '\u0D44'),
+    ('\u0D4D', '\u0D4D'),
+    ('\u0D62', '\u0D63'),
+    ('\u0D81', '\u0D81'),
+    ('\u0DCA', '\u0DCA'),
+    ('\u0DD2', '\u0DD4'),
+    ('\u0DD6', '\u0DD6'),
+    ('\u0E31', '\u0E31'),
+    ('\u0E34', '\u0E3A'),
+    ('\u0E47', '\u0E4E'),
+    ('\u0EB1', '\u0EB1'),
+    ('\u0EB4', '\u0EBC'),
+    ('\u0EC8', '\u0ECD'),
+    ('\u0F18', '\u0F19'),
+    ('\u0F35', '\u0F35'),
+    ('\u0F37', '\u0F37'),
+    ('\u0F39', '\u0F39'),
+    ('\u0F71', '\u0F7E'),
+    ('\u0F80', '\u0F84'),
+    ('\u0F86', '\u0F87'),
+    ('\u0F8D', '\u0F97'),
+    ('\u0F99', '\u0FBC'),
+    ('\u0FC6', '\u0FC6'),
+    ('\u102D', '\u1030'),
+    ('\u1032', '\u1037'),
+    ('\u1039', '\u103A'),
+    ('\u103D', '\u103E'),
+    ('\u1058', '\u1059'),
+    ('\u105E', '\u1060'),
+    ('\u1071', '\u1074'),
+    ('\u1082', '\u1082'),
+    ('\u1085', '\u1086'),
+    ('\u108D', '\u108D'),
+    ('\u109D', '\u109D'),
+    ('\u135D', '\u135F'),
+    ('\u1712', '\u1714'),
+    ('\u1732', '\u1733'),
+    ('\u1752', '\u1753'),
+    ('\u1772', '\u1773'),
+    ('\u17B4', '\u17B5'),
+    ('\u17B7', '\u17BD'),
+    ('\u17C6', '\u17C6'),
+    ('\u17C9', '\u17D3'),
+    ('\u17DD', '\u17DD'),
+    ('\u180B', '\u180D'),
+    ('\u180F', '\u180F'),
+    ('\u1885', '\u1886'),
+    ('\u18A9', '\u18A9'),
+    ('\u1920', '\u1922'),
+    ('\u1927', '\u1928'),
+    ('\u1932', '\u1932'),
+    ('\u1939', '\u193B'),
+    ('\u1A17', '\u1A18'),
+    ('\u1A1B', '\u1A1B'),
+    ('\u1A56', '\u1A56'),
+    ('\u1A58', '\u1A5E'),
+    ('\u1A60', '\u1A60'),
+    ('\u1A62', '\u1A62'),
+    ('\u1A65', '\u1A6C'),
+    ('\u1A73', '\u1A7C'),
+    ('\u1A7F', '\u1A7F'),
+    ('\u1AB0', '\u1ABD'),
+    ('\u1ABF', '\u1ACE'),
+    ('\u1B00', '\u1B03'),
+    ('\u1B34', '\u1B34'),
+    ('\u1B36', '\u1B3A'),
+    ('\u1B3C', '\u1B3C'),
+    ('\u1B42', '\u1B42'),
+    ('\u1B6B', '\u1B73'),
+    ('\u1B80', '\u1B81'),
+    ('\u1BA2', '\u1BA5'),
+    ('\u1BA8', '\u1BA9'),
+    ('\u1BAB', '\u1BAD'),
+    ('\u1BE6', '\u1BE6'),
+    ('\u1BE8', '\u1BE9'),
+    ('\u1BED', '\u1BED'),
+    ('\u1BEF', '\u1BF1'),
+    ('\u1C2C', '\u1C33'),
+    ('\u1C36', '\u1C37'),
+    ('\u1CD0', '\u1CD2'),
+    ('\u1CD4', '\u1CE0'),
+    ('\u1CE2', '\u1CE8'),
+    ('\u1CED', '\u1CED'),
+    ('\u1CF4', '\u1CF4'),
+    ('\u1CF8', '\u1CF9'),
+    ('\u1DC0', '\u1DFF'),
+    ('\u20D0', '\u20DC'),
+    ('\u20E1', '\u20E1'),
+    ('\u20E5', '\u20F0'),
+    ('\u2CEF', '\u2CF1'),
+    ('\u2D7F', '\u2D7F'),
+    ('\u2DE0', '\u2DFF'),
+    ('\u302A', '\u302D'),
+    ('\u3099', '\u309A'),
+    ('\uA66F', '\uA66F'),
+    ('\uA674', '\uA67D'),
+    ('\uA69E', '\uA69F'),
+    ('\uA6F0', '\uA6F1'),
+    ('\uA802', '\uA802'),
+    ('\uA806', '\uA806'),
+    ('\uA80B', '\uA80B'),
+    ('\uA825', '\uA826'),
+    ('\uA82C', '\uA82C'),
+    ('\uA8C4', '\uA8C5'),
+    ('\uA8E0', '\uA8F1'),
+    ('\uA8FF', '\uA8FF'),
+    ('\uA926', '\uA92D'),
+    ('\uA947', '\uA951'),
+    ('\uA980', '\uA982'),
+    ('\uA9B3', '\uA9B3'),
+    ('\uA9B6', '\uA9B9'),
+    ('\uA9BC', '\uA9BD'),
+    ('\uA9E5', '\uA9E5'),
+    ('\uAA29', '\uAA2E'),
+    ('\uAA31', '\uAA32'),
+    ('\uAA35', '\uAA36'),
+    ('\uAA43', '\uAA43'),
+    ('\uAA4C', '\uAA4C'),
+    ('\uAA7C', '\uAA7C'),
+    ('\uAAB0', '\uAAB0'),
+    ('\uAAB2', '\uAAB4'),
+    ('\uAAB7', '\uAAB8'),
+    ('\uAABE', '\uAABF'),
+    ('\uAAC1', '\uAAC1'),
+    ('\uAAEC', '\uAAED'),
+    ('\uAAF6', '\uAAF6'),
+    ('\uABE5', '\uABE5'),
+    ('\uABE8', '\uABE8'),
+    ('\uABED', '\uABED'),
+    ('\uFB1E', '\uFB1E'),
+    ('\uFE00', '\uFE0F'),
+    ('\uFE20', '\uFE2F'),
+    ('\U000101FD', '\U000101FD'),
+    ('\U000102E0', '\U000102E0'),
+    ('\U00010376', '\U0001037A'),
+    ('\U00010A01', '\U00010A03'),
+    ('\U00010A05', '\U00010A06'),
+    ('\U00010A0C', '\U00010A0F'),
+    ('\U00010A38', '\U00010A3A'),
+    ('\U00010A3F', '\U00010A3F'),
+    ('\U00010AE5', '\U00010AE6'),
+    ('\U00010D24', '\U00010D27'),
+    ('\U00010EAB', '\U00010EAC'),
+    ('\U00010F46', '\U00010F50'),
+    ('\U00010F82', '\U00010F85'),
+    ('\U00011001', '\U00011001'),
+    ('\U00011038', '\U00011046'),
+    ('\U00011070', '\U00011070'),
+    ('\U00011073', '\U00011074'),
+    ('\U0001107F', '\U00011081'),
+    ('\U000110B3', '\U000110B6'),
+    ('\U000110B9', '\U000110BA'),
+    ('\U000110C2', '\U000110C2'),
+    ('\U00011100', '\U00011102'),
+    ('\U00011127', '\U0001112B'),
+    ('\U0001112D', '\U00011134'),
+    ('\U00011173', '\U00011173'),
+    ('\U00011180', '\U00011181'),
+    ('\U000111B6', '\U000111BE'),
+    ('\U000111C9', '\U000111CC'),
+    ('\U000111CF', '\U000111CF'),
+    ('\U0001122F', '\U00011231'),
+    ('\U00011234', '\U00011234'),
+    ('\U00011236', '\U00011237'),
+    ('\U0001123E', '\U0001123E'),
+    ('\U000112DF', '\U000112DF'),
+    ('\U000112E3', '\U000112EA'),
+    ('\U00011300', '\U00011301'),
+    ('\U0001133B', '\U0001133C'),
+    ('\U00011340', '\U00011340'),
+    ('\U00011366', '\U0001136C'),
+    ('\U00011370', '\U00011374'),
+    ('\U00011438', '\U0001143F'),
+    ('\U00011442', '\U00011444'),
+    ('\U00011446', '\U00011446'),
+    ('\U0001145E', '\U0001145E'),
+    ('\U000114B3', '\U000114B8'),
+    ('\U000114BA', '\U000114BA'),
+    ('\U000114BF', '\U000114C0'),
+    ('\U000114C2', '\U000114C3'),
+    ('\U000115B2', '\U000115B5'),
+    ('\U000115BC', '\U000115BD'),
+    ('\U000115BF', '\U000115C0'),
+    ('\U000115DC', '\U000115DD'),
+    ('\U00011633', '\U0001163A'),
+    ('\U0001163D', '\U0001163D'),
+    ('\U0001163F', '\U00011640'),
+    ('\U000116AB', '\U000116AB'),
+    ('\U000116AD', '\U000116AD'),
+    ('\U000116B0', '\U000116B5'),
+    ('\U000116B7', '\U000116B7'),
+    ('\U0001171D', '\U0001171F'),
+    ('\U00011722', '\U00011725'),
+    ('\U00011727', '\U0001172B'),
+    ('\U0001182F', '\U00011837'),
+    ('\U00011839', '\U0001183A'),
+    ('\U0001193B', '\U0001193C'),
+    ('\U0001193E', '\U0001193E'),
+    ('\U00011943', '\U00011943'),
+    ('\U000119D4', '\U000119D7'),
+    ('\U000119DA', '\U000119DB'),
+    ('\U000119E0', '\U000119E0'),
+    ('\U00011A01', '\U00011A0A'),
+    ('\U00011A33', '\U00011A38'),
+    ('\U00011A3B', '\U00011A3E'),
+    ('\U00011A47', '\U00011A47'),
+    ('\U00011A51', '\U00011A56'),
+    ('\U00011A59', '\U00011A5B'),
+    ('\U00011A8A', '\U00011A96'),
+    ('\U00011A98', '\U00011A99'),
+    ('\U00011C30', '\U00011C36'),
+    ('\U00011C38', '\U00011C3D'),
+    ('\U00011C3F', '\U00011C3F'),
+    ('\U00011C92', '\U00011CA7'),
+    ('\U00011CAA', '\U00011CB0'),
+    ('\U00011CB2', '\U00011CB3'),
+    ('\U00011CB5', '\U00011CB6'),
+    ('\U00011D31', '\U00011D36'),
+    ('\U00011D3A', '\U00011D3A'),
+    ('\U00011D3C', '\U00011D3D'),
+    ('\U00011D3F', '\U00011D45'),
+    ('\U00011D47', '\U00011D47'),
+    ('\U00011D90', '\U00011D91'),
+    ('\U00011D95', '\U00011D95'),
+    ('\U00011D97', '\U00011D97'),
+    ('\U00011EF3', '\U00011EF4'),
+    ('\U00016AF0', '\U00016AF4'),
+    ('\U00016B30', '\U00016B36'),
+    ('\U00016F4F', '\U00016F4F'),
+    ('\U00016F8F', '\U00016F92'),
+    ('\U00016FE4', '\U00016FE4'),
+    ('\U0001BC9D', '\U0001BC9E'),
+    ('\U0001CF00', '\U0001CF2D'),
+    ('\U0001CF30', '\U0001CF46'),
+    ('\U0001D167', '\U0001D169'),
+    ('\U0001D17B', '\U0001D182'),
+    ('\U0001D185', '\U0001D18B'),
+    ('\U0001D1AA', '\U0001D1AD'),
+    ('\U0001D242', '\U0001D244'),
+    ('\U0001DA00', '\U0001DA36'),
+    ('\U0001DA3B', '\U0001DA6C'),
+    ('\U0001DA75', '\U0001DA75'),
+    ('\U0001DA84', '\U0001DA84'),
+    ('\U0001DA9B', '\U0001DA9F'),
+    ('\U0001DAA1', '\U0001DAAF'),
+    ('\U0001E000', '\U0001E006'),
+    ('\U0001E008', '\U0001E018'),
+    ('\U0001E01B', '\U0001E021'),
+    ('\U0001E023', '\U0001E024'),
+    ('\U0001E026', '\U0001E02A'),
+    ('\U0001E130', '\U0001E136'),
+    ('\U0001E2AE', '\U0001E2AE'),
+    ('\U0001E2EC', '\U0001E2EF'),
+    ('\U0001E8D0', '\U0001E8D6'),
+    ('\U0001E944', '\U0001E94A'),
+    ('\U000E0100', '\U000E01EF')
+  ]),
+  ("n", &[
+    ('\u0030', '\u0039'),
+    ('\u00B2', '\u00B3'),
+    ('\u00B9', '\u00B9'),
+    ('\u00BC', '\u00BE'),
+    ('\u0660', '\u0669'),
+    ('\u06F0', '\u06F9'),
+    ('\u07C0', '\u07C9'),
+    ('\u0966', '\u096F'),
+    ('\u09E6', '\u09EF'),
+    ('\u09F4', '\u09F9'),
+    ('\u0A66', '\u0A6F'),
+    ('\u0AE6', '\u0AEF'),
+    ('\u0B66', '\u0B6F'),
+    ('\u0B72', '\u0B77'),
+    ('\u0BE6', '\u0BF2'),
+    ('\u0C66', '\u0C6F'),
+    ('\u0C78', '\u0C7E'),
+    ('\u0CE6', '\u0CEF'),
+    ('\u0D58', '\u0D5E'),
+    ('\u0D66', '\u0D78'),
+    ('\u0DE6', '\u0DEF'),
+    ('\u0E50', '\u0E59'),
+    ('\u0ED0', '\u0ED9'),
+    ('\u0F20', '\u0F33'),
+    ('\u1040', '\u1049'),
+    ('\u1090', '\u1099'),
+    ('\u1369', '\u137C'),
+    ('\u16EE', '\u16F0'),
+    ('\u17E0', '\u17E9'),
+    ('\u17F0', '\u17F9'),
+    ('\u1810', '\u1819'),
+    ('\u1946', '\u194F'),
+    ('\u19D0', '\u19DA'),
+    ('\u1A80', '\u1A89'),
+    ('\u1A90', '\u1A99'),
+    ('\u1B50', '\u1B59'),
+    ('\u1BB0', '\u1BB9'),
+    ('\u1C40', '\u1C49'),
+    ('\u1C50', '\u1C59'),
+    ('\u2070', '\u2070'),
+    ('\u2074', '\u2079'),
+    ('\u2080', '\u2089'),
+    ('\u2150', '\u2182'),
+    ('\u2185', '\u2189'),
+    ('\u2460', '\u249B'),
+    ('\u24EA', '\u24FF'),
+    ('\u2776', '\u2793'),
+    ('\u2CFD', '\u2CFD'),
+    ('\u3007', '\u3007'),
+    ('\u3021', '\u3029'),
+    ('\u3038', '\u303A'),
+    ('\u3192', '\u3195'),
+    ('\u3220', '\u3229'),
+    ('\u3248', '\u324F'),
+    ('\u3251', '\u325F'),
+    ('\u3280', '\u3289'),
+    ('\u32B1', '\u32BF'),
+    ('\uA620', '\uA629'),
+    ('\uA6E6', '\uA6EF'),
+    ('\uA830', '\uA835'),
+    ('\uA8D0', '\uA8D9'),
+    ('\uA900', '\uA909'),
+    ('\uA9D0', '\uA9D9'),
+    ('\uA9F0', '\uA9F9'),
+    ('\uAA50', '\uAA59'),
+    ('\uABF0', '\uABF9'),
+    ('\uFF10', '\uFF19'),
+    ('\U00010107', '\U00010133'),
+    ('\U00010140', '\U00010178'),
+    ('\U0001018A', '\U0001018B'),
+    ('\U000102E1', '\U000102FB'),
+    ('\U00010320', '\U00010323'),
+    ('\U00010341', '\U00010341'),
+    ('\U0001034A', '\U0001034A'),
+    ('\U000103D1', '\U000103D5'),
+    ('\U000104A0', '\U000104A9'),
+    ('\U00010858', '\U0001085F'),
+    ('\U00010879', '\U0001087F'),
+    ('\U000108A7', '\U000108AF'),
+    ('\U000108FB', '\U000108FF'),
+    ('\U00010916', '\U0001091B'),
+    ('\U000109BC', '\U000109BD'),
+    ('\U000109C0', '\U000109CF'),
+    ('\U000109D2', '\U000109FF'),
+    ('\U00010A40', '\U00010A48'),
+    ('\U00010A7D', '\U00010A7E'),
+    ('\U00010A9D', '\U00010A9F'),
+    ('\U00010AEB', '\U00010AEF'),
+    ('\U00010B58', '\U00010B5F'),
+    ('\U00010B78', '\U00010B7F'),
+    ('\U00010BA9', '\U00010BAF'),
+    ('\U00010CFA', '\U00010CFF'),
+    ('\U00010D30', '\U00010D39'),
+    ('\U00010E60', '\U00010E7E'),
+    ('\U00010F1D', '\U00010F26'),
+    ('\U00010F51', '\U00010F54'),
+    ('\U00010FC5', '\U00010FCB'),
+    ('\U00011052', '\U0001106F'),
+    ('\U000110F0', '\U000110F9'),
+    ('\U00011136', '\U0001113F'),
+    ('\U000111D0', '\U000111D9'),
+    ('\U000111E1', '\U000111F4'),
+    ('\U000112F0', '\U000112F9'),
+    ('\U00011450', '\U00011459'),
+    ('\U000114D0', '\U000114D9'),
+    ('\U00011650', '\U00011659'),
+    ('\U000116C0', '\U000116C9'),
+    ('\U00011730', '\U0001173B'),
+    ('\U000118E0', '\U000118F2'),
+    ('\U00011950', '\U00011959'),
+    ('\U00011C50', '\U00011C6C'),
+    ('\U00011D50', '\U00011D59'),
+    ('\U00011DA0', '\U00011DA9'),
+    ('\U00011FC0', '\U00011FD4'),
+    ('\U00012400', '\U0001246E'),
+    ('\U00016A60', '\U00016A69'),
+    ('\U00016AC0', '\U00016AC9'),
+    ('\U00016B50', '\U00016B59'),
+    ('\U00016B5B', '\U00016B61'),
+    ('\U00016E80', '\U00016E96'),
+    ('\U0001D2E0', '\U0001D2F3'),
+    ('\U0001D360', '\U0001D378'),
+    ('\U0001D7CE', '\U0001D7FF'),
+    ('\U0001E140', '\U0001E149'),
+    ('\U0001E2F0', '\U0001E2F9'),
+    ('\U0001E8C7', '\U0001E8CF'),
+    ('\U0001E950', '\U0001E959'),
+    ('\U0001EC71', '\U0001ECAB'),
+    ('\U0001ECAD', '\U0001ECAF'),
+    ('\U0001ECB1', '\U0001ECB4'),
+    ('\U0001ED01', '\U0001ED2D'),
+    ('\U0001ED2F', '\U0001ED3D'),
+    ('\U0001F100', '\U0001F10C'),
+    ('\U0001FBF0', '\U0001FBF9')
+  ]),
+  ("nd", &[
+    ('\u0030', '\u0039'),
+    ('\u0660', '\u0669'),
+    ('\u06F0', '\u06F9'),
+    ('\u07C0', '\u07C9'),
+    ('\u0966', '\u096F'),
+    ('\u09E6', '\u09EF'),
+    ('\u0A66', '\u0A6F'),
+    ('\u0AE6', '\u0AEF'),
+    ('\u0B66', '\u0B6F'),
+    ('\u0BE6', '\u0BEF'),
+    ('\u0C66', '\u0C6F'),
+    ('\u0CE6', '\u0CEF'),
+    ('\u0D66', '\u0D6F'),
+    ('\u0DE6', '\u0DEF'),
+    ('\u0E50', '\u0E59'),
+    ('\u0ED0', '\u0ED9'),
+    ('\u0F20', '\u0F29'),
+    ('\u1040', '\u1049'),
+    ('\u1090', '\u1099'),
+    ('\u17E0', '\u17E9'),
+    ('\u1810', '\u1819'),
+    ('\u1946', '\u194F'),
+    ('\u19D0', '\u19D9'),
+    ('\u1A80', '\u1A89'),
+    ('\u1A90', '\u1A99'),
+    ('\u1B50', '\u1B59'),
+    ('\u1BB0', '\u1BB9'),
+    ('\u1C40', '\u1C49'),
+    ('\u1C50', '\u1C59'),
+    ('\uA620', '\uA629'),
+    ('\uA8D0', '\uA8D9'),
+    ('\uA900', '\uA909'),
+    ('\uA9D0', '\uA9D9'),
+    ('\uA9F0', '\uA9F9'),
+    ('\uAA50', '\uAA59'),
+    ('\uABF0', '\uABF9'),
+    ('\uFF10', '\uFF19'),
+    ('\U000104A0', '\U000104A9'),
+    ('\U00010D30', '\U00010D39'),
+    ('\U00011066', '\U0001106F'),
+    ('\U000110F0', '\U000110F9'),
+    ('\U00011136', '\U0001113F'),
+    ('\U000111D0', '\U000111D9'),
+    ('\U000112F0', '\U000112F9'),
+    ('\U00011450', '\U00011459'),
+    ('\U000114D0', '\U000114D9'),
+    ('\U00011650', '\U00011659'),
+    ('\U000116C0', '\U000116C9'),
+    ('\U00011730', '\U00011739'),
+    ('\U000118E0', '\U000118E9'),
+    ('\U00011950', '\U00011959'),
+    ('\U00011C50', '\U00011C59'),
+    ('\U00011D50', '\U00011D59'),
+    ('\U00011DA0', '\U00011DA9'),
+    ('\U00016A60', '\U00016A69'),
+    ('\U00016AC0', '\U00016AC9'),
+    ('\U00016B50', '\U00016B59'),
+    ('\U0001D7CE', '\U0001D7FF'),
+    ('\U0001E140', '\U0001E149'),
+    ('\U0001E2F0', '\U0001E2F9'),
+    ('\U0001E950', '\U0001E959'),
+    ('\U0001FBF0', '\U0001FBF9')
+  ]),
+  ("nl", &[
+    ('\u16EE', '\u16F0'),
+    ('\u2160', '\u2182'),
+    ('\u2185', '\u2188'),
+    ('\u3007', '\u3007'),
+    ('\u3021', '\u3029'),
+    ('\u3038', '\u303A'),
+    ('\uA6E6', '\uA6EF'),
+    ('\U00010140', '\U00010174'),
+    ('\U00010341', '\U00010341'),
+    ('\U0001034A', '\U0001034A'),
+    ('\U000103D1', '\U000103D5'),
+    ('\U00012400', '\U0001246E')
+  ]),
+  ("no", &[
+    ('\u00B2', '\u00B3'),
+    ('\u00B9', '\u00B9'),
+    ('\u00BC', '\u00BE'),
+    ('\u09F4', '\u09F9'),
+    ('\u0B72', '\u0B77'),
+    ('\u0BF0', '\u0BF2'),
+    ('\u0C78', '\u0C7E'),
+    ('\u0D58', '\u0D5E'),
+    ('\u0D70', '\u0D78'),
+    ('\u0F2A', '\u0F33'),
+    ('\u1369', '\u137C'),
+    ('\u17F0', '\u17F9'),
+    ('\u19DA', '\u19DA'),
+    ('\u2070', '\u2070'),
+    ('\u2074', '\u2079'),
+    ('\u2080', '\u2089'),
+    ('\u2150', '\u215F'),
+    ('\u2189', '\u2189'),
+    ('\u2460', '\u249B'),
+    ('\u24EA', '\u24FF'),
+    ('\u2776', '\u2793'),
+    ('\u2CFD', '\u2CFD'),
+    ('\u3192', '\u3195'),
+    ('\u3220', '\u3229'),
+    ('\u3248', '\u324F'),
+    ('\u3251', '\u325F'),
+    ('\u3280', '\u3289'),
+    ('\u32B1', '\u32BF'),
+    ('\uA830', '\uA835'),
+    ('\U00010107', '\U00010133'),
+    ('\U00010175', '\U00010178'),
+    ('\U0001018A', '\U0001018B'),
+    ('\U000102E1', '\U000102FB'),
+    ('\U00010320', '\U00010323'),
+    ('\U00010858', '\U0001085F'),
+    ('\U00010879', '\U0001087F'),
+    ('\U000108A7', '\U000108AF'),
+    ('\U000108FB', '\U000108FF'),
+    ('\U00010916', '\U0001091B'),
+    ('\U000109BC', '\U000109BD'),
+    ('\U000109C0', '\U000109CF'),
+    ('\U000109D2', '\U000109FF'),
+    ('\U00010A40', '\U00010A48'),
+    ('\U00010A7D', '\U00010A7E'),
+    ('\U00010A9D', '\U00010A9F'),
+    ('\U00010AEB', '\U00010AEF'),
+    ('\U00010B58', '\U00010B5F'),
+    ('\U00010B78', '\U00010B7F'),
+    ('\U00010BA9', '\U00010BAF'),
+    ('\U00010CFA', '\U00010CFF'),
+    ('\U00010E60', '\U00010E7E'),
+    ('\U00010F1D', '\U00010F26'),
+    ('\U00010F51', '\U00010F54'),
+    ('\U00010FC5', '\U00010FCB'),
+    ('\U00011052', '\U00011065'),
+    ('\U000111E1', '\U000111F4'),
+    ('\U0001173A', '\U0001173B'),
+    ('\U000118EA', '\U000118F2'),
+    ('\U00011C5A', '\U00011C6C'),
+    ('\U00011FC0', '\U00011FD4'),
+    ('\U00016B5B', '\U00016B61'),
+    ('\U00016E80', '\U00016E96'),
+    ('\U0001D2E0', '\U0001D2F3'),
+    ('\U0001D360', '\U0001D378'),
+    ('\U0001E8C7', '\U0001E8CF'),
+    ('\U0001EC71', '\U0001ECAB'),
+    ('\U0001ECAD', '\U0001ECAF'),
+    ('\U0001ECB1', '\U0001ECB4'),
+    ('\U0001ED01', '\U0001ED2D'),
+    ('\U0001ED2F', '\U0001ED3D'),
+    ('\U0001F100', '\U0001F10C')
+  ]),
+  ("p", &[
+    ('\u0021', '\u0023'),
+    ('\u0025', '\u002A'),
+    ('\u002C', '\u002F'),
+    ('\u003A', '\u003B'),
+    ('\u003F', '\u0040'),
+    ('\u005B', '\u005D'),
+    ('\u005F', '\u005F'),
+    ('\u007B', '\u007B'),
+    ('\u007D', '\u007D'),
+    ('\u00A1', '\u00A1'),
+    ('\u00A7', '\u00A7'),
+    ('\u00AB', '\u00AB'),
+    ('\u00B6', '\u00B7'),
+    ('\u00BB', '\u00BB'),
+    ('\u00BF', '\u00BF'),
+    ('\u037E', '\u037E'),
+    ('\u0387', '\u0387'),
+    ('\u055A', '\u055F'),
+    ('\u0589', '\u058A'),
+    ('\u05BE', '\u05BE'),
+    ('\u05C0', '\u05C0'),
+    ('\u05C3', '\u05C3'),
+    ('\u05C6', '\u05C6'),
+    ('\u05F3', '\u05F4'),
+    ('\u0609', '\u060A'),
+    ('\u060C', '\u060D'),
+    ('\u061B', '\u061B'),
+    ('\u061D', '\u061F'),
+    ('\u066A', '\u066D'),
+    ('\u06D4', '\u06D4'),
+    ('\u0700', '\u070D'),
+    ('\u07F7', '\u07F9'),
+    ('\u0830', '\u083E'),
+    ('\u085E', '\u085E'),
+    ('\u0964', '\u0965'),
+    ('\u0970', '\u0970'),
+    ('\u09FD', '\u09FD'),
+    ('\u0A76', '\u0A76'),
+    ('\u0AF0', '\u0AF0'),
+    ('\u0C77', '\u0C77'),
+    ('\u0C84', '\u0C84'),
+    ('\u0DF4', '\u0DF4'),
+    ('\u0E4F', '\u0E4F'),
+    ('\u0E5A', '\u0E5B'),
+    ('\u0F04', '\u0F12'),
+    ('\u0F14', '\u0F14'),
+    ('\u0F3A', '\u0F3D'),
+    ('\u0F85', '\u0F85'),
+    ('\u0FD0', '\u0FD4'),
+    ('\u0FD9', '\u0FDA'),
+    ('\u104A', '\u104F'),
+    ('\u10FB', '\u10FB'),
+    ('\u1360', '\u1368'),
+    ('\u1400', '\u1400'),
+    ('\u166E', '\u166E'),
+    ('\u169B', '\u169C'),
+    ('\u16EB', '\u16ED'),
+    ('\u1735', '\u1736'),
+    ('\u17D4', '\u17D6'),
+    ('\u17D8', '\u17DA'),
+    ('\u1800', '\u180A'),
+    ('\u1944', '\u1945'),
+    ('\u1A1E', '\u1A1F'),
+    ('\u1AA0', '\u1AA6'),
+    ('\u1AA8', '\u1AAD'),
+    ('\u1B5A', '\u1B60'),
+    ('\u1B7D', '\u1B7E'),
+    ('\u1BFC', '\u1BFF'),
+    ('\u1C3B', '\u1C3F'),
+    ('\u1C7E', '\u1C7F'),
+    ('\u1CC0', '\u1CC7'),
+    ('\u1CD3', '\u1CD3'),
+    ('\u2010', '\u2027'),
+    ('\u2030', '\u2043'),
+    ('\u2045', '\u2051'),
+    ('\u2053', '\u205E'),
+    ('\u207D', '\u207E'),
+    ('\u208D', '\u208E'),
+    ('\u2308', '\u230B'),
+    ('\u2329', '\u232A'),
+    ('\u2768', '\u2775'),
+    ('\u27C5', '\u27C6'),
+    ('\u27E6', '\u27EF'),
+    ('\u2983', '\u2998'),
+    ('\u29D8', '\u29DB'),
+    ('\u29FC', '\u29FD'),
+    ('\u2CF9', '\u2CFC'),
+    ('\u2CFE', '\u2CFF'),
+    ('\u2D70', '\u2D70'),
+    ('\u2E00', '\u2E2E'),
+    ('\u2E30', '\u2E4F'),
+    ('\u2E52', '\u2E5D'),
+    ('\u3001', '\u3003'),
+    ('\u3008', '\u3011'),
+    ('\u3014', '\u301F'),
+    ('\u3030', '\u3030'),
+    ('\u303D', '\u303D'),
+    ('\u30A0', '\u30A0'),
+    ('\u30FB', '\u30FB'),
+    ('\uA4FE', '\uA4FF'),
+    ('\uA60D', '\uA60F'),
+    ('\uA673', '\uA673'),
+    ('\uA67E', '\uA67E'),
+    ('\uA6F2', '\uA6F7'),
+    ('\uA874', '\uA877'),
+    ('\uA8CE', '\uA8CF'),
+    ('\uA8F8', '\uA8FA'),
+    ('\uA8FC', '\uA8FC'),
+    ('\uA92E', '\uA92F'),
+    ('\uA95F', '\uA95F'),
+    ('\uA9C1', '\uA9CD'),
+    ('\uA9DE', '\uA9DF'),
+    ('\uAA5C', '\uAA5F'),
+    ('\uAADE', '\uAADF'),
+    ('\uAAF0', '\uAAF1'),
+    ('\uABEB', '\uABEB'),
+    ('\uFD3E', '\uFD3F'),
+    ('\uFE10', '\uFE19'),
+    ('\uFE30', '\uFE52'),
+    ('\uFE54', '\uFE61'),
+    ('\uFE63', '\uFE63'),
+    ('\uFE68', '\uFE68'),
+    ('\uFE6A', '\uFE6B'),
+    ('\uFF01', '\uFF03'),
+    ('\uFF05', '\uFF0A'),
+    ('\uFF0C', '\uFF0F'),
+    ('\uFF1A', '\uFF1B'),
+    ('\uFF1F', '\uFF20'),
+    ('\uFF3B', '\uFF3D'),
+    ('\uFF3F', '\uFF3F'),
+    ('\uFF5B', '\uFF5B'),
+    ('\uFF5D', '\uFF5D'),
+    ('\uFF5F', '\uFF65'),
+    ('\U00010100', '\U00010102'),
+    ('\U0001039F', '\U0001039F'),
+    ('\U000103D0', '\U000103D0'),
+    ('\U0001056F', '\U0001056F'),
+    ('\U00010857', '\U00010857'),
+    ('\U0001091F', '\U0001091F'),
+    ('\U0001093F', '\U0001093F'),
+    ('\U00010A50', '\U00010A58'),
+    ('\U00010A7F', '\U00010A7F'),
+    ('\U00010AF0', '\U00010AF6'),
+    ('\U00010B39', '\U00010B3F'),
+    ('\U00010B99', '\U00010B9C'),
+    ('\U00010EAD', '\U00010EAD'),
+    ('\U00010F55', '\U00010F59'),
+    ('\U00010F86', '\U00010F89'),
+    ('\U00011047', '\U0001104D'),
+    ('\U000110BB', '\U000110BC'),
+    ('\U000110BE', '\U000110C1'),
+    ('\U00011140', '\U00011143'),
+    ('\U00011174', '\U00011175'),
+    ('\U000111C5', '\U000111C8'),
+    ('\U000111CD', '\U000111CD'),
+    ('\U000111DB', '\U000111DB'),
+    ('\U000111DD', '\U000111DF'),
+    ('\U00011238', '\U0001123D'),
+    ('\U000112A9', '\U000112A9'),
+    ('\U0001144B', '\U0001144F'),
+    ('\U0001145A', '\U0001145B'),
+    ('\U0001145D', '\U0001145D'),
+    ('\U000114C6', '\U000114C6'),
+    ('\U000115C1', '\U000115D7'),
+    ('\U00011641', '\U00011643'),
+    ('\U00011660', '\U0001166C'),
+    ('\U000116B9', '\U000116B9'),
+    ('\U0001173C', '\U0001173E'),
+    ('\U0001183B', '\U0001183B'),
+    ('\U00011944', '\U00011946'),
+    ('\U000119E2', '\U000119E2'),
+    ('\U00011A3F', '\U00011A46'),
+    ('\U00011A9A', '\U00011A9C'),
+    ('\U00011A9E', '\U00011AA2'),
+    ('\U00011C41', '\U00011C45'),
+    ('\U00011C70', '\U00011C71'),
+    ('\U00011EF7', '\U00011EF8'),
+    ('\U00011FFF', '\U00011FFF'),
+    ('\U00012470', '\U00012474'),
+    ('\U00012FF1', '\U00012FF2'),
+    ('\U00016A6E', '\U00016A6F'),
+    ('\U00016AF5', '\U00016AF5'),
+    ('\U00016B37', '\U00016B3B'),
+    ('\U00016B44', '\U00016B44'),
+    ('\U00016E97', '\U00016E9A'),
+    ('\U00016FE2', '\U00016FE2'),
+    ('\U0001BC9F', '\U0001BC9F'),
+    ('\U0001DA87', '\U0001DA8B'),
+    ('\U0001E95E', '\U0001E95F')
+  ]),
+  ("pc", &[
+    ('\u005F', '\u005F'),
+    ('\u203F', '\u2040'),
+    ('\u2054', '\u2054'),
+    ('\uFE33', '\uFE34'),
+    ('\uFE4D', '\uFE4F'),
+    ('\uFF3F', '\uFF3F')
+  ]),
+  ("pd", &[
+    ('\u002D', '\u002D'),
+    ('\u058A', '\u058A'),
+    ('\u05BE', '\u05BE'),
+    ('\u1400', '\u1400'),
+    ('\u1806', '\u1806'),
+    ('\u2010', '\u2015'),
+    ('\u2E17', '\u2E17'),
+    ('\u2E1A', '\u2E1A'),
+    ('\u2E3A', '\u2E3B'),
+    ('\u2E40', '\u2E40'),
+    ('\u2E5D', '\u2E5D'),
+    ('\u301C', '\u301C'),
+    ('\u3030', '\u3030'),
+    ('\u30A0', '\u30A0'),
+    ('\uFE31', '\uFE32'),
+    ('\uFE58', '\uFE58'),
+    ('\uFE63', '\uFE63'),
+    ('\uFF0D', '\uFF0D'),
+    ('\U00010EAD', '\U00010EAD')
+  ]),
+  ("pe", &[
+    ('\u0029', '\u0029'),
+    ('\u005D', '\u005D'),
+    ('\u007D', '\u007D'),
+    ('\u0F3B', '\u0F3B'),
+    ('\u0F3D', '\u0F3D'),
+    ('\u169C', '\u169C'),
+    ('\u2046', '\u2046'),
+    ('\u207E', '\u207E'),
+    ('\u208E', '\u208E'),
+    ('\u2309', '\u2309'),
+    ('\u230B', '\u230B'),
+    ('\u232A', '\u232A'),
+    ('\u2769', '\u2769'),
+    ('\u276B', '\u276B'),
+    ('\u276D', '\u276D'),
+    ('\u276F', '\u276F'),
+    ('\u2771', '\u2771'),
+    ('\u2773', '\u2773'),
+    ('\u2775', '\u2775'),
+    ('\u27C6', '\u27C6'),
+    ('\u27E7', '\u27E7'),
+    ('\u27E9', '\u27E9'),
+    ('\u27EB', '\u27EB'),
+    ('\u27ED', '\u27ED'),
+    ('\u27EF', '\u27EF'),
+    ('\u2984', '\u2984'),
+    ('\u2986', '\u2986'),
+    ('\u2988', '\u2988'),
+    ('\u298A', '\u298A'),
+    ('\u298C', '\u298C'),
+    ('\u298E', '\u298E'),
+    ('\u2990', '\u2990'),
+    ('\u2992', '\u2992'),
+    ('\u2994', '\u2994'),
+    ('\u2996', '\u2996'),
+    ('\u2998', '\u2998'),
+    ('\u29D9', '\u29D9'),
+    ('\u29DB', '\u29DB'),
+    ('\u29FD', '\u29FD'),
+    ('\u2E23', '\u2E23'),
+    ('\u2E25', '\u2E25'),
+    ('\u2E27', '\u2E27'),
+    ('\u2E29', '\u2E29'),
+    ('\u2E56', '\u2E56'),
+    ('\u2E58', '\u2E58'),
+    ('\u2E5A', '\u2E5A'),
+    ('\u2E5C', '\u2E5C'),
+    ('\u3009', '\u3009'),
+    ('\u300B', '\u300B'),
+    ('\u300D', '\u300D'),
+    ('\u300F', '\u300F'),
+    ('\u3011', '\u3011'),
+    ('\u3015', '\u3015'),
+    ('\u3017', '\u3017'),
+    ('\u3019', '\u3019'),
+    ('\u301B', '\u301B'),
+    ('\u301E', '\u301F'),
+    ('\uFD3E', '\uFD3E'),
+    ('\uFE18', '\uFE18'),
+    ('\uFE36', '\uFE36'),
+    ('\uFE38', '\uFE38'),
+    ('\uFE3A', '\uFE3A'),
+    ('\uFE3C', '\uFE3C'),
+    ('\uFE3E', '\uFE3E'),
+    ('\uFE40', '\uFE40'),
+    ('\uFE42', '\uFE42'),
+    ('\uFE44', '\uFE44'),
+    ('\uFE48', '\uFE48'),
+    ('\uFE5A', '\uFE5A'),
+    ('\uFE5C', '\uFE5C'),
+    ('\uFE5E', '\uFE5E'),
+    ('\uFF09', '\uFF09'),
+    ('\uFF3D', '\uFF3D'),
+    ('\uFF5D', '\uFF5D'),
+    ('\uFF60', '\uFF60'),
+    ('\uFF63', '\uFF63')
+  ]),
+  ("pf", &[
+    ('\u00BB', '\u00BB'),
+    ('\u2019', '\u2019'),
+    ('\u201D', '\u201D'),
+    ('\u203A', '\u203A'),
+    ('\u2E03', '\u2E03'),
+    ('\u2E05', '\u2E05'),
+    ('\u2E0A', '\u2E0A'),
+    ('\u2E0D', '\u2E0D'),
+    ('\u2E1D', '\u2E1D'),
+    ('\u2E21', '\u2E21')
+  ]),
+  ("pi", &[
+    ('\u00AB', '\u00AB'),
+    ('\u2018', '\u2018'),
+    ('\u201B', '\u201C'),
+    ('\u201F', '\u201F'),
+    ('\u2039', '\u2039'),
+    ('\u2E02', '\u2E02'),
+    ('\u2E04', '\u2E04'),
+    ('\u2E09', '\u2E09'),
+    ('\u2E0C', '\u2E0C'),
+    ('\u2E1C', '\u2E1C'),
+    ('\u2E20', '\u2E20')
+  ]),
+  ("po", &[
+    ('\u0021', '\u0023'),
+    ('\u0025', '\u0027'),
+    ('\u002A', '\u002A'),
+    ('\u002C', '\u002C'),
+    ('\u002E', '\u002F'),
+    ('\u003A', '\u003B'),
+    ('\u003F', '\u0040'),
+    ('\u005C', '\u005C'),
+    ('\u00A1', '\u00A1'),
+    ('\u00A7', '\u00A7'),
+    ('\u00B6', '\u00B7'),
+    ('\u00BF', '\u00BF'),
+    ('\u037E', '\u037E'),
+    ('\u0387', '\u0387'),
+    ('\u055A', '\u055F'),
+    ('\u0589', '\u0589'),
+    ('\u05C0', '\u05C0'),
+    ('\u05C3', '\u05C3'),
+    ('\u05C6', '\u05C6'),
+    ('\u05F3', '\u05F4'),
+    ('\u0609', '\u060A'),
+    ('\u060C', '\u060D'),
+    ('\u061B', '\u061B'),
+    ('\u061D', '\u061F'),
+    ('\u066A', '\u066D'),
+    ('\u06D4', '\u06D4'),
+    ('\u0700', '\u070D'),
+    ('\u07F7', '\u07F9'),
+    ('\u0830', '\u083E'),
+    ('\u085E', '\u085E'),
+    ('\u0964', '\u0965'),
+    ('\u0970', '\u0970'),
+    ('\u09FD', '\u09FD'),
+    ('\u0A76', '\u0A76'),
+    ('\u0AF0', '\u0AF0'),
+    ('\u0C77', '\u0C77'),
+    ('\u0C84', '\u0C84'),
+    ('\u0DF4', '\u0DF4'),
+    ('\u0E4F', '\u0E4F'),
+    ('\u0E5A', '\u0E5B'),
+    ('\u0F04', '\u0F12'),
+    ('\u0F14', '\u0F14'),
+    ('\u0F85', '\u0F85'),
+    ('\u0FD0', '\u0FD4'),
+    ('\u0FD9', '\u0FDA'),
+    ('\u104A', '\u104F'),
+    ('\u10FB', '\u10FB'),
+    ('\u1360', '\u1368'),
+    ('\u166E', '\u166E'),
+    ('\u16EB', '\u16ED'),
+    ('\u1735', '\u1736'),
+    ('\u17D4', '\u17D6'),
+    ('\u17D8', '\u17DA'),
+    ('\u1800', '\u1805'),
+    ('\u1807', '\u180A'),
+    ('\u1944', '\u1945'),
+    ('\u1A1E', '\u1A1F'),
+    ('\u1AA0', '\u1AA6'),
+    ('\u1AA8', '\u1AAD'),
+    ('\u1B5A', '\u1B60'),
+    ('\u1B7D', '\u1B7E'),
+    ('\u1BFC', '\u1BFF'),
+    ('\u1C3B', '\u1C3F'),
+    ('\u1C7E', '\u1C7F'),
+    ('\u1CC0', '\u1CC7'),
+    ('\u1CD3', '\u1CD3'),
+    ('\u2016', '\u2017'),
+    ('\u2020', '\u2027'),
+    ('\u2030', '\u2038'),
+    ('\u203B', '\u203E'),
+    ('\u2041', '\u2043'),
+    ('\u2047', '\u2051'),
+    ('\u2053', '\u2053'),
+    ('\u2055', '\u205E'),
+    ('\u2CF9', '\u2CFC'),
+    ('\u2CFE', '\u2CFF'),
+    ('\u2D70', '\u2D70'),
+    ('\u2E00', '\u2E01'),
+    ('\u2E06', '\u2E08'),
+    ('\u2E0B', '\u2E0B'),
+    ('\u2E0E', '\u2E16'),
+    ('\u2E18', '\u2E19'),
+    ('\u2E1B', '\u2E1B'),
+    ('\u2E1E', '\u2E1F'),
+    ('\u2E2A', '\u2E2E'),
+    ('\u2E30', '\u2E39'),
+    ('\u2E3C', '\u2E3F'),
+    ('\u2E41', '\u2E41'),
+    ('\u2E43', '\u2E4F'),
+    ('\u2E52', '\u2E54'),
+    ('\u3001', '\u3003'),
+    ('\u303D', '\u303D'),
+    ('\u30FB', '\u30FB'),
+    ('\uA4FE', '\uA4FF'),
+    ('\uA60D', '\uA60F'),
+    ('\uA673', '\uA673'),
+    ('\uA67E', '\uA67E'),
+    ('\uA6F2', '\uA6F7'),
+    ('\uA874', '\uA877'),
+    ('\uA8CE', '\uA8CF'),
+    ('\uA8F8', '\uA8FA'),
+    ('\uA8FC', '\uA8FC'),
+    ('\uA92E', '\uA92F'),
+    ('\uA95F', '\uA95F'),
+    ('\uA9C1', '\uA9CD'),
+    ('\uA9DE', '\uA9DF'),
+    ('\uAA5C', '\uAA5F'),
+    ('\uAADE', '\uAADF'),
+    ('\uAAF0', '\uAAF1'),
+    ('\uABEB', '\uABEB'),
+    ('\uFE10', '\uFE16'),
+    ('\uFE19', '\uFE19'),
+    ('\uFE30', '\uFE30'),
+    ('\uFE45', '\uFE46'),
+    ('\uFE49', '\uFE4C'),
+    ('\uFE50', '\uFE52'),
+    ('\uFE54', '\uFE57'),
+    ('\uFE5F', '\uFE61'),
+    ('\uFE68', '\uFE68'),
+    ('\uFE6A', '\uFE6B'),
+    ('\uFF01', '\uFF03'),
+    ('\uFF05', '\uFF07'),
+    ('\uFF0A', '\uFF0A'),
+    ('\uFF0C', '\uFF0C'),
+    ('\uFF0E', '\uFF0F'),
+    ('\uFF1A', '\uFF1B'),
+    ('\uFF1F', '\uFF20'),
+    ('\uFF3C', '\uFF3C'),
+    ('\uFF61', '\uFF61'),
+    ('\uFF64', '\uFF65'),
+    ('\U00010100', '\U00010102'),
+    ('\U0001039F', '\U0001039F'),
+    ('\U000103D0', '\U000103D0'),
+    ('\U0001056F', '\U0001056F'),
+    ('\U00010857', '\U00010857'),
+    ('\U0001091F', '\U0001091F'),
+    ('\U0001093F', '\U0001093F'),
+    ('\U00010A50', '\U00010A58'),
+    ('\U00010A7F', '\U00010A7F'),
+    ('\U00010AF0', '\U00010AF6'),
+    ('\U00010B39', '\U00010B3F'),
+    ('\U00010B99', '\U00010B9C'),
+    ('\U00010F55', '\U00010F59'),
+    ('\U00010F86', '\U00010F89'),
+    ('\U00011047', '\U0001104D'),
+    ('\U000110BB', '\U000110BC'),
+    ('\U000110BE', '\U000110C1'),
+    ('\U00011140', '\U00011143'),
+    ('\U00011174', '\U00011175'),
+    ('\U000111C5', '\U000111C8'),
+    ('\U000111CD', '\U000111CD'),
+    ('\U000111DB', '\U000111DB'),
+    ('\U000111DD', '\U000111DF'),
+    ('\U00011238', '\U0001123D'),
+    ('\U000112A9', '\U000112A9'),
+    ('\U0001144B', '\U0001144F'),
+    ('\U0001145A', '\U0001145B'),
+    ('\U0001145D', '\U0001145D'),
+    ('\U000114C6', '\U000114C6'),
+    ('\U000115C1', '\U000115D7'),
+    ('\U00011641', '\U00011643'),
+    ('\U00011660', '\U0001166C'),
+    ('\U000116B9', '\U000116B9'),
+    ('\U0001173C', '\U0001173E'),
+    ('\U0001183B', '\U0001183B'),
+    ('\U00011944', '\U00011946'),
+    ('\U000119E2', '\U000119E2'),
+    ('\U00011A3F', '\U00011A46'),
+    ('\U00011A9A', '\U00011A9C'),
+    ('\U00011A9E', '\U00011AA2'),
+    ('\U00011C41', '\U00011C45'),
+    ('\U00011C70', '\U00011C71'),
+    ('\U00011EF7', '\U00011EF8'),
+    ('\U00011FFF', '\U00011FFF'),
+    ('\U00012470', '\U00012474'),
+    ('\U00012FF1', '\U00012FF2'),
+    ('\U00016A6E', '\U00016A6F'),
+    ('\U00016AF5', '\U00016AF5'),
+    ('\U00016B37', '\U00016B3B'),
+    ('\U00016B44', '\U00016B44'),
+    ('\U00016E97', '\U00016E9A'),
+    ('\U00016FE2', '\U00016FE2'),
+    ('\U0001BC9F', '\U0001BC9F'),
+    ('\U0001DA87', '\U0001DA8B'),
+    ('\U0001E95E', '\U0001E95F')
+  ]),
+  ("ps", &[
+    ('\u0028', '\u0028'),
+    ('\u005B', '\u005B'),
+    ('\u007B', '\u007B'),
+    ('\u0F3A', '\u0F3A'),
+    ('\u0F3C', '\u0F3C'),
+    ('\u169B', '\u169B'),
+    ('\u201A', '\u201A'),
+    ('\u201E', '\u201E'),
+    ('\u2045', '\u2045'),
+    ('\u207D', '\u207D'),
+    ('\u208D', '\u208D'),
+    ('\u2308', '\u2308'),
+    ('\u230A', '\u230A'),
+    ('\u2329', '\u2329'),
+    ('\u2768', '\u2768'),
+    ('\u276A', '\u276A'),
+    ('\u276C', '\u276C'),
+    ('\u276E', '\u276E'),
+    ('\u2770', '\u2770'),
+    ('\u2772', '\u2772'),
+    ('\u2774', '\u2774'),
+    ('\u27C5', '\u27C5'),
+    ('\u27E6', '\u27E6'),
+    ('\u27E8', '\u27E8'),
+    ('\u27EA', '\u27EA'),
+    ('\u27EC', '\u27EC'),
+    ('\u27EE', '\u27EE'),
+    ('\u2983', '\u2983'),
+    ('\u2985', '\u2985'),
+    ('\u2987', '\u2987'),
+    ('\u2989', '\u2989'),
+    ('\u298B', '\u298B'),
+    ('\u298D', '\u298D'),
+    ('\u298F', '\u298F'),
+    ('\u2991', '\u2991'),
+    ('\u2993', '\u2993'),
+    ('\u2995', '\u2995'),
+    ('\u2997', '\u2997'),
+    ('\u29D8', '\u29D8'),
+    ('\u29DA', '\u29DA'),
+    ('\u29FC', '\u29FC'),
+    ('\u2E22', '\u2E22'),
+    ('\u2E24', '\u2E24'),
+    ('\u2E26', '\u2E26'),
+    ('\u2E28', '\u2E28'),
+    ('\u2E42', '\u2E42'),
+    ('\u2E55', '\u2E55'),
+    ('\u2E57', '\u2E57'),
+    ('\u2E59', '\u2E59'),
+    ('\u2E5B', '\u2E5B'),
+    ('\u3008', '\u3008'),
+    ('\u300A', '\u300A'),
+    ('\u300C', '\u300C'),
+    ('\u300E', '\u300E'),
+    ('\u3010', '\u3010'),
+    ('\u3014', '\u3014'),
+    ('\u3016', '\u3016'),
+    ('\u3018', '\u3018'),
+    ('\u301A', '\u301A'),
+    ('\u301D', '\u301D'),
+    ('\uFD3F', '\uFD3F'),
+    ('\uFE17', '\uFE17'),
+    ('\uFE35', '\uFE35'),
+    ('\uFE37', '\uFE37'),
+    ('\uFE39', '\uFE39'),
+    ('\uFE3B', '\uFE3B'),
+    ('\uFE3D', '\uFE3D'),
+    ('\uFE3F', '\uFE3F'),
+    ('\uFE41', '\uFE41'),
+    ('\uFE43', '\uFE43'),
+    ('\uFE47', '\uFE47'),
+    ('\uFE59', '\uFE59'),
+    ('\uFE5B', '\uFE5B'),
+    ('\uFE5D', '\uFE5D'),
+    ('\uFF08', '\uFF08'),
+    ('\uFF3B', '\uFF3B'),
+    ('\uFF5B', '\uFF5B'),
+    ('\uFF5F', '\uFF5F'),
+    ('\uFF62', '\uFF62')
+  ]),
+  ("s", &[
+    ('\u0024', '\u0024'),
+    ('\u002B', '\u002B'),
+    ('\u003C', '\u003E'),
+    ('\u005E', '\u005E'),
+    ('\u0060', '\u0060'),
+    ('\u007C', '\u007C'),
+    ('\u007E', '\u007E'),
+    ('\u00A2', '\u00A6'),
+    ('\u00A8', '\u00A9'),
+    ('\u00AC', '\u00AC'),
+    ('\u00AE', '\u00B1'),
+    ('\u00B4', '\u00B4'),
+    ('\u00B8', '\u00B8'),
+    ('\u00D7', '\u00D7'),
+    ('\u00F7', '\u00F7'),
+    ('\u02C2', '\u02C5'),
+    ('\u02D2', '\u02DF'),
+    ('\u02E5', '\u02EB'),
+    ('\u02ED', '\u02ED'),
+    ('\u02EF', '\u02FF'),
+    ('\u0375', '\u0375'),
+    ('\u0384', '\u0385'),
+    ('\u03F6', '\u03F6'),
+    ('\u0482', '\u0482'),
+    ('\u058D', '\u058F'),
+    ('\u0606', '\u0608'),
+    ('\u060B', '\u060B'),
+    ('\u060E', '\u060F'),
+    ('\u06DE', '\u06DE'),
+    ('\u06E9', '\u06E9'),
+    ('\u06FD', '\u06FE'),
+    ('\u07F6', '\u07F6'),
+    ('\u07FE', '\u07FF'),
+    ('\u0888', '\u0888'),
+    ('\u09F2', '\u09F3'),
+    ('\u09FA', '\u09FB'),
+    ('\u0AF1', '\u0AF1'),
+    ('\u0B70', '\u0B70'),
+    ('\u0BF3', '\u0BFA'),
+    ('\u0C7F', '\u0C7F'),
+    ('\u0D4F', '\u0D4F'),
+    ('\u0D79', '\u0D79'),
+    ('\u0E3F', '\u0E3F'),
+    ('\u0F01', '\u0F03'),
+    ('\u0F13', '\u0F13'),
+    ('\u0F15', '\u0F17'),
+    ('\u0F1A', '\u0F1F'),
+    ('\u0F34', '\u0F34'),
+    ('\u0F36', '\u0F36'),
+    ('\u0F38', '\u0F38'),
+    ('\u0FBE', '\u0FC5'),
+    ('\u0FC7', '\u0FCC'),
+    ('\u0FCE', '\u0FCF'),
+    ('\u0FD5', '\u0FD8'),
+    ('\u109E', '\u109F'),
+    ('\u1390', '\u1399'),
+    ('\u166D', '\u166D'),
+    ('\u17DB', '\u17DB'),
+    ('\u1940', '\u1940'),
+    ('\u19DE', '\u19FF'),
+    ('\u1B61', '\u1B6A'),
+    ('\u1B74', '\u1B7C'),
+    ('\u1FBD', '\u1FBD'),
+    ('\u1FBF', '\u1FC1'),
+    ('\u1FCD', '\u1FCF'),
+    ('\u1FDD', '\u1FDF'),
+    ('\u1FED', '\u1FEF'),
+    ('\u1FFD', '\u1FFE'),
+    ('\u2044', '\u2044'),
+    ('\u2052', '\u2052'),
+    ('\u207A', '\u207C'),
+    ('\u208A', '\u208C'),
+    ('\u20A0', '\u20C0'),
+    ('\u2100', '\u2101'),
+    ('\u2103', '\u2106'),
+    ('\u2108', '\u2109'),
+    ('\u2114', '\u2114'),
+    ('\u2116', '\u2118'),
+    ('\u211E', '\u2123'),
+    ('\u2125', '\u2125'),
+    ('\u2127', '\u2127'),
+    ('\u2129', '\u2129'),
+    ('\u212E', '\u212E'),
+    ('\u213A', '\u213B'),
+    ('\u2140', '\u2144'),
+    ('\u214A', '\u214D'),
+    ('\u214F', '\u214F'),
+    ('\u218A', '\u218B'),
+    ('\u2190', '\u2307'),
+    ('\u230C', '\u2328'),
+    ('\u232B', '\u2426'),
+    ('\u2440', '\u244A'),
+    ('\u249C', '\u24E9'),
+    ('\u2500', '\u2767'),
+    ('\u2794', '\u27C4'),
+    ('\u27C7', '\u27E5'),
+    ('\u27F0', '\u2982'),
+    ('\u2999', '\u29D7'),
+    ('\u29DC', '\u29FB'),
+    ('\u29FE', '\u2B73'),
+    ('\u2B76', '\u2B95'),
+    ('\u2B97', '\u2BFF'),
+    ('\u2CE5', '\u2CEA'),
+    ('\u2E50', '\u2E51'),
+    ('\u2E80', '\u2E99'),
+    ('\u2E9B', '\u2EF3'),
+    ('\u2F00', '\u2FD5'),
+    ('\u2FF0', '\u2FFB'),
+    ('\u3004', '\u3004'),
+    ('\u3012', '\u3013'),
+    ('\u3020', '\u3020'),
+    ('\u3036', '\u3037'),
+    ('\u303E', '\u303F'),
+    ('\u309B', '\u309C'),
+    ('\u3190', '\u3191'),
+    ('\u3196', '\u319F'),
+    ('\u31C0', '\u31E3'),
+    ('\u3200', '\u321E'),
+    ('\u322A', '\u3247'),
+    ('\u3250', '\u3250'),
+    ('\u3260', '\u327F'),
+    ('\u328A', '\u32B0'),
+    ('\u32C0', '\u33FF'),
+    ('\u4DC0', '\u4DFF'),
+    ('\uA490', '\uA4C6'),
+    ('\uA700', '\uA716'),
+    ('\uA720', '\uA721'),
+    ('\uA789', '\uA78A'),
+    ('\uA828', '\uA82B'),
+    ('\uA836', '\uA839'),
+    ('\uAA77', '\uAA79'),
+    ('\uAB5B', '\uAB5B'),
+    ('\uAB6A', '\uAB6B'),
+    ('\uFB29', '\uFB29'),
+    ('\uFBB2', '\uFBC2'),
+    ('\uFD40', '\uFD4F'),
+    ('\uFDCF', '\uFDCF'),
+    ('\uFDFC', '\uFDFF'),
+    ('\uFE62', '\uFE62'),
+    ('\uFE64', '\uFE66'),
+    ('\uFE69', '\uFE69'),
+    ('\uFF04', '\uFF04'),
+    ('\uFF0B', '\uFF0B'),
+    ('\uFF1C', '\uFF1E'),
+    ('\uFF3E', '\uFF3E'),
+    ('\uFF40', '\uFF40'),
+    ('\uFF5C', '\uFF5C'),
+    ('\uFF5E', '\uFF5E'),
+    ('\uFFE0', '\uFFE6'),
+    ('\uFFE8', '\uFFEE'),
+    ('\uFFFC', '\uFFFD'),
+    ('\U00010137', '\U0001013F'),
+    ('\U00010179', '\U00010189'),
+    ('\U0001018C', '\U0001018E'),
+    ('\U00010190', '\U0001019C'),
+    ('\U000101A0', '\U000101A0'),
+    ('\U000101D0', '\U000101FC'),
+    ('\U00010877', '\U00010878'),
+    ('\U00010AC8', '\U00010AC8'),
+    ('\U0001173F', '\U0001173F'),
+    ('\U00011FD5', '\U00011FF1'),
+    ('\U00016B3C', '\U00016B3F'),
+    ('\U00016B45', '\U00016B45'),
+    ('\U0001BC9C', '\U0001BC9C'),
+    ('\U0001CF50', '\U0001CFC3'),
+    ('\U0001D000', '\U0001D0F5'),
+    ('\U0001D100', '\U0001D126'),
+    ('\U0001D129', '\U0001D164'),
+    ('\U0001D16A', '\U0001D16C'),
+    ('\U0001D183', '\U0001D184'),
+    ('\U0001D18C', '\U0001D1A9'),
+    ('\U0001D1AE', '\U0001D1EA'),
+    ('\U0001D200', '\U0001D241'),
+    ('\U0001D245', '\U0001D245'),
+    ('\U0001D300', '\U0001D356'),
+    ('\U0001D6C1', '\U0001D6C1'),
+    ('\U0001D6DB', '\U0001D6DB'),
+    ('\U0001D6FB', '\U0001D6FB'),
+    ('\U0001D715', '\U0001D715'),
+    ('\U0001D735', '\U0001D735'),
+    ('\U0001D74F', '\U0001D74F'),
+    ('\U0001D76F', '\U0001D76F'),
+    ('\U0001D789', '\U0001D789'),
+    ('\U0001D7A9', '\U0001D7A9'),
+    ('\U0001D7C3', '\U0001D7C3'),
+    ('\U0001D800', '\U0001D9FF'),
+    ('\U0001DA37', '\U0001DA3A'),
+    ('\U0001DA6D', '\U0001DA74'),
+    ('\U0001DA76', '\U0001DA83'),
+    ('\U0001DA85', '\U0001DA86'),
+    ('\U0001E14F', '\U0001E14F'),
+    ('\U0001E2FF', '\U0001E2FF'),
+    ('\U0001ECAC', '\U0001ECAC'),
+    ('\U0001ECB0', '\U0001ECB0'),
+    ('\U0001ED2E', '\U0001ED2E'),
+    ('\U0001EEF0', '\U0001EEF1'),
+    ('\U0001F000', '\U0001F02B'),
+    ('\U0001F030', '\U0001F093'),
+    ('\U0001F0A0', '\U0001F0AE'),
+    ('\U0001F0B1', '\U0001F0BF'),
+    ('\U0001F0C1', '\U0001F0CF'),
+    ('\U0001F0D1', '\U0001F0F5'),
+    ('\U0001F10D', '\U0001F1AD'),
+    ('\U0001F1E6', '\U0001F202'),
+    ('\U0001F210', '\U0001F23B'),
+    ('\U0001F240', '\U0001F248'),
+    ('\U0001F250', '\U0001F251'),
+    ('\U0001F260', '\U0001F265'),
+    ('\U0001F300', '\U0001F6D7'),
+    ('\U0001F6DD', '\U0001F6EC'),
+    ('\U0001F6F0', '\U0001F6FC'),
+    ('\U0001F700', '\U0001F773'),
+    ('\U0001F780', '\U0001F7D8'),
+    ('\U0001F7E0', '\U0001F7EB'),
+    ('\U0001F7F0', '\U0001F7F0'),
+    ('\U0001F800', '\U0001F80B'),
+    ('\U0001F810', '\U0001F847'),
+    ('\U0001F850', '\U0001F859'),
+    ('\U0001F860', '\U0001F887'),
+    ('\U0001F890', '\U0001F8AD'),
+    ('\U0001F8B0', '\U0001F8B1'),
+    ('\U0001F900', '\U0001FA53'),
+    ('\U0001FA60', '\U0001FA6D'),
+    ('\U0001FA70', '\U0001FA74'),
+    ('\U0001FA78', '\U0001FA7C'),
+    ('\U0001FA80', '\U0001FA86'),
+    ('\U0001FA90', '\U0001FAAC'),
+    ('\U0001FAB0', '\U0001FABA'),
+    ('\U0001FAC0', '\U0001FAC5'),
+    ('\U0001FAD0', '\U0001FAD9'),
+    ('\U0001FAE0', '\U0001FAE7'),
+    ('\U0001FAF0', '\U0001FAF6'),
+    ('\U0001FB00', '\U0001FB92'),
+    ('\U0001FB94', '\U0001FBCA')
+  ]),
+  ("sc", &[
+    ('\u0024', '\u0024'),
+    ('\u00A2', '\u00A5'),
+    ('\u058F', '\u058F'),
+    ('\u060B', '\u060B'),
+    ('\u07FE', '\u07FF'),
+    ('\u09F2', '\u09F3'),
+    ('\u09FB', '\u09FB'),
+    ('\u0AF1', '\u0AF1'),
+    ('\u0BF9', '\u0BF9'),
+    ('\u0E3F', '\u0E3F'),
+    ('\u17DB', '\u17DB'),
+    ('\u20A0', '\u20C0'),
+    ('\uA838', '\uA838'),
+    ('\uFDFC', '\uFDFC'),
+    ('\uFE69', '\uFE69'),
+    ('\uFF04', '\uFF04'),
+    ('\uFFE0', '\uFFE1'),
+    ('\uFFE5', '\uFFE6'),
+    ('\U00011FDD', '\U00011FE0'),
+    ('\U0001E2FF', '\U0001E2FF'),
+    ('\U0001ECB0', '\U0001ECB0')
+  ]),
+  ("sk", &[
+    ('\u005E', '\u005E'),
+    ('\u0060', '\u0060'),
+    ('\u00A8', '\u00A8'),
+    ('\u00AF', '\u00AF'),
+    ('\u00B4', '\u00B4'),
+    ('\u00B8', '\u00B8'),
+    ('\u02C2', '\u02C5'),
+    ('\u02D2', '\u02DF'),
+    ('\u02E5', '\u02EB'),
+    ('\u02ED', '\u02ED'),
+    ('\u02EF', '\u02FF'),
+    ('\u0375', '\u0375'),
+    ('\u0384', '\u0385'),
+    ('\u0888', '\u0888'),
+    ('\u1FBD', '\u1FBD'),
+    ('\u1FBF', '\u1FC1'),
+    ('\u1FCD', '\u1FCF'),
+    ('\u1FDD', '\u1FDF'),
+    ('\u1FED', '\u1FEF'),
+    ('\u1FFD', '\u1FFE'),
+    ('\u309B', '\u309C'),
+    ('\uA700', '\uA716'),
+    ('\uA720', '\uA721'),
+    ('\uA789', '\uA78A'),
+    ('\uAB5B', '\uAB5B'),
+    ('\uAB6A', '\uAB6B'),
+    ('\uFBB2', '\uFBC2'),
+    ('\uFF3E', '\uFF3E'),
+    ('\uFF40', '\uFF40'),
+    ('\uFFE3', '\uFFE3'),
+    ('\U0001F3FB', '\U0001F3FF')
+  ]),
+  ("sm", &[
+    ('\u002B', '\u002B'),
+    ('\u003C', '\u003E'),
+    ('\u007C', '\u007C'),
+    ('\u007E', '\u007E'),
+    ('\u00AC', '\u00AC'),
+    ('\u00B1', '\u00B1'),
+    ('\u00D7', '\u00D7'),
+    ('\u00F7', '\u00F7'),
+    ('\u03F6', '\u03F6'),
+    ('\u0606', '\u0608'),
+    ('\u2044', '\u2044'),
+    ('\u2052', '\u2052'),
+    ('\u207A', '\u207C'),
+    ('\u208A', '\u208C'),
+    ('\u2118', '\u2118'),
+    ('\u2140', '\u2144'),
+    ('\u214B', '\u214B'),
+    ('\u2190', '\u2194'),
+    ('\u219A', '\u219B'),
+    ('\u21A0', '\u21A0'),
+    ('\u21A3', '\u21A3'),
+    ('\u21A6', '\u21A6'),
+    ('\u21AE', '\u21AE'),
+    ('\u21CE', '\u21CF'),
+    ('\u21D2', '\u21D2'),
+    ('\u21D4', '\u21D4'),
+    ('\u21F4', '\u22FF'),
+    ('\u2320', '\u2321'),
+    ('\u237C', '\u237C'),
+    ('\u239B', '\u23B3'),
+    ('\u23DC', '\u23E1'),
+    ('\u25B7', '\u25B7'),
+    ('\u25C1', '\u25C1'),
+    ('\u25F8', '\u25FF'),
+    ('\u266F', '\u266F'),
+    ('\u27C0', '\u27C4'),
+    ('\u27C7', '\u27E5'),
+    ('\u27F0', '\u27FF'),
+    ('\u2900', '\u2982'),
+    ('\u2999', '\u29D7'),
+    ('\u29DC', '\u29FB'),
+    ('\u29FE', '\u2AFF'),
+    ('\u2B30', '\u2B44'),
+    ('\u2B47', '\u2B4C'),
+    ('\uFB29', '\uFB29'),
+    ('\uFE62', '\uFE62'),
+    ('\uFE64', '\uFE66'),
+    ('\uFF0B', '\uFF0B'),
+    ('\uFF1C', '\uFF1E'),
+    ('\uFF5C', '\uFF5C'),
+    ('\uFF5E', '\uFF5E'),
+    ('\uFFE2', '\uFFE2'),
+    ('\uFFE9', '\uFFEC'),
+    ('\U0001D6C1', '\U0001D6C1'),
+    ('\U0001D6DB', '\U0001D6DB'),
+    ('\U0001D6FB', '\U0001D6FB'),
+    ('\U0001D715', '\U0001D715'),
+    ('\U0001D735', '\U0001D735'),
+    ('\U0001D74F', '\U0001D74F'),
+    ('\U0001D76F', '\U0001D76F'),
+    ('\U0001D789', '\U0001D789'),
+    ('\U0001D7A9', '\U0001D7A9'),
+    ('\U0001D7C3', '\U0001D7C3'),
+    ('\U0001EEF0', '\U0001EEF1')
+  ]),
+  ("so", &[
+    ('\u00A6', '\u00A6'),
+    ('\u00A9', '\u00A9'),
+    ('\u00AE', '\u00AE'),
+    ('\u00B0', '\u00B0'),
+    ('\u0482', '\u0482'),
+    ('\u058D', '\u058E'),
+    ('\u060E', '\u060F'),
+    ('\u06DE', '\u06DE'),
+    ('\u06E9', '\u06E9'),
+    ('\u06FD', '\u06FE'),
+    ('\u07F6', '\u07F6'),
+    ('\u09FA', '\u09FA'),
+    ('\u0B70', '\u0B70'),
+    ('\u0BF3', '\u0BF8'),
+    ('\u0BFA', '\u0BFA'),
+    ('\u0C7F', '\u0C7F'),
+    ('\u0D4F', '\u0D4F'),
+    ('\u0D79', '\u0D79'),
+    ('\u0F01', '\u0F03'),
+    ('\u0F13', '\u0F13'),
+    ('\u0F15', '\u0F17'),
+    ('\u0F1A', '\u0F1F'),
+    ('\u0F34', '\u0F34'),
+    ('\u0F36', '\u0F36'),
+    ('\u0F38', '\u0F38'),
+    ('\u0FBE', '\u0FC5'),
+    ('\u0FC7', '\u0FCC'),
+    ('\u0FCE', '\u0FCF'),
+    ('\u0FD5', '\u0FD8'),
+    ('\u109E', '\u109F'),
+    ('\u1390', '\u1399'),
+    ('\u166D', '\u166D'),
+    ('\u1940', '\u1940'),
+    ('\u19DE', '\u19FF'),
+    ('\u1B61', '\u1B6A'),
+    ('\u1B74', '\u1B7C'),
+    ('\u2100', '\u2101'),
+    ('\u2103', '\u2106'),
+    ('\u2108', '\u2109'),
+    ('\u2114', '\u2114'),
+    ('\u2116', '\u2117'),
+    ('\u211E', '\u2123'),
+    ('\u2125', '\u2125'),
+    ('\u2127', '\u2127'),
+    ('\u2129', '\u2129'),
+    ('\u212E', '\u212E'),
+    ('\u213A', '\u213B'),
+    ('\u214A', '\u214A'),
+    ('\u214C', '\u214D'),
+    ('\u214F', '\u214F'),
+    ('\u218A', '\u218B'),
+    ('\u2195', '\u2199'),
+    ('\u219C', '\u219F'),
+    ('\u21A1', '\u21A2'),
+    ('\u21A4', '\u21A5'),
+    ('\u21A7', '\u21AD'),
+    ('\u21AF', '\u21CD'),
+    ('\u21D0', '\u21D1'),
+    ('\u21D3', '\u21D3'),
+    ('\u21D5', '\u21F3'),
+    ('\u2300', '\u2307'),
+    ('\u230C', '\u231F'),
+    ('\u2322', '\u2328'),
+    ('\u232B', '\u237B'),
+    ('\u237D', '\u239A'),
+    ('\u23B4', '\u23DB'),
+    ('\u23E2', '\u2426'),
+    ('\u2440', '\u244A'),
+    ('\u249C', '\u24E9'),
+    ('\u2500', '\u25B6'),
+    ('\u25B8', '\u25C0'),
+    ('\u25C2', '\u25F7'),
+    ('\u2600', '\u266E'),
+    ('\u2670', '\u2767'),
+    ('\u2794', '\u27BF'),
+    ('\u2800', '\u28FF'),
+    ('\u2B00', '\u2B2F'),
+    ('\u2B45', '\u2B46'),
+    ('\u2B4D', '\u2B73'),
+    ('\u2B76', '\u2B95'),
+    ('\u2B97', '\u2BFF'),
+    ('\u2CE5', '\u2CEA'),
+    ('\u2E50', '\u2E51'),
+    ('\u2E80', '\u2E99'),
+    ('\u2E9B', '\u2EF3'),
+    ('\u2F00', '\u2FD5'),
+    ('\u2FF0', '\u2FFB'),
+    ('\u3004', '\u3004'),
+    ('\u3012', '\u3013'),
+    ('\u3020', '\u3020'),
+    ('\u3036', '\u3037'),
+    ('\u303E', '\u303F'),
+    ('\u3190', '\u3191'),
+    ('\u3196', '\u319F'),
+    ('\u31C0', '\u31E3'),
+    ('\u3200', '\u321E'),
+    ('\u322A', '\u3247'),
+    ('\u3250', '\u3250'),
+    ('\u3260', '\u327F'),
+    ('\u328A', '\u32B0'),
+    ('\u32C0', '\u33FF'),
+    ('\u4DC0', '\u4DFF'),
+    ('\uA490', '\uA4C6'),
+    ('\uA828', '\uA82B'),
+    ('\uA836', '\uA837'),
+    ('\uA839', '\uA839'),
+    ('\uAA77', '\uAA79'),
+    ('\uFD40', '\uFD4F'),
+    ('\uFDCF', '\uFDCF'),
+    ('\uFDFD', '\uFDFF'),
+    ('\uFFE4', '\uFFE4'),
+    ('\uFFE8', '\uFFE8'),
+    ('\uFFED', '\uFFEE'),
+    ('\uFFFC', '\uFFFD'),
+    ('\U00010137', '\U0001013F'),
+    ('\U00010179', '\U00010189'),
+    ('\U0001018C', '\U0001018E'),
+    ('\U00010190', '\U0001019C'),
+    ('\U000101A0', '\U000101A0'),
+    ('\U000101D0', '\U000101FC'),
+    ('\U00010877', '\U00010878'),
+    ('\U00010AC8', '\U00010AC8'),
+    ('\U0001173F', '\U0001173F'),
+    ('\U00011FD5', '\U00011FDC'),
+    ('\U00011FE1', '\U00011FF1'),
+    ('\U00016B3C', '\U00016B3F'),
+    ('\U00016B45', '\U00016B45'),
+    ('\U0001BC9C', '\U0001BC9C'),
+    ('\U0001CF50', '\U0001CFC3'),
+    ('\U0001D000', '\U0001D0F5'),
+    ('\U0001D100', '\U0001D126'),
+    ('\U0001D129', '\U0001D164'),
+    ('\U0001D16A', '\U0001D16C'),
+    ('\U0001D183', '\U0001D184'),
+    ('\U0001D18C', '\U0001D1A9'),
+    ('\U0001D1AE', '\U0001D1EA'),
+    ('\U0001D200', '\U0001D241'),
+    ('\U0001D245', '\U0001D245'),
+    ('\U0001D300', '\U0001D356'),
+    ('\U0001D800', '\U0001D9FF'),
+    ('\U0001DA37', '\U0001DA3A'),
+    ('\U0001DA6D', '\U0001DA74'),
+    ('\U0001DA76', '\U0001DA83'),
+    ('\U0001DA85', '\U0001DA86'),
+    ('\U0001E14F', '\U0001E14F'),
+    ('\U0001ECAC', '\U0001ECAC'),
+    ('\U0001ED2E', '\U0001ED2E'),
+    ('\U0001F000', '\U0001F02B'),
+    ('\U0001F030', '\U0001F093'),
+    ('\U0001F0A0', '\U0001F0AE'),
+    ('\U0001F0B1', '\U0001F0BF'),
+    ('\U0001F0C1', '\U0001F0CF'),
+    ('\U0001F0D1', '\U0001F0F5'),
+    ('\U0001F10D', '\U0001F1AD'),
+    ('\U0001F1E6', '\U0001F202'),
+    ('\U0001F210', '\U0001F23B'),
+    ('\U0001F240', '\U0001F248'),
+    ('\U0001F250', '\U0001F251'),
+    ('\U0001F260', '\U0001F265'),
+    ('\U0001F300', '\U0001F3FA'),
+    ('\U0001F400', '\U0001F6D7'),
+    ('\U0001F6DD', '\U0001F6EC'),
+    ('\U0001F6F0', '\U0001F6FC'),
+    ('\U0001F700', '\U0001F773'),
+    ('\U0001F780', '\U0001F7D8'),
+    ('\U0001F7E0', '\U0001F7EB'),
+    ('\U0001F7F0', '\U0001F7F0'),
+    ('\U0001F800', '\U0001F80B'),
+    ('\U0001F810', '\U0001F847'),
+    ('\U0001F850', '\U0001F859'),
+    ('\U0001F860', '\U0001F887'),
+    ('\U0001F890', '\U0001F8AD'),
+    ('\U0001F8B0', '\U0001F8B1'),
+    ('\U0001F900', '\U0001FA53'),
+    ('\U0001FA60', '\U0001FA6D'),
+    ('\U0001FA70', '\U0001FA74'),
+    ('\U0001FA78', '\U0001FA7C'),
+    ('\U0001FA80', '\U0001FA86'),
+    ('\U0001FA90', '\U0001FAAC'),
+    ('\U0001FAB0', '\U0001FABA'),
+    ('\U0001FAC0', '\U0001FAC5'),
+    ('\U0001FAD0', '\U0001FAD9'),
+    ('\U0001FAE0', '\U0001FAE7'),
+    ('\U0001FAF0', '\U0001FAF6'),
+    ('\U0001FB00', '\U0001FB92'),
+    ('\U0001FB94', '\U0001FBCA')
+  ]),
+  ("z", &[
+    ('\u0020', '\u0020'),
+    ('\u00A0', '\u00A0'),
+    ('\u1680', '\u1680'),
+    ('\u2000', '\u200A'),
+    ('\u2028', '\u2029'),
+    ('\u202F', '\u202F'),
+    ('\u205F', '\u205F'),
+    ('\u3000', '\u3000')
+  ]),
+  ("zl", &[
+    ('\u2028', '\u2028')
+  ]),
+  ("zp", &[
+    ('\u2029', '\u2029')
+  ]),
+  ("zs", &[
+    ('\u0020', '\u0020'),
+    ('\u00A0', '\u00A0'),
+    ('\u1680', '\u1680'),
+    ('\u2000', '\u200A'),
+    ('\u202F', '\u202F'),
+    ('\u205F', '\u205F'),
+    ('\u3000', '\u3000')
+  ])
+];
+
+/// Normalized names of general categories, mapped to their short name
+pub static GeneralCategoryAliases: &'static [(&'static str, &'static str)] = &[
+  ("c", "c"),
+  ("casedletter", "lc"),
+  ("cc", "cc"),
+  ("cf", "cf"),
+  ("closepunctuation", "pe"),
+  ("cn", "cn"),
+  ("cntrl", "cc"),
+  ("co", "co"),
+  ("combiningmark", "m"),
+  ("connectorpunctuation", "pc"),
+  ("control", "cc"),
+  ("cs", "cs"),
+  ("currencysymbol", "sc"),
+  ("dashpunctuation", "pd"),
+  ("decimalnumber", "nd"),
+  ("digit", "nd"),
+  ("enclosingmark", "me"),
+  ("finalpunctuation", "pf"),
+  ("format", "cf"),
+  ("initialpunctuation", "pi"),
+  ("l", "l"),
+  ("lc", "lc"),
+  ("letter", "l"),
+  ("letternumber", "nl"),
+  ("lineseparator", "zl"),
+  ("ll", "ll"),
+  ("lm", "lm"),
+  ("lo", "lo"),
+  ("lowercaseletter", "ll"),
+  ("lt", "lt"),
+  ("lu", "lu"),
+  ("m", "m"),
+  ("mark", "m"),
+  ("mathsymbol", "sm"),
+  ("mc", "mc"),
+  ("me", "me"),
+  ("mn", "mn"),
+  ("modifierletter", "lm"),
+  ("modifiersymbol", "sk"),
+  ("n", "n"),
+  ("nd", "nd"),
+  ("nl", "nl"),
+  ("no", "no"),
+  ("nonspacingmark", "mn"),
+  ("number", "n"),
+  ("openpunctuation", "ps"),
+  ("other", "c"),
+  ("otherletter", "lo"),
+  ("othernumber", "no"),
+  ("otherpunctuation", "po"),
+  ("othersymbol", "so"),
+  ("p", "p"),
+  ("paragraphseparator", "zp"),
+  ("pc", "pc"),
+  ("pd", "pd"),
+  ("pe", "pe"),
+  ("pf", "pf"),
+  ("pi", "pi"),
+  ("po", "po"),
+  ("privateuse", "co"),
+  ("ps", "ps"),
+  ("punct", "p"),
+  ("punctuation", "p"),
+  ("s", "s"),
+  ("sc", "sc"),
+  ("separator", "z"),
+  ("sk", "sk"),
+  ("sm", "sm"),
+  ("so", "so"),
+  ("spaceseparator", "zs"),
+  ("spacingmark", "mc"),
+  ("surrogate", "cs"),
+  ("symbol", "s"),
+  ("titlecaseletter", "lt"),
+  ("unassigned", "cn"),
+  ("uppercaseletter", "lu"),
+  ("z", "z"),
+  ("zl", "zl"),
+  ("zp", "zp"),
+  ("zs", "zs")
+];
+
+/// Scripts, by normalized long name
+pub static Scripts: &'static [(&'static str, &'static [(char, char)])] = &[
+  ("adlam", &[
+    ('\U0001E900', '\U0001E94B'),
+    ('\U0001E950', '\U0001E959'),
+    ('\U0001E95E', '\U0001E95F')
+  ]),
+  ("ahom", &[
+    ('\U00011700', '\U0001171A'),
+    ('\U0001171D', '\U0001172B'),
+    ('\U00011730', '\U00011746')
+  ]),
+  ("anatolianhieroglyphs", &[
+    ('\U00014400', '\U00014646')
+  ]),
+  ("arabic", &[
+    ('\u0600', '\u0604'),
+    ('\u0606', '\u060B'),
+    ('\u060D', '\u061A'),
+    ('\u061C', '\u061E'),
+    ('\u0620', '\u063F'),
+    ('\u0641', '\u064A'),
+    ('\u0656', '\u066F'),
+    ('\u0671', '\u06DC'),
+    ('\u06DE', '\u06FF'),
+    ('\u0750', '\u077F'),
+    ('\u0870', '\u088E'),
+    ('\u0890', '\u0891'),
+    ('\u0898', '\u08E1'),
+    ('\u08E3', '\u08FF'),
+    ('\uFB50', '\uFBC2'),
+    ('\uFBD3', '\uFD3D'),
+    ('\uFD40', '\uFD8F'),
+    ('\uFD92', '\uFDC7'),
+    ('\uFDCF', '\uFDCF'),
+    ('\uFDF0', '\uFDFF'),
+    ('\uFE70', '\uFE74'),
+    ('\uFE76', '\uFEFC'),
+    ('\U00010E60', '\U00010E7E'),
+    ('\U0001EE00', '\U0001EE03'),
+    ('\U0001EE05', '\U0001EE1F'),
+    ('\U0001EE21', '\U0001EE22'),
+    ('\U0001EE24', '\U0001EE24'),
+    ('\U0001EE27', '\U0001EE27'),
+    ('\U0001EE29', '\U0001EE32'),
+    ('\U0001EE34', '\U0001EE37'),
+    ('\U0001EE39', '\U0001EE39'),
+    ('\U0001EE3B', '\U0001EE3B'),
+    ('\U0001EE42', '\U0001EE42'),
+    ('\U0001EE47', '\U0001EE47'),
+    ('\U0001EE49', '\U0001EE49'),
+    ('\U0001EE4B', '\U0001EE4B'),
+    ('\U0001EE4D', '\U0001EE4F'),
+    ('\U0001EE51', '\U0001EE52'),
+    ('\U0001EE54', '\U0001EE54'),
+    ('\U0001EE57', '\U0001EE57'),
+    ('\U0001EE59', '\U0001EE59'),
+    ('\U0001EE5B', '\U0001EE5B'),
+    ('\U0001EE5D', '\U0001EE5D'),
+    ('\U0001EE5F', '\U0001EE5F'),
+    ('\U0001EE61', '\U0001EE62'),
+    ('\U0001EE64', '\U0001EE64'),
+    ('\U0001EE67', '\U0001EE6A'),
+    ('\U0001EE6C', '\U0001EE72'),
+    ('\U0001EE74', '\U0001EE77'),
+    ('\U0001EE79', '\U0001EE7C'),
+    ('\U0001EE7E', '\U0001EE7E'),
+    ('\U0001EE80', '\U0001EE89'),
+    ('\U0001EE8B', '\U0001EE9B'),
+    ('\U0001EEA1', '\U0001EEA3'),
+    ('\U0001EEA5', '\U0001EEA9'),
+    ('\U0001EEAB', '\U0001EEBB'),
+    ('\U0001EEF0', '\U0001EEF1')
+  ]),
+  ("armenian", &[
+    ('\u0531', '\u0556'),
+    ('\u0559', '\u058A'),
+    ('\u058D', '\u058F'),
+    ('\uFB13', '\uFB17')
+  ]),
+  ("avestan", &[
+    ('\U00010B00', '\U00010B35'),
+    ('\U00010B39', '\U00010B3F')
+  ]),
+  ("balinese", &[
+    ('\u1B00', '\u1B4C'),
+    ('\u1B50', '\u1B7E')
+  ]),
+  ("bamum", &[
+    ('\uA6A0', '\uA6F7'),
+    ('\U00016800', '\U00016A38')
+  ]),
+  ("bassavah", &[
+    ('\U00016AD0', '\U00016AED'),
+    ('\U00016AF0', '\U00016AF5')
+  ]),
+  ("batak", &[
+    ('\u1BC0', '\u1BF3'),
+    ('\u1BFC', '\u1BFF')
+  ]),
+  ("bengali", &[
+    ('\u0980', '\u0983'),
+    ('\u0985', '\u098C'),
+    ('\u098F', '\u0990'),
+    ('\u0993', '\u09A8'),
+    ('\u09AA', '\u09B0'),
+    ('\u09B2', '\u09B2'),
+    ('\u09B6', '\u09B9'),
+    ('\u09BC', '\u09C4'),
+    ('\u09C7', '\u09C8'),
+    ('\u09CB', '\u09CE'),
+    ('\u09D7', '\u09D7'),
+    ('\u09DC', '\u09DD'),
+    ('\u09DF', '\u09E3'),
+    ('\u09E6', '\u09FE')
+  ]),
+  ("bhaiksuki", &[
+    ('\U00011C00', '\U00011C08'),
+    ('\U00011C0A', '\U00011C36'),
+    ('\U00011C38', '\U00011C45'),
+    ('\U00011C50', '\U00011C6C')
+  ]),
+  ("bopomofo", &[
+    ('\u02EA', '\u02EB'),
+    ('\u3105', '\u312F'),
+    ('\u31A0', '\u31BF')
+  ]),
+  ("brahmi", &[
+    ('\U00011000', '\U0001104D'),
+    ('\U00011052', '\U00011075'),
+    ('\U0001107F', '\U0001107F')
+  ]),
+  ("braille", &[
+    ('\u2800', '\u28FF')
+  ]),
+  ("buginese", &[
+    ('\u1A00', '\u1A1B'),
+    ('\u1A1E', '\u1A1F')
+  ]),
+  ("buhid", &[
+    ('\u1740', '\u1753')
+  ]),
+  ("canadianaboriginal", &[
+    ('\u1400', '\u167F'),
+    ('\u18B0', '\u18F5'),
+    ('\U00011AB0', '\U00011ABF')
+  ]),
+  ("carian", &[
+    ('\U000102A0', '\U000102D0')
+  ]),
+  ("caucasianalbanian", &[
+    ('\U00010530', '\U00010563'),
+    ('\U0001056F', '\U0001056F')
+  ]),
+  ("chakma", &[
+    ('\U00011100', '\U00011134'),
+    ('\U00011136', '\U00011147')
+  ]),
+  ("cham", &[
+    ('\uAA00', '\uAA36'),
+    ('\uAA40', '\uAA4D'),
+    ('\uAA50', '\uAA59'),
+    ('\uAA5C', '\uAA5F')
+  ]),
+  ("cherokee", &[
+    ('\u13A0', '\u13F5'),
+    ('\u13F8', '\u13FD'),
+    ('\uAB70', '\uABBF')
+  ]),
+  ("chorasmian", &[
+    ('\U00010FB0', '\U00010FCB')
+  ]),
+  ("common", &[
+    ('\u0000', '\u0040'),
+    ('\u005B', '\u0060'),
+    ('\u007B', '\u00A9'),
+    ('\u00AB', '\u00B9'),
+    ('\u00BB', '\u00BF'),
+    ('\u00D7', '\u00D7'),
+    ('\u00F7', '\u00F7'),
+    ('\u02B9', '\u02DF'),
+    ('\u02E5', '\u02E9'),
+    ('\u02EC', '\u02FF'),
+    ('\u0374', '\u0374'),
+    ('\u037E', '\u037E'),
+    ('\u0385', '\u0385'),
+    ('\u0387', '\u0387'),
+    ('\u0605', '\u0605'),
+    ('\u060C', '\u060C'),
+    ('\u061B', '\u061B'),
+    ('\u061F', '\u061F'),
+    ('\u0640', '\u0640'),
+    ('\u06DD', '\u06DD'),
+    ('\u08E2', '\u08E2'),
+    ('\u0964', '\u0965'),
+    ('\u0E3F', '\u0E3F'),
+    ('\u0FD5', '\u0FD8'),
+    ('\u10FB', '\u10FB'),
+    ('\u16EB', '\u16ED'),
+    ('\u1735', '\u1736'),
+    ('\u1802', '\u1803'),
+    ('\u1805', '\u1805'),
+    ('\u1CD3', '\u1CD3'),
+    ('\u1CE1', '\u1CE1'),
+    ('\u1CE9', '\u1CEC'),
+    ('\u1CEE', '\u1CF3'),
+    ('\u1CF5', '\u1CF7'),
+    ('\u1CFA', '\u1CFA'),
+    ('\u2000', '\u200B'),
+    ('\u200E', '\u2064'),
+    ('\u2066', '\u2070'),
+    ('\u2074', '\u207E'),
+    ('\u2080', '\u208E'),
+    ('\u20A0', '\u20C0'),
+    ('\u2100', '\u2125'),
+    ('\u2127', '\u2129'),
+    ('\u212C', '\u2131'),
+    ('\u2133', '\u214D'),
+    ('\u214F', '\u215F'),
+    ('\u2189', '\u218B'),
+    ('\u2190', '\u2426'),
+    ('\u2440', '\u244A'),
+    ('\u2460', '\u27FF'),
+    ('\u2900', '\u2B73'),
+    ('\u2B76', '\u2B95'),
+    ('\u2B97', '\u2BFF'),
+    ('\u2E00', '\u2E5D'),
+    ('\u2FF0', '\u2FFB'),
+    ('\u3000', '\u3004'),
+    ('\u3006', '\u3006'),
+    ('\u3008', '\u3020'),
+    ('\u3030', '\u3037'),
+    ('\u303C', '\u303F'),
+    ('\u309B', '\u309C'),
+    ('\u30A0', '\u30A0'),
+    ('\u30FB', '\u30FC'),
+    ('\u3190', '\u319F'),
+    ('\u31C0', '\u31E3'),
+    ('\u3220', '\u325F'),
+    ('\u327F', '\u32CF'),
+    ('\u32FF', '\u32FF'),
+    ('\u3358', '\u33FF'),
+    ('\u4DC0', '\u4DFF'),
+    ('\uA700', '\uA721'),
+    ('\uA788', '\uA78A'),
+    ('\uA830', '\uA839'),
+    ('\uA92E', '\uA92E'),
+    ('\uA9CF', '\uA9CF'),
+    ('\uAB5B', '\uAB5B'),
+    ('\uAB6A', '\uAB6B'),
+    ('\uFD3E', '\uFD3F'),
+    ('\uFE10', '\uFE19'),
+    ('\uFE30', '\uFE52'),
+    ('\uFE54', '\uFE66'),
+    ('\uFE68', '\uFE6B'),
+    ('\uFEFF', '\uFEFF'),
+    ('\uFF01', '\uFF20'),
+    ('\uFF3B', '\uFF40'),
+    ('\uFF5B', '\uFF65'),
+    ('\uFF70', '\uFF70'),
+    ('\uFF9E', '\uFF9F'),
+    ('\uFFE0', '\uFFE6'),
+    ('\uFFE8', '\uFFEE'),
+    ('\uFFF9', '\uFFFD'),
+    ('\U00010100', '\U00010102'),
+    ('\U00010107', '\U00010133'),
+    ('\U00010137', '\U0001013F'),
+    ('\U00010190', '\U0001019C'),
+    ('\U000101D0', '\U000101FC'),
+    ('\U000102E1', '\U000102FB'),
+    ('\U0001BCA0', '\U0001BCA3'),
+    ('\U0001CF50', '\U0001CFC3'),
+    ('\U0001D000', '\U0001D0F5'),
+    ('\U0001D100', '\U0001D126'),
+    ('\U0001D129', '\U0001D166'),
+    ('\U0001D16A', '\U0001D17A'),
+    ('\U0001D183', '\U0001D184'),
+    ('\U0001D18C', '\U0001D1A9'),
+    ('\U0001D1AE', '\U0001D1EA'),
+    ('\U0001D2E0', '\U0001D2F3'),
+    ('\U0001D300', '\U0001D356'),
+    ('\U0001D360', '\U0001D378'),
+    ('\U0001D400', '\U0001D454'),
+    ('\U0001D456', '\U0001D49C'),
+    ('\U0001D49E', '\U0001D49F'),
+    ('\U0001D4A2', '\U0001D4A2'),
+    ('\U0001D4A5', '\U0001D4A6'),
+    ('\U0001D4A9', '\U0001D4AC'),
+    ('\U0001D4AE', '\U0001D4B9'),
+    ('\U0001D4BB', '\U0001D4BB'),
+    ('\U0001D4BD', '\U0001D4C3'),
+    ('\U0001D4C5', '\U0001D505'),
+    ('\U0001D507', '\U0001D50A'),
+    ('\U0001D50D', '\U0001D514'),
+    ('\U0001D516', '\U0001D51C'),
+    ('\U0001D51E', '\U0001D539'),
+    ('\U0001D53B', '\U0001D53E'),
+    ('\U0001D540', '\U0001D544'),
+    ('\U0001D546', '\U0001D546'),
+    ('\U0001D54A', '\U0001D550'),
+    ('\U0001D552', '\U0001D6A5'),
+    ('\U0001D6A8', '\U0001D7CB'),
+    ('\U0001D7CE', '\U0001D7FF'),
+    ('\U0001EC71', '\U0001ECB4'),
+    ('\U0001ED01', '\U0001ED3D'),
+    ('\U0001F000', '\U0001F02B'),
+    ('\U0001F030', '\U0001F093'),
+    ('\U0001F0A0', '\U0001F0AE'),
+    ('\U0001F0B1', '\U0001F0BF'),
+    ('\U0001F0C1', '\U0001F0CF'),
+    ('\U0001F0D1', '\U0001F0F5'),
+    ('\U0001F100', '\U0001F1AD'),
+    ('\U0001F1E6', '\U0001F1FF'),
+    ('\U0001F201', '\U0001F202'),
+    ('\U0001F210', '\U0001F23B'),
+    ('\U0001F240', '\U0001F248'),
+    ('\U0001F250', '\U0001F251'),
+    ('\U0001F260', '\U0001F265'),
+    ('\U0001F300', '\U0001F6D7'),
+    ('\U0001F6DD', '\U0001F6EC'),
+    ('\U0001F6F0', '\U0001F6FC'),
+    ('\U0001F700', '\U0001F773'),
+    ('\U0001F780', '\U0001F7D8'),
+    ('\U0001F7E0', '\U0001F7EB'),
+    ('\U0001F7F0', '\U0001F7F0'),
+    ('\U0001F800', '\U0001F80B'),
+    ('\U0001F810', '\U0001F847'),
+    ('\U0001F850', '\U0001F859'),
+    ('\U0001F860', '\U0001F887'),
+    ('\U0001F890', '\U0001F8AD'),
+    ('\U0001F8B0', '\U0001F8B1'),
+    ('\U0001F900', '\U0001FA53'),
+    ('\U0001FA60', '\U0001FA6D'),
+    ('\U0001FA70', '\U0001FA74'),
+    ('\U0001FA78', '\U0001FA7C'),
+    ('\U0001FA80', '\U0001FA86'),
+    ('\U0001FA90', '\U0001FAAC'),
+    ('\U0001FAB0', '\U0001FABA'),
+    ('\U0001FAC0', '\U0001FAC5'),
+    ('\U0001FAD0', '\U0001FAD9'),
+    ('\U0001FAE0', '\U0001FAE7'),
+    ('\U0001FAF0', '\U0001FAF6'),
+    ('\U0001FB00', '\U0001FB92'),
+    ('\U0001FB94', '\U0001FBCA'),
+    ('\U0001FBF0', '\U0001FBF9'),
+    ('\U000E0001', '\U000E0001'),
+    ('\U000E0020', '\U000E007F')
+  ]),
+  ("coptic", &[
+    ('\u03E2', '\u03EF'),
+    ('\u2C80', '\u2CF3'),
+    ('\u2CF9', '\u2CFF')
+  ]),
+  ("cuneiform", &[
+    ('\U00012000', '\U00012399'),
+    ('\U00012400', '\U0001246E'),
+    ('\U00012470', '\U00012474'),
+    ('\U00012480', '\U00012543')
+  ]),
+  ("cypriot", &[
+    ('\U00010800', '\U00010805'),
+    ('\U00010808', '\U00010808'),
+    ('\U0001080A', '\U00010835'),
+    ('\U00010837', '\U00010838'),
+    ('\U0001083C', '\U0001083C'),
+    ('\U0001083F', '\U0001083F')
+  ]),
+  ("cyprominoan", &[
+    ('\U00012F90', '\U00012FF2')
+  ]),
+  ("cyrillic", &[
+    ('\u0400', '\u0484'),
+    ('\u0487', '\u052F'),
+    ('\u1C80', '\u1C88'),
+    ('\u1D2B', '\u1D2B'),
+    ('\u1D78', '\u1D78'),
+    ('\u2DE0', '\u2DFF'),
+    ('\uA640', '\uA69F'),
+    ('\uFE2E', '\uFE2F')
+  ]),
+  ("deseret", &[
+    ('\U00010400', '\U0001044F')
+  ]),
+  ("devanagari", &[
+    ('\u0900', '\u0950'),
+    ('\u0955', '\u0963'),
+    ('\u0966', '\u097F'),
+    ('\uA8E0', '\uA8FF')
+  ]),
+  ("divesakuru", &[
+    ('\U00011900', '\U00011906'),
+    ('\U00011909', '\U00011909'),
+    ('\U0001190C', '\U00011913'),
+    ('\U00011915', '\U00011916'),
+    ('\U00011918', '\U00011935'),
+    ('\U00011937', '\U00011938'),
+    ('\U0001193B', '\U00011946'),
+    ('\U00011950', '\U00011959')
+  ]),
+  ("dogra", &[
+    ('\U00011800', '\U0001183B')
+  ]),
+  ("duployan", &[
+    ('\U0001BC00', '\U0001BC6A'),
+    ('\U0001BC70', '\U0001BC7C'),
+    ('\U0001BC80', '\U0001BC88'),
+    ('\U0001BC90', '\U0001BC99'),
+    ('\U0001BC9C', '\U0001BC9F')
+  ]),
+  ("egyptianhieroglyphs", &[
+    ('\U00013000', '\U0001342E'),
+    ('\U00013430', '\U00013438')
+  ]),
+  ("elbasan", &[
+    ('\U00010500', '\U00010527')
+  ]),
+  ("elymaic", &[
+    ('\U00010FE0', '\U00010FF6')
+  ]),
+  ("ethiopic", &[
+    ('\u1200', '\u1248'),
+    ('\u124A', '\u124D'),
+    ('\u1250', '\u1256'),
+    ('\u1258', '\u1258'),
+    ('\u125A', '\u125D'),
+    ('\u1260', '\u1288'),
+    ('\u128A', '\u128D'),
+    ('\u1290', '\u12B0'),
+    ('\u12B2', '\u12B5'),
+    ('\u12B8', '\u12BE'),
+    ('\u12C0', '\u12C0'),
+    ('\u12C2', '\u12C5'),
+    ('\u12C8', '\u12D6'),
+    ('\u12D8', '\u1310'),
+    ('\u1312', '\u1315'),
+    ('\u1318', '\u135A'),
+    ('\u135D', '\u137C'),
+    ('\u1380', '\u1399'),
+    ('\u2D80', '\u2D96'),
+    ('\u2DA0', '\u2DA6'),
+    ('\u2DA8', '\u2DAE'),
+    ('\u2DB0', '\u2DB6'),
+    ('\u2DB8', '\u2DBE'),
+    ('\u2DC0', '\u2DC6'),
+    ('\u2DC8', '\u2DCE'),
+    ('\u2DD0', '\u2DD6'),
+    ('\u2DD8', '\u2DDE'),
+    ('\uAB01', '\uAB06'),
+    ('\uAB09', '\uAB0E'),
+    ('\uAB11', '\uAB16'),
+    ('\uAB20', '\uAB26'),
+    ('\uAB28', '\uAB2E'),
+    ('\U0001E7E0', '\U0001E7E6'),
+    ('\U0001E7E8', '\U0001E7EB'),
+    ('\U0001E7ED', '\U0001E7EE'),
+    ('\U0001E7F0', '\U0001E7FE')
+  ]),
+  ("georgian", &[
+    ('\u10A0', '\u10C5'),
+    ('\u10C7', '\u10C7'),
+    ('\u10CD', '\u10CD'),
+    ('\u10D0', '\u10FA'),
+    ('\u10FC', '\u10FF'),
+    ('\u1C90', '\u1CBA'),
+    ('\u1CBD', '\u1CBF'),
+    ('\u2D00', '\u2D25'),
+    ('\u2D27', '\u2D27'),
+    ('\u2D2D', '\u2D2D')
+  ]),
+  ("glagolitic", &[
+    ('\u2C00', '\u2C5F'),
+    ('\U0001E000', '\U0001E006'),
+    ('\U0001E008', '\U0001E018'),
+    ('\U0001E01B', '\U0001E021'),
+    ('\U0001E023', '\U0001E024'),
+    ('\U0001E026', '\U0001E02A')
+  ]),
+  ("gothic", &[
+    ('\U00010330', '\U0001034A')
+  ]),
+  ("grantha", &[
+    ('\U00011300', '\U00011303'),
+    ('\U00011305', '\U0001130C'),
+    ('\U0001130F', '\U00011310'),
+    ('\U00011313', '\U00011328'),
+    ('\U0001132A', '\U00011330'),
+    ('\U00011332', '\U00011333'),
+    ('\U00011335', '\U00011339'),
+    ('\U0001133C', '\U00011344'),
+    ('\U00011347', '\U00011348'),
+    ('\U0001134B', '\U0001134D'),
+    ('\U00011350', '\U00011350'),
+    ('\U00011357', '\U00011357'),
+    ('\U0001135D', '\U00011363'),
+    ('\U00011366', '\U0001136C'),
+    ('\U00011370', '\U00011374')
+  ]),
+  ("greek", &[
+    ('\u0370', '\u0373'),
+    ('\u0375', '\u0377'),
+    ('\u037A', '\u037D'),
+    ('\u037F', '\u037F'),
+    ('\u0384', '\u0384'),
+    ('\u0386', '\u0386'),
+    ('\u0388', '\u038A'),
+    ('\u038C', '\u038C'),
+    ('\u038E', '\u03A1'),
+    ('\u03A3', '\u03E1'),
+    ('\u03F0', '\u03FF'),
+    ('\u1D26', '\u1D2A'),
+    ('\u1D5D', '\u1D61'),
+    ('\u1D66', '\u1D6A'),
+    ('\u1DBF', '\u1DBF'),
+    ('\u1F00', '\u1F15'),
+    ('\u1F18', '\u1F1D'),
+    ('\u1F20', '\u1F45'),
+    ('\u1F48', '\u1F4D'),
+    ('\u1F50', '\u1F57'),
+    ('\u1F59', '\u1F59'),
+    ('\u1F5B', '\u1F5B'),
+    ('\u1F5D', '\u1F5D'),
+    ('\u1F5F', '\u1F7D'),
+    ('\u1F80', '\u1FB4'),
+    ('\u1FB6', '\u1FC4'),
+    ('\u1FC6', '\u1FD3'),
+    ('\u1FD6', '\u1FDB'),
+    ('\u1FDD', '\u1FEF'),
+    ('\u1FF2', '\u1FF4'),
+    ('\u1FF6', '\u1FFE'),
+    ('\u2126', '\u2126'),
+    ('\uAB65', '\uAB65'),
+    ('\U00010140', '\U0001018E'),
+    ('\U000101A0', '\U000101A0'),
+    ('\U0001D200', '\U0001D245')
+  ]),
+  ("gujarati", &[
+    ('\u0A81', '\u0A83'),
+    ('\u0A85', '\u0A8D'),
+    ('\u0A8F', '\u0A91'),
+    ('\u0A93', '\u0AA8'),
+    ('\u0AAA', '\u0AB0'),
+    ('\u0AB2', '\u0AB3'),
+    ('\u0AB5', '\u0AB9'),
+    ('\u0ABC', '\u0AC5'),
+    ('\u0AC7', '\u0AC9'),
+    ('\u0ACB', '\u0ACD'),
+    ('\u0AD0', '\u0AD0'),
+    ('\u0AE0', '\u0AE3'),
+    ('\u0AE6', '\u0AF1'),
+    ('\u0AF9', '\u0AFF')
+  ]),
+  ("gunjalagondi", &[
+    ('\U00011D60', '\U00011D65'),
+    ('\U00011D67', '\U00011D68'),
+    ('\U00011D6A', '\U00011D8E'),
+    ('\U00011D90', '\U00011D91'),
+    ('\U00011D93', '\U00011D98'),
+    ('\U00011DA0', '\U00011DA9')
+  ]),
+  ("gurmukhi", &[
+    ('\u0A01', '\u0A03'),
+    ('\u0A05', '\u0A0A'),
+    ('\u0A0F', '\u0A10'),
+    ('\u0A13', '\u0A28'),
+    ('\u0A2A', '\u0A30'),
+    ('\u0A32', '\u0A33'),
+    ('\u0A35', '\u0A36'),
+    ('\u0A38', '\u0A39'),
+    ('\u0A3C', '\u0A3C'),
+    ('\u0A3E', '\u0A42'),
+    ('\u0A47', '\u0A48'),
+    ('\u0A4B', '\u0A4D'),
+    ('\u0A51', '\u0A51'),
+    ('\u0A59', '\u0A5C'),
+    ('\u0A5E', '\u0A5E'),
+    ('\u0A66', '\u0A76')
+  ]),
+  ("han", &[
+    ('\u2E80', '\u2E99'),
+    ('\u2E9B', '\u2EF3'),
+    ('\u2F00', '\u2FD5'),
+    ('\u3005', '\u3005'),
+    ('\u3007', '\u3007'),
+    ('\u3021', '\u3029'),
+    ('\u3038', '\u303B'),
+    ('\u3400', '\u4DBF'),
+    ('\u4E00', '\u9FFF'),
+    ('\uF900', '\uFA6D'),
+    ('\uFA70', '\uFAD9'),
+    ('\U00016FE2', '\U00016FE3'),
+    ('\U00016FF0', '\U00016FF1'),
+    ('\U00020000', '\U0002A6DF'),
+    ('\U0002A700', '\U0002B738'),
+    ('\U0002B740', '\U0002B81D'),
+    ('\U0002B820', '\U0002CEA1'),
+    ('\U0002CEB0', '\U0002EBE0'),
+    ('\U0002F800', '\U0002FA1D'),
+    ('\U00030000', '\U0003134A')
+  ]),
+  ("hangul", &[
+    ('\u1100', '\u11FF'),
+    ('\u302E', '\u302F'),
+    ('\u3131', '\u318E'),
+    ('\u3200', '\u321E'),
+    ('\u3260', '\u327E'),
+    ('\uA960', '\uA97C'),
+    ('\uAC00', '\uD7A3'),
+    ('\uD7B0', '\uD7C6'),
+    ('\uD7CB', '\uD7FB'),
+    ('\uFFA0', '\uFFBE'),
+    ('\uFFC2', '\uFFC7'),
+    ('\uFFCA', '\uFFCF'),
+    ('\uFFD2', '\uFFD7'),
+    ('\uFFDA', '\uFFDC')
+  ]),
+  ("hanifirohingya", &[
+    ('\U00010D00', '\U00010D27'),
+    ('\U00010D30', '\U00010D39')
+  ]),
+  ("hanunoo", &[
+    ('\u1720', '\u1734')
+  ]),
+  ("hatran", &[
+    ('\U000108E0', '\U000108F2'),
+    ('\U000108F4', '\U000108F5'),
+    ('\U000108FB', '\U000108FF')
+  ]),
+  ("hebrew", &[
+    ('\u0591', '\u05C7'),
+    ('\u05D0', '\u05EA'),
+    ('\u05EF', '\u05F4'),
+    ('\uFB1D', '\uFB36'),
+    ('\uFB38', '\uFB3C'),
+    ('\uFB3E', '\uFB3E'),
+    ('\uFB40', '\uFB41'),
+    ('\uFB43', '\uFB44'),
+    ('\uFB46', '\uFB4F')
+  ]),
+  ("hiragana", &[
+    ('\u3041', '\u3096'),
+    ('\u309D', '\u309F'),
+    ('\U0001B001', '\U0001B11F'),
+    ('\U0001B150', '\U0001B152'),
+    ('\U0001F200', '\U0001F200')
+  ]),
+  ("imperialaramaic", &[
+    ('\U00010840', '\U00010855'),
+    ('\U00010857', '\U0001085F')
+  ]),
+  ("inherited", &[
+    ('\u0300', '\u036F'),
+    ('\u0485', '\u0486'),
+    ('\u064B', '\u0655'),
+    ('\u0670', '\u0670'),
+    ('\u0951', '\u0954'),
+    ('\u1AB0', '\u1ACE'),
+    ('\u1CD0', '\u1CD2'),
+    ('\u1CD4', '\u1CE0'),
+    ('\u1CE2', '\u1CE8'),
+    ('\u1CED', '\u1CED'),
+    ('\u1CF4', '\u1CF4'),
+    ('\u1CF8', '\u1CF9'),
+    ('\u1DC0', '\u1DFF'),
+    ('\u200C', '\u200D'),
+    ('\u20D0', '\u20F0'),
+    ('\u302A', '\u302D'),
+    ('\u3099', '\u309A'),
+    ('\uFE00', '\uFE0F'),
+    ('\uFE20', '\uFE2D'),
+    ('\U000101FD', '\U000101FD'),
+    ('\U000102E0', '\U000102E0'),
+    ('\U0001133B', '\U0001133B'),
+    ('\U0001CF00', '\U0001CF2D'),
+    ('\U0001CF30', '\U0001CF46'),
+    ('\U0001D167', '\U0001D169'),
+    ('\U0001D17B', '\U0001D182'),
+    ('\U0001D185', '\U0001D18B'),
+    ('\U0001D1AA', '\U0001D1AD'),
+    ('\U000E0100', '\U000E01EF')
+  ]),
+  ("inscriptionalpahlavi", &[
+    ('\U00010B60', '\U00010B72'),
+    ('\U00010B78', '\U00010B7F')
+  ]),
+  ("inscriptionalparthian", &[
+    ('\U00010B40', '\U00010B55'),
+    ('\U00010B58', '\U00010B5F')
+  ]),
+  ("javanese", &[
+    ('\uA980', '\uA9CD'),
+    ('\uA9D0', '\uA9D9'),
+    ('\uA9DE', '\uA9DF')
+  ]),
+  ("kaithi", &[
+    ('\U00011080', '\U000110C2'),
+    ('\U000110CD', '\U000110CD')
+  ]),
+  ("kannada", &[
+    ('\u0C80', '\u0C8C'),
+    ('\u0C8E', '\u0C90'),
+    ('\u0C92', '\u0CA8'),
+    ('\u0CAA', '\u0CB3'),
+    ('\u0CB5', '\u0CB9'),
+    ('\u0CBC', '\u0CC4'),
+    ('\u0CC6', '\u0CC8'),
+    ('\u0CCA', '\u0CCD'),
+    ('\u0CD5', '\u0CD6'),
+    ('\u0CDD', '\u0CDE'),
+    ('\u0CE0', '\u0CE3'),
+    ('\u0CE6', '\u0CEF'),
+    ('\u0CF1', '\u0CF2')
+  ]),
+  ("katakana", &[
+    ('\u30A1', '\u30FA'),
+    ('\u30FD', '\u30FF'),
+    ('\u31F0', '\u31FF'),
+    ('\u32D0', '\u32FE'),
+    ('\u3300', '\u3357'),
+    ('\uFF66', '\uFF6F'),
+    ('\uFF71', '\uFF9D'),
+    ('\U0001AFF0', '\U0001AFF3'),
+    ('\U0001AFF5', '\U0001AFFB'),
+    ('\U0001AFFD', '\U0001AFFE'),
+    ('\U0001B000', '\U0001B000'),
+    ('\U0001B120', '\U0001B122'),
+    ('\U0001B164', '\U0001B167')
+  ]),
+  ("kayahli", &[
+    ('\uA900', '\uA92D'),
+    ('\uA92F', '\uA92F')
+  ]),
+  ("kharoshthi", &[
+    ('\U00010A00', '\U00010A03'),
+    ('\U00010A05', '\U00010A06'),
+    ('\U00010A0C', '\U00010A13'),
+    ('\U00010A15', '\U00010A17'),
+    ('\U00010A19', '\U00010A35'),
+    ('\U00010A38', '\U00010A3A'),
+    ('\U00010A3F', '\U00010A48'),
+    ('\U00010A50', '\U00010A58')
+  ]),
+  ("khitansmallscript", &[
+    ('\U00016FE4', '\U00016FE4'),
+    ('\U00018B00', '\U00018CD5')
+  ]),
+  ("khmer", &[
+    ('\u1780', '\u17DD'),
+    ('\u17E0', '\u17E9'),
+    ('\u17F0', '\u17F9'),
+    ('\u19E0', '\u19FF')
+  ]),
+  ("khojki", &[
+    ('\U00011200', '\U00011211'),
+    ('\U00011213', '\U0001123E')
+  ]),
+  ("khudawadi", &[
+    ('\U000112B0', '\U000112EA'),
+    ('\U000112F0', '\U000112F9')
+  ]),
+  ("lao", &[
+    ('\u0E81', '\u0E82'),
+    ('\u0E84', '\u0E84'),
+    ('\u0E86', '\u0E8A'),
+    ('\u0E8C', '\u0EA3'),
+    ('\u0EA5', '\u0EA5'),
+    ('\u0EA7', '\u0EBD'),
+    ('\u0EC0', '\u0EC4'),
+    ('\u0EC6', '\u0EC6'),
+    ('\u0EC8', '\u0ECD'),
+    ('\u0ED0', '\u0ED9'),
+    ('\u0EDC', '\u0EDF')
+  ]),
+  ("latin", &[
+    ('\u0041', '\u005A'),
+    ('\u0061', '\u007A'),
+    ('\u00AA', '\u00AA'),
+    ('\u00BA', '\u00BA'),
+    ('\u00C0', '\u00D6'),
+    ('\u00D8', '\u00F6'),
+    ('\u00F8', '\u02B8'),
+    ('\u02E0', '\u02E4'),
+    ('\u1D00', '\u1D25'),
+    ('\u1D2C', '\u1D5C'),
+    ('\u1D62', '\u1D65'),
+    ('\u1D6B', '\u1D77'),
+    ('\u1D79', '\u1DBE'),
+    ('\u1E00', '\u1EFF'),
+    ('\u2071', '\u2071'),
+    ('\u207F', '\u207F'),
+    ('\u2090', '\u209C'),
+    ('\u212A', '\u212B'),
+    ('\u2132', '\u2132'),
+    ('\u214E', '\u214E'),
+    ('\u2160', '\u2188'),
+    ('\u2C60', '\u2C7F'),
+    ('\uA722', '\uA787'),
+    ('\uA78B', '\uA7CA'),
+    ('\uA7D0', '\uA7D1'),
+    ('\uA7D3', '\uA7D3'),
+    ('\uA7D5', '\uA7D9'),
+    ('\uA7F2', '\uA7FF'),
+    ('\uAB30', '\uAB5A'),
+    ('\uAB5C', '\uAB64'),
+    ('\uAB66', '\uAB69'),
+    ('\uFB00', '\uFB06'),
+    ('\uFF21', '\uFF3A'),
+    ('\uFF41', '\uFF5A'),
+    ('\U00010780', '\U00010785'),
+    ('\U00010787', '\U000107B0'),
+    ('\U000107B2', '\U000107BA'),
+    ('\U0001DF00', '\U0001DF1E')
+  ]),
+  ("lepcha", &[
+    ('\u1C00', '\u1C37'),
+    ('\u1C3B', '\u1C49'),
+    ('\u1C4D', '\u1C4F')
+  ]),
+  ("limbu", &[
+    ('\u1900', '\u191E'),
+    ('\u1920', '\u192B'),
+    ('\u1930', '\u193B'),
+    ('\u1940', '\u1940'),
+    ('\u1944', '\u194F')
+  ]),
+  ("lineara", &[
+    ('\U00010600', '\U00010736'),
+    ('\U00010740', '\U00010755'),
+    ('\U00010760', '\U00010767')
+  ]),
+  ("linearb", &[
+    ('\U00010000', '\U0001000B'),
+    ('\U0001000D', '\U00010026'),
+    ('\U00010028', '\U0001003A'),
+    ('\U0001003C', '\U0001003D'),
+    ('\U0001003F', '\U0001004D'),
+    ('\U00010050', '\U0001005D'),
+    ('\U00010080', '\U000100FA')
+  ]),
+  ("lisu", &[
+    ('\uA4D0', '\uA4FF'),
+    ('\U00011FB0', '\U00011FB0')
+  ]),
+  ("lycian", &[
+    ('\U00010280', '\U0001029C')
+  ]),
+  ("lydian", &[
+    ('\U00010920', '\U00010939'),
+    ('\U0001093F', '\U0001093F')
+  ]),
+  ("mahajani", &[
+    ('\U00011150', '\U00011176')
+  ]),
+  ("makasar", &[
+    ('\U00011EE0', '\U00011EF8')
+  ]),
+  ("malayalam", &[
+    ('\u0D00', '\u0D0C'),
+    ('\u0D0E', '\u0D10'),
+    ('\u0D12', '\u0D44'),
+    ('\u0D46', '\u0D48'),
+    ('\u0D4A', '\u0D4F'),
+    ('\u0D54', '\u0D63'),
+    ('\u0D66', '\u0D7F')
+  ]),
+  ("mandaic", &[
+    ('\u0840', '\u085B'),
+    ('\u085E', '\u085E')
+  ]),
+  ("manichaean", &[
+    ('\U00010AC0', '\U00010AE6'),
+    ('\U00010AEB', '\U00010AF6')
+  ]),
+  ("marchen", &[
+    ('\U00011C70', '\U00011C8F'),
+    ('\U00011C92', '\U00011CA7'),
+    ('\U00011CA9', '\U00011CB6')
+  ]),
+  ("masaramgondi", &[
+    ('\U00011D00', '\U00011D06'),
+    ('\U00011D08', '\U00011D09'),
+    ('\U00011D0B', '\U00011D36'),
+    ('\U00011D3A', '\U00011D3A'),
+    ('\U00011D3C', '\U00011D3D'),
+    ('\U00011D3F', '\U00011D47'),
+    ('\U00011D50', '\U00011D59')
+  ]),
+  ("medefaidrin", &[
+    ('\U00016E40', '\U00016E9A')
+  ]),
+  ("meeteimayek", &[
+    ('\uAAE0', '\uAAF6'),
+    ('\uABC0', '\uABED'),
+    ('\uABF0', '\uABF9')
+  ]),
+  ("mendekikakui", &[
+    ('\U0001E800', '\U0001E8C4'),
+    ('\U0001E8C7', '\U0001E8D6')
+  ]),
+  ("meroiticcursive", &[
+    ('\U000109A0', '\U000109B7'),
+    ('\U000109BC', '\U000109CF'),
+    ('\U000109D2', '\U000109FF')
+  ]),
+  ("meroitichieroglyphs", &[
+    ('\U00010980', '\U0001099F')
+  ]),
+  ("miao", &[
+    ('\U00016F00', '\U00016F4A'),
+    ('\U00016F4F', '\U00016F87'),
+    ('\U00016F8F', '\U00016F9F')
+  ]),
+  ("modi", &[
+    ('\U00011600', '\U00011644'),
+    ('\U00011650', '\U00011659')
+  ]),
+  ("mongolian", &[
+    ('\u1800', '\u1801'),
+    ('\u1804', '\u1804'),
+    ('\u1806', '\u1819'),
+    ('\u1820', '\u1878'),
+    ('\u1880', '\u18AA'),
+    ('\U00011660', '\U0001166C')
+  ]),
+  ("mro", &[
+    ('\U00016A40', '\U00016A5E'),
+    ('\U00016A60', '\U00016A69'),
+    ('\U00016A6E', '\U00016A6F')
+  ]),
+  ("multani", &[
+    ('\U00011280', '\U00011286'),
+    ('\U00011288', '\U00011288'),
+    ('\U0001128A', '\U0001128D'),
+    ('\U0001128F', '\U0001129D'),
+    ('\U0001129F', '\U000112A9')
+  ]),
+  ("myanmar", &[
+    ('\u1000', '\u109F'),
+    ('\uA9E0', '\uA9FE'),
+    ('\uAA60', '\uAA7F')
+  ]),
+  ("nabataean", &[
+    ('\U00010880', '\U0001089E'),
+    ('\U000108A7', '\U000108AF')
+  ]),
+  ("nandinagari", &[
+    ('\U000119A0', '\U000119A7'),
+    ('\U000119AA', '\U000119D7'),
+    ('\U000119DA', '\U000119E4')
+  ]),
+  ("newa", &[
+    ('\U00011400', '\U0001145B'),
+    ('\U0001145D', '\U00011461')
+  ]),
+  ("newtailue", &[
+    ('\u1980', '\u19AB'),
+    ('\u19B0', '\u19C9'),
+    ('\u19D0', '\u19DA'),
+    ('\u19DE', '\u19DF')
+  ]),
+  ("nko", &[
+    ('\u07C0', '\u07FA'),
+    ('\u07FD', '\u07FF')
+  ]),
+  ("nushu", &[
+    ('\U00016FE1', '\U00016FE1'),
+    ('\U0001B170', '\U0001B2FB')
+  ]),
+  ("nyiakengpuachuehmong", &[
+    ('\U0001E100', '\U0001E12C'),
+    ('\U0001E130', '\U0001E13D'),
+    ('\U0001E140', '\U0001E149'),
+    ('\U0001E14E', '\U0001E14F')
+  ]),
+  ("ogham", &[
+    ('\u1680', '\u169C')
+  ]),
+  ("olchiki", &[
+    ('\u1C50', '\u1C7F')
+  ]),
+  ("oldhungarian", &[
+    ('\U00010C80', '\U00010CB2'),
+    ('\U00010CC0', '\U00010CF2'),
+    ('\U00010CFA', '\U00010CFF')
+  ]),
+  ("olditalic", &[
+    ('\U00010300', '\U00010323'),
+    ('\U0001032D', '\U0001032F')
+  ]),
+  ("oldnortharabian", &[
+    ('\U00010A80', '\U00010A9F')
+  ]),
+  ("oldpermic", &[
+    ('\U00010350', '\U0001037A')
+  ]),
+  ("oldpersian", &[
+    ('\U000103A0', '\U000103C3'),
+    ('\U000103C8', '\U000103D5')
+  ]),
+  ("oldsogdian", &[
+    ('\U00010F00', '\U00010F27')
+  ]),
+  ("oldsoutharabian", &[
+    ('\U00010A60', '\U00010A7F')
+  ]),
+  ("oldturkic", &[
+    ('\U00010C00', '\U00010C48')
+  ]),
+  ("olduyghur", &[
+    ('\U00010F70', '\U00010F89')
+  ]),
+  ("oriya", &[
+    ('\u0B01', '\u0B03'),
+    ('\u0B05', '\u0B0C'),
+    ('\u0B0F', '\u0B10'),
+    ('\u0B13', '\u0B28'),
+    ('\u0B2A', '\u0B30'),
+    ('\u0B32', '\u0B33'),
+    ('\u0B35', '\u0B39'),
+    ('\u0B3C', '\u0B44'),
+    ('\u0B47', '\u0B48'),
+    ('\u0B4B', '\u0B4D'),
+    ('\u0B55', '\u0B57'),
+    ('\u0B5C', '\u0B5D'),
+    ('\u0B5F', '\u0B63'),
+    ('\u0B66', '\u0B77')
+  ]),
+  ("osage", &[
+    ('\U000104B0', '\U000104D3'),
+    ('\U000104D8', '\U000104FB')
+  ]),
+  ("osmanya", &[
+    ('\U00010480', '\U0001049D'),
+    ('\U000104A0', '\U000104A9')
+  ]),
+  ("pahawhhmong", &[
+    ('\U00016B00', '\U00016B45'),
+    ('\U00016B50', '\U00016B59'),
+    ('\U00016B5B', '\U00016B61'),
+    ('\U00016B63', '\U00016B77'),
+    ('\U00016B7D', '\U00016B8F')
+  ]),
+  ("palmyrene", &[
+    ('\U00010860', '\U0001087F')
+  ]),
+  ("paucinhau", &[
+    ('\U00011AC0', '\U00011AF8')
+  ]),
+  ("phagspa", &[
+    ('\uA840', '\uA877')
+  ]),
+  ("phoenician", &[
+    ('\U00010900', '\U0001091B'),
+    ('\U0001091F', '\U0001091F')
+  ]),
+  ("psalterpahlavi", &[
+    ('\U00010B80', '\U00010B91'),
+    ('\U00010B99', '\U00010B9C'),
+    ('\U00010BA9', '\U00010BAF')
+  ]),
+  ("rejang", &[
+    ('\uA930', '\uA953'),
+    ('\uA95F', '\uA95F')
+  ]),
+  ("runic", &[
+    ('\u16A0', '\u16EA'),
+    ('\u16EE', '\u16F8')
+  ]),
+  ("samaritan", &[
+    ('\u0800', '\u082D'),
+    ('\u0830', '\u083E')
+  ]),
+  ("saurashtra", &[
+    ('\uA880', '\uA8C5'),
+    ('\uA8CE', '\uA8D9')
+  ]),
+  ("sharada", &[
+    ('\U00011180', '\U000111DF')
+  ]),
+  ("shavian", &[
+    ('\U00010450', '\U0001047F')
+  ]),
+  ("siddham", &[
+    ('\U00011580', '\U000115B5'),
+    ('\U000115B8', '\U000115DD')
+  ]),
+  ("signwriting", &[
+    ('\U0001D800', '\U0001DA8B'),
+    ('\U0001DA9B', '\U0001DA9F'),
+    ('\U0001DAA1', '\U0001DAAF')
+  ]),
+  ("sinhala", &[
+    ('\u0D81', '\u0D83'),
+    ('\u0D85', '\u0D96'),
+    ('\u0D9A', '\u0DB1'),
+    ('\u0DB3', '\u0DBB'),
+    ('\u0DBD', '\u0DBD'),
+    ('\u0DC0', '\u0DC6'),
+    ('\u0DCA', '\u0DCA'),
+    ('\u0DCF', '\u0DD4'),
+    ('\u0DD6', '\u0DD6'),
+    ('\u0DD8', '\u0DDF'),
+    ('\u0DE6', '\u0DEF'),
+    ('\u0DF2', '\u0DF4'),
+    ('\U000111E1', '\U000111F4')
+  ]),
+  ("sogdian", &[
+    ('\U00010F30', '\U00010F59')
+  ]),
+  ("sorasompeng", &[
+    ('\U000110D0', '\U000110E8'),
+    ('\U000110F0', '\U000110F9')
+  ]),
+  ("soyombo", &[
+    ('\U00011A50', '\U00011AA2')
+  ]),
+  ("sundanese", &[
+    ('\u1B80', '\u1BBF'),
+    ('\u1CC0', '\u1CC7')
+  ]),
+  ("sylotinagri", &[
+    ('\uA800', '\uA82C')
+  ]),
+  ("syriac", &[
+    ('\u0700', '\u070D'),
+    ('\u070F', '\u074A'),
+    ('\u074D', '\u074F'),
+    ('\u0860', '\u086A')
+  ]),
+  ("tagalog", &[
+    ('\u1700', '\u1715'),
+    ('\u171F', '\u171F')
+  ]),
+  ("tagbanwa", &[
+    ('\u1760', '\u176C'),
+    ('\u176E', '\u1770'),
+    ('\u1772', '\u1773')
+  ]),
+  ("taile", &[
+    ('\u1950', '\u196D'),
+    ('\u1970', '\u1974')
+  ]),
+  ("taitham", &[
+    ('\u1A20', '\u1A5E'),
+    ('\u1A60', '\u1A7C'),
+    ('\u1A7F', '\u1A89'),
+    ('\u1A90', '\u1A99'),
+    ('\u1AA0', '\u1AAD')
+  ]),
+  ("taiviet", &[
+    ('\uAA80', '\uAAC2'),
+    ('\uAADB', '\uAADF')
+  ]),
+  ("takri", &[
+    ('\U00011680', '\U000116B9'),
+    ('\U000116C0', '\U000116C9')
+  ]),
+  ("tamil", &[
+    ('\u0B82', '\u0B83'),
+    ('\u0B85', '\u0B8A'),
+    ('\u0B8E', '\u0B90'),
+    ('\u0B92', '\u0B95'),
+    ('\u0B99', '\u0B9A'),
+    ('\u0B9C', '\u0B9C'),
+    ('\u0B9E', '\u0B9F'),
+    ('\u0BA3', '\u0BA4'),
+    ('\u0BA8', '\u0BAA'),
+    ('\u0BAE', '\u0BB9'),
+    ('\u0BBE', '\u0BC2'),
+    ('\u0BC6', '\u0BC8'),
+    ('\u0BCA', '\u0BCD'),
+    ('\u0BD0', '\u0BD0'),
+    ('\u0BD7', '\u0BD7'),
+    ('\u0BE6', '\u0BFA'),
+    ('\U00011FC0', '\U00011FF1'),
+    ('\U00011FFF', '\U00011FFF')
+  ]),
+  ("tangsa", &[
+    ('\U00016A70', '\U00016ABE'),
+    ('\U00016AC0', '\U00016AC9')
+  ]),
+  ("tangut", &[
+    ('\U00016FE0', '\U00016FE0'),
+    ('\U00017000', '\U000187F7'),
+    ('\U00018800', '\U00018AFF'),
+    ('\U00018D00', '\U00018D08')
+  ]),
+  ("telugu", &[
+    ('\u0C00', '\u0C0C'),
+    ('\u0C0E', '\u0C10'),
+    ('\u0C12', '\u0C28'),
+    ('\u0C2A', '\u0C39'),
+    ('\u0C3C', '\u0C44'),
+    ('\u0C46', '\u0C48'),
+    ('\u0C4A', '\u0C4D'),
+    ('\u0C55', '\u0C56'),
+    ('\u0C58', '\u0C5A'),
+    ('\u0C5D', '\u0C5D'),
+    ('\u0C60', '\u0C63'),
+    ('\u0C66', '\u0C6F'),
+    ('\u0C77', '\u0C7F')
+  ]),
+  ("thaana", &[
+    ('\u0780', '\u07B1')
+  ]),
+  ("thai", &[
+    ('\u0E01', '\u0E3A'),
+    ('\u0E40', '\u0E5B')
+  ]),
+  ("tibetan", &[
+    ('\u0F00', '\u0F47'),
+    ('\u0F49', '\u0F6C'),
+    ('\u0F71', '\u0F97'),
+    ('\u0F99', '\u0FBC'),
+    ('\u0FBE', '\u0FCC'),
+    ('\u0FCE', '\u0FD4'),
+    ('\u0FD9', '\u0FDA')
+  ]),
+  ("tifinagh", &[
+    ('\u2D30', '\u2D67'),
+    ('\u2D6F', '\u2D70'),
+    ('\u2D7F', '\u2D7F')
+  ]),
+  ("tirhuta", &[
+    ('\U00011480', '\U000114C7'),
+    ('\U000114D0', '\U000114D9')
+  ]),
+  ("toto", &[
+    ('\U0001E290', '\U0001E2AE')
+  ]),
+  ("ugaritic", &[
+    ('\U00010380', '\U0001039D'),
+    ('\U0001039F', '\U0001039F')
+  ]),
+  ("vai", &[
+    ('\uA500', '\uA62B')
+  ]),
+  ("vithkuqi", &[
+    ('\U00010570', '\U0001057A'),
+    ('\U0001057C', '\U0001058A'),
+    ('\U0001058C', '\U00010592'),
+    ('\U00010594', '\U00010595'),
+    ('\U00010597', '\U000105A1'),
+    ('\U000105A3', '\U000105B1'),
+    ('\U000105B3', '\U000105B9'),
+    ('\U000105BB', '\U000105BC')
+  ]),
+  ("wancho", &[
+    ('\U0001E2C0', '\U0001E2F9'),
+    ('\U0001E2FF', '\U0001E2FF')
+  ]),
+  ("warangciti", &[
+    ('\U000118A0', '\U000118F2'),
+    ('\U000118FF', '\U000118FF')
+  ]),
+  ("yezidi", &[
+    ('\U00010E80', '\U00010EA9'),
+    ('\U00010EAB', '\U00010EAD'),
+    ('\U00010EB0', '\U00010EB1')
+  ]),
+  ("yi", &[
+    ('\uA000', '\uA48C'),
+    ('\uA490', '\uA4C6')
+  ]),
+  ("zanabazarsquare", &[
+    ('\U00011A00', '\U00011A47')
+  ])
+];
+
+/// Normalized names of scripts, mapped to their long name
+pub static ScriptAliases: &'static [(&'static str, &'static str)] = &[
+  ("adlam", "adlam"),
+  ("adlm", "adlam"),
+  ("aghb", "caucasianalbanian"),
+  ("ahom", "ahom"),
+  ("anatolianhieroglyphs", "anatolianhieroglyphs"),
+  ("arab", "arabic"),
+  ("arabic", "arabic"),
+  ("armenian", "armenian"),
+  ("armi", "imperialaramaic"),
+  ("armn", "armenian"),
+  ("avestan", "avestan"),
+  ("avst", "avestan"),
+  ("bali", "balinese"),
+  ("balinese", "balinese"),
+  ("bamu", "bamum"),
+  ("bamum", "bamum"),
+  ("bass", "bassavah"),
+  ("bassavah", "bassavah"),
+  ("batak", "batak"),
+  ("batk", "batak"),
+  ("beng", "bengali"),
+  ("bengali", "bengali"),
+  ("bhaiksuki", "bhaiksuki"),
+  ("bhks", "bhaiksuki"),
+  ("bopo", "bopomofo"),
+  ("bopomofo", "bopomofo"),
+  ("brah", "brahmi"),
+  ("brahmi", "brahmi"),
+  ("brai", "braille"),
+  ("braille", "braille"),
+  ("bugi", "buginese"),
+  ("buginese", "buginese"),
+  ("buhd", "buhid"),
+  ("buhid", "buhid"),
+  ("cakm", "chakma"),
+  ("canadianaboriginal", "canadianaboriginal"),
+  ("cans", "canadianaboriginal"),
+  ("cari", "carian"),
+  ("carian", "carian"),
+  ("caucasianalbanian", "caucasianalbanian"),
+  ("chakma", "chakma"),
+  ("cham", "cham"),
+  ("cher", "cherokee"),
+  ("cherokee", "cherokee"),
+  ("chorasmian", "chorasmian"),
+  ("chrs", "chorasmian"),
+  ("common", "common"),
+  ("copt", "coptic"),
+  ("coptic", "coptic"),
+  ("cpmn", "cyprominoan"),
+  ("cprt", "cypriot"),
+  ("cuneiform", "cuneiform"),
+  ("cypriot", "cypriot"),
+  ("cyprominoan", "cyprominoan"),
+  ("cyrillic", "cyrillic"),
+  ("cyrl", "cyrillic"),
+  ("deseret", "deseret"),
+  ("deva", "devanagari"),
+  ("devanagari", "devanagari"),
+  ("diak", "divesakuru"),
+  ("divesakuru", "divesakuru"),
+  ("dogr", "dogra"),
+  ("dogra", "dogra"),
+  ("dsrt", "deseret"),
+  ("dupl", "duployan"),
+  ("duployan", "duployan"),
+  ("egyp", "egyptianhieroglyphs"),
+  ("egyptianhieroglyphs", "egyptianhieroglyphs"),
+  ("elba", "elbasan"),
+  ("elbasan", "elbasan"),
+  ("elym", "elymaic"),
+  ("elymaic", "elymaic"),
+  ("ethi", "ethiopic"),
+  ("ethiopic", "ethiopic"),
+  ("geor", "georgian"),
+  ("georgian", "georgian"),
+  ("glag", "glagolitic"),
+  ("glagolitic", "glagolitic"),
+  ("gong", "gunjalagondi"),
+  ("gonm", "masaramgondi"),
+  ("goth", "gothic"),
+  ("gothic", "gothic"),
+  ("gran", "grantha"),
+  ("grantha", "grantha"),
+  ("greek", "greek"),
+  ("grek", "greek"),
+  ("gujarati", "gujarati"),
+  ("gujr", "gujarati"),
+  ("gunjalagondi", "gunjalagondi"),
+  ("gurmukhi", "gurmukhi"),
+  ("guru", "gurmukhi"),
+  ("han", "han"),
+  ("hang", "hangul"),
+  ("hangul", "hangul"),
+  ("hani", "han"),
+  ("hanifirohingya", "hanifirohingya"),
+  ("hano", "hanunoo"),
+  ("hanunoo", "hanunoo"),
+  ("hatr", "hatran"),
+  ("hatran", "hatran"),
+  ("hebr", "hebrew"),
+  ("hebrew", "hebrew"),
+  ("hira", "hiragana"),
+  ("hiragana", "hiragana"),
+  ("hluw", "anatolianhieroglyphs"),
+  ("hmng", "pahawhhmong"),
+  ("hmnp", "nyiakengpuachuehmong"),
+  ("hung", "oldhungarian"),
+  ("imperialaramaic", "imperialaramaic"),
+  ("inherited", "inherited"),
+  ("inscriptionalpahlavi", "inscriptionalpahlavi"),
+  ("inscriptionalparthian", "inscriptionalparthian"),
+  ("ital", "olditalic"),
+  ("java", "javanese"),
+  ("javanese", "javanese"),
+  ("kaithi", "kaithi"),
+  ("kali", "kayahli"),
+  ("kana", "katakana"),
+  ("kannada", "kannada"),
+  ("katakana", "katakana"),
+  ("kayahli", "kayahli"),
+  ("khar", "kharoshthi"),
+  ("kharoshthi", "kharoshthi"),
+  ("khitansmallscript", "khitansmallscript"),
+  ("khmer", "khmer"),
+  ("khmr", "khmer"),
+  ("khoj", "khojki"),
+  ("khojki", "khojki"),
+  ("khudawadi", "khudawadi"),
+  ("kits", "khitansmallscript"),
+  ("knda", "kannada"),
+  ("kthi", "kaithi"),
+  ("lana", "taitham"),
+  ("lao", "lao"),
+  ("laoo", "lao"),
+  ("latin", "latin"),
+  ("latn", "latin"),
+  ("lepc", "lepcha"),
+  ("lepcha", "lepcha"),
+  ("limb", "limbu"),
+  ("limbu", "limbu"),
+  ("lina", "lineara"),
+  ("linb", "linearb"),
+  ("lineara", "lineara"),
+  ("linearb", "linearb"),
+  ("lisu", "lisu"),
+  ("lyci", "lycian"),
+  ("lycian", "lycian"),
+  ("lydi", "lydian"),
+  ("lydian", "lydian"),
+  ("mahajani", "mahajani"),
+  ("mahj", "mahajani"),
+  ("maka", "makasar"),
+  ("makasar", "makasar"),
+  ("malayalam", "malayalam"),
+  ("mand", "mandaic"),
+  ("mandaic", "mandaic"),
+  ("mani", "manichaean"),
+  ("manichaean", "manichaean"),
+  ("marc", "marchen"),
+  ("marchen", "marchen"),
+  ("masaramgondi", "masaramgondi"),
+  ("medefaidrin", "medefaidrin"),
+  ("medf", "medefaidrin"),
+  ("meeteimayek", "meeteimayek"),
+  ("mend", "mendekikakui"),
+  ("mendekikakui", "mendekikakui"),
+  ("merc", "meroiticcursive"),
+  ("mero", "meroitichieroglyphs"),
+  ("meroiticcursive", "meroiticcursive"),
+  ("meroitichieroglyphs", "meroitichieroglyphs"),
+  ("miao", "miao"),
+  ("mlym", "malayalam"),
+  ("modi", "modi"),
+  ("mong", "mongolian"),
+  ("mongolian", "mongolian"),
+  ("mro", "mro"),
+  ("mroo", "mro"),
+  ("mtei", "meeteimayek"),
+  ("mult", "multani"),
+  ("multani", "multani"),
+  ("myanmar", "myanmar"),
+  ("mymr", "myanmar"),
+  ("nabataean", "nabataean"),
+  ("nand", "nandinagari"),
+  ("nandinagari", "nandinagari"),
+  ("narb", "oldnortharabian"),
+  ("nbat", "nabataean"),
+  ("newa", "newa"),
+  ("newtailue", "newtailue"),
+  ("nko", "nko"),
+  ("nkoo", "nko"),
+  ("nshu", "nushu"),
+  ("nushu", "nushu"),
+  ("nyiakengpuachuehmong", "nyiakengpuachuehmong"),
+  ("ogam", "ogham"),
+  ("ogham", "ogham"),
+  ("olchiki", "olchiki"),
+  ("olck", "olchiki"),
+  ("oldhungarian", "oldhungarian"),
+  ("olditalic", "olditalic"),
+  ("oldnortharabian", "oldnortharabian"),
+  ("oldpermic", "oldpermic"),
+  ("oldpersian", "oldpersian"),
+  ("oldsogdian", "oldsogdian"),
+  ("oldsoutharabian", "oldsoutharabian"),
+  ("oldturkic", "oldturkic"),
+  ("olduyghur", "olduyghur"),
+  ("oriya", "oriya"),
+  ("orkh", "oldturkic"),
+  ("orya", "oriya"),
+  ("osage", "osage"),
+  ("osge", "osage"),
+  ("osma", "osmanya"),
+  ("osmanya", "osmanya"),
+  ("ougr", "olduyghur"),
+  ("pahawhhmong", "pahawhhmong"),
+  ("palm", "palmyrene"),
+  ("palmyrene", "palmyrene"),
+  ("pauc", "paucinhau"),
+  ("paucinhau", "paucinhau"),
+  ("perm", "oldpermic"),
+  ("phag", "phagspa"),
+  ("phagspa", "phagspa"),
+  ("phli", "inscriptionalpahlavi"),
+  ("phlp", "psalterpahlavi"),
+  ("phnx", "phoenician"),
+  ("phoenician", "phoenician"),
+  ("plrd", "miao"),
+  ("prti", "inscriptionalparthian"),
+  ("psalterpahlavi", "psalterpahlavi"),
+  ("qaac", "coptic"),
+  ("qaai", "inherited"),
+  ("rejang", "rejang"),
+  ("rjng", "rejang"),
+  ("rohg", "hanifirohingya"),
+  ("runic", "runic"),
+  ("runr", "runic"),
+  ("samaritan", "samaritan"),
+  ("samr", "samaritan"),
+  ("sarb", "oldsoutharabian"),
+  ("saur", "saurashtra"),
+  ("saurashtra", "saurashtra"),
+  ("sgnw", "signwriting"),
+  ("sharada", "sharada"),
+  ("shavian", "shavian"),
+  ("shaw", "shavian"),
+  ("shrd", "sharada"),
+  ("sidd", "siddham"),
+  ("siddham", "siddham"),
+  ("signwriting", "signwriting"),
+  ("sind", "khudawadi"),
+  ("sinh", "sinhala"),
+  ("sinhala", "sinhala"),
+  ("sogd", "sogdian"),
+  ("sogdian", "sogdian"),
+  ("sogo", "oldsogdian"),
+  ("sora", "sorasompeng"),
+  ("sorasompeng", "sorasompeng"),
+  ("soyo", "soyombo"),
+  ("soyombo", "soyombo"),
+  ("sund", "sundanese"),
+  ("sundanese", "sundanese"),
+  ("sylo", "sylotinagri"),
+  ("sylotinagri", "sylotinagri"),
+  ("syrc", "syriac"),
+  ("syriac", "syriac"),
+  ("tagalog", "tagalog"),
+  ("tagb", "tagbanwa"),
+  ("tagbanwa", "tagbanwa"),
+  ("taile", "taile"),
+  ("taitham", "taitham"),
+  ("taiviet", "taiviet"),
+  ("takr", "takri"),
+  ("takri", "takri"),
+  ("tale", "taile"),
+  ("talu", "newtailue"),
+  ("tamil", "tamil"),
+  ("taml", "tamil"),
+  ("tang", "tangut"),
+  ("tangsa", "tangsa"),
+  ("tangut", "tangut"),
+  ("tavt", "taiviet"),
+  ("telu", "telugu"),
+  ("telugu", "telugu"),
+  ("tfng", "tifinagh"),
+  ("tglg", "tagalog"),
+  ("thaa", "thaana"),
+  ("thaana", "thaana"),
+  ("thai", "thai"),
+  ("tibetan", "tibetan"),
+  ("tibt", "tibetan"),
+  ("tifinagh", "tifinagh"),
+  ("tirh", "tirhuta"),
+  ("tirhuta", "tirhuta"),
+  ("tnsa", "tangsa"),
+  ("toto", "toto"),
+  ("ugar", "ugaritic"),
+  ("ugaritic", "ugaritic"),
+  ("unknown", "unknown"),
+  ("vai", "vai"),
+  ("vaii", "vai"),
+  ("vith", "vithkuqi"),
+  ("vithkuqi", "vithkuqi"),
+  ("wancho", "wancho"),
+  ("wara", "warangciti"),
+  ("warangciti", "warangciti"),
+  ("wcho", "wancho"),
+  ("xpeo", "oldpersian"),
+  ("xsux", "cuneiform"),
+  ("yezi", "yezidi"),
+  ("yezidi", "yezidi"),
+  ("yi", "yi"),
+  ("yiii", "yi"),
+  ("zanabazarsquare", "zanabazarsquare"),
+  ("zanb", "zanabazarsquare"),
+  ("zinh", "inherited"),
+  ("zyyy", "common"),
+  ("zzzz", "unknown")
+];
+
+/// Binary properties, by normalized name
+pub static BinaryProperties: &'static [(&'static str, &'static [(char, char)])] = &[
+  ("alphabetic", &[
+    ('\u0041', '\u005A'),
+    ('\u0061', '\u007A'),
+    ('\u00AA', '\u00AA'),
+    ('\u00B5', '\u00B5'),
+    ('\u00BA', '\u00BA'),
+    ('\u00C0', '\u00D6'),
+    ('\u00D8', '\u00F6'),
+    ('\u00F8', '\u02C1'),
+    ('\u02C6', '\u02D1'),
+    ('\u02E0', '\u02E4'),
+    ('\u02EC', '\u02EC'),
+    ('\u02EE', '\u02EE'),
+    ('\u0345', '\u0345'),
+    ('\u0370', '\u0374'),
+    ('\u0376', '\u0377'),
+    ('\u037A', '\u037D'),
+    ('\u037F', '\u037F'),
+    ('\u0386', '\u0386'),
+    ('\u0388', '\u038A'),
+    ('\u038C', '\u038C'),
+    ('\u038E', '\u03A1'),
+    ('\u03A3', '\u03F5'),
+    ('\u03F7', '\u0481'),
+    ('\u048A', '\u052F'),
+    ('\u0531', '\u0556'),
+    ('\u0559', '\u0559'),
+    ('\u0560', '\u0588'),
+    ('\u05B0', '\u05BD'),
+    ('\u05BF', '\u05BF'),
+    ('\u05C1', '\u05C2'),
+    ('\u05C4', '\u05C5'),
+    ('\u05C7', '\u05C7'),
+    ('\u05D0', '\u05EA'),
+    ('\u05EF', '\u05F2'),
+    ('\u0610', '\u061A'),
+    ('\u0620', '\u0657'),
+    ('\u0659', '\u065F'),
+    ('\u066E', '\u06D3'),
+    ('\u06D5', '\u06DC'),
+    ('\u06E1', '\u06E8'),
+    ('\u06ED', '\u06EF'),
+    ('\u06FA', '\u06FC'),
+    ('\u06FF', '\u06FF'),
+    ('\u0710', '\u073F'),
+    ('\u074D', '\u07B1'),
+    ('\u07CA', '\u07EA'),
+    ('\u07F4', '\u07F5'),
+    ('\u07FA', '\u07FA'),
+    ('\u0800', '\u0817'),
+    ('\u081A', '\u082C'),
+    ('\u0840', '\u0858'),
+    ('\u0860', '\u086A'),
+    ('\u0870', '\u0887'),
+    ('\u0889', '\u088E'),
+    ('\u08A0', '\u08C9'),
+    ('\u08D4', '\u08DF'),
+    ('\u08E3', '\u08E9'),
+    ('\u08F0', '\u093B'),
+    ('\u093D', '\u094C'),
+    ('\u094E', '\u0950'),
+    ('\u0955', '\u0963'),
+    ('\u0971', '\u0983'),
+    ('\u0985', '\u098C'),
+    ('\u098F', '\u0990'),
+    ('\u0993', '\u09A8'),
+    ('\u09AA', '\u09B0'),
+    ('\u09B2', '\u09B2'),
+    ('\u09B6', '\u09B9'),
+    ('\u09BD', '\u09C4'),
+    ('\u09C7', '\u09C8'),
+    ('\u09CB', '\u09CC'),
+    ('\u09CE', '\u09CE'),
+    ('\u09D7', '\u09D7'),
+    ('\u09DC', '\u09DD'),
+    ('\u09DF', '\u09E3'),
+    ('\u09F0', '\u09F1'),
+    ('\u09FC', '\u09FC'),
+    ('\u0A01', '\u0A03'),
+    ('\u0A05', '\u0A0A'),
+    ('\u0A0F', '\u0A10'),
+    ('\u0A13', '\u0A28'),
+    ('\u0A2A', '\u0A30'),
+    ('\u0A32', '\u0A33'),
+    ('\u0A35', '\u0A36'),
+    ('\u0A38', '\u0A39'),
+    ('\u0A3E', '\u0A42'),
+    ('\u0A47', '\u0A48'),
+    ('\u0A4B', '\u0A4C'),
+    ('\u0A51', '\u0A51'),
+    ('\u0A59', '\u0A5C'),
+    ('\u0A5E', '\u0A5E'),
+    ('\u0A70', '\u0A75'),
+    ('\u0A81', '\u0A83'),
+    ('\u0A85', '\u0A8D'),
+    ('\u0A8F', '\u0A91'),
+    ('\u0A93', '\u0AA8'),
+    ('\u0AAA', '\u0AB0'),
+    ('\u0AB2', '\u0AB3'),
+    ('\u0AB5', '\u0AB9'),
+    ('\u0ABD', '\u0AC5'),
+    ('\u0AC7', '\u0AC9'),
+    ('\u0ACB', '\u0ACC'),
+    ('\u0AD0', '\u0AD0'),
+    ('\u0AE0', '\u0AE3'),
+    ('\u0AF9', '\u0AFC'),
+    ('\u0B01', '\u0B03'),
+    ('\u0B05', '\u0B0C'),
+    ('\u0B0F', '\u0B10'),
+    ('\u0B13', '\u0B28'),
+    ('\u0B2A', '\u0B30'),
+    ('\u0B32', '\u0B33'),
+    ('\u0B35', '\u0B39'),
+    ('\u0B3D', '\u0B44'),
+    ('\u0B47', '\u0B48'),
+    ('\u0B4B', '\u0B4C'),
+    ('\u0B56', '\u0B57'),
+    ('\u0B5C', '\u0B5D'),
+    ('\u0B5F', '\u0B63'),
+    ('\u0B71', '\u0B71'),
+    ('\u0B82', '\u0B83'),
+    ('\u0B85', '\u0B8A'),
+    ('\u0B8E', '\u0B90'),
+    ('\u0B92', '\u0B95'),
+    ('\u0B99', '\u0B9A'),
+    ('\u0B9C', '\u0B9C'),
+    ('\u0B9E', '\u0B9F'),
+    ('\u0BA3', '\u0BA4'),
+    ('\u0BA8', '\u0BAA'),
+    ('\u0BAE', '\u0BB9'),
+    ('\u0BBE', '\u0BC2'),
+    ('\u0BC6', '\u0BC8'),
+    ('\u0BCA', '\u0BCC'),
+    ('\u0BD0', '\u0BD0'),
+    ('\u0BD7', '\u0BD7'),
+    ('\u0C00', '\u0C03'),
+    ('\u0C05', '\u0C0C'),
+    ('\u0C0E', '\u0C10'),
+    ('\u0C12', '\u0C28'),
+    ('\u0C2A', '\u0C39'),
+    ('\u0C3D', '\u0C44'),
+    ('\u0C46', '\u0C48'),
+    ('\u0C4A', '\u0C4C'),
+    ('\u0C55', '\u0C56'),
+    ('\u0C58', '\u0C5A'),
+    ('\u0C5D', '\u0C5D'),
+    ('\u0C60', '\u0C63'),
+    ('\u0C80', '\u0C83'),
+    ('\u0C85', '\u0C8C'),
+    ('\u0C8E', '\u0C90'),
+    ('\u0C92', '\u0CA8'),
+    ('\u0CAA', '\u0CB3'),
+    ('\u0CB5', '\u0CB9'),
+    ('\u0CBD', '\u0CC4'),
+    ('\u0CC6', '\u0CC8'),
+    ('\u0CCA', '\u0CCC'),
+    ('\u0CD5', '\u0CD6'),
+    ('\u0CDD', '\u0CDE'),
+    ('\u0CE0', '\u0CE3'),
+    ('\u0CF1', '\u0CF2'),
+    ('\u0D00', '\u0D0C'),
+    ('\u0D0E', '\u0D10'),
+    ('\u0D12', '\u0D3A'),
+    ('\u0D3D', '\u0D44'),
+    ('\u0D46', '\u0D48'),
+    ('\u0D4A', '\u0D4C'),
+    ('\u0D4E', '\u0D4E'),
+    ('\u0D54', '\u0D57'),
+    ('\u0D5F', '\u0D63'),
+    ('\u0D7A', '\u0D7F'),
+    ('\u0D81', '\u0D83'),
+    ('\u0D85', '\u0D96'),
+    ('\u0D9A', '\u0DB1'),
+    ('\u0DB3', '\u0DBB'),
+    ('\u0DBD', '\u0DBD'),
+    ('\u0DC0', '\u0DC6'),
+    ('\u0DCF', '\u0DD4'),
+    ('\u0DD6', '\u0DD6'),
+    ('\u0DD8', '\u0DDF'),
+    ('\u0DF2', '\u0DF3'),
+    ('\u0E01', '\u0E3A'),
+    ('\u0E40', '\u0E46'),
+    ('\u0E4D', '\u0E4D'),
+    ('\u0E81', '\u0E82'),
+    ('\u0E84', '\u0E84'),
+    ('\u0E86', '\u0E8A'),
+    ('\u0E8C', '\u0EA3'),
+    ('\u0EA5', '\u0EA5'),
+    ('\u0EA7', '\u0EB9'),
+    ('\u0EBB', '\u0EBD'),
+    ('\u0EC0', '\u0EC4'),
+    ('\u0EC6', '\u0EC6'),
+    ('\u0ECD', '\u0ECD'),
+    ('\u0EDC', '\u0EDF'),
+    ('\u0F00', '\u0F00'),
+    ('\u0F40', '\u0F47'),
+    ('\u0F49', '\u0F6C'),
+    ('\u0F71', '\u0F81'),
+    ('\u0F88', '\u0F97'),
+    ('\u0F99', '\u0FBC'),
+    ('\u1000', '\u1036'),
+    ('\u1038', '\u1038'),
+    ('\u103B', '\u103F'),
+    ('\u1050', '\u108F'),
+    ('\u109A', '\u109D'),
+    ('\u10A0', '\u10C5'),
+    ('\u10C7', '\u10C7'),
+    ('\u10CD', '\u10CD'),
+    ('\u10D0', '\u10FA'),
+    ('\u10FC', '\u1248'),
+    ('\u124A', '\u124D'),
+    ('\u1250', '\u1256'),
+    ('\u1258', '\u1258'),
+    ('\u125A', '\u125D'),
+    ('\u1260', '\u1288'),
+    ('\u128A', '\u128D'),
+    ('\u1290', '\u12B0'),
+    ('\u12B2', '\u12B5'),
+    ('\u12B8', '\u12BE'),
+    ('\u12C0', '\u12C0'),
+    ('\u12C2', '\u12C5'),
+    ('\u12C8', '\u12D6'),
+    ('\u12D8', '\u1310'),
+    ('\u1312', '\u1315'),
+    ('\u1318', '\u135A'),
+    ('\u1380', '\u138F'),
+    ('\u13A0', '\u13F5'),
+    ('\u13F8', '\u13FD'),
+    ('\u1401', '\u166C'),
+    ('\u166F', '\u167F'),
+    ('\u1681', '\u169A'),
+    ('\u16A0', '\u16EA'),
+    ('\u16EE', '\u16F8'),
+    ('\u1700', '\u1713'),
+    ('\u171F', '\u1733'),
+    ('\u1740', '\u1753'),
+    ('\u1760', '\u176C'),
+    ('\u176E', '\u1770'),
+    ('\u1772', '\u1773'),
+    ('\u1780', '\u17B3'),
+    ('\u17B6', '\u17C8'),
+    ('\u17D7', '\u17D7'),
+    ('\u17DC', '\u17DC'),
+    ('\u1820', '\u1878'),
+    ('\u1880', '\u18AA'),
+    ('\u18B0', '\u18F5'),
+    ('\u1900', '\u191E'),
+    ('\u1920', '\u192B'),
+    ('\u1930', '\u1938'),
+    ('\u1950', '\u196D'),
+    ('\u1970', '\u1974'),
+    ('\u1980', '\u19AB'),
+    ('\u19B0', '\u19C9'),
+    ('\u1A00', '\u1A1B'),
+    ('\u1A20', '\u1A5E'),
+    ('\u1A61', '\u1A74'),
+    ('\u1AA7', '\u1AA7'),
+    ('\u1ABF', '\u1AC0'),
+    ('\u1ACC', '\u1ACE'),
+    ('\u1B00', '\u1B33'),
+    ('\u1B35', '\u1B43'),
+    ('\u1B45', '\u1B4C'),
+    ('\u1B80', '\u1BA9'),
+    ('\u1BAC', '\u1BAF'),
+    ('\u1BBA', '\u1BE5'),
+    ('\u1BE7', '\u1BF1'),
+    ('\u1C00', '\u1C36'),
+    ('\u1C4D', '\u1C4F'),
+    ('\u1C5A', '\u1C7D'),
+    ('\u1C80', '\u1C88'),
+    ('\u1C90', '\u1CBA'),
+    ('\u1CBD', '\u1CBF'),
+    ('\u1CE9', '\u1CEC'),
+    ('\u1CEE', '\u1CF3'),
+    ('\u1CF5', '\u1CF6'),
+    ('\u1CFA', '\u1CFA'),
+    ('\u1D00', '\u1DBF'),
+    ('\u1DE7', '\u1DF4'),
+    ('\u1E00', '\u1F15'),
+    ('\u1F18', '\u1F1D'),
+    ('\u1F20', '\u1F45'),
+    ('\u1F48', '\u1F4D'),
+    ('\u1F50', '\u1F57'),
+    ('\u1F59', '\u1F59'),
+    ('\u1F5B', '\u1F5B'),
+    ('\u1F5D', '\u1F5D'),
+    ('\u1F5F', '\u1F7D'),
+    ('\u1F80', '\u1FB4'),
+    ('\u1FB6', '\u1FBC'),
+    ('\u1FBE', '\u1FBE'),
+    ('\u1FC2', '\u1FC4'),
+    ('\u1FC6', '\u1FCC'),
+    ('\u1FD0', '\u1FD3'),
+    ('\u1FD6', '\u1FDB'),
+    ('\u1FE0', '\u1FEC'),
+    ('\u1FF2', '\u1FF4'),
+    ('\u1FF6', '\u1FFC'),
+    ('\u2071', '\u2071'),
+    ('\u207F', '\u207F'),
+    ('\u2090', '\u209C'),
+    ('\u2102', '\u2102'),
+    ('\u2107', '\u2107'),
+    ('\u210A', '\u2113'),
+    ('\u2115', '\u2115'),
+    ('\u2119', '\u211D'),
+    ('\u2124', '\u2124'),
+    ('\u2126', '\u2126'),
+    ('\u2128', '\u2128'),
+    ('\u212A', '\u212D'),
+    ('\u212F', '\u2139'),
+    ('\u213C', '\u213F'),
+    ('\u2145', '\u2149'),
+    ('\u214E', '\u214E'),
+    ('\u2160', '\u2188'),
+    ('\u24B6', '\u24E9'),
+    ('\u2C00', '\u2CE4'),
+    ('\u2CEB', '\u2CEE'),
+    ('\u2CF2', '\u2CF3'),
+    ('\u2D00', '\u2D25'),
+    ('\u2D27', '\u2D27'),
+    ('\u2D2D', '\u2D2D'),
+    ('\u2D30', '\u2D67'),
+    ('\u2D6F', '\u2D6F'),
+    ('\u2D80', '\u2D96'),
+    ('\u2DA0', '\u2DA6'),
+    ('\u2DA8', '\u2DAE'),
+    ('\u2DB0', '\u2DB6'),
+    ('\u2DB8', '\u2DBE'),
+    ('\u2DC0', '\u2DC6'),
+    ('\u2DC8', '\u2DCE'),
+    ('\u2DD0', '\u2DD6'),
+    ('\u2DD8', '\u2DDE'),
+    ('\u2DE0', '\u2DFF'),
+    ('\u2E2F', '\u2E2F'),
+    ('\u3005', '\u3007'),
+    ('\u3021', '\u3029'),
+    ('\u3031', '\u3035'),
+    ('\u3038', '\u303C'),
+    ('\u3041', '\u3096'),
+    ('\u309D', '\u309F'),
+    ('\u30A1', '\u30FA'),
+    ('\u30FC', '\u30FF'),
+    ('\u3105', '\u312F'),
+    ('\u3131', '\u318E'),
+    ('\u31A0', '\u31BF'),
+    ('\u31F0', '\u31FF'),
+    ('\u3400', '\u4DBF'),
+    ('\u4E00', '\uA48C'),
+    ('\uA4D0', '\uA4FD'),
+    ('\uA500', '\uA60C'),
+    ('\uA610', '\uA61F'),
+    ('\uA62A', '\uA62B'),
+    ('\uA640', '\uA66E'),
+    ('\uA674', '\uA67B'),
+    ('\uA67F', '\uA6EF'),
+    ('\uA717', '\uA71F'),
+    ('\uA722', '\uA788'),
+    ('\uA78B', '\uA7CA'),
+    ('\uA7D0', '\uA7D1'),
+    ('\uA7D3', '\uA7D3'),
+    ('\uA7D5', '\uA7D9'),
+    ('\uA7F2', '\uA805'),
+    ('\uA807', '\uA827'),
+    ('\uA840', '\uA873'),
+    ('\uA880', '\uA8C3'),
+    ('\uA8C5', '\uA8C5'),
+    ('\uA8F2', '\uA8F7'),
+    ('\uA8FB', '\uA8FB'),
+    ('\uA8FD', '\uA8FF'),
+    ('\uA90A', '\uA92A'),
+    ('\uA930', '\uA952'),
+    ('\uA960', '\uA97C'),
+    ('\uA980', '\uA9B2'),
+    ('\uA9B4', '\uA9BF'),
+    ('\uA9CF', '\uA9CF'),
+    ('\uA9E0', '\uA9EF'),
+    ('\uA9FA', '\uA9FE'),
+    ('\uAA00', '\uAA36'),
+    ('\uAA40', '\uAA4D'),
+    ('\uAA60', '\uAA76'),
+    ('\uAA7A', '\uAABE'),
+    ('\uAAC0', '\uAAC0'),
+    ('\uAAC2', '\uAAC2'),
+    ('\uAADB', '\uAADD'),
+    ('\uAAE0', '\uAAEF'),
+    ('\uAAF2', '\uAAF5'),
+    ('\uAB01', '\uAB06'),
+    ('\uAB09', '\uAB0E'),
+    ('\uAB11', '\uAB16'),
+    ('\uAB20', '\uAB26'),
+    ('\uAB28', '\uAB2E'),
+    ('\uAB30', '\uAB5A'),
+    ('\uAB5C', '\uAB69'),
+    ('\uAB70', '\uABEA'),
+    ('\uAC00', '\uD7A3'),
+    ('\uD7B0', '\uD7C6'),
+    ('\uD7CB', '\uD7FB'),
+    ('\uF900', '\uFA6D'),
+    ('\uFA70', '\uFAD9'),
+    ('\uFB00', '\uFB06'),
+    ('\uFB13', '\uFB17'),
+    ('\uFB1D', '\uFB28'),
+    ('\uFB2A', '\uFB36'),
+    ('\uFB38', '\uFB3C'),
+    ('\uFB3E', '\uFB3E'),
+    ('\uFB40', '\uFB41'),
+    ('\uFB43', '\uFB44'),
+    ('\uFB46', '\uFBB1'),
+    ('\uFBD3', '\uFD3D'),
+    ('\uFD50', '\uFD8F'),
+    ('\uFD92', '\uFDC7'),
+    ('\uFDF0', '\uFDFB'),
+    ('\uFE70', '\uFE74'),
+    ('\uFE76', '\uFEFC'),
+    ('\uFF21', '\uFF3A'),
+    ('\uFF41', '\uFF5A'),
+    ('\uFF66', '\uFFBE'),
+    ('\uFFC2', '\uFFC7'),
+    ('\uFFCA', '\uFFCF'),
+    ('\uFFD2', '\uFFD7'),
+    ('\uFFDA', '\uFFDC'),
+    ('\U00010000', '\U0001000B'),
+    ('\U0001000D', '\U00010026'),
+    ('\U00010028', '\U0001003A'),
+    ('\U0001003C', '\U0001003D'),
+    ('\U0001003F', '\U0001004D'),
+    ('\U00010050', '\U0001005D'),
+    ('\U00010080', '\U000100FA'),
+    ('\U00010140', '\U00010174'),
+    ('\U00010280', '\U0001029C'),
+    ('\U000102A0', '\U000102D0'),
+    ('\U00010300', '\U0001031F'),
+    ('\U0001032D', '\U0001034A'),
+    ('\U00010350', '\U0001037A'),
+    ('\U00010380', '\U0001039D'),
+    ('\U000103A0', '\U000103C3'),
+    ('\U000103C8', '\U000103CF'),
+    ('\U000103D1', '\U000103D5'),
+    ('\U00010400', '\U0001049D'),
+    ('\U000104B0', '\U000104D3'),
+    ('\U000104D8', '\U000104FB'),
+    ('\U00010500', '\U00010527'),
+    ('\U00010530', '\U00010563'),
+    ('\U00010570', '\U0001057A'),
+    ('\U0001057C', '\U0001058A'),
+    ('\U0001058C', '\U00010592'),
+    ('\U00010594', '\U00010595'),
+    ('\U00010597', '\U000105A1'),
+    ('\U000105A3', '\U000105B1'),
+    ('\U000105B3', '\U000105B9'),
+    ('\U000105BB', '\U000105BC'),
+    ('\U00010600', '\U00010736'),
+    ('\U00010740', '\U00010755'),
+    ('\U00010760', '\U00010767'),
+    ('\U00010780', '\U00010785'),
+    ('\U00010787', '\U000107B0'),
+    ('\U000107B2', '\U000107BA'),
+    ('\U00010800', '\U00010805'),
+    ('\U00010808', '\U00010808'),
+    ('\U0001080A', '\U00010835'),
+    ('\U00010837', '\U00010838'),
+    ('\U0001083C', '\U0001083C'),
+    ('\U0001083F', '\U00010855'),
+    ('\U00010860', '\U00010876'),
+    ('\U00010880', '\U0001089E'),
+    ('\U000108E0', '\U000108F2'),
+    ('\U000108F4', '\U000108F5'),
+    ('\U00010900', '\U00010915'),
+    ('\U00010920', '\U00010939'),
+    ('\U00010980', '\U000109B7'),
+    ('\U000109BE', '\U000109BF'),
+    ('\U00010A00', '\U00010A03'),
+    ('\U00010A05', '\U00010A06'),
+    ('\U00010A0C', '\U00010A13'),
+    ('\U00010A15', '\U00010A17'),
+    ('\U00010A19', '\U00010A35'),
+    ('\U00010A60', '\U00010A7C'),
+    ('\U00010A80', '\U00010A9C'),
+    ('\U00010AC0', '\U00010AC7'),
+    ('\U00010AC9', '\U00010AE4'),
+    ('\U00010B00', '\U00010B35'),
+    ('\U00010B40', '\U00010B55'),
+    ('\U00010B60', '\U00010B72'),
+    ('\U00010B80', '\U00010B91'),
+    ('\U00010C00', '\U00010C48'),
+    ('\U00010C80', '\U00010CB2'),
+    ('\U00010CC0', '\U00010CF2'),
+    ('\U00010D00', '\U00010D27'),
+    ('\U00010E80', '\U00010EA9'),
+    ('\U00010EAB', '\U00010EAC'),
+    ('\U00010EB0', '\U00010EB1'),
+    ('\U00010F00', '\U00010F1C'),
+    ('\U00010F27', '\U00010F27'),
+    ('\U00010F30', '\U00010F45'),
+    ('\U00010F70', '\U00010F81'),
+    ('\U00010FB0', '\U00010FC4'),
+    ('\U00010FE0', '\U00010FF6'),
+    ('\U00011000', '\U00011045'),
+    ('\U00011071', '\U00011075'),
+    ('\U00011082', '\U000110B8'),
+    ('\U000110C2', '\U000110C2'),
+    ('\U000110D0', '\U000110E8'),
+    ('\U00011100', '\U00011132'),
+    ('\U00011144', '\U00011147'),
+    ('\U00011150', '\U00011172'),
+    ('\U00011176', '\U00011176'),
+    ('\U00011180', '\U000111BF'),
+    ('\U000111C1', '\U000111C4'),
+    ('\U000111CE', '\U000111CF'),
+    ('\U000111DA', '\U000111DA'),
+    ('\U000111DC', '\U000111DC'),
+    ('\U00011200', '\U00011211'),
+    ('\U00011213', '\U00011234'),
+    ('\U00011237', '\U00011237'),
+    ('\U0001123E', '\U0001123E'),
+    ('\U00011280', '\U00011286'),
+    ('\U00011288', '\U00011288'),
+    ('\U0001128A', '\U0001128D'),
+    ('\U0001128F', '\U0001129D'),
+    ('\U0001129F', '\U000112A8'),
+    ('\U000112B0', '\U000112E8'),
+    ('\U00011300', '\U00011303'),
+    ('\U00011305', '\U0001130C'),
+    ('\U0001130F', '\U00011310'),
+    ('\U00011313', '\U00011328'),
+    ('\U0001132A', '\U00011330'),
+    ('\U00011332', '\U00011333'),
+    ('\U00011335', '\U00011339'),
+    ('\U0001133D', '\U00011344'),
+    ('\U00011347', '\U00011348'),
+    ('\U0001134B', '\U0001134C'),
+    ('\U00011350', '\U00011350'),
+    ('\U00011357', '\U00011357'),
+    ('\U0001135D', '\U00011363'),
+    ('\U00011400', '\U00011441'),
+    ('\U00011443', '\U00011445'),
+    ('\U00011447', '\U0001144A'),
+    ('\U0001145F', '\U00011461'),
+    ('\U00011480', '\U000114C1'),
+    ('\U000114C4', '\U000114C5'),
+    ('\U000114C7', '\U000114C7'),
+    ('\U00011580', '\U000115B5'),
+    ('\U000115B8', '\U000115BE'),
+    ('\U000115D8', '\U000115DD'),
+    ('\U00011600', '\U0001163E'),
+    ('\U00011640', '\U00011640'),
+    ('\U00011644', '\U00011644'),
+    ('\U00011680', '\U000116B5'),
+    ('\U000116B8', '\U000116B8'),
+    ('\U00011700', '\U0001171A'),
+    ('\U0001171D', '\U0001172A'),
+    ('\U00011740', '\U00011746'),
+    ('\U00011800', '\U00011838'),
+    ('\U000118A0', '\U000118DF'),
+    ('\U000118FF', '\U00011906'),
+    ('\U00011909', '\U00011909'),
+    ('\U0001190C', '\U00011913'),
+    ('\U00011915', '\U00011916'),
+    ('\U00011918', '\U00011935'),
+    ('\U00011937', '\U00011938'),
+    ('\U0001193B', '\U0001193C'),
+    ('\U0001193F', '\U00011942'),
+    ('\U000119A0', '\U000119A7'),
+    ('\U000119AA', '\U000119D7'),
+    ('\U000119DA', '\U000119DF'),
+    ('\U000119E1', '\U000119E1'),
+    ('\U000119E3', '\U000119E4'),
+    ('\U00011A00', '\U00011A32'),
+    ('\U00011A35', '\U00011A3E'),
+    ('\U00011A50', '\U00011A97'),
+    ('\U00011A9D', '\U00011A9D'),
+    ('\U00011AB0', '\U00011AF8'),
+    ('\U00011C00', '\U00011C08'),
+    ('\U00011C0A', '\U00011C36'),
+    ('\U00011C38', '\U00011C3E'),
+    ('\U00011C40', '\U00011C40'),
+    ('\U00011C72', '\U00011C8F'),
+    ('\U00011C92', '\U00011CA7'),
+    ('\U00011CA9', '\U00011CB6'),
+    ('\U00011D00', '\U00011D06'),
+    ('\U00011D08', '\U00011D09'),
+    ('\U00011D0B', '\U00011D36'),
+    ('\U00011D3A', '\U00011D3A'),
+    ('\U00011D3C', '\U00011D3D'),
+    ('\U00011D3F', '\U00011D41'),
+    ('\U00011D43', '\U00011D43'),
+    ('\U00011D46', '\U00011D47'),
+    ('\U00011D60', '\U00011D65'),
+    ('\U00011D67', '\U00011D68'),
+    ('\U00011D6A', '\U00011D8E'),
+    ('\U00011D90', '\U00011D91'),
+    ('\U00011D93', '\U00011D96'),
+    ('\U00011D98', '\U00011D98'),
+    ('\U00011EE0', '\U00011EF6'),
+    ('\U00011FB0', '\U00011FB0'),
+    ('\U00012000', '\U00012399'),
+    ('\U00012400', '\U0001246E'),
+    ('\U00012480', '\U00012543'),
+    ('\U00012F90', '\U00012FF0'),
+    ('\U00013000', '\U0001342E'),
+    ('\U00014400', '\U00014646'),
+    ('\U00016800', '\U00016A38'),
+    ('\U00016A40', '\U00016A5E'),
+    ('\U00016A70', '\U00016ABE'),
+    ('\U00016AD0', '\U00016AED'),
+    ('\U00016B00', '\U00016B2F'),
+    ('\U00016B40', '\U00016B43'),
+    ('\U00016B63', '\U00016B77'),
+    ('\U00016B7D', '\U00016B8F'),
+    ('\U00016E40', '\U00016E7F'),
+    ('\U00016F00', '\U00016F4A'),
+    ('\U00016F4F', '\U00016F87'),
+    ('\U00016F8F', '\U00016F9F'),
+    ('\U00016FE0', '\U00016FE1'),
+    ('\U00016FE3', '\U00016FE3'),
+    ('\U00016FF0', '\U00016FF1'),
+    ('\U00017000', '\U000187F7'),
+    ('\U00018800', '\U00018CD5'),
+    ('\U00018D00', '\U00018D08'),
+    ('\U0001AFF0', '\U0001AFF3'),
+    ('\U0001AFF5', '\U0001AFFB'),
+    ('\U0001AFFD', '\U0001AFFE'),
+    ('\U0001B000', '\U0001B122'),
+    ('\U0001B150', '\U0001B152'),
+    ('\U0001B164', '\U0001B167'),
+    ('\U0001B170', '\U0001B2FB'),
+    ('\U0001BC00', '\U0001BC6A'),
+    ('\U0001BC70', '\U0001BC7C'),
+    ('\U0001BC80', '\U0001BC88'),
+    ('\U0001BC90', '\U0001BC99'),
+    ('\U0001BC9E', '\U0001BC9E'),
+    ('\U0001D400', '\U0001D454'),
+    ('\U0001D456', '\U0001D49C'),
+    ('\U0001D49E', '\U0001D49F'),
+    ('\U0001D4A2', '\U0001D4A2'),
+    ('\U0001D4A5', '\U0001D4A6'),
+    ('\U0001D4A9', '\U0001D4AC'),
+    ('\U0001D4AE', '\U0001D4B9'),
+    ('\U0001D4BB', '\U0001D4BB'),
+    ('\U0001D4BD', '\U0001D4C3'),
+    ('\U0001D4C5', '\U0001D505'),
+    ('\U0001D507', '\U0001D50A'),
+    ('\U0001D50D', '\U0001D514'),
+    ('\U0001D516', '\U0001D51C'),
+    ('\U0001D51E', '\U0001D539'),
+    ('\U0001D53B', '\U0001D53E'),
+    ('\U0001D540', '\U0001D544'),
+    ('\U0001D546', '\U0001D546'),
+    ('\U0001D54A', '\U0001D550'),
+    ('\U0001D552', '\U0001D6A5'),
+    ('\U0001D6A8', '\U0001D6C0'),
+    ('\U0001D6C2', '\U0001D6DA'),
+    ('\U0001D6DC', '\U0001D6FA'),
+    ('\U0001D6FC', '\U0001D714'),
+    ('\U0001D716', '\U0001D734'),
+    ('\U0001D736', '\U0001D74E'),
+    ('\U0001D750', '\U0001D76E'),
+    ('\U0001D770', '\U0001D788'),
+    ('\U0001D78A', '\U0001D7A8'),
+    ('\U0001D7AA', '\U0001D7C2'),
+    ('\U0001D7C4', '\U0001D7CB'),
+    ('\U0001DF00', '\U0001DF1E'),
+    ('\U0001E000', '\U0001E006'),
+    ('\U0001E008', '\U0001E018'),
+    ('\U0001E01B', '\U0001E021'),
+    ('\U0001E023', '\U0001E024'),
+    ('\U0001E026', '\U0001E02A'),
+    ('\U0001E100', '\U0001E12C'),
+    ('\U0001E137', '\U0001E13D'),
+    ('\U0001E14E', '\U0001E14E'),
+    ('\U0001E290', '\U0001E2AD'),
+    ('\U0001E2C0', '\U0001E2EB'),
+    ('\U0001E7E0', '\U0001E7E6'),
+    ('\U0001E7E8', '\U0001E7EB'),
+    ('\U0001E7ED', '\U0001E7EE'),
+    ('\U0001E7F0', '\U0001E7FE'),
+    ('\U0001E800', '\U0001E8C4'),
+    ('\U0001E900', '\U0001E943'),
+    ('\U0001E947', '\U0001E947'),
+    ('\U0001E94B', '\U0001E94B'),
+    ('\U0001EE00', '\U0001EE03'),
+    ('\U0001EE05', '\U0001EE1F'),
+    ('\U0001EE21', '\U0001EE22'),
+    ('\U0001EE24', '\U0001EE24'),
+    ('\U0001EE27', '\U0001EE27'),
+    ('\U0001EE29', '\U0001EE32'),
+    ('\U0001EE34', '\U0001EE37'),
+    ('\U0001EE39', '\U0001EE39'),
+    ('\U0001EE3B', '\U0001EE3B'),
+    ('\U0001EE42', '\U0001EE42'),
+    ('\U0001EE47', '\U0001EE47'),
+    ('\U0001EE49', '\U0001EE49'),
+    ('\U0001EE4B', '\U0001EE4B'),
+    ('\U0001EE4D', '\U0001EE4F'),
+    ('\U0001EE51', '\U0001EE52'),
+    ('\U0001EE54', '\U0001EE54'),
+    ('\U0001EE57', '\U0001EE57'),
+    ('\U0001EE59', '\U0001EE59'),
+    ('\U0001EE5B', '\U0001EE5B'),
+    ('\U0001EE5D', '\U0001EE5D'),
+    ('\U0001EE5F', '\U0001EE5F'),
+    ('\U0001EE61', '\U0001EE62'),
+    ('\U0001EE64', '\U0001EE64'),
+    ('\U0001EE67', '\U0001EE6A'),
+    ('\U0001EE6C', '\U0001EE72'),
+    ('\U0001EE74', '\U0001EE77'),
+    ('\U0001EE79', '\U0001EE7C'),
+    ('\U0001EE7E', '\U0001EE7E'),
+    ('\U0001EE80', '\U0001EE89'),
+    ('\U0001EE8B', '\U0001EE9B'),
+    ('\U0001EEA1', '\U0001EEA3'),
+    ('\U0001EEA5', '\U0001EEA9'),
+    ('\U0001EEAB', '\U0001EEBB'),
+    ('\U0001F130', '\U0001F149'),
+    ('\U0001F150', '\U0001F169'),
+    ('\U0001F170', '\U0001F189'),
+    ('\U00020000', '\U0002A6DF'),
+    ('\U0002A700', '\U0002B738'),
+    ('\U0002B740', '\U0002B81D'),
+    ('\U0002B820', '\U0002CEA1'),
+    ('\U0002CEB0', '\U0002EBE0'),
+    ('\U0002F800', '\U0002FA1D'),
+    ('\U00030000', '\U0003134A')
+  ]),
+  ("any", &[
+    ('\u0000', '\uD7FF'),
+    ('\uE000', '\U0010FFFF')
+  ]),
+  ("ascii", &[
+    ('\u0000', '\u007F')
+  ]),
+  ("asciihexdigit", &[
+    ('\u0030', '\u0039'),
+    ('\u0041', '\u0046'),
+    ('\u0061', '\u0066')
+  ]),
+  ("cased", &[
+    ('\u0041', '\u005A'),
+    ('\u0061', '\u007A'),
+    ('\u00AA', '\u00AA'),
+    ('\u00B5', '\u00B5'),
+    ('\u00BA', '\u00BA'),
+    ('\u00C0', '\u00D6'),
+    ('\u00D8', '\u00F6'),
+    ('\u00F8', '\u01BA'),
+    ('\u01BC', '\u01BF'),
+    ('\u01C4', '\u0293'),
+    ('\u0295', '\u02B8'),
+    ('\u02C0', '\u02C1'),
+    ('\u02E0', '\u02E4'),
+    ('\u0345', '\u0345'),
+    ('\u0370', '\u0373'),
+    ('\u0376', '\u0377'),
+    ('\u037A', '\u037D'),
+    ('\u037F', '\u037F'),
+    ('\u0386', '\u0386'),
+    ('\u0388', '\u038A'),
+    ('\u038C', '\u038C'),
+    ('\u038E', '\u03A1'),
+    ('\u03A3', '\u03F5'),
+    ('\u03F7', '\u0481'),
+    ('\u048A', '\u052F'),
+    ('\u0531', '\u0556'),
+    ('\u0560', '\u0588'),
+    ('\u10A0', '\u10C5'),
+    ('\u10C7', '\u10C7'),
+    ('\u10CD', '\u10CD'),
+    ('\u10D0', '\u10FA'),
+    ('\u10FD', '\u10FF'),
+    ('\u13A0', '\u13F5'),
+    ('\u13F8', '\u13FD'),
+    ('\u1C80', '\u1C88'),
+    ('\u1C90', '\u1CBA'),
+    ('\u1CBD', '\u1CBF'),
+    ('\u1D00', '\u1DBF'),
+    ('\u1E00', '\u1F15'),
+    ('\u1F18', '\u1F1D'),
+    ('\u1F20', '\u1F45'),
+    ('\u1F48', '\u1F4D'),
+    ('\u1F50', '\u1F57'),
+    ('\u1F59', '\u1F59'),
+    ('\u1F5B', '\u1F5B'),
+    ('\u1F5D', '\u1F5D'),
+    ('\u1F5F', '\u1F7D'),
+    ('\u1F80', '\u1FB4'),
+    ('\u1FB6', '\u1FBC'),
+    ('\u1FBE', '\u1FBE'),
+    ('\u1FC2', '\u1FC4'),
+    ('\u1FC6', '\u1FCC'),
+    ('\u1FD0', '\u1FD3'),
+    ('\u1FD6', '\u1FDB'),
+    ('\u1FE0', '\u1FEC'),
+    ('\u1FF2', '\u1FF4'),
+    ('\u1FF6', '\u1FFC'),
+    ('\u2071', '\u2071'),
+    ('\u207F', '\u207F'),
+    ('\u2090', '\u209C'),
+    ('\u2102', '\u2102'),
+    ('\u2107', '\u2107'),
+    ('\u210A', '\u2113'),
+    ('\u2115', '\u2115'),
+    ('\u2119', '\u211D'),
+    ('\u2124', '\u2124'),
+    ('\u2126', '\u2126'),
+    ('\u2128', '\u2128'),
+    ('\u212A', '\u212D'),
+    ('\u212F', '\u2134'),
+    ('\u2139', '\u2139'),
+    ('\u213C', '\u213F'),
+    ('\u2145', '\u2149'),
+    ('\u214E', '\u214E'),
+    ('\u2160', '\u217F'),
+    ('\u2183', '\u2184'),
+    ('\u24B6', '\u24E9'),
+    ('\u2C00', '\u2CE4'),
+    ('\u2CEB', '\u2CEE'),
+    ('\u2CF2', '\u2CF3'),
+    ('\u2D00', '\u2D25'),
+    ('\u2D27', '\u2D27'),
+    ('\u2D2D', '\u2D2D'),
+    ('\uA640', '\uA66D'),
+    ('\uA680', '\uA69D'),
+    ('\uA722', '\uA787'),
+    ('\uA78B', '\uA78E'),
+    ('\uA790', '\uA7CA'),
+    ('\uA7D0', '\uA7D1'),
+    ('\uA7D3', '\uA7D3'),
+    ('\uA7D5', '\uA7D9'),
+    ('\uA7F5', '\uA7F6'),
+    ('\uA7F8', '\uA7FA'),
+    ('\uAB30', '\uAB5A'),
+    ('\uAB5C', '\uAB68'),
+    ('\uAB70', '\uABBF'),
+    ('\uFB00', '\uFB06'),
+    ('\uFB13', '\uFB17'),
+    ('\uFF21', '\uFF3A'),
+    ('\uFF41', '\uFF5A'),
+    ('\U00010400', '\U0001044F'),
+    ('\U000104B0', '\U000104D3'),
+    ('\U000104D8', '\U000104FB'),
+    ('\U00010570', '\U0001057A'),
+    ('\U0001057C', '\U0001058A'),
+    ('\U0001058C', '\U00010592'),
+    ('\U00010594', '\U00010595'),
+    ('\U00010597', '\U000105A1'),
+    ('\U000105A3', '\U000105B1'),
+    ('\U000105B3', '\U000105B9'),
+    ('\U000105BB', '\U000105BC'),
+    ('\U00010780', '\U00010780'),
+    ('\U00010783', '\U00010785'),
+    ('\U00010787', '\U000107B0'),
+    ('\U000107B2', '\U000107BA'),
+    ('\U00010C80', '\U00010CB2'),
+    ('\U00010CC0', '\U00010CF2'),
+    ('\U000118A0', '\U000118DF'),
+    ('\U00016E40', '\U00016E7F'),
+    ('\U0001D400', '\U0001D454'),
+    ('\U0001D456', '\U0001D49C'),
+    ('\U0001D49E', '\U0001D49F'),
+    ('\U0001D4A2', '\U0001D4A2'),
+    ('\U0001D4A5', '\U0001D4A6'),
+    ('\U0001D4A9', '\U0001D4AC'),
+    ('\U0001D4AE', '\U0001D4B9'),
+    ('\U0001D4BB', '\U0001D4BB'),
+    ('\U0001D4BD', '\U0001D4C3'),
+    ('\U0001D4C5', '\U0001D505'),
+    ('\U0001D507', '\U0001D50A'),
+    ('\U0001D50D', '\U0001D514'),
+    ('\U0001D516', '\U0001D51C'),
+    ('\U0001D51E', '\U0001D539'),
+    ('\U0001D53B', '\U0001D53E'),
+    ('\U0001D540', '\U0001D544'),
+    ('\U0001D546', '\U0001D546'),
+    ('\U0001D54A', '\U0001D550'),
+    ('\U0001D552', '\U0001D6A5'),
+    ('\U0001D6A8', '\U0001D6C0'),
+    ('\U0001D6C2', '\U0001D6DA'),
+    ('\U0001D6DC', '\U0001D6FA'),
+    ('\U0001D6FC', '\U0001D714'),
+    ('\U0001D716', '\U0001D734'),
+    ('\U0001D736', '\U0001D74E'),
+    ('\U0001D750', '\U0001D76E'),
+    ('\U0001D770', '\U0001D788'),
+    ('\U0001D78A', '\U0001D7A8'),
+    ('\U0001D7AA', '\U0001D7C2'),
+    ('\U0001D7C4', '\U0001D7CB'),
+    ('\U0001DF00', '\U0001DF09'),
+    ('\U0001DF0B', '\U0001DF1E'),
+    ('\U0001E900', '\U0001E943'),
+    ('\U0001F130', '\U0001F149'),
+    ('\U0001F150', '\U0001F169'),
+    ('\U0001F170', '\U0001F189')
+  ]),
+  ("caseignorable", &[
+    ('\u0027', '\u0027'),
+    ('\u002E', '\u002E'),
+    ('\u003A', '\u003A'),
+    ('\u005E', '\u005E'),
+    ('\u0060', '\u0060'),
+    ('\u00A8', '\u00A8'),
+    ('\u00AD', '\u00AD'),
+    ('\u00AF', '\u00AF'),
+    ('\u00B4', '\u00B4'),
+    ('\u00B7', '\u00B8'),
+    ('\u02B0', '\u036F'),
+    ('\u0374', '\u0375'),
+    ('\u037A', '\u037A'),
+    ('\u0384', '\u0385'),
+    ('\u0387', '\u0387'),
+    ('\u0483', '\u0489'),
+    ('\u0559', '\u0559'),
+    ('\u055F', '\u055F'),
+    ('\u0591', '\u05BD'),
+    ('\u05BF', '\u05BF'),
+    ('\u05C1', '\u05C2'),
+    ('\u05C4', '\u05C5'),
+    ('\u05C7', '\u05C7'),
+    ('\u05F4', '\u05F4'),
+    ('\u0600', '\u0605'),
+    ('\u0610', '\u061A'),
+    ('\u061C', '\u061C'),
+    ('\u0640', '\u0640'),
+    ('\u064B', '\u065F'),
+    ('\u0670', '\u0670'),
+    ('\u06D6', '\u06DD'),
+    ('\u06DF', '\u06E8'),
+    ('\u06EA', '\u06ED'),
+    ('\u070F', '\u070F'),
+    ('\u0711', '\u0711'),
+    ('\u0730', '\u074A'),
+    ('\u07A6', '\u07B0'),
+    ('\u07EB', '\u07F5'),
+    ('\u07FA', '\u07FA'),
+    ('\u07FD', '\u07FD'),
+    ('\u0816', '\u082D'),
+    ('\u0859', '\u085B'),
+    ('\u0888', '\u0888'),
+    ('\u0890', '\u0891'),
+    ('\u0898', '\u089F'),
+    ('\u08C9', '\u0902'),
+    ('\u093A', '\u093A'),
+    ('\u093C', '\u093C'),
+    ('\u0941', '\u0948'),
+    ('\u094D', '\u094D'),
+    ('\u0951', '\u0957'),
+    ('\u0962', '\u0963'),
+    ('\u0971', '\u0971'),
+    ('\u0981', '\u0981'),
+    ('\u09BC', '\u09BC'),
+    ('\u09C1', '\u09C4'),
+    ('\u09CD', '\u09CD'),
+    ('\u09E2', '\u09E3'),
+    ('\u09FE', '\u09FE'),
+    ('\u0A01', '\u0A02'),
+    ('\u0A3C', '\u0A3C'),
+    ('\u0A41', '\u0A42'),
+    ('\u0A47', '\u0A48'),
+    ('\u0A4B', '\u0A4D'),
+    ('\u0A51', '\u0A51'),
+    ('\u0A70', '\u0A71'),
+    ('\u0A75', '\u0A75'),
+    ('\u0A81', '\u0A82'),
+    ('\u0ABC', '\u0ABC'),
+    ('\u0AC1', '\u0AC5'),
+    ('\u0AC7', '\u0AC8'),
+    ('\u0ACD', '\u0ACD'),
+    ('\u0AE2', '\u0AE3'),
+    ('\u0AFA', '\u0AFF'),
+    ('\u0B01', '\u0B01'),
+    ('\u0B3C', '\u0B3C'),
+    ('\u0B3F', '\u0B3F'),
+    ('\u0B41', '\u0B44'),
+    ('\u0B4D', '\u0B4D'),
+    ('\u0B55', '\u0B56'),
+    ('\u0B62', '\u0B63'),
+    ('\u0B82', '\u0B82'),
+    ('\u0BC0', '\u0BC0'),
+    ('\u0BCD', '\u0BCD'),
+    ('\u0C00', '\u0C00'),
+    ('\u0C04', '\u0C04'),
+    ('\u0C3C', '\u0C3C'),
+    ('\u0C3E', '\u0C40'),
+    ('\u0C46', '\u0C48'),
+    ('\u0C4A', '\u0C4D'),
+    ('\u0C55', '\u0C56'),
+    ('\u0C62', '\u0C63'),
+    ('\u0C81', '\u0C81'),
+    ('\u0CBC', '\u0CBC'),
+    ('\u0CBF', '\u0CBF'),
+    ('\u0CC6', '\u0CC6'),
+    ('\u0CCC', '\u0CCD'),
+    ('\u0CE2', '\u0CE3'),
+    ('\u0D00', '\u0D01'),
+    ('\u0D3B', '\u0D3C'),
+    ('\u0D41', '\u0D44'),
+    ('\u0D4D', '\u0D4D'),
+    ('\u0D62', '\u0D63'),
+    ('\u0D81', '\u0D81'),
+    ('\u0DCA', '\u0DCA'),
+    ('\u0DD2', '\u0DD4'),
+    ('\u0DD6', '\u0DD6'),
+    ('\u0E31', '\u0E31'),
+    ('\u0E34', '\u0E3A'),
+    ('\u0E46', '\u0E4E'),
+    ('\u0EB1', '\u0EB1'),
+    ('\u0EB4', '\u0EBC'),
+    ('\u0EC6', '\u0EC6'),
+    ('\u0EC8', '\u0ECD'),
+    ('\u0F18', '\u0F19'),
+    ('\u0F35', '\u0F35'),
+    ('\u0F37', '\u0F37'),
+    ('\u0F39', '\u0F39'),
+    ('\u0F71', '\u0F7E'),
+    ('\u0F80', '\u0F84'),
+    ('\u0F86', '\u0F87'),
+    ('\u0F8D', '\u0F97'),
+    ('\u0F99', '\u0FBC'),
+    ('\u0FC6', '\u0FC6'),
+    ('\u102D', '\u1030'),
+    ('\u1032', '\u1037'),
+    ('\u1039', '\u103A'),
+    ('\u103D', '\u103E'),
+    ('\u1058', '\u1059'),
+    ('\u105E', '\u1060'),
+    ('\u1071', '\u1074'),
+    ('\u1082', '\u1082'),
+    ('\u1085', '\u1086'),
+    ('\u108D', '\u108D'),
+    ('\u109D', '\u109D'),
+    ('\u10FC', '\u10FC'),
+    ('\u135D', '\u135F'),
+    ('\u1712', '\u1714'),
+    ('\u1732', '\u1733'),
+    ('\u1752', '\u1753'),
+    ('\u1772', '\u1773'),
+    ('\u17B4', '\u17B5'),
+    ('\u17B7', '\u17BD'),
+    ('\u17C6', '\u17C6'),
+    ('\u17C9', '\u17D3'),
+    ('\u17D7', '\u17D7'),
+    ('\u17DD', '\u17DD'),
+    ('\u180B', '\u180F'),
+    ('\u1843', '\u1843'),
+    ('\u1885', '\u1886'),
+    ('\u18A9', '\u18A9'),
+    ('\u1920', '\u1922'),
+    ('\u1927', '\u1928'),
+    ('\u1932', '\u1932'),
+    ('\u1939', '\u193B'),
+    ('\u1A17', '\u1A18'),
+    ('\u1A1B', '\u1A1B'),
+    ('\u1A56', '\u1A56'),
+    ('\u1A58', '\u1A5E'),
+    ('\u1A60', '\u1A60'),
+    ('\u1A62', '\u1A62'),
+    ('\u1A65', '\u1A6C'),
+    ('\u1A73', '\u1A7C'),
+    ('\u1A7F', '\u1A7F'),
+    ('\u1AA7', '\u1AA7'),
+    ('\u1AB0', '\u1ACE'),
+    ('\u1B00', '\u1B03'),
+    ('\u1B34', '\u1B34'),
+    ('\u1B36', '\u1B3A'),
+    ('\u1B3C', '\u1B3C'),
+    ('\u1B42', '\u1B42'),
+    ('\u1B6B', '\u1B73'),
+    ('\u1B80', '\u1B81'),
+    ('\u1BA2', '\u1BA5'),
+    ('\u1BA8', '\u1BA9'),
+    ('\u1BAB', '\u1BAD'),
+    ('\u1BE6', '\u1BE6'),
+    ('\u1BE8', '\u1BE9'),
+    ('\u1BED', '\u1BED'),
+    ('\u1BEF', '\u1BF1'),
+    ('\u1C2C', '\u1C33'),
+    ('\u1C36', '\u1C37'),
+    ('\u1C78', '\u1C7D'),
+    ('\u1CD0', '\u1CD2'),
+    ('\u1CD4', '\u1CE0'),
+    ('\u1CE2', '\u1CE8'),
+    ('\u1CED', '\u1CED'),
+    ('\u1CF4', '\u1CF4'),
+    ('\u1CF8', '\u1CF9'),
+    ('\u1D2C', '\u1D6A'),
+    ('\u1D78', '\u1D78'),
+    ('\u1D9B', '\u1DFF'),
+    ('\u1FBD', '\u1FBD'),
+    ('\u1FBF', '\u1FC1'),
+    ('\u1FCD', '\u1FCF'),
+    ('\u1FDD', '\u1FDF'),
+    ('\u1FED', '\u1FEF'),
+    ('\u1FFD', '\u1FFE'),
+    ('\u200B', '\u200F'),
+    ('\u2018', '\u2019'),
+    ('\u2024', '\u2024'),
+    ('\u2027', '\u2027'),
+    ('\u202A', '\u202E'),
+    ('\u2060', '\u2064'),
+    ('\u2066', '\u206F'),
+    ('\u2071', '\u2071'),
+    ('\u207F', '\u207F'),
+    ('\u2090', '\u209C'),
+    ('\u20D0', '\u20F0'),
+    ('\u2C7C', '\u2C7D'),
+    ('\u2CEF', '\u2CF1'),
+    ('\u2D6F', '\u2D6F'),
+    ('\u2D7F', '\u2D7F'),
+    ('\u2DE0', '\u2DFF'),
+    ('\u2E2F', '\u2E2F'),
+    ('\u3005', '\u3005'),
+    ('\u302A', '\u302D'),
+    ('\u3031', '\u3035'),
+    ('\u303B', '\u303B'),
+    ('\u3099', '\u309E'),
+    ('\u30FC', '\u30FE'),
+    ('\uA015', '\uA015'),
+    ('\uA4F8', '\uA4FD'),
+    ('\uA60C', '\uA60C'),
+    ('\uA66F', '\uA672'),
+    ('\uA674', '\uA67D'),
+    ('\uA67F', '\uA67F'),
+    ('\uA69C', '\uA69F'),
+    ('\uA6F0', '\uA6F1'),
+    ('\uA700', '\uA721'),
+    ('\uA770', '\uA770'),
+    ('\uA788', '\uA78A'),
+    ('\uA7F2', '\uA7F4'),
+    ('\uA7F8', '\uA7F9'),
+    ('\uA802', '\uA802'),
+    ('\uA806', '\uA806'),
+    ('\uA80B', '\uA80B'),
+    ('\uA825', '\uA826'),
+    ('\uA82C', '\uA82C'),
+    ('\uA8C4', '\uA8C5'),
+    ('\uA8E0', '\uA8F1'),
+    ('\uA8FF', '\uA8FF'),
+    ('\uA926', '\uA92D'),
+    ('\uA947', '\uA951'),
+    ('\uA980', '\uA982'),
+    ('\uA9B3', '\uA9B3'),
+    ('\uA9B6', '\uA9B9'),
+    ('\uA9BC', '\uA9BD'),
+    ('\uA9CF', '\uA9CF'),
+    ('\uA9E5', '\uA9E6'),
+    ('\uAA29', '\uAA2E'),
+    ('\uAA31', '\uAA32'),
+    ('\uAA35', '\uAA36'),
+    ('\uAA43', '\uAA43'),
+    ('\uAA4C', '\uAA4C'),
+    ('\uAA70', '\uAA70'),
+    ('\uAA7C', '\uAA7C'),
+    ('\uAAB0', '\uAAB0'),
+    ('\uAAB2', '\uAAB4'),
+    ('\uAAB7', '\uAAB8'),
+    ('\uAABE', '\uAABF'),
+    ('\uAAC1', '\uAAC1'),
+    ('\uAADD', '\uAADD'),
+    ('\uAAEC', '\uAAED'),
+    ('\uAAF3', '\uAAF4'),
+    ('\uAAF6', '\uAAF6'),
+    ('\uAB5B', '\uAB5F'),
+    ('\uAB69', '\uAB6B'),
+    ('\uABE5', '\uABE5'),
+    ('\uABE8', '\uABE8'),
+    ('\uABED', '\uABED'),
+    ('\uFB1E', '\uFB1E'),
+    ('\uFBB2', '\uFBC2'),
+    ('\uFE00', '\uFE0F'),
+    ('\uFE13', '\uFE13'),
+    ('\uFE20', '\uFE2F'),
+    ('\uFE52', '\uFE52'),
+    ('\uFE55', '\uFE55'),
+    ('\uFEFF', '\uFEFF'),
+    ('\uFF07', '\uFF07'),
+    ('\uFF0E', '\uFF0E'),
+    ('\uFF1A', '\uFF1A'),
+    ('\uFF3E', '\uFF3E'),
+    ('\uFF40', '\uFF40'),
+    ('\uFF70', '\uFF70'),
+    ('\uFF9E', '\uFF9F'),
+    ('\uFFE3', '\uFFE3'),
+    ('\uFFF9', '\uFFFB'),
+    ('\U000101FD', '\U000101FD'),
+    ('\U000102E0', '\U000102E0'),
+    ('\U00010376', '\U0001037A'),
+    ('\U00010780', '\U00010785'),
+    ('\U00010787', '\U000107B0'),
+    ('\U000107B2', '\U000107BA'),
+    ('\U00010A01', '\U00010A03'),
+    ('\U00010A05', '\U00010A06'),
+    ('\U00010A0C', '\U00010A0F'),
+    ('\U00010A38', '\U00010A3A'),
+    ('\U00010A3F', '\U00010A3F'),
+    ('\U00010AE5', '\U00010AE6'),
+    ('\U00010D24', '\U00010D27'),
+    ('\U00010EAB', '\U00010EAC'),
+    ('\U00010F46', '\U00010F50'),
+    ('\U00010F82', '\U00010F85'),
+    ('\U00011001', '\U00011001'),
+    ('\U00011038', '\U00011046'),
+    ('\U00011070', '\U00011070'),
+    ('\U00011073', '\U00011074'),
+    ('\U0001107F', '\U00011081'),
+    ('\U000110B3', '\U000110B6'),
+    ('\U000110B9', '\U000110BA'),
+    ('\U000110BD', '\U000110BD'),
+    ('\U000110C2', '\U000110C2'),
+    ('\U000110CD', '\U000110CD'),
+    ('\U00011100', '\U00011102'),
+    ('\U00011127', '\U0001112B'),
+    ('\U0001112D', '\U00011134'),
+    ('\U00011173', '\U00011173'),
+    ('\U00011180', '\U00011181'),
+    ('\U000111B6', '\U000111BE'),
+    ('\U000111C9', '\U000111CC'),
+    ('\U000111CF', '\U000111CF'),
+    ('\U0001122F', '\U00011231'),
+    ('\U00011234', '\U00011234'),
+    ('\U00011236', '\U00011237'),
+    ('\U0001123E', '\U0001123E'),
+    ('\U000112DF', '\U000112DF'),
+    ('\U000112E3', '\U000112EA'),
+    ('\U00011300', '\U00011301'),
+    ('\U0001133B', '\U0001133C'),
+    ('\U00011340', '\U00011340'),
+    ('\U00011366', '\U0001136C'),
+    ('\U00011370', '\U00011374'),
+    ('\U00011438', '\U0001143F'),
+    ('\U00011442', '\U00011444'),
+    ('\U00011446', '\U00011446'),
+    ('\U0001145E', '\U0001145E'),
+    ('\U000114B3', '\U000114B8'),
+    ('\U000114BA', '\U000114BA'),
+    ('\U000114BF', '\U000114C0'),
+    ('\U000114C2', '\U000114C3'),
+    ('\U000115B2', '\U000115B5'),
+    ('\U000115BC', '\U000115BD'),
+    ('\U000115BF', '\U000115C0'),
+    ('\U000115DC', '\U000115DD'),
+    ('\U00011633', '\U0001163A'),
+    ('\U0001163D', '\U0001163D'),
+    ('\U0001163F', '\U00011640'),
+    ('\U000116AB', '\U000116AB'),
+    ('\U000116AD', '\U000116AD'),
+    ('\U000116B0', '\U000116B5'),
+    ('\U000116B7', '\U000116B7'),
+    ('\U0001171D', '\U0001171F'),
+    ('\U00011722', '\U00011725'),
+    ('\U00011727', '\U0001172B'),
+    ('\U0001182F', '\U00011837'),
+    ('\U00011839', '\U0001183A'),
+    ('\U0001193B', '\U0001193C'),
+    ('\U0001193E', '\U0001193E'),
+    ('\U00011943', '\U00011943'),
+    ('\U000119D4', '\U000119D7'),
+    ('\U000119DA', '\U000119DB'),
+    ('\U000119E0', '\U000119E0'),
+    ('\U00011A01', '\U00011A0A'),
+    ('\U00011A33', '\U00011A38'),
+    ('\U00011A3B', '\U00011A3E'),
+    ('\U00011A47', '\U00011A47'),
+    ('\U00011A51', '\U00011A56'),
+    ('\U00011A59', '\U00011A5B'),
+    ('\U00011A8A', '\U00011A96'),
+    ('\U00011A98', '\U00011A99'),
+    ('\U00011C30', '\U00011C36'),
+    ('\U00011C38', '\U00011C3D'),
+    ('\U00011C3F', '\U00011C3F'),
+    ('\U00011C92', '\U00011CA7'),
+    ('\U00011CAA', '\U00011CB0'),
+    ('\U00011CB2', '\U00011CB3'),
+    ('\U00011CB5', '\U00011CB6'),
+    ('\U00011D31', '\U00011D36'),
+    ('\U00011D3A', '\U00011D3A'),
+    ('\U00011D3C', '\U00011D3D'),
+    ('\U00011D3F', '\U00011D45'),
+    ('\U00011D47', '\U00011D47'),
+    ('\U00011D90', '\U00011D91'),
+    ('\U00011D95', '\U00011D95'),
+    ('\U00011D97', '\U00011D97'),
+    ('\U00011EF3', '\U00011EF4'),
+    ('\U00013430', '\U00013438'),
+    ('\U00016AF0', '\U00016AF4'),
+    ('\U00016B30', '\U00016B36'),
+    ('\U00016B40', '\U00016B43'),
+    ('\U00016F4F', '\U00016F4F'),
+    ('\U00016F8F', '\U00016F9F'),
+    ('\U00016FE0', '\U00016FE1'),
+    ('\U00016FE3', '\U00016FE4'),
+    ('\U0001AFF0', '\U0001AFF3'),
+    ('\U0001AFF5', '\U0001AFFB'),
+    ('\U0001AFFD', '\U0001AFFE'),
+    ('\U0001BC9D', '\U0001BC9E'),
+    ('\U0001BCA0', '\U0001BCA3'),
+    ('\U0001CF00', '\U0001CF2D'),
+    ('\U0001CF30', '\U0001CF46'),
+    ('\U0001D167', '\U0001D169'),
+    ('\U0001D173', '\U0001D182'),
+    ('\U0001D185', '\U0001D18B'),
+    ('\U0001D1AA', '\U0001D1AD'),
+    ('\U0001D242', '\U0001D244'),
+    ('\U0001DA00', '\U0001DA36'),
+    ('\U0001DA3B', '\U0001DA6C'),
+    ('\U0001DA75', '\U0001DA75'),
+    ('\U0001DA84', '\U0001DA84'),
+    ('\U0001DA9B', '\U0001DA9F'),
+    ('\U0001DAA1', '\U0001DAAF'),
+    ('\U0001E000', '\U0001E006'),
+    ('\U0001E008', '\U0001E018'),
+    ('\U0001E01B', '\U0001E021'),
+    ('\U0001E023', '\U0001E024'),
+    ('\U0001E026', '\U0001E02A'),
+    ('\U0001E130', '\U0001E13D'),
+    ('\U0001E2AE', '\U0001E2AE'),
+    ('\U0001E2EC', '\U0001E2EF'),
+    ('\U0001E8D0', '\U0001E8D6'),
+    ('\U0001E944', '\U0001E94B'),
+    ('\U0001F3FB', '\U0001F3FF'),
+    ('\U000E0001', '\U000E0001'),
+    ('\U000E0020', '\U000E007F'),
+    ('\U000E0100', '\U000E01EF')
+  ]),
+  ("dash", &[
+    ('\u002D', '\u002D'),
+    ('\u058A', '\u058A'),
+    ('\u05BE', '\u05BE'),
+    ('\u1400', '\u1400'),
+    ('\u1806', '\u1806'),
+    ('\u2010', '\u2015'),
+    ('\u2053', '\u2053'),
+    ('\u207B', '\u207B'),
+    ('\u208B', '\u208B'),
+    ('\u2212', '\u2212'),
+    ('\u2E17', '\u2E17'),
+    ('\u2E1A', '\u2E1A'),
+    ('\u2E3A', '\u2E3B'),
+    ('\u2E40', '\u2E40'),
+    ('\u2E5D', '\u2E5D'),
+    ('\u301C', '\u301C'),
+    ('\u3030', '\u3030'),
+    ('\u30A0', '\u30A0'),
+    ('\uFE31', '\uFE32'),
+    ('\uFE58', '\uFE58'),
+    ('\uFE63', '\uFE63'),
+    ('\uFF0D', '\uFF0D'),
+    ('\U00010EAD', '\U00010EAD')
+  ]),
+  ("defaultignorablecodepoint", &[
+    ('\u00AD', '\u00AD'),
+    ('\u034F', '\u034F'),
+    ('\u061C', '\u061C'),
+    ('\u115F', '\u1160'),
+    ('\u17B4', '\u17B5'),
+    ('\u180B', '\u180F'),
+    ('\u200B', '\u200F'),
+    ('\u202A', '\u202E'),
+    ('\u2060', '\u206F'),
+    ('\u3164', '\u3164'),
+    ('\uFE00', '\uFE0F'),
+    ('\uFEFF', '\uFEFF'),
+    ('\uFFA0', '\uFFA0'),
+    ('\uFFF0', '\uFFF8'),
+    ('\U0001BCA0', '\U0001BCA3'),
+    ('\U0001D173', '\U0001D17A'),
+    ('\U000E0000', '\U000E0FFF')
+  ]),
+  ("hexdigit", &[
+    ('\u0030', '\u0039'),
+    ('\u0041', '\u0046'),
+    ('\u0061', '\u0066'),
+    ('\uFF10', '\uFF19'),
+    ('\uFF21', '\uFF26'),
+    ('\uFF41', '\uFF46')
+  ]),
+  ("idcontinue", &[
+    ('\u0030', '\u0039'),
+    ('\u0041', '\u005A'),
+    ('\u005F', '\u005F'),
+    ('\u0061', '\u007A'),
+    ('\u00AA', '\u00AA'),
+    ('\u00B5', '\u00B5'),
+    ('\u00B7', '\u00B7'),
+    ('\u00BA', '\u00BA'),
+    ('\u00C0', '\u00D6'),
+    ('\u00D8', '\u00F6'),
+    ('\u00F8', '\u02C1'),
+    ('\u02C6', '\u02D1'),
+    ('\u02E0', '\u02E4'),
+    ('\u02EC', '\u02EC'),
+    ('\u02EE', '\u02EE'),
+    ('\u0300', '\u0374'),
+    ('\u0376', '\u0377'),
+    ('\u037A', '\u037D'),
+    ('\u037F', '\u037F'),
+    ('\u0386', '\u038A'),
+    ('\u038C', '\u038C'),
+    ('\u038E', '\u03A1'),
+    ('\u03A3', '\u03F5'),
+    ('\u03F7', '\u0481'),
+    ('\u0483', '\u0487'),
+    ('\u048A', '\u052F'),
+    ('\u0531', '\u0556'),
+    ('\u0559', '\u0559'),
+    ('\u0560', '\u0588'),
+    ('\u0591', '\u05BD'),
+    ('\u05BF', '\u05BF'),
+    ('\u05C1', '\u05C2'),
+    ('\u05C4', '\u05C5'),
+    ('\u05C7', '\u05C7'),
+    ('\u05D0', '\u05EA'),
+    ('\u05EF', '\u05F2'),
+    ('\u0610', '\u061A'),
+    ('\u0620', '\u0669'),
+    ('\u066E', '\u06D3'),
+    ('\u06D5', '\u06DC'),
+    ('\u06DF', '\u06E8'),
+    ('\u06EA', '\u06FC'),
+    ('\u06FF', '\u06FF'),
+    ('\u0710', '\u074A'),
+    ('\u074D', '\u07B1'),
+    ('\u07C0', '\u07F5'),
+    ('\u07FA', '\u07FA'),
+    ('\u07FD', '\u07FD'),
+    ('\u0800', '\u082D'),
+    ('\u0840', '\u085B'),
+    ('\u0860', '\u086A'),
+    ('\u0870', '\u0887'),
+    ('\u0889', '\u088E'),
+    ('\u0898', '\u08E1'),
+    ('\u08E3', '\u0963'),
+    ('\u0966', '\u096F'),
+    ('\u0971', '\u0983'),
+    ('\u0985', '\u098C'),
+    ('\u098F', '\u0990'),
+    ('\u0993', '\u09A8'),
+    ('\u09AA', '\u09B0'),
+    ('\u09B2', '\u09B2'),
+    ('\u09B6', '\u09B9'),
+    ('\u09BC', '\u09C4'),
+    ('\u09C7', '\u09C8'),
+    ('\u09CB', '\u09CE'),
+    ('\u09D7', '\u09D7'),
+    ('\u09DC', '\u09DD'),
+    ('\u09DF', '\u09E3'),
+    ('\u09E6', '\u09F1'),
+    ('\u09FC', '\u09FC'),
+    ('\u09FE', '\u09FE'),
+    ('\u0A01', '\u0A03'),
+    ('\u0A05', '\u0A0A'),
+    ('\u0A0F', '\u0A10'),
+    ('\u0A13', '\u0A28'),
+    ('\u0A2A', '\u0A30'),
+    ('\u0A32', '\u0A33'),
+    ('\u0A35', '\u0A36'),
+    ('\u0A38', '\u0A39'),
+    ('\u0A3C', '\u0A3C'),
+    ('\u0A3E', '\u0A42'),
+    ('\u0A47', '\u0A48'),
+    ('\u0A4B', '\u0A4D'),
+    ('\u0A51', '\u0A51'),
+    ('\u0A59', '\u0A5C'),
+    ('\u0A5E', '\u0A5E'),
+    ('\u0A66', '\u0A75'),
+    ('\u0A81', '\u0A83'),
+    ('\u0A85', '\u0A8D'),
+    ('\u0A8F', '\u0A91'),
+    ('\u0A93', '\u0AA8'),
+    ('\u0AAA', '\u0AB0'),
+    ('\u0AB2', '\u0AB3'),
+    ('\u0AB5', '\u0AB9'),
+    ('\u0ABC', '\u0AC5'),
+    ('\u0AC7', '\u0AC9'),
+    ('\u0ACB', '\u0ACD'),
+    ('\u0AD0', '\u0AD0'),
+    ('\u0AE0', '\u0AE3'),
+    ('\u0AE6', '\u0AEF'),
+    ('\u0AF9', '\u0AFF'),
+    ('\u0B01', '\u0B03'),
+    ('\u0B05', '\u0B0C'),
+    ('\u0B0F', '\u0B10'),
+    ('\u0B13', '\u0B28'),
+    ('\u0B2A', '\u0B30'),
+    ('\u0B32', '\u0B33'),
+    ('\u0B35', '\u0B39'),
+    ('\u0B3C', '\u0B44'),
+    ('\u0B47', '\u0B48'),
+    ('\u0B4B', '\u0B4D'),
+    ('\u0B55', '\u0B57'),
+    ('\u0B5C', '\u0B5D'),
+    ('\u0B5F', '\u0B63'),
+    ('\u0B66', '\u0B6F'),
+    ('\u0B71', '\u0B71'),
+    ('\u0B82', '\u0B83'),
+    ('\u0B85', '\u0B8A'),
+    ('\u0B8E', '\u0B90'),
+    ('\u0B92', '\u0B95'),
+    ('\u0B99', '\u0B9A'),
+    ('\u0B9C', '\u0B9C'),
+    ('\u0B9E', '\u0B9F'),
+    ('\u0BA3', '\u0BA4'),
+    ('\u0BA8', '\u0BAA'),
+    ('\u0BAE', '\u0BB9'),
+    ('\u0BBE', '\u0BC2'),
+    ('\u0BC6', '\u0BC8'),
+    ('\u0BCA', '\u0BCD'),
+    ('\u0BD0', '\u0BD0'),
+    ('\u0BD7', '\u0BD7'),
+    ('\u0BE6', '\u0BEF'),
+    ('\u0C00', '\u0C0C'),
+    ('\u0C0E', '\u0C10'),
+    ('\u0C12', '\u0C28'),
+    ('\u0C2A', '\u0C39'),
+    ('\u0C3C', '\u0C44'),
+    ('\u0C46', '\u0C48'),
+    ('\u0C4A', '\u0C4D'),
+    ('\u0C55', '\u0C56'),
+    ('\u0C58', '\u0C5A'),
+    ('\u0C5D', '\u0C5D'),
+    ('\u0C60', '\u0C63'),
+    ('\u0C66', '\u0C6F'),
+    ('\u0C80', '\u0C83'),
+    ('\u0C85', '\u0C8C'),
+    ('\u0C8E', '\u0C90'),
+    ('\u0C92', '\u0CA8'),
+    ('\u0CAA', '\u0CB3'),
+    ('\u0CB5', '\u0CB9'),
+    ('\u0CBC', '\u0CC4'),
+    ('\u0CC6', '\u0CC8'),
+    ('\u0CCA', '\u0CCD'),
+    ('\u0CD5', '\u0CD6'),
+    ('\u0CDD', '\u0CDE'),
+    ('\u0CE0', '\u0CE3'),
+    ('\u0CE6', '\u0CEF'),
+    ('\u0CF1', '\u0CF2'),
+    ('\u0D00', '\u0D0C'),
+    ('\u0D0E', '\u0D10'),
+    ('\u0D12', '\u0D44'),
+    ('\u0D46', '\u0D48'),
+    ('\u0D4A', '\u0D4E'),
+    ('\u0D54', '\u0D57'),
+    ('\u0D5F', '\u0D63'),
+    ('\u0D66', '\u0D6F'),
+    ('\u0D7A', '\u0D7F'),
+    ('\u0D81', '\u0D83'),
+    ('\u0D85', '\u0D96'),
+    ('\u0D9A', '\u0DB1'),
+    ('\u0DB3', '\u0DBB'),
+    ('\u0DBD', '\u0DBD'),
+    ('\u0DC0', '\u0DC6'),
+    ('\u0DCA', '\u0DCA'),
+    ('\u0DCF', '\u0DD4'),
+    ('\u0DD6', '\u0DD6'),
+    ('\u0DD8', '\u0DDF'),
+    ('\u0DE6', '\u0DEF'),
+    ('\u0DF2', '\u0DF3'),
+    ('\u0E01', '\u0E3A'),
+    ('\u0E40', '\u0E4E'),
+    ('\u0E50', '\u0E59'),
+    ('\u0E81', '\u0E82'),
+    ('\u0E84', '\u0E84'),
+    ('\u0E86', '\u0E8A'),
+    ('\u0E8C', '\u0EA3'),
+    ('\u0EA5', '\u0EA5'),
+    ('\u0EA7', '\u0EBD'),
+    ('\u0EC0', '\u0EC4'),
+    ('\u0EC6', '\u0EC6'),
+    ('\u0EC8', '\u0ECD'),
+    ('\u0ED0', '\u0ED9'),
+    ('\u0EDC', '\u0EDF'),
+    ('\u0F00', '\u0F00'),
+    ('\u0F18', '\u0F19'),
+    ('\u0F20', '\u0F29'),
+    ('\u0F35', '\u0F35'),
+    ('\u0F37', '\u0F37'),
+    ('\u0F39', '\u0F39'),
+    ('\u0F3E', '\u0F47'),
+    ('\u0F49', '\u0F6C'),
+    ('\u0F71', '\u0F84'),
+    ('\u0F86', '\u0F97'),
+    ('\u0F99', '\u0FBC'),
+    ('\u0FC6', '\u0FC6'),
+    ('\u1000', '\u1049'),
+    ('\u1050', '\u109D'),
+    ('\u10A0', '\u10C5'),
+    ('\u10C7', '\u10C7'),
+    ('\u10CD', '\u10CD'),
+    ('\u10D0', '\u10FA'),
+    ('\u10FC', '\u1248'),
+    ('\u124A', '\u124D'),
+    ('\u1250', '\u1256'),
+    ('\u1258', '\u1258'),
+    ('\u125A', '\u125D'),
+    ('\u1260', '\u1288'),
+    ('\u128A', '\u128D'),
+    ('\u1290', '\u12B0'),
+    ('\u12B2', '\u12B5'),
+    ('\u12B8', '\u12BE'),
+    ('\u12C0', '\u12C0'),
+    ('\u12C2', '\u12C5'),
+    ('\u12C8', '\u12D6'),
+    ('\u12D8', '\u1310'),
+    ('\u1312', '\u1315'),
+    ('\u1318', '\u135A'),
+    ('\u135D', '\u135F'),
+    ('\u1369', '\u1371'),
+    ('\u1380', '\u138F'),
+    ('\u13A0', '\u13F5'),
+    ('\u13F8', '\u13FD'),
+    ('\u1401', '\u166C'),
+    ('\u166F', '\u167F'),
+    ('\u1681', '\u169A'),
+    ('\u16A0', '\u16EA'),
+    ('\u16EE', '\u16F8'),
+    ('\u1700', '\u1715'),
+    ('\u171F', '\u1734'),
+    ('\u1740', '\u1753'),
+    ('\u1760', '\u176C'),
+    ('\u176E', '\u1770'),
+    ('\u1772', '\u1773'),
+    ('\u1780', '\u17D3'),
+    ('\u17D7', '\u17D7'),
+    ('\u17DC', '\u17DD'),
+    ('\u17E0', '\u17E9'),
+    ('\u180B', '\u180D'),
+    ('\u180F', '\u1819'),
+    ('\u1820', '\u1878'),
+    ('\u1880', '\u18AA'),
+    ('\u18B0', '\u18F5'),
+    ('\u1900', '\u191E'),
+    ('\u1920', '\u192B'),
+    ('\u1930', '\u193B'),
+    ('\u1946', '\u196D'),
+    ('\u1970', '\u1974'),
+    ('\u1980', '\u19AB'),
+    ('\u19B0', '\u19C9'),
+    ('\u19D0', '\u19DA'),
+    ('\u1A00', '\u1A1B'),
+    ('\u1A20', '\u1A5E'),
+    ('\u1A60', '\u1A7C'),
+    ('\u1A7F', '\u1A89'),
+    ('\u1A90', '\u1A99'),
+    ('\u1AA7', '\u1AA7'),
+    ('\u1AB0', '\u1ABD'),
+    ('\u1ABF', '\u1ACE'),
+    ('\u1B00', '\u1B4C'),
+    ('\u1B50', '\u1B59'),
+    ('\u1B6B', '\u1B73'),
+    ('\u1B80', '\u1BF3'),
+    ('\u1C00', '\u1C37'),
+    ('\u1C40', '\u1C49'),
+    ('\u1C4D', '\u1C7D'),
+    ('\u1C80', '\u1C88'),
+    ('\u1C90', '\u1CBA'),
+    ('\u1CBD', '\u1CBF'),
+    ('\u1CD0', '\u1CD2'),
+    ('\u1CD4', '\u1CFA'),
+    ('\u1D00', '\u1F15'),
+    ('\u1F18', '\u1F1D'),
+    ('\u1F20', '\u1F45'),
+    ('\u1F48', '\u1F4D'),
+    ('\u1F50', '\u1F57'),
+    ('\u1F59', '\u1F59'),
+    ('\u1F5B', '\u1F5B'),
+    ('\u1F5D', '\u1F5D'),
+    ('\u1F5F', '\u1F7D'),
+    ('\u1F80', '\u1FB4'),
+    ('\u1FB6', '\u1FBC'),
+    ('\u1FBE', '\u1FBE'),
+    ('\u1FC2', '\u1FC4'),
+    ('\u1FC6', '\u1FCC'),
+    ('\u1FD0', '\u1FD3'),
+    ('\u1FD6', '\u1FDB'),
+    ('\u1FE0', '\u1FEC'),
+    ('\u1FF2', '\u1FF4'),
+    ('\u1FF6', '\u1FFC'),
+    ('\u203F', '\u2040'),
+    ('\u2054', '\u2054'),
+    ('\u2071', '\u2071'),
+    ('\u207F', '\u207F'),
+    ('\u2090', '\u209C'),
+    ('\u20D0', '\u20DC'),
+    ('\u20E1', '\u20E1'),
+    ('\u20E5', '\u20F0'),
+    ('\u2102', '\u2102'),
+    ('\u2107', '\u2107'),
+    ('\u210A', '\u2113'),
+    ('\u2115', '\u2115'),
+    ('\u2118', '\u211D'),
+    ('\u2124', '\u2124'),
+    ('\u2126', '\u2126'),
+    ('\u2128', '\u2128'),
+    ('\u212A', '\u2139'),
+    ('\u213C', '\u213F'),
+    ('\u2145', '\u2149'),
+    ('\u214E', '\u214E'),
+    ('\u2160', '\u2188'),
+    ('\u2C00', '\u2CE4'),
+    ('\u2CEB', '\u2CF3'),
+    ('\u2D00', '\u2D25'),
+    ('\u2D27', '\u2D27'),
+    ('\u2D2D', '\u2D2D'),
+    ('\u2D30', '\u2D67'),
+    ('\u2D6F', '\u2D6F'),
+    ('\u2D7F', '\u2D96'),
+    ('\u2DA0', '\u2DA6'),
+    ('\u2DA8', '\u2DAE'),
+    ('\u2DB0', '\u2DB6'),
+    ('\u2DB8', '\u2DBE'),
+    ('\u2DC0', '\u2DC6'),
+    ('\u2DC8', '\u2DCE'),
+    ('\u2DD0', '\u2DD6'),
+    ('\u2DD8', '\u2DDE'),
+    ('\u2DE0', '\u2DFF'),
+    ('\u3005', '\u3007'),
+    ('\u3021', '\u302F'),
+    ('\u3031', '\u3035'),
+    ('\u3038', '\u303C'),
+    ('\u3041', '\u3096'),
+    ('\u3099', '\u309F'),
+    ('\u30A1', '\u30FA'),
+    ('\u30FC', '\u30FF'),
+    ('\u3105', '\u312F'),
+    ('\u3131', '\u318E'),
+    ('\u31A0', '\u31BF'),
+    ('\u31F0', '\u31FF'),
+    ('\u3400', '\u4DBF'),
+    ('\u4E00', '\uA48C'),
+    ('\uA4D0', '\uA4FD'),
+    ('\uA500', '\uA60C'),
+    ('\uA610', '\uA62B'),
+    ('\uA640', '\uA66F'),
+    ('\uA674', '\uA67D'),
+    ('\uA67F', '\uA6F1'),
+    ('\uA717', '\uA71F'),
+    ('\uA722', '\uA788'),
+    ('\uA78B', '\uA7CA'),
+    ('\uA7D0', '\uA7D1'),
+    ('\uA7D3', '\uA7D3'),
+    ('\uA7D5', '\uA7D9'),
+    ('\uA7F2', '\uA827'),
+    ('\uA82C', '\uA82C'),
+    ('\uA840', '\uA873'),
+    ('\uA880', '\uA8C5'),
+    ('\uA8D0', '\uA8D9'),
+    ('\uA8E0', '\uA8F7'),
+    ('\uA8FB', '\uA8FB'),
+    ('\uA8FD', '\uA92D'),
+    ('\uA930', '\uA953'),
+    ('\uA960', '\uA97C'),
+    ('\uA980', '\uA9C0'),
+    ('\uA9CF', '\uA9D9'),
+    ('\uA9E0', '\uA9FE'),
+    ('\uAA00', '\uAA36'),
+    ('\uAA40', '\uAA4D'),
+    ('\uAA50', '\uAA59'),
+    ('\uAA60', '\uAA76'),
+    ('\uAA7A', '\uAAC2'),
+    ('\uAADB', '\uAADD'),
+    ('\uAAE0', '\uAAEF'),
+    ('\uAAF2', '\uAAF6'),
+    ('\uAB01', '\uAB06'),
+    ('\uAB09', '\uAB0E'),
+    ('\uAB11', '\uAB16'),
+    ('\uAB20', '\uAB26'),
+    ('\uAB28', '\uAB2E'),
+    ('\uAB30', '\uAB5A'),
+    ('\uAB5C', '\uAB69'),
+    ('\uAB70', '\uABEA'),
+    ('\uABEC', '\uABED'),
+    ('\uABF0', '\uABF9'),
+    ('\uAC00', '\uD7A3'),
+    ('\uD7B0', '\uD7C6'),
+    ('\uD7CB', '\uD7FB'),
+    ('\uF900', '\uFA6D'),
+    ('\uFA70', '\uFAD9'),
+    ('\uFB00', '\uFB06'),
+    ('\uFB13', '\uFB17'),
+    ('\uFB1D', '\uFB28'),
+    ('\uFB2A', '\uFB36'),
+    ('\uFB38', '\uFB3C'),
+    ('\uFB3E', '\uFB3E'),
+    ('\uFB40', '\uFB41'),
+    ('\uFB43', '\uFB44'),
+    ('\uFB46', '\uFBB1'),
+    ('\uFBD3', '\uFD3D'),
+    ('\uFD50', '\uFD8F'),
+    ('\uFD92', '\uFDC7'),
+    ('\uFDF0', '\uFDFB'),
+    ('\uFE00', '\uFE0F'),
+    ('\uFE20', '\uFE2F'),
+    ('\uFE33', '\uFE34'),
+    ('\uFE4D', '\uFE4F'),
+    ('\uFE70', '\uFE74'),
+    ('\uFE76', '\uFEFC'),
+    ('\uFF10', '\uFF19'),
+    ('\uFF21', '\uFF3A'),
+    ('\uFF3F', '\uFF3F'),
+    ('\uFF41', '\uFF5A'),
+    ('\uFF66', '\uFFBE'),
+    ('\uFFC2', '\uFFC7'),
+    ('\uFFCA', '\uFFCF'),
+    ('\uFFD2', '\uFFD7'),
+    ('\uFFDA', '\uFFDC'),
+    ('\U00010000', '\U0001000B'),
+    ('\U0001000D', '\U00010026'),
+    ('\U00010028', '\U0001003A'),
+    ('\U0001003C', '\U0001003D'),
+    ('\U0001003F', '\U0001004D'),
+    ('\U00010050', '\U0001005D'),
+    ('\U00010080', '\U000100FA'),
+    ('\U00010140', '\U00010174'),
+    ('\U000101FD', '\U000101FD'),
+    ('\U00010280', '\U0001029C'),
+    ('\U000102A0', '\U000102D0'),
+    ('\U000102E0', '\U000102E0'),
+    ('\U00010300', '\U0001031F'),
+    ('\U0001032D', '\U0001034A'),
+    ('\U00010350', '\U0001037A'),
+    ('\U00010380', '\U0001039D'),
+    ('\U000103A0', '\U000103C3'),
+    ('\U000103C8', '\U000103CF'),
+    ('\U000103D1', '\U000103D5'),
+    ('\U00010400', '\U0001049D'),
+    ('\U000104A0', '\U000104A9'),
+    ('\U000104B0', '\U000104D3'),
+    ('\U000104D8', '\U000104FB'),
+    ('\U00010500', '\U00010527'),
+    ('\U00010530', '\U00010563'),
+    ('\U00010570', '\U0001057A'),
+    ('\U0001057C', '\U0001058A'),
+    ('\U0001058C', '\U00010592'),
+    ('\U00010594', '\U00010595'),
+    ('\U00010597', '\U000105A1'),
+    ('\U000105A3', '\U000105B1'),
+    ('\U000105B3', '\U000105B9'),
+    ('\U000105BB', '\U000105BC'),
+    ('\U00010600', '\U00010736'),
+    ('\U00010740', '\U00010755'),
+    ('\U00010760', '\U00010767'),
+    ('\U00010780', '\U00010785'),
+    ('\U00010787', '\U000107B0'),
+    ('\U000107B2', '\U000107BA'),
+    ('\U00010800', '\U00010805'),
+    ('\U00010808', '\U00010808'),
+    ('\U0001080A', '\U00010835'),
+    ('\U00010837', '\U00010838'),
+    ('\U0001083C', '\U0001083C'),
+    ('\U0001083F', '\U00010855'),
+    ('\U00010860', '\U00010876'),
+    ('\U00010880', '\U0001089E'),
+    ('\U000108E0', '\U000108F2'),
+    ('\U000108F4', '\U000108F5'),
+    ('\U00010900', '\U00010915'),
+    ('\U00010920', '\U00010939'),
+    ('\U00010980', '\U000109B7'),
+    ('\U000109BE', '\U000109BF'),
+    ('\U00010A00', '\U00010A03'),
+    ('\U00010A05', '\U00010A06'),
+    ('\U00010A0C', '\U00010A13'),
+    ('\U00010A15', '\U00010A17'),
+    ('\U00010A19', '\U00010A35'),
+    ('\U00010A38', '\U00010A3A'),
+    ('\U00010A3F', '\U00010A3F'),
+    ('\U00010A60', '\U00010A7C'),
+    ('\U00010A80', '\U00010A9C'),
+    ('\U00010AC0', '\U00010AC7'),
+    ('\U00010AC9', '\U00010AE6'),
+    ('\U00010B00', '\U00010B35'),
+    ('\U00010B40', '\U00010B55'),
+    ('\U00010B60', '\U00010B72'),
+    ('\U00010B80', '\U00010B91'),
+    ('\U00010C00', '\U00010C48'),
+    ('\U00010C80', '\U00010CB2'),
+    ('\U00010CC0', '\U00010CF2'),
+    ('\U00010D00', '\U00010D27'),
+    ('\U00010D30', '\U00010D39'),
+    ('\U00010E80', '\U00010EA9'),
+    ('\U00010EAB', '\U00010EAC'),
+    ('\U00010EB0', '\U00010EB1'),
+    ('\U00010F00', '\U00010F1C'),
+    ('\U00010F27', '\U00010F27'),
+    ('\U00010F30', '\U00010F50'),
+    ('\U00010F70', '\U00010F85'),
+    ('\U00010FB0', '\U00010FC4'),
+    ('\U00010FE0', '\U00010FF6'),
+    ('\U00011000', '\U00011046'),
+    ('\U00011066', '\U00011075'),
+    ('\U0001107F', '\U000110BA'),
+    ('\U000110C2', '\U000110C2'),
+    ('\U000110D0', '\U000110E8'),
+    ('\U000110F0', '\U000110F9'),
+    ('\U00011100', '\U00011134'),
+    ('\U00011136', '\U0001113F'),
+    ('\U00011144', '\U00011147'),
+    ('\U00011150', '\U00011173'),
+    ('\U00011176', '\U00011176'),
+    ('\U00011180', '\U000111C4'),
+    ('\U000111C9', '\U000111CC'),
+    ('\U000111CE', '\U000111DA'),
+    ('\U000111DC', '\U000111DC'),
+    ('\U00011200', '\U00011211'),
+    ('\U00011213', '\U00011237'),
+    ('\U0001123E', '\U0001123E'),
+    ('\U00011280', '\U00011286'),
+    ('\U00011288', '\U00011288'),
+    ('\U0001128A', '\U0001128D'),
+    ('\U0001128F', '\U0001129D'),
+    ('\U0001129F', '\U000112A8'),
+    ('\U000112B0', '\U000112EA'),
+    ('\U000112F0', '\U000112F9'),
+    ('\U00011300', '\U00011303'),
+    ('\U00011305', '\U0001130C'),
+    ('\U0001130F', '\U00011310'),
+    ('\U00011313', '\U00011328'),
+    ('\U0001132A', '\U00011330'),
+    ('\U00011332', '\U00011333'),
+    ('\U00011335', '\U00011339'),
+    ('\U0001133B', '\U00011344'),
+    ('\U00011347', '\U00011348'),
+    ('\U0001134B', '\U0001134D'),
+    ('\U00011350', '\U00011350'),
+    ('\U00011357', '\U00011357'),
+    ('\U0001135D', '\U00011363'),
+    ('\U00011366', '\U0001136C'),
+    ('\U00011370', '\U00011374'),
+    ('\U00011400', '\U0001144A'),
+    ('\U00011450', '\U00011459'),
+    ('\U0001145E', '\U00011461'),
+    ('\U00011480', '\U000114C5'),
+    ('\U000114C7', '\U000114C7'),
+    ('\U000114D0', '\U000114D9'),
+    ('\U00011580', '\U000115B5'),
+    ('\U000115B8', '\U000115C0'),
+    ('\U000115D8', '\U000115DD'),
+    ('\U00011600', '\U00011640'),
+    ('\U00011644', '\U00011644'),
+    ('\U00011650', '\U00011659'),
+    ('\U00011680', '\U000116B8'),
+    ('\U000116C0', '\U000116C9'),
+    ('\U00011700', '\U0001171A'),
+    ('\U0001171D', '\U0001172B'),
+    ('\U00011730', '\U00011739'),
+    ('\U00011740', '\U00011746'),
+    ('\U00011800', '\U0001183A'),
+    ('\U000118A0', '\U000118E9'),
+    ('\U000118FF', '\U00011906'),
+    ('\U00011909', '\U00011909'),
+    ('\U0001190C', '\U00011913'),
+    ('\U00011915', '\U00011916'),
+    ('\U00011918', '\U00011935'),
+    ('\U00011937', '\U00011938'),
+    ('\U0001193B', '\U00011943'),
+    ('\U00011950', '\U00011959'),
+    ('\U000119A0', '\U000119A7'),
+    ('\U000119AA', '\U000119D7'),
+    ('\U000119DA', '\U000119E1'),
+    ('\U000119E3', '\U000119E4'),
+    ('\U00011A00', '\U00011A3E'),
+    ('\U00011A47', '\U00011A47'),
+    ('\U00011A50', '\U00011A99'),
+    ('\U00011A9D', '\U00011A9D'),
+    ('\U00011AB0', '\U00011AF8'),
+    ('\U00011C00', '\U00011C08'),
+    ('\U00011C0A', '\U00011C36'),
+    ('\U00011C38', '\U00011C40'),
+    ('\U00011C50', '\U00011C59'),
+    ('\U00011C72', '\U00011C8F'),
+    ('\U00011C92', '\U00011CA7'),
+    ('\U00011CA9', '\U00011CB6'),
+    ('\U00011D00', '\U00011D06'),
+    ('\U00011D08', '\U00011D09'),
+    ('\U00011D0B', '\U00011D36'),
+    ('\U00011D3A', '\U00011D3A'),
+    ('\U00011D3C', '\U00011D3D'),
+    ('\U00011D3F', '\U00011D47'),
+    ('\U00011D50', '\U00011D59'),
+    ('\U00011D60', '\U00011D65'),
+    ('\U00011D67', '\U00011D68'),
+    ('\U00011D6A', '\U00011D8E'),
+    ('\U00011D90', '\U00011D91'),
+    ('\U00011D93', '\U00011D98'),
+    ('\U00011DA0', '\U00011DA9'),
+    ('\U00011EE0', '\U00011EF6'),
+    ('\U00011FB0', '\U00011FB0'),
+    ('\U00012000', '\U00012399'),
+    ('\U00012400', '\U0001246E'),
+    ('\U00012480', '\U00012543'),
+    ('\U00012F90', '\U00012FF0'),
+    ('\U00013000', '\U0001342E'),
+    ('\U00014400', '\U00014646'),
+    ('\U00016800', '\U00016A38'),
+    ('\U00016A40', '\U00016A5E'),
+    ('\U00016A60', '\U00016A69'),
+    ('\U00016A70', '\U00016ABE'),
+    ('\U00016AC0', '\U00016AC9'),
+    ('\U00016AD0', '\U00016AED'),
+    ('\U00016AF0', '\U00016AF4'),
+    ('\U00016B00', '\U00016B36'),
+    ('\U00016B40', '\U00016B43'),
+    ('\U00016B50', '\U00016B59'),
+    ('\U00016B63', '\U00016B77'),
+    ('\U00016B7D', '\U00016B8F'),
+    ('\U00016E40', '\U00016E7F'),
+    ('\U00016F00', '\U00016F4A'),
+    ('\U00016F4F', '\U00016F87'),
+    ('\U00016F8F', '\U00016F9F'),
+    ('\U00016FE0', '\U00016FE1'),
+    ('\U00016FE3', '\U00016FE4'),
+    ('\U00016FF0', '\U00016FF1'),
+    ('\U00017000', '\U000187F7'),
+    ('\U00018800', '\U00018CD5'),
+    ('\U00018D00', '\U00018D08'),
+    ('\U0001AFF0', '\U0001AFF3'),
+    ('\U0001AFF5', '\U0001AFFB'),
+    ('\U0001AFFD', '\U0001AFFE'),
+    ('\U0001B000', '\U0001B122'),
+    ('\U0001B150', '\U0001B152'),
+    ('\U0001B164', '\U0001B167'),
+    ('\U0001B170', '\U0001B2FB'),
+    ('\U0001BC00', '\U0001BC6A'),
+    ('\U0001BC70', '\U0001BC7C'),
+    ('\U0001BC80', '\U0001BC88'),
+    ('\U0001BC90', '\U0001BC99'),
+    ('\U0001BC9D', '\U0001BC9E'),
+    ('\U0001CF00', '\U0001CF2D'),
+    ('\U0001CF30', '\U0001CF46'),
+    ('\U0001D165', '\U0001D169'),
+    ('\U0001D16D', '\U0001D172'),
+    ('\U0001D17B', '\U0001D182'),
+    ('\U0001D185', '\U0001D18B'),
+    ('\U0001D1AA', '\U0001D1AD'),
+    ('\U0001D242', '\U0001D244'),
+    ('\U0001D400', '\U0001D454'),
+    ('\U0001D456', '\U0001D49C'),
+    ('\U0001D49E', '\U0001D49F'),
+    ('\U0001D4A2', '\U0001D4A2'),
+    ('\U0001D4A5', '\U0001D4A6'),
+    ('\U0001D4A9', '\U0001D4AC'),
+    ('\U0001D4AE', '\U0001D4B9'),
+    ('\U0001D4BB', '\U0001D4BB'),
+    ('\U0001D4BD', '\U0001D4C3'),
+    ('\U0001D4C5', '\U0001D505'),
+    ('\U0001D507', '\U0001D50A'),
+    ('\U0001D50D', '\U0001D514'),
+    ('\U0001D516', '\U0001D51C'),
+    ('\U0001D51E', '\U0001D539'),
+    ('\U0001D53B', '\U0001D53E'),
+    ('\U0001D540', '\U0001D544'),
+    ('\U0001D546', '\U0001D546'),
+    ('\U0001D54A', '\U0001D550'),
+    ('\U0001D552', '\U0001D6A5'),
+    ('\U0001D6A8', '\U0001D6C0'),
+    ('\U0001D6C2', '\U0001D6DA'),
+    ('\U0001D6DC', '\U0001D6FA'),
+    ('\U0001D6FC', '\U0001D714'),
+    ('\U0001D716', '\U0001D734'),
+    ('\U0001D736', '\U0001D74E'),
+    ('\U0001D750', '\U0001D76E'),
+    ('\U0001D770', '\U0001D788'),
+    ('\U0001D78A', '\U0001D7A8'),
+    ('\U0001D7AA', '\U0001D7C2'),
+    ('\U0001D7C4', '\U0001D7CB'),
+    ('\U0001D7CE', '\U0001D7FF'),
+    ('\U0001DA00', '\U0001DA36'),
+    ('\U0001DA3B', '\U0001DA6C'),
+    ('\U0001DA75', '\U0001DA75'),
+    ('\U0001DA84', '\U0001DA84'),
+    ('\U0001DA9B', '\U0001DA9F'),
+    ('\U0001DAA1', '\U0001DAAF'),
+    ('\U0001DF00', '\U0001DF1E'),
+    ('\U0001E000', '\U0001E006'),
+    ('\U0001E008', '\U0001E018'),
+    ('\U0001E01B', '\U0001E021'),
+    ('\U0001E023', '\U0001E024'),
+    ('\U0001E026', '\U0001E02A'),
+    ('\U0001E100', '\U0001E12C'),
+    ('\U0001E130', '\U0001E13D'),
+    ('\U0001E140', '\U0001E149'),
+    ('\U0001E14E', '\U0001E14E'),
+    ('\U0001E290', '\U0001E2AE'),
+    ('\U0001E2C0', '\U0001E2F9'),
+    ('\U0001E7E0', '\U0001E7E6'),
+    ('\U0001E7E8', '\U0001E7EB'),
+    ('\U0001E7ED', '\U0001E7EE'),
+    ('\U0001E7F0', '\U0001E7FE'),
+    ('\U0001E800', '\U0001E8C4'),
+    ('\U0001E8D0', '\U0001E8D6'),
+    ('\U0001E900', '\U0001E94B'),
+    ('\U0001E950', '\U0001E959'),
+    ('\U0001EE00', '\U0001EE03'),
+    ('\U0001EE05', '\U0001EE1F'),
+    ('\U0001EE21', '\U0001EE22'),
+    ('\U0001EE24', '\U0001EE24'),
+    ('\U0001EE27', '\U0001EE27'),
+    ('\U0001EE29', '\U0001EE32'),
+    ('\U0001EE34', '\U0001EE37'),
+    ('\U0001EE39', '\U0001EE39'),
+    ('\U0001EE3B', '\U0001EE3B'),
+    ('\U0001EE42', '\U0001EE42'),
+    ('\U0001EE47', '\U0001EE47'),
+    ('\U0001EE49', '\U0001EE49'),
+    ('\U0001EE4B', '\U0001EE4B'),
+    ('\U0001EE4D', '\U0001EE4F'),
+    ('\U0001EE51', '\U0001EE52'),
+    ('\U0001EE54', '\U0001EE54'),
+    ('\U0001EE57', '\U0001EE57'),
+    ('\U0001EE59', '\U0001EE59'),
+    ('\U0001EE5B', '\U0001EE5B'),
+    ('\U0001EE5D', '\U0001EE5D'),
+    ('\U0001EE5F', '\U0001EE5F'),
+    ('\U0001EE61', '\U0001EE62'),
+    ('\U0001EE64', '\U0001EE64'),
+    ('\U0001EE67', '\U0001EE6A'),
+    ('\U0001EE6C', '\U0001EE72'),
+    ('\U0001EE74', '\U0001EE77'),
+    ('\U0001EE79', '\U0001EE7C'),
+    ('\U0001EE7E', '\U0001EE7E'),
+    ('\U0001EE80', '\U0001EE89'),
+    ('\U0001EE8B', '\U0001EE9B'),
+    ('\U0001EEA1', '\U0001EEA3'),
+    ('\U0001EEA5', '\U0001EEA9'),
+    ('\U0001EEAB', '\U0001EEBB'),
+    ('\U0001FBF0', '\U0001FBF9'),
+    ('\U00020000', '\U0002A6DF'),
+    ('\U0002A700', '\U0002B738'),
+    ('\U0002B740', '\U0002B81D'),
+    ('\U0002B820', '\U0002CEA1'),
+    ('\U0002CEB0', '\U0002EBE0'),
+    ('\U0002F800', '\U0002FA1D'),
+    ('\U00030000', '\U0003134A'),
+    ('\U000E0100', '\U000E01EF')
+  ]),
+  ("ideographic", &[
+    ('\u3006', '\u3007'),
+    ('\u3021', '\u3029'),
+    ('\u3038', '\u303A'),
+    ('\u3400', '\u4DBF'),
+    ('\u4E00', '\u9FFF'),
+    ('\uF900', '\uFA6D'),
+    ('\uFA70', '\uFAD9'),
+    ('\U00016FE4', '\U00016FE4'),
+    ('\U00017000', '\U000187F7'),
+    ('\U00018800', '\U00018CD5'),
+    ('\U00018D00', '\U00018D08'),
+    ('\U0001B170', '\U0001B2FB'),
+    ('\U00020000', '\U0002A6DF'),
+    ('\U0002A700', '\U0002B738'),
+    ('\U0002B740', '\U0002B81D'),
+    ('\U0002B820', '\U0002CEA1'),
+    ('\U0002CEB0', '\U0002EBE0'),
+    ('\U0002F800', '\U0002FA1D'),
+    ('\U00030000', '\U0003134A')
+  ]),
+  ("idstart", &[
+    ('\u0041', '\u005A'),
+    ('\u0061', '\u007A'),
+    ('\u00AA', '\u00AA'),
+    ('\u00B5', '\u00B5'),
+    ('\u00BA', '\u00BA'),
+    ('\u00C0', '\u00D6'),
+    ('\u00D8', '\u00F6'),
+    ('\u00F8', '\u02C1'),
+    ('\u02C6', '\u02D1'),
+    ('\u02E0', '\u02E4'),
+    ('\u02EC', '\u02EC'),
+    ('\u02EE', '\u02EE'),
+    ('\u0370', '\u0374'),
+    ('\u0376', '\u0377'),
+    ('\u037A', '\u037D'),
+    ('\u037F', '\u037F'),
+    ('\u0386', '\u0386'),
+    ('\u0388', '\u038A'),
+    ('\u038C', '\u038C'),
+    ('\u038E', '\u03A1'),
+    ('\u03A3', '\u03F5'),
+    ('\u03F7', '\u0481'),
+    ('\u048A', '\u052F'),
+    ('\u0531', '\u0556'),
+    ('\u0559', '\u0559'),
+    ('\u0560', '\u0588'),
+    ('\u05D0', '\u05EA'),
+    ('\u05EF', '\u05F2'),
+    ('\u0620', '\u064A'),
+    ('\u066E', '\u066F'),
+    ('\u0671', '\u06D3'),
+    ('\u06D5', '\u06D5'),
+    ('\u06E5', '\u06E6'),
+    ('\u06EE', '\u06EF'),
+    ('\u06FA', '\u06FC'),
+    ('\u06FF', '\u06FF'),
+    ('\u0710', '\u0710'),
+    ('\u0712', '\u072F'),
+    ('\u074D', '\u07A5'),
+    ('\u07B1', '\u07B1'),
+    ('\u07CA', '\u07EA'),
+    ('\u07F4', '\u07F5'),
+    ('\u07FA', '\u07FA'),
+    ('\u0800', '\u0815'),
+    ('\u081A', '\u081A'),
+    ('\u0824', '\u0824'),
+    ('\u0828', '\u0828'),
+    ('\u0840', '\u0858'),
+    ('\u0860', '\u086A'),
+    ('\u0870', '\u0887'),
+    ('\u0889', '\u088E'),
+    ('\u08A0', '\u08C9'),
+    ('\u0904', '\u0939'),
+    ('\u093D', '\u093D'),
+    ('\u0950', '\u0950'),
+    ('\u0958', '\u0961'),
+    ('\u0971', '\u0980'),
+    ('\u0985', '\u098C'),
+    ('\u098F', '\u0990'),
+    ('\u0993', '\u09A8'),
+    ('\u09AA', '\u09B0'),
+    ('\u09B2', '\u09B2'),
+    ('\u09B6', '\u09B9'),
+    ('\u09BD', '\u09BD'),
+    ('\u09CE', '\u09CE'),
+    ('\u09DC', '\u09DD'),
+    ('\u09DF', '\u09E1'),
+    ('\u09F0', '\u09F1'),
+    ('\u09FC', '\u09FC'),
+    ('\u0A05', '\u0A0A'),
+    ('\u0A0F', '\u0A10'),
+    ('\u0A13', '\u0A28'),
+    ('\u0A2A', '\u0A30'),
+    ('\u0A32', '\u0A33'),
+    ('\u0A35', '\u0A36'),
+    ('\u0A38', '\u0A39'),
+    ('\u0A59', '\u0A5C'),
+    ('\u0A5E', '\u0A5E'),
+    ('\u0A72', '\u0A74'),
+    ('\u0A85', '\u0A8D'),
+    ('\u0A8F', '\u0A91'),
+    ('\u0A93', '\u0AA8'),
+    ('\u0AAA', '\u0AB0'),
+    ('\u0AB2', '\u0AB3'),
+    ('\u0AB5', '\u0AB9'),
+    ('\u0ABD', '\u0ABD'),
+    ('\u0AD0', '\u0AD0'),
+    ('\u0AE0', '\u0AE1'),
+    ('\u0AF9', '\u0AF9'),
+    ('\u0B05', '\u0B0C'),
+    ('\u0B0F', '\u0B10'),
+    ('\u0B13', '\u0B28'),
+    ('\u0B2A', '\u0B30'),
+    ('\u0B32', '\u0B33'),
+    ('\u0B35', '\u0B39'),
+    ('\u0B3D', '\u0B3D'),
+    ('\u0B5C', '\u0B5D'),
+    ('\u0B5F', '\u0B61'),
+    ('\u0B71', '\u0B71'),
+    ('\u0B83', '\u0B83'),
+    ('\u0B85', '\u0B8A'),
+    ('\u0B8E', '\u0B90'),
+    ('\u0B92', '\u0B95'),
+    ('\u0B99', '\u0B9A'),
+    ('\u0B9C', '\u0B9C'),
+    ('\u0B9E', '\u0B9F'),
+    ('\u0BA3', '\u0BA4'),
+    ('\u0BA8', '\u0BAA'),
+    ('\u0BAE', '\u0BB9'),
+    ('\u0BD0', '\u0BD0'),
+    ('\u0C05', '\u0C0C'),
+    ('\u0C0E', '\u0C10'),
+    ('\u0C12', '\u0C28'),
+    ('\u0C2A', '\u0C39'),
+    ('\u0C3D', '\u0C3D'),
+    ('\u0C58', '\u0C5A'),
+    ('\u0C5D', '\u0C5D'),
+    ('\u0C60', '\u0C61'),
+    ('\u0C80', '\u0C80'),
+    ('\u0C85', '\u0C8C'),
+    ('\u0C8E', '\u0C90'),
+    ('\u0C92', '\u0CA8'),
+    ('\u0CAA', '\u0CB3'),
+    ('\u0CB5', '\u0CB9'),
+    ('\u0CBD', '\u0CBD'),
+    ('\u0CDD', '\u0CDE'),
+    ('\u0CE0', '\u0CE1'),
+    ('\u0CF1', '\u0CF2'),
+    ('\u0D04', '\u0D0C'),
+    ('\u0D0E', '\u0D10'),
+    ('\u0D12', '\u0D3A'),
+    ('\u0D3D', '\u0D3D'),
+    ('\u0D4E', '\u0D4E'),
+    ('\u0D54', '\u0D56'),
+    ('\u0D5F', '\u0D61'),
+    ('\u0D7A', '\u0D7F'),
+    ('\u0D85', '\u0D96'),
+    ('\u0D9A', '\u0DB1'),
+    ('\u0DB3', '\u0DBB'),
+    ('\u0DBD', '\u0DBD'),
+    ('\u0DC0', '\u0DC6'),
+    ('\u0E01', '\u0E30'),
+    ('\u0E32', '\u0E33'),
+    ('\u0E40', '\u0E46'),
+    ('\u0E81', '\u0E82'),
+    ('\u0E84', '\u0E84'),
+    ('\u0E86', '\u0E8A'),
+    ('\u0E8C', '\u0EA3'),
+    ('\u0EA5', '\u0EA5'),
+    ('\u0EA7', '\u0EB0'),
+    ('\u0EB2', '\u0EB3'),
+    ('\u0EBD', '\u0EBD'),
+    ('\u0EC0', '\u0EC4'),
+    ('\u0EC6', '\u0EC6'),
+    ('\u0EDC', '\u0EDF'),
+    ('\u0F00', '\u0F00'),
+    ('\u0F40', '\u0F47'),
+    ('\u0F49', '\u0F6C'),
+    ('\u0F88', '\u0F8C'),
+    ('\u1000', '\u102A'),
+    ('\u103F', '\u103F'),
+    ('\u1050', '\u1055'),
+    ('\u105A', '\u105D'),
+    ('\u1061', '\u1061'),
+    ('\u1065', '\u1066'),
+    ('\u106E', '\u1070'),
+    ('\u1075', '\u1081'),
+    ('\u108E', '\u108E'),
+    ('\u10A0', '\u10C5'),
+    ('\u10C7', '\u10C7'),
+    ('\u10CD', '\u10CD'),
+    ('\u10D0', '\u10FA'),
+    ('\u10FC', '\u1248'),
+    ('\u124A', '\u124D'),
+    ('\u1250', '\u1256'),
+    ('\u1258', '\u1258'),
+    ('\u125A', '\u125D'),
+    ('\u1260', '\u1288'),
+    ('\u128A', '\u128D'),
+    ('\u1290', '\u12B0'),
+    ('\u12B2', '\u12B5'),
+    ('\u12B8', '\u12BE'),
+    ('\u12C0', '\u12C0'),
+    ('\u12C2', '\u12C5'),
+    ('\u12C8', '\u12D6'),
+    ('\u12D8', '\u1310'),
+    ('\u1312', '\u1315'),
+    ('\u1318', '\u135A'),
+    ('\u1380', '\u138F'),
+    ('\u13A0', '\u13F5'),
+    ('\u13F8', '\u13FD'),
+    ('\u1401', '\u166C'),
+    ('\u166F', '\u167F'),
+    ('\u1681', '\u169A'),
+    ('\u16A0', '\u16EA'),
+    ('\u16EE', '\u16F8'),
+    ('\u1700', '\u1711'),
+    ('\u171F', '\u1731'),
+    ('\u1740', '\u1751'),
+    ('\u1760', '\u176C'),
+    ('\u176E', '\u1770'),
+    ('\u1780', '\u17B3'),
+    ('\u17D7', '\u17D7'),
+    ('\u17DC', '\u17DC'),
+    ('\u1820', '\u1878'),
+    ('\u1880', '\u18A8'),
+    ('\u18AA', '\u18AA'),
+    ('\u18B0', '\u18F5'),
+    ('\u1900', '\u191E'),
+    ('\u1950', '\u196D'),
+    ('\u1970', '\u1974'),
+    ('\u1980', '\u19AB'),
+    ('\u19B0', '\u19C9'),
+    ('\u1A00', '\u1A16'),
+    ('\u1A20', '\u1A54'),
+    ('\u1AA7', '\u1AA7'),
+    ('\u1B05', '\u1B33'),
+    ('\u1B45', '\u1B4C'),
+    ('\u1B83', '\u1BA0'),
+    ('\u1BAE', '\u1BAF'),
+    ('\u1BBA', '\u1BE5'),
+    ('\u1C00', '\u1C23'),
+    ('\u1C4D', '\u1C4F'),
+    ('\u1C5A', '\u1C7D'),
+    ('\u1C80', '\u1C88'),
+    ('\u1C90', '\u1CBA'),
+    ('\u1CBD', '\u1CBF'),
+    ('\u1CE9', '\u1CEC'),
+    ('\u1CEE', '\u1CF3'),
+    ('\u1CF5', '\u1CF6'),
+    ('\u1CFA', '\u1CFA'),
+    ('\u1D00', '\u1DBF'),
+    ('\u1E00', '\u1F15'),
+    ('\u1F18', '\u1F1D'),
+    ('\u1F20', '\u1F45'),
+    ('\u1F48', '\u1F4D'),
+    ('\u1F50', '\u1F57'),
+    ('\u1F59', '\u1F59'),
+    ('\u1F5B', '\u1F5B'),
+    ('\u1F5D', '\u1F5D'),
+    ('\u1F5F', '\u1F7D'),
+    ('\u1F80', '\u1FB4'),
+    ('\u1FB6', '\u1FBC'),
+    ('\u1FBE', '\u1FBE'),
+    ('\u1FC2', '\u1FC4'),
+    ('\u1FC6', '\u1FCC'),
+    ('\u1FD0', '\u1FD3'),
+    ('\u1FD6', '\u1FDB'),
+    ('\u1FE0', '\u1FEC'),
+    ('\u1FF2', '\u1FF4'),
+    ('\u1FF6', '\u1FFC'),
+    ('\u2071', '\u2071'),
+    ('\u207F', '\u207F'),
+    ('\u2090', '\u209C'),
+    ('\u2102', '\u2102'),
+    ('\u2107', '\u2107'),
+    ('\u210A', '\u2113'),
+    ('\u2115', '\u2115'),
+    ('\u2118', '\u211D'),
+    ('\u2124', '\u2124'),
+    ('\u2126', '\u2126'),
+    ('\u2128', '\u2128'),
+    ('\u212A', '\u2139'),
+    ('\u213C', '\u213F'),
+    ('\u2145', '\u2149'),
+    ('\u214E', '\u214E'),
+    ('\u2160', '\u2188'),
+    ('\u2C00', '\u2CE4'),
+    ('\u2CEB', '\u2CEE'),
+    ('\u2CF2', '\u2CF3'),
+    ('\u2D00', '\u2D25'),
+    ('\u2D27', '\u2D27'),
+    ('\u2D2D', '\u2D2D'),
+    ('\u2D30', '\u2D67'),
+    ('\u2D6F', '\u2D6F'),
+    ('\u2D80', '\u2D96'),
+    ('\u2DA0', '\u2DA6'),
+    ('\u2DA8', '\u2DAE'),
+    ('\u2DB0', '\u2DB6'),
+    ('\u2DB8', '\u2DBE'),
+    ('\u2DC0', '\u2DC6'),
+    ('\u2DC8', '\u2DCE'),
+    ('\u2DD0', '\u2DD6'),
+    ('\u2DD8', '\u2DDE'),
+    ('\u3005', '\u3007'),
+    ('\u3021', '\u3029'),
+    ('\u3031', '\u3035'),
+    ('\u3038', '\u303C'),
+    ('\u3041', '\u3096'),
+    ('\u309B', '\u309F'),
+    ('\u30A1', '\u30FA'),
+    ('\u30FC', '\u30FF'),
+    ('\u3105', '\u312F'),
+    ('\u3131', '\u318E'),
+    ('\u31A0', '\u31BF'),
+    ('\u31F0', '\u31FF'),
+    ('\u3400', '\u4DBF'),
+    ('\u4E00', '\uA48C'),
+    ('\uA4D0', '\uA4FD'),
+    ('\uA500', '\uA60C'),
+    ('\uA610', '\uA61F'),
+    ('\uA62A', '\uA62B'),
+    ('\uA640', '\uA66E'),
+    ('\uA67F', '\uA69D'),
+    ('\uA6A0', '\uA6EF'),
+    ('\uA717', '\uA71F'),
+    ('\uA722', '\uA788'),
+    ('\uA78B', '\uA7CA'),
+    ('\uA7D0', '\uA7D1'),
+    ('\uA7D3', '\uA7D3'),
+    ('\uA7D5', '\uA7D9'),
+    ('\uA7F2', '\uA801'),
+    ('\uA803', '\uA805'),
+    ('\uA807', '\uA80A'),
+    ('\uA80C', '\uA822'),
+    ('\uA840', '\uA873'),
+    ('\uA882', '\uA8B3'),
+    ('\uA8F2', '\uA8F7'),
+    ('\uA8FB', '\uA8FB'),
+    ('\uA8FD', '\uA8FE'),
+    ('\uA90A', '\uA925'),
+    ('\uA930', '\uA946'),
+    ('\uA960', '\uA97C'),
+    ('\uA984', '\uA9B2'),
+    ('\uA9CF', '\uA9CF'),
+    ('\uA9E0', '\uA9E4'),
+    ('\uA9E6', '\uA9EF'),
+    ('\uA9FA', '\uA9FE'),
+    ('\uAA00', '\uAA28'),
+    ('\uAA40', '\uAA42'),
+    ('\uAA44', '\uAA4B'),
+    ('\uAA60', '\uAA76'),
+    ('\uAA7A', '\uAA7A'),
+    ('\uAA7E', '\uAAAF'),
+    ('\uAAB1', '\uAAB1'),
+    ('\uAAB5', '\uAAB6'),
+    ('\uAAB9', '\uAABD'),
+    ('\uAAC0', '\uAAC0'),
+    ('\uAAC2', '\uAAC2'),
+    ('\uAADB', '\uAADD'),
+    ('\uAAE0', '\uAAEA'),
+    ('\uAAF2', '\uAAF4'),
+    ('\uAB01', '\uAB06'),
+    ('\uAB09', '\uAB0E'),
+    ('\uAB11', '\uAB16'),
+    ('\uAB20', '\uAB26'),
+    ('\uAB28', '\uAB2E'),
+    ('\uAB30', '\uAB5A'),
+    ('\uAB5C', '\uAB69'),
+    ('\uAB70', '\uABE2'),
+    ('\uAC00', '\uD7A3'),
+    ('\uD7B0', '\uD7C6'),
+    ('\uD7CB', '\uD7FB'),
+    ('\uF900', '\uFA6D'),
+    ('\uFA70', '\uFAD9'),
+    ('\uFB00', '\uFB06'),
+    ('\uFB13', '\uFB17'),
+    ('\uFB1D', '\uFB1D'),
+    ('\uFB1F', '\uFB28'),
+    ('\uFB2A', '\uFB36'),
+    ('\uFB38', '\uFB3C'),
+    ('\uFB3E', '\uFB3E'),
+    ('\uFB40', '\uFB41'),
+    ('\uFB43', '\uFB44'),
+    ('\uFB46', '\uFBB1'),
+    ('\uFBD3', '\uFD3D'),
+    ('\uFD50', '\uFD8F'),
+    ('\uFD92', '\uFDC7'),
+    ('\uFDF0', '\uFDFB'),
+    ('\uFE70', '\uFE74'),
+    ('\uFE76', '\uFEFC'),
+    ('\uFF21', '\uFF3A'),
+    ('\uFF41', '\uFF5A'),
+    ('\uFF66', '\uFFBE'),
+    ('\uFFC2', '\uFFC7'),
+    ('\uFFCA', '\uFFCF'),
+    ('\uFFD2', '\uFFD7'),
+    ('\uFFDA', '\uFFDC'),
+    ('\U00010000', '\U0001000B'),
+    ('\U0001000D', '\U00010026'),
+    ('\U00010028', '\U0001003A'),
+    ('\U0001003C', '\U0001003D'),
+    ('\U0001003F', '\U0001004D'),
+    ('\U00010050', '\U0001005D'),
+    ('\U00010080', '\U000100FA'),
+    ('\U00010140', '\U00010174'),
+    ('\U00010280', '\U0001029C'),
+    ('\U000102A0', '\U000102D0'),
+    ('\U00010300', '\U0001031F'),
+    ('\U0001032D', '\U0001034A'),
+    ('\U00010350', '\U00010375'),
+    ('\U00010380', '\U0001039D'),
+    ('\U000103A0', '\U000103C3'),
+    ('\U000103C8', '\U000103CF'),
+    ('\U000103D1', '\U000103D5'),
+    ('\U00010400', '\U0001049D'),
+    ('\U000104B0', '\U000104D3'),
+    ('\U000104D8', '\U000104FB'),
+    ('\U00010500', '\U00010527'),
+    ('\U00010530', '\U00010563'),
+    ('\U00010570', '\U0001057A'),
+    ('\U0001057C', '\U0001058A'),
+    ('\U0001058C', '\U00010592'),
+    ('\U00010594', '\U00010595'),
+    ('\U00010597', '\U000105A1'),
+    ('\U000105A3', '\U000105B1'),
+    ('\U000105B3', '\U000105B9'),
+    ('\U000105BB', '\U000105BC'),
+    ('\U00010600', '\U00010736'),
+    ('\U00010740', '\U00010755'),
+    ('\U00010760', '\U00010767'),
+    ('\U00010780', '\U00010785'),
+    ('\U00010787', '\U000107B0'),
+    ('\U000107B2', '\U000107BA'),
+    ('\U00010800', '\U00010805'),
+    ('\U00010808', '\U00010808'),
+    ('\U0001080A', '\U00010835'),
+    ('\U00010837', '\U00010838'),
+    ('\U0001083C', '\U0001083C'),
+    ('\U0001083F', '\U00010855'),
+    ('\U00010860', '\U00010876'),
+    ('\U00010880', '\U0001089E'),
+    ('\U000108E0', '\U000108F2'),
+    ('\U000108F4', '\U000108F5'),
+    ('\U00010900', '\U00010915'),
+    ('\U00010920', '\U00010939'),
+    ('\U00010980', '\U000109B7'),
+    ('\U000109BE', '\U000109BF'),
+    ('\U00010A00', '\U00010A00'),
+    ('\U00010A10', '\U00010A13'),
+    ('\U00010A15', '\U00010A17'),
+    ('\U00010A19', '\U00010A35'),
+    ('\U00010A60', '\U00010A7C'),
+    ('\U00010A80', '\U00010A9C'),
+    ('\U00010AC0', '\U00010AC7'),
+    ('\U00010AC9', '\U00010AE4'),
+    ('\U00010B00', '\U00010B35'),
+    ('\U00010B40', '\U00010B55'),
+    ('\U00010B60', '\U00010B72'),
+    ('\U00010B80', '\U00010B91'),
+    ('\U00010C00', '\U00010C48'),
+    ('\U00010C80', '\U00010CB2'),
+    ('\U00010CC0', '\U00010CF2'),
+    ('\U00010D00', '\U00010D23'),
+    ('\U00010E80', '\U00010EA9'),
+    ('\U00010EB0', '\U00010EB1'),
+    ('\U00010F00', '\U00010F1C'),
+    ('\U00010F27', '\U00010F27'),
+    ('\U00010F30', '\U00010F45'),
+    ('\U00010F70', '\U00010F81'),
+    ('\U00010FB0', '\U00010FC4'),
+    ('\U00010FE0', '\U00010FF6'),
+    ('\U00011003', '\U00011037'),
+    ('\U00011071', '\U00011072'),
+    ('\U00011075', '\U00011075'),
+    ('\U00011083', '\U000110AF'),
+    ('\U000110D0', '\U000110E8'),
+    ('\U00011103', '\U00011126'),
+    ('\U00011144', '\U00011144'),
+    ('\U00011147', '\U00011147'),
+    ('\U00011150', '\U00011172'),
+    ('\U00011176', '\U00011176'),
+    ('\U00011183', '\U000111B2'),
+    ('\U000111C1', '\U000111C4'),
+    ('\U000111DA', '\U000111DA'),
+    ('\U000111DC', '\U000111DC'),
+    ('\U00011200', '\U00011211'),
+    ('\U00011213', '\U0001122B'),
+    ('\U00011280', '\U00011286'),
+    ('\U00011288', '\U00011288'),
+    ('\U0001128A', '\U0001128D'),
+    ('\U0001128F', '\U0001129D'),
+    ('\U0001129F', '\U000112A8'),
+    ('\U000112B0', '\U000112DE'),
+    ('\U00011305', '\U0001130C'),
+    ('\U0001130F', '\U00011310'),
+    ('\U00011313', '\U00011328'),
+    ('\U0001132A', '\U00011330'),
+    ('\U00011332', '\U00011333'),
+    ('\U00011335', '\U00011339'),
+    ('\U0001133D', '\U0001133D'),
+    ('\U00011350', '\U00011350'),
+    ('\U0001135D', '\U00011361'),
+    ('\U00011400', '\U00011434'),
+    ('\U00011447', '\U0001144A'),
+    ('\U0001145F', '\U00011461'),
+    ('\U00011480', '\U000114AF'),
+    ('\U000114C4', '\U000114C5'),
+    ('\U000114C7', '\U000114C7'),
+    ('\U00011580', '\U000115AE'),
+    ('\U000115D8', '\U000115DB'),
+    ('\U00011600', '\U0001162F'),
+    ('\U00011644', '\U00011644'),
+    ('\U00011680', '\U000116AA'),
+    ('\U000116B8', '\U000116B8'),
+    ('\U00011700', '\U0001171A'),
+    ('\U00011740', '\U00011746'),
+    ('\U00011800', '\U0001182B'),
+    ('\U000118A0', '\U000118DF'),
+    ('\U000118FF', '\U00011906'),
+    ('\U00011909', '\U00011909'),
+    ('\U0001190C', '\U00011913'),
+    ('\U00011915', '\U00011916'),
+    ('\U00011918', '\U0001192F'),
+    ('\U0001193F', '\U0001193F'),
+    ('\U00011941', '\U00011941'),
+    ('\U000119A0', '\U000119A7'),
+    ('\U000119AA', '\U000119D0'),
+    ('\U000119E1', '\U000119E1'),
+    ('\U000119E3', '\U000119E3'),
+    ('\U00011A00', '\U00011A00'),
+    ('\U00011A0B', '\U00011A32'),
+    ('\U00011A3A', '\U00011A3A'),
+    ('\U00011A50', '\U00011A50'),
+    ('\U00011A5C', '\U00011A89'),
+    ('\U00011A9D', '\U00011A9D'),
+    ('\U00011AB0', '\U00011AF8'),
+    ('\U00011C00', '\U00011C08'),
+    ('\U00011C0A', '\U00011C2E'),
+    ('\U00011C40', '\U00011C40'),
+    ('\U00011C72', '\U00011C8F'),
+    ('\U00011D00', '\U00011D06'),
+    ('\U00011D08', '\U00011D09'),
+    ('\U00011D0B', '\U00011D30'),
+    ('\U00011D46', '\U00011D46'),
+    ('\U00011D60', '\U00011D65'),
+    ('\U00011D67', '\U00011D68'),
+    ('\U00011D6A', '\U00011D89'),
+    ('\U00011D98', '\U00011D98'),
+    ('\U00011EE0', '\U00011EF2'),
+    ('\U00011FB0', '\U00011FB0'),
+    ('\U00012000', '\U00012399'),
+    ('\U00012400', '\U0001246E'),
+    ('\U00012480', '\U00012543'),
+    ('\U00012F90', '\U00012FF0'),
+    ('\U00013000', '\U0001342E'),
+    ('\U00014400', '\U00014646'),
+    ('\U00016800', '\U00016A38'),
+    ('\U00016A40', '\U00016A5E'),
+    ('\U00016A70', '\U00016ABE'),
+    ('\U00016AD0', '\U00016AED'),
+    ('\U00016B00', '\U00016B2F'),
+    ('\U00016B40', '\U00016B43'),
+    ('\U00016B63', '\U00016B77'),
+    ('\U00016B7D', '\U00016B8F'),
+    ('\U00016E40', '\U00016E7F'),
+    ('\U00016F00', '\U00016F4A'),
+    ('\U00016F50', '\U00016F50'),
+    ('\U00016F93', '\U00016F9F'),
+    ('\U00016FE0', '\U00016FE1'),
+    ('\U00016FE3', '\U00016FE3'),
+    ('\U00017000', '\U000187F7'),
+    ('\U00018800', '\U00018CD5'),
+    ('\U00018D00', '\U00018D08'),
+    ('\U0001AFF0', '\U0001AFF3'),
+    ('\U0001AFF5', '\U0001AFFB'),
+    ('\U0001AFFD', '\U0001AFFE'),
+    ('\U0001B000', '\U0001B122'),
+    ('\U0001B150', '\U0001B152'),
+    ('\U0001B164', '\U0001B167'),
+    ('\U0001B170', '\U0001B2FB'),
+    ('\U0001BC00', '\U0001BC6A'),
+    ('\U0001BC70', '\U0001BC7C'),
+    ('\U0001BC80', '\U0001BC88'),
+    ('\U0001BC90', '\U0001BC99'),
+    ('\U0001D400', '\U0001D454'),
+    ('\U0001D456', '\U0001D49C'),
+    ('\U0001D49E', '\U0001D49F'),
+    ('\U0001D4A2', '\U0001D4A2'),
+    ('\U0001D4A5', '\U0001D4A6'),
+    ('\U0001D4A9', '\U0001D4AC'),
+    ('\U0001D4AE', '\U0001D4B9'),
+    ('\U0001D4BB', '\U0001D4BB'),
+    ('\U0001D4BD', '\U0001D4C3'),
+    ('\U0001D4C5', '\U0001D505'),
+    ('\U0001D507', '\U0001D50A'),
+    ('\U0001D50D', '\U0001D514'),
+    ('\U0001D516', '\U0001D51C'),
+    ('\U0001D51E', '\U0001D539'),
+    ('\U0001D53B', '\U0001D53E'),
+    ('\U0001D540', '\U0001D544'),
+    ('\U0001D546', '\U0001D546'),
+    ('\U0001D54A', '\U0001D550'),
+    ('\U0001D552', '\U0001D6A5'),
+    ('\U0001D6A8', '\U0001D6C0'),
+    ('\U0001D6C2', '\U0001D6DA'),
+    ('\U0001D6DC', '\U0001D6FA'),
+    ('\U0001D6FC', '\U0001D714'),
+    ('\U0001D716', '\U0001D734'),
+    ('\U0001D736', '\U0001D74E'),
+    ('\U0001D750', '\U0001D76E'),
+    ('\U0001D770', '\U0001D788'),
+    ('\U0001D78A', '\U0001D7A8'),
+    ('\U0001D7AA', '\U0001D7C2'),
+    ('\U0001D7C4', '\U0001D7CB'),
+    ('\U0001DF00', '\U0001DF1E'),
+    ('\U0001E100', '\U0001E12C'),
+    ('\U0001E137', '\U0001E13D'),
+    ('\U0001E14E', '\U0001E14E'),
+    ('\U0001E290', '\U0001E2AD'),
+    ('\U0001E2C0', '\U0001E2EB'),
+    ('\U0001E7E0', '\U0001E7E6'),
+    ('\U0001E7E8', '\U0001E7EB'),
+    ('\U0001E7ED', '\U0001E7EE'),
+    ('\U0001E7F0', '\U0001E7FE'),
+    ('\U0001E800', '\U0001E8C4'),
+    ('\U0001E900', '\U0001E943'),
+    ('\U0001E94B', '\U0001E94B'),
+    ('\U0001EE00', '\U0001EE03'),
+    ('\U0001EE05', '\U0001EE1F'),
+    ('\U0001EE21', '\U0001EE22'),
+    ('\U0001EE24', '\U0001EE24'),
+    ('\U0001EE27', '\U0001EE27'),
+    ('\U0001EE29', '\U0001EE32'),
+    ('\U0001EE34', '\U0001EE37'),
+    ('\U0001EE39', '\U0001EE39'),
+    ('\U0001EE3B', '\U0001EE3B'),
+    ('\U0001EE42', '\U0001EE42'),
+    ('\U0001EE47', '\U0001EE47'),
+    ('\U0001EE49', '\U0001EE49'),
+    ('\U0001EE4B', '\U0001EE4B'),
+    ('\U0001EE4D', '\U0001EE4F'),
+    ('\U0001EE51', '\U0001EE52'),
+    ('\U0001EE54', '\U0001EE54'),
+    ('\U0001EE57', '\U0001EE57'),
+    ('\U0001EE59', '\U0001EE59'),
+    ('\U0001EE5B', '\U0001EE5B'),
+    ('\U0001EE5D', '\U0001EE5D'),
+    ('\U0001EE5F', '\U0001EE5F'),
+    ('\U0001EE61', '\U0001EE62'),
+    ('\U0001EE64', '\U0001EE64'),
+    ('\U0001EE67', '\U0001EE6A'),
+    ('\U0001EE6C', '\U0001EE72'),
+    ('\U0001EE74', '\U0001EE77'),
+    ('\U0001EE79', '\U0001EE7C'),
+    ('\U0001EE7E', '\U0001EE7E'),
+    ('\U0001EE80', '\U0001EE89'),
+    ('\U0001EE8B', '\U0001EE9B'),
+    ('\U0001EEA1', '\U0001EEA3'),
+    ('\U0001EEA5', '\U0001EEA9'),
+    ('\U0001EEAB', '\U0001EEBB'),
+    ('\U00020000', '\U0002A6DF'),
+    ('\U0002A700', '\U0002B738'),
+    ('\U0002B740', '\U0002B81D'),
+    ('\U0002B820', '\U0002CEA1'),
+    ('\U0002CEB0', '\U0002EBE0'),
+    ('\U0002F800', '\U0002FA1D'),
+    ('\U00030000', '\U0003134A')
+  ]),
+  ("joincontrol", &[
+    ('\u200C', '\u200D')
+  ]),
+  ("lowercase", &[
+    ('\u0061', '\u007A'),
+    ('\u00AA', '\u00AA'),
+    ('\u00B5', '\u00B5'),
+    ('\u00BA', '\u00BA'),
+    ('\u00DF', '\u00F6'),
+    ('\u00F8', '\u00FF'),
+    ('\u0101', '\u0101'),
+    ('\u0103', '\u0103'),
+    ('\u0105', '\u0105'),
+    ('\u0107', '\u0107'),
+    ('\u0109', '\u0109'),
+    ('\u010B', '\u010B'),
+    ('\u010D', '\u010D'),
+    ('\u010F', '\u010F'),
+    ('\u0111', '\u0111'),
+    ('\u0113', '\u0113'),
+    ('\u0115', '\u0115'),
+    ('\u0117', '\u0117'),
+    ('\u0119', '\u0119'),
+    ('\u011B', '\u011B'),
+    ('\u011D', '\u011D'),
+    ('\u011F', '\u011F'),
+    ('\u0121', '\u0121'),
+    ('\u0123', '\u0123'),
+    ('\u0125', '\u0125'),
+    ('\u0127', '\u0127'),
+    ('\u0129', '\u0129'),
+    ('\u012B', '\u012B'),
+    ('\u012D', '\u012D'),
+    ('\u012F', '\u012F'),
+    ('\u0131', '\u0131'),
+    ('\u0133', '\u0133'),
+    ('\u0135', '\u0135'),
+    ('\u0137', '\u0138'),
+    ('\u013A', '\u013A'),
+    ('\u013C', '\u013C'),
+    ('\u013E', '\u013E'),
+    ('\u0140', '\u0140'),
+    ('\u0142', '\u0142'),
+    ('\u0144', '\u0144'),
+    ('\u0146', '\u0146'),
+    ('\u0148', '\u0149'),
+    ('\u014B', '\u014B'),
+    ('\u014D', '\u014D'),
+    ('\u014F', '\u014F'),
+    ('\u0151', '\u0151'),
+    ('\u0153', '\u0153'),
+    ('\u0155', '\u0155'),
+    ('\u0157', '\u0157'),
+    ('\u0159', '\u0159'),
+    ('\u015B', '\u015B'),
+    ('\u015D', '\u015D'),
+    ('\u015F', '\u015F'),
+    ('\u0161', '\u0161'),
+    ('\u0163', '\u0163'),
+    ('\u0165', '\u0165'),
+    ('\u0167', '\u0167'),
+    ('\u0169', '\u0169'),
+    ('\u016B', '\u016B'),
+    ('\u016D', '\u016D'),
+    ('\u016F', '\u016F'),
+    ('\u0171', '\u0171'),
+    ('\u0173', '\u0173'),
+    ('\u0175', '\u0175'),
+    ('\u0177', '\u0177'),
+    ('\u017A', '\u017A'),
+    ('\u017C', '\u017C'),
+    ('\u017E', '\u0180'),
+    ('\u0183', '\u0183'),
+    ('\u0185', '\u0185'),
+    ('\u0188', '\u0188'),
+    ('\u018C', '\u018D'),
+    ('\u0192', '\u0192'),
+    ('\u0195', '\u0195'),
+    ('\u0199', '\u019B'),
+    ('\u019E', '\u019E'),
+    ('\u01A1', '\u01A1'),
+    ('\u01A3', '\u01A3'),
+    ('\u01A5', '\u01A5'),
+    ('\u01A8', '\u01A8'),
+    ('\u01AA', '\u01AB'),
+    ('\u01AD', '\u01AD'),
+    ('\u01B0', '\u01B0'),
+    ('\u01B4', '\u01B4'),
+    ('\u01B6', '\u01B6'),
+    ('\u01B9', '\u01BA'),
+    ('\u01BD', '\u01BF'),
+    ('\u01C6', '\u01C6'),
+    ('\u01C9', '\u01C9'),
+    ('\u01CC', '\u01CC'),
+    ('\u01CE', '\u01CE'),
+    ('\u01D0', '\u01D0'),
+    ('\u01D2', '\u01D2'),
+    ('\u01D4', '\u01D4'),
+    ('\u01D6', '\u01D6'),
+    ('\u01D8', '\u01D8'),
+    ('\u01DA', '\u01DA'),
+    ('\u01DC', '\u01DD'),
+    ('\u01DF', '\u01DF'),
+    ('\u01E1', '\u01E1'),
+    ('\u01E3', '\u01E3'),
+    ('\u01E5', '\u01E5'),
+    ('\u01E7', '\u01E7'),
+    ('\u01E9', '\u01E9'),
+    ('\u01EB', '\u01EB'),
+    ('\u01ED', '\u01ED'),
+    ('\u01EF', '\u01F0'),
+    ('\u01F3', '\u01F3'),
+    ('\u01F5', '\u01F5'),
+    ('\u01F9', '\u01F9'),
+    ('\u01FB', '\u01FB'),
+    ('\u01FD', '\u01FD'),
+    ('\u01FF', '\u01FF'),
+    ('\u0201', '\u0201'),
+    ('\u0203', '\u0203'),
+    ('\u0205', '\u0205'),
+    ('\u0207', '\u0207'),
+    ('\u0209', '\u0209'),
+    ('\u020B', '\u020B'),
+    ('\u020D', '\u020D'),
+    ('\u020F', '\u020F'),
+    ('\u0211', '\u0211'),
+    ('\u0213', '\u0213'),
+    ('\u0215', '\u0215'),
+    ('\u0217', '\u0217'),
+    ('\u0219', '\u0219'),
+    ('\u021B', '\u021B'),
+    ('\u021D', '\u021D'),
+    ('\u021F', '\u021F'),
+    ('\u0221', '\u0221'),
+    ('\u0223', '\u0223'),
+    ('\u0225', '\u0225'),
+    ('\u0227', '\u0227'),
+    ('\u0229', '\u0229'),
+    ('\u022B', '\u022B'),
+    ('\u022D', '\u022D'),
+    ('\u022F', '\u022F'),
+    ('\u0231', '\u0231'),
+    ('\u0233', '\u0239'),
+    ('\u023C', '\u023C'),
+    ('\u023F', '\u0240'),
+    ('\u0242', '\u0242'),
+    ('\u0247', '\u0247'),
+    ('\u0249', '\u0249'),
+    ('\u024B', '\u024B'),
+    ('\u024D', '\u024D'),
+    ('\u024F', '\u0293'),
+    ('\u0295', '\u02B8'),
+    ('\u02C0', '\u02C1'),
+    ('\u02E0', '\u02E4'),
+    ('\u0345', '\u0345'),
+    ('\u0371', '\u0371'),
+    ('\u0373', '\u0373'),
+    ('\u0377', '\u0377'),
+    ('\u037A', '\u037D'),
+    ('\u0390', '\u0390'),
+    ('\u03AC', '\u03CE'),
+    ('\u03D0', '\u03D1'),
+    ('\u03D5', '\u03D7'),
+    ('\u03D9', '\u03D9'),
+    ('\u03DB', '\u03DB'),
+    ('\u03DD', '\u03DD'),
+    ('\u03DF', '\u03DF'),
+    ('\u03E1', '\u03E1'),
+    ('\u03E3', '\u03E3'),
+    ('\u03E5', '\u03E5'),
+    ('\u03E7', '\u03E7'),
+    ('\u03E9', '\u03E9'),
+    ('\u03EB', '\u03EB'),
+    ('\u03ED', '\u03ED'),
+    ('\u03EF', '\u03F3'),
+    ('\u03F5', '\u03F5'),
+    ('\u03F8', '\u03F8'),
+    ('\u03FB', '\u03FC'),
+    ('\u0430', '\u045F'),
+    ('\u0461', '\u0461'),
+    ('\u0463', '\u0463'),
+    ('\u0465', '\u0465'),
+    ('\u0467', '\u0467'),
+    ('\u0469', '\u0469'),
+    ('\u046B', '\u046B'),
+    ('\u046D', '\u046D'),
+    ('\u046F', '\u046F'),
+    ('\u0471', '\u0471'),
+    ('\u0473', '\u0473'),
+    ('\u0475', '\u0475'),
+    ('\u0477', '\u0477'),
+    ('\u0479', '\u0479'),
+    ('\u047B', '\u047B'),
+    ('\u047D', '\u047D'),
+    ('\u047F', '\u047F'),
+    ('\u0481', '\u0481'),
+    ('\u048B', '\u048B'),
+    ('\u048D', '\u048D'),
+    ('\u048F', '\u048F'),
+    ('\u0491', '\u0491'),
+    ('\u0493', '\u0493'),
+    ('\u0495', '\u0495'),
+    ('\u0497', '\u0497'),
+    ('\u0499', '\u0499'),
+    ('\u049B', '\u049B'),
+    ('\u049D', '\u049D'),
+    ('\u049F', '\u049F'),
+    ('\u04A1', '\u04A1'),
+    ('\u04A3', '\u04A3'),
+    ('\u04A5', '\u04A5'),
+    ('\u04A7', '\u04A7'),
+    ('\u04A9', '\u04A9'),
+    ('\u04AB', '\u04AB'),
+    ('\u04AD', '\u04AD'),
+    ('\u04AF', '\u04AF'),
+    ('\u04B1', '\u04B1'),
+    ('\u04B3', '\u04B3'),
+    ('\u04B5', '\u04B5'),
+    ('\u04B7', '\u04B7'),
+    ('\u04B9', '\u04B9'),
+    ('\u04BB', '\u04BB'),
+    ('\u04BD', '\u04BD'),
+    ('\u04BF', '\u04BF'),
+    ('\u04C2', '\u04C2'),
+    ('\u04C4', '\u04C4'),
+    ('\u04C6', '\u04C6'),
+    ('\u04C8', '\u04C8'),
+    ('\u04CA', '\u04CA'),
+    ('\u04CC', '\u04CC'),
+    ('\u04CE', '\u04CF'),
+    ('\u04D1', '\u04D1'),
+    ('\u04D3', '\u04D3'),
+    ('\u04D5', '\u04D5'),
+    ('\u04D7', '\u04D7'),
+    ('\u04D9', '\u04D9'),
+    ('\u04DB', '\u04DB'),
+    ('\u04DD', '\u04DD'),
+    ('\u04DF', '\u04DF'),
+    ('\u04E1', '\u04E1'),
+    ('\u04E3', '\u04E3'),
+    ('\u04E5', '\u04E5'),
+    ('\u04E7', '\u04E7'),
+    ('\u04E9', '\u04E9'),
+    ('\u04EB', '\u04EB'),
+    ('\u04ED', '\u04ED'),
+    ('\u04EF', '\u04EF'),
+    ('\u04F1', '\u04F1'),
+    ('\u04F3', '\u04F3'),
+    ('\u04F5', '\u04F5'),
+    ('\u04F7', '\u04F7'),
+    ('\u04F9', '\u04F9'),
+    ('\u04FB', '\u04FB'),
+    ('\u04FD', '\u04FD'),
+    ('\u04FF', '\u04FF'),
+    ('\u0501', '\u0501'),
+    ('\u0503', '\u0503'),
+    ('\u0505', '\u0505'),
+    ('\u0507', '\u0507'),
+    ('\u0509', '\u0509'),
+    ('\u050B', '\u050B'),
+    ('\u050D', '\u050D'),
+    ('\u050F', '\u050F'),
+    ('\u0511', '\u0511'),
+    ('\u0513', '\u0513'),
+    ('\u0515', '\u0515'),
+    ('\u0517', '\u0517'),
+    ('\u0519', '\u0519'),
+    ('\u051B', '\u051B'),
+    ('\u051D', '\u051D'),
+    ('\u051F', '\u051F'),
+    ('\u0521', '\u0521'),
+    ('\u0523', '\u0523'),
+    ('\u0525', '\u0525'),
+    ('\u0527', '\u0527'),
+    ('\u0529', '\u0529'),
+    ('\u052B', '\u052B'),
+    ('\u052D', '\u052D'),
+    ('\u052F', '\u052F'),
+    ('\u0560', '\u0588'),
+    ('\u10D0', '\u10FA'),
+    ('\u10FD', '\u10FF'),
+    ('\u13F8', '\u13FD'),
+    ('\u1C80', '\u1C88'),
+    ('\u1D00', '\u1DBF'),
+    ('\u1E01', '\u1E01'),
+    ('\u1E03', '\u1E03'),
+    ('\u1E05', '\u1E05'),
+    ('\u1E07', '\u1E07'),
+    ('\u1E09', '\u1E09'),
+    ('\u1E0B', '\u1E0B'),
+    ('\u1E0D', '\u1E0D'),
+    ('\u1E0F', '\u1E0F'),
+    ('\u1E11', '\u1E11'),
+    ('\u1E13', '\u1E13'),
+    ('\u1E15', '\u1E15'),
+    ('\u1E17', '\u1E17'),
+    ('\u1E19', '\u1E19'),
+    ('\u1E1B', '\u1E1B'),
+    ('\u1E1D', '\u1E1D'),
+    ('\u1E1F', '\u1E1F'),
+    ('\u1E21', '\u1E21'),
+    ('\u1E23', '\u1E23'),
+    ('\u1E25', '\u1E25'),
+    ('\u1E27', '\u1E27'),
+    ('\u1E29', '\u1E29'),
+    ('\u1E2B', '\u1E2B'),
+    ('\u1E2D', '\u1E2D'),
+    ('\u1E2F', '\u1E2F'),
+    ('\u1E31', '\u1E31'),
+    ('\u1E33', '\u1E33'),
+    ('\u1E35', '\u1E35'),
+    ('\u1E37', '\u1E37'),
+    ('\u1E39', '\u1E39'),
+    ('\u1E3B', '\u1E3B'),
+    ('\u1E3D', '\u1E3D'),
+    ('\u1E3F', '\u1E3F'),
+    ('\u1E41', '\u1E41'),
+    ('\u1E43', '\u1E43'),
+    ('\u1E45', '\u1E45'),
+    ('\u1E47', '\u1E47'),
+    ('\u1E49', '\u1E49'),
+    ('\u1E4B', '\u1E4B'),
+    ('\u1E4D', '\u1E4D'),
+    ('\u1E4F', '\u1E4F'),
+    ('\u1E51', '\u1E51'),
+    ('\u1E53', '\u1E53'),
+    ('\u1E55', '\u1E55'),
+    ('\u1E57', '\u1E57'),
+    ('\u1E59', '\u1E59'),
+    ('\u1E5B', '\u1E5B'),
+    ('\u1E5D', '\u1E5D'),
+    ('\u1E5F', '\u1E5F'),
+    ('\u1E61', '\u1E61'),
+    ('\u1E63', '\u1E63'),
+    ('\u1E65', '\u1E65'),
+    ('\u1E67', '\u1E67'),
+    ('\u1E69', '\u1E69'),
+    ('\u1E6B', '\u1E6B'),
+    ('\u1E6D', '\u1E6D'),
+    ('\u1E6F', '\u1E6F'),
+    ('\u1E71', '\u1E71'),
+    ('\u1E73', '\u1E73'),
+    ('\u1E75', '\u1E75'),
+    ('\u1E77', '\u1E77'),
+    ('\u1E79', '\u1E79'),
+    ('\u1E7B', '\u1E7B'),
+    ('\u1E7D', '\u1E7D'),
+    ('\u1E7F', '\u1E7F'),
+    ('\u1E81', '\u1E81'),
+    ('\u1E83', '\u1E83'),
+    ('\u1E85', '\u1E85'),
+    ('\u1E87', '\u1E87'),
+    ('\u1E89', '\u1E89'),
+    ('\u1E8B', '\u1E8B'),
+    ('\u1E8D', '\u1E8D'),
+    ('\u1E8F', '\u1E8F'),
+    ('\u1E91', '\u1E91'),
+    ('\u1E93', '\u1E93'),
+    ('\u1E95', '\u1E9D'),
+    ('\u1E9F', '\u1E9F'),
+    ('\u1EA1', '\u1EA1'),
+    ('\u1EA3', '\u1EA3'),
+    ('\u1EA5', '\u1EA5'),
+    ('\u1EA7', '\u1EA7'),
+    ('\u1EA9', '\u1EA9'),
+    ('\u1EAB', '\u1EAB'),
+    ('\u1EAD', '\u1EAD'),
+    ('\u1EAF', '\u1EAF'),
+    ('\u1EB1', '\u1EB1'),
+    ('\u1EB3', '\u1EB3'),
+    ('\u1EB5', '\u1EB5'),
+    ('\u1EB7', '\u1EB7'),
+    ('\u1EB9', '\u1EB9'),
+    ('\u1EBB', '\u1EBB'),
+    ('\u1EBD', '\u1EBD'),
+    ('\u1EBF', '\u1EBF'),
+    ('\u1EC1', '\u1EC1'),
+    ('\u1EC3', '\u1EC3'),
+    ('\u1EC5', '\u1EC5'),
+    ('\u1EC7', '\u1EC7'),
+    ('\u1EC9', '\u1EC9'),
+    ('\u1ECB', '\u1ECB'),
+    ('\u1ECD', '\u1ECD'),
+    ('\u1ECF', '\u1ECF'),
+    ('\u1ED1', '\u1ED1'),
+    ('\u1ED3', '\u1ED3'),
+    ('\u1ED5', '\u1ED5'),
+    ('\u1ED7', '\u1ED7'),
+    ('\u1ED9', '\u1ED9'),
+    ('\u1EDB', '\u1EDB'),
+    ('\u1EDD', '\u1EDD'),
+    ('\u1EDF', '\u1EDF'),
+    ('\u1EE1', '\u1EE1'),
+    ('\u1EE3', '\u1EE3'),
+    ('\u1EE5', '\u1EE5'),
+    ('\u1EE7', '\u1EE7'),
+    ('\u1EE9', '\u1EE9'),
+    ('\u1EEB', '\u1EEB'),
+    ('\u1EED', '\u1EED'),
+    ('\u1EEF', '\u1EEF'),
+    ('\u1EF1', '\u1EF1'),
+    ('\u1EF3', '\u1EF3'),
+    ('\u1EF5', '\u1EF5'),
+    ('\u1EF7', '\u1EF7'),
+    ('\u1EF9', '\u1EF9'),
+    ('\u1EFB', '\u1EFB'),
+    ('\u1EFD', '\u1EFD'),
+    ('\u1EFF', '\u1F07'),
+    ('\u1F10', '\u1F15'),
+    ('\u1F20', '\u1F27'),
+    ('\u1F30', '\u1F37'),
+    ('\u1F40', '\u1F45'),
+    ('\u1F50', '\u1F57'),
+    ('\u1F60', '\u1F67'),
+    ('\u1F70', '\u1F7D'),
+    ('\u1F80', '\u1F87'),
+    ('\u1F90', '\u1F97'),
+    ('\u1FA0', '\u1FA7'),
+    ('\u1FB0', '\u1FB4'),
+    ('\u1FB6', '\u1FB7'),
+    ('\u1FBE', '\u1FBE'),
+    ('\u1FC2', '\u1FC4'),
+    ('\u1FC6', '\u1FC7'),
+    ('\u1FD0', '\u1FD3'),
+    ('\u1FD6', '\u1FD7'),
+    ('\u1FE0', '\u1FE7'),
+    ('\u1FF2', '\u1FF4'),
+    ('\u1FF6', '\u1FF7'),
+    ('\u2071', '\u2071'),
+    ('\u207F', '\u207F'),
+    ('\u2090', '\u209C'),
+    ('\u210A', '\u210A'),
+    ('\u210E', '\u210F'),
+    ('\u2113', '\u2113'),
+    ('\u212F', '\u212F'),
+    ('\u2134', '\u2134'),
+    ('\u2139', '\u2139'),
+    ('\u213C', '\u213D'),
+    ('\u2146', '\u2149'),
+    ('\u214E', '\u214E'),
+    ('\u2170', '\u217F'),
+    ('\u2184', '\u2184'),
+    ('\u24D0', '\u24E9'),
+    ('\u2C30', '\u2C5F'),
+    ('\u2C61', '\u2C61'),
+    ('\u2C65', '\u2C66'),
+    ('\u2C68', '\u2C68'),
+    ('\u2C6A', '\u2C6A'),
+    ('\u2C6C', '\u2C6C'),
+    ('\u2C71', '\u2C71'),
+    ('\u2C73', '\u2C74'),
+    ('\u2C76', '\u2C7D'),
+    ('\u2C81', '\u2C81'),
+    ('\u2C83', '\u2C83'),
+    ('\u2C85', '\u2C85'),
+    ('\u2C87', '\u2C87'),
+    ('\u2C89', '\u2C89'),
+    ('\u2C8B', '\u2C8B'),
+    ('\u2C8D', '\u2C8D'),
+    ('\u2C8F', '\u2C8F'),
+    ('\u2C91', '\u2C91'),
+    ('\u2C93', '\u2C93'),
+    ('\u2C95', '\u2C95'),
+    ('\u2C97', '\u2C97'),
+    ('\u2C99', '\u2C99'),
+    ('\u2C9B', '\u2C9B'),
+    ('\u2C9D', '\u2C9D'),
+    ('\u2C9F', '\u2C9F'),
+    ('\u2CA1', '\u2CA1'),
+    ('\u2CA3', '\u2CA3'),
+    ('\u2CA5', '\u2CA5'),
+    ('\u2CA7', '\u2CA7'),
+    ('\u2CA9', '\u2CA9'),
+    ('\u2CAB', '\u2CAB'),
+    ('\u2CAD', '\u2CAD'),
+    ('\u2CAF', '\u2CAF'),
+    ('\u2CB1', '\u2CB1'),
+    ('\u2CB3', '\u2CB3'),
+    ('\u2CB5', '\u2CB5'),
+    ('\u2CB7', '\u2CB7'),
+    ('\u2CB9', '\u2CB9'),
+    ('\u2CBB', '\u2CBB'),
+    ('\u2CBD', '\u2CBD'),
+    ('\u2CBF', '\u2CBF'),
+    ('\u2CC1', '\u2CC1'),
+    ('\u2CC3', '\u2CC3'),
+    ('\u2CC5', '\u2CC5'),
+    ('\u2CC7', '\u2CC7'),
+    ('\u2CC9', '\u2CC9'),
+    ('\u2CCB', '\u2CCB'),
+    ('\u2CCD', '\u2CCD'),
+    ('\u2CCF', '\u2CCF'),
+    ('\u2CD1', '\u2CD1'),
+    ('\u2CD3', '\u2CD3'),
+    ('\u2CD5', '\u2CD5'),
+    ('\u2CD7', '\u2CD7'),
+    ('\u2CD9', '\u2CD9'),
+    ('\u2CDB', '\u2CDB'),
+    ('\u2CDD', '\u2CDD'),
+    ('\u2CDF', '\u2CDF'),
+    ('\u2CE1', '\u2CE1'),
+    ('\u2CE3', '\u2CE4'),
+    ('\u2CEC', '\u2CEC'),
+    ('\u2CEE', '\u2CEE'),
+    ('\u2CF3', '\u2CF3'),
+    ('\u2D00', '\u2D25'),
+    ('\u2D27', '\u2D27'),
+    ('\u2D2D', '\u2D2D'),
+    ('\uA641', '\uA641'),
+    ('\uA643', '\uA643'),
+    ('\uA645', '\uA645'),
+    ('\uA647', '\uA647'),
+    ('\uA649', '\uA649'),
+    ('\uA64B', '\uA64B'),
+    ('\uA64D', '\uA64D'),
+    ('\uA64F', '\uA64F'),
+    ('\uA651', '\uA651'),
+    ('\uA653', '\uA653'),
+    ('\uA655', '\uA655'),
+    ('\uA657', '\uA657'),
+    ('\uA659', '\uA659'),
+    ('\uA65B', '\uA65B'),
+    ('\uA65D', '\uA65D'),
+    ('\uA65F', '\uA65F'),
+    ('\uA661', '\uA661'),
+    ('\uA663', '\uA663'),
+    ('\uA665', '\uA665'),
+    ('\uA667', '\uA667'),
+    ('\uA669', '\uA669'),
+    ('\uA66B', '\uA66B'),
+    ('\uA66D', '\uA66D'),
+    ('\uA681', '\uA681'),
+    ('\uA683', '\uA683'),
+    ('\uA685', '\uA685'),
+    ('\uA687', '\uA687'),
+    ('\uA689', '\uA689'),
+    ('\uA68B', '\uA68B'),
+    ('\uA68D', '\uA68D'),
+    ('\uA68F', '\uA68F'),
+    ('\uA691', '\uA691'),
+    ('\uA693', '\uA693'),
+    ('\uA695', '\uA695'),
+    ('\uA697', '\uA697'),
+    ('\uA699', '\uA699'),
+    ('\uA69B', '\uA69D'),
+    ('\uA723', '\uA723'),
+    ('\uA725', '\uA725'),
+    ('\uA727', '\uA727'),
+    ('\uA729', '\uA729'),
+    ('\uA72B', '\uA72B'),
+    ('\uA72D', '\uA72D'),
+    ('\uA72F', '\uA731'),
+    ('\uA733', '\uA733'),
+    ('\uA735', '\uA735'),
+    ('\uA737', '\uA737'),
+    ('\uA739', '\uA739'),
+    ('\uA73B', '\uA73B'),
+    ('\uA73D', '\uA73D'),
+    ('\uA73F', '\uA73F'),
+    ('\uA741', '\uA741'),
+    ('\uA743', '\uA743'),
+    ('\uA745', '\uA745'),
+    ('\uA747', '\uA747'),
+    ('\uA749', '\uA749'),
+    ('\uA74B', '\uA74B'),
+    ('\uA74D', '\uA74D'),
+    ('\uA74F', '\uA74F'),
+    ('\uA751', '\uA751'),
+    ('\uA753', '\uA753'),
+    ('\uA755', '\uA755'),
+    ('\uA757', '\uA757'),
+    ('\uA759', '\uA759'),
+    ('\uA75B', '\uA75B'),
+    ('\uA75D', '\uA75D'),
+    ('\uA75F', '\uA75F'),
+    ('\uA761', '\uA761'),
+    ('\uA763', '\uA763'),
+    ('\uA765', '\uA765'),
+    ('\uA767', '\uA767'),
+    ('\uA769', '\uA769'),
+    ('\uA76B', '\uA76B'),
+    ('\uA76D', '\uA76D'),
+    ('\uA76F', '\uA778'),
+    ('\uA77A', '\uA77A'),
+    ('\uA77C', '\uA77C'),
+    ('\uA77F', '\uA77F'),
+    ('\uA781', '\uA781'),
+    ('\uA783', '\uA783'),
+    ('\uA785', '\uA785'),
+    ('\uA787', '\uA787'),
+    ('\uA78C', '\uA78C'),
+    ('\uA78E', '\uA78E'),
+    ('\uA791', '\uA791'),
+    ('\uA793', '\uA795'),
+    ('\uA797', '\uA797'),
+    ('\uA799', '\uA799'),
+    ('\uA79B', '\uA79B'),
+    ('\uA79D', '\uA79D'),
+    ('\uA79F', '\uA79F'),
+    ('\uA7A1', '\uA7A1'),
+    ('\uA7A3', '\uA7A3'),
+    ('\uA7A5', '\uA7A5'),
+    ('\uA7A7', '\uA7A7'),
+    ('\uA7A9', '\uA7A9'),
+    ('\uA7AF', '\uA7AF'),
+    ('\uA7B5', '\uA7B5'),
+    ('\uA7B7', '\uA7B7'),
+    ('\uA7B9', '\uA7B9'),
+    ('\uA7BB', '\uA7BB'),
+    ('\uA7BD', '\uA7BD'),
+    ('\uA7BF', '\uA7BF'),
+    ('\uA7C1', '\uA7C1'),
+    ('\uA7C3', '\uA7C3'),
+    ('\uA7C8', '\uA7C8'),
+    ('\uA7CA', '\uA7CA'),
+    ('\uA7D1', '\uA7D1'),
+    ('\uA7D3', '\uA7D3'),
+    ('\uA7D5', '\uA7D5'),
+    ('\uA7D7', '\uA7D7'),
+    ('\uA7D9', '\uA7D9'),
+    ('\uA7F6', '\uA7F6'),
+    ('\uA7F8', '\uA7FA'),
+    ('\uAB30', '\uAB5A'),
+    ('\uAB5C', '\uAB68'),
+    ('\uAB70', '\uABBF'),
+    ('\uFB00', '\uFB06'),
+    ('\uFB13', '\uFB17'),
+    ('\uFF41', '\uFF5A'),
+    ('\U00010428', '\U0001044F'),
+    ('\U000104D8', '\U000104FB'),
+    ('\U00010597', '\U000105A1'),
+    ('\U000105A3', '\U000105B1'),
+    ('\U000105B3', '\U000105B9'),
+    ('\U000105BB', '\U000105BC'),
+    ('\U00010780', '\U00010780'),
+    ('\U00010783', '\U00010785'),
+    ('\U00010787', '\U000107B0'),
+    ('\U000107B2', '\U000107BA'),
+    ('\U00010CC0', '\U00010CF2'),
+    ('\U000118C0', '\U000118DF'),
+    ('\U00016E60', '\U00016E7F'),
+    ('\U0001D41A', '\U0001D433'),
+    ('\U0001D44E', '\U0001D454'),
+    ('\U0001D456', '\U0001D467'),
+    ('\U0001D482', '\U0001D49B'),
+    ('\U0001D4B6', '\U0001D4B9'),
+    ('\U0001D4BB', '\U0001D4BB'),
+    ('\U0001D4BD', '\U0001D4C3'),
+    ('\U0001D4C5', '\U0001D4CF'),
+    ('\U0001D4EA', '\U0001D503'),
+    ('\U0001D51E', '\U0001D537'),
+    ('\U0001D552', '\U0001D56B'),
+    ('\U0001D586', '\U0001D59F'),
+    ('\U0001D5BA', '\U0001D5D3'),
+    ('\U0001D5EE', '\U0001D607'),
+    ('\U0001D622', '\U0001D63B'),
+    ('\U0001D656', '\U0001D66F'),
+    ('\U0001D68A', '\U0001D6A5'),
+    ('\U0001D6C2', '\U0001D6DA'),
+    ('\U0001D6DC', '\U0001D6E1'),
+    ('\U0001D6FC', '\U0001D714'),
+    ('\U0001D716', '\U0001D71B'),
+    ('\U0001D736', '\U0001D74E'),
+    ('\U0001D750', '\U0001D755'),
+    ('\U0001D770', '\U0001D788'),
+    ('\U0001D78A', '\U0001D78F'),
+    ('\U0001D7AA', '\U0001D7C2'),
+    ('\U0001D7C4', '\U0001D7C9'),
+    ('\U0001D7CB', '\U0001D7CB'),
+    ('\U0001DF00', '\U0001DF09'),
+    ('\U0001DF0B', '\U0001DF1E'),
+    ('\U0001E922', '\U0001E943')
+  ]),
+  ("math", &[
+    ('\u002B', '\u002B'),
+    ('\u003C', '\u003E'),
+    ('\u005E', '\u005E'),
+    ('\u007C', '\u007C'),
+    ('\u007E', '\u007E'),
+    ('\u00AC', '\u00AC'),
+    ('\u00B1', '\u00B1'),
+    ('\u00D7', '\u00D7'),
+    ('\u00F7', '\u00F7'),
+    ('\u03D0', '\u03D2'),
+    ('\u03D5', '\u03D5'),
+    ('\u03F0', '\u03F1'),
+    ('\u03F4', '\u03F6'),
+    ('\u0606', '\u0608'),
+    ('\u2016', '\u2016'),
+    ('\u2032', '\u2034'),
+    ('\u2040', '\u2040'),
+    ('\u2044', '\u2044'),
+    ('\u2052', '\u2052'),
+    ('\u2061', '\u2064'),
+    ('\u207A', '\u207E'),
+    ('\u208A', '\u208E'),
+    ('\u20D0', '\u20DC'),
+    ('\u20E1', '\u20E1'),
+    ('\u20E5', '\u20E6'),
+    ('\u20EB', '\u20EF'),
+    ('\u2102', '\u2102'),
+    ('\u2107', '\u2107'),
+    ('\u210A', '\u2113'),
+    ('\u2115', '\u2115'),
+    ('\u2118', '\u211D'),
+    ('\u2124', '\u2124'),
+    ('\u2128', '\u2129'),
+    ('\u212C', '\u212D'),
+    ('\u212F', '\u2131'),
+    ('\u2133', '\u2138'),
+    ('\u213C', '\u2149'),
+    ('\u214B', '\u214B'),
+    ('\u2190', '\u21A7'),
+    ('\u21A9', '\u21AE'),
+    ('\u21B0', '\u21B1'),
+    ('\u21B6', '\u21B7'),
+    ('\u21BC', '\u21DB'),
+    ('\u21DD', '\u21DD'),
+    ('\u21E4', '\u21E5'),
+    ('\u21F4', '\u22FF'),
+    ('\u2308', '\u230B'),
+    ('\u2320', '\u2321'),
+    ('\u237C', '\u237C'),
+    ('\u239B', '\u23B5'),
+    ('\u23B7', '\u23B7'),
+    ('\u23D0', '\u23D0'),
+    ('\u23DC', '\u23E2'),
+    ('\u25A0', '\u25A1'),
+    ('\u25AE', '\u25B7'),
+    ('\u25BC', '\u25C1'),
+    ('\u25C6', '\u25C7'),
+    ('\u25CA', '\u25CB'),
+    ('\u25CF', '\u25D3'),
+    ('\u25E2', '\u25E2'),
+    ('\u25E4', '\u25E4'),
+    ('\u25E7', '\u25EC'),
+    ('\u25F8', '\u25FF'),
+    ('\u2605', '\u2606'),
+    ('\u2640', '\u2640'),
+    ('\u2642', '\u2642'),
+    ('\u2660', '\u2663'),
+    ('\u266D', '\u266F'),
+    ('\u27C0', '\u27FF'),
+    ('\u2900', '\u2AFF'),
+    ('\u2B30', '\u2B44'),
+    ('\u2B47', '\u2B4C'),
+    ('\uFB29', '\uFB29'),
+    ('\uFE61', '\uFE66'),
+    ('\uFE68', '\uFE68'),
+    ('\uFF0B', '\uFF0B'),
+    ('\uFF1C', '\uFF1E'),
+    ('\uFF3C', '\uFF3C'),
+    ('\uFF3E', '\uFF3E'),
+    ('\uFF5C', '\uFF5C'),
+    ('\uFF5E', '\uFF5E'),
+    ('\uFFE2', '\uFFE2'),
+    ('\uFFE9', '\uFFEC'),
+    ('\U0001D400', '\U0001D454'),
+    ('\U0001D456', '\U0001D49C'),
+    ('\U0001D49E', '\U0001D49F'),
+    ('\U0001D4A2', '\U0001D4A2'),
+    ('\U0001D4A5', '\U0001D4A6'),
+    ('\U0001D4A9', '\U0001D4AC'),
+    ('\U0001D4AE', '\U0001D4B9'),
+    ('\U0001D4BB', '\U0001D4BB'),
+    ('\U0001D4BD', '\U0001D4C3'),
+    ('\U0001D4C5', '\U0001D505'),
+    ('\U0001D507', '\U0001D50A'),
+    ('\U0001D50D', '\U0001D514'),
+    ('\U0001D516', '\U0001D51C'),
+    ('\U0001D51E', '\U0001D539'),
+    ('\U0001D53B', '\U0001D53E'),
+    ('\U0001D540', '\U0001D544'),
+    ('\U0001D546', '\U0001D546'),
+    ('\U0001D54A', '\U0001D550'),
+    ('\U0001D552', '\U0001D6A5'),
+    ('\U0001D6A8', '\U0001D7CB'),
+    ('\U0001D7CE', '\U0001D7FF'),
+    ('\U0001EE00', '\U0001EE03'),
+    ('\U0001EE05', '\U0001EE1F'),
+    ('\U0001EE21', '\U0001EE22'),
+    ('\U0001EE24', '\U0001EE24'),
+    ('\U0001EE27', '\U0001EE27'),
+    ('\U0001EE29', '\U0001EE32'),
+    ('\U0001EE34', '\U0001EE37'),
+    ('\U0001EE39', '\U0001EE39'),
+    ('\U0001EE3B', '\U0001EE3B'),
+    ('\U0001EE42', '\U0001EE42'),
+    ('\U0001EE47', '\U0001EE47'),
+    ('\U0001EE49', '\U0001EE49'),
+    ('\U0001EE4B', '\U0001EE4B'),
+    ('\U0001EE4D', '\U0001EE4F'),
+    ('\U0001EE51', '\U0001EE52'),
+    ('\U0001EE54', '\U0001EE54'),
+    ('\U0001EE57', '\U0001EE57'),
+    ('\U0001EE59', '\U0001EE59'),
+    ('\U0001EE5B', '\U0001EE5B'),
+    ('\U0001EE5D', '\U0001EE5D'),
+    ('\U0001EE5F', '\U0001EE5F'),
+    ('\U0001EE61', '\U0001EE62'),
+    ('\U0001EE64', '\U0001EE64'),
+    ('\U0001EE67', '\U0001EE6A'),
+    ('\U0001EE6C', '\U0001EE72'),
+    ('\U0001EE74', '\U0001EE77'),
+    ('\U0001EE79', '\U0001EE7C'),
+    ('\U0001EE7E', '\U0001EE7E'),
+    ('\U0001EE80', '\U0001EE89'),
+    ('\U0001EE8B', '\U0001EE9B'),
+    ('\U0001EEA1', '\U0001EEA3'),
+    ('\U0001EEA5', '\U0001EEA9'),
+    ('\U0001EEAB', '\U0001EEBB'),
+    ('\U0001EEF0', '\U0001EEF1')
+  ]),
+  ("noncharactercodepoint", &[
+    ('\uFDD0', '\uFDEF'),
+    ('\uFFFE', '\uFFFF'),
+    ('\U0001FFFE', '\U0001FFFF'),
+    ('\U0002FFFE', '\U0002FFFF'),
+    ('\U0003FFFE', '\U0003FFFF'),
+    ('\U0004FFFE', '\U0004FFFF'),
+    ('\U0005FFFE', '\U0005FFFF'),
+    ('\U0006FFFE', '\U0006FFFF'),
+    ('\U0007FFFE', '\U0007FFFF'),
+    ('\U0008FFFE', '\U0008FFFF'),
+    ('\U0009FFFE', '\U0009FFFF'),
+    ('\U000AFFFE', '\U000AFFFF'),
+    ('\U000BFFFE', '\U000BFFFF'),
+    ('\U000CFFFE', '\U000CFFFF'),
+    ('\U000DFFFE', '\U000DFFFF'),
+    ('\U000EFFFE', '\U000EFFFF'),
+    ('\U000FFFFE', '\U000FFFFF'),
+    ('\U0010FFFE', '\U0010FFFF')
+  ]),
+  ("quotationmark", &[
+    ('\u0022', '\u0022'),
+    ('\u0027', '\u0027'),
+    ('\u00AB', '\u00AB'),
+    ('\u00BB', '\u00BB'),
+    ('\u2018', '\u201F'),
+    ('\u2039', '\u203A'),
+    ('\u2E42', '\u2E42'),
+    ('\u300C', '\u300F'),
+    ('\u301D', '\u301F'),
+    ('\uFE41', '\uFE44'),
+    ('\uFF02', '\uFF02'),
+    ('\uFF07', '\uFF07'),
+    ('\uFF62', '\uFF63')
+  ]),
+  ("terminalpunctuation", &[
+    ('\u0021', '\u0021'),
+    ('\u002C', '\u002C'),
+    ('\u002E', '\u002E'),
+    ('\u003A', '\u003B'),
+    ('\u003F', '\u003F'),
+    ('\u037E', '\u037E'),
+    ('\u0387', '\u0387'),
+    ('\u0589', '\u0589'),
+    ('\u05C3', '\u05C3'),
+    ('\u060C', '\u060C'),
+    ('\u061B', '\u061B'),
+    ('\u061D', '\u061F'),
+    ('\u06D4', '\u06D4'),
+    ('\u0700', '\u070A'),
+    ('\u070C', '\u070C'),
+    ('\u07F8', '\u07F9'),
+    ('\u0830', '\u083E'),
+    ('\u085E', '\u085E'),
+    ('\u0964', '\u0965'),
+    ('\u0E5A', '\u0E5B'),
+    ('\u0F08', '\u0F08'),
+    ('\u0F0D', '\u0F12'),
+    ('\u104A', '\u104B'),
+    ('\u1361', '\u1368'),
+    ('\u166E', '\u166E'),
+    ('\u16EB', '\u16ED'),
+    ('\u1735', '\u1736'),
+    ('\u17D4', '\u17D6'),
+    ('\u17DA', '\u17DA'),
+    ('\u1802', '\u1805'),
+    ('\u1808', '\u1809'),
+    ('\u1944', '\u1945'),
+    ('\u1AA8', '\u1AAB'),
+    ('\u1B5A', '\u1B5B'),
+    ('\u1B5D', '\u1B5F'),
+    ('\u1B7D', '\u1B7E'),
+    ('\u1C3B', '\u1C3F'),
+    ('\u1C7E', '\u1C7F'),
+    ('\u203C', '\u203D'),
+    ('\u2047', '\u2049'),
+    ('\u2E2E', '\u2E2E'),
+    ('\u2E3C', '\u2E3C'),
+    ('\u2E41', '\u2E41'),
+    ('\u2E4C', '\u2E4C'),
+    ('\u2E4E', '\u2E4F'),
+    ('\u2E53', '\u2E54'),
+    ('\u3001', '\u3002'),
+    ('\uA4FE', '\uA4FF'),
+    ('\uA60D', '\uA60F'),
+    ('\uA6F3', '\uA6F7'),
+    ('\uA876', '\uA877'),
+    ('\uA8CE', '\uA8CF'),
+    ('\uA92F', '\uA92F'),
+    ('\uA9C7', '\uA9C9'),
+    ('\uAA5D', '\uAA5F'),
+    ('\uAADF', '\uAADF'),
+    ('\uAAF0', '\uAAF1'),
+    ('\uABEB', '\uABEB'),
+    ('\uFE50', '\uFE52'),
+    ('\uFE54', '\uFE57'),
+    ('\uFF01', '\uFF01'),
+    ('\uFF0C', '\uFF0C'),
+    ('\uFF0E', '\uFF0E'),
+    ('\uFF1A', '\uFF1B'),
+    ('\uFF1F', '\uFF1F'),
+    ('\uFF61', '\uFF61'),
+    ('\uFF64', '\uFF64'),
+    ('\U0001039F', '\U0001039F'),
+    ('\U000103D0', '\U000103D0'),
+    ('\U00010857', '\U00010857'),
+    ('\U0001091F', '\U0001091F'),
+    ('\U00010A56', '\U00010A57'),
+    ('\U00010AF0', '\U00010AF5'),
+    ('\U00010B3A', '\U00010B3F'),
+    ('\U00010B99', '\U00010B9C'),
+    ('\U00010F55', '\U00010F59'),
+    ('\U00010F86', '\U00010F89'),
+    ('\U00011047', '\U0001104D'),
+    ('\U000110BE', '\U000110C1'),
+    ('\U00011141', '\U00011143'),
+    ('\U000111C5', '\U000111C6'),
+    ('\U000111CD', '\U000111CD'),
+    ('\U000111DE', '\U000111DF'),
+    ('\U00011238', '\U0001123C'),
+    ('\U000112A9', '\U000112A9'),
+    ('\U0001144B', '\U0001144D'),
+    ('\U0001145A', '\U0001145B'),
+    ('\U000115C2', '\U000115C5'),
+    ('\U000115C9', '\U000115D7'),
+    ('\U00011641', '\U00011642'),
+    ('\U0001173C', '\U0001173E'),
+    ('\U00011944', '\U00011944'),
+    ('\U00011946', '\U00011946'),
+    ('\U00011A42', '\U00011A43'),
+    ('\U00011A9B', '\U00011A9C'),
+    ('\U00011AA1', '\U00011AA2'),
+    ('\U00011C41', '\U00011C43'),
+    ('\U00011C71', '\U00011C71'),
+    ('\U00011EF7', '\U00011EF8'),
+    ('\U00012470', '\U00012474'),
+    ('\U00016A6E', '\U00016A6F'),
+    ('\U00016AF5', '\U00016AF5'),
+    ('\U00016B37', '\U00016B39'),
+    ('\U00016B44', '\U00016B44'),
+    ('\U00016E97', '\U00016E98'),
+    ('\U0001BC9F', '\U0001BC9F'),
+    ('\U0001DA87', '\U0001DA8A')
+  ]),
+  ("uppercase", &[
+    ('\u0041', '\u005A'),
+    ('\u00C0', '\u00D6'),
+    ('\u00D8', '\u00DE'),
+    ('\u0100', '\u0100'),
+    ('\u0102', '\u0102'),
+    ('\u0104', '\u0104'),
+    ('\u0106', '\u0106'),
+    ('\u0108', '\u0108'),
+    ('\u010A', '\u010A'),
+    ('\u010C', '\u010C'),
+    ('\u010E', '\u010E'),
+    ('\u0110', '\u0110'),
+    ('\u0112', '\u0112'),
+    ('\u0114', '\u0114'),
+    ('\u0116', '\u0116'),
+    ('\u0118', '\u0118'),
+    ('\u011A', '\u011A'),
+    ('\u011C', '\u011C'),
+    ('\u011E', '\u011E'),
+    ('\u0120', '\u0120'),
+    ('\u0122', '\u0122'),
+    ('\u0124', '\u0124'),
+    ('\u0126', '\u0126'),
+    ('\u0128', '\u0128'),
+    ('\u012A', '\u012A'),
+    ('\u012C', '\u012C'),
+    ('\u012E', '\u012E'),
+    ('\u0130', '\u0130'),
+    ('\u0132', '\u0132'),
+    ('\u0134', '\u0134'),
+    ('\u0136', '\u0136'),
+    ('\u0139', '\u0139'),
+    ('\u013B', '\u013B'),
+    ('\u013D', '\u013D'),
+    ('\u013F', '\u013F'),
+    ('\u0141', '\u0141'),
+    ('\u0143', '\u0143'),
+    ('\u0145', '\u0145'),
+    ('\u0147', '\u0147'),
+    ('\u014A', '\u014A'),
+    ('\u014C', '\u014C'),
+    ('\u014E', '\u014E'),
+    ('\u0150', '\u0150'),
+    ('\u0152', '\u0152'),
+    ('\u0154', '\u0154'),
+    ('\u0156', '\u0156'),
+    ('\u0158', '\u0158'),
+    ('\u015A', '\u015A'),
+    ('\u015C', '\u015C'),
+    ('\u015E', '\u015E'),
+    ('\u0160', '\u0160'),
+    ('\u0162', '\u0162'),
+    ('\u0164', '\u0164'),
+    ('\u0166', '\u0166'),
+    ('\u0168', '\u0168'),
+    ('\u016A', '\u016A'),
+    ('\u016C', '\u016C'),
+    ('\u016E', '\u016E'),
+    ('\u0170', '\u0170'),
+    ('\u0172', '\u0172'),
+    ('\u0174', '\u0174'),
+    ('\u0176', '\u0176'),
+    ('\u0178', '\u0179'),
+    ('\u017B', '\u017B'),
+    ('\u017D', '\u017D'),
+    ('\u0181', '\u0182'),
+    ('\u0184', '\u0184'),
+    ('\u0186', '\u0187'),
+    ('\u0189', '\u018B'),
+    ('\u018E', '\u0191'),
+    ('\u0193', '\u0194'),
+    ('\u0196', '\u0198'),
+    ('\u019C', '\u019D'),
+    ('\u019F', '\u01A0'),
+    ('\u01A2', '\u01A2'),
+    ('\u01A4', '\u01A4'),
+    ('\u01A6', '\u01A7'),
+    ('\u01A9', '\u01A9'),
+    ('\u01AC', '\u01AC'),
+    ('\u01AE', '\u01AF'),
+    ('\u01B1', '\u01B3'),
+    ('\u01B5', '\u01B5'),
+    ('\u01B7', '\u01B8'),
+    ('\u01BC', '\u01BC'),
+    ('\u01C4', '\u01C4'),
+    ('\u01C7', '\u01C7'),
+    ('\u01CA', '\u01CA'),
+    ('\u01CD', '\u01CD'),
+    ('\u01CF', '\u01CF'),
+    ('\u01D1', '\u01D1'),
+    ('\u01D3', '\u01D3'),
+    ('\u01D5', '\u01D5'),
+    ('\u01D7', '\u01D7'),
+    ('\u01D9', '\u01D9'),
+    ('\u01DB', '\u01DB'),
+    ('\u01DE', '\u01DE'),
+    ('\u01E0', '\u01E0'),
+    ('\u01E2', '\u01E2'),
+    ('\u01E4', '\u01E4'),
+    ('\u01E6', '\u01E6'),
+    ('\u01E8', '\u01E8'),
+    ('\u01EA', '\u01EA'),
+    ('\u01EC', '\u01EC'),
+    ('\u01EE', '\u01EE'),
+    ('\u01F1', '\u01F1'),
+    ('\u01F4', '\u01F4'),
+    ('\u01F6', '\u01F8'),
+    ('\u01FA', '\u01FA'),
+    ('\u01FC', '\u01FC'),
+    ('\u01FE', '\u01FE'),
+    ('\u0200', '\u0200'),
+    ('\u0202', '\u0202'),
+    ('\u0204', '\u0204'),
+    ('\u0206', '\u0206'),
+    ('\u0208', '\u0208'),
+    ('\u020A', '\u020A'),
+    ('\u020C', '\u020C'),
+    ('\u020E', '\u020E'),
+    ('\u0210', '\u0210'),
+    ('\u0212', '\u0212'),
+    ('\u0214', '\u0214'),
+    ('\u0216', '\u0216'),
+    ('\u0218', '\u0218'),
+    ('\u021A', '\u021A'),
+    ('\u021C', '\u021C'),
+    ('\u021E', '\u021E'),
+    ('\u0220', '\u0220'),
+    ('\u0222', '\u0222'),
+    ('\u0224', '\u0224'),
+    ('\u0226', '\u0226'),
+    ('\u0228', '\u0228'),
+    ('\u022A', '\u022A'),
+    ('\u022C', '\u022C'),
+    ('\u022E', '\u022E'),
+    ('\u0230', '\u0230'),
+    ('\u0232', '\u0232'),
+    ('\u023A', '\u023B'),
+    ('\u023D', '\u023E'),
+    ('\u0241', '\u0241'),
+    ('\u0243', '\u0246'),
+    ('\u0248', '\u0248'),
+    ('\u024A', '\u024A'),
+    ('\u024C', '\u024C'),
+    ('\u024E', '\u024E'),
+    ('\u0370', '\u0370'),
+    ('\u0372', '\u0372'),
+    ('\u0376', '\u0376'),
+    ('\u037F', '\u037F'),
+    ('\u0386', '\u0386'),
+    ('\u0388', '\u038A'),
+    ('\u038C', '\u038C'),
+    ('\u038E', '\u038F'),
+    ('\u0391', '\u03A1'),
+    ('\u03A3', '\u03AB'),
+    ('\u03CF', '\u03CF'),
+    ('\u03D2', '\u03D4'),
+    ('\u03D8', '\u03D8'),
+    ('\u03DA', '\u03DA'),
+    ('\u03DC', '\u03DC'),
+    ('\u03DE', '\u03DE'),
+    ('\u03E0', '\u03E0'),
+    ('\u03E2', '\u03E2'),
+    ('\u03E4', '\u03E4'),
+    ('\u03E6', '\u03E6'),
+    ('\u03E8', '\u03E8'),
+    ('\u03EA', '\u03EA'),
+    ('\u03EC', '\u03EC'),
+    ('\u03EE', '\u03EE'),
+    ('\u03F4', '\u03F4'),
+    ('\u03F7', '\u03F7'),
+    ('\u03F9', '\u03FA'),
+    ('\u03FD', '\u042F'),
+    ('\u0460', '\u0460'),
+    ('\u0462', '\u0462'),
+    ('\u0464', '\u0464'),
+    ('\u0466', '\u0466'),
+    ('\u0468', '\u0468'),
+    ('\u046A', '\u046A'),
+    ('\u046C', '\u046C'),
+    ('\u046E', '\u046E'),
+    ('\u0470', '\u0470'),
+    ('\u0472', '\u0472'),
+    ('\u0474', '\u0474'),
+    ('\u0476', '\u0476'),
+    ('\u0478', '\u0478'),
+    ('\u047A', '\u047A'),
+    ('\u047C', '\u047C'),
+    ('\u047E', '\u047E'),
+    ('\u0480', '\u0480'),
+    ('\u048A', '\u048A'),
+    ('\u048C', '\u048C'),
+    ('\u048E', '\u048E'),
+    ('\u0490', '\u0490'),
+    ('\u0492', '\u0492'),
+    ('\u0494', '\u0494'),
+    ('\u0496', '\u0496'),
+    ('\u0498', '\u0498'),
+    ('\u049A', '\u049A'),
+    ('\u049C', '\u049C'),
+    ('\u049E', '\u049E'),
+    ('\u04A0', '\u04A0'),
+    ('\u04A2', '\u04A2'),
+    ('\u04A4', '\u04A4'),
+    ('\u04A6', '\u04A6'),
+    ('\u04A8', '\u04A8'),
+    ('\u04AA', '\u04AA'),
+    ('\u04AC', '\u04AC'),
+    ('\u04AE', '\u04AE'),
+    ('\u04B0', '\u04B0'),
+    ('\u04B2', '\u04B2'),
+    ('\u04B4', '\u04B4'),
+    ('\u04B6', '\u04B6'),
+    ('\u04B8', '\u04B8'),
+    ('\u04BA', '\u04BA'),
+    ('\u04BC', '\u04BC'),
+    ('\u04BE', '\u04BE'),
+    ('\u04C0', '\u04C1'),
+    ('\u04C3', '\u04C3'),
+    ('\u04C5', '\u04C5'),
+    ('\u04C7', '\u04C7'),
+    ('\u04C9', '\u04C9'),
+    ('\u04CB', '\u04CB'),
+    ('\u04CD', '\u04CD'),
+    ('\u04D0', '\u04D0'),
+    ('\u04D2', '\u04D2'),
+    ('\u04D4', '\u04D4'),
+    ('\u04D6', '\u04D6'),
+    ('\u04D8', '\u04D8'),
+    ('\u04DA', '\u04DA'),
+    ('\u04DC', '\u04DC'),
+    ('\u04DE', '\u04DE'),
+    ('\u04E0', '\u04E0'),
+    ('\u04E2', '\u04E2'),
+    ('\u04E4', '\u04E4'),
+    ('\u04E6', '\u04E6'),
+    ('\u04E8', '\u04E8'),
+    ('\u04EA', '\u04EA'),
+    ('\u04EC', '\u04EC'),
+    ('\u04EE', '\u04EE'),
+    ('\u04F0', '\u04F0'),
+    ('\u04F2', '\u04F2'),
+    ('\u04F4', '\u04F4'),
+    ('\u04F6', '\u04F6'),
+    ('\u04F8', '\u04F8'),
+    ('\u04FA', '\u04FA'),
+    ('\u04FC', '\u04FC'),
+    ('\u04FE', '\u04FE'),
+    ('\u0500', '\u0500'),
+    ('\u0502', '\u0502'),
+    ('\u0504', '\u0504'),
+    ('\u0506', '\u0506'),
+    ('\u0508', '\u0508'),
+    ('\u050A', '\u050A'),
+    ('\u050C', '\u050C'),
+    ('\u050E', '\u050E'),
+    ('\u0510', '\u0510'),
+    ('\u0512', '\u0512'),
+    ('\u0514', '\u0514'),
+    ('\u0516', '\u0516'),
+    ('\u0518', '\u0518'),
+    ('\u051A', '\u051A'),
+    ('\u051C', '\u051C'),
+    ('\u051E', '\u051E'),
+    ('\u0520', '\u0520'),
+    ('\u0522', '\u0522'),
+    ('\u0524', '\u0524'),
+    ('\u0526', '\u0526'),
+    ('\u0528', '\u0528'),
+    ('\u052A', '\u052A'),
+    ('\u052C', '\u052C'),
+    ('\u052E', '\u052E'),
+    ('\u0531', '\u0556'),
+    ('\u10A0', '\u10C5'),
+    ('\u10C7', '\u10C7'),
+    ('\u10CD', '\u10CD'),
+    ('\u13A0', '\u13F5'),
+    ('\u1C90', '\u1CBA'),
+    ('\u1CBD', '\u1CBF'),
+    ('\u1E00', '\u1E00'),
+    ('\u1E02', '\u1E02'),
+    ('\u1E04', '\u1E04'),
+    ('\u1E06', '\u1E06'),
+    ('\u1E08', '\u1E08'),
+    ('\u1E0A', '\u1E0A'),
+    ('\u1E0C', '\u1E0C'),
+    ('\u1E0E', '\u1E0E'),
+    ('\u1E10', '\u1E10'),
+    ('\u1E12', '\u1E12'),
+    ('\u1E14', '\u1E14'),
+    ('\u1E16', '\u1E16'),
+    ('\u1E18', '\u1E18'),
+    ('\u1E1A', '\u1E1A'),
+    ('\u1E1C', '\u1E1C'),
+    ('\u1E1E', '\u1E1E'),
+    ('\u1E20', '\u1E20'),
+    ('\u1E22', '\u1E22'),
+    ('\u1E24', '\u1E24'),
+    ('\u1E26', '\u1E26'),
+    ('\u1E28', '\u1E28'),
+    ('\u1E2A', '\u1E2A'),
+    ('\u1E2C', '\u1E2C'),
+    ('\u1E2E', '\u1E2E'),
+    ('\u1E30', '\u1E30'),
+    ('\u1E32', '\u1E32'),
+    ('\u1E34', '\u1E34'),
+    ('\u1E36', '\u1E36'),
+    ('\u1E38', '\u1E38'),
+    ('\u1E3A', '\u1E3A'),
+    ('\u1E3C', '\u1E3C'),
+    ('\u1E3E', '\u1E3E'),
+    ('\u1E40', '\u1E40'),
+    ('\u1E42', '\u1E42'),
+    ('\u1E44', '\u1E44'),
+    ('\u1E46', '\u1E46'),
+    ('\u1E48', '\u1E48'),
+    ('\u1E4A', '\u1E4A'),
+    ('\u1E4C', '\u1E4C'),
+    ('\u1E4E', '\u1E4E'),
+    ('\u1E50', '\u1E50'),
+    ('\u1E52', '\u1E52'),
+    ('\u1E54', '\u1E54'),
+    ('\u1E56', '\u1E56'),
+    ('\u1E58', '\u1E58'),
+    ('\u1E5A', '\u1E5A'),
+    ('\u1E5C', '\u1E5C'),
+    ('\u1E5E', '\u1E5E'),
+    ('\u1E60', '\u1E60'),
+    ('\u1E62', '\u1E62'),
+    ('\u1E64', '\u1E64'),
+    ('\u1E66', '\u1E66'),
+    ('\u1E68', '\u1E68'),
+    ('\u1E6A', '\u1E6A'),
+    ('\u1E6C', '\u1E6C'),
+    ('\u1E6E', '\u1E6E'),
+    ('\u1E70', '\u1E70'),
+    ('\u1E72', '\u1E72'),
+    ('\u1E74', '\u1E74'),
+    ('\u1E76', '\u1E76'),
+    ('\u1E78', '\u1E78'),
+    ('\u1E7A', '\u1E7A'),
+    ('\u1E7C', '\u1E7C'),
+    ('\u1E7E', '\u1E7E'),
+    ('\u1E80', '\u1E80'),
+    ('\u1E82', '\u1E82'),
+    ('\u1E84', '\u1E84'),
+    ('\u1E86', '\u1E86'),
+    ('\u1E88', '\u1E88'),
+    ('\u1E8A', '\u1E8A'),
+    ('\u1E8C', '\u1E8C'),
+    ('\u1E8E', '\u1E8E'),
+    ('\u1E90', '\u1E90'),
+    ('\u1E92', '\u1E92'),
+    ('\u1E94', '\u1E94'),
+    ('\u1E9E', '\u1E9E'),
+    ('\u1EA0', '\u1EA0'),
+    ('\u1EA2', '\u1EA2'),
+    ('\u1EA4', '\u1EA4'),
+    ('\u1EA6', '\u1EA6'),
+    ('\u1EA8', '\u1EA8'),
+    ('\u1EAA', '\u1EAA'),
+    ('\u1EAC', '\u1EAC'),
+    ('\u1EAE', '\u1EAE'),
+    ('\u1EB0', '\u1EB0'),
+    ('\u1EB2', '\u1EB2'),
+    ('\u1EB4', '\u1EB4'),
+    ('\u1EB6', '\u1EB6'),
+    ('\u1EB8', '\u1EB8'),
+    ('\u1EBA', '\u1EBA'),
+    ('\u1EBC', '\u1EBC'),
+    ('\u1EBE', '\u1EBE'),
+    ('\u1EC0', '\u1EC0'),
+    ('\u1EC2', '\u1EC2'),
+    ('\u1EC4', '\u1EC4'),
+    ('\u1EC6', '\u1EC6'),
+    ('\u1EC8', '\u1EC8'),
+    ('\u1ECA', '\u1ECA'),
+    ('\u1ECC', '\u1ECC'),
+    ('\u1ECE', '\u1ECE'),
+    ('\u1ED0', '\u1ED0'),
+    ('\u1ED2', '\u1ED2'),
+    ('\u1ED4', '\u1ED4'),
+    ('\u1ED6', '\u1ED6'),
+    ('\u1ED8', '\u1ED8'),
+    ('\u1EDA', '\u1EDA'),
+    ('\u1EDC', '\u1EDC'),
+    ('\u1EDE', '\u1EDE'),
+    ('\u1EE0', '\u1EE0'),
+    ('\u1EE2', '\u1EE2'),
+    ('\u1EE4', '\u1EE4'),
+    ('\u1EE6', '\u1EE6'),
+    ('\u1EE8', '\u1EE8'),
+    ('\u1EEA', '\u1EEA'),
+    ('\u1EEC', '\u1EEC'),
+    ('\u1EEE', '\u1EEE'),
+    ('\u1EF0', '\u1EF0'),
+    ('\u1EF2', '\u1EF2'),
+    ('\u1EF4', '\u1EF4'),
+    ('\u1EF6', '\u1EF6'),
+    ('\u1EF8', '\u1EF8'),
+    ('\u1EFA', '\u1EFA'),
+    ('\u1EFC', '\u1EFC'),
+    ('\u1EFE', '\u1EFE'),
+    ('\u1F08', '\u1F0F'),
+    ('\u1F18', '\u1F1D'),
+    ('\u1F28', '\u1F2F'),
+    ('\u1F38', '\u1F3F'),
+    ('\u1F48', '\u1F4D'),
+    ('\u1F59', '\u1F59'),
+    ('\u1F5B', '\u1F5B'),
+    ('\u1F5D', '\u1F5D'),
+    ('\u1F5F', '\u1F5F'),
+    ('\u1F68', '\u1F6F'),
+    ('\u1FB8', '\u1FBB'),
+    ('\u1FC8', '\u1FCB'),
+    ('\u1FD8', '\u1FDB'),
+    ('\u1FE8', '\u1FEC'),
+    ('\u1FF8', '\u1FFB'),
+    ('\u2102', '\u2102'),
+    ('\u2107', '\u2107'),
+    ('\u210B', '\u210D'),
+    ('\u2110', '\u2112'),
+    ('\u2115', '\u2115'),
+    ('\u2119', '\u211D'),
+    ('\u2124', '\u2124'),
+    ('\u2126', '\u2126'),
+    ('\u2128', '\u2128'),
+    ('\u212A', '\u212D'),
+    ('\u2130', '\u2133'),
+    ('\u213E', '\u213F'),
+    ('\u2145', '\u2145'),
+    ('\u2160', '\u216F'),
+    ('\u2183', '\u2183'),
+    ('\u24B6', '\u24CF'),
+    ('\u2C00', '\u2C2F'),
+    ('\u2C60', '\u2C60'),
+    ('\u2C62', '\u2C64'),
+    ('\u2C67', '\u2C67'),
+    ('\u2C69', '\u2C69'),
+    ('\u2C6B', '\u2C6B'),
+    ('\u2C6D', '\u2C70'),
+    ('\u2C72', '\u2C72'),
+    ('\u2C75', '\u2C75'),
+    ('\u2C7E', '\u2C80'),
+    ('\u2C82', '\u2C82'),
+    ('\u2C84', '\u2C84'),
+    ('\u2C86', '\u2C86'),
+    ('\u2C88', '\u2C88'),
+    ('\u2C8A', '\u2C8A'),
+    ('\u2C8C', '\u2C8C'),
+    ('\u2C8E', '\u2C8E'),
+    ('\u2C90', '\u2C90'),
+    ('\u2C92', '\u2C92'),
+    ('\u2C94', '\u2C94'),
+    ('\u2C96', '\u2C96'),
+    ('\u2C98', '\u2C98'),
+    ('\u2C9A', '\u2C9A'),
+    ('\u2C9C', '\u2C9C'),
+    ('\u2C9E', '\u2C9E'),
+    ('\u2CA0', '\u2CA0'),
+    ('\u2CA2', '\u2CA2'),
+    ('\u2CA4', '\u2CA4'),
+    ('\u2CA6', '\u2CA6'),
+    ('\u2CA8', '\u2CA8'),
+    ('\u2CAA', '\u2CAA'),
+    ('\u2CAC', '\u2CAC'),
+    ('\u2CAE', '\u2CAE'),
+    ('\u2CB0', '\u2CB0'),
+    ('\u2CB2', '\u2CB2'),
+    ('\u2CB4', '\u2CB4'),
+    ('\u2CB6', '\u2CB6'),
+    ('\u2CB8', '\u2CB8'),
+    ('\u2CBA', '\u2CBA'),
+    ('\u2CBC', '\u2CBC'),
+    ('\u2CBE', '\u2CBE'),
+    ('\u2CC0', '\u2CC0'),
+    ('\u2CC2', '\u2CC2'),
+    ('\u2CC4', '\u2CC4'),
+    ('\u2CC6', '\u2CC6'),
+    ('\u2CC8', '\u2CC8'),
+    ('\u2CCA', '\u2CCA'),
+    ('\u2CCC', '\u2CCC'),
+    ('\u2CCE', '\u2CCE'),
+    ('\u2CD0', '\u2CD0'),
+    ('\u2CD2', '\u2CD2'),
+    ('\u2CD4', '\u2CD4'),
+    ('\u2CD6', '\u2CD6'),
+    ('\u2CD8', '\u2CD8'),
+    ('\u2CDA', '\u2CDA'),
+    ('\u2CDC', '\u2CDC'),
+    ('\u2CDE', '\u2CDE'),
+    ('\u2CE0', '\u2CE0'),
+    ('\u2CE2', '\u2CE2'),
+    ('\u2CEB', '\u2CEB'),
+    ('\u2CED', '\u2CED'),
+    ('\u2CF2', '\u2CF2'),
+    ('\uA640', '\uA640'),
+    ('\uA642', '\uA642'),
+    ('\uA644', '\uA644'),
+    ('\uA646', '\uA646'),
+    ('\uA648', '\uA648'),
+    ('\uA64A', '\uA64A'),
+    ('\uA64C', '\uA64C'),
+    ('\uA64E', '\uA64E'),
+    ('\uA650', '\uA650'),
+    ('\uA652', '\uA652'),
+    ('\uA654', '\uA654'),
+    ('\uA656', '\uA656'),
+    ('\uA658', '\uA658'),
+    ('\uA65A', '\uA65A'),
+    ('\uA65C', '\uA65C'),
+    ('\uA65E', '\uA65E'),
+    ('\uA660', '\uA660'),
+    ('\uA662', '\uA662'),
+    ('\uA664', '\uA664'),
+    ('\uA666', '\uA666'),
+    ('\uA668', '\uA668'),
+    ('\uA66A', '\uA66A'),
+    ('\uA66C', '\uA66C'),
+    ('\uA680', '\uA680'),
+    ('\uA682', '\uA682'),
+    ('\uA684', '\uA684'),
+    ('\uA686', '\uA686'),
+    ('\uA688', '\uA688'),
+    ('\uA68A', '\uA68A'),
+    ('\uA68C', '\uA68C'),
+    ('\uA68E', '\uA68E'),
+    ('\uA690', '\uA690'),
+    ('\uA692', '\uA692'),
+    ('\uA694', '\uA694'),
+    ('\uA696', '\uA696'),
+    ('\uA698', '\uA698'),
+    ('\uA69A', '\uA69A'),
+    ('\uA722', '\uA722'),
+    ('\uA724', '\uA724'),
+    ('\uA726', '\uA726'),
+    ('\uA728', '\uA728'),
+    ('\uA72A', '\uA72A'),
+    ('\uA72C', '\uA72C'),
+    ('\uA72E', '\uA72E'),
+    ('\uA732', '\uA732'),
+    ('\uA734', '\uA734'),
+    ('\uA736', '\uA736'),
+    ('\uA738', '\uA738'),
+    ('\uA73A', '\uA73A'),
+    ('\uA73C', '\uA73C'),
+    ('\uA73E', '\uA73E'),
+    ('\uA740', '\uA740'),
+    ('\uA742', '\uA742'),
+    ('\uA744', '\uA744'),
+    ('\uA746', '\uA746'),
+    ('\uA748', '\uA748'),
+    ('\uA74A', '\uA74A'),
+    ('\uA74C', '\uA74C'),
+    ('\uA74E', '\uA74E'),
+    ('\uA750', '\uA750'),
+    ('\uA752', '\uA752'),
+    ('\uA754', '\uA754'),
+    ('\uA756', '\uA756'),
+    ('\uA758', '\uA758'),
+    ('\uA75A', '\uA75A'),
+    ('\uA75C', '\uA75C'),
+    ('\uA75E', '\uA75E'),
+    ('\uA760', '\uA760'),
+    ('\uA762', '\uA762'),
+    ('\uA764', '\uA764'),
+    ('\uA766', '\uA766'),
+    ('\uA768', '\uA768'),
+    ('\uA76A', '\uA76A'),
+    ('\uA76C', '\uA76C'),
+    ('\uA76E', '\uA76E'),
+    ('\uA779', '\uA779'),
+    ('\uA77B', '\uA77B'),
+    ('\uA77D', '\uA77E'),
+    ('\uA780', '\uA780'),
+    ('\uA782', '\uA782'),
+    ('\uA784', '\uA784'),
+    ('\uA786', '\uA786'),
+    ('\uA78B', '\uA78B'),
+    ('\uA78D', '\uA78D'),
+    ('\uA790', '\uA790'),
+    ('\uA792', '\uA792'),
+    ('\uA796', '\uA796'),
+    ('\uA798', '\uA798'),
+    ('\uA79A', '\uA79A'),
+    ('\uA79C', '\uA79C'),
+    ('\uA79E', '\uA79E'),
+    ('\uA7A0', '\uA7A0'),
+    ('\uA7A2', '\uA7A2'),
+    ('\uA7A4', '\uA7A4'),
+    ('\uA7A6', '\uA7A6'),
+    ('\uA7A8', '\uA7A8'),
+    ('\uA7AA', '\uA7AE'),
+    ('\uA7B0', '\uA7B4'),
+    ('\uA7B6', '\uA7B6'),
+    ('\uA7B8', '\uA7B8'),
+    ('\uA7BA', '\uA7BA'),
+    ('\uA7BC', '\uA7BC'),
+    ('\uA7BE', '\uA7BE'),
+    ('\uA7C0', '\uA7C0'),
+    ('\uA7C2', '\uA7C2'),
+    ('\uA7C4', '\uA7C7'),
+    ('\uA7C9', '\uA7C9'),
+    ('\uA7D0', '\uA7D0'),
+    ('\uA7D6', '\uA7D6'),
+    ('\uA7D8', '\uA7D8'),
+    ('\uA7F5', '\uA7F5'),
+    ('\uFF21', '\uFF3A'),
+    ('\U00010400', '\U00010427'),
+    ('\U000104B0', '\U000104D3'),
+    ('\U00010570', '\U0001057A'),
+    ('\U0001057C', '\U0001058A'),
+    ('\U0001058C', '\U00010592'),
+    ('\U00010594', '\U00010595'),
+    ('\U00010C80', '\U00010CB2'),
+    ('\U000118A0', '\U000118BF'),
+    ('\U00016E40', '\U00016E5F'),
+    ('\U0001D400', '\U0001D419'),
+    ('\U0001D434', '\U0001D44D'),
+    ('\U0001D468', '\U0001D481'),
+    ('\U0001D49C', '\U0001D49C'),
+    ('\U0001D49E', '\U0001D49F'),
+    ('\U0001D4A2', '\U0001D4A2'),
+    ('\U0001D4A5', '\U0001D4A6'),
+    ('\U0001D4A9', '\U0001D4AC'),
+    ('\U0001D4AE', '\U0001D4B5'),
+    ('\U0001D4D0', '\U0001D4E9'),
+    ('\U0001D504', '\U0001D505'),
+    ('\U0001D507', '\U0001D50A'),
+    ('\U0001D50D', '\U0001D514'),
+    ('\U0001D516', '\U0001D51C'),
+    ('\U0001D538', '\U0001D539'),
+    ('\U0001D53B', '\U0001D53E'),
+    ('\U0001D540', '\U0001D544'),
+    ('\U0001D546', '\U0001D546'),
+    ('\U0001D54A', '\U0001D550'),
+    ('\U0001D56C', '\U0001D585'),
+    ('\U0001D5A0', '\U0001D5B9'),
+    ('\U0001D5D4', '\U0001D5ED'),
+    ('\U0001D608', '\U0001D621'),
+    ('\U0001D63C', '\U0001D655'),
+    ('\U0001D670', '\U0001D689'),
+    ('\U0001D6A8', '\U0001D6C0'),
+    ('\U0001D6E2', '\U0001D6FA'),
+    ('\U0001D71C', '\U0001D734'),
+    ('\U0001D756', '\U0001D76E'),
+    ('\U0001D790', '\U0001D7A8'),
+    ('\U0001D7CA', '\U0001D7CA'),
+    ('\U0001E900', '\U0001E921'),
+    ('\U0001F130', '\U0001F149'),
+    ('\U0001F150', '\U0001F169'),
+    ('\U0001F170', '\U0001F189')
+  ]),
+  ("whitespace", &[
+    ('\u0009', '\u000D'),
+    ('\u0020', '\u0020'),
+    ('\u0085', '\u0085'),
+    ('\u00A0', '\u00A0'),
+    ('\u1680', '\u1680'),
+    ('\u2000', '\u200A'),
+    ('\u2028', '\u2029'),
+    ('\u202F', '\u202F'),
+    ('\u205F', '\u205F'),
+    ('\u3000', '\u3000')
+  ])
+];
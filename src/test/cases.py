@@ -171,6 +171,67 @@ TESTS = [
   ("a{5}", "aaaaa", "aaaaa", MATCH),
   ("a{5,}", "aaaaaaa", "aaaaaaa", MATCH),
   ("a{5,7}", "aaaaaa", "aaaaaa", MATCH),
-  ("a{5,}", "aaaa", "", NOMATCH)
+  ("a{5,}", "aaaa", "", NOMATCH),
+  ("a(?=b)", "acab", "a", MATCH),
+  ("a(?!b)", "abac", "a", MATCH),
+  ("a(?!b)", "ab", "", NOMATCH),
+  ("(?<=a)b", "cbab", "b", MATCH),
+  ("(?<!a)b", "abcb", "b", MATCH),
+  ("(?<!a)b", "ab", "", NOMATCH),
+  ("(?<=(a)|(b))c", "bc", "c", MATCH, ["", "b"]),
+  ("(\\w)(?=(\\w))", "x y", "", NOMATCH),
+  ("(?=(a+))a*b\\1", "baaabac", "aba", MATCH, ["a"]),
+  ("(?i)abc", "xABCy", "ABC", MATCH),
+  ("a(?i:b)c", "aBc", "aBc", MATCH),
+  ("a(?i:b)c", "aBC", "", NOMATCH),
+  ("(?i)a(?-i:b)c", "AbC", "AbC", MATCH),
+  ("(?i)a(?-i:b)c", "ABC", "", NOMATCH),
+  ("(?i)[^a]", "A", "", NOMATCH),
+  ("(?i)(a)\\1", "aA", "aA", MATCH, ["a"]),
+  ("(?m)^b", "a\nb", "b", MATCH),
+  ("(?m)a$", "a\nb", "a", MATCH),
+  ("^b", "a\nb", "", NOMATCH),
+  ("(?m)^$", "a\n\nb", "", MATCH),
+  ("(?m)\\Ab", "a\nb", "", NOMATCH),
+  ("(?m)a\\Z", "a\nb", "", NOMATCH),
+  ("\\Aab\\Z", "ab", "ab", MATCH),
+  ("a.c", "a\nc", "", NOMATCH),
+  ("(?s)a.c", "a\nc", "a\nc", MATCH),
+  ("a(?s:.)c.", "a\nc\n", "", NOMATCH),
+  (".+", "ab\ncd", "ab", MATCH),
+  ("a(?#comment)b", "ab", "ab", MATCH),
+  ("(?x) a b # c\n c", "abc", "abc", MATCH),
+  ("(?x)a\\ b", "a b", "a b", MATCH),
+  ("(?x)a b", "a b", "", NOMATCH),
+  ("(?x)[ #]+", "x #", " #", MATCH),
+  ("(?x)a +", "aaa", "aaa", MATCH),
+  ("\\w+", "héllo", "héllo", MATCH),
+  ("\\d+", "x٣٤", "٣٤", MATCH),
+  ("\\bé\\b", "-é-", "é", MATCH),
+  ("\\W", "é!", "!", MATCH),
+  ("(?a)\\w+", "héllo", "h", MATCH),
+  ("(?a)\\bl", "él", "l", MATCH),
+  ("\\Bl", "él", "l", MATCH),
+  ("[\\d]+", "ab12", "12", MATCH),
+  ("[^\\W\\d]+", "12ab3", "ab", MATCH),
+  ("[\\s,]+", "a , b", " , ", MATCH),
+  ("[a\\-z]+", "b-az", "-az", MATCH),
+  ("\\n", "a\nb", "\n", MATCH),
+  ("\\x41B\\U00000043", "xABC", "ABC", MATCH),
+  ("[\\x30-\\x39]+", "ab123", "123", MATCH),
+  ("\\101", "A", "A", MATCH),
+  ("[\\t\\n]+", "a\t\nb", "\t\n", MATCH),
+  ("\\u00e9", "café", "é", MATCH),
+  ("a(?>bc|b)c", "abcc", "abcc", MATCH),
+  ("a(?>bc|b)c", "abc", "", NOMATCH),
+  ("(?>(a+))b\\1", "xaaaba", "aba", MATCH, ["a"]),
+  ("a*+a", "aaa", "", NOMATCH),
+  ("a++b", "aaab", "aaab", MATCH),
+  ("a{1,2}+a", "aaa", "aaa", MATCH),
+  ("a{1,2}+a", "aa", "", NOMATCH),
+  ("(abc)\\1", "abcabc", "abcabc", MATCH, ["abc"]),
+  ("([a-c]*)\\1", "abcabc", "abcabc", MATCH, ["abc"]),
+  ("(a+).\\1$", "aaaaa", "aaaaa", MATCH, ["aa"]),
+  ("^(a+).\\1$", "aaaa", "", NOMATCH)
 ]
 
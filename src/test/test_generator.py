@@ -15,7 +15,7 @@ macro_rules! run_tests(
   ($re: expr, $input: expr, $matched: expr, $ident: expr, 
    $expect: pat, $groups: expr) => (
     {
-      let re = match UncompiledRegexp::new($re) {
+      let re = match Regexp::new($re) {
         Ok(regex) => regex,
         Err(e) => fail!(e)
       };
@@ -55,7 +55,7 @@ macro_rules! run_tests(
 
 #[cfg(test)]
 mod python_tests {
-  use regexp::UncompiledRegexp;
+  use regexp::Regexp;
 
   // Tests start here
   %s
@@ -85,6 +85,11 @@ def generate_test_num(num, digits):
     ret = "0" + ret
   return ret
 
+def escape_str(s):
+  s = re.sub("\\\\", "\\\\\\\\", s)
+  s = s.replace("\n", "\\n").replace("\r", "\\r").replace("\t", "\\t")
+  return s
+
 def generate_test_case(ident, regexp, input_str, 
     matched_str, expected, groups):
   if expected == NOMATCH:
@@ -94,9 +99,9 @@ def generate_test_case(ident, regexp, input_str,
   elif expected == MATCH:
     match = "Some(_)"
 
-  regexp = re.sub("\\\\", "\\\\\\\\", regexp)
-  input_str = re.sub("\\\\", "\\\\\\\\", input_str)
-  matched_str = re.sub("\\\\", "\\\\\\\\", matched_str)
+  regexp = escape_str(regexp)
+  input_str = escape_str(input_str)
+  matched_str = escape_str(matched_str)
 
   if (len(groups) > 0):
     groups_str  = "\"" + "\", \"".join(groups) + "\""